@@ -0,0 +1,182 @@
+use crate::infra::{
+    common_component::{CommonComponent, CommonComponentParts},
+    graphql::DateTimeUtc,
+};
+use anyhow::{anyhow, bail, Result};
+use chrono::TimeZone;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/set_account_validity.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct SetAccountValidity;
+
+/// The dates between which the account can be used to log in, e.g. for contractors. Only the
+/// admins can change them.
+pub struct AccountValidity {
+    common: CommonComponentParts<Self>,
+    activation_date: NodeRef,
+    expiration_date: NodeRef,
+    saved: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+    pub activation_date: Option<DateTimeUtc>,
+    pub expiration_date: Option<DateTimeUtc>,
+}
+
+pub enum Msg {
+    Submit,
+    SubmitResponse(Result<set_account_validity::ResponseData>),
+}
+
+/// The date of the input, at midnight UTC. `None` if it is empty.
+fn get_date(input: &NodeRef) -> Result<Option<DateTimeUtc>> {
+    let value = input
+        .cast::<web_sys::HtmlInputElement>()
+        .expect("Date input should be present")
+        .value();
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    let date = chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid date: {}", value))?;
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .map(|date| chrono::Utc.from_utc_datetime(&date)))
+}
+
+fn to_input_value(date: &Option<DateTimeUtc>) -> String {
+    date.map(|d| d.naive_utc().date().to_string())
+        .unwrap_or_default()
+}
+
+impl CommonComponent<AccountValidity> for AccountValidity {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Submit => {
+                let activation_date = get_date(&self.activation_date)?;
+                let expiration_date = get_date(&self.expiration_date)?;
+                if let (Some(activation), Some(expiration)) = (activation_date, expiration_date) {
+                    if expiration <= activation {
+                        bail!("The account must expire after its activation");
+                    }
+                }
+                self.saved = false;
+                self.common.call_graphql::<SetAccountValidity, _>(
+                    set_account_validity::Variables {
+                        user_id: self.common.username.clone(),
+                        activation_date,
+                        expiration_date,
+                    },
+                    Msg::SubmitResponse,
+                    "Error trying to change the validity of the account",
+                );
+            }
+            Msg::SubmitResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.saved = true;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for AccountValidity {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        AccountValidity {
+            common: CommonComponentParts::<Self>::create(props, link),
+            activation_date: NodeRef::default(),
+            expiration_date: NodeRef::default(),
+            saved: false,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-4">
+              <h5 class="row m-3 fw-bold">{"Account validity"}</h5>
+            </div>
+            <p>
+              {"The user can't log in before the activation date, nor from the expiration date \
+                on. Leave a date empty to remove the limit."}
+            </p>
+            <div class="row mb-3">
+              <label for="activationDate" class="form-label col-4 col-form-label">
+                {"Activation date (UTC):"}
+              </label>
+              <div class="col-8">
+                <input
+                  id="activationDate"
+                  class="form-control"
+                  type="date"
+                  value=to_input_value(&self.common.activation_date)
+                  ref=self.activation_date.clone() />
+              </div>
+            </div>
+            <div class="row mb-3">
+              <label for="expirationDate" class="form-label col-4 col-form-label">
+                {"Expiration date (UTC):"}
+              </label>
+              <div class="col-8">
+                <input
+                  id="expirationDate"
+                  class="form-control"
+                  type="date"
+                  value=to_input_value(&self.common.expiration_date)
+                  ref=self.expiration_date.clone() />
+              </div>
+            </div>
+            <div class="row justify-content-center">
+              <button
+                type="button"
+                class="btn btn-primary col-auto col-form-label"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::Submit)>
+                <i class="bi-save me-2"></i>
+                {"Save the dates"}
+              </button>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else if self.saved {
+                html! {
+                  <div class="alert alert-success mt-3 mb-3">
+                    {"Saved"}
+                  </div>
+                }
+              } else { html! {} }
+            }
+          </>
+        }
+    }
+}
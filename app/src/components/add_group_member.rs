@@ -17,6 +17,16 @@ use yew::prelude::*;
 )]
 pub struct AddUserToGroup;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/request_group_membership.graphql",
+    response_derives = "Debug",
+    variables_derives = "Clone",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RequestGroupMembership;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "../schema.graphql",
@@ -40,6 +50,7 @@ pub enum Msg {
     UserListResponse(Result<list_user_names::ResponseData>),
     SubmitAddMember,
     AddMemberResponse(Result<add_user_to_group::ResponseData>),
+    RequestMembershipResponse(Result<request_group_membership::ResponseData>),
     SelectionChanged(Option<SelectOptionProps>),
 }
 
@@ -48,6 +59,9 @@ pub struct Props {
     pub group_id: i64,
     pub users: Vec<User>,
     pub on_user_added_to_group: Callback<User>,
+    /// The new members have to be approved: submitting only asks for the user to be added.
+    pub requires_approval: bool,
+    pub on_membership_requested: Callback<User>,
     pub on_error: Callback<Error>,
 }
 
@@ -70,6 +84,16 @@ impl CommonComponent<AddGroupMemberComponent> for AddGroupMemberComponent {
                 // Remove the user from the dropdown.
                 self.common.on_user_added_to_group.emit(user);
             }
+            Msg::RequestMembershipResponse(response) => {
+                response?;
+                self.common.cancel_task();
+                let user = self
+                    .selected_user
+                    .as_ref()
+                    .expect("Could not get selected user")
+                    .clone();
+                self.common.on_membership_requested.emit(user);
+            }
             Msg::SelectionChanged(option_props) => {
                 let was_some = self.selected_user.is_some();
                 self.selected_user = option_props.map(|u| User {
@@ -101,6 +125,17 @@ impl AddGroupMemberComponent {
             None => return Ok(false),
             Some(user) => user.id,
         };
+        if self.common.requires_approval {
+            self.common.call_graphql::<RequestGroupMembership, _>(
+                request_group_membership::Variables {
+                    user: user_id,
+                    group: self.common.group_id,
+                },
+                Msg::RequestMembershipResponse,
+                "Error trying to request the membership",
+            );
+            return Ok(true);
+        }
         self.common.call_graphql::<AddUserToGroup, _>(
             add_user_to_group::Variables {
                 user: user_id,
@@ -151,6 +186,11 @@ impl Component for AddGroupMemberComponent {
     fn view(&self) -> Html {
         if let Some(user_list) = &self.user_list {
             let to_add_user_list = self.get_selectable_user_list(user_list);
+            let submit_label = if self.common.requires_approval {
+                "Request membership"
+            } else {
+                "Add to group"
+            };
             #[allow(unused_braces)]
             let make_select_option = |user: User| {
                 html_nested! {
@@ -175,7 +215,7 @@ impl Component for AddGroupMemberComponent {
                   disabled=self.selected_user.is_none() || self.common.is_task_running()
                   onclick=self.common.callback(|_| Msg::SubmitAddMember)>
                    <i class="bi-person-plus me-2"></i>
-                  {"Add to group"}
+                  {submit_label}
                 </button>
               </div>
             </div>
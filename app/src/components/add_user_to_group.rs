@@ -104,7 +104,7 @@ impl CommonComponent<AddUserToGroupComponent> for AddUserToGroupComponent {
 impl AddUserToGroupComponent {
     fn get_group_list(&mut self) {
         self.common.call_graphql::<GetGroupList, _>(
-            get_group_list::Variables,
+            get_group_list::Variables { archived: None },
             Msg::GroupListResponse,
             "Error trying to fetch group list",
         );
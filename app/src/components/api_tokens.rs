@@ -0,0 +1,283 @@
+use crate::infra::common_component::{CommonComponent, CommonComponentParts};
+use anyhow::{bail, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/list_api_tokens.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ListApiTokens;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/create_api_token.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct CreateApiToken;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/delete_api_token.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteApiToken;
+
+type ApiToken = list_api_tokens::ListApiTokensApiTokens;
+
+/// The tokens for scripts to call the GraphQL API, instead of the JWT of an admin.
+pub struct ApiTokens {
+    common: CommonComponentParts<Self>,
+    tokens: Option<Vec<ApiToken>>,
+    /// The secret of the token that was just created, only shown once.
+    new_secret: Option<String>,
+    name: NodeRef,
+    user_management: NodeRef,
+    group_management: NodeRef,
+}
+
+pub enum Msg {
+    ListResponse(Result<list_api_tokens::ResponseData>),
+    Create,
+    CreateResponse(Result<create_api_token::ResponseData>),
+    Delete(String),
+    DeleteResponse(Result<delete_api_token::ResponseData>),
+}
+
+fn is_checked(checkbox: &NodeRef) -> bool {
+    checkbox
+        .cast::<web_sys::HtmlInputElement>()
+        .map_or(false, |c| c.checked())
+}
+
+impl ApiTokens {
+    fn fetch_tokens(&mut self) {
+        self.common.call_graphql::<ListApiTokens, _>(
+            list_api_tokens::Variables {},
+            Msg::ListResponse,
+            "Error trying to fetch the API tokens",
+        );
+    }
+
+    fn create_token(&mut self) -> Result<()> {
+        let name = self
+            .name
+            .cast::<web_sys::HtmlInputElement>()
+            .expect("Name input should be present")
+            .value()
+            .trim()
+            .to_string();
+        if name.is_empty() {
+            bail!("The name of the token is required");
+        }
+        let mut scopes = vec!["read_only".to_string()];
+        if is_checked(&self.user_management) {
+            scopes.push("user_management".to_string());
+        }
+        if is_checked(&self.group_management) {
+            scopes.push("group_management".to_string());
+        }
+        self.common.call_graphql::<CreateApiToken, _>(
+            create_api_token::Variables { name, scopes },
+            Msg::CreateResponse,
+            "Error trying to create the API token",
+        );
+        Ok(())
+    }
+
+    fn view_token(&self, token: &ApiToken) -> Html {
+        let token_id = token.token_id.clone();
+        html! {
+          <tr key=token.token_id.clone()>
+            <td>{&token.name}</td>
+            <td>{token.scopes.join(", ")}</td>
+            <td>{&token.creator_id}</td>
+            <td>{&token.creation_date.naive_local().date()}</td>
+            <td>
+              <button
+                class="btn btn-danger btn-sm"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(move |_| Msg::Delete(token_id.clone()))>
+                <i class="bi-x-circle-fill" aria-label="Revoke token" />
+              </button>
+            </td>
+          </tr>
+        }
+    }
+
+    fn view_tokens(&self) -> Html {
+        match &self.tokens {
+            None => html! {{"Loading..."}},
+            Some(tokens) if tokens.is_empty() => html! {
+              <p>{"No API token."}</p>
+            },
+            Some(tokens) => html! {
+              <div class="table-responsive">
+                <table class="table table-hover">
+                  <thead>
+                    <tr>
+                      <th>{"Name"}</th>
+                      <th>{"Scopes"}</th>
+                      <th>{"Created by"}</th>
+                      <th>{"Created"}</th>
+                      <th>{"Revoke"}</th>
+                    </tr>
+                  </thead>
+                  <tbody>
+                    {for tokens.iter().map(|t| self.view_token(t))}
+                  </tbody>
+                </table>
+              </div>
+            },
+        }
+    }
+
+    fn view_new_secret(&self) -> Html {
+        match &self.new_secret {
+            None => html! {},
+            Some(secret) => html! {
+              <div class="alert alert-success mt-3">
+                <p>
+                  {"Copy the secret of the new token now, it won't be shown again. Send it as \
+                    `Authorization: Bearer <secret>` to /api/graphql."}
+                </p>
+                <code>{secret}</code>
+              </div>
+            },
+        }
+    }
+
+    fn view_creation(&self) -> Html {
+        html! {
+          <div class="mt-4">
+            <h5 class="fw-bold">{"Create a token"}</h5>
+            <p>
+              {"All the tokens can read all the users and groups. They can't modify the admins, \
+                nor add users to the lldap_ groups."}
+            </p>
+            <div class="form-check">
+              <input
+                class="form-check-input"
+                type="checkbox"
+                id="user_management"
+                ref=self.user_management.clone() />
+              <label class="form-check-label" for="user_management">
+                {"Create, modify and delete users"}
+              </label>
+            </div>
+            <div class="form-check">
+              <input
+                class="form-check-input"
+                type="checkbox"
+                id="group_management"
+                ref=self.group_management.clone() />
+              <label class="form-check-label" for="group_management">
+                {"Create, modify and delete groups, and change their members"}
+              </label>
+            </div>
+            <div class="input-group mt-2">
+              <input
+                class="form-control"
+                type="text"
+                placeholder="Name, e.g. Provisioning script"
+                ref=self.name.clone() />
+              <button
+                type="button"
+                class="btn btn-primary"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::Create)>
+                <i class="bi-key me-2"></i>
+                {"Create"}
+              </button>
+            </div>
+          </div>
+        }
+    }
+}
+
+impl CommonComponent<ApiTokens> for ApiTokens {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::ListResponse(response) => {
+                self.tokens = Some(response?.api_tokens);
+            }
+            Msg::Create => {
+                self.new_secret = None;
+                self.create_token()?;
+            }
+            Msg::CreateResponse(response) => {
+                self.new_secret = Some(response?.create_api_token.secret);
+                self.fetch_tokens();
+            }
+            Msg::Delete(token_id) => {
+                self.common.call_graphql::<DeleteApiToken, _>(
+                    delete_api_token::Variables { token_id },
+                    Msg::DeleteResponse,
+                    "Error trying to revoke the API token",
+                );
+            }
+            Msg::DeleteResponse(response) => {
+                response?;
+                self.fetch_tokens();
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ApiTokens {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = ApiTokens {
+            common: CommonComponentParts::<Self>::create(props, link),
+            tokens: None,
+            new_secret: None,
+            name: NodeRef::default(),
+            user_management: NodeRef::default(),
+            group_management: NodeRef::default(),
+        };
+        component.fetch_tokens();
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <div>
+            <h3>{"API tokens"}</h3>
+            {self.view_tokens()}
+            {self.view_new_secret()}
+            {self.view_creation()}
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+          </div>
+        }
+    }
+}
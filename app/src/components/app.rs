@@ -1,19 +1,25 @@
 use crate::{
     components::{
+        api_tokens::ApiTokens,
         change_password::ChangePasswordForm,
         create_group::CreateGroupForm,
         create_user::CreateUserForm,
+        export_membership_report::ExportMembershipReport,
         group_details::GroupDetails,
         group_table::GroupTable,
         login::LoginForm,
         logout::LogoutButton,
+        magic_login::MagicLogin,
         reset_password_step1::ResetPasswordStep1Form,
         reset_password_step2::ResetPasswordStep2Form,
         router::{AppRoute, Link, NavButton},
+        single_sign_on::SingleSignOn,
+        sso_login::SsoLogin,
+        stats::StatsDashboard,
         user_details::UserDetails,
         user_table::UserTable,
     },
-    infra::cookies::get_cookie,
+    infra::cookies::{get_cookie, set_document_language},
 };
 use yew::prelude::*;
 use yew::services::ConsoleService;
@@ -41,6 +47,12 @@ impl Component for App {
     type Properties = ();
 
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
+        // The language of the last user who logged in, set by the login.
+        if let Err(e) = get_cookie("language")
+            .and_then(|language| language.map_or(Ok(()), |l| set_document_language(&l)))
+        {
+            ConsoleService::error(&e.to_string());
+        }
         let mut app = Self {
             link,
             user_info: get_cookie("user_id")
@@ -134,7 +146,10 @@ impl App {
     fn apply_initial_redirections(&mut self) {
         let route_service = RouteService::<()>::new();
         let current_route = route_service.get_path();
-        if current_route.contains("reset-password") {
+        if current_route.contains("reset-password")
+            || current_route.contains("magic-login")
+            || current_route.contains("sso-login")
+        {
             return;
         }
         match &self.user_info {
@@ -189,10 +204,17 @@ impl App {
                     <i class="bi-plus-circle me-2"></i>
                     {"Create a group"}
                   </NavButton>
+                  <ExportMembershipReport />
                 </div>
             },
             AppRoute::GroupDetails(group_id) => html! {
-                <GroupDetails group_id=group_id />
+                <GroupDetails group_id=group_id is_admin=is_admin />
+            },
+            AppRoute::Stats => html! {
+                <StatsDashboard />
+            },
+            AppRoute::ApiTokens => html! {
+                <ApiTokens />
             },
             AppRoute::UserDetails(username) => html! {
                 <UserDetails username=username is_admin=is_admin />
@@ -206,6 +228,18 @@ impl App {
             AppRoute::FinishResetPassword(token) => html! {
                 <ResetPasswordStep2Form token=token />
             },
+            AppRoute::ExpiredPassword(token) => html! {
+                <ResetPasswordStep2Form token=token expired=true />
+            },
+            AppRoute::SingleSignOn(request_id) => html! {
+                <SingleSignOn request_id=request_id />
+            },
+            AppRoute::MagicLogin(token) => html! {
+                <MagicLogin token=token on_logged_in=link.callback(Msg::Login)/>
+            },
+            AppRoute::SsoLogin => html! {
+                <SsoLogin on_logged_in=link.callback(Msg::Login)/>
+            },
         }
     }
 
@@ -237,6 +271,22 @@ impl App {
                           {"Groups"}
                         </Link>
                       </li>
+                      <li>
+                        <Link
+                          classes="nav-link px-2 link-dark h6"
+                          route=AppRoute::Stats>
+                          <i class="bi-speedometer2 me-2"></i>
+                          {"Stats"}
+                        </Link>
+                      </li>
+                      <li>
+                        <Link
+                          classes="nav-link px-2 link-dark h6"
+                          route=AppRoute::ApiTokens>
+                          <i class="bi-key me-2"></i>
+                          {"API tokens"}
+                        </Link>
+                      </li>
                     </>
                   } } else { html!{} } }
                 </ul>
@@ -1,12 +1,20 @@
 use crate::{
-    components::router::{AppRoute, NavButton},
+    components::{
+        router::{AppRoute, NavButton},
+        totp_setup::TotpSetup,
+        webauthn_setup::WebauthnSetup,
+    },
     infra::{
         api::HostService,
         common_component::{CommonComponent, CommonComponentParts},
     },
 };
 use anyhow::{anyhow, bail, Context, Result};
-use lldap_auth::*;
+use graphql_client::GraphQLQuery;
+use lldap_auth::{
+    password_strength::{estimate_password_strength, PasswordPolicy, MAX_SCORE},
+    *,
+};
 use validator_derive::Validate;
 use yew::{prelude::*, services::ConsoleService};
 use yew_form::Form;
@@ -16,6 +24,27 @@ use yew_router::{
     route::Route,
 };
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_password_policy.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetPasswordPolicy;
+
+/// The policy returned by the server, to run the same checks as the server before submitting.
+pub fn to_password_policy(
+    policy: get_password_policy::GetPasswordPolicyPasswordPolicy,
+) -> PasswordPolicy {
+    PasswordPolicy {
+        min_length: usize::try_from(policy.min_length).unwrap_or_default(),
+        min_character_classes: u8::try_from(policy.min_character_classes).unwrap_or(u8::MAX),
+        min_strength: u8::try_from(policy.min_strength).unwrap_or(MAX_SCORE),
+        banned_passwords: policy.banned_passwords,
+    }
+}
+
 #[derive(PartialEq, Eq)]
 enum OpaqueData {
     None,
@@ -62,6 +91,8 @@ pub struct ChangePasswordForm {
     form: Form<FormModel>,
     opaque_data: OpaqueData,
     route_dispatcher: RouteAgentDispatcher,
+    /// The requirements of the server for new passwords.
+    password_policy: PasswordPolicy,
 }
 
 #[derive(Clone, PartialEq, Eq, Properties)]
@@ -71,8 +102,11 @@ pub struct Props {
 }
 
 pub enum Msg {
+    PasswordPolicyResponse(Result<get_password_policy::ResponseData>),
     FormUpdate,
     Submit,
+    /// The old password is checked like a login, with the same challenge.
+    ChallengeResponse(Result<Option<proof_of_work::Challenge>>),
     AuthenticationStartResponse(Result<Box<login::ServerLoginStartResponse>>),
     SubmitNewPassword,
     RegistrationStartResponse(Result<Box<registration::ServerRegistrationStartResponse>>),
@@ -82,35 +116,55 @@ pub enum Msg {
 impl CommonComponent<ChangePasswordForm> for ChangePasswordForm {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
+            Msg::PasswordPolicyResponse(response) => {
+                self.password_policy = to_password_policy(response?.password_policy);
+                Ok(true)
+            }
             Msg::FormUpdate => Ok(true),
             Msg::Submit => {
                 if !self.form.validate() {
                     bail!("Check the form for errors");
                 }
+                self.password_policy
+                    .check(
+                        &self.form.model().password,
+                        &[self.common.username.as_str()],
+                    )
+                    .map_err(|e| anyhow!(e))?;
                 if self.common.is_admin {
                     self.handle_msg(Msg::SubmitNewPassword)
                 } else {
-                    let old_password = self.form.model().old_password;
-                    if old_password.is_empty() {
+                    if self.form.model().old_password.is_empty() {
                         bail!("Current password should not be empty");
                     }
-                    let mut rng = rand::rngs::OsRng;
-                    let login_start_request =
-                        opaque::client::login::start_login(&old_password, &mut rng)
-                            .context("Could not initialize login")?;
-                    self.opaque_data = OpaqueData::Login(login_start_request.state);
-                    let req = login::ClientLoginStartRequest {
-                        username: self.common.username.clone(),
-                        login_start_request: login_start_request.message,
-                    };
                     self.common.call_backend(
-                        HostService::login_start,
-                        req,
-                        Msg::AuthenticationStartResponse,
+                        HostService::login_challenge,
+                        (),
+                        Msg::ChallengeResponse,
                     )?;
                     Ok(true)
                 }
             }
+            Msg::ChallengeResponse(challenge) => {
+                let proof_of_work = challenge?.map(|c| c.solve());
+                let old_password = self.form.model().old_password;
+                let mut rng = rand::rngs::OsRng;
+                let login_start_request =
+                    opaque::client::login::start_login(&old_password, &mut rng)
+                        .context("Could not initialize login")?;
+                self.opaque_data = OpaqueData::Login(login_start_request.state);
+                let req = login::ClientLoginStartRequest {
+                    username: self.common.username.clone(),
+                    login_start_request: login_start_request.message,
+                    proof_of_work,
+                };
+                self.common.call_backend(
+                    HostService::login_start,
+                    req,
+                    Msg::AuthenticationStartResponse,
+                )?;
+                Ok(true)
+            }
             Msg::AuthenticationStartResponse(res) => {
                 let res = res.context("Could not initiate login")?;
                 match self.opaque_data.take() {
@@ -161,9 +215,15 @@ impl CommonComponent<ChangePasswordForm> for ChangePasswordForm {
                                 &mut rng,
                             )
                             .context("Error during password change")?;
+                        let password_history_digest = password_history::password_history_digest(
+                            &self.common.username,
+                            &self.form.model().password,
+                        )
+                        .map_err(anyhow::Error::msg)?;
                         let req = registration::ClientRegistrationFinishRequest {
                             server_data: res.server_data,
                             registration_upload: registration_finish.message,
+                            password_history_digest: Some(password_history_digest),
                         };
                         self.common.call_backend(
                             HostService::register_finish,
@@ -194,17 +254,65 @@ impl CommonComponent<ChangePasswordForm> for ChangePasswordForm {
     }
 }
 
+impl ChangePasswordForm {
+    fn view_password_strength(&self) -> Html {
+        let password = self.form.model().password;
+        if password.is_empty() {
+            return html! {};
+        }
+        let strength = estimate_password_strength(&password, &[self.common.username.as_str()]);
+        let min_strength = self.password_policy.min_strength;
+        let color = if strength.score < min_strength.max(1) {
+            "bg-danger"
+        } else if strength.score < MAX_SCORE {
+            "bg-warning"
+        } else {
+            "bg-success"
+        };
+        let width = format!(
+            "width: {}%",
+            (u32::from(strength.score) + 1) * 100 / (u32::from(MAX_SCORE) + 1)
+        );
+        html! {
+          <div class="mt-2">
+            <div class="progress" style="height: 6px">
+              <div class=format!("progress-bar {}", color) role="progressbar" style=width></div>
+            </div>
+            <small class="form-text text-muted">
+              {format!("Strength: {}/{}", strength.score, MAX_SCORE)}
+              {if min_strength > 0 {
+                format!(" (at least {} required)", min_strength)
+              } else { String::new() }}
+            </small>
+            {if let Err(e) = self.password_policy.check_requirements(&password) { html! {
+              <div class="form-text text-danger small">{e}</div>
+            }} else { html! {} }}
+            <ul class="form-text text-muted small mb-0">
+              {for strength.feedback.iter().map(|f| html! {<li>{f}</li>})}
+            </ul>
+          </div>
+        }
+    }
+}
+
 impl Component for ChangePasswordForm {
     type Message = Msg;
     type Properties = Props;
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
-        ChangePasswordForm {
+        let mut form = ChangePasswordForm {
             common: CommonComponentParts::<Self>::create(props, link),
             form: yew_form::Form::<FormModel>::new(FormModel::default()),
             opaque_data: OpaqueData::None,
             route_dispatcher: RouteAgentDispatcher::new(),
-        }
+            password_policy: PasswordPolicy::default(),
+        };
+        form.common.call_graphql::<GetPasswordPolicy, _>(
+            get_password_policy::Variables {},
+            Msg::PasswordPolicyResponse,
+            "Error trying to fetch the password policy",
+        );
+        form
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
@@ -277,6 +385,7 @@ impl Component for ChangePasswordForm {
                   <div class="invalid-feedback">
                     {&self.form.field_message("password")}
                   </div>
+                  {self.view_password_strength()}
                 </div>
               </div>
               <div class="form-group row mb-3">
@@ -318,6 +427,8 @@ impl Component for ChangePasswordForm {
                 </NavButton>
               </div>
             </form>
+            <TotpSetup username=self.common.username.clone() is_admin=is_admin />
+            <WebauthnSetup username=self.common.username.clone() is_admin=is_admin />
           </>
         }
     }
@@ -22,6 +22,15 @@ use yew_router::{
 )]
 pub struct CreateGroup;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/create_dynamic_group.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct CreateDynamicGroup;
+
 pub struct CreateGroupForm {
     common: CommonComponentParts<Self>,
     route_dispatcher: RouteAgentDispatcher,
@@ -32,12 +41,13 @@ pub struct CreateGroupForm {
 pub struct CreateGroupModel {
     #[validate(length(min = 1, message = "Groupname is required"))]
     groupname: String,
+    filter: String,
 }
 
 pub enum Msg {
     Update,
     SubmitForm,
-    CreateGroupResponse(Result<create_group::ResponseData>),
+    CreateGroupResponse(Result<String>),
 }
 
 impl CommonComponent<CreateGroupForm> for CreateGroupForm {
@@ -49,21 +59,33 @@ impl CommonComponent<CreateGroupForm> for CreateGroupForm {
                     bail!("Check the form for errors");
                 }
                 let model = self.form.model();
-                let req = create_group::Variables {
-                    name: model.groupname,
-                };
-                self.common.call_graphql::<CreateGroup, _>(
-                    req,
-                    Msg::CreateGroupResponse,
-                    "Error trying to create group",
-                );
+                let filter = model.filter.trim();
+                if filter.is_empty() {
+                    let req = create_group::Variables {
+                        name: model.groupname,
+                    };
+                    self.common.call_graphql::<CreateGroup, _>(
+                        req,
+                        |r| Msg::CreateGroupResponse(r.map(|r| r.create_group.display_name)),
+                        "Error trying to create group",
+                    );
+                } else {
+                    let req = create_dynamic_group::Variables {
+                        name: model.groupname,
+                        filter: filter.to_string(),
+                    };
+                    self.common.call_graphql::<CreateDynamicGroup, _>(
+                        req,
+                        |r| {
+                            Msg::CreateGroupResponse(r.map(|r| r.create_dynamic_group.display_name))
+                        },
+                        "Error trying to create group",
+                    );
+                }
                 Ok(true)
             }
             Msg::CreateGroupResponse(response) => {
-                ConsoleService::log(&format!(
-                    "Created group '{}'",
-                    &response?.create_group.display_name
-                ));
+                ConsoleService::log(&format!("Created group '{}'", &response?));
                 self.route_dispatcher
                     .send(RouteRequest::ChangeRoute(Route::from(AppRoute::ListGroups)));
                 Ok(true)
@@ -125,6 +147,25 @@ impl Component for CreateGroupForm {
                   </div>
                 </div>
               </div>
+              <div class="form-group row mb-3">
+                <label for="filter"
+                  class="form-label col-4 col-form-label">
+                  {"Member filter:"}
+                </label>
+                <div class="col-8">
+                  <Field
+                    form=&self.form
+                    field_name="filter"
+                    class="form-control"
+                    class_invalid="is-invalid has-error"
+                    class_valid="has-success"
+                    placeholder="(&(objectClass=person)(sn=Smith))"
+                    oninput=self.common.callback(|_| Msg::Update) />
+                  <small class="form-text text-muted">
+                    {"Optional. The members of a dynamic group are the users matching this LDAP filter."}
+                  </small>
+                </div>
+              </div>
               <div class="form-group row justify-content-center">
                 <button
                   class="btn btn-primary col-auto col-form-label"
@@ -1,13 +1,21 @@
 use crate::{
-    components::router::AppRoute,
+    components::{
+        change_password::{get_password_policy, to_password_policy, GetPasswordPolicy},
+        router::AppRoute,
+    },
     infra::{
         api::HostService,
         common_component::{CommonComponent, CommonComponentParts},
     },
 };
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use graphql_client::GraphQLQuery;
-use lldap_auth::{opaque, registration};
+use lldap_auth::{
+    opaque,
+    password_history::password_history_digest,
+    password_strength::{PasswordPolicy, MAX_SCORE, MIN_PASSWORD_LENGTH},
+    registration,
+};
 use validator_derive::Validate;
 use yew::prelude::*;
 use yew::services::ConsoleService;
@@ -26,10 +34,23 @@ use yew_router::{
 )]
 pub struct CreateUser;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/validate_user.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ValidateUser;
+
 pub struct CreateUserForm {
     common: CommonComponentParts<Self>,
     route_dispatcher: RouteAgentDispatcher,
     form: yew_form::Form<CreateUserModel>,
+    /// The requirements of the server for new passwords.
+    password_policy: PasswordPolicy,
+    /// The problems found by the server with the last submission, as (field, message).
+    validation_errors: Vec<(String, String)>,
 }
 
 #[derive(Model, Validate, PartialEq, Eq, Clone, Default)]
@@ -59,8 +80,10 @@ fn empty_or_long(value: &str) -> Result<(), validator::ValidationError> {
 }
 
 pub enum Msg {
+    PasswordPolicyResponse(Result<get_password_policy::ResponseData>),
     Update,
     SubmitForm,
+    ValidateUserResponse(Result<validate_user::ResponseData>),
     CreateUserResponse(Result<create_user::ResponseData>),
     SuccessfulCreation,
     RegistrationStartResponse(
@@ -75,12 +98,61 @@ pub enum Msg {
 impl CommonComponent<CreateUserForm> for CreateUserForm {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
+            Msg::PasswordPolicyResponse(response) => {
+                self.password_policy = to_password_policy(response?.password_policy);
+                Ok(true)
+            }
             Msg::Update => Ok(true),
             Msg::SubmitForm => {
                 if !self.form.validate() {
                     bail!("Check the form for errors");
                 }
                 let model = self.form.model();
+                if !model.password.is_empty() {
+                    self.password_policy
+                        .check(
+                            &model.password,
+                            &[model.username.as_str(), model.email.as_str()],
+                        )
+                        .map_err(|e| anyhow!(e))?;
+                }
+                self.validation_errors.clear();
+                let to_option = |s: String| if s.is_empty() { None } else { Some(s) };
+                let req = validate_user::Variables {
+                    user: validate_user::CreateUserInput {
+                        id: model.username,
+                        email: model.email,
+                        displayName: to_option(model.display_name),
+                        firstName: to_option(model.first_name),
+                        lastName: to_option(model.last_name),
+                        avatar: None,
+                        preferredLanguage: None,
+                        emailAliases: None,
+                        sshPublicKeys: None,
+                        uidNumber: None,
+                        gidNumber: None,
+                        homeDirectory: None,
+                        loginShell: None,
+                        attributes: None,
+                    },
+                };
+                self.common.call_graphql::<ValidateUser, _>(
+                    req,
+                    Msg::ValidateUserResponse,
+                    "Error trying to validate user",
+                );
+                Ok(true)
+            }
+            Msg::ValidateUserResponse(response) => {
+                self.validation_errors = response?
+                    .validate_user
+                    .into_iter()
+                    .map(|e| (e.field, e.message))
+                    .collect();
+                if !self.validation_errors.is_empty() {
+                    return Ok(true);
+                }
+                let model = self.form.model();
                 let to_option = |s: String| if s.is_empty() { None } else { Some(s) };
                 let req = create_user::Variables {
                     user: create_user::CreateUserInput {
@@ -90,6 +162,14 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
                         firstName: to_option(model.first_name),
                         lastName: to_option(model.last_name),
                         avatar: None,
+                        preferredLanguage: None,
+                        emailAliases: None,
+                        sshPublicKeys: None,
+                        uidNumber: None,
+                        gidNumber: None,
+                        homeDirectory: None,
+                        loginShell: None,
+                        attributes: None,
                     },
                 };
                 self.common.call_graphql::<CreateUser, _>(
@@ -139,9 +219,14 @@ impl CommonComponent<CreateUserForm> for CreateUserForm {
                     response.registration_response,
                     &mut rng,
                 )?;
+                let model = self.form.model();
+                let password_history_digest =
+                    password_history_digest(&model.username, &model.password)
+                        .map_err(anyhow::Error::msg)?;
                 let req = registration::ClientRegistrationFinishRequest {
                     server_data: response.server_data,
                     registration_upload: registration_upload.message,
+                    password_history_digest: Some(password_history_digest),
                 };
                 self.common
                     .call_backend(
@@ -174,11 +259,19 @@ impl Component for CreateUserForm {
     type Properties = ();
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
-        Self {
+        let mut form = Self {
             common: CommonComponentParts::<Self>::create(props, link),
             route_dispatcher: RouteAgentDispatcher::new(),
             form: yew_form::Form::<CreateUserModel>::new(CreateUserModel::default()),
-        }
+            password_policy: PasswordPolicy::default(),
+            validation_errors: Vec::new(),
+        };
+        form.common.call_graphql::<GetPasswordPolicy, _>(
+            get_password_policy::Variables {},
+            Msg::PasswordPolicyResponse,
+            "Error trying to fetch the password policy",
+        );
+        form
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
@@ -191,6 +284,8 @@ impl Component for CreateUserForm {
 
     fn view(&self) -> Html {
         type Field = yew_form::Field<CreateUserModel>;
+        let policy = &self.password_policy;
+        let password = self.form.model().password;
         html! {
           <div class="row justify-content-center">
             <form class="form py-3" style="max-width: 636px">
@@ -216,6 +311,7 @@ impl Component for CreateUserForm {
                   <div class="invalid-feedback">
                     {&self.form.field_message("username")}
                   </div>
+                  {self.view_validation_errors(|field| field == "id")}
                 </div>
               </div>
               <div class="form-group row mb-3">
@@ -238,6 +334,7 @@ impl Component for CreateUserForm {
                   <div class="invalid-feedback">
                     {&self.form.field_message("email")}
                   </div>
+                  {self.view_validation_errors(|field| field == "email")}
                 </div>
               </div>
               <div class="form-group row mb-3">
@@ -315,6 +412,30 @@ impl Component for CreateUserForm {
                   <div class="invalid-feedback">
                     {&self.form.field_message("password")}
                   </div>
+                  <small class="form-text text-muted">
+                    {format!(
+                      "At least {} characters",
+                      policy.min_length.max(MIN_PASSWORD_LENGTH)
+                    )}
+                    {if policy.min_character_classes > 0 {
+                      format!(
+                        ", using at least {} of lowercase, uppercase, digits and symbols",
+                        policy.min_character_classes
+                      )
+                    } else { String::new() }}
+                    {if policy.min_strength > 0 {
+                      format!(
+                        ", with a strength of at least {} out of {}",
+                        policy.min_strength, MAX_SCORE
+                      )
+                    } else { String::new() }}
+                  </small>
+                  {match policy.check_requirements(&password) {
+                    Err(e) if !password.is_empty() => html! {
+                      <div class="form-text text-danger small">{e}</div>
+                    },
+                    _ => html! {},
+                  }}
                 </div>
               </div>
               <div class="form-group row mb-3">
@@ -347,6 +468,7 @@ impl Component for CreateUserForm {
                   {"Submit"}
                 </button>
               </div>
+              {self.view_validation_errors(|field| field != "id" && field != "email")}
             </form>
             {
               if let Some(e) = &self.common.error {
@@ -361,3 +483,18 @@ impl Component for CreateUserForm {
         }
     }
 }
+
+impl CreateUserForm {
+    /// The problems found by the server with the fields matching the predicate.
+    fn view_validation_errors(&self, is_shown: impl Fn(&str) -> bool) -> Html {
+        html! {
+          <>
+            {for self.validation_errors.iter().filter(|(field, _)| is_shown(field)).map(
+              |(_, message)| html! {
+                <div class="form-text text-danger small">{message}</div>
+              }
+            )}
+          </>
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::infra::common_component::{CommonComponent, CommonComponentParts};
+use anyhow::{anyhow, Result};
+use graphql_client::GraphQLQuery;
+use wasm_bindgen::JsCast;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_membership_report.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetMembershipReport;
+
+use get_membership_report::{GetMembershipReportMembershipReport as Row, MembershipReportView};
+
+/// Quote a CSV field if it contains a separator, a quote or a line break.
+fn escape_csv_field(field: &str) -> String {
+    if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn make_csv_line(fields: &[&Option<String>]) -> String {
+    let mut line = fields
+        .iter()
+        .map(|f| escape_csv_field(f.as_deref().unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+fn make_csv(view: &MembershipReportView, rows: &[Row]) -> String {
+    let group_first = matches!(view, MembershipReportView::GROUP);
+    let header = if group_first {
+        "group,user_id,display_name,email\r\n"
+    } else {
+        "user_id,display_name,email,group\r\n"
+    };
+    std::iter::once(header.to_owned())
+        .chain(rows.iter().map(|row| {
+            if group_first {
+                make_csv_line(&[&row.group, &row.user_id, &row.display_name, &row.email])
+            } else {
+                make_csv_line(&[&row.user_id, &row.display_name, &row.email, &row.group])
+            }
+        }))
+        .collect()
+}
+
+/// Save the CSV through a temporary link, since the report comes from a GraphQL response.
+fn download_csv(file_name: &str, csv: &str) -> Result<()> {
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or_else(|| anyhow!("Could not get the document"))?;
+    let link = document
+        .create_element("a")
+        .map_err(|_| anyhow!("Could not create the download link"))?;
+    let url = format!(
+        "data:text/csv;charset=utf-8,{}",
+        String::from(js_sys::encode_uri_component(csv))
+    );
+    link.set_attribute("href", &url)
+        .and_then(|_| link.set_attribute("download", file_name))
+        .map_err(|_| anyhow!("Could not create the download link"))?;
+    link.dyn_into::<web_sys::HtmlElement>()
+        .map_err(|_| anyhow!("Could not create the download link"))?
+        .click();
+    Ok(())
+}
+
+/// The buttons to download who is in which group as CSV, by group or by user.
+pub struct ExportMembershipReport {
+    common: CommonComponentParts<Self>,
+}
+
+pub enum Msg {
+    Export(MembershipReportView),
+    Response(
+        MembershipReportView,
+        Result<get_membership_report::ResponseData>,
+    ),
+}
+
+impl CommonComponent<ExportMembershipReport> for ExportMembershipReport {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Export(view) => {
+                let is_group_view = matches!(view, MembershipReportView::GROUP);
+                self.common.call_graphql::<GetMembershipReport, _>(
+                    get_membership_report::Variables { view: Some(view) },
+                    move |response| {
+                        let view = if is_group_view {
+                            MembershipReportView::GROUP
+                        } else {
+                            MembershipReportView::USER
+                        };
+                        Msg::Response(view, response)
+                    },
+                    "Error trying to fetch the membership report",
+                );
+            }
+            Msg::Response(view, response) => {
+                let csv = make_csv(&view, &response?.membership_report);
+                download_csv("membership_report.csv", &csv)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ExportMembershipReport {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        Self {
+            common: CommonComponentParts::<Self>::create(props, link),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <div class="btn-group ms-2">
+              <button
+                class="btn btn-secondary"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::Export(MembershipReportView::GROUP))>
+                <i class="bi-download me-2"></i>
+                {"Export membership report"}
+              </button>
+              <button
+                class="btn btn-outline-secondary"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::Export(MembershipReportView::USER))>
+                {"By user"}
+              </button>
+            </div>
+            {if let Some(e) = &self.common.error {
+              html! {
+                <div class="alert alert-danger mt-2">
+                  {e.to_string()}
+                </div>
+              }
+            } else { html! {} }}
+          </>
+        }
+    }
+}
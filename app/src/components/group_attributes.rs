@@ -0,0 +1,277 @@
+use crate::{
+    components::{
+        group_details::{
+            update_group, AttributeSchema, AttributeType, AttributeValue, UpdateGroup,
+        },
+        user_details_form::to_attribute_values,
+    },
+    infra::common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::Result;
+use yew::prelude::*;
+
+/// The custom attributes of a group. The admins can change all of them, the group managers only
+/// the editable ones.
+pub struct GroupAttributes {
+    common: CommonComponentParts<Self>,
+    /// The saved values, to only send the attributes that changed.
+    attributes: Vec<AttributeValue>,
+    /// The inputs, by name. The values of the lists are on separate lines.
+    texts: Vec<(String, String)>,
+    just_updated: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub group_id: i64,
+    pub is_admin: bool,
+    pub schema: Vec<AttributeSchema>,
+    pub attributes: Vec<AttributeValue>,
+}
+
+pub enum Msg {
+    /// The input of an attribute changed.
+    Changed(String, String),
+    /// Nothing to do, but re-render.
+    Update,
+    Submit,
+    Updated(Result<update_group::ResponseData>),
+}
+
+fn to_texts(attributes: &[AttributeValue]) -> Vec<(String, String)> {
+    attributes
+        .iter()
+        .map(|a| (a.name.clone(), a.values.join("\n")))
+        .collect()
+}
+
+impl CommonComponent<GroupAttributes> for GroupAttributes {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Changed(name, text) => {
+                self.just_updated = false;
+                match self.texts.iter_mut().find(|(n, _)| n == &name) {
+                    Some((_, current)) => *current = text,
+                    None => self.texts.push((name, text)),
+                }
+            }
+            Msg::Update => (),
+            Msg::Submit => return self.submit(),
+            Msg::Updated(response) => {
+                self.common.cancel_task();
+                response?;
+                self.attributes = self
+                    .common
+                    .schema
+                    .iter()
+                    .map(|schema| AttributeValue {
+                        name: schema.name.clone(),
+                        values: to_attribute_values(schema.is_list, &self.text(&schema.name)),
+                    })
+                    .filter(|a| !a.values.is_empty())
+                    .collect();
+                self.just_updated = true;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl GroupAttributes {
+    fn can_edit(&self, schema: &AttributeSchema) -> bool {
+        self.common.is_admin || schema.is_editable
+    }
+
+    fn text(&self, name: &str) -> String {
+        self.texts
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, text)| text.clone())
+            .unwrap_or_default()
+    }
+
+    fn submit(&mut self) -> Result<bool> {
+        let changed_attributes = self
+            .common
+            .schema
+            .iter()
+            .filter(|schema| self.can_edit(schema))
+            .filter_map(|schema| {
+                let values = to_attribute_values(schema.is_list, &self.text(&schema.name));
+                let saved_values = self
+                    .attributes
+                    .iter()
+                    .find(|a| a.name == schema.name)
+                    .map(|a| a.values.clone())
+                    .unwrap_or_default();
+                (values != saved_values).then(|| update_group::AttributeValueInput {
+                    name: schema.name.clone(),
+                    values,
+                })
+            })
+            .collect::<Vec<_>>();
+        if changed_attributes.is_empty() {
+            return Ok(false);
+        }
+        self.common.call_graphql::<UpdateGroup, _>(
+            update_group::Variables {
+                group: update_group::UpdateGroupInput {
+                    id: self.common.group_id,
+                    displayName: None,
+                    gidNumber: None,
+                    email: None,
+                    attributes: Some(changed_attributes),
+                },
+            },
+            Msg::Updated,
+            "Error trying to update the group attributes",
+        );
+        Ok(false)
+    }
+
+    /// The input matching the type of the attribute, like on the user details page.
+    fn view_attribute(&self, schema: &AttributeSchema) -> Html {
+        let id = format!("group_attribute_{}", schema.name);
+        let text = self.text(&schema.name);
+        let name = schema.name.clone();
+        let oninput = self
+            .common
+            .callback(move |e: InputData| Msg::Changed(name.clone(), e.value));
+        let input = if !self.can_edit(schema) {
+            html! {
+              <span id=id.clone() class="form-control-static text-break">
+                {text.lines().collect::<Vec<_>>().join(", ")}
+              </span>
+            }
+        } else if schema.is_list || matches!(schema.attribute_type, AttributeType::BINARY) {
+            let placeholder = if schema.is_list {
+                "One value per line"
+            } else {
+                "Base64"
+            };
+            html! {
+              <textarea
+                class="form-control"
+                id=id.clone()
+                rows="3"
+                placeholder=placeholder
+                value=text
+                oninput=oninput />
+            }
+        } else if matches!(schema.attribute_type, AttributeType::BOOLEAN) {
+            let name = schema.name.clone();
+            html! {
+              <select
+                class="form-select"
+                id=id.clone()
+                onchange=self.common.callback(move |e: ChangeData| match e {
+                    ChangeData::Select(select) => Msg::Changed(name.clone(), select.value()),
+                    _ => Msg::Update,
+                })>
+                <option value="" selected={text.is_empty()}>{"Not set"}</option>
+                <option value="true" selected={text == "true"}>{"Yes"}</option>
+                <option value="false" selected={text == "false"}>{"No"}</option>
+              </select>
+            }
+        } else {
+            let input_type = match schema.attribute_type {
+                AttributeType::INTEGER => "number",
+                AttributeType::DATE => "date",
+                _ => "text",
+            };
+            html! {
+              <input
+                class="form-control"
+                id=id.clone()
+                type=input_type
+                value=text
+                oninput=oninput />
+            }
+        };
+        html! {
+          <div class="form-group row mb-3">
+            <label for=id
+              class="form-label col-4 col-form-label">
+              {format!("{}: ", schema.name)}
+            </label>
+            <div class="col-8">
+              {input}
+            </div>
+          </div>
+        }
+    }
+}
+
+impl Component for GroupAttributes {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let attributes = props.attributes.clone();
+        let texts = to_texts(&attributes);
+        GroupAttributes {
+            common: CommonComponentParts::<Self>::create(props, link),
+            attributes,
+            texts,
+            just_updated: false,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if props != self.common.props {
+            self.attributes = props.attributes.clone();
+            self.texts = to_texts(&self.attributes);
+        }
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        if self.common.schema.is_empty() {
+            return html! {};
+        }
+        html! {
+          <>
+            <h5 class="fw-bold">{"Attributes"}</h5>
+            <form class="form">
+              {
+                self.common.schema
+                  .iter()
+                  .map(|schema| self.view_attribute(schema))
+                  .collect::<Vec<_>>()
+              }
+              {if self.common.schema.iter().any(|schema| self.can_edit(schema)) {
+                html! {
+                  <button
+                    type="button"
+                    class="btn btn-primary mb-3"
+                    disabled=self.common.is_task_running()
+                    onclick=self.common.callback(|_| Msg::Submit)>
+                    {"Save attributes"}
+                  </button>
+                }
+              } else { html! {} }}
+            </form>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            <div hidden=!self.just_updated>
+              <div class="alert alert-success mb-3">{"Attributes successfully updated!"}</div>
+            </div>
+          </>
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{
     components::{
         add_group_member::{self, AddGroupMemberComponent},
+        group_attributes::GroupAttributes,
+        group_managers::GroupManagers,
+        group_membership_requests::GroupMembershipRequests,
         remove_user_from_group::RemoveUserFromGroupComponent,
         router::{AppRoute, Link},
     },
@@ -19,8 +22,21 @@ use yew::prelude::*;
 )]
 pub struct GetGroupDetails;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/update_group.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct UpdateGroup;
+
 pub type Group = get_group_details::GetGroupDetailsGroup;
 pub type User = get_group_details::GetGroupDetailsGroupUsers;
+pub type AttributeSchema = get_group_details::GetGroupDetailsGroupAttributeSchema;
+pub type AttributeValue = get_group_details::GetGroupDetailsGroupAttributes;
+pub type MembershipRequest = get_group_details::GetGroupDetailsGroupMembershipRequests;
+pub use get_group_details::AttributeType;
 pub type AddGroupMemberUser = add_group_member::User;
 
 pub struct GroupDetails {
@@ -28,6 +44,12 @@ pub struct GroupDetails {
     /// The group info. If none, the error is in `error`. If `error` is None, then we haven't
     /// received the server response yet.
     group: Option<Group>,
+    /// The custom group attributes, sent with the group.
+    attribute_schema: Vec<AttributeSchema>,
+    /// The input used to edit the POSIX group ID.
+    gid_number: NodeRef,
+    /// The input used to edit the email address.
+    email: NodeRef,
 }
 
 /// State machine describing the possible transitions of the component state.
@@ -38,11 +60,21 @@ pub enum Msg {
     OnError(Error),
     OnUserAddedToGroup(AddGroupMemberUser),
     OnUserRemovedFromGroup((String, i64)),
+    /// A membership request was made or approved: reload the members and the requests.
+    OnMembershipRequestChanged,
+    /// The "Save" button next to the GID number was clicked.
+    SubmitGidNumber,
+    GidNumberUpdated((Option<i64>, Result<update_group::ResponseData>)),
+    /// The "Save" button next to the email address was clicked.
+    SubmitEmail,
+    EmailUpdated((Option<String>, Result<update_group::ResponseData>)),
 }
 
 #[derive(yew::Properties, Clone, PartialEq, Eq)]
 pub struct Props {
     pub group_id: i64,
+    /// The group managers can only change the members and the editable attributes.
+    pub is_admin: bool,
 }
 
 impl GroupDetails {
@@ -56,6 +88,70 @@ impl GroupDetails {
         );
     }
 
+    fn submit_gid_number(&mut self) -> Result<bool> {
+        let group = self.group.as_ref().unwrap();
+        let value = self
+            .gid_number
+            .cast::<web_sys::HtmlInputElement>()
+            .expect("GID number input should be present")
+            .value();
+        // The GID can be changed but not removed.
+        if value.trim().is_empty() {
+            return Ok(false);
+        }
+        let gid_number = match value.trim().parse::<i32>() {
+            Ok(gid) => i64::from(gid),
+            Err(_) => bail!("Invalid GID number: {}", value),
+        };
+        if group.gid_number == Some(gid_number) {
+            return Ok(false);
+        }
+        self.common.call_graphql::<UpdateGroup, _>(
+            update_group::Variables {
+                group: update_group::UpdateGroupInput {
+                    id: group.id,
+                    displayName: None,
+                    gidNumber: Some(gid_number),
+                    email: None,
+                    attributes: None,
+                },
+            },
+            move |response| Msg::GidNumberUpdated((Some(gid_number), response)),
+            "Error trying to update the group",
+        );
+        Ok(false)
+    }
+
+    fn submit_email(&mut self) -> Result<bool> {
+        let group = self.group.as_ref().unwrap();
+        let value = self
+            .email
+            .cast::<web_sys::HtmlInputElement>()
+            .expect("Email input should be present")
+            .value()
+            .trim()
+            .to_string();
+        let email = if value.is_empty() { None } else { Some(value) };
+        if group.email == email {
+            return Ok(false);
+        }
+        self.common.call_graphql::<UpdateGroup, _>(
+            update_group::Variables {
+                group: update_group::UpdateGroupInput {
+                    id: group.id,
+                    displayName: None,
+                    gidNumber: None,
+                    // An empty string removes the address.
+                    email: Some(email.clone().unwrap_or_default()),
+                    attributes: None,
+                },
+            },
+            move |response| Msg::EmailUpdated((email, response)),
+            "Error trying to update the group",
+        );
+        Ok(false)
+    }
+
     fn view_messages(&self, error: &Option<Error>) -> Html {
         if let Some(e) = error {
             html! {
@@ -101,12 +197,76 @@ impl GroupDetails {
                     <span id="uuid" class="form-constrol-static">{g.uuid.to_string()}</span>
                   </div>
                 </div>
+                {if let Some(filter) = &g.dynamic_filter {
+                  html! {
+                    <div class="form-group row mb-3">
+                      <label for="dynamicFilter"
+                        class="form-label col-4 col-form-label">
+                        {"Member filter: "}
+                      </label>
+                      <div class="col-8">
+                        <code id="dynamicFilter" class="form-constrol-static">{filter}</code>
+                      </div>
+                    </div>
+                  }
+                } else { html! {} }}
+                <div class="form-group row mb-3">
+                  <label for="gidNumber"
+                    class="form-label col-4 col-form-label">
+                    {"GID number: "}
+                  </label>
+                  <div class="col-8">
+                    <div class="input-group">
+                      <input
+                        class="form-control"
+                        id="gidNumber"
+                        type="number"
+                        value=g.gid_number.map(|gid| gid.to_string()).unwrap_or_default()
+                        readonly=!self.common.is_admin
+                        ref=self.gid_number.clone() />
+                      {self.view_save_button(self.common.callback(|_| Msg::SubmitGidNumber))}
+                    </div>
+                  </div>
+                </div>
+                <div class="form-group row mb-3">
+                  <label for="email"
+                    class="form-label col-4 col-form-label">
+                    {"Email: "}
+                  </label>
+                  <div class="col-8">
+                    <div class="input-group">
+                      <input
+                        class="form-control"
+                        id="email"
+                        type="email"
+                        value=g.email.clone().unwrap_or_default()
+                        readonly=!self.common.is_admin
+                        ref=self.email.clone() />
+                      {self.view_save_button(self.common.callback(|_| Msg::SubmitEmail))}
+                    </div>
+                  </div>
+                </div>
               </form>
             </div>
           </>
         }
     }
 
+    fn view_save_button(&self, onclick: Callback<MouseEvent>) -> Html {
+        if !self.common.is_admin {
+            return html! {};
+        }
+        html! {
+          <button
+            type="button"
+            class="btn btn-outline-primary"
+            disabled=self.common.is_task_running()
+            onclick=onclick>
+            {"Save"}
+          </button>
+        }
+    }
+
     fn view_user_list(&self, g: &Group) -> Html {
         let make_user_row = |user: &User| {
             let user_id = user.id.clone();
@@ -120,11 +280,15 @@ impl GroupDetails {
                 </td>
                 <td>{display_name}</td>
                 <td>
-                  <RemoveUserFromGroupComponent
-                    username=user_id
-                    group_id=g.id
-                    on_user_removed_from_group=self.common.callback(Msg::OnUserRemovedFromGroup)
-                    on_error=self.common.callback(Msg::OnError)/>
+                  {if g.dynamic_filter.is_none() {
+                    html! {
+                      <RemoveUserFromGroupComponent
+                        username=user_id
+                        group_id=g.id
+                        on_user_removed_from_group=self.common.callback(Msg::OnUserRemovedFromGroup)
+                        on_error=self.common.callback(Msg::OnError)/>
+                    }
+                  } else { html! {} }}
                 </td>
               </tr>
             }
@@ -160,6 +324,9 @@ impl GroupDetails {
     }
 
     fn view_add_user_button(&self, g: &Group) -> Html {
+        if g.dynamic_filter.is_some() {
+            return html! {};
+        }
         let users: Vec<_> = g
             .users
             .iter()
@@ -172,8 +339,47 @@ impl GroupDetails {
             <AddGroupMemberComponent
                 group_id=g.id
                 users=users
+                requires_approval=g.requires_approval && !self.common.is_admin
                 on_error=self.common.callback(Msg::OnError)
-                on_user_added_to_group=self.common.callback(Msg::OnUserAddedToGroup)/>
+                on_user_added_to_group=self.common.callback(Msg::OnUserAddedToGroup)
+                on_membership_requested=self.common.callback(|_| Msg::OnMembershipRequestChanged)/>
+        }
+    }
+
+    fn view_membership_requests(&self, g: &Group) -> Html {
+        if !g.requires_approval {
+            return html! {};
+        }
+        html! {
+          <div class="mt-4">
+            <GroupMembershipRequests
+              group_id=g.id
+              requests=g.membership_requests.clone()
+              on_request_approved=self.common.callback(|_| Msg::OnMembershipRequestChanged) />
+          </div>
+        }
+    }
+
+    fn view_attributes(&self, g: &Group) -> Html {
+        html! {
+          <div class="mt-4">
+            <GroupAttributes
+              group_id=g.id
+              is_admin=self.common.is_admin
+              schema=self.attribute_schema.clone()
+              attributes=g.attributes.clone() />
+          </div>
+        }
+    }
+
+    fn view_managers(&self, g: &Group) -> Html {
+        if !self.common.is_admin || g.dynamic_filter.is_some() {
+            return html! {};
+        }
+        html! {
+          <div class="mt-4">
+            <GroupManagers group_id=g.id managers=g.managers.clone() />
+          </div>
         }
     }
 }
@@ -182,7 +388,10 @@ impl CommonComponent<GroupDetails> for GroupDetails {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
             Msg::GroupDetailsResponse(response) => match response {
-                Ok(group) => self.group = Some(group.group),
+                Ok(data) => {
+                    self.group = Some(data.group);
+                    self.attribute_schema = data.group_attribute_schema;
+                }
                 Err(e) => {
                     self.group = None;
                     bail!("Error getting user details: {}", e);
@@ -202,6 +411,19 @@ impl CommonComponent<GroupDetails> for GroupDetails {
                     .users
                     .retain(|u| u.id != user_id);
             }
+            Msg::OnMembershipRequestChanged => self.get_group_details(),
+            Msg::SubmitGidNumber => return self.submit_gid_number(),
+            Msg::GidNumberUpdated((gid_number, response)) => {
+                self.common.cancel_task();
+                response?;
+                self.group.as_mut().unwrap().gid_number = gid_number;
+            }
+            Msg::SubmitEmail => return self.submit_email(),
+            Msg::EmailUpdated((email, response)) => {
+                self.common.cancel_task();
+                response?;
+                self.group.as_mut().unwrap().email = email;
+            }
         }
         Ok(true)
     }
@@ -219,6 +441,9 @@ impl Component for GroupDetails {
         let mut table = Self {
             common: CommonComponentParts::<Self>::create(props, link),
             group: None,
+            attribute_schema: Vec::new(),
+            gid_number: NodeRef::default(),
+            email: NodeRef::default(),
         };
         table.get_group_details();
         table
@@ -240,9 +465,12 @@ impl Component for GroupDetails {
                 html! {
                     <div>
                       {self.view_details(u)}
+                      {self.view_attributes(u)}
                       {self.view_user_list(u)}
                       {self.view_add_user_button(u)}
                       {self.view_messages(error)}
+                      {self.view_membership_requests(u)}
+                      {self.view_managers(u)}
                     </div>
                 }
             }
@@ -0,0 +1,209 @@
+use crate::{
+    components::router::{AppRoute, Link},
+    infra::common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::{bail, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/add_group_manager.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct AddGroupManager;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/remove_group_manager.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RemoveGroupManager;
+
+/// The users who can change the members of a group without being admins. Only the admins can
+/// change them.
+pub struct GroupManagers {
+    common: CommonComponentParts<Self>,
+    managers: Vec<String>,
+    new_manager: NodeRef,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub group_id: i64,
+    pub managers: Vec<String>,
+}
+
+pub enum Msg {
+    Add,
+    AddResponse((String, Result<add_group_manager::ResponseData>)),
+    Remove(String),
+    RemoveResponse((String, Result<remove_group_manager::ResponseData>)),
+}
+
+impl CommonComponent<GroupManagers> for GroupManagers {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Add => {
+                let input = self
+                    .new_manager
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("Manager input should be present");
+                let user_id = input.value().trim().to_string();
+                if user_id.is_empty() {
+                    return Ok(false);
+                }
+                if self.managers.contains(&user_id) {
+                    bail!("{} already manages the group", user_id);
+                }
+                self.common.call_graphql::<AddGroupManager, _>(
+                    add_group_manager::Variables {
+                        group_id: self.common.group_id,
+                        user_id: user_id.clone(),
+                    },
+                    move |response| Msg::AddResponse((user_id, response)),
+                    "Error trying to add the manager",
+                );
+            }
+            Msg::AddResponse((user_id, response)) => {
+                self.common.cancel_task();
+                response?;
+                self.managers.push(user_id);
+                self.managers.sort();
+                if let Some(input) = self.new_manager.cast::<web_sys::HtmlInputElement>() {
+                    input.set_value("");
+                }
+            }
+            Msg::Remove(user_id) => {
+                self.common.call_graphql::<RemoveGroupManager, _>(
+                    remove_group_manager::Variables {
+                        group_id: self.common.group_id,
+                        user_id: user_id.clone(),
+                    },
+                    move |response| Msg::RemoveResponse((user_id, response)),
+                    "Error trying to remove the manager",
+                );
+            }
+            Msg::RemoveResponse((user_id, response)) => {
+                self.common.cancel_task();
+                response?;
+                self.managers.retain(|m| m != &user_id);
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl GroupManagers {
+    fn view_manager(&self, user_id: &str) -> Html {
+        let to_remove = user_id.to_string();
+        html! {
+          <tr key=user_id.to_string()>
+            <td>
+              <Link route=AppRoute::UserDetails(user_id.to_string())>
+                {user_id}
+              </Link>
+            </td>
+            <td>
+              <button
+                class="btn btn-danger btn-sm"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(move |_| Msg::Remove(to_remove.clone()))>
+                <i class="bi-x-circle-fill" aria-label="Remove manager" />
+              </button>
+            </td>
+          </tr>
+        }
+    }
+}
+
+impl Component for GroupManagers {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let managers = props.managers.clone();
+        GroupManagers {
+            common: CommonComponentParts::<Self>::create(props, link),
+            managers,
+            new_manager: NodeRef::default(),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if props != self.common.props {
+            self.managers = props.managers.clone();
+        }
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <h5 class="fw-bold">{"Managers"}</h5>
+            <p>
+              {"The managers can add and remove the members of the group from their own page, \
+                without being admins."}
+            </p>
+            <div class="table-responsive">
+              <table class="table table-hover">
+                <thead>
+                  <tr key="headerRow">
+                    <th>{"User Id"}</th>
+                    <th></th>
+                  </tr>
+                </thead>
+                <tbody>
+                  {if self.managers.is_empty() {
+                    html! {
+                      <tr key="EmptyRow">
+                        <td>{"This group has no managers."}</td>
+                        <td/>
+                      </tr>
+                    }
+                  } else {
+                    html! {<>{for self.managers.iter().map(|m| self.view_manager(m))}</>}
+                  }}
+                </tbody>
+              </table>
+            </div>
+            <div class="input-group mb-3">
+              <input
+                class="form-control"
+                type="text"
+                placeholder="User ID"
+                ref=self.new_manager.clone() />
+              <button
+                type="button"
+                class="btn btn-secondary"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::Add)>
+                <i class="bi-person-plus me-2"></i>
+                {"Add manager"}
+              </button>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+          </>
+        }
+    }
+}
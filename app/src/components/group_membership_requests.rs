@@ -0,0 +1,193 @@
+use crate::{
+    components::{
+        group_details::MembershipRequest,
+        router::{AppRoute, Link},
+    },
+    infra::common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/approve_membership_request.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ApproveMembershipRequest;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/deny_membership_request.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DenyMembershipRequest;
+
+/// The pending requests to join a group that requires an approval. The admins and the managers
+/// can approve or deny them, except for the requests they made themselves.
+pub struct GroupMembershipRequests {
+    common: CommonComponentParts<Self>,
+    requests: Vec<MembershipRequest>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub group_id: i64,
+    pub requests: Vec<MembershipRequest>,
+    /// Called with the user ID once a request is approved, to refresh the members.
+    pub on_request_approved: Callback<String>,
+}
+
+pub enum Msg {
+    Approve(i64),
+    ApproveResponse((i64, Result<approve_membership_request::ResponseData>)),
+    Deny(i64),
+    DenyResponse((i64, Result<deny_membership_request::ResponseData>)),
+}
+
+impl CommonComponent<GroupMembershipRequests> for GroupMembershipRequests {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Approve(id) => {
+                self.common.call_graphql::<ApproveMembershipRequest, _>(
+                    approve_membership_request::Variables { id },
+                    move |response| Msg::ApproveResponse((id, response)),
+                    "Error trying to approve the request",
+                );
+            }
+            Msg::ApproveResponse((id, response)) => {
+                self.common.cancel_task();
+                response?;
+                if let Some(request) = self.requests.iter().find(|r| r.id == id) {
+                    self.common
+                        .on_request_approved
+                        .emit(request.user_id.clone());
+                }
+                self.requests.retain(|r| r.id != id);
+            }
+            Msg::Deny(id) => {
+                self.common.call_graphql::<DenyMembershipRequest, _>(
+                    deny_membership_request::Variables { id },
+                    move |response| Msg::DenyResponse((id, response)),
+                    "Error trying to deny the request",
+                );
+            }
+            Msg::DenyResponse((id, response)) => {
+                self.common.cancel_task();
+                response?;
+                self.requests.retain(|r| r.id != id);
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl GroupMembershipRequests {
+    fn view_request(&self, request: &MembershipRequest) -> Html {
+        let id = request.id;
+        html! {
+          <tr key=id.to_string()>
+            <td>
+              <Link route=AppRoute::UserDetails(request.user_id.clone())>
+                {&request.user_id}
+              </Link>
+            </td>
+            <td>{&request.requested_by}</td>
+            <td>{&request.creation_date.naive_local().date()}</td>
+            <td>{&request.expiry_date.naive_local().date()}</td>
+            <td>
+              <button
+                class="btn btn-success btn-sm me-2"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(move |_| Msg::Approve(id))>
+                <i class="bi-check-circle-fill" aria-label="Approve request" />
+              </button>
+              <button
+                class="btn btn-danger btn-sm"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(move |_| Msg::Deny(id))>
+                <i class="bi-x-circle-fill" aria-label="Deny request" />
+              </button>
+            </td>
+          </tr>
+        }
+    }
+}
+
+impl Component for GroupMembershipRequests {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let requests = props.requests.clone();
+        GroupMembershipRequests {
+            common: CommonComponentParts::<Self>::create(props, link),
+            requests,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if props != self.common.props {
+            self.requests = props.requests.clone();
+        }
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <h5 class="fw-bold">{"Membership requests"}</h5>
+            <p>
+              {"The new members of this group have to be approved by an admin or a manager, \
+                other than the one who asked for them."}
+            </p>
+            <div class="table-responsive">
+              <table class="table table-hover">
+                <thead>
+                  <tr key="headerRow">
+                    <th>{"User Id"}</th>
+                    <th>{"Requested by"}</th>
+                    <th>{"Requested on"}</th>
+                    <th>{"Expires on"}</th>
+                    <th></th>
+                  </tr>
+                </thead>
+                <tbody>
+                  {if self.requests.is_empty() {
+                    html! {
+                      <tr key="EmptyRow">
+                        <td>{"There are no pending requests."}</td>
+                        <td/>
+                      </tr>
+                    }
+                  } else {
+                    html! {<>{for self.requests.iter().map(|r| self.view_request(r))}</>}
+                  }}
+                </tbody>
+              </table>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+          </>
+        }
+    }
+}
@@ -3,33 +3,96 @@ use crate::{
         delete_group::DeleteGroup,
         router::{AppRoute, Link},
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        pagination::Pagination,
+    },
 };
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use graphql_client::GraphQLQuery;
+use std::collections::HashSet;
 use yew::prelude::*;
 
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "../schema.graphql",
-    query_path = "queries/get_group_list.graphql",
+    query_path = "queries/list_groups_page.graphql",
     response_derives = "Debug,Clone,PartialEq,Eq",
     custom_scalars_module = "crate::infra::graphql"
 )]
-pub struct GetGroupList;
+pub struct ListGroupsPage;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/delete_groups.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteGroupsQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/archive_group.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ArchiveGroupQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/unarchive_group.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct UnarchiveGroupQuery;
+
+use list_groups_page::{GroupOrderBy, ResponseData};
 
-use get_group_list::ResponseData;
+pub type Group = list_groups_page::ListGroupsPageGroupsPageNodes;
 
-pub type Group = get_group_list::GetGroupListGroups;
+/// The columns that the table can be sorted by.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    DisplayName,
+    CreationDate,
+}
+
+impl From<SortColumn> for GroupOrderBy {
+    fn from(column: SortColumn) -> Self {
+        match column {
+            SortColumn::DisplayName => GroupOrderBy::DISPLAY_NAME,
+            SortColumn::CreationDate => GroupOrderBy::CREATION_DATE,
+        }
+    }
+}
 
 pub struct GroupTable {
     common: CommonComponentParts<Self>,
     groups: Option<Vec<Group>>,
+    selected_groups: HashSet<i64>,
+    show_archived: bool,
+    search: String,
+    pagination: Pagination,
+    order_by: SortColumn,
+    descending: bool,
 }
 
 pub enum Msg {
     ListGroupsResponse(Result<ResponseData>),
     OnGroupDeleted(i64),
+    ToggleGroupSelection(i64),
+    DeleteSelectedGroups,
+    DeleteGroupsResponse(Result<delete_groups_query::ResponseData>),
+    ToggleShowArchived,
+    SearchUpdated(String),
+    PreviousPage,
+    NextPage,
+    SortBy(SortColumn),
+    ToggleArchived(i64),
+    ArchiveGroupResponse(i64, Result<()>),
     OnError(Error),
 }
 
@@ -37,13 +100,130 @@ impl CommonComponent<GroupTable> for GroupTable {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
             Msg::ListGroupsResponse(groups) => {
-                self.groups = Some(groups?.groups.into_iter().collect());
+                self.common.cancel_task();
+                let page = groups?.groups_page;
+                self.pagination.set_page_info(
+                    page.page_info.end_cursor,
+                    page.page_info.has_next_page,
+                    page.total_count,
+                );
+                self.groups = Some(page.nodes);
                 Ok(true)
             }
             Msg::OnError(e) => Err(e),
             Msg::OnGroupDeleted(group_id) => {
                 debug_assert!(self.groups.is_some());
                 self.groups.as_mut().unwrap().retain(|u| u.id != group_id);
+                self.selected_groups.remove(&group_id);
+                self.pagination.total_count -= 1;
+                Ok(true)
+            }
+            Msg::ToggleGroupSelection(group_id) => {
+                if !self.selected_groups.remove(&group_id) {
+                    self.selected_groups.insert(group_id);
+                }
+                Ok(true)
+            }
+            Msg::DeleteSelectedGroups => {
+                self.common.call_graphql::<DeleteGroupsQuery, _>(
+                    delete_groups_query::Variables {
+                        group_ids: self.selected_groups.iter().copied().collect(),
+                    },
+                    Msg::DeleteGroupsResponse,
+                    "Error trying to delete groups",
+                );
+                Ok(true)
+            }
+            Msg::DeleteGroupsResponse(response) => {
+                self.common.cancel_task();
+                let results = response?.delete_groups;
+                let errors = results
+                    .iter()
+                    .filter_map(|r| r.error.as_ref().map(|e| format!("{}: {}", r.id, e)))
+                    .collect::<Vec<_>>();
+                let deleted = results
+                    .into_iter()
+                    .filter(|r| r.ok)
+                    .map(|r| r.id)
+                    .collect::<HashSet<_>>();
+                debug_assert!(self.groups.is_some());
+                self.groups
+                    .as_mut()
+                    .unwrap()
+                    .retain(|g| !deleted.contains(&g.id));
+                self.selected_groups.retain(|id| !deleted.contains(id));
+                self.pagination.total_count -= deleted.len() as i64;
+                if errors.is_empty() {
+                    Ok(true)
+                } else {
+                    Err(anyhow!("Could not delete groups: {}", errors.join(", ")))
+                }
+            }
+            Msg::ToggleShowArchived => {
+                self.show_archived = !self.show_archived;
+                self.groups = None;
+                self.selected_groups.clear();
+                self.pagination.reset();
+                self.get_group_list();
+                Ok(true)
+            }
+            Msg::SearchUpdated(search) => {
+                self.search = search;
+                self.pagination.reset();
+                self.get_group_list();
+                Ok(false)
+            }
+            Msg::PreviousPage => {
+                if self.pagination.go_previous() {
+                    self.get_group_list();
+                }
+                Ok(false)
+            }
+            Msg::NextPage => {
+                if self.pagination.go_next() {
+                    self.get_group_list();
+                }
+                Ok(false)
+            }
+            Msg::SortBy(column) => {
+                if self.order_by == column {
+                    self.descending = !self.descending;
+                } else {
+                    self.order_by = column;
+                    self.descending = false;
+                }
+                self.pagination.reset();
+                self.get_group_list();
+                Ok(true)
+            }
+            Msg::ToggleArchived(group_id) => {
+                if self.show_archived {
+                    self.common.call_graphql::<UnarchiveGroupQuery, _>(
+                        unarchive_group_query::Variables { group_id },
+                        move |r: Result<unarchive_group_query::ResponseData>| {
+                            Msg::ArchiveGroupResponse(group_id, r.map(|_| ()))
+                        },
+                        "Error trying to unarchive group",
+                    );
+                } else {
+                    self.common.call_graphql::<ArchiveGroupQuery, _>(
+                        archive_group_query::Variables { group_id },
+                        move |r: Result<archive_group_query::ResponseData>| {
+                            Msg::ArchiveGroupResponse(group_id, r.map(|_| ()))
+                        },
+                        "Error trying to archive group",
+                    );
+                }
+                Ok(true)
+            }
+            Msg::ArchiveGroupResponse(group_id, response) => {
+                self.common.cancel_task();
+                response?;
+                // The group moved to the other list.
+                debug_assert!(self.groups.is_some());
+                self.groups.as_mut().unwrap().retain(|g| g.id != group_id);
+                self.selected_groups.remove(&group_id);
+                self.pagination.total_count -= 1;
                 Ok(true)
             }
         }
@@ -62,12 +242,14 @@ impl Component for GroupTable {
         let mut table = GroupTable {
             common: CommonComponentParts::<Self>::create(props, link),
             groups: None,
+            selected_groups: HashSet::new(),
+            show_archived: false,
+            search: String::new(),
+            pagination: Pagination::default(),
+            order_by: SortColumn::DisplayName,
+            descending: false,
         };
-        table.common.call_graphql::<GetGroupList, _>(
-            get_group_list::Variables {},
-            Msg::ListGroupsResponse,
-            "Error trying to fetch groups",
-        );
+        table.get_group_list();
         table
     }
 
@@ -82,7 +264,19 @@ impl Component for GroupTable {
     fn view(&self) -> Html {
         html! {
             <div>
+              <input
+                type="search"
+                class="form-control mb-3"
+                placeholder="Search groups"
+                value=self.search.clone()
+                oninput=self.common.callback(|e: InputData| Msg::SearchUpdated(e.value)) />
+              {self.view_archived_filter()}
               {self.view_groups()}
+              {self.pagination.view(
+                  self.common.callback(|_| Msg::PreviousPage),
+                  self.common.callback(|_| Msg::NextPage),
+                  self.common.is_task_running(),
+              )}
               {self.view_errors()}
             </div>
         }
@@ -90,6 +284,38 @@ impl Component for GroupTable {
 }
 
 impl GroupTable {
+    fn get_group_list(&mut self) {
+        let search = self.search.trim();
+        self.common.call_graphql::<ListGroupsPage, _>(
+            list_groups_page::Variables {
+                archived: Some(self.show_archived),
+                display_name_contains: (!search.is_empty()).then(|| search.to_owned()),
+                first: None,
+                after: self.pagination.after(),
+                order_by: Some(self.order_by.into()),
+                descending: Some(self.descending),
+            },
+            Msg::ListGroupsResponse,
+            "Error trying to fetch groups",
+        );
+    }
+
+    fn view_archived_filter(&self) -> Html {
+        html! {
+          <div class="form-check form-switch mb-3">
+            <input
+              type="checkbox"
+              class="form-check-input"
+              id="showArchived"
+              checked=self.show_archived
+              onchange=self.common.callback(|_| Msg::ToggleShowArchived) />
+            <label class="form-check-label" for="showArchived">
+              {"Show archived groups"}
+            </label>
+          </div>
+        }
+    }
+
     fn view_groups(&self) -> Html {
         let make_table = |groups: &Vec<Group>| {
             html! {
@@ -97,8 +323,10 @@ impl GroupTable {
                   <table class="table table-hover">
                     <thead>
                       <tr>
-                        <th>{"Group name"}</th>
-                        <th>{"Creation date"}</th>
+                        <th></th>
+                        {self.view_sort_header("Group name", SortColumn::DisplayName)}
+                        {self.view_sort_header("Creation date", SortColumn::CreationDate)}
+                        <th>{if self.show_archived { "Unarchive" } else { "Archive" }}</th>
                         <th>{"Delete"}</th>
                       </tr>
                     </thead>
@@ -106,6 +334,13 @@ impl GroupTable {
                       {groups.iter().map(|u| self.view_group(u)).collect::<Vec<_>>()}
                     </tbody>
                   </table>
+                  <button
+                    class="btn btn-danger mb-3"
+                    disabled=self.selected_groups.is_empty() || self.common.is_task_running()
+                    onclick=self.common.callback(|_| Msg::DeleteSelectedGroups)>
+                    <i class="bi-x-circle-fill me-2"></i>
+                    {"Delete selected groups"}
+                  </button>
                 </div>
             }
         };
@@ -115,9 +350,32 @@ impl GroupTable {
         }
     }
 
+    /// Clicking the header sorts by the column, or reverses the order.
+    fn view_sort_header(&self, title: &'static str, column: SortColumn) -> Html {
+        let icon = match (self.order_by == column, self.descending) {
+            (false, _) => "",
+            (true, false) => "bi-caret-up-fill",
+            (true, true) => "bi-caret-down-fill",
+        };
+        html! {
+          <th role="button" onclick=self.common.callback(move |_| Msg::SortBy(column))>
+            {title}
+            <i class=format!("ms-1 {}", icon)></i>
+          </th>
+        }
+    }
+
     fn view_group(&self, group: &Group) -> Html {
+        let group_id = group.id;
         html! {
           <tr key=group.id>
+              <td>
+                <input
+                  type="checkbox"
+                  class="form-check-input"
+                  checked=self.selected_groups.contains(&group_id)
+                  onchange=self.common.callback(move |_| Msg::ToggleGroupSelection(group_id)) />
+              </td>
               <td>
                 <Link route=AppRoute::GroupDetails(group.id)>
                   {&group.display_name}
@@ -126,6 +384,14 @@ impl GroupTable {
               <td>
                 {&group.creation_date.naive_local().date()}
               </td>
+              <td>
+                <button
+                  class="btn btn-secondary"
+                  disabled=self.common.is_task_running()
+                  onclick=self.common.callback(move |_| Msg::ToggleArchived(group_id))>
+                  <i class={if self.show_archived { "bi-box-arrow-up" } else { "bi-archive" }}></i>
+                </button>
+              </td>
               <td>
                 <DeleteGroup
                   group=group.clone()
@@ -1,8 +1,13 @@
 use crate::{
-    components::router::{AppRoute, NavButton},
+    components::{
+        reset_password_step2::redirect_to_expired_password,
+        router::{AppRoute, NavButton},
+        totp_login::TotpLoginForm,
+    },
     infra::{
-        api::HostService,
+        api::{HostService, LoginResponse},
         common_component::{CommonComponent, CommonComponentParts},
+        webauthn::get_credential,
     },
 };
 use anyhow::{anyhow, bail, Context, Result};
@@ -16,6 +21,9 @@ pub struct LoginForm {
     common: CommonComponentParts<Self>,
     form: Form<FormModel>,
     refreshing: bool,
+    /// Set once the password was accepted, if the user has TOTP enabled.
+    totp_challenge: Option<String>,
+    sso_login_enabled: bool,
 }
 
 /// The fields of the form, with the constraints.
@@ -35,14 +43,23 @@ pub struct Props {
 pub enum Msg {
     Update,
     Submit,
+    /// Fetched before each password login, since a challenge can only be used once.
+    ChallengeResponse(Result<Option<proof_of_work::Challenge>>),
     AuthenticationRefreshResponse(Result<(String, bool)>),
+    ProxyLoginResponse(Result<(String, bool)>),
+    SsoLoginInfoResponse(Result<sso::ServerSsoLoginInfo>),
     AuthenticationStartResponse(
         (
             opaque::client::login::ClientLogin,
             Result<Box<login::ServerLoginStartResponse>>,
         ),
     ),
-    AuthenticationFinishResponse(Result<(String, bool)>),
+    /// After the password or the security key.
+    AuthenticationFinishResponse(Result<LoginResponse>),
+    WebauthnLogin,
+    WebauthnStartResponse(Result<Box<webauthn::ServerLoginStartResponse>>),
+    /// The challenge, and the response of the authenticator.
+    WebauthnCredential((String, Result<webauthn::PublicKeyCredential>)),
 }
 
 impl CommonComponent<LoginForm> for LoginForm {
@@ -53,7 +70,30 @@ impl CommonComponent<LoginForm> for LoginForm {
                 if !self.form.validate() {
                     bail!("Check the form for errors");
                 }
+                self.common.call_backend(
+                    HostService::login_challenge,
+                    (),
+                    Msg::ChallengeResponse,
+                )?;
+                Ok(true)
+            }
+            Msg::ChallengeResponse(challenge) => {
+                // Solving it blocks the page for a moment, which is the point.
+                let proof_of_work = challenge?.map(|c| c.solve());
                 let FormModel { username, password } = self.form.model();
+                if password.starts_with(login::TEMPORARY_PASSWORD_PREFIX) {
+                    // Not an OPAQUE password: the server checks it and asks for a new one.
+                    self.common.call_backend(
+                        HostService::temporary_login,
+                        login::ClientSimpleLoginRequest {
+                            username,
+                            password,
+                            proof_of_work,
+                        },
+                        Msg::AuthenticationFinishResponse,
+                    )?;
+                    return Ok(true);
+                }
                 let mut rng = rand::rngs::OsRng;
                 let opaque::client::login::ClientLoginStartResult { state, message } =
                     opaque::client::login::start_login(&password, &mut rng)
@@ -61,6 +101,7 @@ impl CommonComponent<LoginForm> for LoginForm {
                 let req = login::ClientLoginStartRequest {
                     username,
                     login_start_request: message,
+                    proof_of_work,
                 };
                 self.common
                     .call_backend(HostService::login_start, req, move |r| {
@@ -94,18 +135,90 @@ impl CommonComponent<LoginForm> for LoginForm {
                 )?;
                 Ok(false)
             }
-            Msg::AuthenticationFinishResponse(user_info) => {
+            Msg::AuthenticationFinishResponse(response) => {
                 self.common.cancel_task();
-                self.common
-                    .on_logged_in
-                    .emit(user_info.context("Could not log in")?);
+                match response.context("Could not log in")? {
+                    LoginResponse::LoggedIn(user_info) => self.common.on_logged_in.emit(user_info),
+                    LoginResponse::TotpRequired(challenge) => self.totp_challenge = Some(challenge),
+                    LoginResponse::PasswordExpired(token) => redirect_to_expired_password(token),
+                }
+                Ok(true)
+            }
+            Msg::WebauthnLogin => {
+                let username = self.form.model().username;
+                if username.is_empty() {
+                    bail!("Enter your username to log in with a security key");
+                }
+                self.common.call_backend(
+                    HostService::webauthn_login_start,
+                    webauthn::ClientLoginStartRequest { username },
+                    Msg::WebauthnStartResponse,
+                )?;
+                Ok(true)
+            }
+            Msg::WebauthnStartResponse(response) => {
+                self.common.cancel_task();
+                let response = response?;
+                let challenge = response.webauthn_challenge;
+                get_credential(
+                    response.options,
+                    self.common
+                        .callback(move |r| Msg::WebauthnCredential((challenge.clone(), r))),
+                )?;
+                Ok(true)
+            }
+            Msg::WebauthnCredential((webauthn_challenge, credential)) => {
+                let req = webauthn::ClientLoginFinishRequest {
+                    webauthn_challenge,
+                    credential: credential?,
+                };
+                self.common.call_backend(
+                    HostService::webauthn_login_finish,
+                    req,
+                    Msg::AuthenticationFinishResponse,
+                )?;
                 Ok(true)
             }
             Msg::AuthenticationRefreshResponse(user_info) => {
-                self.refreshing = false;
                 self.common.cancel_task();
-                if let Ok(user_info) = user_info {
-                    self.common.on_logged_in.emit(user_info);
+                match user_info {
+                    Ok(user_info) => {
+                        self.refreshing = false;
+                        self.common.on_logged_in.emit(user_info);
+                    }
+                    // Without a session, the reverse proxy in front of LLDAP may have logged
+                    // the user in already.
+                    Err(_) => {
+                        if let Err(e) = self.common.call_backend(
+                            HostService::proxy_login,
+                            (),
+                            Msg::ProxyLoginResponse,
+                        ) {
+                            ConsoleService::debug(&format!("Could not try the proxy login: {}", e));
+                            self.show_form();
+                        }
+                    }
+                }
+                Ok(true)
+            }
+            Msg::ProxyLoginResponse(user_info) => {
+                self.common.cancel_task();
+                match user_info {
+                    Ok(user_info) => {
+                        self.refreshing = false;
+                        self.common.on_logged_in.emit(user_info);
+                    }
+                    Err(_) => self.show_form(),
+                }
+                Ok(true)
+            }
+            Msg::SsoLoginInfoResponse(info) => {
+                self.common.cancel_task();
+                match info {
+                    Ok(info) => self.sso_login_enabled = info.enabled,
+                    Err(e) => {
+                        ConsoleService::debug(&format!("Could not check the SSO login: {}", e))
+                    }
                 }
                 Ok(true)
             }
@@ -117,6 +230,19 @@ impl CommonComponent<LoginForm> for LoginForm {
     }
 }
 
+impl LoginForm {
+    /// Nobody is logged in: shows the form, with the SSO button if it's enabled.
+    fn show_form(&mut self) {
+        self.refreshing = false;
+        if let Err(e) =
+            self.common
+                .call_backend(HostService::sso_login_info, (), Msg::SsoLoginInfoResponse)
+        {
+            ConsoleService::debug(&format!("Could not check the SSO login: {}", e));
+        }
+    }
+}
+
 impl Component for LoginForm {
     type Message = Msg;
     type Properties = Props;
@@ -126,13 +252,15 @@ impl Component for LoginForm {
             common: CommonComponentParts::<Self>::create(props, link),
             form: Form::<FormModel>::new(FormModel::default()),
             refreshing: true,
+            totp_challenge: None,
+            sso_login_enabled: false,
         };
         if let Err(e) =
             app.common
                 .call_backend(HostService::refresh, (), Msg::AuthenticationRefreshResponse)
         {
             ConsoleService::debug(&format!("Could not refresh auth: {}", e));
-            app.refreshing = false;
+            app.show_form();
         }
         app
     }
@@ -153,6 +281,12 @@ impl Component for LoginForm {
                 <img src={"spinner.gif"} alt={"Loading"} />
               </div>
             }
+        } else if let Some(challenge) = &self.totp_challenge {
+            html! {
+              <TotpLoginForm
+                challenge=challenge.clone()
+                on_logged_in=self.common.on_logged_in.clone() />
+            }
         } else {
             html! {
               <form
@@ -198,6 +332,23 @@ impl Component for LoginForm {
                       <i class="bi-box-arrow-in-right me-2"/>
                       {"Login"}
                     </button>
+                    <button
+                      type="button"
+                      class="btn btn-secondary ms-2"
+                      disabled=self.common.is_task_running()
+                      onclick=self.common.callback(|_| Msg::WebauthnLogin)>
+                      <i class="bi-key me-2"/>
+                      {"Use a security key"}
+                    </button>
+                    { if self.sso_login_enabled {
+                        html! {
+                          <a class="btn btn-secondary ms-2" href="/auth/sso/start">
+                            <i class="bi-box-arrow-in-right me-2"/>
+                            {"Log in with SSO"}
+                          </a>
+                        }
+                      } else { html! {} }
+                    }
                     <NavButton
                       classes="btn-link btn"
                       disabled=self.common.is_task_running()
@@ -0,0 +1,100 @@
+use crate::{
+    components::{reset_password_step2::redirect_to_expired_password, totp_login::TotpLoginForm},
+    infra::{
+        api::{HostService, LoginResponse},
+        common_component::{CommonComponent, CommonComponentParts},
+    },
+};
+use anyhow::Result;
+use yew::prelude::*;
+
+/// Logs the user in with a single-use link created by an admin.
+pub struct MagicLogin {
+    common: CommonComponentParts<Self>,
+    /// Set if the user has TOTP enabled: the link only replaces the password.
+    totp_challenge: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub token: String,
+    pub on_logged_in: Callback<(String, bool)>,
+}
+
+pub enum Msg {
+    LoginResponse(Result<LoginResponse>),
+}
+
+impl CommonComponent<MagicLogin> for MagicLogin {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::LoginResponse(response) => {
+                self.common.cancel_task();
+                match response? {
+                    LoginResponse::LoggedIn(user_info) => {
+                        self.common.on_logged_in.emit(user_info);
+                        Ok(false)
+                    }
+                    LoginResponse::TotpRequired(challenge) => {
+                        self.totp_challenge = Some(challenge);
+                        Ok(true)
+                    }
+                    LoginResponse::PasswordExpired(token) => {
+                        redirect_to_expired_password(token);
+                        Ok(false)
+                    }
+                }
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for MagicLogin {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = MagicLogin {
+            common: CommonComponentParts::<Self>::create(props, link),
+            totp_challenge: None,
+        };
+        let token = component.common.token.clone();
+        component
+            .common
+            .call_backend(HostService::magic_login, &token, Msg::LoginResponse)
+            .unwrap();
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        if let Some(challenge) = &self.totp_challenge {
+            return html! {
+              <TotpLoginForm
+                challenge=challenge.clone()
+                on_logged_in=self.common.on_logged_in.clone() />
+            };
+        }
+        match &self.common.error {
+            None => html! {
+              {"Logging in..."}
+            },
+            Some(e) => html! {
+              <div class="alert alert-danger">
+                {e.to_string()}
+              </div>
+            },
+        }
+    }
+}
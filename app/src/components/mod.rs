@@ -1,20 +1,37 @@
+pub mod account_validity;
 pub mod add_group_member;
 pub mod add_user_to_group;
+pub mod api_tokens;
 pub mod app;
 pub mod change_password;
 pub mod create_group;
 pub mod create_user;
 pub mod delete_group;
 pub mod delete_user;
+pub mod export_membership_report;
+pub mod group_attributes;
 pub mod group_details;
+pub mod group_managers;
+pub mod group_membership_requests;
 pub mod group_table;
 pub mod login;
 pub mod logout;
+pub mod magic_login;
+pub mod password_reset_link;
 pub mod remove_user_from_group;
 pub mod reset_password_step1;
 pub mod reset_password_step2;
 pub mod router;
 pub mod select;
+pub mod sessions;
+pub mod single_sign_on;
+pub mod sso_login;
+pub mod stats;
+pub mod temporary_password;
+pub mod toggle_user_enabled;
+pub mod totp_login;
+pub mod totp_setup;
 pub mod user_details;
 pub mod user_details_form;
 pub mod user_table;
+pub mod webauthn_setup;
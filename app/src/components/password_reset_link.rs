@@ -0,0 +1,174 @@
+use crate::infra::{
+    clipboard::copy_input_value,
+    common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::{anyhow, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/create_password_reset_link.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct CreatePasswordResetLink;
+
+/// Lets an admin create a password reset link for the user, to hand over to them when no mail
+/// server is configured to send the password reset emails.
+pub struct PasswordResetLink {
+    common: CommonComponentParts<Self>,
+    /// The link that was just created, only shown once.
+    link: Option<String>,
+    valid_for_hours: NodeRef,
+    link_input: NodeRef,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+}
+
+pub enum Msg {
+    Create,
+    CreateResponse(Result<create_password_reset_link::ResponseData>),
+    Copy,
+}
+
+impl CommonComponent<PasswordResetLink> for PasswordResetLink {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Create => {
+                let valid_for_hours = self
+                    .valid_for_hours
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("Validity input should be present")
+                    .value()
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| anyhow!("The validity must be a number of hours"))?;
+                self.link = None;
+                self.common.call_graphql::<CreatePasswordResetLink, _>(
+                    create_password_reset_link::Variables {
+                        user_id: self.common.username.clone(),
+                        valid_for_hours,
+                    },
+                    Msg::CreateResponse,
+                    "Error trying to create the password reset link",
+                );
+            }
+            Msg::CreateResponse(response) => {
+                self.common.cancel_task();
+                self.link = Some(response?.create_password_reset_link);
+            }
+            Msg::Copy => {
+                copy_input_value(&self.link_input)?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl PasswordResetLink {
+    fn view_link(&self) -> Html {
+        match &self.link {
+            None => html! {},
+            Some(link) => html! {
+              <div class="alert alert-success mt-3">
+                <p>
+                  {"Send this link to the user, it won't be shown again. It can only be used \
+                    once, to choose a new password."}
+                </p>
+                <div class="input-group">
+                  <input
+                    class="form-control font-monospace"
+                    type="text"
+                    readonly=true
+                    value=link.clone()
+                    ref=self.link_input.clone() />
+                  <button
+                    type="button"
+                    class="btn btn-secondary"
+                    onclick=self.common.callback(|_| Msg::Copy)>
+                    <i class="bi-clipboard me-2"></i>
+                    {"Copy"}
+                  </button>
+                </div>
+              </div>
+            },
+        }
+    }
+}
+
+impl Component for PasswordResetLink {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        PasswordResetLink {
+            common: CommonComponentParts::<Self>::create(props, link),
+            link: None,
+            valid_for_hours: NodeRef::default(),
+            link_input: NodeRef::default(),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if props.username != self.common.username {
+            self.link = None;
+        }
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-4">
+              <h5 class="row m-3 fw-bold">{"Password reset link"}</h5>
+            </div>
+            <p>
+              {"Create a link for the user to choose a new password, e.g. when the password \
+                reset emails can't be sent."}
+            </p>
+            <div class="input-group">
+              <span class="input-group-text">{"Valid for (hours)"}</span>
+              <input
+                class="form-control"
+                type="number"
+                min="1"
+                max="168"
+                value="24"
+                ref=self.valid_for_hours.clone() />
+              <button
+                type="button"
+                class="btn btn-primary"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::Create)>
+                <i class="bi-link-45deg me-2"></i>
+                {"Create"}
+              </button>
+            </div>
+            {self.view_link()}
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+          </>
+        }
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
 };
 use anyhow::{bail, Context, Result};
 use lldap_auth::{
-    opaque::client::registration as opaque_registration,
+    opaque::client::registration as opaque_registration, password_history::password_history_digest,
     password_reset::ServerPasswordResetResponse, registration,
 };
 use validator_derive::Validate;
@@ -39,6 +39,16 @@ pub struct ResetPasswordStep2Form {
 #[derive(Clone, PartialEq, Eq, Properties)]
 pub struct Props {
     pub token: String,
+    /// Whether the user was sent here by the login because their password expired.
+    #[prop_or_default]
+    pub expired: bool,
+}
+
+/// Sends the user to the choice of a new password, when the login says that theirs expired.
+pub fn redirect_to_expired_password(token: String) {
+    RouteAgentDispatcher::<()>::new().send(RouteRequest::ChangeRoute(Route::from(
+        AppRoute::ExpiredPassword(token),
+    )));
 }
 
 pub enum Msg {
@@ -89,9 +99,15 @@ impl CommonComponent<ResetPasswordStep2Form> for ResetPasswordStep2Form {
                     &mut rng,
                 )
                 .context("Error during password change")?;
+                let password_history_digest = password_history_digest(
+                    self.username.as_ref().unwrap(),
+                    &self.form.model().password,
+                )
+                .map_err(anyhow::Error::msg)?;
                 let req = registration::ClientRegistrationFinishRequest {
                     server_data: res.server_data,
                     registration_upload: registration_finish.message,
+                    password_history_digest: Some(password_history_digest),
                 };
                 self.common.call_backend(
                     HostService::register_finish,
@@ -168,7 +184,14 @@ impl Component for ResetPasswordStep2Form {
         type Field = yew_form::Field<FormModel>;
         html! {
           <>
-            <h2>{"Reset your password"}</h2>
+            {if self.common.expired { html! {
+              <>
+                <h2>{"Your password expired"}</h2>
+                <p>{"Choose a new password to log in."}</p>
+              </>
+            }} else { html! {
+              <h2>{"Reset your password"}</h2>
+            }}}
             <form
               class="form">
               <div class="form-group row">
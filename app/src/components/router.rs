@@ -7,10 +7,16 @@ use yew_router::{
 pub enum AppRoute {
     #[to = "/login"]
     Login,
+    #[to = "/magic-login/{token}"]
+    MagicLogin(String),
+    #[to = "/sso-login"]
+    SsoLogin,
     #[to = "/reset-password/step1"]
     StartResetPassword,
     #[to = "/reset-password/step2/{token}"]
     FinishResetPassword(String),
+    #[to = "/reset-password/expired/{token}"]
+    ExpiredPassword(String),
     #[to = "/users/create"]
     CreateUser,
     #[to = "/users"]
@@ -25,6 +31,12 @@ pub enum AppRoute {
     ListGroups,
     #[to = "/group/{group_id}"]
     GroupDetails(i64),
+    #[to = "/stats"]
+    Stats,
+    #[to = "/api-tokens"]
+    ApiTokens,
+    #[to = "/single-sign-on/{request_id}"]
+    SingleSignOn(String),
     #[to = "/"]
     Index,
 }
@@ -0,0 +1,279 @@
+use crate::infra::{
+    common_component::{CommonComponent, CommonComponentParts},
+    cookies::get_cookie,
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_user_sessions.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetUserSessions;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/delete_session.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteSession;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/delete_sessions.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteSessions;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/set_login_notifications.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct SetLoginNotifications;
+
+type Session = get_user_sessions::GetUserSessionsUserSessions;
+
+/// The devices on which a user is logged in to the web UI, to log them out remotely.
+pub struct Sessions {
+    common: CommonComponentParts<Self>,
+    sessions: Option<Vec<Session>>,
+    /// Whether the user is emailed when they log in from a new device.
+    login_notifications: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+    pub is_admin: bool,
+}
+
+pub enum Msg {
+    ListResponse(Result<get_user_sessions::ResponseData>),
+    Delete(String),
+    DeleteResponse(Result<delete_session::ResponseData>),
+    DeleteAll,
+    DeleteAllResponse(Result<delete_sessions::ResponseData>),
+    ToggleLoginNotifications,
+    LoginNotificationsResponse(Result<set_login_notifications::ResponseData>),
+}
+
+impl CommonComponent<Sessions> for Sessions {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::ListResponse(response) => {
+                let user = response?.user;
+                self.login_notifications = user.login_notifications;
+                self.sessions = Some(user.sessions);
+            }
+            Msg::Delete(session_id) => {
+                self.common.call_graphql::<DeleteSession, _>(
+                    delete_session::Variables {
+                        user_id: self.common.username.clone(),
+                        session_id,
+                    },
+                    Msg::DeleteResponse,
+                    "Error trying to revoke the session",
+                );
+            }
+            Msg::DeleteResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.fetch_sessions();
+            }
+            Msg::DeleteAll => {
+                self.common.call_graphql::<DeleteSessions, _>(
+                    delete_sessions::Variables {
+                        user_id: self.common.username.clone(),
+                    },
+                    Msg::DeleteAllResponse,
+                    "Error trying to revoke the sessions",
+                );
+            }
+            Msg::DeleteAllResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.fetch_sessions();
+            }
+            Msg::ToggleLoginNotifications => {
+                self.common.call_graphql::<SetLoginNotifications, _>(
+                    set_login_notifications::Variables {
+                        user_id: self.common.username.clone(),
+                        enabled: !self.login_notifications,
+                    },
+                    Msg::LoginNotificationsResponse,
+                    "Error trying to change the login notifications",
+                );
+            }
+            Msg::LoginNotificationsResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.login_notifications = !self.login_notifications;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Sessions {
+    fn fetch_sessions(&mut self) {
+        self.common.call_graphql::<GetUserSessions, _>(
+            get_user_sessions::Variables {
+                id: self.common.username.clone(),
+            },
+            Msg::ListResponse,
+            "Error trying to fetch the sessions",
+        );
+    }
+
+    fn can_revoke(&self) -> bool {
+        self.common.is_admin
+            || get_cookie("user_id").ok().flatten().as_ref() == Some(&self.common.username)
+    }
+
+    fn view_session(&self, session: &Session) -> Html {
+        let session_id = session.session_id.clone();
+        let or_unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+        html! {
+          <tr key=session.session_id.clone()>
+            <td>
+              {session
+                .creation_date
+                .map(|d| d.naive_local().format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string())}
+            </td>
+            <td>{or_unknown(&session.ip_address)}</td>
+            <td>{or_unknown(&session.user_agent)}</td>
+            <td>{&session.expiry_date.naive_local().date()}</td>
+            <td>
+              {if self.can_revoke() { html! {
+                <button
+                  class="btn btn-danger btn-sm"
+                  disabled=self.common.is_task_running()
+                  onclick=self.common.callback(move |_| Msg::Delete(session_id.clone()))>
+                  <i class="bi-x-circle-fill" aria-label="Revoke session" />
+                </button>
+              }} else { html! {} }}
+            </td>
+          </tr>
+        }
+    }
+
+    fn view_delete_all(&self) -> Html {
+        match &self.sessions {
+            Some(sessions) if !sessions.is_empty() && self.can_revoke() => html! {
+              <button
+                type="button"
+                class="btn btn-danger"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::DeleteAll)>
+                <i class="bi-box-arrow-right me-2"></i>
+                {"Log out everywhere"}
+              </button>
+            },
+            _ => html! {},
+        }
+    }
+
+    fn view_login_notifications(&self) -> Html {
+        if self.sessions.is_none() {
+            return html! {};
+        }
+        html! {
+          <div class="form-check mt-3">
+            <input
+              id="loginNotifications"
+              class="form-check-input"
+              type="checkbox"
+              checked=self.login_notifications
+              disabled=!self.can_revoke() || self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::ToggleLoginNotifications) />
+            <label for="loginNotifications" class="form-check-label">
+              {"Send an email on each login from a new device, if the server sends emails"}
+            </label>
+          </div>
+        }
+    }
+}
+
+impl Component for Sessions {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = Sessions {
+            common: CommonComponentParts::<Self>::create(props, link),
+            sessions: None,
+            login_notifications: true,
+        };
+        component.fetch_sessions();
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-4">
+              <h5 class="row m-3 fw-bold">{"Sessions"}</h5>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            {match &self.sessions {
+              None => html! {{"Loading..."}},
+              Some(sessions) if sessions.is_empty() => html! {
+                <p>{"No active session."}</p>
+              },
+              Some(sessions) => html! {
+                <div class="table-responsive">
+                  <table class="table table-hover">
+                    <thead>
+                      <tr>
+                        <th>{"Logged in"}</th>
+                        <th>{"IP address"}</th>
+                        <th>{"Browser"}</th>
+                        <th>{"Expires"}</th>
+                        <th>{"Revoke"}</th>
+                      </tr>
+                    </thead>
+                    <tbody>
+                      {for sessions.iter().map(|s| self.view_session(s))}
+                    </tbody>
+                  </table>
+                </div>
+              },
+            }}
+            {self.view_delete_all()}
+            {self.view_login_notifications()}
+          </>
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::infra::{
+    api::HostService,
+    common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::{anyhow, Result};
+use lldap_auth::oidc;
+use yew::prelude::*;
+
+/// Sends the user back to the application that asked to log them in with OpenID Connect, once
+/// they are logged in to LLDAP.
+pub struct SingleSignOn {
+    common: CommonComponentParts<Self>,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    /// The login request of the application, from `/oidc/authorize`.
+    pub request_id: String,
+}
+
+pub enum Msg {
+    AuthorizeResponse(Result<oidc::ServerAuthorizeResponse>),
+}
+
+impl CommonComponent<SingleSignOn> for SingleSignOn {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::AuthorizeResponse(response) => {
+                self.common.cancel_task();
+                let redirect_url = response?.redirect_url;
+                web_sys::window()
+                    .ok_or_else(|| anyhow!("Could not get window"))?
+                    .location()
+                    .set_href(&redirect_url)
+                    .map_err(|e| anyhow!("Could not redirect to the application: {:?}", e))?;
+                Ok(false)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for SingleSignOn {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = SingleSignOn {
+            common: CommonComponentParts::<Self>::create(props, link),
+        };
+        let request_id = component.common.request_id.clone();
+        component
+            .common
+            .call_backend(
+                HostService::oidc_authorize,
+                &request_id,
+                Msg::AuthorizeResponse,
+            )
+            .unwrap();
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        match &self.common.error {
+            None => html! {
+              {"Logging in to the application..."}
+            },
+            Some(e) => html! {
+              <div class="alert alert-danger">
+                {e.to_string()}
+              </div>
+            },
+        }
+    }
+}
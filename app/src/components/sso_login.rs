@@ -0,0 +1,85 @@
+use crate::infra::{
+    api::HostService,
+    common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::{anyhow, Result};
+use yew::prelude::*;
+
+/// The page the SSO provider sends the user back to: the server checks the code and logs the user
+/// in.
+pub struct SsoLogin {
+    common: CommonComponentParts<Self>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub on_logged_in: Callback<(String, bool)>,
+}
+
+pub enum Msg {
+    LoginResponse(Result<(String, bool)>),
+}
+
+impl CommonComponent<SsoLogin> for SsoLogin {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::LoginResponse(response) => {
+                self.common.cancel_task();
+                self.common.on_logged_in.emit(response?);
+                Ok(false)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+fn get_query() -> Result<String> {
+    web_sys::window()
+        .ok_or_else(|| anyhow!("Could not get window"))?
+        .location()
+        .search()
+        .map_err(|e| anyhow!("Could not read the SSO response: {:?}", e))
+}
+
+impl Component for SsoLogin {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = SsoLogin {
+            common: CommonComponentParts::<Self>::create(props, link),
+        };
+        if let Err(e) = get_query().and_then(|query| {
+            component
+                .common
+                .call_backend(HostService::sso_login, &query, Msg::LoginResponse)
+        }) {
+            component.common.error = Some(e);
+        }
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        match &self.common.error {
+            None => html! {
+              {"Logging in..."}
+            },
+            Some(e) => html! {
+              <div class="alert alert-danger">
+                {e.to_string()}
+              </div>
+            },
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::infra::common_component::{CommonComponent, CommonComponentParts};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_stats.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetStats;
+
+pub type Stats = get_stats::GetStatsStats;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/repair_memberships.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct RepairMemberships;
+
+pub type RepairReport = repair_memberships::RepairMembershipsRepairMemberships;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/optimize_database.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct OptimizeDatabase;
+
+pub type OptimizationReport = optimize_database::OptimizeDatabaseOptimizeDatabase;
+
+fn format_database_size(size: Option<f64>) -> String {
+    match size {
+        Some(size) => format!("{:.1} MiB", size / (1024.0 * 1024.0)),
+        None => "Unknown".to_string(),
+    }
+}
+
+pub struct StatsDashboard {
+    common: CommonComponentParts<Self>,
+    stats: Option<Stats>,
+    repair_report: Option<RepairReport>,
+    optimization_report: Option<OptimizationReport>,
+}
+
+pub enum Msg {
+    Refresh,
+    StatsResponse(Result<get_stats::ResponseData>),
+    RepairMemberships,
+    RepairMembershipsResponse(Result<repair_memberships::ResponseData>),
+    OptimizeDatabase,
+    OptimizeDatabaseResponse(Result<optimize_database::ResponseData>),
+}
+
+impl StatsDashboard {
+    fn get_stats(&mut self) {
+        self.common.call_graphql::<GetStats, _>(
+            get_stats::Variables {},
+            Msg::StatsResponse,
+            "Error trying to fetch the server statistics",
+        );
+    }
+
+    fn view_stats(stats: &Stats) -> Html {
+        let database_size = format_database_size(stats.database_size);
+        let row = |label: &str, value: String| {
+            html! {
+              <tr>
+                <th>{label}</th>
+                <td>{value}</td>
+              </tr>
+            }
+        };
+        html! {
+          <div class="table-responsive">
+            <table class="table table-hover">
+              <tbody>
+                {row("Active LDAP connections", stats.active_ldap_connections.to_string())}
+                {row("Successful LDAP binds", stats.successful_ldap_binds.to_string())}
+                {row("Failed LDAP binds", stats.failed_ldap_binds.to_string())}
+                {row("GraphQL requests", stats.graphql_requests.to_string())}
+                {row("Users", stats.user_count.to_string())}
+                {row("Groups", stats.group_count.to_string())}
+                {row("Database size", database_size)}
+              </tbody>
+            </table>
+          </div>
+        }
+    }
+
+    fn view_maintenance(&self) -> Html {
+        html! {
+          <div class="mt-4">
+            <h3>{"Maintenance"}</h3>
+            <p>
+              {"Remove the group memberships that shouldn't exist, e.g. after an import that \
+                bypassed the database constraints. This is safe to run at any time."}
+            </p>
+            <button
+              class="btn btn-secondary"
+              disabled=self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::RepairMemberships)>
+              <i class="bi-wrench me-2"></i>
+              {"Repair memberships"}
+            </button>
+            {match &self.repair_report {
+                None => html! {},
+                Some(report) => html! {
+                  <div class="mt-2">
+                    {format!(
+                        "Removed {} dangling memberships and {} dynamic group memberships.",
+                        report.dangling_memberships_removed,
+                        report.dynamic_group_memberships_removed,
+                    )}
+                  </div>
+                },
+            }}
+            <p class="mt-3">
+              {"Reclaim the free space and refresh the statistics and indices of the database. \
+                With SQLite, the changes are blocked until it completes, which can take a while \
+                on a large database."}
+            </p>
+            <button
+              class="btn btn-secondary"
+              disabled=self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::OptimizeDatabase)>
+              <i class="bi-speedometer2 me-2"></i>
+              {"Optimize database"}
+            </button>
+            {match &self.optimization_report {
+                None => html! {},
+                Some(report) => html! {
+                  <div class="mt-2">
+                    {format!(
+                        "Database optimized, from {} to {}.",
+                        format_database_size(report.database_size_before),
+                        format_database_size(report.database_size_after),
+                    )}
+                  </div>
+                },
+            }}
+          </div>
+        }
+    }
+
+    fn view_errors(&self) -> Html {
+        match &self.common.error {
+            None => html! {},
+            Some(e) => html! {<div>{"Error: "}{e.to_string()}</div>},
+        }
+    }
+}
+
+impl CommonComponent<StatsDashboard> for StatsDashboard {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Refresh => {
+                self.get_stats();
+                Ok(true)
+            }
+            Msg::StatsResponse(stats) => {
+                self.stats = Some(stats?.stats);
+                Ok(true)
+            }
+            Msg::RepairMemberships => {
+                self.common.call_graphql::<RepairMemberships, _>(
+                    repair_memberships::Variables {},
+                    Msg::RepairMembershipsResponse,
+                    "Error trying to repair the memberships",
+                );
+                Ok(true)
+            }
+            Msg::RepairMembershipsResponse(report) => {
+                self.repair_report = Some(report?.repair_memberships);
+                Ok(true)
+            }
+            Msg::OptimizeDatabase => {
+                self.common.call_graphql::<OptimizeDatabase, _>(
+                    optimize_database::Variables {},
+                    Msg::OptimizeDatabaseResponse,
+                    "Error trying to optimize the database",
+                );
+                Ok(true)
+            }
+            Msg::OptimizeDatabaseResponse(report) => {
+                self.optimization_report = Some(report?.optimize_database);
+                // The size of the database changed.
+                self.get_stats();
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for StatsDashboard {
+    type Message = Msg;
+    type Properties = ();
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut dashboard = StatsDashboard {
+            common: CommonComponentParts::<Self>::create(props, link),
+            stats: None,
+            repair_report: None,
+            optimization_report: None,
+        };
+        dashboard.get_stats();
+        dashboard
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <div>
+            <h3>{"Server statistics"}</h3>
+            {match &self.stats {
+                None => html! {{"Loading..."}},
+                Some(stats) => Self::view_stats(stats),
+            }}
+            <button
+              class="btn btn-secondary"
+              disabled=self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::Refresh)>
+              <i class="bi-arrow-clockwise me-2"></i>
+              {"Refresh"}
+            </button>
+            {self.view_maintenance()}
+            {self.view_errors()}
+          </div>
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::infra::{
+    clipboard::copy_input_value,
+    common_component::{CommonComponent, CommonComponentParts},
+};
+use anyhow::{anyhow, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/create_temporary_password.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct CreateTemporaryPassword;
+
+/// Lets an admin create a single-use password for a new user, to share with them out of band.
+/// The user has to choose their own password right after logging in with it.
+pub struct TemporaryPassword {
+    common: CommonComponentParts<Self>,
+    /// The password that was just created, only shown once.
+    password: Option<String>,
+    valid_for_hours: NodeRef,
+    password_input: NodeRef,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+}
+
+pub enum Msg {
+    Create,
+    CreateResponse(Result<create_temporary_password::ResponseData>),
+    Copy,
+}
+
+impl CommonComponent<TemporaryPassword> for TemporaryPassword {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Create => {
+                let valid_for_hours = self
+                    .valid_for_hours
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("Validity input should be present")
+                    .value()
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| anyhow!("The validity must be a number of hours"))?;
+                self.password = None;
+                self.common.call_graphql::<CreateTemporaryPassword, _>(
+                    create_temporary_password::Variables {
+                        user_id: self.common.username.clone(),
+                        valid_for_hours,
+                    },
+                    Msg::CreateResponse,
+                    "Error trying to create the temporary password",
+                );
+            }
+            Msg::CreateResponse(response) => {
+                self.common.cancel_task();
+                self.password = Some(response?.create_temporary_password);
+            }
+            Msg::Copy => {
+                copy_input_value(&self.password_input)?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl TemporaryPassword {
+    fn view_password(&self) -> Html {
+        match &self.password {
+            None => html! {},
+            Some(password) => html! {
+              <div class="alert alert-success mt-3">
+                <p>
+                  {"Share this password with the user, it won't be shown again. It can only be \
+                    used once, and they will have to choose their own password right away."}
+                </p>
+                <div class="input-group">
+                  <input
+                    class="form-control font-monospace"
+                    type="text"
+                    readonly=true
+                    value=password.clone()
+                    ref=self.password_input.clone() />
+                  <button
+                    type="button"
+                    class="btn btn-secondary"
+                    onclick=self.common.callback(|_| Msg::Copy)>
+                    <i class="bi-clipboard me-2"></i>
+                    {"Copy"}
+                  </button>
+                </div>
+              </div>
+            },
+        }
+    }
+}
+
+impl Component for TemporaryPassword {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        TemporaryPassword {
+            common: CommonComponentParts::<Self>::create(props, link),
+            password: None,
+            valid_for_hours: NodeRef::default(),
+            password_input: NodeRef::default(),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if props.username != self.common.username {
+            self.password = None;
+        }
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-4">
+              <h5 class="row m-3 fw-bold">{"Temporary password"}</h5>
+            </div>
+            <p>
+              {"Create a single-use password for the user to log in for the first time. It \
+                replaces the previous temporary password, if any."}
+            </p>
+            <div class="input-group">
+              <span class="input-group-text">{"Valid for (hours)"}</span>
+              <input
+                class="form-control"
+                type="number"
+                min="1"
+                max="168"
+                value="24"
+                ref=self.valid_for_hours.clone() />
+              <button
+                type="button"
+                class="btn btn-primary"
+                disabled=self.common.is_task_running()
+                onclick=self.common.callback(|_| Msg::Create)>
+                <i class="bi-person-badge me-2"></i>
+                {"Create"}
+              </button>
+            </div>
+            {self.view_password()}
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+          </>
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::infra::common_component::{CommonComponent, CommonComponentParts};
+use anyhow::{Error, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/disable_user.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DisableUser;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/enable_user.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct EnableUser;
+
+/// Disables the account of the user, or enables it again. Only for the admins.
+pub struct ToggleUserEnabled {
+    common: CommonComponentParts<Self>,
+}
+
+#[derive(yew::Properties, Clone, PartialEq, Debug)]
+pub struct Props {
+    pub username: String,
+    pub enabled: bool,
+    /// Called with the user ID and whether the account is now enabled.
+    pub on_changed: Callback<(String, bool)>,
+    pub on_error: Callback<Error>,
+}
+
+pub enum Msg {
+    Toggle,
+    /// Whether the account is now enabled, if the mutation succeeded.
+    Response(Result<bool>),
+}
+
+impl CommonComponent<ToggleUserEnabled> for ToggleUserEnabled {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Toggle => {
+                let user_id = self.common.username.clone();
+                if self.common.enabled {
+                    self.common.call_graphql::<DisableUser, _>(
+                        disable_user::Variables { user_id },
+                        |response| Msg::Response(response.map(|_| false)),
+                        "Error trying to disable the user",
+                    );
+                } else {
+                    self.common.call_graphql::<EnableUser, _>(
+                        enable_user::Variables { user_id },
+                        |response| Msg::Response(response.map(|_| true)),
+                        "Error trying to enable the user",
+                    );
+                }
+            }
+            Msg::Response(response) => {
+                self.common.cancel_task();
+                let enabled = response?;
+                self.common
+                    .props
+                    .on_changed
+                    .emit((self.common.username.clone(), enabled));
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for ToggleUserEnabled {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        Self {
+            common: CommonComponentParts::<Self>::create(props, link),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update_and_report_error(
+            self,
+            msg,
+            self.common.on_error.clone(),
+        )
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        let (class, icon, label) = if self.common.enabled {
+            (
+                "btn btn-outline-secondary btn-sm",
+                "bi-person-slash",
+                "Disable",
+            )
+        } else {
+            ("btn btn-success btn-sm", "bi-person-check", "Enable")
+        };
+        html! {
+          <button
+            type="button"
+            class=class
+            disabled=self.common.is_task_running()
+            onclick=self.common.callback(|_| Msg::Toggle)>
+            <i class=format!("{} me-2", icon)></i>
+            {label}
+          </button>
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    components::reset_password_step2::redirect_to_expired_password,
+    infra::{
+        api::{HostService, LoginResponse},
+        common_component::{CommonComponent, CommonComponentParts},
+    },
+};
+use anyhow::{bail, Result};
+use lldap_auth::login;
+use yew::prelude::*;
+
+/// The second step of the login of the users with TOTP enabled, once their password was
+/// verified.
+pub struct TotpLoginForm {
+    common: CommonComponentParts<Self>,
+    code: NodeRef,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    /// Returned by the server when the password was accepted.
+    pub challenge: String,
+    pub on_logged_in: Callback<(String, bool)>,
+}
+
+pub enum Msg {
+    Submit,
+    LoginResponse(Result<LoginResponse>),
+}
+
+impl CommonComponent<TotpLoginForm> for TotpLoginForm {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::Submit => {
+                let code = self
+                    .code
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("Code input should be present")
+                    .value()
+                    .trim()
+                    .to_string();
+                if code.is_empty() {
+                    bail!("Missing code");
+                }
+                let req = login::ClientTotpLoginRequest {
+                    totp_challenge: self.common.challenge.clone(),
+                    code,
+                };
+                self.common
+                    .call_backend(HostService::totp_login, req, Msg::LoginResponse)?;
+                Ok(true)
+            }
+            Msg::LoginResponse(response) => {
+                self.common.cancel_task();
+                match response? {
+                    LoginResponse::LoggedIn(user_info) => self.common.on_logged_in.emit(user_info),
+                    LoginResponse::PasswordExpired(token) => redirect_to_expired_password(token),
+                    LoginResponse::TotpRequired(_) => bail!("Unexpected TOTP challenge"),
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl Component for TotpLoginForm {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        TotpLoginForm {
+            common: CommonComponentParts::<Self>::create(props, link),
+            code: NodeRef::default(),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <form
+            class="form center-block col-sm-4 col-offset-4">
+              <p>{"Enter the code of your authenticator app, or one of your recovery codes."}</p>
+              <div class="input-group">
+                <div class="input-group-prepend">
+                  <span class="input-group-text">
+                    <i class="bi-shield-lock-fill"/>
+                  </span>
+                </div>
+                <input
+                  class="form-control"
+                  type="text"
+                  placeholder="Code"
+                  autocomplete="one-time-code"
+                  ref=self.code.clone() />
+              </div>
+              <div class="form-group mt-3">
+                <button
+                  type="submit"
+                  class="btn btn-primary"
+                  disabled=self.common.is_task_running()
+                  onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::Submit})>
+                  <i class="bi-box-arrow-in-right me-2"/>
+                  {"Login"}
+                </button>
+              </div>
+              <div class="form-group">
+              { if let Some(e) = &self.common.error {
+                  html! { e.to_string() }
+                } else { html! {} }
+              }
+              </div>
+          </form>
+        }
+    }
+}
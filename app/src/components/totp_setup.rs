@@ -0,0 +1,286 @@
+use crate::infra::{
+    common_component::{CommonComponent, CommonComponentParts},
+    cookies::get_cookie,
+};
+use anyhow::{bail, Result};
+use graphql_client::GraphQLQuery;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_user_totp.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetUserTotp;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/start_totp_enrollment.graphql",
+    response_derives = "Debug, Clone",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct StartTotpEnrollment;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/confirm_totp_enrollment.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct ConfirmTotpEnrollment;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/disable_totp.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DisableTotp;
+
+type Enrollment = start_totp_enrollment::StartTotpEnrollmentStartTotpEnrollment;
+
+enum State {
+    Loading,
+    Disabled,
+    /// Waiting for the first code of the authenticator.
+    Enrolling(Enrollment),
+    /// Shown once, right after the enrollment.
+    RecoveryCodes(Vec<String>),
+    Enabled,
+}
+
+/// The two-factor authentication of the web UI logins: enrollment with an authenticator app, or
+/// removal. LDAP binds only use the password.
+pub struct TotpSetup {
+    common: CommonComponentParts<Self>,
+    state: State,
+    code: NodeRef,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+    pub is_admin: bool,
+}
+
+pub enum Msg {
+    StatusResponse(Result<get_user_totp::ResponseData>),
+    StartEnrollment,
+    EnrollmentResponse(Result<start_totp_enrollment::ResponseData>),
+    ConfirmEnrollment,
+    ConfirmationResponse(Result<confirm_totp_enrollment::ResponseData>),
+    RecoveryCodesSaved,
+    Disable,
+    DisableResponse(Result<disable_totp::ResponseData>),
+}
+
+impl CommonComponent<TotpSetup> for TotpSetup {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::StatusResponse(response) => {
+                self.state = if response?.user.totp_enabled {
+                    State::Enabled
+                } else {
+                    State::Disabled
+                };
+            }
+            Msg::StartEnrollment => {
+                self.common.call_graphql::<StartTotpEnrollment, _>(
+                    start_totp_enrollment::Variables {
+                        user_id: self.common.username.clone(),
+                    },
+                    Msg::EnrollmentResponse,
+                    "Error trying to start the TOTP enrollment",
+                );
+            }
+            Msg::EnrollmentResponse(response) => {
+                self.common.cancel_task();
+                self.state = State::Enrolling(response?.start_totp_enrollment);
+            }
+            Msg::ConfirmEnrollment => {
+                let code = self
+                    .code
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("Code input should be present")
+                    .value()
+                    .trim()
+                    .to_string();
+                if code.is_empty() {
+                    bail!("Enter the code shown by the authenticator app");
+                }
+                self.common.call_graphql::<ConfirmTotpEnrollment, _>(
+                    confirm_totp_enrollment::Variables {
+                        user_id: self.common.username.clone(),
+                        code,
+                    },
+                    Msg::ConfirmationResponse,
+                    "Error trying to confirm the TOTP enrollment",
+                );
+            }
+            Msg::ConfirmationResponse(response) => {
+                self.common.cancel_task();
+                self.state = State::RecoveryCodes(response?.confirm_totp_enrollment);
+            }
+            Msg::RecoveryCodesSaved => self.state = State::Enabled,
+            Msg::Disable => {
+                self.common.call_graphql::<DisableTotp, _>(
+                    disable_totp::Variables {
+                        user_id: self.common.username.clone(),
+                    },
+                    Msg::DisableResponse,
+                    "Error trying to disable TOTP",
+                );
+            }
+            Msg::DisableResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.state = State::Disabled;
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl TotpSetup {
+    /// Only the users themselves can enroll, since the secret is shown on the screen.
+    fn is_own_account(&self) -> bool {
+        get_cookie("user_id").ok().flatten().as_ref() == Some(&self.common.username)
+    }
+
+    fn view_state(&self) -> Html {
+        match &self.state {
+            State::Loading => html! {{"Loading..."}},
+            State::Disabled if self.is_own_account() => html! {
+              <>
+                <p>{"Require a code from an authenticator app, on top of the password, to log in."}</p>
+                <button
+                  class="btn btn-primary"
+                  disabled=self.common.is_task_running()
+                  onclick=self.common.callback(|_| Msg::StartEnrollment)>
+                  <i class="bi-shield-lock me-2"></i>
+                  {"Enable two-factor authentication"}
+                </button>
+              </>
+            },
+            State::Disabled => html! {
+              <p>{"Two-factor authentication is disabled."}</p>
+            },
+            State::Enrolling(enrollment) => html! {
+              <>
+                <p>{"Scan the QR code with your authenticator app, then enter the code it shows."}</p>
+                <img src=enrollment.qr_code.clone() alt="QR code of the TOTP secret" />
+                <p class="mt-2">
+                  {"Or enter the secret manually: "}
+                  <code>{&enrollment.secret}</code>
+                </p>
+                <div class="input-group">
+                  <input
+                    class="form-control"
+                    type="text"
+                    placeholder="Code"
+                    autocomplete="one-time-code"
+                    ref=self.code.clone() />
+                  <button
+                    type="button"
+                    class="btn btn-primary"
+                    disabled=self.common.is_task_running()
+                    onclick=self.common.callback(|_| Msg::ConfirmEnrollment)>
+                    {"Confirm"}
+                  </button>
+                </div>
+              </>
+            },
+            State::RecoveryCodes(codes) => html! {
+              <>
+                <p>
+                  {"Two-factor authentication is enabled. Save these recovery codes: each of \
+                    them can replace a code once, if you lose your authenticator. They won't be \
+                    shown again."}
+                </p>
+                <ul>
+                  {for codes.iter().map(|code| html! {<li><code>{code}</code></li>})}
+                </ul>
+                <button
+                  class="btn btn-primary"
+                  onclick=self.common.callback(|_| Msg::RecoveryCodesSaved)>
+                  {"I saved the recovery codes"}
+                </button>
+              </>
+            },
+            State::Enabled => html! {
+              <>
+                <p>{"Two-factor authentication is enabled."}</p>
+                {if self.is_own_account() || self.common.is_admin { html! {
+                  <button
+                    class="btn btn-danger"
+                    disabled=self.common.is_task_running()
+                    onclick=self.common.callback(|_| Msg::Disable)>
+                    <i class="bi-shield-x me-2"></i>
+                    {"Disable two-factor authentication"}
+                  </button>
+                }} else { html! {} }}
+              </>
+            },
+        }
+    }
+}
+
+impl Component for TotpSetup {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = TotpSetup {
+            common: CommonComponentParts::<Self>::create(props, link),
+            state: State::Loading,
+            code: NodeRef::default(),
+        };
+        component.common.call_graphql::<GetUserTotp, _>(
+            get_user_totp::Variables {
+                id: component.common.username.clone(),
+            },
+            Msg::StatusResponse,
+            "Error trying to fetch the TOTP status",
+        );
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-4">
+              <h5 class="fw-bold">
+                {"Two-factor authentication"}
+              </h5>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            {self.view_state()}
+          </>
+        }
+    }
+}
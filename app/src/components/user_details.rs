@@ -1,8 +1,13 @@
 use crate::{
     components::{
+        account_validity::AccountValidity,
         add_user_to_group::AddUserToGroupComponent,
+        password_reset_link::PasswordResetLink,
         remove_user_from_group::RemoveUserFromGroupComponent,
         router::{AppRoute, Link, NavButton},
+        sessions::Sessions,
+        temporary_password::TemporaryPassword,
+        toggle_user_enabled::ToggleUserEnabled,
         user_details_form::UserDetailsForm,
     },
     infra::common_component::{CommonComponent, CommonComponentParts},
@@ -28,6 +33,8 @@ pub struct UserDetails {
     /// The user info. If none, the error is in `error`. If `error` is None, then we haven't
     /// received the server response yet.
     user: Option<User>,
+    /// How long before the expiration of the password to warn about it.
+    expiry_warning_days: i64,
 }
 
 /// State machine describing the possible transitions of the component state.
@@ -38,6 +45,7 @@ pub enum Msg {
     OnError(Error),
     OnUserAddedToGroup(Group),
     OnUserRemovedFromGroup((String, i64)),
+    OnUserEnabledChanged((String, bool)),
 }
 
 #[derive(yew::Properties, Clone, PartialEq, Eq)]
@@ -50,7 +58,10 @@ impl CommonComponent<UserDetails> for UserDetails {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
             Msg::UserDetailsResponse(response) => match response {
-                Ok(user) => self.user = Some(user.user),
+                Ok(response) => {
+                    self.user = Some(response.user);
+                    self.expiry_warning_days = response.password_policy.expiry_warning_days.into();
+                }
                 Err(e) => {
                     self.user = None;
                     bail!("Error getting user details: {}", e);
@@ -67,6 +78,9 @@ impl CommonComponent<UserDetails> for UserDetails {
                     .groups
                     .retain(|g| g.id != group_id);
             }
+            Msg::OnUserEnabledChanged((_, enabled)) => {
+                self.user.as_mut().unwrap().enabled = enabled;
+            }
         }
         Ok(true)
     }
@@ -99,6 +113,53 @@ impl UserDetails {
         }
     }
 
+    /// Warns when the password expires soon, or already expired.
+    fn view_password_expiry(&self, u: &User) -> Html {
+        let expiration = match u.password_expiration_date {
+            Some(date) => date,
+            None => return html! {},
+        };
+        let now = chrono::Utc::now();
+        if expiration > now + chrono::Duration::days(self.expiry_warning_days) {
+            return html! {};
+        }
+        html! {
+          <div class="alert alert-warning">
+            {if expiration <= now {
+              "The password expired, it has to be changed at the next login.".to_string()
+            } else {
+              format!("The password expires on {}.", expiration.naive_local().date())
+            }}
+          </div>
+        }
+    }
+
+    /// Warns when the account can't be used to log in at the moment.
+    fn view_account_validity(&self, u: &User) -> Html {
+        if !u.enabled {
+            return html! {
+              <div class="alert alert-secondary">
+                {"The account is disabled: the user can't log in."}
+              </div>
+            };
+        }
+        let now = chrono::Utc::now();
+        let message = match (u.activation_date, u.expiration_date) {
+            (Some(activation), _) if now < activation => format!(
+                "The account is only active from {}.",
+                activation.naive_local().date()
+            ),
+            (_, Some(expiration)) if expiration <= now => format!(
+                "The account expired on {}.",
+                expiration.naive_local().date()
+            ),
+            _ => return html! {},
+        };
+        html! {
+          <div class="alert alert-warning">{message}</div>
+        }
+    }
+
     fn view_group_memberships(&self, u: &User) -> Html {
         let make_group_row = |group: &Group| {
             let display_name = group.display_name.clone();
@@ -153,6 +214,33 @@ impl UserDetails {
         }
     }
 
+    /// The groups of which the user can change the members, with links to them.
+    fn view_managed_groups(&self, u: &User) -> Html {
+        if u.managed_groups.is_empty() {
+            return html! {};
+        }
+        html! {
+          <>
+            <h5 class="row m-3 fw-bold">{"Managed groups"}</h5>
+            <div class="table-responsive">
+              <table class="table table-hover">
+                <tbody>
+                  {for u.managed_groups.iter().map(|group| html! {
+                    <tr key="managedGroupRow_".to_string() + &group.display_name>
+                      <td>
+                        <Link route=AppRoute::GroupDetails(group.id)>
+                          {&group.display_name}
+                        </Link>
+                      </td>
+                    </tr>
+                  })}
+                </tbody>
+              </table>
+            </div>
+          </>
+        }
+    }
+
     fn view_add_group_button(&self, u: &User) -> Html {
         if self.common.is_admin {
             html! {
@@ -176,6 +264,7 @@ impl Component for UserDetails {
         let mut table = Self {
             common: CommonComponentParts::<Self>::create(props, link),
             user: None,
+            expiry_warning_days: 0,
         };
         table.get_user_details();
         table
@@ -197,6 +286,8 @@ impl Component for UserDetails {
                 html! {
                   <>
                     <h3>{u.id.to_string()}</h3>
+                    {self.view_password_expiry(u)}
+                    {self.view_account_validity(u)}
                     <div class="d-flex flex-row-reverse">
                       <NavButton
                         route=AppRoute::ChangePassword(u.id.clone())
@@ -204,14 +295,38 @@ impl Component for UserDetails {
                         <i class="bi-key me-2"></i>
                         {"Modify password"}
                       </NavButton>
+                      {if self.common.is_admin { html! {
+                        <div class="me-2">
+                          <ToggleUserEnabled
+                            username=u.id.clone()
+                            enabled=u.enabled
+                            on_changed=self.common.callback(Msg::OnUserEnabledChanged)
+                            on_error=self.common.callback(Msg::OnError)/>
+                        </div>
+                      }} else { html! {} }}
                     </div>
                     <div>
                       <h5 class="row m-3 fw-bold">{"User details"}</h5>
                     </div>
                     <UserDetailsForm
-                      user=u.clone() />
+                      user=u.clone()
+                      is_admin=self.common.is_admin />
+                    {if self.common.is_admin { html! {
+                      <AccountValidity
+                        username=u.id.clone()
+                        activation_date=u.activation_date
+                        expiration_date=u.expiration_date />
+                    }} else { html! {} }}
                     {self.view_group_memberships(u)}
                     {self.view_add_group_button(u)}
+                    {self.view_managed_groups(u)}
+                    <Sessions username=u.id.clone() is_admin=self.common.is_admin />
+                    {if self.common.is_admin { html! {
+                      <>
+                        <TemporaryPassword username=u.id.clone() />
+                        <PasswordResetLink username=u.id.clone() />
+                      </>
+                    }} else { html! {} }}
                     {self.view_messages(error)}
                   </>
                 }
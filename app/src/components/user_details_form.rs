@@ -1,14 +1,14 @@
 use std::str::FromStr;
 
 use crate::{
-    components::user_details::User,
+    components::user_details::{get_user_details, User},
     infra::common_component::{CommonComponent, CommonComponentParts},
 };
 use anyhow::{bail, Error, Result};
 use graphql_client::GraphQLQuery;
 use validator_derive::Validate;
 use wasm_bindgen::JsCast;
-use yew::{prelude::*, services::ConsoleService};
+use yew::{html::ChangeData, prelude::*, services::ConsoleService};
 use yew_form_derive::Model;
 
 #[derive(PartialEq, Eq, Clone, Default)]
@@ -46,6 +46,11 @@ pub struct UserModel {
     display_name: String,
     first_name: String,
     last_name: String,
+    preferred_language: String,
+    uid_number: String,
+    gid_number: String,
+    home_directory: String,
+    login_shell: String,
 }
 
 /// The GraphQL query sent to the server to update the user details.
@@ -59,13 +64,67 @@ pub struct UserModel {
 )]
 pub struct UpdateUser;
 
+/// The GraphQL query sent to the server to get the sections of the page.
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_user_details_layout.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetUserDetailsLayout;
+
+pub type Section = get_user_details_layout::GetUserDetailsLayoutUserDetailsLayout;
+pub type AttributeSchema = get_user_details_layout::GetUserDetailsLayoutUserAttributeSchema;
+use get_user_details_layout::AttributeType;
+
+/// The layout used until the server sends the configured one: all the fields, in one section.
+fn default_layout() -> Vec<Section> {
+    vec![Section {
+        title: None,
+        fields: [
+            "user_id",
+            "creation_date",
+            "uuid",
+            "email",
+            "email_aliases",
+            "display_name",
+            "first_name",
+            "last_name",
+            "preferred_language",
+            "ssh_public_keys",
+            "posix_attributes",
+            "avatar",
+            "custom_attributes",
+        ]
+        .iter()
+        .map(|f| f.to_string())
+        .collect(),
+    }]
+}
+
 /// A [yew::Component] to display the user details, with a form allowing to edit them.
 pub struct UserDetailsForm {
     common: CommonComponentParts<Self>,
     form: yew_form::Form<UserModel>,
     avatar: JsFile,
+    /// The additional email addresses, as edited in the form.
+    email_aliases: Vec<String>,
+    /// The input used to add a new email alias.
+    new_email_alias: NodeRef,
+    /// The public SSH keys, as edited in the form.
+    ssh_public_keys: Vec<String>,
+    /// The input used to add a new SSH key.
+    new_ssh_public_key: NodeRef,
     /// True if we just successfully updated the user, to display a success message.
     just_updated: bool,
+    /// The sections of the page, with the fields to display.
+    layout: Vec<Section>,
+    /// The custom attributes defined by the admins, sent with the layout.
+    attribute_schema: Vec<AttributeSchema>,
+    /// The custom attributes as edited in the form, by name. The values of the lists are on
+    /// separate lines.
+    custom_attributes: Vec<(String, String)>,
 }
 
 pub enum Msg {
@@ -75,14 +134,30 @@ pub enum Msg {
     SubmitClicked,
     /// A picked file finished loading.
     FileLoaded(yew::services::reader::FileData),
+    /// The "Add" button next to the new email alias was clicked.
+    AddEmailAlias,
+    /// An email alias should be removed.
+    RemoveEmailAlias(usize),
+    /// An email alias should be swapped with the primary email.
+    MakeEmailPrimary(usize),
+    /// The "Add" button next to the new SSH key was clicked.
+    AddSshPublicKey,
+    /// An SSH key should be removed.
+    RemoveSshPublicKey(usize),
+    /// The input of a custom attribute changed.
+    CustomAttributeChanged(String, String),
     /// We got the response from the server about our update message.
     UserUpdated(Result<update_user::ResponseData>),
+    /// We got the layout of the page from the server.
+    LayoutResponse(Result<get_user_details_layout::ResponseData>),
 }
 
 #[derive(yew::Properties, Clone, PartialEq, Eq)]
 pub struct Props {
     /// The current user details.
     pub user: User,
+    /// Only admins can edit the POSIX attributes.
+    pub is_admin: bool,
 }
 
 impl CommonComponent<UserDetailsForm> for UserDetailsForm {
@@ -91,13 +166,14 @@ impl CommonComponent<UserDetailsForm> for UserDetailsForm {
             Msg::Update => {
                 let window = web_sys::window().expect("no global `window` exists");
                 let document = window.document().expect("should have a document on window");
-                let input = document
-                    .get_element_by_id("avatarInput")
-                    .expect("Form field avatarInput should be present")
-                    .dyn_into::<web_sys::HtmlInputElement>()
-                    .expect("Should be an HtmlInputElement");
                 ConsoleService::log("Form update");
-                if let Some(files) = input.files() {
+                // The avatar field is absent if the layout hides it.
+                let input = document.get_element_by_id("avatarInput").map(|input| {
+                    input
+                        .dyn_into::<web_sys::HtmlInputElement>()
+                        .expect("Should be an HtmlInputElement")
+                });
+                if let Some(files) = input.and_then(|input| input.files()) {
                     ConsoleService::log("Got file list");
                     if files.length() > 0 {
                         ConsoleService::log("Got a file");
@@ -119,6 +195,18 @@ impl CommonComponent<UserDetailsForm> for UserDetailsForm {
             }
             Msg::SubmitClicked => self.submit_user_update_form(),
             Msg::UserUpdated(response) => self.user_update_finished(response),
+            Msg::LayoutResponse(response) => {
+                self.common.cancel_task();
+                match response {
+                    Ok(data) => {
+                        self.layout = data.user_details_layout;
+                        self.attribute_schema = data.user_attribute_schema;
+                    }
+                    // Keep the default layout: the page is still usable.
+                    Err(e) => ConsoleService::error(&format!("Error getting the layout: {}", e)),
+                }
+                Ok(true)
+            }
             Msg::FileLoaded(data) => {
                 self.common.cancel_task();
                 if let Some(file) = &self.avatar.file {
@@ -135,6 +223,61 @@ impl CommonComponent<UserDetailsForm> for UserDetailsForm {
                 }
                 Ok(false)
             }
+            Msg::AddEmailAlias => {
+                let input = self
+                    .new_email_alias
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("Email alias input should be present");
+                let alias = input.value().trim().to_owned();
+                if alias.is_empty() {
+                    return Ok(false);
+                }
+                if !alias.contains('@') {
+                    bail!("Invalid email address: {}", alias);
+                }
+                if !self.email_aliases.contains(&alias) {
+                    self.email_aliases.push(alias);
+                }
+                input.set_value("");
+                Ok(true)
+            }
+            Msg::RemoveEmailAlias(index) => {
+                self.email_aliases.remove(index);
+                Ok(true)
+            }
+            Msg::MakeEmailPrimary(index) => {
+                let mut model = self.form.model();
+                std::mem::swap(&mut model.email, &mut self.email_aliases[index]);
+                self.form = yew_form::Form::new(model);
+                Ok(true)
+            }
+            Msg::AddSshPublicKey => {
+                let input = self
+                    .new_ssh_public_key
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("SSH key input should be present");
+                let key = input.value().trim().to_owned();
+                if key.is_empty() {
+                    return Ok(false);
+                }
+                // The server checks the key format when saving.
+                if !self.ssh_public_keys.contains(&key) {
+                    self.ssh_public_keys.push(key);
+                }
+                input.set_value("");
+                Ok(true)
+            }
+            Msg::RemoveSshPublicKey(index) => {
+                self.ssh_public_keys.remove(index);
+                Ok(true)
+            }
+            Msg::CustomAttributeChanged(name, text) => {
+                match self.custom_attributes.iter_mut().find(|(n, _)| n == &name) {
+                    Some((_, current)) => *current = text,
+                    None => self.custom_attributes.push((name, text)),
+                }
+                Ok(true)
+            }
         }
     }
 
@@ -153,13 +296,39 @@ impl Component for UserDetailsForm {
             display_name: props.user.display_name.clone(),
             first_name: props.user.first_name.clone(),
             last_name: props.user.last_name.clone(),
+            preferred_language: props.user.preferred_language.clone().unwrap_or_default(),
+            uid_number: to_string_or_empty(props.user.uid_number),
+            gid_number: to_string_or_empty(props.user.gid_number),
+            home_directory: props.user.home_directory.clone().unwrap_or_default(),
+            login_shell: props.user.login_shell.clone().unwrap_or_default(),
         };
-        Self {
+        let email_aliases = props.user.email_aliases.clone();
+        let ssh_public_keys = props.user.ssh_public_keys.clone();
+        let custom_attributes = props
+            .user
+            .attributes
+            .iter()
+            .map(|a| (a.name.clone(), a.values.join("\n")))
+            .collect();
+        let mut form = Self {
             common: CommonComponentParts::<Self>::create(props, link),
             form: yew_form::Form::new(model),
             avatar: JsFile::default(),
+            email_aliases,
+            new_email_alias: NodeRef::default(),
+            ssh_public_keys,
+            new_ssh_public_key: NodeRef::default(),
             just_updated: false,
-        }
+            layout: default_layout(),
+            attribute_schema: Vec::new(),
+            custom_attributes,
+        };
+        form.common.call_graphql::<GetUserDetailsLayout, _>(
+            get_user_details_layout::Variables {},
+            Msg::LayoutResponse,
+            "Error trying to fetch the user details layout",
+        );
+        form
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
@@ -172,16 +341,67 @@ impl Component for UserDetailsForm {
     }
 
     fn view(&self) -> Html {
-        type Field = yew_form::Field<UserModel>;
-
-        let avatar_base64 = maybe_to_base64(&self.avatar).unwrap_or_default();
-        let avatar_string = avatar_base64
-            .as_deref()
-            .or(self.common.user.avatar.as_deref())
-            .unwrap_or("");
         html! {
           <div class="py-3">
             <form class="form">
+              {
+                self.layout
+                  .iter()
+                  .map(|section| self.view_section(section))
+                  .collect::<Vec<_>>()
+              }
+              <div class="form-group row justify-content-center mt-3">
+                <button
+                  type="submit"
+                  class="btn btn-primary col-auto col-form-label"
+                  disabled=self.common.is_task_running()
+                  onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitClicked})>
+                  <i class="bi-save me-2"></i>
+                  {"Save changes"}
+                </button>
+              </div>
+            </form>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            <div hidden=!self.just_updated>
+              <div class="alert alert-success mt-4">{"User successfully updated!"}</div>
+            </div>
+          </div>
+        }
+    }
+}
+
+impl UserDetailsForm {
+    fn view_section(&self, section: &Section) -> Html {
+        html! {
+          <>
+            {
+              match &section.title {
+                None => html! {},
+                Some(title) => html! {<h5 class="mt-4 mb-3">{title}</h5>},
+              }
+            }
+            {
+              section.fields
+                .iter()
+                .map(|field| self.view_field(field))
+                .collect::<Vec<_>>()
+            }
+          </>
+        }
+    }
+
+    fn view_field(&self, field: &str) -> Html {
+        type Field = yew_form::Field<UserModel>;
+        match field {
+            "user_id" => html! {
               <div class="form-group row mb-3">
                 <label for="userId"
                   class="form-label col-4 col-form-label">
@@ -191,6 +411,8 @@ impl Component for UserDetailsForm {
                   <span id="userId" class="form-control-static"><i>{&self.common.user.id}</i></span>
                 </div>
               </div>
+            },
+            "creation_date" => html! {
               <div class="form-group row mb-3">
                 <label for="creationDate"
                   class="form-label col-4 col-form-label">
@@ -200,6 +422,8 @@ impl Component for UserDetailsForm {
                   <span id="creationDate" class="form-control-static">{&self.common.user.creation_date.naive_local().date()}</span>
                 </div>
               </div>
+            },
+            "uuid" => html! {
               <div class="form-group row mb-3">
                 <label for="uuid"
                   class="form-label col-4 col-form-label">
@@ -209,6 +433,8 @@ impl Component for UserDetailsForm {
                   <span id="creationDate" class="form-control-static">{&self.common.user.uuid}</span>
                 </div>
               </div>
+            },
+            "email" => html! {
               <div class="form-group row mb-3">
                 <label for="email"
                   class="form-label col-4 col-form-label">
@@ -230,6 +456,39 @@ impl Component for UserDetailsForm {
                   </div>
                 </div>
               </div>
+            },
+            "email_aliases" => html! {
+              <div class="form-group row mb-3">
+                <label for="newEmailAlias"
+                  class="form-label col-4 col-form-label">
+                  {"Other emails: "}
+                </label>
+                <div class="col-8">
+                  {
+                    self.email_aliases
+                      .iter()
+                      .enumerate()
+                      .map(|(i, alias)| self.view_email_alias(i, alias))
+                      .collect::<Vec<_>>()
+                  }
+                  <div class="input-group">
+                    <input
+                      class="form-control"
+                      id="newEmailAlias"
+                      type="email"
+                      placeholder="Add an email address"
+                      ref=self.new_email_alias.clone() />
+                    <button
+                      type="button"
+                      class="btn btn-outline-primary"
+                      onclick=self.common.callback(|_| Msg::AddEmailAlias)>
+                      {"Add"}
+                    </button>
+                  </div>
+                </div>
+              </div>
+            },
+            "display_name" => html! {
               <div class="form-group row mb-3">
                 <label for="display_name"
                   class="form-label col-4 col-form-label">
@@ -249,6 +508,8 @@ impl Component for UserDetailsForm {
                   </div>
                 </div>
               </div>
+            },
+            "first_name" => html! {
               <div class="form-group row mb-3">
                 <label for="first_name"
                   class="form-label col-4 col-form-label">
@@ -266,6 +527,8 @@ impl Component for UserDetailsForm {
                   </div>
                 </div>
               </div>
+            },
+            "last_name" => html! {
               <div class="form-group row mb-3">
                 <label for="last_name"
                   class="form-label col-4 col-form-label">
@@ -283,60 +546,274 @@ impl Component for UserDetailsForm {
                   </div>
                 </div>
               </div>
-              <div class="form-group row align-items-center mb-3">
-                <label for="avatar"
+            },
+            "preferred_language" => html! {
+              <div class="form-group row mb-3">
+                <label for="preferred_language"
                   class="form-label col-4 col-form-label">
-                  {"Avatar: "}
+                  {"Preferred language: "}
                 </label>
                 <div class="col-8">
-                  <div class="row align-items-center">
-                    <div class="col-8">
-                      <input
-                        class="form-control"
-                        id="avatarInput"
-                        type="file"
-                        accept="image/jpeg"
-                        oninput=self.common.callback(|_| Msg::Update) />
-                    </div>
-                    <div class="col-4">
-                      <img
-                        id="avatarDisplay"
-                        src={format!("data:image/jpeg;base64, {}", avatar_string)}
-                        style="max-height:128px;max-width:128px;height:auto;width:auto;"
-                        alt="Avatar" />
-                    </div>
+                  <Field
+                    class="form-control"
+                    form=&self.form
+                    field_name="preferred_language"
+                    autocomplete="language"
+                    placeholder="Server default"
+                    oninput=self.common.callback(|_| Msg::Update) />
+                  <div class="invalid-feedback">
+                    {&self.form.field_message("preferred_language")}
                   </div>
                 </div>
               </div>
-              <div class="form-group row justify-content-center mt-3">
-                <button
-                  type="submit"
-                  class="btn btn-primary col-auto col-form-label"
-                  disabled=self.common.is_task_running()
-                  onclick=self.common.callback(|e: MouseEvent| {e.prevent_default(); Msg::SubmitClicked})>
-                  <i class="bi-save me-2"></i>
-                  {"Save changes"}
-                </button>
+            },
+            "ssh_public_keys" => html! {
+              <div class="form-group row mb-3">
+                <label for="newSshPublicKey"
+                  class="form-label col-4 col-form-label">
+                  {"SSH public keys: "}
+                </label>
+                <div class="col-8">
+                  {
+                    self.ssh_public_keys
+                      .iter()
+                      .enumerate()
+                      .map(|(i, key)| self.view_ssh_public_key(i, key))
+                      .collect::<Vec<_>>()
+                  }
+                  <div class="input-group">
+                    <input
+                      class="form-control"
+                      id="newSshPublicKey"
+                      type="text"
+                      placeholder="ssh-ed25519 AAAA... user@host"
+                      ref=self.new_ssh_public_key.clone() />
+                    <button
+                      type="button"
+                      class="btn btn-outline-primary"
+                      onclick=self.common.callback(|_| Msg::AddSshPublicKey)>
+                      {"Add"}
+                    </button>
+                  </div>
+                </div>
               </div>
-            </form>
-            {
-              if let Some(e) = &self.common.error {
+            },
+            "posix_attributes" => {
+                let user = &self.common.user;
+                let uid_number = user.uid_number.map(|n| n.to_string());
+                let gid_number = user.gid_number.map(|n| n.to_string());
                 html! {
-                  <div class="alert alert-danger">
-                    {e.to_string() }
+                  <>
+                    {self.view_posix_field("uid_number", "UID number: ", uid_number)}
+                    {self.view_posix_field("gid_number", "GID number: ", gid_number)}
+                    {self.view_posix_field(
+                      "home_directory", "Home directory: ", user.home_directory.clone())}
+                    {self.view_posix_field(
+                      "login_shell", "Login shell: ", user.login_shell.clone())}
+                  </>
+                }
+            }
+            "avatar" => {
+                let avatar_base64 = maybe_to_base64(&self.avatar).unwrap_or_default();
+                let avatar_string = avatar_base64
+                    .as_deref()
+                    .or(self.common.user.avatar.as_deref())
+                    .unwrap_or("");
+                html! {
+                  <div class="form-group row align-items-center mb-3">
+                    <label for="avatar"
+                      class="form-label col-4 col-form-label">
+                      {"Avatar: "}
+                    </label>
+                    <div class="col-8">
+                      <div class="row align-items-center">
+                        <div class="col-8">
+                          <input
+                            class="form-control"
+                            id="avatarInput"
+                            type="file"
+                            accept="image/jpeg"
+                            oninput=self.common.callback(|_| Msg::Update) />
+                        </div>
+                        <div class="col-4">
+                          <img
+                            id="avatarDisplay"
+                            src={format!("data:image/jpeg;base64, {}", avatar_string)}
+                            style="max-height:128px;max-width:128px;height:auto;width:auto;"
+                            alt="Avatar" />
+                        </div>
+                      </div>
+                    </div>
                   </div>
                 }
-              } else { html! {} }
             }
-            <div hidden=!self.just_updated>
-              <div class="alert alert-success mt-4">{"User successfully updated!"}</div>
+            "custom_attributes" => html! {
+              <>
+                {
+                  self.attribute_schema
+                    .iter()
+                    .map(|schema| self.view_custom_attribute(schema))
+                    .collect::<Vec<_>>()
+                }
+              </>
+            },
+            // Fields added in a newer server version.
+            _ => html! {},
+        }
+    }
+
+    /// An editable field for admins, read-only for the other users.
+    fn view_posix_field(&self, field_name: &str, label: &str, value: Option<String>) -> Html {
+        type Field = yew_form::Field<UserModel>;
+        html! {
+          <div class="form-group row mb-3">
+            <label for=field_name.to_string()
+              class="form-label col-4 col-form-label">
+              {label}
+            </label>
+            <div class="col-8">
+              {if self.common.is_admin {
+                html! {
+                  <>
+                    <Field
+                      class="form-control"
+                      form=&self.form
+                      field_name=field_name.to_string()
+                      oninput=self.common.callback(|_| Msg::Update) />
+                    <div class="invalid-feedback">
+                      {&self.form.field_message(field_name)}
+                    </div>
+                  </>
+                }
+              } else {
+                html! {
+                  <span id=field_name.to_string() class="form-control-static">
+                    {value.unwrap_or_default()}
+                  </span>
+                }
+              }}
             </div>
           </div>
         }
     }
-}
 
-impl UserDetailsForm {
+    fn custom_attribute_text(&self, name: &str) -> String {
+        self.custom_attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, text)| text.clone())
+            .unwrap_or_default()
+    }
+
+    /// The input matching the type of the attribute. The users can only change the editable
+    /// attributes.
+    fn view_custom_attribute(&self, schema: &AttributeSchema) -> Html {
+        let id = format!("attribute_{}", schema.name);
+        let text = self.custom_attribute_text(&schema.name);
+        let name = schema.name.clone();
+        let oninput = self
+            .common
+            .callback(move |e: InputData| Msg::CustomAttributeChanged(name.clone(), e.value));
+        let input = if !self.common.is_admin && !schema.is_editable {
+            html! {
+              <span id=id.clone() class="form-control-static text-break">
+                {text.lines().collect::<Vec<_>>().join(", ")}
+              </span>
+            }
+        } else if schema.is_list || matches!(schema.attribute_type, AttributeType::BINARY) {
+            let placeholder = if schema.is_list {
+                "One value per line"
+            } else {
+                "Base64"
+            };
+            html! {
+              <textarea
+                class="form-control"
+                id=id.clone()
+                rows="3"
+                placeholder=placeholder
+                value=text
+                oninput=oninput />
+            }
+        } else if matches!(schema.attribute_type, AttributeType::BOOLEAN) {
+            let name = schema.name.clone();
+            html! {
+              <select
+                class="form-select"
+                id=id.clone()
+                onchange=self.common.callback(move |e: ChangeData| match e {
+                    ChangeData::Select(select) => {
+                        Msg::CustomAttributeChanged(name.clone(), select.value())
+                    }
+                    _ => Msg::Update,
+                })>
+                <option value="" selected={text.is_empty()}>{"Not set"}</option>
+                <option value="true" selected={text == "true"}>{"Yes"}</option>
+                <option value="false" selected={text == "false"}>{"No"}</option>
+              </select>
+            }
+        } else {
+            let input_type = match schema.attribute_type {
+                AttributeType::INTEGER => "number",
+                AttributeType::DATE => "date",
+                _ => "text",
+            };
+            html! {
+              <input
+                class="form-control"
+                id=id.clone()
+                type=input_type
+                value=text
+                oninput=oninput />
+            }
+        };
+        html! {
+          <div class="form-group row mb-3">
+            <label for=id
+              class="form-label col-4 col-form-label">
+              {format!("{}: ", schema.name)}
+            </label>
+            <div class="col-8">
+              {input}
+            </div>
+          </div>
+        }
+    }
+
+    fn view_email_alias(&self, index: usize, alias: &str) -> Html {
+        html! {
+          <div class="input-group mb-2">
+            <span class="form-control">{alias}</span>
+            <button
+              type="button"
+              class="btn btn-outline-secondary"
+              onclick=self.common.callback(move |_| Msg::MakeEmailPrimary(index))>
+              {"Make primary"}
+            </button>
+            <button
+              type="button"
+              class="btn btn-outline-danger"
+              onclick=self.common.callback(move |_| Msg::RemoveEmailAlias(index))>
+              <i class="bi-x-circle-fill"></i>
+            </button>
+          </div>
+        }
+    }
+
+    fn view_ssh_public_key(&self, index: usize, key: &str) -> Html {
+        html! {
+          <div class="input-group mb-2">
+            <span class="form-control text-truncate" title=key.to_string()>{key}</span>
+            <button
+              type="button"
+              class="btn btn-outline-danger"
+              onclick=self.common.callback(move |_| Msg::RemoveSshPublicKey(index))>
+              <i class="bi-x-circle-fill"></i>
+            </button>
+          </div>
+        }
+    }
+
     fn submit_user_update_form(&mut self) -> Result<bool> {
         ConsoleService::log("Submit");
         if !self.form.validate() {
@@ -359,6 +836,15 @@ impl UserDetailsForm {
             firstName: None,
             lastName: None,
             avatar: None,
+            preferredLanguage: None,
+            emailAliases: None,
+            sshPublicKeys: None,
+            uidNumber: None,
+            gidNumber: None,
+            homeDirectory: None,
+            loginShell: None,
+            loginNotifications: None,
+            attributes: None,
         };
         let default_user_input = user_input.clone();
         let model = self.form.model();
@@ -375,6 +861,58 @@ impl UserDetailsForm {
         if base_user.last_name != model.last_name {
             user_input.lastName = Some(model.last_name);
         }
+        if base_user.preferred_language.as_deref().unwrap_or_default() != model.preferred_language {
+            user_input.preferredLanguage = Some(model.preferred_language);
+        }
+        // The POSIX attributes can be changed but not removed, so empty values are ignored.
+        if self.common.is_admin {
+            let uid_number = parse_posix_id("UID", &model.uid_number)?;
+            if uid_number.is_some() && uid_number != base_user.uid_number {
+                user_input.uidNumber = uid_number;
+            }
+            let gid_number = parse_posix_id("GID", &model.gid_number)?;
+            if gid_number.is_some() && gid_number != base_user.gid_number {
+                user_input.gidNumber = gid_number;
+            }
+            if !model.home_directory.is_empty()
+                && base_user.home_directory.as_deref() != Some(model.home_directory.as_str())
+            {
+                user_input.homeDirectory = Some(model.home_directory.clone());
+            }
+            if !model.login_shell.is_empty()
+                && base_user.login_shell.as_deref() != Some(model.login_shell.as_str())
+            {
+                user_input.loginShell = Some(model.login_shell.clone());
+            }
+        }
+        if base_user.email_aliases != self.email_aliases {
+            user_input.emailAliases = Some(self.email_aliases.clone());
+        }
+        if base_user.ssh_public_keys != self.ssh_public_keys {
+            user_input.sshPublicKeys = Some(self.ssh_public_keys.clone());
+        }
+        let changed_attributes = self
+            .attribute_schema
+            .iter()
+            .filter(|schema| self.common.is_admin || schema.is_editable)
+            .filter_map(|schema| {
+                let values =
+                    to_attribute_values(schema.is_list, &self.custom_attribute_text(&schema.name));
+                let base_values = base_user
+                    .attributes
+                    .iter()
+                    .find(|a| a.name == schema.name)
+                    .map(|a| a.values.clone())
+                    .unwrap_or_default();
+                (values != base_values).then(|| update_user::AttributeValueInput {
+                    name: schema.name.clone(),
+                    values,
+                })
+            })
+            .collect::<Vec<_>>();
+        if !changed_attributes.is_empty() {
+            user_input.attributes = Some(changed_attributes);
+        }
         user_input.avatar = maybe_to_base64(&self.avatar)?;
         // Nothing changed.
         if user_input == default_user_input {
@@ -398,12 +936,43 @@ impl UserDetailsForm {
             Ok(_) => {
                 let model = self.form.model();
                 self.common.user.email = model.email;
+                self.common.user.email_aliases = self.email_aliases.clone();
+                self.common.user.ssh_public_keys = self.ssh_public_keys.clone();
                 self.common.user.display_name = model.display_name;
                 self.common.user.first_name = model.first_name;
                 self.common.user.last_name = model.last_name;
+                self.common.user.preferred_language =
+                    Some(model.preferred_language).filter(|l| !l.is_empty());
+                if self.common.is_admin {
+                    let uid_number = parse_posix_id("UID", &model.uid_number)?;
+                    let gid_number = parse_posix_id("GID", &model.gid_number)?;
+                    let user = &mut self.common.user;
+                    user.uid_number = uid_number.or(user.uid_number);
+                    user.gid_number = gid_number.or(user.gid_number);
+                    if !model.home_directory.is_empty() {
+                        user.home_directory = Some(model.home_directory);
+                    }
+                    if !model.login_shell.is_empty() {
+                        user.login_shell = Some(model.login_shell);
+                    }
+                }
                 if let Some(avatar) = maybe_to_base64(&self.avatar)? {
                     self.common.user.avatar = Some(avatar);
                 }
+                let mut attributes = self
+                    .attribute_schema
+                    .iter()
+                    .map(|schema| get_user_details::GetUserDetailsUserAttributes {
+                        name: schema.name.clone(),
+                        values: to_attribute_values(
+                            schema.is_list,
+                            &self.custom_attribute_text(&schema.name),
+                        ),
+                    })
+                    .filter(|a| !a.values.is_empty())
+                    .collect::<Vec<_>>();
+                attributes.sort_by(|a, b| a.name.cmp(&b.name));
+                self.common.user.attributes = attributes;
                 self.just_updated = true;
             }
         };
@@ -411,6 +980,38 @@ impl UserDetailsForm {
     }
 }
 
+/// The values of a custom attribute from its input, without the empty ones. Also used for the
+/// group attributes.
+pub fn to_attribute_values(is_list: bool, text: &str) -> Vec<String> {
+    if is_list {
+        text.lines()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_owned)
+            .collect()
+    } else if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![text.trim().to_owned()]
+    }
+}
+
+fn to_string_or_empty(number: Option<i64>) -> String {
+    number.map(|n| n.to_string()).unwrap_or_default()
+}
+
+/// Parses a UID or GID number from the form, empty meaning unset.
+fn parse_posix_id(name: &str, value: &str) -> Result<Option<i64>> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.parse::<i32>() {
+        Ok(id) => Ok(Some(i64::from(id))),
+        Err(_) => bail!("Invalid {} number: {}", name, value),
+    }
+}
+
 fn is_valid_jpeg(bytes: &[u8]) -> bool {
     image::io::Reader::with_format(std::io::Cursor::new(bytes), image::ImageFormat::Jpeg)
         .decode()
@@ -2,8 +2,13 @@ use crate::{
     components::{
         delete_user::DeleteUser,
         router::{AppRoute, Link},
+        toggle_user_enabled::ToggleUserEnabled,
+    },
+    infra::{
+        common_component::{CommonComponent, CommonComponentParts},
+        directory_changes::DirectoryChanges,
+        pagination::Pagination,
     },
-    infra::common_component::{CommonComponent, CommonComponentParts},
 };
 use anyhow::{Error, Result};
 use graphql_client::GraphQLQuery;
@@ -18,32 +23,195 @@ use yew::prelude::*;
 )]
 pub struct ListUsersQuery;
 
-use list_users_query::{RequestFilter, ResponseData};
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/search_users.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct SearchUsersQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/unlock_user.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct UnlockUser;
 
-type User = list_users_query::ListUsersQueryUsers;
+use list_users_query::{ResponseData, UserOrderBy};
+
+type User = list_users_query::ListUsersQueryUsersPageNodes;
+
+/// The columns that the table can be sorted by.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    UserId,
+    Email,
+    DisplayName,
+    FirstName,
+    LastName,
+    CreationDate,
+}
+
+impl From<SortColumn> for UserOrderBy {
+    fn from(column: SortColumn) -> Self {
+        match column {
+            SortColumn::UserId => UserOrderBy::USER_ID,
+            SortColumn::Email => UserOrderBy::EMAIL,
+            SortColumn::DisplayName => UserOrderBy::DISPLAY_NAME,
+            SortColumn::FirstName => UserOrderBy::FIRST_NAME,
+            SortColumn::LastName => UserOrderBy::LAST_NAME,
+            SortColumn::CreationDate => UserOrderBy::CREATION_DATE,
+        }
+    }
+}
+
+impl From<search_users_query::SearchUsersQuerySearchUsers> for User {
+    fn from(user: search_users_query::SearchUsersQuerySearchUsers) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            display_name: user.display_name,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            creation_date: user.creation_date,
+            locked_date: user.locked_date,
+            enabled: user.enabled,
+        }
+    }
+}
 
 pub struct UserTable {
     common: CommonComponentParts<Self>,
     users: Option<Vec<User>>,
+    search: String,
+    /// The search results come in a single page.
+    pagination: Pagination,
+    order_by: SortColumn,
+    descending: bool,
+    /// Refreshes the list when another admin changes the users. `None` if the connection failed.
+    _changes: Option<DirectoryChanges>,
 }
 
 pub enum Msg {
     ListUsersResponse(Result<ResponseData>),
+    SearchUpdated(String),
+    SearchUsersResponse(Result<search_users_query::ResponseData>),
+    PreviousPage,
+    NextPage,
+    SortBy(SortColumn),
     OnUserDeleted(String),
+    Unlock(String),
+    UnlockResponse((String, Result<unlock_user::ResponseData>)),
+    /// The user was disabled, or enabled again.
+    OnUserEnabledChanged((String, bool)),
     OnError(Error),
+    /// A user was changed elsewhere.
+    DirectoryChanged,
 }
 
 impl CommonComponent<UserTable> for UserTable {
     fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
         match msg {
             Msg::ListUsersResponse(users) => {
-                self.users = Some(users?.users.into_iter().collect());
+                self.common.cancel_task();
+                let page = users?.users_page;
+                self.pagination.set_page_info(
+                    page.page_info.end_cursor,
+                    page.page_info.has_next_page,
+                    page.total_count,
+                );
+                self.users = Some(page.nodes);
+                Ok(true)
+            }
+            Msg::SearchUpdated(search) => {
+                self.search = search;
+                if self.search.trim().is_empty() {
+                    self.pagination.reset();
+                    self.get_users();
+                } else {
+                    self.search_users();
+                }
+                Ok(false)
+            }
+            Msg::SearchUsersResponse(users) => {
+                self.users = Some(users?.search_users.into_iter().map(Into::into).collect());
+                Ok(true)
+            }
+            Msg::PreviousPage => {
+                if self.pagination.go_previous() {
+                    self.get_users();
+                }
+                Ok(false)
+            }
+            Msg::NextPage => {
+                if self.pagination.go_next() {
+                    self.get_users();
+                }
+                Ok(false)
+            }
+            Msg::SortBy(column) => {
+                if self.order_by == column {
+                    self.descending = !self.descending;
+                } else {
+                    self.order_by = column;
+                    self.descending = false;
+                }
+                // The search results are sorted by relevance.
+                self.search.clear();
+                self.pagination.reset();
+                self.get_users();
                 Ok(true)
             }
             Msg::OnError(e) => Err(e),
+            Msg::OnUserEnabledChanged((user_id, enabled)) => {
+                if let Some(user) = self
+                    .users
+                    .as_mut()
+                    .and_then(|users| users.iter_mut().find(|u| u.id == user_id))
+                {
+                    user.enabled = enabled;
+                }
+                Ok(true)
+            }
+            Msg::DirectoryChanged => {
+                // Reloads the current page, or the search results.
+                if self.search.trim().is_empty() {
+                    self.get_users();
+                } else {
+                    self.search_users();
+                }
+                Ok(false)
+            }
             Msg::OnUserDeleted(user_id) => {
                 debug_assert!(self.users.is_some());
                 self.users.as_mut().unwrap().retain(|u| u.id != user_id);
+                self.pagination.total_count -= 1;
+                Ok(true)
+            }
+            Msg::Unlock(user_id) => {
+                self.common.call_graphql::<UnlockUser, _>(
+                    unlock_user::Variables {
+                        user_id: user_id.clone(),
+                    },
+                    move |r| Msg::UnlockResponse((user_id.clone(), r)),
+                    "Error trying to unlock the user",
+                );
+                Ok(true)
+            }
+            Msg::UnlockResponse((user_id, response)) => {
+                self.common.cancel_task();
+                response?;
+                if let Some(user) = self
+                    .users
+                    .as_mut()
+                    .and_then(|users| users.iter_mut().find(|u| u.id == user_id))
+                {
+                    user.locked_date = None;
+                }
                 Ok(true)
             }
         }
@@ -55,13 +223,29 @@ impl CommonComponent<UserTable> for UserTable {
 }
 
 impl UserTable {
-    fn get_users(&mut self, req: Option<RequestFilter>) {
+    fn get_users(&mut self) {
         self.common.call_graphql::<ListUsersQuery, _>(
-            list_users_query::Variables { filters: req },
+            list_users_query::Variables {
+                filters: None,
+                first: None,
+                after: self.pagination.after(),
+                order_by: Some(self.order_by.into()),
+                descending: Some(self.descending),
+            },
             Msg::ListUsersResponse,
             "Error trying to fetch users",
         );
     }
+
+    fn search_users(&mut self) {
+        self.common.call_graphql::<SearchUsersQuery, _>(
+            search_users_query::Variables {
+                query: self.search.clone(),
+            },
+            Msg::SearchUsersResponse,
+            "Error trying to search users",
+        );
+    }
 }
 
 impl Component for UserTable {
@@ -69,11 +253,17 @@ impl Component for UserTable {
     type Properties = ();
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let changes = DirectoryChanges::subscribe(link.callback(|_| Msg::DirectoryChanged)).ok();
         let mut table = UserTable {
             common: CommonComponentParts::<Self>::create(props, link),
             users: None,
+            search: String::new(),
+            pagination: Pagination::default(),
+            order_by: SortColumn::UserId,
+            descending: false,
+            _changes: changes,
         };
-        table.get_users(None);
+        table.get_users();
         table
     }
 
@@ -88,7 +278,14 @@ impl Component for UserTable {
     fn view(&self) -> Html {
         html! {
             <div>
+              <input
+                type="search"
+                class="form-control mb-3"
+                placeholder="Search users"
+                value=self.search.clone()
+                oninput=self.common.callback(|e: InputData| Msg::SearchUpdated(e.value)) />
               {self.view_users()}
+              {self.view_pagination()}
               {self.view_errors()}
             </div>
         }
@@ -103,12 +300,14 @@ impl UserTable {
                   <table class="table table-hover">
                     <thead>
                       <tr>
-                        <th>{"User ID"}</th>
-                        <th>{"Email"}</th>
-                        <th>{"Display name"}</th>
-                        <th>{"First name"}</th>
-                        <th>{"Last name"}</th>
-                        <th>{"Creation date"}</th>
+                        {self.view_sort_header("User ID", SortColumn::UserId)}
+                        {self.view_sort_header("Email", SortColumn::Email)}
+                        {self.view_sort_header("Display name", SortColumn::DisplayName)}
+                        {self.view_sort_header("First name", SortColumn::FirstName)}
+                        {self.view_sort_header("Last name", SortColumn::LastName)}
+                        {self.view_sort_header("Creation date", SortColumn::CreationDate)}
+                        <th>{"Locked"}</th>
+                        <th>{"Enabled"}</th>
                         <th>{"Delete"}</th>
                       </tr>
                     </thead>
@@ -125,15 +324,54 @@ impl UserTable {
         }
     }
 
+    /// Clicking the header sorts by the column, or reverses the order.
+    fn view_sort_header(&self, title: &'static str, column: SortColumn) -> Html {
+        let icon = match (self.order_by == column, self.descending) {
+            (false, _) => "",
+            (true, false) => "bi-caret-up-fill",
+            (true, true) => "bi-caret-down-fill",
+        };
+        html! {
+          <th role="button" onclick=self.common.callback(move |_| Msg::SortBy(column))>
+            {title}
+            <i class=format!("ms-1 {}", icon)></i>
+          </th>
+        }
+    }
+
+    fn view_pagination(&self) -> Html {
+        if !self.search.trim().is_empty() {
+            return html! {};
+        }
+        self.pagination.view(
+            self.common.callback(|_| Msg::PreviousPage),
+            self.common.callback(|_| Msg::NextPage),
+            self.common.is_task_running(),
+        )
+    }
+
     fn view_user(&self, user: &User) -> Html {
         html! {
-          <tr key=user.id.clone()>
-              <td><Link route=AppRoute::UserDetails(user.id.clone())>{&user.id}</Link></td>
+          <tr key=user.id.clone() class={if user.enabled { "" } else { "table-secondary" }}>
+              <td>
+                <Link route=AppRoute::UserDetails(user.id.clone())>{&user.id}</Link>
+                {if user.enabled { html! {} } else { html! {
+                  <span class="badge bg-secondary ms-2">{"Disabled"}</span>
+                }}}
+              </td>
               <td>{&user.email}</td>
               <td>{&user.display_name}</td>
               <td>{&user.first_name}</td>
               <td>{&user.last_name}</td>
               <td>{&user.creation_date.naive_local().date()}</td>
+              <td>{self.view_lock(user)}</td>
+              <td>
+                <ToggleUserEnabled
+                  username=user.id.clone()
+                  enabled=user.enabled
+                  on_changed=self.common.callback(Msg::OnUserEnabledChanged)
+                  on_error=self.common.callback(Msg::OnError)/>
+              </td>
               <td>
                 <DeleteUser
                   username=user.id.clone()
@@ -144,6 +382,26 @@ impl UserTable {
         }
     }
 
+    /// Locked after too many wrong passwords.
+    fn view_lock(&self, user: &User) -> Html {
+        match &user.locked_date {
+            None => html! {},
+            Some(locked_date) => {
+                let user_id = user.id.clone();
+                html! {
+                  <button
+                    class="btn btn-warning btn-sm"
+                    title=format!("Locked since {}", locked_date.naive_local().date())
+                    disabled=self.common.is_task_running()
+                    onclick=self.common.callback(move |_| Msg::Unlock(user_id.clone()))>
+                    <i class="bi-unlock me-2"></i>
+                    {"Unlock"}
+                  </button>
+                }
+            }
+        }
+    }
+
     fn view_errors(&self) -> Html {
         match &self.common.error {
             None => html! {},
@@ -0,0 +1,251 @@
+use crate::infra::{
+    api::HostService,
+    common_component::{CommonComponent, CommonComponentParts},
+    cookies::get_cookie,
+    webauthn::create_credential,
+};
+use anyhow::Result;
+use graphql_client::GraphQLQuery;
+use lldap_auth::webauthn;
+use yew::prelude::*;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/get_user_webauthn_credentials.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct GetUserWebauthnCredentials;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "../schema.graphql",
+    query_path = "queries/delete_webauthn_credential.graphql",
+    response_derives = "Debug",
+    custom_scalars_module = "crate::infra::graphql"
+)]
+pub struct DeleteWebauthnCredential;
+
+type Credential = get_user_webauthn_credentials::GetUserWebauthnCredentialsUserWebauthnCredentials;
+
+/// The security keys and passkeys of a user, to log in to the web UI without a password.
+pub struct WebauthnSetup {
+    common: CommonComponentParts<Self>,
+    credentials: Option<Vec<Credential>>,
+    name: NodeRef,
+}
+
+#[derive(Clone, PartialEq, Eq, Properties)]
+pub struct Props {
+    pub username: String,
+    pub is_admin: bool,
+}
+
+pub enum Msg {
+    ListResponse(Result<get_user_webauthn_credentials::ResponseData>),
+    StartRegistration,
+    /// The name of the new credential, and the options to pass to the browser.
+    RegistrationOptions((String, Result<Box<webauthn::CreationChallengeResponse>>)),
+    RegistrationCredential((String, Result<webauthn::RegisterPublicKeyCredential>)),
+    RegistrationResponse(Result<()>),
+    Delete(String),
+    DeleteResponse(Result<delete_webauthn_credential::ResponseData>),
+}
+
+impl CommonComponent<WebauthnSetup> for WebauthnSetup {
+    fn handle_msg(&mut self, msg: <Self as Component>::Message) -> Result<bool> {
+        match msg {
+            Msg::ListResponse(response) => {
+                self.credentials = Some(response?.user.webauthn_credentials);
+            }
+            Msg::StartRegistration => {
+                let name = self
+                    .name
+                    .cast::<web_sys::HtmlInputElement>()
+                    .expect("Name input should be present")
+                    .value()
+                    .trim()
+                    .to_string();
+                let name = if name.is_empty() {
+                    "Security key".to_string()
+                } else {
+                    name
+                };
+                self.common
+                    .call_backend(HostService::webauthn_register_start, (), move |r| {
+                        Msg::RegistrationOptions((name, r))
+                    })?;
+            }
+            Msg::RegistrationOptions((name, options)) => {
+                self.common.cancel_task();
+                create_credential(
+                    *options?,
+                    self.common
+                        .callback(move |r| Msg::RegistrationCredential((name.clone(), r))),
+                )?;
+            }
+            Msg::RegistrationCredential((name, credential)) => {
+                let req = webauthn::ClientRegistrationFinishRequest {
+                    name,
+                    credential: credential?,
+                };
+                self.common.call_backend(
+                    HostService::webauthn_register_finish,
+                    req,
+                    Msg::RegistrationResponse,
+                )?;
+            }
+            Msg::RegistrationResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.fetch_credentials();
+            }
+            Msg::Delete(credential_id) => {
+                self.common.call_graphql::<DeleteWebauthnCredential, _>(
+                    delete_webauthn_credential::Variables {
+                        user_id: self.common.username.clone(),
+                        credential_id,
+                    },
+                    Msg::DeleteResponse,
+                    "Error trying to remove the security key",
+                );
+            }
+            Msg::DeleteResponse(response) => {
+                self.common.cancel_task();
+                response?;
+                self.fetch_credentials();
+            }
+        }
+        Ok(true)
+    }
+
+    fn mut_common(&mut self) -> &mut CommonComponentParts<Self> {
+        &mut self.common
+    }
+}
+
+impl WebauthnSetup {
+    fn fetch_credentials(&mut self) {
+        self.common.call_graphql::<GetUserWebauthnCredentials, _>(
+            get_user_webauthn_credentials::Variables {
+                id: self.common.username.clone(),
+            },
+            Msg::ListResponse,
+            "Error trying to fetch the security keys",
+        );
+    }
+
+    /// Only the users themselves can register a security key, since it has to be plugged in.
+    fn is_own_account(&self) -> bool {
+        get_cookie("user_id").ok().flatten().as_ref() == Some(&self.common.username)
+    }
+
+    fn view_credential(&self, credential: &Credential) -> Html {
+        let credential_id = credential.credential_id.clone();
+        html! {
+          <tr key=credential.credential_id.clone()>
+            <td>{&credential.name}</td>
+            <td>{&credential.creation_date.naive_local().date()}</td>
+            <td>
+              {if self.is_own_account() || self.common.is_admin { html! {
+                <button
+                  class="btn btn-danger btn-sm"
+                  disabled=self.common.is_task_running()
+                  onclick=self.common.callback(move |_| Msg::Delete(credential_id.clone()))>
+                  <i class="bi-x-circle-fill" aria-label="Remove security key" />
+                </button>
+              }} else { html! {} }}
+            </td>
+          </tr>
+        }
+    }
+
+    fn view_registration(&self) -> Html {
+        if !self.is_own_account() {
+            return html! {};
+        }
+        html! {
+          <div class="input-group mt-2">
+            <input
+              class="form-control"
+              type="text"
+              placeholder="Name, e.g. Phone"
+              ref=self.name.clone() />
+            <button
+              type="button"
+              class="btn btn-primary"
+              disabled=self.common.is_task_running()
+              onclick=self.common.callback(|_| Msg::StartRegistration)>
+              <i class="bi-key me-2"></i>
+              {"Add a security key"}
+            </button>
+          </div>
+        }
+    }
+}
+
+impl Component for WebauthnSetup {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let mut component = WebauthnSetup {
+            common: CommonComponentParts::<Self>::create(props, link),
+            credentials: None,
+            name: NodeRef::default(),
+        };
+        component.fetch_credentials();
+        component
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        CommonComponentParts::<Self>::update(self, msg)
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.common.change(props)
+    }
+
+    fn view(&self) -> Html {
+        html! {
+          <>
+            <div class="mb-2 mt-4">
+              <h5 class="fw-bold">
+                {"Security keys and passkeys"}
+              </h5>
+            </div>
+            {
+              if let Some(e) = &self.common.error {
+                html! {
+                  <div class="alert alert-danger mt-3 mb-3">
+                    {e.to_string() }
+                  </div>
+                }
+              } else { html! {} }
+            }
+            {match &self.credentials {
+              None => html! {{"Loading..."}},
+              Some(credentials) if credentials.is_empty() => html! {
+                <p>{"No security key registered."}</p>
+              },
+              Some(credentials) => html! {
+                <table class="table table-sm">
+                  <thead>
+                    <tr>
+                      <th>{"Name"}</th>
+                      <th>{"Added"}</th>
+                      <th></th>
+                    </tr>
+                  </thead>
+                  <tbody>
+                    {for credentials.iter().map(|c| self.view_credential(c))}
+                  </tbody>
+                </table>
+              },
+            }}
+            {self.view_registration()}
+          </>
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use super::cookies::set_cookie;
+use super::cookies::{set_cookie, set_document_language};
 use anyhow::{anyhow, Context, Result};
 use graphql_client::GraphQLQuery;
-use lldap_auth::{login, registration, JWTClaims};
+use lldap_auth::{login, oidc, proof_of_work, registration, sso, webauthn, JWTClaims};
 
 use yew::callback::Callback;
 use yew::format::Json;
@@ -23,6 +23,64 @@ fn get_claims_from_jwt(jwt: &str) -> Result<JWTClaims> {
     Ok(token.claims().clone())
 }
 
+/// Sets the cookies used by the app to know who is logged in, and returns the user and whether
+/// they are an admin.
+fn parse_login_response(data: String) -> Result<(String, bool)> {
+    let set_cookies = |jwt_claims: JWTClaims| {
+        let is_admin = jwt_claims.groups.contains("lldap_admin");
+        set_cookie("user_id", &jwt_claims.user, &jwt_claims.exp)
+            .map(|_| set_cookie("is_admin", &is_admin.to_string(), &jwt_claims.exp))
+            .map(|_| (jwt_claims.user.clone(), is_admin))
+            .context("Error clearing cookie")
+    };
+    serde_json::from_str::<login::ServerLoginResponse>(&data)
+        .context("Could not parse response")
+        .and_then(|r| {
+            get_claims_from_jwt(r.token.as_str())
+                .context("Could not parse response")
+                .and_then(|jwt_claims| {
+                    if let Some(language) = r.language.as_deref().filter(|l| is_language_tag(l)) {
+                        set_cookie("language", language, &jwt_claims.exp)
+                            .and_then(|_| set_document_language(language))
+                            .context("Error setting the language")?;
+                    }
+                    set_cookies(jwt_claims)
+                })
+        })
+}
+
+/// Whether the string looks like a language tag, e.g. `en` or `fr-CA`, and is safe to put in a
+/// cookie.
+fn is_language_tag(language: &str) -> bool {
+    !language.is_empty()
+        && language
+            .split('-')
+            .all(|subtag| !subtag.is_empty() && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// The outcome of the password step of a login.
+pub enum LoginResponse {
+    /// The user and whether they are an admin.
+    LoggedIn((String, bool)),
+    /// The challenge to send back with the TOTP code of the user, to `HostService::totp_login`.
+    TotpRequired(String),
+    /// The password of the user expired: the token lets them choose a new one, like a password
+    /// reset token.
+    PasswordExpired(String),
+}
+
+fn parse_login_or_totp_response(data: String) -> Result<LoginResponse> {
+    if let Ok(response) = serde_json::from_str::<login::ServerTotpChallengeResponse>(&data) {
+        return Ok(LoginResponse::TotpRequired(response.totp_challenge));
+    }
+    if let Ok(response) = serde_json::from_str::<login::ServerPasswordExpiredResponse>(&data) {
+        return Ok(LoginResponse::PasswordExpired(
+            response.password_reset_token,
+        ));
+    }
+    parse_login_response(data).map(LoginResponse::LoggedIn)
+}
+
 fn create_handler<Resp, CallbackResult, F>(
     callback: Callback<Result<CallbackResult>>,
     handler: F,
@@ -40,6 +98,28 @@ where
     })
 }
 
+/// A GraphQL request sent with the hash of its query, so that the server can register it as a
+/// persisted query.
+#[derive(serde::Serialize)]
+struct PersistedQueryBody<Variables> {
+    #[serde(flatten)]
+    body: graphql_client::QueryBody<Variables>,
+    extensions: serde_json::Value,
+}
+
+impl<Variables> From<graphql_client::QueryBody<Variables>> for PersistedQueryBody<Variables> {
+    fn from(body: graphql_client::QueryBody<Variables>) -> Self {
+        use sha2::{Digest, Sha256};
+        let hash = format!("{:x}", Sha256::digest(body.query.as_bytes()));
+        Self {
+            body,
+            extensions: serde_json::json!({
+                "persistedQuery": { "version": 1, "sha256Hash": hash }
+            }),
+        }
+    }
+}
+
 struct RequestBody<T>(T);
 
 impl<'a, R> From<&'a R> for RequestBody<Json<&'a R>>
@@ -152,7 +232,7 @@ impl HostService {
                 .context("Could not parse response")
                 .and_then(unwrap_graphql_response)
         };
-        let request_body = QueryType::build_query(variables);
+        let request_body = PersistedQueryBody::from(QueryType::build_query(variables));
         call_server(
             "/api/graphql",
             &request_body,
@@ -162,6 +242,19 @@ impl HostService {
         )
     }
 
+    /// `None` if the server doesn't require a proof of work with the passwords.
+    pub fn login_challenge(
+        _request: (),
+        callback: Callback<Result<Option<proof_of_work::Challenge>>>,
+    ) -> Result<FetchTask> {
+        call_server_json_with_error_message(
+            "/auth/login/challenge",
+            yew::format::Nothing,
+            callback,
+            "Could not get the login challenge",
+        )
+    }
+
     pub fn login_start(
         request: login::ClientLoginStartRequest,
         callback: Callback<Result<Box<login::ServerLoginStartResponse>>>,
@@ -176,30 +269,89 @@ impl HostService {
 
     pub fn login_finish(
         request: login::ClientLoginFinishRequest,
-        callback: Callback<Result<(String, bool)>>,
+        callback: Callback<Result<LoginResponse>>,
     ) -> Result<FetchTask> {
-        let set_cookies = |jwt_claims: JWTClaims| {
-            let is_admin = jwt_claims.groups.contains("lldap_admin");
-            set_cookie("user_id", &jwt_claims.user, &jwt_claims.exp)
-                .map(|_| set_cookie("is_admin", &is_admin.to_string(), &jwt_claims.exp))
-                .map(|_| (jwt_claims.user.clone(), is_admin))
-                .context("Error clearing cookie")
-        };
-        let parse_token = move |data: String| {
-            serde_json::from_str::<login::ServerLoginResponse>(&data)
-                .context("Could not parse response")
-                .and_then(|r| {
-                    get_claims_from_jwt(r.token.as_str())
-                        .context("Could not parse response")
-                        .and_then(set_cookies)
-                })
-        };
         call_server(
             "/auth/opaque/login/finish",
             &request,
             callback,
             "Could not finish authentication",
-            parse_token,
+            parse_login_or_totp_response,
+        )
+    }
+
+    pub fn temporary_login(
+        request: login::ClientSimpleLoginRequest,
+        callback: Callback<Result<LoginResponse>>,
+    ) -> Result<FetchTask> {
+        call_server(
+            "/auth/temporary/login",
+            &request,
+            callback,
+            "Could not log in",
+            parse_login_or_totp_response,
+        )
+    }
+
+    pub fn totp_login(
+        request: login::ClientTotpLoginRequest,
+        callback: Callback<Result<LoginResponse>>,
+    ) -> Result<FetchTask> {
+        call_server(
+            "/auth/totp/login",
+            &request,
+            callback,
+            "Could not log in",
+            parse_login_or_totp_response,
+        )
+    }
+
+    pub fn webauthn_login_start(
+        request: webauthn::ClientLoginStartRequest,
+        callback: Callback<Result<Box<webauthn::ServerLoginStartResponse>>>,
+    ) -> Result<FetchTask> {
+        call_server_json_with_error_message(
+            "/auth/webauthn/login/start",
+            &request,
+            callback,
+            "Could not start the login with a security key",
+        )
+    }
+
+    pub fn webauthn_login_finish(
+        request: webauthn::ClientLoginFinishRequest,
+        callback: Callback<Result<LoginResponse>>,
+    ) -> Result<FetchTask> {
+        call_server(
+            "/auth/webauthn/login/finish",
+            &request,
+            callback,
+            "Could not log in with the security key",
+            parse_login_or_totp_response,
+        )
+    }
+
+    pub fn webauthn_register_start(
+        _request: (),
+        callback: Callback<Result<Box<webauthn::CreationChallengeResponse>>>,
+    ) -> Result<FetchTask> {
+        call_server_json_with_error_message(
+            "/auth/webauthn/register/start",
+            yew::format::Nothing,
+            callback,
+            "Could not start the registration of the security key",
+        )
+    }
+
+    pub fn webauthn_register_finish(
+        request: webauthn::ClientRegistrationFinishRequest,
+        callback: Callback<Result<()>>,
+    ) -> Result<FetchTask> {
+        call_server_empty_response_with_error_message(
+            "/auth/webauthn/register/finish",
+            &request,
+            callback,
+            "Could not register the security key",
         )
     }
 
@@ -228,28 +380,51 @@ impl HostService {
     }
 
     pub fn refresh(_request: (), callback: Callback<Result<(String, bool)>>) -> Result<FetchTask> {
-        let set_cookies = |jwt_claims: JWTClaims| {
-            let is_admin = jwt_claims.groups.contains("lldap_admin");
-            set_cookie("user_id", &jwt_claims.user, &jwt_claims.exp)
-                .map(|_| set_cookie("is_admin", &is_admin.to_string(), &jwt_claims.exp))
-                .map(|_| (jwt_claims.user.clone(), is_admin))
-                .context("Error clearing cookie")
-        };
-        let parse_token = move |data: String| {
-            serde_json::from_str::<login::ServerLoginResponse>(&data)
-                .context("Could not parse response")
-                .and_then(|r| {
-                    get_claims_from_jwt(r.token.as_str())
-                        .context("Could not parse response")
-                        .and_then(set_cookies)
-                })
-        };
         call_server(
             "/auth/refresh",
             yew::format::Nothing,
             callback,
             "Could not start authentication: ",
-            parse_token,
+            parse_login_response,
+        )
+    }
+
+    // The `_request` parameter is to make it the same shape as the other functions.
+    pub fn proxy_login(
+        _request: (),
+        callback: Callback<Result<(String, bool)>>,
+    ) -> Result<FetchTask> {
+        call_server(
+            "/auth/proxy",
+            yew::format::Nothing,
+            callback,
+            "Could not log in through the proxy: ",
+            parse_login_response,
+        )
+    }
+
+    // The `_request` parameter is to make it the same shape as the other functions.
+    pub fn sso_login_info(
+        _request: (),
+        callback: Callback<Result<sso::ServerSsoLoginInfo>>,
+    ) -> Result<FetchTask> {
+        call_server_json_with_error_message(
+            "/auth/sso",
+            yew::format::Nothing,
+            callback,
+            "Could not check the SSO login",
+        )
+    }
+
+    /// `query` is the query string that the SSO provider sent the user back with, including the
+    /// leading `?`.
+    pub fn sso_login(query: &str, callback: Callback<Result<(String, bool)>>) -> Result<FetchTask> {
+        call_server(
+            &format!("/auth/sso/callback{}", query),
+            yew::format::Nothing,
+            callback,
+            "Could not log in with SSO: ",
+            parse_login_response,
         )
     }
 
@@ -275,6 +450,19 @@ impl HostService {
         )
     }
 
+    pub fn magic_login(
+        token: &str,
+        callback: Callback<Result<LoginResponse>>,
+    ) -> Result<FetchTask> {
+        call_server(
+            &format!("/auth/magic/{}", token),
+            yew::format::Nothing,
+            callback,
+            "Could not log in with this link",
+            parse_login_or_totp_response,
+        )
+    }
+
     pub fn reset_password_step2(
         token: &str,
         callback: Callback<Result<lldap_auth::password_reset::ServerPasswordResetResponse>>,
@@ -286,4 +474,16 @@ impl HostService {
             "Could not validate token",
         )
     }
+
+    pub fn oidc_authorize(
+        request_id: &str,
+        callback: Callback<Result<oidc::ServerAuthorizeResponse>>,
+    ) -> Result<FetchTask> {
+        call_server_json_with_error_message(
+            &format!("/oidc/authorize/{}", request_id),
+            &(),
+            callback,
+            "Could not log in to the application",
+        )
+    }
 }
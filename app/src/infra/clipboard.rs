@@ -0,0 +1,21 @@
+use anyhow::{bail, Result};
+use wasm_bindgen::JsCast;
+use yew::NodeRef;
+
+/// Copies the value of the input, e.g. a secret shown once. The clipboard API isn't available
+/// without `web_sys_unstable_apis`, so this selects the text and runs the copy command.
+pub fn copy_input_value(input: &NodeRef) -> Result<()> {
+    let input = input
+        .cast::<web_sys::HtmlInputElement>()
+        .expect("Input should be present");
+    input.select();
+    let copied = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.dyn_into::<web_sys::HtmlDocument>().ok())
+        .and_then(|d| d.exec_command("copy").ok())
+        .unwrap_or(false);
+    if !copied {
+        bail!("Could not copy, select the text and copy it manually");
+    }
+    Ok(())
+}
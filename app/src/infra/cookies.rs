@@ -51,6 +51,15 @@ pub fn get_cookie(cookie_name: &str) -> Result<Option<String>> {
         }))
 }
 
+/// Sets the language of the page, e.g. for the spell checker and the screen readers.
+pub fn set_document_language(language: &str) -> Result<()> {
+    get_document()?
+        .document_element()
+        .ok_or_else(|| anyhow!("Could not get the document element"))?
+        .set_attribute("lang", language)
+        .map_err(|_| anyhow!("Could not set the language"))
+}
+
 pub fn delete_cookie(cookie_name: &str) -> Result<()> {
     if get_cookie(cookie_name)?.is_some() {
         set_cookie(
@@ -0,0 +1,84 @@
+//! The live updates of the directory, from the GraphQL subscription over a WebSocket. The
+//! WebSocket is authenticated by the cookie, like the other requests.
+
+use anyhow::{anyhow, Result};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{MessageEvent, WebSocket};
+use yew::callback::Callback;
+
+const SUBSCRIPTION: &str = "subscription { directoryChanges { changeType } }";
+
+fn to_error(error: JsValue) -> anyhow::Error {
+    anyhow!(
+        "Could not connect to the live updates: {}",
+        error.as_string().unwrap_or_else(|| format!("{:?}", error))
+    )
+}
+
+fn get_url() -> Result<String> {
+    let location = web_sys::window()
+        .ok_or_else(|| anyhow!("Could not get window"))?
+        .location();
+    let protocol = match location.protocol().map_err(to_error)?.as_str() {
+        "https:" => "wss",
+        _ => "ws",
+    };
+    Ok(format!(
+        "{}://{}/api/graphql/ws",
+        protocol,
+        location.host().map_err(to_error)?
+    ))
+}
+
+/// The subscription to the changes of the directory. The connection is closed when it is
+/// dropped; if it is lost, there are just no more updates.
+pub struct DirectoryChanges {
+    socket: WebSocket,
+    _on_open: Closure<dyn FnMut()>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl DirectoryChanges {
+    /// `on_change` is called for each change, whatever it is.
+    pub fn subscribe(on_change: Callback<()>) -> Result<Self> {
+        let socket = WebSocket::new_with_str(&get_url()?, "graphql-ws").map_err(to_error)?;
+        let on_open = {
+            let socket = socket.clone();
+            Closure::wrap(Box::new(move || {
+                let start = serde_json::json!({
+                    "id": "1",
+                    "type": "start",
+                    "payload": { "query": SUBSCRIPTION },
+                });
+                let _ = socket.send_with_str(r#"{"type":"connection_init","payload":{}}"#);
+                let _ = socket.send_with_str(&start.to_string());
+            }) as Box<dyn FnMut()>)
+        };
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            let is_change = event
+                .data()
+                .as_string()
+                .and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok())
+                .map(|message| message["type"] == "data")
+                .unwrap_or(false);
+            if is_change {
+                on_change.emit(());
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        Ok(Self {
+            socket,
+            _on_open: on_open,
+            _on_message: on_message,
+        })
+    }
+}
+
+impl Drop for DirectoryChanges {
+    fn drop(&mut self) {
+        self.socket.set_onopen(None);
+        self.socket.set_onmessage(None);
+        let _ = self.socket.close();
+    }
+}
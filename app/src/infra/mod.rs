@@ -1,5 +1,9 @@
 pub mod api;
+pub mod clipboard;
 pub mod common_component;
 pub mod cookies;
+pub mod directory_changes;
 pub mod graphql;
 pub mod modal;
+pub mod pagination;
+pub mod webauthn;
@@ -0,0 +1,83 @@
+use yew::{html, Callback, Html, MouseEvent};
+
+/// The page of a listing fetched one page at a time, like `usersPage` and `groupsPage`: the
+/// cursors are opaque, so the ones of the previous pages are kept to go back.
+#[derive(Default)]
+pub struct Pagination {
+    /// The `after` cursors of the previous pages.
+    previous: Vec<Option<String>>,
+    /// The `after` cursor of the current page, `None` for the first one.
+    current: Option<String>,
+    /// The `after` cursor of the next page, if there is one.
+    next: Option<String>,
+    pub total_count: i64,
+}
+
+impl Pagination {
+    /// The cursor to fetch the current page with.
+    pub fn after(&self) -> Option<String> {
+        self.current.clone()
+    }
+
+    /// Back to the first page, e.g. when the sort order changes.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Called with the `pageInfo` and `totalCount` of the response.
+    pub fn set_page_info(&mut self, end_cursor: Option<String>, has_next_page: bool, total: i64) {
+        self.next = end_cursor.filter(|_| has_next_page);
+        self.total_count = total;
+    }
+
+    /// Returns whether there was a next page to move to.
+    pub fn go_next(&mut self) -> bool {
+        match self.next.take() {
+            None => false,
+            Some(next) => {
+                self.previous.push(self.current.replace(next));
+                true
+            }
+        }
+    }
+
+    /// Returns whether there was a previous page to move to.
+    pub fn go_previous(&mut self) -> bool {
+        match self.previous.pop() {
+            None => false,
+            Some(previous) => {
+                self.current = previous;
+                self.next = None;
+                true
+            }
+        }
+    }
+
+    /// The buttons to move between the pages, with the total number of entries.
+    pub fn view(
+        &self,
+        on_previous: Callback<MouseEvent>,
+        on_next: Callback<MouseEvent>,
+        disabled: bool,
+    ) -> Html {
+        html! {
+          <div class="d-flex align-items-center mb-3">
+            <button
+              type="button"
+              class="btn btn-secondary btn-sm me-2"
+              disabled=disabled || self.previous.is_empty()
+              onclick=on_previous>
+              <i class="bi-chevron-left" aria-label="Previous page" />
+            </button>
+            <button
+              type="button"
+              class="btn btn-secondary btn-sm me-3"
+              disabled=disabled || self.next.is_none()
+              onclick=on_next>
+              <i class="bi-chevron-right" aria-label="Next page" />
+            </button>
+            <span class="text-muted">{format!("{} in total", self.total_count)}</span>
+          </div>
+        }
+    }
+}
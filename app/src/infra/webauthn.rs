@@ -0,0 +1,65 @@
+//! Calls to the WebAuthn API of the browser, which prompts the user for their security key or
+//! passkey.
+
+use anyhow::{anyhow, Result};
+use lldap_auth::webauthn::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use yew::callback::Callback;
+
+fn get_credentials_container() -> Result<web_sys::CredentialsContainer> {
+    web_sys::window()
+        .map(|w| w.navigator().credentials())
+        .ok_or_else(|| anyhow!("Could not get window"))
+}
+
+/// The browser rejects the promise when the user cancels the prompt, or when no authenticator
+/// can be used.
+fn to_error(error: JsValue) -> anyhow::Error {
+    anyhow!(
+        "The security key was not accepted: {}",
+        error.as_string().unwrap_or_else(|| format!("{:?}", error))
+    )
+}
+
+fn await_credential<Resp>(
+    promise: std::result::Result<js_sys::Promise, JsValue>,
+    callback: Callback<Result<Resp>>,
+) where
+    Resp: From<web_sys::PublicKeyCredential> + 'static,
+{
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = match promise {
+            Ok(promise) => JsFuture::from(promise)
+                .await
+                .map(|credential| Resp::from(web_sys::PublicKeyCredential::from(credential)))
+                .map_err(to_error),
+            Err(e) => Err(to_error(e)),
+        };
+        callback.emit(result);
+    });
+}
+
+/// Creates a new credential with the options from `HostService::webauthn_register_start`.
+pub fn create_credential(
+    options: CreationChallengeResponse,
+    callback: Callback<Result<RegisterPublicKeyCredential>>,
+) -> Result<()> {
+    let container = get_credentials_container()?;
+    await_credential(container.create_with_options(&options.into()), callback);
+    Ok(())
+}
+
+/// Signs the challenge from `HostService::webauthn_login_start` with one of the credentials of
+/// the user.
+pub fn get_credential(
+    options: RequestChallengeResponse,
+    callback: Callback<Result<PublicKeyCredential>>,
+) -> Result<()> {
+    let container = get_credentials_container()?;
+    await_credential(container.get_with_options(&options.into()), callback);
+    Ok(())
+}
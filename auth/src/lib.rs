@@ -6,11 +6,18 @@ use std::collections::HashSet;
 use std::fmt;
 
 pub mod opaque;
+pub mod password_history;
+pub mod password_strength;
+pub mod proof_of_work;
 
 /// The messages for the 3-step OPAQUE and simple login process.
 pub mod login {
     use super::*;
 
+    /// The prefix of the single-use passwords created by the admins for new users, which are sent
+    /// in a `ClientSimpleLoginRequest` to `/auth/temporary/login` instead of going through OPAQUE.
+    pub const TEMPORARY_PASSWORD_PREFIX: &str = "lldap_otp_";
+
     #[derive(Serialize, Deserialize, Clone)]
     pub struct ServerData {
         pub username: String,
@@ -21,6 +28,10 @@ pub mod login {
     pub struct ClientLoginStartRequest {
         pub username: String,
         pub login_start_request: opaque::server::login::CredentialRequest,
+        /// Required when the server enables the login challenge, see
+        /// [`crate::proof_of_work`].
+        #[serde(default)]
+        pub proof_of_work: Option<proof_of_work::Solution>,
     }
 
     #[derive(Serialize, Deserialize, Clone)]
@@ -41,6 +52,10 @@ pub mod login {
     pub struct ClientSimpleLoginRequest {
         pub username: String,
         pub password: String,
+        /// Required when the server enables the login challenge, see
+        /// [`crate::proof_of_work`].
+        #[serde(default)]
+        pub proof_of_work: Option<proof_of_work::Solution>,
     }
 
     impl fmt::Debug for ClientSimpleLoginRequest {
@@ -57,6 +72,41 @@ pub mod login {
         pub token: String,
         #[serde(rename = "refreshToken", skip_serializing_if = "Option::is_none")]
         pub refresh_token: Option<String>,
+        /// The preferred language of the user, or the default language of the server.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub language: Option<String>,
+    }
+
+    /// Sent instead of the tokens when the user has TOTP enabled: the login is finished by
+    /// sending the challenge back with the code, in a `ClientTotpLoginRequest`.
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerTotpChallengeResponse {
+        #[serde(rename = "totpChallenge")]
+        pub totp_challenge: String,
+    }
+
+    /// Sent instead of the tokens when the password of the user expired: it has to be changed
+    /// with the reset token, like after a password reset email.
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerPasswordExpiredResponse {
+        #[serde(rename = "passwordResetToken")]
+        pub password_reset_token: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientTotpLoginRequest {
+        pub totp_challenge: String,
+        /// The TOTP code, or one of the recovery codes.
+        pub code: String,
+    }
+
+    impl fmt::Debug for ClientTotpLoginRequest {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("ClientTotpLoginRequest")
+                .field("totp_challenge", &"***********")
+                .field("code", &"***********")
+                .finish()
+        }
     }
 }
 
@@ -88,6 +138,45 @@ pub mod registration {
         /// Encrypted ServerData from the previous step.
         pub server_data: String,
         pub registration_upload: opaque::server::registration::RegistrationUpload,
+        /// See [`crate::password_history`]. Required when the server keeps a password history:
+        /// without it, the new password is refused.
+        #[serde(default)]
+        pub password_history_digest: Option<String>,
+    }
+}
+
+/// The messages for the logins with a security key or a passkey, and for their registration.
+/// The options and the responses of the authenticators are the ones of the WebAuthn API.
+pub mod webauthn {
+    use super::*;
+    pub use webauthn_rs_proto::{
+        CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+        RequestChallengeResponse,
+    };
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct ClientLoginStartRequest {
+        pub username: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerLoginStartResponse {
+        /// To be passed back to the server with the response of the authenticator.
+        pub webauthn_challenge: String,
+        pub options: RequestChallengeResponse,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientLoginFinishRequest {
+        pub webauthn_challenge: String,
+        pub credential: PublicKeyCredential,
+    }
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ClientRegistrationFinishRequest {
+        /// Chosen by the user, to tell their credentials apart.
+        pub name: String,
+        pub credential: RegisterPublicKeyCredential,
     }
 }
 
@@ -104,6 +193,27 @@ pub mod password_reset {
     }
 }
 
+/// The messages for the OpenID Connect logins to other applications.
+pub mod oidc {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerAuthorizeResponse {
+        /// Where to send the browser back, with the authorization code for the application.
+        pub redirect_url: String,
+    }
+}
+
+pub mod sso {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Clone)]
+    pub struct ServerSsoLoginInfo {
+        /// Whether the users can log in with the upstream OpenID Connect provider.
+        pub enabled: bool,
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct JWTClaims {
     pub exp: DateTime<Utc>,
@@ -0,0 +1,59 @@
+//! Reuse detection for the password history.
+//!
+//! With OPAQUE, the server never sees the password and each registration produces a different
+//! password file, so it can't tell whether a new password was already used. Instead, whoever
+//! knows the plaintext password (the frontend, or the server when it receives one through LDAP)
+//! sends a digest of it along with the registration, and the server compares it with the digests
+//! of the previous passwords.
+//!
+//! The server can't check that the digest matches the registered password: a client can send the
+//! digest of another password and reuse an old one anyway. The history keeps the users of the
+//! regular clients from reusing their passwords by mistake, it doesn't enforce it.
+//!
+//! The digest is a slow hash salted with the user ID, so it can't be compared across users. The
+//! server only stores an HMAC of it keyed with its own secret, so the database alone isn't enough
+//! to bruteforce it.
+
+use sha2::{Digest, Sha256};
+
+/// The salt of each user has to be different, and is derived from their (lowercase) user ID.
+const SALT_PREFIX: &[u8] = b"lldap_password_history_salt";
+
+const CONFIG: &argon2::Config<'static> = &argon2::Config {
+    ad: &[],
+    hash_length: 32,
+    lanes: 1,
+    mem_cost: 50 * 1024, // 50 MB, in KB
+    secret: &[],
+    thread_mode: argon2::ThreadMode::Sequential,
+    time_cost: 1,
+    variant: argon2::Variant::Argon2id,
+    version: argon2::Version::Version13,
+};
+
+/// The digest of `password` to store in the history of `username`, hex-encoded.
+pub fn password_history_digest(username: &str, password: &str) -> Result<String, String> {
+    let mut salt = Sha256::new();
+    salt.update(SALT_PREFIX);
+    salt.update(username.to_lowercase().as_bytes());
+    let hash = argon2::hash_raw(password.as_bytes(), &salt.finalize(), CONFIG)
+        .map_err(|e| format!("Could not hash the password: {}", e))?;
+    Ok(hash.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_history_digest() {
+        let digest = password_history_digest("Bob", "password").unwrap();
+        assert_eq!(digest.len(), 64);
+        assert_eq!(digest, password_history_digest("bob", "password").unwrap());
+        assert_ne!(digest, password_history_digest("bob", "Password").unwrap());
+        assert_ne!(
+            digest,
+            password_history_digest("alice", "password").unwrap()
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Password strength estimation and password policy, shared by the frontend (for live feedback)
+//! and the server (to enforce the configured policy whenever it sees a plaintext password).
+
+/// Scores go from 0 (too guessable) to 4 (very unguessable), as defined by zxcvbn.
+pub const MAX_SCORE: u8 = 4;
+
+/// Minimum length of new passwords, in bytes. The configured minimum can only be higher.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Lowercase letters, uppercase letters, digits and everything else.
+pub const CHARACTER_CLASSES: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordStrength {
+    pub score: u8,
+    /// Human-readable warning and suggestions to make the password stronger.
+    pub feedback: Vec<String>,
+}
+
+/// Estimates the strength of `password`. `user_inputs` are user-specific strings (user ID,
+/// email, ...) that make a password weaker if it contains them.
+pub fn estimate_password_strength(password: &str, user_inputs: &[&str]) -> PasswordStrength {
+    match zxcvbn::zxcvbn(password, user_inputs) {
+        Ok(entropy) => PasswordStrength {
+            score: entropy.score(),
+            feedback: entropy
+                .feedback()
+                .iter()
+                .flat_map(|feedback| {
+                    feedback
+                        .warning()
+                        .map(|w| w.to_string())
+                        .into_iter()
+                        .chain(feedback.suggestions().iter().map(|s| s.to_string()))
+                })
+                .collect(),
+        },
+        // zxcvbn refuses to score an empty password.
+        Err(_) => PasswordStrength {
+            score: 0,
+            feedback: vec!["Enter a password".to_string()],
+        },
+    }
+}
+
+/// Checks that `password` reaches `min_score`, returning an explanation if it doesn't.
+pub fn check_password_strength(
+    password: &str,
+    user_inputs: &[&str],
+    min_score: u8,
+) -> Result<(), String> {
+    let strength = estimate_password_strength(password, user_inputs);
+    if strength.score >= min_score {
+        Ok(())
+    } else {
+        let mut message = format!(
+            "Password is too weak: strength {} out of {}, at least {} is required",
+            strength.score, MAX_SCORE, min_score
+        );
+        for feedback in strength.feedback {
+            message.push_str(". ");
+            message.push_str(&feedback);
+        }
+        Err(message)
+    }
+}
+
+/// Number of character classes (see [`CHARACTER_CLASSES`]) used in `password`.
+pub fn count_character_classes(password: &str) -> u8 {
+    let (mut lower, mut upper, mut digit, mut other) = (false, false, false, false);
+    for c in password.chars() {
+        if c.is_lowercase() {
+            lower = true;
+        } else if c.is_uppercase() {
+            upper = true;
+        } else if c.is_numeric() {
+            digit = true;
+        } else {
+            other = true;
+        }
+    }
+    [lower, upper, digit, other].iter().filter(|c| **c).count() as u8
+}
+
+/// The requirements on new passwords.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    /// In bytes, at least [`MIN_PASSWORD_LENGTH`].
+    pub min_length: usize,
+    pub min_character_classes: u8,
+    /// Minimum zxcvbn score, see [`MAX_SCORE`].
+    pub min_strength: u8,
+    /// Refused passwords, in lowercase: the comparison ignores the case.
+    pub banned_passwords: Vec<String>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: MIN_PASSWORD_LENGTH,
+            min_character_classes: 0,
+            min_strength: 0,
+            banned_passwords: Vec::new(),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Checks `password` against each requirement in turn, returning an explanation for the
+    /// first one it fails. `user_inputs` are as in [`estimate_password_strength`].
+    pub fn check(&self, password: &str, user_inputs: &[&str]) -> Result<(), String> {
+        self.check_requirements(password)?;
+        check_password_strength(password, user_inputs, self.min_strength)
+    }
+
+    /// Same as [`Self::check`], without the strength: the frontend shows the strength separately.
+    pub fn check_requirements(&self, password: &str) -> Result<(), String> {
+        let min_length = self.min_length.max(MIN_PASSWORD_LENGTH);
+        if password.len() < min_length {
+            return Err(format!(
+                "Password is too short: at least {} characters are required",
+                min_length
+            ));
+        }
+        let classes = count_character_classes(password);
+        if classes < self.min_character_classes {
+            return Err(format!(
+                "Password uses {} kinds of characters, at least {} of lowercase letters, \
+                 uppercase letters, digits and symbols are required",
+                classes, self.min_character_classes
+            ));
+        }
+        let lowercase = password.to_lowercase();
+        if self.banned_passwords.iter().any(|p| p == &lowercase) {
+            return Err("This password is too common, choose another one".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_character_classes() {
+        assert_eq!(count_character_classes(""), 0);
+        assert_eq!(count_character_classes("abcé"), 1);
+        assert_eq!(count_character_classes("abcD"), 2);
+        assert_eq!(count_character_classes("aB3"), 3);
+        assert_eq!(count_character_classes("aB3 "), 4);
+    }
+
+    #[test]
+    fn test_policy_check() {
+        let policy = PasswordPolicy {
+            min_length: 10,
+            min_character_classes: 3,
+            min_strength: 0,
+            banned_passwords: vec!["password123!".to_string()],
+        };
+        policy.check("aB3aB3aB3", &[]).unwrap_err();
+        policy.check("abcabcabcabc", &[]).unwrap_err();
+        policy.check("PassWord123!", &[]).unwrap_err();
+        policy.check("aB3aB3aB3aB3", &[]).unwrap();
+    }
+
+    #[test]
+    fn test_policy_min_length_floor() {
+        let policy = PasswordPolicy {
+            min_length: 2,
+            ..PasswordPolicy::default()
+        };
+        policy.check("abcdefg", &[]).unwrap_err();
+        policy.check("abcdefgh", &[]).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+//! The optional proof of work of the login form, to slow down the bots trying passwords.
+//!
+//! The server hands out a random challenge, and the client has to find a nonce such that the
+//! SHA-256 digest of the challenge followed by the nonce starts with `difficulty` zero bits. It
+//! takes about `2^difficulty` hashes to find one, and a single hash to check it.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    pub challenge: String,
+    /// The number of leading zero bits of the digest.
+    pub difficulty: u8,
+}
+
+/// Sent with the login requests, each challenge can only be used once.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    pub challenge: String,
+    pub nonce: u64,
+}
+
+fn leading_zero_bits(digest: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in digest {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Whether `nonce` solves `challenge` with the given difficulty.
+pub fn is_solution(challenge: &str, nonce: u64, difficulty: u8) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(challenge.as_bytes());
+    hasher.update(nonce.to_be_bytes());
+    leading_zero_bits(&hasher.finalize()) >= u32::from(difficulty)
+}
+
+impl Challenge {
+    /// Tries the nonces one after the other until one works.
+    pub fn solve(&self) -> Solution {
+        let nonce = (0..=u64::MAX)
+            .find(|nonce| is_solution(&self.challenge, *nonce, self.difficulty))
+            .expect("No solution to the challenge");
+        Solution {
+            challenge: self.challenge.clone(),
+            nonce,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0xff, 0]), 0);
+        assert_eq!(leading_zero_bits(&[0, 0x10, 0]), 11);
+        assert_eq!(leading_zero_bits(&[0, 0]), 16);
+    }
+
+    #[test]
+    fn test_solve() {
+        let challenge = Challenge {
+            challenge: "abcdef".to_string(),
+            difficulty: 8,
+        };
+        let solution = challenge.solve();
+        assert_eq!(solution.challenge, "abcdef");
+        assert!(is_solution("abcdef", solution.nonce, 8));
+        assert!(is_solution("abcdef", 12345, 0));
+    }
+}
@@ -178,6 +178,39 @@ impl TryFrom<ResultEntry> for User {
             .or_else(|| get_optional_attribute("name"))
             .or_else(|| get_optional_attribute("displayName"));
         let first_name = get_optional_attribute("givenName");
+        let preferred_language = get_optional_attribute("preferredLanguage");
+        let get_optional_number = |attr: &str| {
+            get_optional_attribute(attr)
+                .map(|n| n.parse::<i32>().map(i64::from))
+                .transpose()
+                .with_context(|| format!("Invalid {} for user '{}'", attr, id))
+        };
+        let uid_number = get_optional_number("uidNumber")?;
+        let gid_number = get_optional_number("gidNumber")?;
+        let home_directory = get_optional_attribute("homeDirectory");
+        let login_shell = get_optional_attribute("loginShell");
+        // The first mail value is the primary email, the others become aliases.
+        let email_aliases = entry
+            .attrs
+            .get("mail")
+            .map(|v| {
+                v.iter()
+                    .skip(1)
+                    .filter(|s| !s.is_empty())
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|v| !v.is_empty());
+        let ssh_public_keys = entry
+            .attrs
+            .get("sshPublicKey")
+            .map(|v| {
+                v.iter()
+                    .filter(|s| !s.is_empty())
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|v| !v.is_empty());
         let avatar = entry
             .attrs
             .get("jpegPhoto")
@@ -194,6 +227,14 @@ impl TryFrom<ResultEntry> for User {
                 first_name,
                 last_name,
                 avatar: avatar.map(base64::encode),
+                preferred_language,
+                email_aliases,
+                ssh_public_keys,
+                uid_number,
+                gid_number,
+                home_directory,
+                login_shell,
+                attributes: None,
             },
             password,
             entry.dn,
@@ -303,6 +344,12 @@ pub fn get_users(connection: &mut LdapClient) -> Result<Vec<User>, anyhow::Error
                 "commonName",
                 "displayName",
                 "name",
+                "preferredLanguage",
+                "uidNumber",
+                "gidNumber",
+                "homeDirectory",
+                "loginShell",
+                "sshPublicKey",
                 "userPassword",
             ],
         )?
@@ -135,9 +135,17 @@ fn try_login(
     use lldap_auth::opaque::client::login::*;
     let ClientLoginStartResult { state, message } =
         start_login(password, &mut rng).context("Could not initialize login")?;
+    // The older servers don't have a login challenge, and answer with the web app.
+    let challenge = client
+        .get(format!("{}/auth/login/challenge", lldap_server))
+        .send()
+        .context("while trying to get the login challenge of LLDAP")?
+        .json::<Option<lldap_auth::proof_of_work::Challenge>>()
+        .unwrap_or_default();
     let req = ClientLoginStartRequest {
         username: username.to_owned(),
         login_start_request: message,
+        proof_of_work: challenge.map(|c| c.solve()),
     };
     let response = client
         .post(format!("{}/auth/opaque/login/start", lldap_server))
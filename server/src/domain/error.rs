@@ -17,6 +17,10 @@ pub enum DomainError {
     Base64DecodeError(#[from] base64::DecodeError),
     #[error("Entity not found: `{0}`")]
     EntityNotFound(String),
+    #[error("Entity already exists: `{0}`")]
+    EntityAlreadyExists(String),
+    #[error("Invalid input: `{0}`")]
+    InvalidInput(String),
     #[error("Internal error: `{0}`")]
     InternalError(String),
 }
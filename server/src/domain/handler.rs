@@ -1,12 +1,14 @@
 use super::{
     error::Result,
     types::{
-        Group, GroupDetails, GroupId, JpegPhoto, User, UserAndGroups, UserColumn, UserId, Uuid,
+        ApiToken, ApiTokenScope, AttributeSchema, AttributeValue, AuditLogEntry, DateTime, Group,
+        GroupColumn, GroupDetails, GroupId, JpegPhoto, MembershipRequest, Session, User,
+        UserAndGroups, UserColumn, UserId, Uuid, WebauthnCredential, Webhook, WebhookEvent,
     },
 };
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct BindRequest {
@@ -14,6 +16,70 @@ pub struct BindRequest {
     pub password: String,
 }
 
+/// The parts of an LDAP substring filter: `initial*any1*any2*final`, all of them optional.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SubStringFilter {
+    pub initial: Option<String>,
+    pub any: Vec<String>,
+    pub final_: Option<String>,
+}
+
+impl SubStringFilter {
+    /// Matches the value anywhere.
+    pub fn contains(value: &str) -> Self {
+        Self {
+            any: vec![value.to_string()],
+            ..Default::default()
+        }
+    }
+
+    fn to_pattern(&self, escape: impl Fn(&str) -> String, wildcard: char) -> String {
+        let mut pattern = self.initial.as_deref().map(&escape).unwrap_or_default();
+        pattern.push(wildcard);
+        for part in &self.any {
+            pattern.push_str(&escape(part));
+            pattern.push(wildcard);
+        }
+        if let Some(final_) = &self.final_ {
+            pattern.push_str(&escape(final_));
+        }
+        pattern
+    }
+
+    /// The lowercase SQL `LIKE` pattern, with the SQL wildcards of the parts escaped with `\`.
+    pub fn to_sql_like_pattern(&self) -> String {
+        self.to_case_sensitive_sql_like_pattern().to_lowercase()
+    }
+
+    /// Same as `to_sql_like_pattern`, keeping the case of the parts.
+    pub fn to_case_sensitive_sql_like_pattern(&self) -> String {
+        self.to_pattern(
+            |part| {
+                part.replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            },
+            '%',
+        )
+    }
+
+    /// The SQLite `GLOB` pattern, which unlike `LIKE` respects the case. The wildcards of the
+    /// parts are escaped by putting them in brackets.
+    pub fn to_sql_glob_pattern(&self) -> String {
+        self.to_pattern(
+            |part| {
+                part.chars()
+                    .map(|c| match c {
+                        '*' | '?' | '[' => format!("[{}]", c),
+                        c => c.to_string(),
+                    })
+                    .collect()
+            },
+            '*',
+        )
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub enum UserRequestFilter {
     And(Vec<UserRequestFilter>),
@@ -21,10 +87,23 @@ pub enum UserRequestFilter {
     Not(Box<UserRequestFilter>),
     UserId(UserId),
     Equality(UserColumn, String),
+    // Case-insensitive match of the parts of the value.
+    Substring(UserColumn, SubStringFilter),
+    // Case-insensitive match of one of the email aliases, not the primary email.
+    EmailAlias(String),
     // Check if a user belongs to a group identified by name.
     MemberOf(String),
     // Same, by id.
     MemberOfId(GroupId),
+    // Match of one of the values of a custom attribute, by attribute name. The case is ignored
+    // unless the attribute is case-sensitive.
+    AttributeEquality(String, String),
+    // Same, for the parts of the values.
+    AttributeSubstring(String, SubStringFilter),
+    // The user has a value for the custom attribute.
+    AttributePresent(String),
+    // Whether the account is enabled, or disabled.
+    Enabled(bool),
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -33,10 +112,73 @@ pub enum GroupRequestFilter {
     Or(Vec<GroupRequestFilter>),
     Not(Box<GroupRequestFilter>),
     DisplayName(String),
+    // Case-insensitive match of the parts of the display name.
+    DisplayNameSubstring(SubStringFilter),
     Uuid(Uuid),
     GroupId(GroupId),
     // Check if the group contains a user identified by uid.
     Member(UserId),
+    // Check if the group is managed by a user identified by uid.
+    Manager(UserId),
+    Archived(bool),
+    GidNumber(i32),
+    // Case-insensitive match of the email address.
+    Email(String),
+    // Match of one of the values of a custom attribute, by attribute name. The case is ignored
+    // unless the attribute is case-sensitive.
+    AttributeEquality(String, String),
+    // Same, for the parts of the values.
+    AttributeSubstring(String, SubStringFilter),
+    // The group has a value for the custom attribute.
+    AttributePresent(String),
+}
+
+/// The part of a listing to return: `limit` entries after the first `offset` ones, sorted by
+/// `order_by` and then by the primary key, so that the pages don't overlap.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct PageRequest<Column> {
+    pub order_by: Column,
+    pub descending: bool,
+    pub offset: u64,
+    pub limit: u64,
+}
+
+/// One of the keys to sort a listing by. The ties are broken by the next keys, and then by the
+/// primary key.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SortKey<Column> {
+    pub column: Column,
+    pub descending: bool,
+}
+
+/// An entry to add to the audit log, see [`AuditLogEntry`]. It is dated by the backend.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct AuditEvent {
+    pub actor: Option<UserId>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub success: bool,
+    pub details: Option<String>,
+}
+
+/// Which entries of the audit log to list: all the set fields have to match. The dates are
+/// inclusive.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub actor: Option<UserId>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub success: Option<bool>,
+    pub since: Option<DateTime>,
+    pub until: Option<DateTime>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The number of entries matching the filters, in all the pages.
+    pub total_count: u64,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
@@ -48,6 +190,15 @@ pub struct CreateUserRequest {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
+    pub preferred_language: Option<String>,
+    pub email_aliases: Vec<String>,
+    pub ssh_public_keys: Vec<String>,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    /// The values of the custom attributes.
+    pub attributes: Vec<AttributeValue>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
@@ -59,12 +210,98 @@ pub struct UpdateUserRequest {
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
+    pub preferred_language: Option<String>,
+    /// Replaces all the email aliases of the user.
+    pub email_aliases: Option<Vec<String>>,
+    /// Replaces all the public SSH keys of the user.
+    pub ssh_public_keys: Option<Vec<String>>,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    pub login_notifications: Option<bool>,
+    /// Replaces the values of these custom attributes, and removes those without values. The
+    /// other attributes are left untouched.
+    pub attributes: Vec<AttributeValue>,
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct MergeUsersRequest {
+    pub source: UserId,
+    pub target: UserId,
+    /// Replace the attributes the target already has with those of the source, instead of only
+    /// filling in the missing ones.
+    pub overwrite_attributes: bool,
+    /// Delete the source afterwards. Its emails then become aliases of the target.
+    pub delete_source: bool,
+}
+
+/// What [`UserBackendHandler::merge_users`] moved to the target.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UserMergeReport {
+    /// The groups the target was added to, excluding those it was already a member of.
+    pub added_groups: Vec<GroupId>,
+    /// The LDAP names of the attributes copied from the source.
+    pub merged_attributes: Vec<String>,
+    /// The emails of the source that became aliases of the target.
+    pub moved_emails: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct BackendStats {
+    pub user_count: u64,
+    pub group_count: u64,
+    /// Archived groups are included in `group_count`, but hidden from LDAP.
+    pub archived_group_count: u64,
+    /// Size of the database on disk, in bytes, if the database can report it.
+    pub database_size: Option<u64>,
+}
+
+/// What [`BackendHandler::repair_memberships`] fixed.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct MembershipRepairReport {
+    /// Memberships pointing to a user or a group that doesn't exist.
+    pub dangling_memberships_removed: u64,
+    /// Explicit memberships of dynamic groups, whose members are defined by their filter instead.
+    pub dynamic_group_memberships_removed: u64,
+}
+
+/// The outcome of [`BackendHandler::optimize_database`].
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct DatabaseOptimizationReport {
+    /// Size of the database on disk before and after the optimization, in bytes, if the database
+    /// can report it.
+    pub size_before: Option<u64>,
+    pub size_after: Option<u64>,
+}
+
+/// The outcome of [`BackendHandler::record_sync_entries`].
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SyncEntries {
+    /// The current change sequence number, which goes in the cookie of the consumer.
+    pub change_sequence: i64,
+    /// The change sequence number at which each entry last changed.
+    pub entries: HashMap<Uuid, i64>,
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct UpdateGroupRequest {
     pub group_id: GroupId,
     pub display_name: Option<String>,
+    pub archived: Option<bool>,
+    pub gid_number: Option<i32>,
+    /// An empty string removes the email address.
+    pub email: Option<String>,
+    /// Replaces the values of the listed custom attributes, like for the users.
+    pub attributes: Vec<AttributeValue>,
+}
+
+/// A group contained in another group: the members of the child group are also, indirectly,
+/// members of the parent group.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct GroupNesting {
+    pub child_group_id: GroupId,
+    pub parent_group: GroupDetails,
 }
 
 #[async_trait]
@@ -75,10 +312,61 @@ pub trait LoginHandler: Clone + Send {
 #[async_trait]
 pub trait GroupBackendHandler {
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+    /// Like `list_groups`, but one page at a time, and without the members.
+    async fn list_groups_page(
+        &self,
+        filters: Option<GroupRequestFilter>,
+        page: PageRequest<GroupColumn>,
+    ) -> Result<Page<GroupDetails>>;
     async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
     async fn create_group(&self, group_name: &str) -> Result<GroupId>;
+    /// The members of a dynamic group are the users matching the LDAP filter, see
+    /// [`crate::domain::ldap::filter::parse_user_filter`].
+    async fn create_dynamic_group(&self, group_name: &str, filter: &str) -> Result<GroupId>;
     async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+    async fn delete_groups(&self, group_ids: &[GroupId]) -> Result<()>;
+    /// Fails if the parent is already contained, directly or not, in the child group.
+    async fn add_group_to_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()>;
+    async fn remove_group_from_group(
+        &self,
+        group_id: GroupId,
+        parent_group_id: GroupId,
+    ) -> Result<()>;
+    /// All the direct nestings, ordered by child and parent group ID.
+    async fn list_group_nestings(&self) -> Result<Vec<GroupNesting>>;
+    /// The users who can change the members of the group without being admins, ordered by ID.
+    async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+    async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+    /// Records a request to add the user to a group that requires an approval, valid for
+    /// `membership_request_validity_days`. Fails if the user is already a member, or already has
+    /// a pending request for the group.
+    async fn create_membership_request(
+        &self,
+        group_id: GroupId,
+        user_id: &UserId,
+        requested_by: &UserId,
+    ) -> Result<MembershipRequest>;
+    /// The requests that haven't expired, oldest first, for one group or all of them.
+    async fn list_membership_requests(
+        &self,
+        group_id: Option<GroupId>,
+    ) -> Result<Vec<MembershipRequest>>;
+    /// Fails if the request doesn't exist or expired.
+    async fn get_membership_request(&self, request_id: i32) -> Result<MembershipRequest>;
+    /// Adds the user to the group and deletes the request, in one transaction.
+    async fn approve_membership_request(&self, request_id: i32) -> Result<()>;
+    async fn delete_membership_request(&self, request_id: i32) -> Result<()>;
+    /// The values of the custom attributes of the group, ordered by name. `list_groups` returns
+    /// them too.
+    async fn get_group_attributes(&self, group_id: GroupId) -> Result<Vec<AttributeValue>>;
+    /// The custom group attributes, ordered by name.
+    async fn list_group_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+    /// Fails if an attribute already has the same name or LDAP name.
+    async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+    /// Also deletes the values of the attribute.
+    async fn delete_group_attribute(&self, name: &str) -> Result<()>;
 }
 
 #[async_trait]
@@ -88,17 +376,189 @@ pub trait UserBackendHandler {
         filters: Option<UserRequestFilter>,
         get_groups: bool,
     ) -> Result<Vec<UserAndGroups>>;
+    /// Like `list_users`, sorted by the given keys instead of the user ID.
+    async fn list_users_sorted(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        sort_keys: Vec<SortKey<UserColumn>>,
+    ) -> Result<Vec<UserAndGroups>>;
+    /// Like `list_users`, but one page at a time, and without the groups.
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        page: PageRequest<UserColumn>,
+    ) -> Result<Page<User>>;
     async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
     async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+    /// Fails with `EntityAlreadyExists` if `create_user` would refuse the emails because another
+    /// user or a group has them.
+    async fn check_emails_available(
+        &self,
+        user_id: &UserId,
+        email: &str,
+        email_aliases: &[String],
+    ) -> Result<()>;
+    /// Creates the users in a single transaction, and returns the outcome for each of them. If one
+    /// fails, the others are only created with `best_effort`.
+    async fn create_users(
+        &self,
+        requests: Vec<CreateUserRequest>,
+        best_effort: bool,
+    ) -> Result<Vec<Result<()>>>;
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
     async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+    /// Like `create_users`, for the deletions.
+    async fn delete_users(&self, user_ids: &[UserId], best_effort: bool)
+        -> Result<Vec<Result<()>>>;
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+    /// Like `create_users`, for the memberships. Fails as a whole if the group doesn't exist.
+    async fn add_users_to_group(
+        &self,
+        user_ids: &[UserId],
+        group_id: GroupId,
+        best_effort: bool,
+    ) -> Result<Vec<Result<()>>>;
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+    /// Replaces the explicit members of the group in a single transaction: if one of the users
+    /// doesn't exist, the membership is left unchanged.
+    async fn set_group_members(&self, group_id: GroupId, user_ids: &[UserId]) -> Result<()>;
+    /// Creates the group with its members in a single transaction: if one of the users doesn't
+    /// exist, the group isn't created.
+    async fn create_group_with_members(
+        &self,
+        group_name: &str,
+        user_ids: &[UserId],
+    ) -> Result<GroupId>;
     async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+    /// Moves the group memberships and attributes of a duplicate account to the target, in a
+    /// single transaction.
+    async fn merge_users(&self, request: MergeUsersRequest) -> Result<UserMergeReport>;
+    /// The custom attributes defined by the admins, ordered by name.
+    async fn list_user_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+    /// Fails if an attribute already has the same name or LDAP name.
+    async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+    /// Also deletes the values of the attribute.
+    async fn delete_user_attribute(&self, name: &str) -> Result<()>;
 }
 
 #[async_trait]
-pub trait BackendHandler: Clone + Send + GroupBackendHandler + UserBackendHandler {}
+pub trait BackendHandler: Clone + Send + GroupBackendHandler + UserBackendHandler {
+    async fn get_stats(&self) -> Result<BackendStats>;
+    /// Removes the memberships that shouldn't exist, e.g. after an import with foreign keys
+    /// disabled. Safe to run repeatedly: a consistent database is left untouched.
+    async fn repair_memberships(&self) -> Result<MembershipRepairReport>;
+    /// Reclaims the free space and refreshes the statistics and indices of the database. On
+    /// SQLite, the database is locked for writing until it completes.
+    async fn optimize_database(&self) -> Result<DatabaseOptimizationReport>;
+    /// Creates a single-use token to log in as the user without a password. Fails if the user
+    /// already has a pending token, to limit how many can be in circulation.
+    async fn create_magic_login_token(
+        &self,
+        user_id: &UserId,
+        valid_for: chrono::Duration,
+    ) -> Result<String>;
+    /// Deletes the token and returns the user it logs in, if it exists and hasn't expired.
+    async fn consume_magic_login_token(&self, token: &str) -> Result<UserId>;
+    /// Creates a password reset token for an admin to hand over to the user, e.g. when no mail
+    /// server is configured. It is used like the tokens sent by email.
+    async fn create_password_reset_token(
+        &self,
+        user_id: &UserId,
+        valid_for: chrono::Duration,
+    ) -> Result<String>;
+    /// Stores a new TOTP secret for the user and returns it. The secret is only required to log
+    /// in once confirmed with a first code. Fails if TOTP is already enabled for the user.
+    async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<String>;
+    /// Enables TOTP if the code matches the pending secret, and returns the new recovery codes.
+    async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<Vec<String>>;
+    async fn is_totp_enabled(&self, user_id: &UserId) -> Result<bool>;
+    /// Checks the TOTP code of a user with TOTP enabled, or consumes one of their recovery codes.
+    async fn check_totp_code(&self, user_id: &UserId, code: &str) -> Result<bool>;
+    /// Removes the TOTP secret and the recovery codes of the user.
+    async fn disable_totp(&self, user_id: &UserId) -> Result<()>;
+    /// Unlocks an account locked after too many wrong passwords, and resets the count.
+    async fn unlock_user(&self, user_id: &UserId) -> Result<()>;
+    /// Uses one of the logins allowed after the password of the user expired, and returns how
+    /// many are left after it. `None` if there were none left: the password has to be changed.
+    async fn use_grace_login(&self, user_id: &UserId, max_grace_logins: u32)
+        -> Result<Option<u32>>;
+    /// Replaces the password of the user with a hash from another system, see
+    /// [`super::legacy_password`].
+    async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()>;
+    /// Creates a single-use password for the user to log in once and choose their own, replacing
+    /// the previous one if any.
+    async fn create_temporary_password(
+        &self,
+        user_id: &UserId,
+        valid_for: chrono::Duration,
+    ) -> Result<String>;
+    /// Clears the temporary password of the user, if it matches and hasn't expired.
+    async fn consume_temporary_password(&self, user_id: &UserId, password: &str) -> Result<()>;
+    /// Sets or clears the dates between which the account can be used to log in.
+    async fn set_account_validity(
+        &self,
+        user_id: &UserId,
+        activation_date: Option<DateTime>,
+        expiration_date: Option<DateTime>,
+    ) -> Result<()>;
+    /// Disables or re-enables the account. The sessions of the user aren't revoked.
+    async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()>;
+    /// Fails if the credential ID is already registered, for any user.
+    async fn add_webauthn_credential(&self, credential: WebauthnCredential) -> Result<()>;
+    async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+    /// Replaces the serialized credential, e.g. when a login updated its signature counter.
+    async fn update_webauthn_credential(
+        &self,
+        credential_id: &str,
+        credential: String,
+    ) -> Result<()>;
+    async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str)
+        -> Result<()>;
+    /// The user that the identity of the upstream OpenID Connect provider logs in as, if any.
+    async fn get_sso_user(&self, issuer: &str, subject: &str) -> Result<Option<UserId>>;
+    /// Fails if the identity is already linked to a user.
+    async fn link_sso_user(&self, issuer: &str, subject: &str, user_id: &UserId) -> Result<()>;
+    /// Stores the digest of the current content of each LDAP entry, for the content
+    /// synchronization. The entries whose digest changed get a new change sequence number, and
+    /// the ones that aren't listed anymore are forgotten.
+    async fn record_sync_entries(&self, digests: Vec<(Uuid, String)>) -> Result<SyncEntries>;
+    /// Returns the new token, and its secret. The secret can't be retrieved later.
+    async fn create_api_token(
+        &self,
+        user_id: &UserId,
+        name: &str,
+        scopes: &[ApiTokenScope],
+    ) -> Result<(ApiToken, String)>;
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+    /// The token with this secret, if it exists.
+    async fn get_api_token(&self, secret: &str) -> Result<Option<ApiToken>>;
+    async fn delete_api_token(&self, token_id: &str) -> Result<()>;
+    /// The sessions of the user in the web UI that haven't expired, oldest first.
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+    /// Ends the session, and returns the hashes of its JWTs that were still valid: they are
+    /// blacklisted, and have to be rejected from now on.
+    async fn delete_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>>;
+    /// Ends all the sessions of the user, like `delete_session`.
+    async fn delete_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>>;
+    async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+    /// The entries matching the filter, newest first.
+    async fn list_audit_logs(
+        &self,
+        filter: AuditLogFilter,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Page<AuditLogEntry>>;
+    /// Creates a webhook with a new random secret.
+    async fn create_webhook(&self, url: &str, events: &[WebhookEvent]) -> Result<Webhook>;
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>>;
+    async fn delete_webhook(&self, webhook_id: i32) -> Result<()>;
+    /// Starts a database transaction. The changes made through the returned handler are only
+    /// visible to the others, and notified, once it's committed.
+    async fn begin_transaction(&self) -> Result<Self>;
+    /// Commits or rolls back the transaction of a handler returned by `begin_transaction`.
+    async fn end_transaction(self, commit: bool) -> Result<()>;
+}
 
 #[cfg(test)]
 mockall::mock! {
@@ -109,24 +569,94 @@ mockall::mock! {
     #[async_trait]
     impl GroupBackendHandler for TestBackendHandler {
         async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+        async fn list_groups_page(&self, filters: Option<GroupRequestFilter>, page: PageRequest<GroupColumn>) -> Result<Page<GroupDetails>>;
         async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
         async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
         async fn create_group(&self, group_name: &str) -> Result<GroupId>;
+        async fn create_dynamic_group(&self, group_name: &str, filter: &str) -> Result<GroupId>;
         async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+        async fn delete_groups(&self, group_ids: &[GroupId]) -> Result<()>;
+        async fn add_group_to_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()>;
+        async fn remove_group_from_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()>;
+        async fn list_group_nestings(&self) -> Result<Vec<GroupNesting>>;
+        async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+        async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        async fn create_membership_request(&self, group_id: GroupId, user_id: &UserId, requested_by: &UserId) -> Result<MembershipRequest>;
+        async fn list_membership_requests(&self, group_id: Option<GroupId>) -> Result<Vec<MembershipRequest>>;
+        async fn get_membership_request(&self, request_id: i32) -> Result<MembershipRequest>;
+        async fn approve_membership_request(&self, request_id: i32) -> Result<()>;
+        async fn delete_membership_request(&self, request_id: i32) -> Result<()>;
+        async fn get_group_attributes(&self, group_id: GroupId) -> Result<Vec<AttributeValue>>;
+        async fn list_group_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_group_attribute(&self, name: &str) -> Result<()>;
     }
     #[async_trait]
     impl UserBackendHandler for TestBackendHandler {
         async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
+        async fn list_users_sorted(&self, filters: Option<UserRequestFilter>, get_groups: bool, sort_keys: Vec<SortKey<UserColumn>>) -> Result<Vec<UserAndGroups>>;
+        async fn list_users_page(&self, filters: Option<UserRequestFilter>, page: PageRequest<UserColumn>) -> Result<Page<User>>;
         async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
         async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+        async fn check_emails_available(&self, user_id: &UserId, email: &str, email_aliases: &[String]) -> Result<()>;
+        async fn create_users(&self, requests: Vec<CreateUserRequest>, best_effort: bool) -> Result<Vec<Result<()>>>;
         async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
         async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+        async fn delete_users(&self, user_ids: &[UserId], best_effort: bool) -> Result<Vec<Result<()>>>;
         async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
         async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId, best_effort: bool) -> Result<Vec<Result<()>>>;
         async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn set_group_members(&self, group_id: GroupId, user_ids: &[UserId]) -> Result<()>;
+        async fn create_group_with_members(&self, group_name: &str, user_ids: &[UserId]) -> Result<GroupId>;
+        async fn merge_users(&self, request: MergeUsersRequest) -> Result<UserMergeReport>;
+        async fn list_user_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_user_attribute(&self, name: &str) -> Result<()>;
     }
     #[async_trait]
-    impl BackendHandler for TestBackendHandler {}
+    impl BackendHandler for TestBackendHandler {
+        async fn get_stats(&self) -> Result<BackendStats>;
+        async fn repair_memberships(&self) -> Result<MembershipRepairReport>;
+        async fn optimize_database(&self) -> Result<DatabaseOptimizationReport>;
+        async fn create_magic_login_token(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+        async fn consume_magic_login_token(&self, token: &str) -> Result<UserId>;
+        async fn create_password_reset_token(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+        async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<String>;
+        async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<Vec<String>>;
+        async fn is_totp_enabled(&self, user_id: &UserId) -> Result<bool>;
+        async fn check_totp_code(&self, user_id: &UserId, code: &str) -> Result<bool>;
+        async fn disable_totp(&self, user_id: &UserId) -> Result<()>;
+        async fn unlock_user(&self, user_id: &UserId) -> Result<()>;
+        async fn use_grace_login(&self, user_id: &UserId, max_grace_logins: u32) -> Result<Option<u32>>;
+        async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()>;
+        async fn create_temporary_password(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+        async fn consume_temporary_password(&self, user_id: &UserId, password: &str) -> Result<()>;
+        async fn set_account_validity(&self, user_id: &UserId, activation_date: Option<DateTime>, expiration_date: Option<DateTime>) -> Result<()>;
+        async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()>;
+        async fn add_webauthn_credential(&self, credential: WebauthnCredential) -> Result<()>;
+        async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+        async fn update_webauthn_credential(&self, credential_id: &str, credential: String) -> Result<()>;
+        async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str) -> Result<()>;
+        async fn get_sso_user(&self, issuer: &str, subject: &str) -> Result<Option<UserId>>;
+        async fn link_sso_user(&self, issuer: &str, subject: &str, user_id: &UserId) -> Result<()>;
+        async fn record_sync_entries(&self, digests: Vec<(Uuid, String)>) -> Result<SyncEntries>;
+        async fn create_api_token(&self, user_id: &UserId, name: &str, scopes: &[ApiTokenScope]) -> Result<(ApiToken, String)>;
+        async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+        async fn get_api_token(&self, secret: &str) -> Result<Option<ApiToken>>;
+        async fn delete_api_token(&self, token_id: &str) -> Result<()>;
+        async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+        async fn delete_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>>;
+        async fn delete_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>>;
+        async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+        async fn list_audit_logs(&self, filter: AuditLogFilter, offset: u64, limit: u64) -> Result<Page<AuditLogEntry>>;
+        async fn create_webhook(&self, url: &str, events: &[WebhookEvent]) -> Result<Webhook>;
+        async fn list_webhooks(&self) -> Result<Vec<Webhook>>;
+        async fn delete_webhook(&self, webhook_id: i32) -> Result<()>;
+        async fn begin_transaction(&self) -> Result<Self>;
+        async fn end_transaction(self, commit: bool) -> Result<()>;
+    }
     #[async_trait]
     impl LoginHandler for TestBackendHandler {
         async fn bind(&self, request: BindRequest) -> Result<()>;
@@ -148,6 +678,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_substring_filter_to_sql_like_pattern() {
+        let filter = |initial: Option<&str>, any: &[&str], final_: Option<&str>| SubStringFilter {
+            initial: initial.map(str::to_string),
+            any: any.iter().map(|s| s.to_string()).collect(),
+            final_: final_.map(str::to_string),
+        };
+        assert_eq!(filter(Some("Jo"), &[], None).to_sql_like_pattern(), "jo%");
+        assert_eq!(
+            filter(None, &[], Some("@example.com")).to_sql_like_pattern(),
+            "%@example.com"
+        );
+        assert_eq!(
+            filter(Some("a"), &["b", "c"], Some("d")).to_sql_like_pattern(),
+            "a%b%c%d"
+        );
+        assert_eq!(
+            SubStringFilter::contains("100%_\\").to_sql_like_pattern(),
+            "%100\\%\\_\\\\%"
+        );
+        assert_eq!(
+            filter(Some("Jo"), &["B"], None).to_case_sensitive_sql_like_pattern(),
+            "Jo%B%"
+        );
+    }
+
+    #[test]
+    fn test_substring_filter_to_sql_glob_pattern() {
+        assert_eq!(
+            SubStringFilter {
+                initial: Some("Jo".to_string()),
+                any: vec!["a*b?".to_string()],
+                final_: Some("[x]".to_string()),
+            }
+            .to_sql_glob_pattern(),
+            "Jo*a[*]b[?]*[[]x]"
+        );
+    }
+
     #[test]
     fn test_jpeg_try_from_bytes() {
         let base64_raw = "/9j/4AAQSkZJRgABAQEASABIAAD/2wBDAP//////////////////////////////////////////////////////////////////////////////////////2wBDAf//////////////////////////////////////////////////////////////////////////////////////wAARCADqATkDASIAAhEBAxEB/8QAFwABAQEBAAAAAAAAAAAAAAAAAAECA//EACQQAQEBAAIBBAMBAQEBAAAAAAABESExQQISUXFhgZGxocHw/8QAFQEBAQAAAAAAAAAAAAAAAAAAAAH/xAAWEQEBAQAAAAAAAAAAAAAAAAAAEQH/2gAMAwEAAhEDEQA/AMriLyCKgg1gQwCgs4FTMOdutepjQak+FzMSVqgxZdRdPPIIvH5WzzGdBriphtTeAXg2ZjKA1pqKDUGZca3foBek8gFv8Ie3fKdA1qb8s7hoL6eLVt51FsAnql3Ut1M7AWbflLMDkEMX/F6/YjK/pADFQAUNA6alYagKk72m/j9p4Bq2fDDSYKLNXPNLoHE/NT6RYC31cJxZ3yWVM+aBYi/S2ZgiAsnYJx5D21vPmqrm3PTfpQQwyAC8JZvSKDni41ZrMuUVVl+Uz9w9v/1QWrZsZ5nFPHYH+JZyureQSF5M+fJ0CAfwRAVRBQA1DAWVUayoJUWoDpsxntPsueBV4+VxhdyAtv8AjOLGpIDMLbeGvbF4iozJfr/WukAVABAXAQXEAAASzVAZdO2WNordm+emFl7XcQSNZiFtv0C9w90nhJf4mA1u+GcJFwIyAqL/AOovwgGNfSRqdIrNa29M0gKCAojU9PAMjWXpckEJFNFEAAXEUBABYz6rZ0ureQc9vyt9XxDF2QAXtABcQAs0AZywkvluJbyipifas52DcyxjlZweAO0xri/hc+wZOEKIu6nSyeToVZyWXwvCg53gW81QQ7aTNAn5dGZJPs1UXURQAUEMCXQLZE93PRZ5hPTgNMrbIzKCm52LZwCs+2M8w2g3sjPuZAXb4IsMAUACzVUGM4/K+md6vEXUUyM5PDR0IxYe6ramih0VNBrS4xoqN8Q1BFQk3yqyAsioioAAKgDSJL4/jQIn5igLrPqtOuf6oOaxbMoAltUAhhIoJiiggrPu+AaOIxtAX3JbaAIaLwi4t9X4T3fg2AFtqcrUUarP20zUDAmqoE0WRBZPNVUVEAAAAVAC8kvih2DSKxOdBqs7Z0l0gI0mKAC4AuHE7ZtBriM+744QAAAAABAFsveIttBICyaikvy1+r/Cen5rWQHIBQa4rIDRqSl5qDWqziqgAAAATA7BpGdqXb2C2+J/UgAtRQBSQtkBWb6vhLbQAAAAAEBRAAAAAUbm+GZNdPxAP+ql2Tjwx7/wIgZ8iKvBk+CJoCXii9gaqZ/qqihAAAEVABGkBFUwBftNkZ3QW34QAAABFAQAVAAAAAARVkl8gs/43sk1jL45LvHArepk+E9XTG35oLqsmIKmLAEygKg0y1AFQBUXwgAAAoBC34S3UAAABAVAAAAAABAUQAVABdRQa1PcYyit2z58M8C4ouM2NXpOEGeWtNZUatiAIoAKIoCoAoG4C9MW6dgIoAIAAAAAAACKWAgL0CAAAALiANCKioNLgM1CrLihmTafkt1EF3SZ5ZVUW4mnIKvAi5fhEURVDWVQBRAAAAAAAAQFRVyAyulgAqCKlF8IqLsEgC9mGoC+IusqCrv5ZEUVOk1RuJfwSLOOkGFi4XPCoYYrNiKauosBGi9ICstM1UAAAAAAFQ0VcTBAXUGgIqGoKhKAzRRUQUAwxoSrGRpkQA/qiosOL9oJptMRRVZa0VUqSiChE6BqMgCwqKqIogAIAqKCKgKoogg0lBFuIKgAAAKNRlf2gqsftsEtZWoAAqAACKoMqAAeSoqp39kL2AqLOlE8rEBFQARYALhigrNC9gGmooLp4TweEQFFBFAECgIoAu0ifIAqAAA//9k=";
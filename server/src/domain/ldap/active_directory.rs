@@ -0,0 +1,294 @@
+//! The Active Directory attributes and object classes, synthesized for the applications that
+//! hardcode them (`ldap_active_directory_compatibility`).
+//!
+//! Users get the `user` object class, `sAMAccountName`, `userPrincipalName`, `objectGUID`,
+//! `userAccountControl` and `accountExpires`; groups get the `group` object class,
+//! `sAMAccountName`, `objectGUID` and `groupType`. These attributes are only returned when
+//! requested by name, and can be used in equality and presence filters, except `objectGUID`.
+
+use crate::domain::{
+    handler::{GroupRequestFilter, UserRequestFilter},
+    types::{DateTime, Group, User, UserId, Uuid},
+};
+
+/// The `userAccountControl` of an enabled account: NORMAL_ACCOUNT.
+const USER_ACCOUNT_CONTROL: &str = "512";
+
+/// The `userAccountControl` of a disabled account: NORMAL_ACCOUNT and ACCOUNTDISABLE.
+const DISABLED_USER_ACCOUNT_CONTROL: &str = "514";
+
+/// The `accountExpires` of an account that never expires.
+const ACCOUNT_NEVER_EXPIRES: &str = "9223372036854775807";
+
+/// The seconds between the epoch of the Windows file times, 1601-01-01, and the Unix epoch.
+const FILE_TIME_EPOCH_OFFSET_SECONDS: i64 = 11_644_473_600;
+
+/// The `groupType` of a global security group.
+const GROUP_TYPE: &str = "-2147483646";
+
+/// The DNS domain of the base DN: `dc=example,dc=com` is `example.com`. Without `dc` components,
+/// all the values are used.
+pub fn get_domain_name(base_dn: &[(String, String)]) -> String {
+    let components = base_dn
+        .iter()
+        .filter(|(key, _)| key == "dc")
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>();
+    if components.is_empty() {
+        base_dn
+            .iter()
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+            .join(".")
+    } else {
+        components.join(".")
+    }
+}
+
+/// The binary form of the UUID, with the first three fields in little-endian order like in the
+/// GUID structure of Windows.
+fn to_object_guid(uuid: &Uuid) -> Option<Vec<u8>> {
+    uuid::Uuid::parse_str(uuid.as_str())
+        .ok()
+        .map(|uuid| uuid.to_bytes_le().to_vec())
+}
+
+/// The date as a Windows file time: 100-nanosecond intervals since 1601-01-01.
+fn to_file_time(date: &DateTime) -> i64 {
+    (date.timestamp() + FILE_TIME_EPOCH_OFFSET_SECONDS) * 10_000_000
+}
+
+/// Accepts the logon names of Active Directory in binds: `bob@example.com` (the user principal
+/// name) and `EXAMPLE\bob` (the down-level logon name).
+pub fn get_user_id_from_logon_name(name: &str, domain: &str) -> Option<UserId> {
+    if let Some((user, user_domain)) = name.rsplit_once('@') {
+        if user_domain.eq_ignore_ascii_case(domain) && !user.is_empty() {
+            return Some(UserId::new(user));
+        }
+    }
+    if let Some((netbios_domain, user)) = name.split_once('\\') {
+        let first_label = domain.split('.').next().unwrap_or_default();
+        if netbios_domain.eq_ignore_ascii_case(first_label) && !user.is_empty() {
+            return Some(UserId::new(user));
+        }
+    }
+    None
+}
+
+fn matches_all_users(matches: bool) -> UserRequestFilter {
+    let all = UserRequestFilter::And(vec![]);
+    if matches {
+        all
+    } else {
+        UserRequestFilter::Not(Box::new(all))
+    }
+}
+
+fn matches_all_groups(matches: bool) -> GroupRequestFilter {
+    let all = GroupRequestFilter::And(vec![]);
+    if matches {
+        all
+    } else {
+        GroupRequestFilter::Not(Box::new(all))
+    }
+}
+
+/// The value of an Active Directory attribute of the user, `None` for the other attributes.
+pub fn get_user_attribute(user: &User, attribute: &str, domain: &str) -> Option<Vec<Vec<u8>>> {
+    Some(match attribute {
+        "samaccountname" => vec![user.user_id.to_string().into_bytes()],
+        "userprincipalname" => vec![format!("{}@{}", user.user_id, domain).into_bytes()],
+        "objectguid" => vec![to_object_guid(&user.uuid)?],
+        "useraccountcontrol" => vec![if user.enabled {
+            USER_ACCOUNT_CONTROL.as_bytes().to_vec()
+        } else {
+            DISABLED_USER_ACCOUNT_CONTROL.as_bytes().to_vec()
+        }],
+        "accountexpires" => vec![match &user.expiration_date {
+            Some(date) => to_file_time(date).to_string().into_bytes(),
+            None => ACCOUNT_NEVER_EXPIRES.as_bytes().to_vec(),
+        }],
+        _ => return None,
+    })
+}
+
+/// Converts the filters on the Active Directory attributes of the users, `None` for the other
+/// attributes. The attribute is in lowercase.
+pub fn convert_user_equality_filter(
+    attribute: &str,
+    value: &str,
+    domain: &str,
+) -> Option<UserRequestFilter> {
+    let value_is = |expected: &[&str]| expected.iter().any(|e| value.eq_ignore_ascii_case(e));
+    Some(match attribute {
+        // The other object classes are handled like without the compatibility mode.
+        "objectclass" if value_is(&["user", "organizationalPerson", "top"]) => {
+            matches_all_users(true)
+        }
+        "objectcategory" => matches_all_users(value_is(&["person", "user"])),
+        "samaccountname" => UserRequestFilter::UserId(UserId::new(value)),
+        "userprincipalname" => match get_user_id_from_logon_name(value, domain) {
+            Some(user_id) if value.contains('@') => UserRequestFilter::UserId(user_id),
+            _ => matches_all_users(false),
+        },
+        "useraccountcontrol" => match value {
+            USER_ACCOUNT_CONTROL => UserRequestFilter::Enabled(true),
+            DISABLED_USER_ACCOUNT_CONTROL => UserRequestFilter::Enabled(false),
+            _ => matches_all_users(false),
+        },
+        _ => return None,
+    })
+}
+
+/// Whether the user attribute, in lowercase, is one of the Active Directory ones.
+pub fn is_user_attribute(attribute: &str) -> bool {
+    matches!(
+        attribute,
+        "objectcategory"
+            | "samaccountname"
+            | "userprincipalname"
+            | "objectguid"
+            | "useraccountcontrol"
+            | "accountexpires"
+    )
+}
+
+/// The value of an Active Directory attribute of the group, `None` for the other attributes.
+pub fn get_group_attribute(group: &Group, attribute: &str) -> Option<Vec<Vec<u8>>> {
+    Some(match attribute {
+        "samaccountname" => vec![group.display_name.clone().into_bytes()],
+        "objectguid" => vec![to_object_guid(&group.uuid)?],
+        "grouptype" => vec![GROUP_TYPE.as_bytes().to_vec()],
+        _ => return None,
+    })
+}
+
+/// Converts the filters on the Active Directory attributes of the groups, `None` for the other
+/// attributes. The attribute and the value are in lowercase.
+pub fn convert_group_equality_filter(attribute: &str, value: &str) -> Option<GroupRequestFilter> {
+    Some(match attribute {
+        "objectclass" if value == "group" || value == "top" => matches_all_groups(true),
+        "objectcategory" => matches_all_groups(value == "group"),
+        "samaccountname" => GroupRequestFilter::DisplayName(value.to_string()),
+        "grouptype" => matches_all_groups(value == GROUP_TYPE),
+        _ => return None,
+    })
+}
+
+/// Whether the group attribute, in lowercase, is one of the Active Directory ones.
+pub fn is_group_attribute(attribute: &str) -> bool {
+    matches!(
+        attribute,
+        "objectcategory" | "samaccountname" | "objectguid" | "grouptype"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_base_dn(components: &[(&str, &str)]) -> Vec<(String, String)> {
+        components
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_domain_name() {
+        assert_eq!(
+            get_domain_name(&make_base_dn(&[("dc", "example"), ("dc", "com")])),
+            "example.com"
+        );
+        assert_eq!(
+            get_domain_name(&make_base_dn(&[
+                ("ou", "lldap"),
+                ("dc", "example"),
+                ("dc", "com")
+            ])),
+            "example.com"
+        );
+        assert_eq!(
+            get_domain_name(&make_base_dn(&[("o", "example"), ("c", "fr")])),
+            "example.fr"
+        );
+    }
+
+    #[test]
+    fn test_to_object_guid() {
+        assert_eq!(
+            to_object_guid(&Uuid::try_from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8").unwrap()),
+            Some(vec![
+                0xa4, 0xa3, 0xa2, 0xa1, 0xb2, 0xb1, 0xc2, 0xc1, 0xd1, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6,
+                0xd7, 0xd8
+            ])
+        );
+    }
+
+    #[test]
+    fn test_account_expires() {
+        use chrono::TimeZone;
+        let mut user = User::default();
+        assert_eq!(
+            get_user_attribute(&user, "accountexpires", "example.com"),
+            Some(vec![ACCOUNT_NEVER_EXPIRES.as_bytes().to_vec()])
+        );
+        user.expiration_date = chrono::Utc.timestamp_opt(0, 0).single();
+        assert_eq!(
+            get_user_attribute(&user, "accountexpires", "example.com"),
+            Some(vec![b"116444736000000000".to_vec()])
+        );
+    }
+
+    #[test]
+    fn test_user_account_control() {
+        let mut user = User::default();
+        assert_eq!(
+            get_user_attribute(&user, "useraccountcontrol", "example.com"),
+            Some(vec![b"512".to_vec()])
+        );
+        user.enabled = false;
+        assert_eq!(
+            get_user_attribute(&user, "useraccountcontrol", "example.com"),
+            Some(vec![b"514".to_vec()])
+        );
+    }
+
+    #[test]
+    fn test_get_user_id_from_logon_name() {
+        let parse = |name| get_user_id_from_logon_name(name, "example.com");
+        assert_eq!(parse("bob@Example.com"), Some(UserId::new("bob")));
+        assert_eq!(parse("EXAMPLE\\bob"), Some(UserId::new("bob")));
+        assert_eq!(parse("bob@other.com"), None);
+        assert_eq!(parse("OTHER\\bob"), None);
+        assert_eq!(parse("@example.com"), None);
+        assert_eq!(parse("uid=bob,ou=people,dc=example,dc=com"), None);
+    }
+
+    #[test]
+    fn test_convert_user_equality_filter() {
+        let convert =
+            |attribute, value| convert_user_equality_filter(attribute, value, "example.com");
+        let none = matches_all_users(false);
+        assert_eq!(
+            convert("objectclass", "User"),
+            Some(matches_all_users(true))
+        );
+        assert_eq!(convert("objectclass", "inetOrgPerson"), None);
+        assert_eq!(convert("objectcategory", "group"), Some(none.clone()));
+        assert_eq!(
+            convert("userprincipalname", "bob@example.com"),
+            Some(UserRequestFilter::UserId(UserId::new("bob")))
+        );
+        assert_eq!(
+            convert("userprincipalname", "EXAMPLE\\bob"),
+            Some(none.clone())
+        );
+        assert_eq!(
+            convert("useraccountcontrol", "514"),
+            Some(UserRequestFilter::Enabled(false))
+        );
+        assert_eq!(convert("useraccountcontrol", "66048"), Some(none));
+        assert_eq!(convert("mail", "bob@example.com"), None);
+    }
+}
@@ -0,0 +1,403 @@
+//! A small BER reader and writer (X.690), for the parts of the LDAP protocol that `ldap3_proto`
+//! doesn't handle: the values of most controls, and the requests its codec would reject as a
+//! whole. LDAP only uses the definite length forms (RFC 4511, section 5.1).
+
+/// The universal tags used by LDAP.
+pub const BOOLEAN: u64 = 1;
+pub const INTEGER: u64 = 2;
+pub const OCTET_STRING: u64 = 4;
+pub const ENUMERATED: u64 = 10;
+pub const SEQUENCE: u64 = 16;
+
+/// Deeper elements are rejected rather than risking the stack on a malicious request.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagClass {
+    Universal,
+    Application,
+    Context,
+    Private,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Payload {
+    Primitive(Vec<u8>),
+    Constructed(Vec<Element>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Element {
+    pub class: TagClass,
+    pub tag: u64,
+    pub payload: Payload,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// More bytes are needed to read the element.
+    Incomplete,
+    Invalid(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+struct Header {
+    class: TagClass,
+    constructed: bool,
+    tag: u64,
+    header_length: usize,
+    content_length: usize,
+}
+
+fn read_header(data: &[u8]) -> Result<Header> {
+    let first = *data.first().ok_or(Error::Incomplete)?;
+    let class = match first >> 6 {
+        0 => TagClass::Universal,
+        1 => TagClass::Application,
+        2 => TagClass::Context,
+        _ => TagClass::Private,
+    };
+    let constructed = first & 0x20 != 0;
+    let mut position = 1;
+    let mut tag = u64::from(first & 0x1f);
+    if tag == 0x1f {
+        // High tag number form: base 128, the last byte has the top bit cleared.
+        tag = 0;
+        loop {
+            let byte = *data.get(position).ok_or(Error::Incomplete)?;
+            position += 1;
+            if tag > u64::MAX >> 7 {
+                return Err(Error::Invalid("Tag number too large"));
+            }
+            tag = (tag << 7) | u64::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    let length_byte = *data.get(position).ok_or(Error::Incomplete)?;
+    position += 1;
+    let content_length = if length_byte & 0x80 == 0 {
+        usize::from(length_byte)
+    } else {
+        let length_bytes = usize::from(length_byte & 0x7f);
+        if length_bytes == 0 {
+            return Err(Error::Invalid("Indefinite lengths are not allowed"));
+        }
+        if length_bytes > std::mem::size_of::<usize>() {
+            return Err(Error::Invalid("Length too large"));
+        }
+        let bytes = data
+            .get(position..position + length_bytes)
+            .ok_or(Error::Incomplete)?;
+        position += length_bytes;
+        bytes
+            .iter()
+            .fold(0usize, |length, byte| (length << 8) | usize::from(*byte))
+    };
+    Ok(Header {
+        class,
+        constructed,
+        tag,
+        header_length: position,
+        content_length,
+    })
+}
+
+/// The total size of the element starting at `data`, from its header only.
+pub fn element_length(data: &[u8]) -> Result<usize> {
+    let header = read_header(data)?;
+    header
+        .header_length
+        .checked_add(header.content_length)
+        .ok_or(Error::Invalid("Length too large"))
+}
+
+fn read_element_at_depth(data: &[u8], depth: usize) -> Result<(Element, usize)> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Invalid("Too many nested elements"));
+    }
+    let header = read_header(data)?;
+    let end = header
+        .header_length
+        .checked_add(header.content_length)
+        .ok_or(Error::Invalid("Length too large"))?;
+    let content = data
+        .get(header.header_length..end)
+        .ok_or(Error::Incomplete)?;
+    let payload = if header.constructed {
+        let mut children = Vec::new();
+        let mut position = 0;
+        while position < content.len() {
+            let (child, length) = read_element_at_depth(&content[position..], depth + 1)
+                // The parent is complete: a truncated child is malformed.
+                .map_err(|e| match e {
+                    Error::Incomplete => Error::Invalid("Truncated element"),
+                    e => e,
+                })?;
+            children.push(child);
+            position += length;
+        }
+        Payload::Constructed(children)
+    } else {
+        Payload::Primitive(content.to_vec())
+    };
+    Ok((
+        Element {
+            class: header.class,
+            tag: header.tag,
+            payload,
+        },
+        end,
+    ))
+}
+
+/// Reads the element at the start of `data`, and returns it with the number of bytes it took.
+pub fn read_element(data: &[u8]) -> Result<(Element, usize)> {
+    read_element_at_depth(data, 0)
+}
+
+/// Reads a buffer holding exactly one element, like the value of a control.
+pub fn parse(data: &[u8]) -> Result<Element> {
+    match read_element(data) {
+        Ok((element, length)) if length == data.len() => Ok(element),
+        Ok(_) => Err(Error::Invalid("Trailing data after the element")),
+        Err(Error::Incomplete) => Err(Error::Invalid("Truncated element")),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_length(length: usize, out: &mut Vec<u8>) {
+    if length < 0x80 {
+        out.push(length as u8);
+    } else {
+        let bytes = length.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+fn integer_bytes(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // The shortest two's complement form: drop the leading bytes that only extend the sign.
+    let mut skip = 0;
+    while skip < bytes.len() - 1
+        && ((bytes[skip] == 0 && bytes[skip + 1] & 0x80 == 0)
+            || (bytes[skip] == 0xff && bytes[skip + 1] & 0x80 != 0))
+    {
+        skip += 1;
+    }
+    bytes[skip..].to_vec()
+}
+
+impl Element {
+    pub fn primitive(class: TagClass, tag: u64, value: Vec<u8>) -> Self {
+        Self {
+            class,
+            tag,
+            payload: Payload::Primitive(value),
+        }
+    }
+
+    pub fn constructed(class: TagClass, tag: u64, children: Vec<Element>) -> Self {
+        Self {
+            class,
+            tag,
+            payload: Payload::Constructed(children),
+        }
+    }
+
+    pub fn boolean(value: bool) -> Self {
+        Self::primitive(
+            TagClass::Universal,
+            BOOLEAN,
+            vec![if value { 0xff } else { 0 }],
+        )
+    }
+
+    pub fn integer(value: i64) -> Self {
+        Self::primitive(TagClass::Universal, INTEGER, integer_bytes(value))
+    }
+
+    pub fn enumerated(value: i64) -> Self {
+        Self::primitive(TagClass::Universal, ENUMERATED, integer_bytes(value))
+    }
+
+    pub fn octet_string(value: impl Into<Vec<u8>>) -> Self {
+        Self::primitive(TagClass::Universal, OCTET_STRING, value.into())
+    }
+
+    pub fn sequence(children: Vec<Element>) -> Self {
+        Self::constructed(TagClass::Universal, SEQUENCE, children)
+    }
+
+    /// The same element with another tag, like the IMPLICIT tags of the ASN.1 modules.
+    pub fn with_tag(self, class: TagClass, tag: u64) -> Self {
+        Self { class, tag, ..self }
+    }
+
+    pub fn is(&self, class: TagClass, tag: u64) -> bool {
+        self.class == class && self.tag == tag
+    }
+
+    /// The content of a primitive element: the bytes of a string, of an integer, etc.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match &self.payload {
+            Payload::Primitive(value) => Some(value),
+            Payload::Constructed(_) => None,
+        }
+    }
+
+    pub fn children(&self) -> Option<&[Element]> {
+        match &self.payload {
+            Payload::Primitive(_) => None,
+            Payload::Constructed(children) => Some(children),
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<Element>> {
+        match &mut self.payload {
+            Payload::Primitive(_) => None,
+            Payload::Constructed(children) => Some(children),
+        }
+    }
+
+    /// The value of an INTEGER or ENUMERATED element, whatever its tag.
+    pub fn as_integer(&self) -> Option<i64> {
+        let bytes = self.as_bytes()?;
+        if bytes.is_empty() || bytes.len() > 8 {
+            return None;
+        }
+        let sign = if bytes[0] & 0x80 != 0 { -1i64 } else { 0 };
+        Some(
+            bytes
+                .iter()
+                .fold(sign, |value, byte| (value << 8) | i64::from(*byte)),
+        )
+    }
+
+    pub fn as_boolean(&self) -> Option<bool> {
+        match self.as_bytes()? {
+            [value] => Some(*value != 0),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(self.as_bytes()?).ok()
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let class = match self.class {
+            TagClass::Universal => 0,
+            TagClass::Application => 1,
+            TagClass::Context => 2,
+            TagClass::Private => 3,
+        };
+        let constructed = match self.payload {
+            Payload::Primitive(_) => 0,
+            Payload::Constructed(_) => 0x20,
+        };
+        if self.tag < 0x1f {
+            out.push((class << 6) | constructed | self.tag as u8);
+        } else {
+            out.push((class << 6) | constructed | 0x1f);
+            // Base 128, most significant group first.
+            let mut groups = vec![(self.tag & 0x7f) as u8];
+            let mut tag = self.tag >> 7;
+            while tag > 0 {
+                groups.push((tag & 0x7f) as u8);
+                tag >>= 7;
+            }
+            let last = groups.len() - 1;
+            for (i, group) in groups.into_iter().rev().enumerate() {
+                out.push(if i < last { group | 0x80 } else { group });
+            }
+        }
+        match &self.payload {
+            Payload::Primitive(value) => {
+                write_length(value.len(), out);
+                out.extend_from_slice(value);
+            }
+            Payload::Constructed(children) => {
+                let mut content = Vec::new();
+                for child in children {
+                    child.write(&mut content);
+                }
+                write_length(content.len(), out);
+                out.extend_from_slice(&content);
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_encoding() {
+        for (value, bytes) in [
+            (0i64, vec![0x02, 0x01, 0x00]),
+            (127, vec![0x02, 0x01, 0x7f]),
+            (128, vec![0x02, 0x02, 0x00, 0x80]),
+            (-1, vec![0x02, 0x01, 0xff]),
+            (-129, vec![0x02, 0x02, 0xff, 0x7f]),
+            (
+                i64::MAX,
+                vec![0x02, 0x08, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+        ] {
+            assert_eq!(Element::integer(value).to_bytes(), bytes);
+            assert_eq!(parse(&bytes).unwrap().as_integer(), Some(value));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let element = Element::sequence(vec![
+            Element::octet_string(vec![b'a'; 300]),
+            Element::boolean(true),
+            Element::constructed(TagClass::Context, 0, vec![Element::integer(5)]),
+            Element::primitive(TagClass::Context, 40, b"high tag".to_vec()),
+        ]);
+        let bytes = element.to_bytes();
+        // A long form length: 0x82 and two bytes.
+        assert_eq!(&bytes[..2], &[0x30, 0x82]);
+        assert_eq!(element_length(&bytes), Ok(bytes.len()));
+        assert_eq!(read_element(&bytes), Ok((element.clone(), bytes.len())));
+        assert_eq!(parse(&bytes), Ok(element));
+    }
+
+    #[test]
+    fn test_incomplete_and_invalid() {
+        let bytes = Element::sequence(vec![Element::integer(1)]).to_bytes();
+        assert_eq!(read_element(&bytes[..2]), Err(Error::Incomplete));
+        assert_eq!(parse(&bytes[..2]), Err(Error::Invalid("Truncated element")));
+        // A child claiming more bytes than its parent has.
+        assert_eq!(
+            read_element(&[0x30, 0x03, 0x02, 0x05, 0x01]),
+            Err(Error::Invalid("Truncated element"))
+        );
+        assert_eq!(
+            read_element(&[0x30, 0x80, 0x00, 0x00]),
+            Err(Error::Invalid("Indefinite lengths are not allowed"))
+        );
+        let mut nested = Element::integer(1);
+        for _ in 0..100 {
+            nested = Element::sequence(vec![nested]);
+        }
+        assert_eq!(
+            parse(&nested.to_bytes()),
+            Err(Error::Invalid("Too many nested elements"))
+        );
+    }
+}
@@ -0,0 +1,39 @@
+//! Helpers for the Cancel extended operation (RFC 3909).
+//!
+//! Only the searches are answered while other requests are read: the other operations are
+//! already over by the time a Cancel request for them is handled.
+
+use super::ber::{self, TagClass};
+
+pub const CANCEL_OID: &str = "1.3.6.1.1.8";
+
+/// The result codes of the Cancel operation, which `ldap3_proto` doesn't have: the cancelled
+/// operation ends with `CANCELED`.
+pub const CANCELED: i64 = 118;
+pub const NO_SUCH_OPERATION: i64 = 119;
+
+/// Parses the value of the request: `SEQUENCE { cancelID MessageID }`.
+pub fn parse_cancel_request(value: Option<&[u8]>) -> Option<i32> {
+    let value = ber::parse(value?).ok()?;
+    match value.children()? {
+        [cancel_id] if cancel_id.is(TagClass::Universal, ber::INTEGER) => {
+            i32::try_from(cancel_id.as_integer()?).ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ldap::ber::Element;
+
+    #[test]
+    fn test_parse_cancel_request() {
+        let value = Element::sequence(vec![Element::integer(3)]);
+        assert_eq!(parse_cancel_request(Some(&value.to_bytes())), Some(3));
+        let value = Element::sequence(vec![Element::octet_string("3")]);
+        assert_eq!(parse_cancel_request(Some(&value.to_bytes())), None);
+        assert_eq!(parse_cancel_request(None), None);
+    }
+}
@@ -0,0 +1,269 @@
+//! Parsing of the string representation of LDAP filters (RFC 4515), used to define the members
+//! of dynamic groups.
+//!
+//! Only the boolean operators, equality, presence and substrings are supported; approximate matches
+//! are treated as equality. Filters cannot refer to group membership, so that dynamic groups never
+//! depend on other groups.
+
+use crate::domain::{
+    handler::{SubStringFilter, UserRequestFilter},
+    ldap::utils::map_user_field,
+    types::{UserColumn, UserId},
+};
+
+/// The object classes of the users, in lowercase.
+const USER_OBJECT_CLASSES: &[&str] = &[
+    "person",
+    "inetorgperson",
+    "posixaccount",
+    "mailaccount",
+    "ldappublickey",
+];
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+/// Parses a filter like `(&(objectClass=person)(preferredLanguage=fr))` into a user filter.
+pub fn parse_user_filter(filter: &str) -> Result<UserRequestFilter, String> {
+    let mut chars = filter.trim().chars().peekable();
+    let result = parse_filter(&mut chars)?;
+    match chars.next() {
+        None => Ok(result),
+        Some(c) => Err(format!("Unexpected '{}' after the end of the filter", c)),
+    }
+}
+
+fn expect(chars: &mut Chars, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("Expected '{}', found '{}'", expected, c)),
+        None => Err(format!(
+            "Expected '{}', found the end of the filter",
+            expected
+        )),
+    }
+}
+
+fn parse_filter(chars: &mut Chars) -> Result<UserRequestFilter, String> {
+    expect(chars, '(')?;
+    let filter = match chars.peek() {
+        Some('&') => {
+            chars.next();
+            UserRequestFilter::And(parse_filter_list(chars)?)
+        }
+        Some('|') => {
+            chars.next();
+            UserRequestFilter::Or(parse_filter_list(chars)?)
+        }
+        Some('!') => {
+            chars.next();
+            UserRequestFilter::Not(Box::new(parse_filter(chars)?))
+        }
+        _ => parse_item(chars)?,
+    };
+    expect(chars, ')')?;
+    Ok(filter)
+}
+
+fn parse_filter_list(chars: &mut Chars) -> Result<Vec<UserRequestFilter>, String> {
+    let mut filters = Vec::new();
+    while chars.peek() == Some(&'(') {
+        filters.push(parse_filter(chars)?);
+    }
+    Ok(filters)
+}
+
+/// The value of an item of the filter.
+enum ItemValue {
+    /// `attr=*`
+    Present,
+    Equality(String),
+    /// `attr=initial*any*final`, each part being optional.
+    Substring(SubStringFilter),
+}
+
+fn to_utf8(bytes: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(bytes).map_err(|_| "Filter value is invalid UTF-8".to_string())
+}
+
+/// Parses the value of an item, up to the closing parenthesis.
+fn parse_value(chars: &mut Chars) -> Result<ItemValue, String> {
+    // The parts of the value between the wildcards.
+    let mut parts = vec![Vec::new()];
+    while let Some(&c) = chars.peek() {
+        match c {
+            ')' => break,
+            '(' => return Err("Unescaped '(' in a filter value".to_string()),
+            '*' => {
+                chars.next();
+                parts.push(Vec::new());
+            }
+            '\\' => {
+                chars.next();
+                let hex = chars
+                    .next()
+                    .into_iter()
+                    .chain(chars.next())
+                    .collect::<String>();
+                let byte = Some(&hex)
+                    .filter(|h| h.len() == 2 && h.chars().all(|c| c.is_ascii_hexdigit()))
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or_else(|| format!("Invalid escape sequence '\\{}'", hex))?;
+                parts.last_mut().unwrap().push(byte);
+            }
+            _ => {
+                chars.next();
+                let mut buffer = [0; 4];
+                parts
+                    .last_mut()
+                    .unwrap()
+                    .extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+    }
+    if parts.len() == 1 {
+        return Ok(ItemValue::Equality(to_utf8(parts.pop().unwrap())?));
+    }
+    let mut parts = parts
+        .into_iter()
+        .map(to_utf8)
+        .collect::<Result<Vec<_>, _>>()?;
+    if parts.iter().all(String::is_empty) && parts.len() == 2 {
+        return Ok(ItemValue::Present);
+    }
+    let non_empty = |part: String| Some(part).filter(|p| !p.is_empty());
+    let final_ = non_empty(parts.pop().unwrap());
+    let initial = non_empty(parts.remove(0));
+    Ok(ItemValue::Substring(SubStringFilter {
+        initial,
+        any: parts.into_iter().filter(|p| !p.is_empty()).collect(),
+        final_,
+    }))
+}
+
+fn parse_item(chars: &mut Chars) -> Result<UserRequestFilter, String> {
+    let mut attribute = String::new();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '=' => break,
+            '<' | '>' => return Err("Ordering filters are not supported".to_string()),
+            // Approximate matches (`attr~=value`) are treated as equality.
+            '~' => {
+                chars.next();
+                break;
+            }
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => {
+                chars.next();
+                attribute.push(c.to_ascii_lowercase());
+            }
+            c => return Err(format!("Unexpected '{}' in an attribute name", c)),
+        }
+    }
+    if attribute.is_empty() {
+        return Err("Missing attribute name".to_string());
+    }
+    expect(chars, '=')?;
+    let value = parse_value(chars)?;
+    match attribute.as_str() {
+        "memberof" => Err("Dynamic group filters cannot depend on group membership".to_string()),
+        "objectclass" => Ok(match value {
+            ItemValue::Present => UserRequestFilter::And(vec![]),
+            ItemValue::Equality(class)
+                if USER_OBJECT_CLASSES.contains(&class.to_ascii_lowercase().as_str()) =>
+            {
+                UserRequestFilter::And(vec![])
+            }
+            _ => UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+        }),
+        _ => match (map_user_field(&attribute), value) {
+            (None, _) => Err(format!("Unknown user attribute '{}'", attribute)),
+            (Some(_), ItemValue::Present) => Ok(UserRequestFilter::And(vec![])),
+            (Some(UserColumn::UserId), ItemValue::Equality(value)) => {
+                Ok(UserRequestFilter::UserId(UserId::new(&value)))
+            }
+            (Some(column), ItemValue::Equality(value)) => {
+                Ok(UserRequestFilter::Equality(column, value))
+            }
+            (Some(column), ItemValue::Substring(filter)) => {
+                Ok(UserRequestFilter::Substring(column, filter))
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_user_filter() {
+        let filter = "(&(objectClass=person)(|(mail=bob@bob.bob)(!(sn=B\\c3\\b6b)))(uid=Bob))";
+        assert_eq!(
+            parse_user_filter(filter),
+            Ok(UserRequestFilter::And(vec![
+                UserRequestFilter::And(vec![]),
+                UserRequestFilter::Or(vec![
+                    UserRequestFilter::Equality(UserColumn::Email, "bob@bob.bob".to_string()),
+                    UserRequestFilter::Not(Box::new(UserRequestFilter::Equality(
+                        UserColumn::LastName,
+                        "Böb".to_string()
+                    ))),
+                ]),
+                UserRequestFilter::UserId(UserId::new("Bob")),
+            ]))
+        );
+        assert_eq!(
+            parse_user_filter(" (givenName=*) "),
+            Ok(UserRequestFilter::And(vec![]))
+        );
+        assert_eq!(
+            parse_user_filter("(sn~=Smith)"),
+            Ok(UserRequestFilter::Equality(
+                UserColumn::LastName,
+                "Smith".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_user_filter_substrings() {
+        assert_eq!(
+            parse_user_filter("(mail=*@example.com)"),
+            Ok(UserRequestFilter::Substring(
+                UserColumn::Email,
+                SubStringFilter {
+                    final_: Some("@example.com".to_string()),
+                    ..Default::default()
+                }
+            ))
+        );
+        assert_eq!(
+            parse_user_filter("(givenName=jo**h\\2an*n*)"),
+            Ok(UserRequestFilter::Substring(
+                UserColumn::FirstName,
+                SubStringFilter {
+                    initial: Some("jo".to_string()),
+                    any: vec!["h*an".to_string(), "n".to_string()],
+                    final_: None,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_user_filter_errors() {
+        for filter in [
+            "",
+            "mail=bob@example.com",
+            "(mail=bob@example.com",
+            "(mail=bob@example.com))",
+            "(mail>=bob)",
+            "(cn~bob)",
+            "(department=Sales)",
+            "(memberOf=cn=admins,ou=groups,dc=example,dc=com)",
+            "(sn=\\zz)",
+            "(=bob)",
+        ] {
+            assert!(parse_user_filter(filter).is_err(), "{}", filter);
+        }
+    }
+}
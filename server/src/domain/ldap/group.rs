@@ -5,43 +5,79 @@ use tracing::{debug, info, instrument, warn};
 
 use crate::domain::{
     handler::{BackendHandler, GroupRequestFilter},
-    ldap::error::LdapError,
-    types::{Group, GroupColumn, UserId, Uuid},
+    ldap::{active_directory, error::LdapError},
+    types::{AttributeSchema, AttributeType, Group, GroupColumn, UserId, Uuid},
 };
 
 use super::{
     error::LdapResult,
     utils::{
-        expand_attribute_wildcards, get_user_id_from_distinguished_name, map_group_field, LdapInfo,
+        add_custom_attributes, escape_dn_value, expand_attribute_wildcards, find_custom_attribute,
+        get_custom_attribute, get_user_id_from_distinguished_name, map_group_field,
+        resolve_attribute_alias, to_custom_attribute_value, to_generalized_time,
+        to_substring_filter, LdapInfo, APPROX_MATCH_PREFIX,
     },
 };
 
+fn make_group_dn(display_name: &str, base_dn_str: &str) -> String {
+    format!(
+        "cn={},ou=groups,{}",
+        escape_dn_value(display_name),
+        base_dn_str
+    )
+}
+
 fn get_group_attribute(
     group: &Group,
     base_dn_str: &str,
     attribute: &str,
     user_filter: &Option<&UserId>,
     ignored_group_attributes: &[String],
+    active_directory: bool,
 ) -> Option<Vec<Vec<u8>>> {
     let attribute = attribute.to_ascii_lowercase();
+    if active_directory {
+        if let Some(values) = active_directory::get_group_attribute(group, &attribute) {
+            return Some(values);
+        }
+    }
     let attribute_values = match attribute.as_str() {
-        "objectclass" => vec![b"groupOfUniqueNames".to_vec()],
+        "objectclass" => {
+            let mut classes = vec![b"groupOfUniqueNames".to_vec()];
+            if group.gid_number.is_some() {
+                classes.push(b"posixGroup".to_vec());
+            }
+            if active_directory {
+                classes.push(b"group".to_vec());
+            }
+            classes
+        }
         // Always returned as part of the base response.
         "dn" | "distinguishedname" => return None,
         "cn" | "uid" => vec![group.display_name.clone().into_bytes()],
         "entryuuid" => vec![group.uuid.to_string().into_bytes()],
+        "entrydn" => vec![make_group_dn(&group.display_name, base_dn_str).into_bytes()],
+        "createtimestamp" => vec![to_generalized_time(&group.creation_date).into_bytes()],
+        "modifytimestamp" => vec![to_generalized_time(&group.modified_date).into_bytes()],
+        "gidnumber" => vec![group.gid_number?.to_string().into_bytes()],
+        "mail" => vec![group.email.clone()?.into_bytes()],
         "member" | "uniquemember" => group
             .users
             .iter()
             .filter(|u| user_filter.map(|f| *u == f).unwrap_or(true))
-            .map(|u| format!("uid={},ou=people,{}", u, base_dn_str).into_bytes())
+            .map(|u| {
+                format!(
+                    "uid={},ou=people,{}",
+                    escape_dn_value(u.as_str()),
+                    base_dn_str
+                )
+                .into_bytes()
+            })
             .collect(),
         "1.1" => return None,
-        // We ignore the operational attribute wildcard
-        "+" => return None,
-        "*" => {
+        "*" | "+" => {
             panic!(
-                "Matched {}, * should have been expanded into attribute list and * removed",
+                "Matched {}, wildcards should have been expanded into attribute list and removed",
                 attribute
             )
         }
@@ -70,30 +106,62 @@ const ALL_GROUP_ATTRIBUTE_KEYS: &[&str] = &[
     "member",
     "uniquemember",
     "entryuuid",
+    "gidNumber",
+    "mail",
 ];
 
+const ALL_GROUP_OPERATIONAL_ATTRIBUTE_KEYS: &[&str] =
+    &["entryuuid", "entrydn", "createtimestamp", "modifytimestamp"];
+
+/// Whether the name is taken by one of the built-in group attributes, which the custom attributes
+/// can't shadow.
+pub fn is_builtin_group_attribute(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ALL_GROUP_ATTRIBUTE_KEYS
+        .iter()
+        .chain(ALL_GROUP_OPERATIONAL_ATTRIBUTE_KEYS)
+        .chain(&["dn", "distinguishedname", "archived"])
+        .any(|key| key.eq_ignore_ascii_case(&name))
+        || map_group_field(&name).is_some()
+        || active_directory::is_group_attribute(&name)
+}
+
 fn make_ldap_search_group_result_entry(
     group: Group,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attributes: &[String],
     user_filter: &Option<&UserId>,
-    ignored_group_attributes: &[String],
+    schema: &[AttributeSchema],
 ) -> LdapSearchResultEntry {
-    let expanded_attributes = expand_attribute_wildcards(attributes, ALL_GROUP_ATTRIBUTE_KEYS);
+    let base_dn_str = &ldap_info.base_dn_str;
+    let mut expanded_attributes = expand_attribute_wildcards(
+        attributes,
+        ALL_GROUP_ATTRIBUTE_KEYS,
+        ALL_GROUP_OPERATIONAL_ATTRIBUTE_KEYS,
+    );
+    add_custom_attributes(&mut expanded_attributes, attributes, schema);
 
     LdapSearchResultEntry {
-        dn: format!("cn={},ou=groups,{}", group.display_name, base_dn_str),
+        dn: make_group_dn(&group.display_name, base_dn_str),
         attributes: expanded_attributes
             .iter()
             .filter_map(|a| {
-                let values = get_group_attribute(
-                    &group,
-                    base_dn_str,
-                    a,
-                    user_filter,
-                    ignored_group_attributes,
-                )?;
+                let attribute = resolve_attribute_alias(&ldap_info.group_attribute_aliases, a);
+                let values = match find_custom_attribute(schema, &attribute) {
+                    Some(attribute_schema) => {
+                        get_custom_attribute(&group.attributes, attribute_schema)
+                    }
+                    None => get_group_attribute(
+                        &group,
+                        base_dn_str,
+                        &attribute,
+                        user_filter,
+                        &ldap_info.ignored_group_attributes,
+                        ldap_info.active_directory_domain.is_some(),
+                    ),
+                }?;
                 Some(LdapPartialAttribute {
+                    // Aliased attributes are returned under the requested name.
                     atype: a.to_string(),
                     vals: values,
                 })
@@ -104,13 +172,41 @@ fn make_ldap_search_group_result_entry(
 
 fn convert_group_filter(
     ldap_info: &LdapInfo,
+    schema: &[AttributeSchema],
     filter: &LdapFilter,
 ) -> LdapResult<GroupRequestFilter> {
-    let rec = |f| convert_group_filter(ldap_info, f);
+    let rec = |f| convert_group_filter(ldap_info, schema, f);
     match filter {
+        // The group names have no phonetic matching: the approximate match is an equality.
+        LdapFilter::Equality(field, value) if field.starts_with(APPROX_MATCH_PREFIX) => {
+            rec(&LdapFilter::Equality(
+                field[APPROX_MATCH_PREFIX.len()..].to_string(),
+                value.clone(),
+            ))
+        }
         LdapFilter::Equality(field, value) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &resolve_attribute_alias(&ldap_info.group_attribute_aliases, field);
+            if let Some(attribute_schema) = find_custom_attribute(schema, field) {
+                return Ok(
+                    match to_custom_attribute_value(attribute_schema.attribute_type, value) {
+                        Some(value) => GroupRequestFilter::AttributeEquality(
+                            attribute_schema.name.clone(),
+                            value,
+                        ),
+                        None => {
+                            warn!(r#"Invalid value "{}" for "{}" in filter"#, value, field);
+                            GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![])))
+                        }
+                    },
+                );
+            }
             let value = &value.to_ascii_lowercase();
+            if ldap_info.active_directory_domain.is_some() {
+                if let Some(filter) = active_directory::convert_group_equality_filter(field, value)
+                {
+                    return Ok(filter);
+                }
+            }
             match field.as_str() {
                 "member" | "uniquemember" => {
                     let user_name = get_user_id_from_distinguished_name(
@@ -120,8 +216,11 @@ fn convert_group_filter(
                     )?;
                     Ok(GroupRequestFilter::Member(user_name))
                 }
+                "archived" => Ok(GroupRequestFilter::Archived(value == "true")),
                 "objectclass" => match value.as_str() {
-                    "groupofuniquenames" | "groupofnames" => Ok(GroupRequestFilter::And(vec![])),
+                    "groupofuniquenames" | "groupofnames" | "posixgroup" => {
+                        Ok(GroupRequestFilter::And(vec![]))
+                    }
                     _ => Ok(GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(
                         vec![],
                     )))),
@@ -136,6 +235,13 @@ fn convert_group_filter(
                             message: format!("Invalid UUID: {:#}", e),
                         })?,
                     )),
+                    Some(GroupColumn::Email) => Ok(GroupRequestFilter::Email(value.to_string())),
+                    Some(GroupColumn::GidNumber) => Ok(match value.parse() {
+                        Ok(gid) => GroupRequestFilter::GidNumber(gid),
+                        Err(_) => {
+                            GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![])))
+                        }
+                    }),
                     _ => {
                         if !ldap_info.ignored_group_attributes.contains(field) {
                             warn!(
@@ -158,12 +264,44 @@ fn convert_group_filter(
             filters.iter().map(rec).collect::<LdapResult<_>>()?,
         )),
         LdapFilter::Not(filter) => Ok(GroupRequestFilter::Not(Box::new(rec(filter)?))),
+        LdapFilter::Substring(field, substring_filter) => {
+            let field = &resolve_attribute_alias(&ldap_info.group_attribute_aliases, field);
+            if let Some(attribute_schema) = find_custom_attribute(schema, field) {
+                // Only the strings have a substring matching rule.
+                return Ok(
+                    if attribute_schema.attribute_type == AttributeType::String {
+                        GroupRequestFilter::AttributeSubstring(
+                            attribute_schema.name.clone(),
+                            to_substring_filter(substring_filter),
+                        )
+                    } else {
+                        GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![])))
+                    },
+                );
+            }
+            match map_group_field(field) {
+                Some(GroupColumn::DisplayName) => Ok(GroupRequestFilter::DisplayNameSubstring(
+                    to_substring_filter(substring_filter),
+                )),
+                _ => Ok(GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(
+                    vec![],
+                )))),
+            }
+        }
         LdapFilter::Present(field) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &resolve_attribute_alias(&ldap_info.group_attribute_aliases, field);
+            if let Some(attribute_schema) = find_custom_attribute(schema, field) {
+                return Ok(GroupRequestFilter::AttributePresent(
+                    attribute_schema.name.clone(),
+                ));
+            }
             if field == "objectclass"
                 || field == "dn"
                 || field == "distinguishedname"
+                || field == "entrydn"
                 || map_group_field(field).is_some()
+                || (ldap_info.active_directory_domain.is_some()
+                    && active_directory::is_group_attribute(field))
             {
                 Ok(GroupRequestFilter::And(vec![]))
             } else {
@@ -172,6 +310,7 @@ fn convert_group_filter(
                 ))))
             }
         }
+        #[allow(unreachable_patterns)]
         _ => Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
             message: format!("Unsupported group filter: {:?}", filter),
@@ -179,6 +318,17 @@ fn convert_group_filter(
     }
 }
 
+fn mentions_archived(filter: &GroupRequestFilter) -> bool {
+    match filter {
+        GroupRequestFilter::Archived(_) => true,
+        GroupRequestFilter::And(fs) | GroupRequestFilter::Or(fs) => {
+            fs.iter().any(mentions_archived)
+        }
+        GroupRequestFilter::Not(f) => mentions_archived(f),
+        _ => false,
+    }
+}
+
 #[instrument(skip_all, level = "debug")]
 pub async fn get_groups_list<Backend: BackendHandler>(
     ldap_info: &LdapInfo,
@@ -189,7 +339,16 @@ pub async fn get_groups_list<Backend: BackendHandler>(
     backend: &mut Backend,
 ) -> LdapResult<Vec<LdapOp>> {
     debug!(?ldap_filter);
-    let filter = convert_group_filter(ldap_info, ldap_filter)?;
+    let schema = backend
+        .list_group_attribute_schema()
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::Other,
+            message: format!("Error while listing the custom attributes: {:#}", e),
+        })?;
+    let filter = convert_group_filter(ldap_info, &schema, ldap_filter)?;
+    // Archived groups are only returned when the filter explicitly asks about them.
+    let include_archived = mentions_archived(&filter);
     let parsed_filters = match user_filter {
         None => filter,
         Some(u) => {
@@ -208,13 +367,14 @@ pub async fn get_groups_list<Backend: BackendHandler>(
 
     Ok(groups
         .into_iter()
+        .filter(|g| include_archived || !g.archived)
         .map(|u| {
             LdapOp::SearchResultEntry(make_ldap_search_group_result_entry(
                 u,
-                &ldap_info.base_dn_str,
+                ldap_info,
                 attributes,
                 user_filter,
-                &ldap_info.ignored_group_attributes,
+                &schema,
             ))
         })
         .collect::<Vec<_>>())
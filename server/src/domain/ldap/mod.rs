@@ -1,4 +1,15 @@
+pub mod active_directory;
+pub mod ber;
+pub mod cancel;
 pub mod error;
+pub mod filter;
 pub mod group;
+pub mod paging;
+pub mod ppolicy;
+pub mod schema;
+pub mod sort;
+pub mod sync;
+pub mod transaction;
 pub mod user;
 pub mod utils;
+pub mod vlv;
@@ -0,0 +1,121 @@
+//! Helpers for the simple paged results control (RFC 2696).
+//!
+//! Each page runs the whole search again and returns a slice of it. The entries are sorted by
+//! DN and the cookie holds the last DN of the page, with a digest of the search so that it can't
+//! be used for another one: entries added or deleted between two pages don't shift the next one,
+//! unlike with an offset.
+
+use ldap3_proto::proto::LdapSearchRequest;
+use sha2::{Digest, Sha256};
+
+use super::ber::{self, Element, TagClass};
+
+pub const PAGED_RESULTS_OID: &str = "1.2.840.113556.1.4.319";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PagedResultsRequest {
+    /// The number of entries to return. Zero abandons the paged search.
+    pub size: usize,
+    /// Empty for the first page.
+    pub cookie: Vec<u8>,
+}
+
+/// Parses the value of the request control: `SEQUENCE { size INTEGER, cookie OCTET STRING }`.
+pub fn parse_paged_results_request(value: Option<&[u8]>) -> Option<PagedResultsRequest> {
+    let value = ber::parse(value?).ok()?;
+    match value.children()? {
+        [size, cookie]
+            if size.is(TagClass::Universal, ber::INTEGER)
+                && cookie.is(TagClass::Universal, ber::OCTET_STRING) =>
+        {
+            Some(PagedResultsRequest {
+                size: usize::try_from(size.as_integer()?).ok()?,
+                cookie: cookie.as_bytes()?.to_vec(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The value of the response control, with the total number of entries and the cookie of the
+/// next page, empty after the last one.
+pub fn make_paged_results_response(total: usize, cookie: Vec<u8>) -> Element {
+    Element::sequence(vec![
+        Element::integer(i64::try_from(total).unwrap_or(i64::MAX)),
+        Element::octet_string(cookie),
+    ])
+}
+
+fn get_search_digest(request: &LdapSearchRequest) -> String {
+    let hash = Sha256::digest(format!("{:?}", request).as_bytes());
+    format!("{:x}", hash)[..16].to_string()
+}
+
+/// The key the entries of a paged search are sorted by.
+pub fn get_page_key(dn: &str) -> String {
+    dn.to_ascii_lowercase()
+}
+
+pub fn make_page_cookie(request: &LdapSearchRequest, last_key: &str) -> Vec<u8> {
+    format!("{}:{}", get_search_digest(request), last_key).into_bytes()
+}
+
+/// Returns the key of the last entry of the previous page, or `None` if the cookie was issued
+/// for another search.
+pub fn parse_page_cookie(request: &LdapSearchRequest, cookie: &[u8]) -> Option<String> {
+    let (digest, last_key) = std::str::from_utf8(cookie).ok()?.split_once(':')?;
+    if digest != get_search_digest(request) {
+        return None;
+    }
+    Some(last_key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::{LdapDerefAliases, LdapFilter, LdapSearchScope};
+
+    fn make_search_request(filter: LdapFilter) -> LdapSearchRequest {
+        LdapSearchRequest {
+            base: "ou=people,dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Subtree,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter,
+            attrs: vec!["uid".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_parse_paged_results_request() {
+        let value = Element::sequence(vec![Element::integer(50), Element::octet_string("abc")]);
+        assert_eq!(
+            parse_paged_results_request(Some(&value.to_bytes())),
+            Some(PagedResultsRequest {
+                size: 50,
+                cookie: b"abc".to_vec(),
+            })
+        );
+        let negative = Element::sequence(vec![Element::integer(-1), Element::octet_string("")]);
+        assert_eq!(
+            parse_paged_results_request(Some(&negative.to_bytes())),
+            None
+        );
+        assert_eq!(parse_paged_results_request(None), None);
+    }
+
+    #[test]
+    fn test_page_cookie() {
+        let request = make_search_request(LdapFilter::Present("objectClass".to_string()));
+        let cookie = make_page_cookie(&request, "uid=bob,ou=people,dc=example,dc=com");
+        assert_eq!(
+            parse_page_cookie(&request, &cookie),
+            Some("uid=bob,ou=people,dc=example,dc=com".to_string())
+        );
+        let other_request = make_search_request(LdapFilter::Present("uid".to_string()));
+        assert_eq!(parse_page_cookie(&other_request, &cookie), None);
+        assert_eq!(parse_page_cookie(&request, b"garbage"), None);
+    }
+}
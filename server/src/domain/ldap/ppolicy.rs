@@ -0,0 +1,53 @@
+//! Helpers for the password policy controls (draft-behera-ldap-password-policy).
+//!
+//! Only the expiration of the passwords is reported, to the binds that send the request control:
+//! the grace logins left once the password expired, or the error when there are none left.
+
+use super::ber::{Element, TagClass};
+
+/// The same OID for the request control, which has no value, and the response control.
+pub const PASSWORD_POLICY_OID: &str = "1.3.6.1.4.1.42.2.27.8.5.1";
+
+/// The error of the response control when the password expired.
+const PASSWORD_EXPIRED: i64 = 0;
+
+/// The state of the password of a bind, when the response control has something to report.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PasswordPolicyState {
+    /// The password expired, and this many grace logins are left after the bind.
+    GraceLogins(u32),
+    /// The password expired with no grace login left: the bind is refused.
+    Expired,
+}
+
+/// The value of the response control: `SEQUENCE { warning [0] CHOICE { timeBeforeExpiration [0]
+/// INTEGER, graceAuthNsRemaining [1] INTEGER } OPTIONAL, error [1] ENUMERATED OPTIONAL }`.
+pub fn make_password_policy_response(state: PasswordPolicyState) -> Element {
+    Element::sequence(vec![match state {
+        PasswordPolicyState::GraceLogins(remaining) => Element::constructed(
+            TagClass::Context,
+            0,
+            vec![Element::integer(i64::from(remaining)).with_tag(TagClass::Context, 1)],
+        ),
+        PasswordPolicyState::Expired => {
+            Element::enumerated(PASSWORD_EXPIRED).with_tag(TagClass::Context, 1)
+        }
+    }])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_password_policy_response() {
+        assert_eq!(
+            make_password_policy_response(PasswordPolicyState::GraceLogins(2)).to_bytes(),
+            vec![0x30, 0x05, 0xa0, 0x03, 0x81, 0x01, 0x02]
+        );
+        assert_eq!(
+            make_password_policy_response(PasswordPolicyState::Expired).to_bytes(),
+            vec![0x30, 0x03, 0x81, 0x01, 0x00]
+        );
+    }
+}
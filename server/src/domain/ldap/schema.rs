@@ -0,0 +1,149 @@
+//! The subschema subentry (RFC 4512, section 4.2), advertised in the root DSE, which describes the
+//! attribute types and object classes of the entries to schema-aware clients.
+//!
+//! The definitions use the standard OIDs, but the required attributes are relaxed to what LLDAP
+//! guarantees: users always have a `uid`, groups a `cn`, and everything else is optional. The
+//! `mailAccount` class has no standard definition, so it is left out.
+
+use ldap3_proto::{LdapPartialAttribute, LdapSearchResultEntry};
+
+use super::utils::expand_attribute_wildcards;
+
+pub const SUBSCHEMA_DN: &str = "cn=Subschema";
+
+const ATTRIBUTE_TYPES: &[&str] = &[
+    "( 2.5.4.0 NAME 'objectClass' EQUALITY objectIdentifierMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.38 )",
+    "( 2.5.4.3 NAME ( 'cn' 'commonName' ) EQUALITY caseIgnoreMatch \
+     SUBSTR caseIgnoreSubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 )",
+    "( 2.5.4.4 NAME ( 'sn' 'surname' ) EQUALITY caseIgnoreMatch \
+     SUBSTR caseIgnoreSubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 )",
+    "( 2.5.4.42 NAME 'givenName' EQUALITY caseIgnoreMatch \
+     SUBSTR caseIgnoreSubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 )",
+    "( 2.5.4.11 NAME ( 'ou' 'organizationalUnitName' ) EQUALITY caseIgnoreMatch \
+     SUBSTR caseIgnoreSubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 )",
+    "( 0.9.2342.19200300.100.1.25 NAME ( 'dc' 'domainComponent' ) EQUALITY caseIgnoreIA5Match \
+     SUBSTR caseIgnoreIA5SubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.26 SINGLE-VALUE )",
+    "( 0.9.2342.19200300.100.1.1 NAME ( 'uid' 'userid' ) EQUALITY caseIgnoreMatch \
+     SUBSTR caseIgnoreSubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 )",
+    "( 0.9.2342.19200300.100.1.3 NAME ( 'mail' 'rfc822Mailbox' ) EQUALITY caseIgnoreIA5Match \
+     SUBSTR caseIgnoreIA5SubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.26 )",
+    "( 2.16.840.1.113730.3.1.241 NAME 'displayName' EQUALITY caseIgnoreMatch \
+     SUBSTR caseIgnoreSubstringsMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 SINGLE-VALUE )",
+    "( 0.9.2342.19200300.100.1.60 NAME 'jpegPhoto' SYNTAX 1.3.6.1.4.1.1466.115.121.1.28 )",
+    "( 2.16.840.1.113730.3.1.39 NAME 'preferredLanguage' EQUALITY caseIgnoreMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.15 SINGLE-VALUE )",
+    "( 1.3.6.1.1.1.1.0 NAME 'uidNumber' EQUALITY integerMatch ORDERING integerOrderingMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.27 SINGLE-VALUE )",
+    "( 1.3.6.1.1.1.1.1 NAME 'gidNumber' EQUALITY integerMatch ORDERING integerOrderingMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.27 SINGLE-VALUE )",
+    "( 1.3.6.1.1.1.1.3 NAME 'homeDirectory' EQUALITY caseExactIA5Match \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.26 SINGLE-VALUE )",
+    "( 1.3.6.1.1.1.1.4 NAME 'loginShell' EQUALITY caseExactIA5Match \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.26 SINGLE-VALUE )",
+    "( 1.3.6.1.4.1.24552.500.1.1.1.13 NAME 'sshPublicKey' EQUALITY octetStringMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.40 )",
+    "( 2.5.4.31 NAME 'member' EQUALITY distinguishedNameMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.12 )",
+    "( 2.5.4.50 NAME 'uniqueMember' EQUALITY uniqueMemberMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.34 )",
+    "( 1.2.840.113556.1.2.102 NAME 'memberOf' EQUALITY distinguishedNameMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.12 NO-USER-MODIFICATION USAGE dSAOperation )",
+    "( 1.3.6.1.1.16.4 NAME 'entryUUID' EQUALITY UUIDMatch ORDERING UUIDOrderingMatch \
+     SYNTAX 1.3.6.1.1.16.1 SINGLE-VALUE NO-USER-MODIFICATION USAGE directoryOperation )",
+    "( 2.5.18.1 NAME 'createTimestamp' EQUALITY generalizedTimeMatch \
+     ORDERING generalizedTimeOrderingMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.24 \
+     SINGLE-VALUE NO-USER-MODIFICATION USAGE directoryOperation )",
+    "( 2.5.18.2 NAME 'modifyTimestamp' EQUALITY generalizedTimeMatch \
+     ORDERING generalizedTimeOrderingMatch SYNTAX 1.3.6.1.4.1.1466.115.121.1.24 \
+     SINGLE-VALUE NO-USER-MODIFICATION USAGE directoryOperation )",
+    "( 1.3.6.1.1.20 NAME 'entryDN' EQUALITY distinguishedNameMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.12 SINGLE-VALUE NO-USER-MODIFICATION \
+     USAGE directoryOperation )",
+    "( 2.5.18.10 NAME 'subschemaSubentry' EQUALITY distinguishedNameMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.12 SINGLE-VALUE NO-USER-MODIFICATION \
+     USAGE directoryOperation )",
+    "( 2.5.21.5 NAME 'attributeTypes' EQUALITY objectIdentifierFirstComponentMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.3 USAGE directoryOperation )",
+    "( 2.5.21.6 NAME 'objectClasses' EQUALITY objectIdentifierFirstComponentMatch \
+     SYNTAX 1.3.6.1.4.1.1466.115.121.1.37 USAGE directoryOperation )",
+];
+
+const OBJECT_CLASSES: &[&str] = &[
+    "( 2.5.6.0 NAME 'top' ABSTRACT MUST objectClass )",
+    "( 0.9.2342.19200300.100.4.13 NAME 'domain' SUP top STRUCTURAL MUST dc )",
+    "( 2.5.6.5 NAME 'organizationalUnit' SUP top STRUCTURAL MUST ou )",
+    "( 2.5.6.6 NAME 'person' SUP top STRUCTURAL MAY ( cn $ sn ) )",
+    "( 2.5.6.7 NAME 'organizationalPerson' SUP person STRUCTURAL )",
+    "( 2.16.840.1.113730.3.2.2 NAME 'inetOrgPerson' SUP organizationalPerson STRUCTURAL \
+     MUST uid MAY ( displayName $ givenName $ jpegPhoto $ mail $ preferredLanguage ) )",
+    "( 1.3.6.1.1.1.2.0 NAME 'posixAccount' SUP top AUXILIARY MUST uid \
+     MAY ( cn $ uidNumber $ gidNumber $ homeDirectory $ loginShell ) )",
+    "( 1.3.6.1.4.1.24552.500.1.1.2.0 NAME 'ldapPublicKey' SUP top AUXILIARY \
+     MUST uid MAY sshPublicKey )",
+    "( 2.5.6.17 NAME 'groupOfUniqueNames' SUP top STRUCTURAL MUST cn \
+     MAY ( uid $ mail $ member $ uniqueMember ) )",
+    "( 1.3.6.1.1.1.2.2 NAME 'posixGroup' SUP top AUXILIARY MUST ( cn $ gidNumber ) )",
+    "( 2.5.17.0 NAME 'subentry' SUP top STRUCTURAL MUST cn )",
+    "( 2.5.20.1 NAME 'subschema' AUXILIARY MAY ( attributeTypes $ objectClasses ) )",
+];
+
+const ALL_SUBSCHEMA_ATTRIBUTE_KEYS: &[&str] = &["objectClass", "cn"];
+
+const ALL_SUBSCHEMA_OPERATIONAL_ATTRIBUTE_KEYS: &[&str] = &["attributeTypes", "objectClasses"];
+
+fn to_values(definitions: &[&str]) -> Vec<Vec<u8>> {
+    definitions.iter().map(|d| d.as_bytes().to_vec()).collect()
+}
+
+/// Like for the other entries, the definitions are operational attributes: they are only returned
+/// if requested by name or with `+`.
+pub fn make_subschema_entry(attributes: &[String]) -> LdapSearchResultEntry {
+    let attributes = expand_attribute_wildcards(
+        attributes,
+        ALL_SUBSCHEMA_ATTRIBUTE_KEYS,
+        ALL_SUBSCHEMA_OPERATIONAL_ATTRIBUTE_KEYS,
+    );
+    LdapSearchResultEntry {
+        dn: SUBSCHEMA_DN.to_string(),
+        attributes: attributes
+            .into_iter()
+            .filter_map(|attribute| {
+                let vals = match attribute.to_ascii_lowercase().as_str() {
+                    "objectclass" => {
+                        vec![b"top".to_vec(), b"subentry".to_vec(), b"subschema".to_vec()]
+                    }
+                    "cn" => vec![b"Subschema".to_vec()],
+                    "attributetypes" => to_values(ATTRIBUTE_TYPES),
+                    "objectclasses" => to_values(OBJECT_CLASSES),
+                    _ => return None,
+                };
+                Some(LdapPartialAttribute {
+                    atype: attribute.to_string(),
+                    vals,
+                })
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definitions_are_well_formed() {
+        for definition in to_values(ATTRIBUTE_TYPES)
+            .into_iter()
+            .chain(to_values(OBJECT_CLASSES))
+        {
+            let definition = String::from_utf8(definition).unwrap();
+            assert!(
+                definition.starts_with("( ") && definition.ends_with(" )"),
+                "{}",
+                definition
+            );
+            assert!(!definition.contains("  "), "{}", definition);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Helpers for the server-side sorting controls (RFC 2891).
+//!
+//! Only the user searches can be sorted: the sort keys are mapped to user columns and pushed
+//! down to the SQL query.
+
+use super::{
+    ber::{self, Element, TagClass},
+    utils::map_user_field,
+};
+use crate::domain::{handler::SortKey, types::UserColumn};
+
+pub const SORT_REQUEST_OID: &str = "1.2.840.113556.1.4.473";
+pub const SORT_RESPONSE_OID: &str = "1.2.840.113556.1.4.474";
+
+/// The result codes of the sort response control that we use.
+pub const SORT_SUCCESS: i64 = 0;
+pub const SORT_NO_SUCH_ATTRIBUTE: i64 = 16;
+pub const SORT_INAPPROPRIATE_MATCHING: i64 = 18;
+pub const SORT_UNWILLING_TO_PERFORM: i64 = 53;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SortRequestKey {
+    pub attribute: String,
+    pub ordering_rule: Option<String>,
+    pub reverse: bool,
+}
+
+/// Parses the value of the request control: `SEQUENCE OF SEQUENCE { attributeType OCTET STRING,
+/// orderingRule [0] OPTIONAL, reverseOrder [1] BOOLEAN DEFAULT FALSE }`.
+pub fn parse_sort_request(value: Option<&[u8]>) -> Option<Vec<SortRequestKey>> {
+    let value = ber::parse(value?).ok()?;
+    let keys = value
+        .children()?
+        .iter()
+        .map(|key| {
+            let (attribute, rest) = key.children()?.split_first()?;
+            if !attribute.is(TagClass::Universal, ber::OCTET_STRING) {
+                return None;
+            }
+            let mut key = SortRequestKey {
+                attribute: attribute.as_str()?.to_string(),
+                ordering_rule: None,
+                reverse: false,
+            };
+            for field in rest {
+                if field.is(TagClass::Context, 0) {
+                    key.ordering_rule = Some(field.as_str()?.to_string());
+                } else if field.is(TagClass::Context, 1) {
+                    key.reverse = field.as_boolean()?;
+                } else {
+                    return None;
+                }
+            }
+            Some(key)
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if keys.is_empty() {
+        return None;
+    }
+    Some(keys)
+}
+
+/// The value of the response control: `SEQUENCE { sortResult ENUMERATED, attributeType [0]
+/// OPTIONAL }`, the attribute being the one that couldn't be sorted by, if any.
+pub fn make_sort_response(code: i64, attribute: Option<&str>) -> Element {
+    let mut fields = vec![Element::enumerated(code)];
+    if let Some(attribute) = attribute {
+        fields.push(Element::primitive(
+            TagClass::Context,
+            0,
+            attribute.as_bytes().to_vec(),
+        ));
+    }
+    Element::sequence(fields)
+}
+
+/// Maps the requested keys to user columns. On failure, returns the sort result code and the
+/// offending attribute.
+pub fn get_user_sort_keys(
+    keys: &[SortRequestKey],
+) -> Result<Vec<SortKey<UserColumn>>, (i64, String)> {
+    keys.iter()
+        .map(|key| {
+            // We only know the default ordering of each attribute.
+            if key.ordering_rule.is_some() {
+                return Err((SORT_INAPPROPRIATE_MATCHING, key.attribute.clone()));
+            }
+            match map_user_field(&key.attribute.to_ascii_lowercase()) {
+                Some(UserColumn::Avatar) | None => {
+                    Err((SORT_NO_SUCH_ATTRIBUTE, key.attribute.clone()))
+                }
+                Some(column) => Ok(SortKey {
+                    column,
+                    descending: key.reverse,
+                }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_key(attribute: &str, reverse: bool) -> Element {
+        let mut fields = vec![Element::octet_string(attribute)];
+        if reverse {
+            fields.push(Element::primitive(TagClass::Context, 1, vec![0xFF]));
+        }
+        Element::sequence(fields)
+    }
+
+    #[test]
+    fn test_parse_sort_request() {
+        let value = Element::sequence(vec![make_key("cn", true), make_key("uid", false)]);
+        assert_eq!(
+            parse_sort_request(Some(&value.to_bytes())),
+            Some(vec![
+                SortRequestKey {
+                    attribute: "cn".to_string(),
+                    ordering_rule: None,
+                    reverse: true,
+                },
+                SortRequestKey {
+                    attribute: "uid".to_string(),
+                    ordering_rule: None,
+                    reverse: false,
+                },
+            ])
+        );
+        assert_eq!(
+            parse_sort_request(Some(&Element::sequence(vec![]).to_bytes())),
+            None
+        );
+        assert_eq!(parse_sort_request(None), None);
+    }
+
+    #[test]
+    fn test_get_user_sort_keys() {
+        let key = |attribute: &str, ordering_rule: Option<&str>| SortRequestKey {
+            attribute: attribute.to_string(),
+            ordering_rule: ordering_rule.map(str::to_string),
+            reverse: false,
+        };
+        assert_eq!(
+            get_user_sort_keys(&[key("createTimestamp", None)]),
+            Ok(vec![SortKey {
+                column: UserColumn::CreationDate,
+                descending: false,
+            }])
+        );
+        assert_eq!(
+            get_user_sort_keys(&[key("uid", None), key("description", None)]),
+            Err((SORT_NO_SUCH_ATTRIBUTE, "description".to_string()))
+        );
+        assert_eq!(
+            get_user_sort_keys(&[key("cn", Some("2.5.13.3"))]),
+            Err((SORT_INAPPROPRIATE_MATCHING, "cn".to_string()))
+        );
+    }
+}
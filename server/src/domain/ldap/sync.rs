@@ -0,0 +1,134 @@
+//! Helpers for the content synchronization provider (syncrepl, RFC 4533).
+//!
+//! Rather than tracking every write that can affect an entry (a membership change also changes
+//! the `memberOf` of the user, a user update can change the members of a dynamic group, etc.),
+//! each refresh computes a digest of the full content of every entry. The backend compares them
+//! with the stored ones and bumps the change sequence number of the entries that differ, which is
+//! what the cookie of the consumer refers to.
+
+use ldap3_proto::proto::LdapSearchResultEntry;
+use sha2::{Digest, Sha256};
+
+use crate::domain::types::Uuid;
+
+const COOKIE_PREFIX: &str = "lldap-csn=";
+
+pub fn make_cookie(change_sequence: i64) -> Vec<u8> {
+    format!("{}{}", COOKIE_PREFIX, change_sequence).into_bytes()
+}
+
+/// Returns the change sequence number of the cookie, or `None` if it wasn't issued by us.
+pub fn parse_cookie(cookie: &[u8]) -> Option<i64> {
+    std::str::from_utf8(cookie)
+        .ok()?
+        .strip_prefix(COOKIE_PREFIX)?
+        .parse()
+        .ok()
+}
+
+/// The `entryUUID` of the entry, if it was included in the attributes.
+pub fn get_entry_uuid(entry: &LdapSearchResultEntry) -> Option<Uuid> {
+    entry
+        .attributes
+        .iter()
+        .find(|a| a.atype.eq_ignore_ascii_case("entryuuid"))
+        .and_then(|a| a.vals.first())
+        .and_then(|v| std::str::from_utf8(v).ok())
+        .and_then(|v| Uuid::try_from(v).ok())
+}
+
+/// A digest of the DN and attributes of the entry, regardless of the order of the attributes and
+/// values.
+pub fn get_entry_digest(entry: &LdapSearchResultEntry) -> String {
+    let mut attributes = entry
+        .attributes
+        .iter()
+        .map(|a| {
+            let mut values = a.vals.iter().collect::<Vec<_>>();
+            values.sort();
+            (a.atype.to_ascii_lowercase(), values)
+        })
+        .collect::<Vec<_>>();
+    attributes.sort();
+    let mut hasher = Sha256::new();
+    let mut add_field = |field: &[u8]| {
+        // Length-prefixed, so that the fields can't run into each other.
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field);
+    };
+    add_field(entry.dn.to_ascii_lowercase().as_bytes());
+    for (atype, values) in attributes {
+        add_field(atype.as_bytes());
+        add_field(&(values.len() as u64).to_be_bytes());
+        for value in values {
+            add_field(value);
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::LdapPartialAttribute;
+
+    fn make_entry(attributes: &[(&str, &[&str])]) -> LdapSearchResultEntry {
+        LdapSearchResultEntry {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(atype, vals)| LdapPartialAttribute {
+                    atype: atype.to_string(),
+                    vals: vals.iter().map(|v| v.as_bytes().to_vec()).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_cookie_round_trip() {
+        assert_eq!(parse_cookie(&make_cookie(42)), Some(42));
+        assert_eq!(parse_cookie(b"42"), None);
+        assert_eq!(parse_cookie(b"lldap-csn=forty-two"), None);
+        assert_eq!(parse_cookie(&[0xff, 0xfe]), None);
+    }
+
+    #[test]
+    fn test_get_entry_uuid() {
+        let entry = make_entry(&[
+            ("uid", &["bob"]),
+            ("entryUuid", &["a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"]),
+        ]);
+        assert_eq!(
+            get_entry_uuid(&entry),
+            Some(Uuid::try_from("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8").unwrap())
+        );
+        assert_eq!(get_entry_uuid(&make_entry(&[("uid", &["bob"])])), None);
+    }
+
+    #[test]
+    fn test_get_entry_digest() {
+        let digest = get_entry_digest(&make_entry(&[
+            ("uid", &["bob"]),
+            ("memberOf", &["cn=a", "cn=b"]),
+        ]));
+        assert_eq!(
+            get_entry_digest(&make_entry(&[
+                ("memberof", &["cn=b", "cn=a"]),
+                ("uid", &["bob"]),
+            ])),
+            digest
+        );
+        assert_ne!(
+            get_entry_digest(&make_entry(&[("uid", &["bob"]), ("memberOf", &["cn=a"])])),
+            digest
+        );
+        assert_ne!(
+            get_entry_digest(&make_entry(&[
+                ("uid", &["bob"]),
+                ("memberOf", &["cn=acn=b"]),
+            ])),
+            digest
+        );
+    }
+}
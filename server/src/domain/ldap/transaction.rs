@@ -0,0 +1,51 @@
+//! Helpers for the LDAP transactions (RFC 5805).
+//!
+//! The update requests of a transaction are only checked and queued when they're received: they
+//! are all applied when the transaction is committed, in a single database transaction.
+
+use super::ber::{self, TagClass};
+
+pub const START_TRANSACTION_OID: &str = "1.3.6.1.1.21.1";
+/// The control of the update requests, whose value is the identifier of their transaction.
+pub const TRANSACTION_SPECIFICATION_OID: &str = "1.3.6.1.1.21.2";
+pub const END_TRANSACTION_OID: &str = "1.3.6.1.1.21.3";
+
+/// Parses the value of the End Transaction request: `SEQUENCE { commit BOOLEAN DEFAULT TRUE,
+/// identifier OCTET STRING }`. Returns whether to commit, and the identifier.
+pub fn parse_end_transaction_request(value: Option<&[u8]>) -> Option<(bool, Vec<u8>)> {
+    let value = ber::parse(value?).ok()?;
+    let (commit, identifier) = match value.children()? {
+        [identifier] => (true, identifier),
+        [commit, identifier] if commit.is(TagClass::Universal, ber::BOOLEAN) => {
+            (commit.as_boolean()?, identifier)
+        }
+        _ => return None,
+    };
+    if !identifier.is(TagClass::Universal, ber::OCTET_STRING) {
+        return None;
+    }
+    Some((commit, identifier.as_bytes()?.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ldap::ber::Element;
+
+    #[test]
+    fn test_parse_end_transaction_request() {
+        let value = Element::sequence(vec![Element::octet_string("abc")]);
+        assert_eq!(
+            parse_end_transaction_request(Some(&value.to_bytes())),
+            Some((true, b"abc".to_vec()))
+        );
+        let value = Element::sequence(vec![Element::boolean(false), Element::octet_string("abc")]);
+        assert_eq!(
+            parse_end_transaction_request(Some(&value.to_bytes())),
+            Some((false, b"abc".to_vec()))
+        );
+        let value = Element::sequence(vec![Element::integer(1), Element::octet_string("abc")]);
+        assert_eq!(parse_end_transaction_request(Some(&value.to_bytes())), None);
+        assert_eq!(parse_end_transaction_request(None), None);
+    }
+}
@@ -4,58 +4,144 @@ use ldap3_proto::{
 use tracing::{debug, info, instrument, warn};
 
 use crate::domain::{
-    handler::{BackendHandler, UserRequestFilter},
-    ldap::{error::LdapError, utils::expand_attribute_wildcards},
-    types::{GroupDetails, User, UserColumn, UserId},
+    error::DomainError,
+    handler::{BackendHandler, GroupNesting, PageRequest, SortKey, UserRequestFilter},
+    ldap::{active_directory, error::LdapError, utils::expand_attribute_wildcards},
+    types::{
+        AttributeSchema, AttributeType, DateTime, GroupDetails, GroupId, PasswordExpiry, User,
+        UserAndGroups, UserColumn, UserId,
+    },
 };
+use std::collections::HashSet;
 
 use super::{
     error::LdapResult,
-    utils::{get_group_id_from_distinguished_name, map_user_field, LdapInfo},
+    utils::{
+        add_custom_attributes, escape_dn_value, find_custom_attribute, get_custom_attribute,
+        get_group_id_from_distinguished_name, map_user_field, parse_generalized_time,
+        resolve_attribute_alias, sounds_like, to_custom_attribute_value, to_generalized_time,
+        to_substring_filter, LdapInfo, APPROX_MATCH_PREFIX,
+    },
 };
 
+fn make_user_dn(user_id: &UserId, base_dn_str: &str) -> String {
+    format!(
+        "uid={},ou=people,{}",
+        escape_dn_value(user_id.as_str()),
+        base_dn_str
+    )
+}
+
+/// The shadowAccount attributes count in days since the epoch.
+fn days_since_epoch(date: &DateTime) -> i64 {
+    date.timestamp().div_euclid(24 * 3600)
+}
+
 fn get_user_attribute(
     user: &User,
     attribute: &str,
     base_dn_str: &str,
     groups: Option<&[GroupDetails]>,
     ignored_user_attributes: &[String],
+    active_directory_domain: Option<&str>,
+    password_expiry: Option<&PasswordExpiry>,
 ) -> Option<Vec<Vec<u8>>> {
     let attribute = attribute.to_ascii_lowercase();
+    if let Some(domain) = active_directory_domain {
+        if let Some(values) = active_directory::get_user_attribute(user, &attribute, domain) {
+            return Some(values);
+        }
+    }
     let attribute_values = match attribute.as_str() {
-        "objectclass" => vec![
-            b"inetOrgPerson".to_vec(),
-            b"posixAccount".to_vec(),
-            b"mailAccount".to_vec(),
-            b"person".to_vec(),
-        ],
+        "objectclass" => {
+            let mut classes = vec![
+                b"inetOrgPerson".to_vec(),
+                b"posixAccount".to_vec(),
+                b"mailAccount".to_vec(),
+                b"person".to_vec(),
+            ];
+            if !user.ssh_public_keys.is_empty() {
+                classes.push(b"ldapPublicKey".to_vec());
+            }
+            if active_directory_domain.is_some() {
+                classes.push(b"organizationalPerson".to_vec());
+                classes.push(b"user".to_vec());
+            }
+            classes
+        }
         // dn is always returned as part of the base response.
         "dn" | "distinguishedname" => return None,
         "uid" => vec![user.user_id.to_string().into_bytes()],
         "entryuuid" => vec![user.uuid.to_string().into_bytes()],
-        "mail" => vec![user.email.clone().into_bytes()],
+        "entrydn" => vec![make_user_dn(&user.user_id, base_dn_str).into_bytes()],
+        "mail" => std::iter::once(&user.email)
+            .chain(&user.email_aliases)
+            .map(|email| email.clone().into_bytes())
+            .collect(),
+        "mailalias" => user
+            .email_aliases
+            .iter()
+            .map(|email| email.clone().into_bytes())
+            .collect(),
         "givenname" => vec![user.first_name.clone()?.into_bytes()],
         "sn" => vec![user.last_name.clone()?.into_bytes()],
         "jpegphoto" => vec![user.avatar.clone()?.into_bytes()],
+        "preferredlanguage" => vec![user.preferred_language.clone()?.into_bytes()],
+        "uidnumber" => vec![user.uid_number?.to_string().into_bytes()],
+        "gidnumber" => vec![user.gid_number?.to_string().into_bytes()],
+        "homedirectory" => vec![user.home_directory.clone()?.into_bytes()],
+        "loginshell" => vec![user.login_shell.clone()?.into_bytes()],
+        "sshpublickey" => user
+            .ssh_public_keys
+            .iter()
+            .map(|key| key.clone().into_bytes())
+            .collect(),
         "memberof" => groups
             .into_iter()
             .flatten()
+            .filter(|g| !g.archived)
             .map(|id_and_name| {
                 format!(
                     "uid={},ou=groups,{}",
-                    &id_and_name.display_name, base_dn_str
+                    escape_dn_value(&id_and_name.display_name),
+                    base_dn_str
                 )
                 .into_bytes()
             })
             .collect(),
         "cn" | "displayname" => vec![user.display_name.clone()?.into_bytes()],
-        "createtimestamp" | "modifytimestamp" => vec![user.creation_date.to_rfc3339().into_bytes()],
+        "createtimestamp" => vec![to_generalized_time(&user.creation_date).into_bytes()],
+        "modifytimestamp" => vec![to_generalized_time(&user.modified_date).into_bytes()],
+        "shadowlastchange" => {
+            vec![days_since_epoch(&user.password_modified_date?)
+                .to_string()
+                .into_bytes()]
+        }
+        // Only for the users whose password can expire.
+        "shadowmax" => {
+            user.password_modified_date?;
+            vec![password_expiry?.max_age_days.to_string().into_bytes()]
+        }
+        "shadowwarning" => {
+            user.password_modified_date?;
+            vec![password_expiry?.warning_days.to_string().into_bytes()]
+        }
+        "shadowexpire" => {
+            vec![days_since_epoch(&user.expiration_date?)
+                .to_string()
+                .into_bytes()]
+        }
+        // Like 389 Directory Server, only set on the disabled accounts.
+        "nsaccountlock" => {
+            if user.enabled {
+                return None;
+            }
+            vec![b"TRUE".to_vec()]
+        }
         "1.1" => return None,
-        // We ignore the operational attribute wildcard.
-        "+" => return None,
-        "*" => {
+        "*" | "+" => {
             panic!(
-                "Matched {}, * should have been expanded into attribute list and * removed",
+                "Matched {}, wildcards should have been expanded into attribute list and removed",
                 attribute
             )
         }
@@ -70,42 +156,92 @@ fn get_user_attribute(
             return None;
         }
     };
-    if attribute_values.len() == 1 && attribute_values[0].is_empty() {
+    if attribute_values.is_empty()
+        || (attribute_values.len() == 1 && attribute_values[0].is_empty())
+    {
         None
     } else {
         Some(attribute_values)
     }
 }
 
+/// Whether the name is taken by one of the built-in user attributes, which the custom attributes
+/// can't shadow.
+pub fn is_builtin_user_attribute(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ALL_USER_ATTRIBUTE_KEYS
+        .iter()
+        .chain(ALL_USER_OPERATIONAL_ATTRIBUTE_KEYS)
+        .chain(&["dn", "distinguishedname"])
+        .any(|key| key.eq_ignore_ascii_case(&name))
+        || map_user_field(&name).is_some()
+        || active_directory::is_user_attribute(&name)
+}
+
 const ALL_USER_ATTRIBUTE_KEYS: &[&str] = &[
     "objectclass",
     "uid",
     "mail",
+    "mailAlias",
     "givenname",
     "sn",
     "cn",
     "jpegPhoto",
+    "preferredLanguage",
+    "uidNumber",
+    "gidNumber",
+    "homeDirectory",
+    "loginShell",
+    "sshPublicKey",
+    "shadowLastChange",
+    "shadowMax",
+    "shadowWarning",
+    "shadowExpire",
+    "createtimestamp",
+    "entryuuid",
+];
+
+const ALL_USER_OPERATIONAL_ATTRIBUTE_KEYS: &[&str] = &[
     "createtimestamp",
+    "modifytimestamp",
     "entryuuid",
+    "entrydn",
+    "memberof",
+    "nsAccountLock",
 ];
 
 fn make_ldap_search_user_result_entry(
     user: User,
-    base_dn_str: &str,
+    ldap_info: &LdapInfo,
     attributes: &[&str],
     groups: Option<&[GroupDetails]>,
-    ignored_user_attributes: &[String],
+    schema: &[AttributeSchema],
 ) -> LdapSearchResultEntry {
-    let dn = format!("uid={},ou=people,{}", user.user_id.as_str(), base_dn_str);
+    let base_dn_str = &ldap_info.base_dn_str;
+    let dn = make_user_dn(&user.user_id, base_dn_str);
 
     LdapSearchResultEntry {
         dn,
         attributes: attributes
             .iter()
             .filter_map(|a| {
-                let values =
-                    get_user_attribute(&user, a, base_dn_str, groups, ignored_user_attributes)?;
+                let attribute = resolve_attribute_alias(&ldap_info.user_attribute_aliases, a);
+                let values = match find_custom_attribute(schema, &attribute) {
+                    Some(attribute_schema) => {
+                        get_custom_attribute(&user.attributes, attribute_schema)
+                    }
+                    None => get_user_attribute(
+                        &user,
+                        &attribute,
+                        base_dn_str,
+                        groups,
+                        &ldap_info.ignored_user_attributes,
+                        ldap_info.active_directory_domain.as_deref(),
+                        ldap_info.password_expiry.as_ref(),
+                    ),
+                }?;
                 Some(LdapPartialAttribute {
+                    // Aliased attributes are returned under the requested name.
                     atype: a.to_string(),
                     vals: values,
                 })
@@ -114,8 +250,88 @@ fn make_ldap_search_user_result_entry(
     }
 }
 
-fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<UserRequestFilter> {
-    let rec = |f| convert_user_filter(ldap_info, f);
+/// The users whose name sounds like the value of an approximate match, for the attributes with
+/// a phonetic matching: the column, the value and the matching users.
+type PhoneticMatch = (UserColumn, String, Vec<UserId>);
+
+fn get_phonetic_column(ldap_info: &LdapInfo, field: &str) -> Option<UserColumn> {
+    let field = resolve_attribute_alias(&ldap_info.user_attribute_aliases, field);
+    match map_user_field(&field)? {
+        column @ (UserColumn::DisplayName | UserColumn::FirstName | UserColumn::LastName) => {
+            Some(column)
+        }
+        _ => None,
+    }
+}
+
+fn collect_phonetic_filters(
+    ldap_info: &LdapInfo,
+    filter: &LdapFilter,
+    filters: &mut Vec<(UserColumn, String)>,
+) {
+    match filter {
+        LdapFilter::And(fs) | LdapFilter::Or(fs) => fs
+            .iter()
+            .for_each(|f| collect_phonetic_filters(ldap_info, f, filters)),
+        LdapFilter::Not(f) => collect_phonetic_filters(ldap_info, f, filters),
+        LdapFilter::Equality(field, value) => {
+            if let Some(column) = field
+                .strip_prefix(APPROX_MATCH_PREFIX)
+                .and_then(|field| get_phonetic_column(ldap_info, field))
+            {
+                filters.push((column, value.clone()));
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Finds the users matching the approximate filters on names, comparing their Soundex codes.
+/// The matching can't be done in SQL, so all the users are read, once for the whole filter.
+async fn get_phonetic_matches<Backend: BackendHandler>(
+    ldap_info: &LdapInfo,
+    filter: &LdapFilter,
+    backend: &mut Backend,
+) -> LdapResult<Vec<PhoneticMatch>> {
+    let mut filters = Vec::new();
+    collect_phonetic_filters(ldap_info, filter, &mut filters);
+    if filters.is_empty() {
+        return Ok(Vec::new());
+    }
+    let users = backend
+        .list_users(None, false)
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::Other,
+            message: format!("Error while listing the users: {:#}", e),
+        })?;
+    Ok(filters
+        .into_iter()
+        .map(|(column, value)| {
+            let user_ids = users
+                .iter()
+                .filter(|u| {
+                    let name = match column {
+                        UserColumn::FirstName => u.user.first_name.as_deref(),
+                        UserColumn::LastName => u.user.last_name.as_deref(),
+                        _ => u.user.display_name.as_deref(),
+                    };
+                    name.map(|name| sounds_like(&value, name)).unwrap_or(false)
+                })
+                .map(|u| u.user.user_id.clone())
+                .collect();
+            (column, value, user_ids)
+        })
+        .collect())
+}
+
+fn convert_user_filter(
+    ldap_info: &LdapInfo,
+    schema: &[AttributeSchema],
+    phonetic_matches: &[PhoneticMatch],
+    filter: &LdapFilter,
+) -> LdapResult<UserRequestFilter> {
+    let rec = |f| convert_user_filter(ldap_info, schema, phonetic_matches, f);
     match filter {
         LdapFilter::And(filters) => Ok(UserRequestFilter::And(
             filters.iter().map(rec).collect::<LdapResult<_>>()?,
@@ -124,8 +340,51 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
             filters.iter().map(rec).collect::<LdapResult<_>>()?,
         )),
         LdapFilter::Not(filter) => Ok(UserRequestFilter::Not(Box::new(rec(filter)?))),
+        LdapFilter::Equality(field, value) if field.starts_with(APPROX_MATCH_PREFIX) => {
+            let field = &field[APPROX_MATCH_PREFIX.len()..];
+            let column = get_phonetic_column(ldap_info, field);
+            match phonetic_matches
+                .iter()
+                .find(|(c, v, _)| Some(*c) == column && v == value)
+            {
+                Some((_, _, user_ids)) if user_ids.is_empty() => Ok(UserRequestFilter::Not(
+                    Box::new(UserRequestFilter::And(vec![])),
+                )),
+                Some((_, _, user_ids)) => Ok(UserRequestFilter::Or(
+                    user_ids
+                        .iter()
+                        .cloned()
+                        .map(UserRequestFilter::UserId)
+                        .collect(),
+                )),
+                // No phonetic matching for the other attributes: it's an equality, which ignores
+                // the case for the text attributes.
+                None => rec(&LdapFilter::Equality(field.to_string(), value.clone())),
+            }
+        }
         LdapFilter::Equality(field, value) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &resolve_attribute_alias(&ldap_info.user_attribute_aliases, field);
+            if let Some(domain) = &ldap_info.active_directory_domain {
+                if let Some(filter) =
+                    active_directory::convert_user_equality_filter(field, value, domain)
+                {
+                    return Ok(filter);
+                }
+            }
+            if let Some(attribute_schema) = find_custom_attribute(schema, field) {
+                return Ok(
+                    match to_custom_attribute_value(attribute_schema.attribute_type, value) {
+                        Some(value) => UserRequestFilter::AttributeEquality(
+                            attribute_schema.name.clone(),
+                            value,
+                        ),
+                        None => {
+                            warn!(r#"Invalid value "{}" for "{}" in filter"#, value, field);
+                            UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![])))
+                        }
+                    },
+                );
+            }
             match field.as_str() {
                 "memberof" => {
                     let group_name = get_group_id_from_distinguished_name(
@@ -135,16 +394,37 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
                     )?;
                     Ok(UserRequestFilter::MemberOf(group_name))
                 }
+                "mailalias" => Ok(UserRequestFilter::EmailAlias(value.clone())),
+                "nsaccountlock" => Ok(UserRequestFilter::Enabled(
+                    !value.eq_ignore_ascii_case("true"),
+                )),
                 "objectclass" => match value.to_ascii_lowercase().as_str() {
-                    "person" | "inetorgperson" | "posixaccount" | "mailaccount" => {
-                        Ok(UserRequestFilter::And(vec![]))
-                    }
+                    "person" | "inetorgperson" | "posixaccount" | "mailaccount"
+                    | "ldappublickey" => Ok(UserRequestFilter::And(vec![])),
                     _ => Ok(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
                         vec![],
                     )))),
                 },
                 _ => match map_user_field(field) {
                     Some(UserColumn::UserId) => Ok(UserRequestFilter::UserId(UserId::new(value))),
+                    // Older versions returned RFC 3339 timestamps, which clients may still send.
+                    Some(column @ (UserColumn::CreationDate | UserColumn::ModifiedDate)) => {
+                        match parse_generalized_time(value).or_else(|| {
+                            chrono::DateTime::parse_from_rfc3339(value)
+                                .ok()
+                                .map(|t| t.with_timezone(&chrono::Utc))
+                        }) {
+                            Some(time) => {
+                                Ok(UserRequestFilter::Equality(column, time.to_rfc3339()))
+                            }
+                            None => {
+                                warn!(r#"Invalid GeneralizedTime "{}" in filter"#, value);
+                                Ok(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
+                                    vec![],
+                                ))))
+                            }
+                        }
+                    }
                     Some(field) => Ok(UserRequestFilter::Equality(field, value.clone())),
                     None => {
                         if !ldap_info.ignored_user_attributes.contains(field) {
@@ -161,13 +441,61 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
                 },
             }
         }
+        LdapFilter::Substring(field, substring_filter) => {
+            let field = &resolve_attribute_alias(&ldap_info.user_attribute_aliases, field);
+            if let Some(attribute_schema) = find_custom_attribute(schema, field) {
+                // Only the strings have a substring matching rule.
+                return Ok(
+                    if attribute_schema.attribute_type == AttributeType::String {
+                        UserRequestFilter::AttributeSubstring(
+                            attribute_schema.name.clone(),
+                            to_substring_filter(substring_filter),
+                        )
+                    } else {
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![])))
+                    },
+                );
+            }
+            match map_user_field(field) {
+                Some(
+                    column @ (UserColumn::UserId
+                    | UserColumn::Email
+                    | UserColumn::DisplayName
+                    | UserColumn::FirstName
+                    | UserColumn::LastName
+                    | UserColumn::PreferredLanguage
+                    | UserColumn::HomeDirectory
+                    | UserColumn::LoginShell
+                    | UserColumn::Uuid),
+                ) => Ok(UserRequestFilter::Substring(
+                    column,
+                    to_substring_filter(substring_filter),
+                )),
+                // The numbers, dates and photos have no substring matching rule: nothing matches.
+                _ => Ok(UserRequestFilter::Not(Box::new(UserRequestFilter::And(
+                    vec![],
+                )))),
+            }
+        }
         LdapFilter::Present(field) => {
-            let field = &field.to_ascii_lowercase();
+            let field = &resolve_attribute_alias(&ldap_info.user_attribute_aliases, field);
+            if let Some(attribute_schema) = find_custom_attribute(schema, field) {
+                return Ok(UserRequestFilter::AttributePresent(
+                    attribute_schema.name.clone(),
+                ));
+            }
+            if field == "nsaccountlock" {
+                return Ok(UserRequestFilter::Enabled(false));
+            }
             // Check that it's a field we support.
             if field == "objectclass"
                 || field == "dn"
                 || field == "distinguishedname"
+                || field == "entrydn"
+                || field == "mailalias"
                 || map_user_field(field).is_some()
+                || (ldap_info.active_directory_domain.is_some()
+                    && active_directory::is_user_attribute(field))
             {
                 Ok(UserRequestFilter::And(vec![]))
             } else {
@@ -176,6 +504,7 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
                 ))))
             }
         }
+        #[allow(unreachable_patterns)]
         _ => Err(LdapError {
             code: LdapResultCode::UnwillingToPerform,
             message: format!("Unsupported user filter: {:?}", filter),
@@ -183,6 +512,109 @@ fn convert_user_filter(ldap_info: &LdapInfo, filter: &LdapFilter) -> LdapResult<
     }
 }
 
+/// Whether the filter checks the status of the accounts, e.g. `(nsAccountLock=TRUE)`: that's how
+/// the admins and the read-only users list the disabled accounts, which are hidden otherwise.
+fn filters_on_account_status(filter: &UserRequestFilter) -> bool {
+    match filter {
+        UserRequestFilter::And(filters) | UserRequestFilter::Or(filters) => {
+            filters.iter().any(filters_on_account_status)
+        }
+        UserRequestFilter::Not(filter) => filters_on_account_status(filter),
+        UserRequestFilter::Enabled(_) => true,
+        _ => false,
+    }
+}
+
+/// Follows the nestings from the given groups for up to `max_depth` levels, and returns the groups
+/// reached on the way, excluding the starting ones.
+fn follow_nestings(
+    start: &[GroupId],
+    max_depth: u8,
+    next: impl Fn(GroupId) -> Vec<GroupId>,
+) -> Vec<GroupId> {
+    let mut seen = start.iter().copied().collect::<HashSet<_>>();
+    let mut current = start.to_vec();
+    let mut reached = Vec::new();
+    for _ in 0..max_depth {
+        current = current
+            .into_iter()
+            .flat_map(&next)
+            .filter(|g| seen.insert(*g))
+            .collect();
+        if current.is_empty() {
+            break;
+        }
+        reached.extend_from_slice(&current);
+    }
+    reached
+}
+
+/// Makes the `memberOf` filters also match the members of the groups nested in the group.
+fn expand_nested_groups(
+    filter: UserRequestFilter,
+    nestings: &[GroupNesting],
+    max_depth: u8,
+) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let rec = |f| expand_nested_groups(f, nestings, max_depth);
+    match filter {
+        And(fs) => And(fs.into_iter().map(rec).collect()),
+        Or(fs) => Or(fs.into_iter().map(rec).collect()),
+        Not(f) => Not(Box::new(rec(*f))),
+        MemberOf(name) => {
+            // Only the groups containing other groups are in the nestings.
+            let group_id = nestings
+                .iter()
+                .find(|n| n.parent_group.display_name == name)
+                .map(|n| n.parent_group.group_id);
+            match group_id {
+                None => MemberOf(name),
+                Some(group_id) => {
+                    let subgroups = follow_nestings(&[group_id], max_depth, |parent| {
+                        nestings
+                            .iter()
+                            .filter(|n| n.parent_group.group_id == parent)
+                            .map(|n| n.child_group_id)
+                            .collect()
+                    });
+                    Or(std::iter::once(MemberOf(name))
+                        .chain(subgroups.into_iter().map(MemberOfId))
+                        .collect())
+                }
+            }
+        }
+        f => f,
+    }
+}
+
+/// Adds the groups containing the groups of the user, directly or not.
+fn add_parent_groups(groups: &mut Vec<GroupDetails>, nestings: &[GroupNesting], max_depth: u8) {
+    let group_ids = groups.iter().map(|g| g.group_id).collect::<Vec<_>>();
+    let parent_groups = follow_nestings(&group_ids, max_depth, |child| {
+        nestings
+            .iter()
+            .filter(|n| n.child_group_id == child)
+            .map(|n| n.parent_group.group_id)
+            .collect()
+    });
+    for group_id in parent_groups {
+        if let Some(n) = nestings
+            .iter()
+            .find(|n| n.parent_group.group_id == group_id)
+        {
+            groups.push(n.parent_group.clone());
+        }
+    }
+}
+
+/// Which of the matching users a search returns.
+enum UserRange {
+    /// All of them, sorted by the given keys, or by user ID.
+    All(Vec<SortKey<UserColumn>>),
+    /// Only a page of them, fetched from the database without the others.
+    Page(PageRequest<UserColumn>),
+}
+
 #[instrument(skip_all, level = "debug")]
 pub async fn get_user_list<Backend: BackendHandler>(
     ldap_info: &LdapInfo,
@@ -190,10 +622,67 @@ pub async fn get_user_list<Backend: BackendHandler>(
     attributes: &[String],
     base: &str,
     user_filter: &Option<&UserId>,
+    sort_keys: &[SortKey<UserColumn>],
     backend: &mut Backend,
 ) -> LdapResult<Vec<LdapOp>> {
+    let range = UserRange::All(sort_keys.to_vec());
+    search_users(
+        ldap_info,
+        ldap_filter,
+        attributes,
+        base,
+        user_filter,
+        range,
+        backend,
+    )
+    .await
+    .map(|(entries, _)| entries)
+}
+
+/// Like [`get_user_list`], for a single page: also returns the number of matching users.
+#[instrument(skip_all, level = "debug")]
+pub async fn get_user_page<Backend: BackendHandler>(
+    ldap_info: &LdapInfo,
+    ldap_filter: &LdapFilter,
+    attributes: &[String],
+    base: &str,
+    user_filter: &Option<&UserId>,
+    page: PageRequest<UserColumn>,
+    backend: &mut Backend,
+) -> LdapResult<(Vec<LdapOp>, u64)> {
+    let range = UserRange::Page(page);
+    search_users(
+        ldap_info,
+        ldap_filter,
+        attributes,
+        base,
+        user_filter,
+        range,
+        backend,
+    )
+    .await
+}
+
+async fn search_users<Backend: BackendHandler>(
+    ldap_info: &LdapInfo,
+    ldap_filter: &LdapFilter,
+    attributes: &[String],
+    base: &str,
+    user_filter: &Option<&UserId>,
+    range: UserRange,
+    backend: &mut Backend,
+) -> LdapResult<(Vec<LdapOp>, u64)> {
     debug!(?ldap_filter);
-    let filters = convert_user_filter(ldap_info, ldap_filter)?;
+    let schema = backend
+        .list_user_attribute_schema()
+        .await
+        .map_err(|e| LdapError {
+            code: LdapResultCode::Other,
+            message: format!("Error while listing the custom attributes: {:#}", e),
+        })?;
+    let phonetic_matches = get_phonetic_matches(ldap_info, ldap_filter, backend).await?;
+    let filters = convert_user_filter(ldap_info, &schema, &phonetic_matches, ldap_filter)?;
+    let include_disabled = user_filter.is_none() && filters_on_account_status(&filters);
     let parsed_filters = match user_filter {
         None => filters,
         Some(u) => {
@@ -202,28 +691,94 @@ pub async fn get_user_list<Backend: BackendHandler>(
         }
     };
     debug!(?parsed_filters);
-    let expanded_attributes = expand_attribute_wildcards(attributes, ALL_USER_ATTRIBUTE_KEYS);
+    let mut expanded_attributes = expand_attribute_wildcards(
+        attributes,
+        ALL_USER_ATTRIBUTE_KEYS,
+        ALL_USER_OPERATIONAL_ATTRIBUTE_KEYS,
+    );
+    add_custom_attributes(&mut expanded_attributes, attributes, &schema);
     let need_groups = expanded_attributes
         .iter()
-        .any(|s| s.to_ascii_lowercase() == "memberof");
-    let users = backend
-        .list_users(Some(parsed_filters), need_groups)
-        .await
-        .map_err(|e| LdapError {
+        .any(|s| resolve_attribute_alias(&ldap_info.user_attribute_aliases, s) == "memberof");
+    let max_depth = ldap_info.nested_groups_max_depth;
+    let nestings = if max_depth > 0 {
+        backend.list_group_nestings().await.map_err(|e| LdapError {
             code: LdapResultCode::Other,
-            message: format!(r#"Error while searching user "{}": {:#}"#, base, e),
-        })?;
+            message: format!("Error while listing the nested groups: {:#}", e),
+        })?
+    } else {
+        Vec::new()
+    };
+    let parsed_filters = expand_nested_groups(parsed_filters, &nestings, max_depth);
+    let search_error = |e: DomainError| LdapError {
+        code: LdapResultCode::Other,
+        message: format!(r#"Error while searching user "{}": {:#}"#, base, e),
+    };
+    let (mut users, total_count) = match range {
+        UserRange::All(sort_keys) => {
+            let users = if sort_keys.is_empty() {
+                backend.list_users(Some(parsed_filters), need_groups).await
+            } else {
+                backend
+                    .list_users_sorted(Some(parsed_filters), need_groups, sort_keys)
+                    .await
+            };
+            let mut users = users.map_err(search_error)?;
+            // The disabled accounts are hidden from the searches, so that they aren't mistaken
+            // for users who can log in, unless a privileged user asks for them.
+            if !include_disabled {
+                users.retain(|u| u.user.enabled);
+            }
+            let total_count = users.len() as u64;
+            (users, total_count)
+        }
+        UserRange::Page(page) => {
+            // The page has to be cut after hiding the disabled accounts.
+            let parsed_filters = if include_disabled {
+                parsed_filters
+            } else {
+                UserRequestFilter::And(vec![parsed_filters, UserRequestFilter::Enabled(true)])
+            };
+            let page = backend
+                .list_users_page(Some(parsed_filters), page)
+                .await
+                .map_err(search_error)?;
+            let mut users = Vec::with_capacity(page.items.len());
+            for user in page.items {
+                let groups = if need_groups {
+                    let mut groups = backend
+                        .get_user_groups(&user.user_id)
+                        .await
+                        .map_err(search_error)?
+                        .into_iter()
+                        .collect::<Vec<_>>();
+                    groups.sort_by_key(|g| g.group_id.0);
+                    Some(groups)
+                } else {
+                    None
+                };
+                users.push(UserAndGroups { user, groups });
+            }
+            (users, page.total_count)
+        }
+    };
+    if !nestings.is_empty() {
+        for groups in users.iter_mut().filter_map(|u| u.groups.as_mut()) {
+            add_parent_groups(groups, &nestings, max_depth);
+        }
+    }
 
-    Ok(users
+    let entries = users
         .into_iter()
         .map(|u| {
             LdapOp::SearchResultEntry(make_ldap_search_user_result_entry(
                 u.user,
-                &ldap_info.base_dn_str,
+                ldap_info,
                 &expanded_attributes,
                 u.groups.as_deref(),
-                &ldap_info.ignored_user_attributes,
+                &schema,
             ))
         })
-        .collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    Ok((entries, total_count))
 }
@@ -1,29 +1,234 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Timelike, Utc};
 use itertools::Itertools;
-use ldap3_proto::LdapResultCode;
+use ldap3_proto::{proto::LdapSubstringFilter, LdapFilter, LdapResultCode};
+use std::collections::HashMap;
 use tracing::{debug, instrument, warn};
 
 use crate::domain::{
+    handler::SubStringFilter,
     ldap::error::{LdapError, LdapResult},
-    types::{GroupColumn, UserColumn, UserId},
+    types::{
+        AttributeSchema, AttributeType, AttributeValue, GroupColumn, PasswordExpiry, UserColumn,
+        UserId,
+    },
 };
 
-fn make_dn_pair<I>(mut iter: I) -> LdapResult<(String, String)>
-where
-    I: Iterator<Item = String>,
-{
-    (|| {
-        let pair = (
-            iter.next().ok_or_else(|| "Empty DN element".to_string())?,
-            iter.next().ok_or_else(|| "Missing DN value".to_string())?,
-        );
-        if let Some(e) = iter.next() {
-            Err(format!(
-                r#"Too many elements in distinguished name: "{:?}", "{:?}", "{:?}""#,
-                pair.0, pair.1, e
-            ))
+/// The approximate matches are passed on by the codec as equality filters on the attribute with
+/// this prefix, which can't start an attribute description.
+pub const APPROX_MATCH_PREFIX: &str = "~";
+
+/// Formats a timestamp as an RFC 4517 GeneralizedTime in UTC, e.g. `20240131120000Z`.
+pub fn to_generalized_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%d%H%M%SZ").to_string()
+}
+
+/// Parses an RFC 4517 GeneralizedTime: `YYYYMMDDHH[MM[SS]][(.|,)fraction](Z|(+|-)HH[MM])`.
+/// Only fractions of a second are supported, not fractions of an hour or of a minute.
+pub fn parse_generalized_time(value: &str) -> Option<DateTime<Utc>> {
+    let (time, offset_seconds) = match value.strip_suffix('Z') {
+        Some(time) => (time, 0),
+        None => {
+            let sign_position = value.rfind(|c| c == '+' || c == '-')?;
+            let (time, offset) = value.split_at(sign_position);
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let offset = &offset[1..];
+            if !matches!(offset.len(), 2 | 4) || !offset.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let hours = offset[..2].parse::<i32>().ok()?;
+            let minutes = offset.get(2..).map_or(Ok(0), str::parse::<i32>).ok()?;
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            (time, sign * (hours * 3600 + minutes * 60))
+        }
+    };
+    let (digits, fraction) = match time.find(|c| c == '.' || c == ',') {
+        Some(position) => (&time[..position], Some(&time[position + 1..])),
+        None => (time, None),
+    };
+    if !digits.bytes().all(|b| b.is_ascii_digit())
+        || !matches!(
+            (digits.len(), fraction),
+            (10 | 12 | 14, None) | (14, Some(_))
+        )
+    {
+        return None;
+    }
+    let nanoseconds = match fraction {
+        None => 0,
+        Some(f) if !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()) => {
+            format!("{:0<9}", &f[..f.len().min(9)])
+                .parse::<u32>()
+                .ok()?
+        }
+        Some(_) => return None,
+    };
+    let time = NaiveDateTime::parse_from_str(&format!("{:0<14}", digits), "%Y%m%d%H%M%S")
+        .ok()?
+        .with_nanosecond(nanoseconds)?;
+    FixedOffset::east_opt(offset_seconds)?
+        .from_local_datetime(&time)
+        .single()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// The custom attribute exposed under the given LDAP name, if any.
+pub fn find_custom_attribute<'a>(
+    schema: &'a [AttributeSchema],
+    attribute: &str,
+) -> Option<&'a AttributeSchema> {
+    schema
+        .iter()
+        .find(|s| s.ldap_name.eq_ignore_ascii_case(attribute))
+}
+
+/// The values of a custom attribute, of a user or a group, in their LDAP format.
+pub fn get_custom_attribute(
+    attributes: &[AttributeValue],
+    schema: &AttributeSchema,
+) -> Option<Vec<Vec<u8>>> {
+    let values = attributes
+        .iter()
+        .find(|a| a.name == schema.name)?
+        .values
+        .iter()
+        .map(|value| match schema.attribute_type {
+            AttributeType::Binary => base64::decode(value).unwrap_or_default(),
+            AttributeType::Boolean => value.to_ascii_uppercase().into_bytes(),
+            // Dates are returned as GeneralizedTime, at midnight UTC.
+            AttributeType::Date => format!("{}000000Z", value.replace('-', "")).into_bytes(),
+            AttributeType::String | AttributeType::Integer => value.as_bytes().to_vec(),
+        })
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        None
+    } else {
+        Some(values)
+    }
+}
+
+/// Converts a value of a filter to the format of the stored values.
+pub fn to_custom_attribute_value(attribute_type: AttributeType, value: &str) -> Option<String> {
+    match attribute_type {
+        AttributeType::Binary => Some(base64::encode(value)),
+        AttributeType::Date => parse_generalized_time(value)
+            .map(|time| time.format("%Y-%m-%d").to_string())
+            .or_else(|| attribute_type.normalize_value(value).ok()),
+        _ => attribute_type.normalize_value(value).ok(),
+    }
+}
+
+/// The custom attributes are part of the attributes returned for "*".
+pub fn add_custom_attributes<'a>(
+    expanded_attributes: &mut Vec<&'a str>,
+    attributes: &[String],
+    schema: &'a [AttributeSchema],
+) {
+    if attributes.is_empty() || attributes.iter().any(|a| a == "*") {
+        for attribute_schema in schema {
+            if !expanded_attributes
+                .iter()
+                .any(|a| a.eq_ignore_ascii_case(&attribute_schema.ldap_name))
+            {
+                expanded_attributes.push(&attribute_schema.ldap_name);
+            }
+        }
+    }
+}
+
+/// Escapes a value to be used in a relative distinguished name, as per RFC 4514 section 2.4.
+pub fn escape_dn_value(value: &str) -> String {
+    let char_count = value.chars().count();
+    let mut escaped = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '"' | '+' | ',' | ';' | '<' | '>' | '\\' | '=' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '#' if i == 0 => escaped.push_str("\\#"),
+            ' ' if i == 0 || i + 1 == char_count => escaped.push_str("\\ "),
+            '\0' => escaped.push_str("\\00"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Splits the string on the separator, except where it is escaped with a backslash.
+fn split_unescaped(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Removes the unescaped whitespace around an RDN value.
+fn trim_dn_value(value: &str) -> &str {
+    let mut value = value.trim_start();
+    while let Some(stripped) = value.strip_suffix(' ') {
+        let backslashes = stripped.chars().rev().take_while(|&c| c == '\\').count();
+        if backslashes % 2 == 1 {
+            // The space is escaped.
+            break;
+        }
+        value = stripped;
+    }
+    value
+}
+
+fn unescape_dn_value(value: &str) -> Result<String, String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let first = chars
+            .next()
+            .ok_or_else(|| format!(r#"Dangling escape character in DN value: "{}""#, value))?;
+        if first.is_ascii_hexdigit() {
+            let second = chars
+                .next()
+                .filter(char::is_ascii_hexdigit)
+                .ok_or_else(|| format!(r#"Invalid hex escape in DN value: "{}""#, value))?;
+            let hex = [first, second].iter().collect::<String>();
+            bytes.push(u8::from_str_radix(&hex, 16).unwrap());
         } else {
-            Ok(pair)
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(first.encode_utf8(&mut buf).as_bytes());
         }
+    }
+    String::from_utf8(bytes).map_err(|_| format!(r#"DN value is not valid UTF-8: "{}""#, value))
+}
+
+fn make_dn_pair(rdn: &str) -> LdapResult<(String, String)> {
+    (|| {
+        let (attribute, value) = rdn
+            .split_once('=')
+            .ok_or_else(|| "Missing DN value".to_string())?;
+        let attribute = attribute.trim();
+        if attribute.is_empty() {
+            return Err("Empty DN element".to_string());
+        }
+        Ok((
+            attribute.to_string(),
+            unescape_dn_value(trim_dn_value(value))?.to_ascii_lowercase(),
+        ))
     })()
     .map_err(|s| LdapError {
         code: LdapResultCode::InvalidDNSyntax,
@@ -31,10 +236,12 @@ where
     })
 }
 
+/// Parses an RFC 4514 distinguished name into its (attribute, value) pairs, unescaping the values.
 pub fn parse_distinguished_name(dn: &str) -> LdapResult<Vec<(String, String)>> {
     assert!(dn == dn.to_ascii_lowercase());
-    dn.split(',')
-        .map(|s| make_dn_pair(s.split('=').map(str::trim).map(String::from)))
+    split_unescaped(dn, ',')
+        .into_iter()
+        .map(make_dn_pair)
         .collect()
 }
 
@@ -88,10 +295,13 @@ pub fn get_group_id_from_distinguished_name(
     get_id_from_distinguished_name(dn, base_tree, base_dn_str, true)
 }
 
+/// Replaces `*` with all the user attributes and `+` with all the operational attributes, keeping
+/// the attributes that were explicitly requested alongside them.
 #[instrument(skip_all, level = "debug")]
 pub fn expand_attribute_wildcards<'a>(
     ldap_attributes: &'a [String],
     all_attribute_keys: &'a [&'static str],
+    all_operational_attribute_keys: &'a [&'static str],
 ) -> Vec<&'a str> {
     let mut attributes_out = ldap_attributes
         .iter()
@@ -104,6 +314,12 @@ pub fn expand_attribute_wildcards<'a>(
         // Splice in all non-operational attributes
         attributes_out.extend(all_attribute_keys.iter());
     }
+    if attributes_out.iter().any(|&x| x == "+") {
+        // Remove occurrences of '+'
+        attributes_out.retain(|&x| x != "+");
+        // Splice in all operational attributes
+        attributes_out.extend(all_operational_attribute_keys.iter());
+    }
 
     // Deduplicate, preserving order
     let resolved_attributes = attributes_out
@@ -144,10 +360,14 @@ pub fn map_user_field(field: &str) -> Option<UserColumn> {
         "givenname" | "first_name" => UserColumn::FirstName,
         "sn" | "last_name" => UserColumn::LastName,
         "avatar" => UserColumn::Avatar,
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
-            UserColumn::CreationDate
-        }
+        "preferredlanguage" | "preferred_language" => UserColumn::PreferredLanguage,
+        "creationdate" | "createtimestamp" | "creation_date" => UserColumn::CreationDate,
+        "modifytimestamp" | "modified_date" => UserColumn::ModifiedDate,
         "entryuuid" | "uuid" => UserColumn::Uuid,
+        "uidnumber" | "uid_number" => UserColumn::UidNumber,
+        "gidnumber" | "gid_number" => UserColumn::GidNumber,
+        "homedirectory" | "home_directory" => UserColumn::HomeDirectory,
+        "loginshell" | "login_shell" => UserColumn::LoginShell,
         _ => return None,
     })
 }
@@ -156,17 +376,211 @@ pub fn map_group_field(field: &str) -> Option<GroupColumn> {
     assert!(field == field.to_ascii_lowercase());
     Some(match field {
         "cn" | "displayname" | "uid" | "display_name" => GroupColumn::DisplayName,
-        "creationdate" | "createtimestamp" | "modifytimestamp" | "creation_date" => {
-            GroupColumn::CreationDate
-        }
+        "creationdate" | "createtimestamp" | "creation_date" => GroupColumn::CreationDate,
+        "modifytimestamp" | "modified_date" => GroupColumn::ModifiedDate,
         "entryuuid" | "uuid" => GroupColumn::Uuid,
+        "gidnumber" | "gid_number" => GroupColumn::GidNumber,
+        "mail" | "email" => GroupColumn::Email,
         _ => return None,
     })
 }
 
+pub fn to_substring_filter(filter: &LdapSubstringFilter) -> SubStringFilter {
+    SubStringFilter {
+        initial: filter.initial.clone(),
+        any: filter.any.clone(),
+        final_: filter.final_.clone(),
+    }
+}
+
+fn escape_filter_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '*' | '(' | ')' | '\\' | '\0' => escaped.push_str(&format!("\\{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The string representation of the filter (RFC 4515), for the logs.
+pub fn format_filter(filter: &LdapFilter) -> String {
+    let format_list = |filters: &[LdapFilter]| filters.iter().map(format_filter).join("");
+    match filter {
+        LdapFilter::And(filters) => format!("(&{})", format_list(filters)),
+        LdapFilter::Or(filters) => format!("(|{})", format_list(filters)),
+        LdapFilter::Not(filter) => format!("(!{})", format_filter(filter)),
+        LdapFilter::Equality(attribute, value) => match attribute.strip_prefix(APPROX_MATCH_PREFIX)
+        {
+            Some(attribute) => format!("({}~={})", attribute, escape_filter_value(value)),
+            None => format!("({}={})", attribute, escape_filter_value(value)),
+        },
+        LdapFilter::Substring(attribute, substring) => format!(
+            "({}={}*{}{})",
+            attribute,
+            substring
+                .initial
+                .as_deref()
+                .map(escape_filter_value)
+                .unwrap_or_default(),
+            substring
+                .any
+                .iter()
+                .map(|s| format!("{}*", escape_filter_value(s)))
+                .join(""),
+            substring
+                .final_
+                .as_deref()
+                .map(escape_filter_value)
+                .unwrap_or_default(),
+        ),
+        LdapFilter::Present(attribute) => format!("({}=*)", attribute),
+        #[allow(unreachable_patterns)]
+        filter => format!("{:?}", filter),
+    }
+}
+
+/// The American Soundex code of a word, e.g. "R163" for both "Robert" and "Rupert", or `None` if
+/// it has no ASCII letter.
+pub fn soundex(word: &str) -> Option<String> {
+    let code = |c: char| match c {
+        'b' | 'f' | 'p' | 'v' => Some('1'),
+        'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' => Some('2'),
+        'd' | 't' => Some('3'),
+        'l' => Some('4'),
+        'm' | 'n' => Some('5'),
+        'r' => Some('6'),
+        _ => None,
+    };
+    let mut letters = word
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let first = letters.next()?;
+    let mut result = first.to_ascii_uppercase().to_string();
+    let mut last = code(first);
+    for c in letters {
+        if result.len() == 4 {
+            break;
+        }
+        // Unlike the vowels, "h" and "w" don't separate two letters with the same code.
+        if c == 'h' || c == 'w' {
+            continue;
+        }
+        let current = code(c);
+        if let Some(digit) = current {
+            if current != last {
+                result.push(digit);
+            }
+        }
+        last = current;
+    }
+    Some(format!("{:0<4}", result))
+}
+
+/// Whether each word of `value` sounds like one of the words of `name`.
+pub fn sounds_like(value: &str, name: &str) -> bool {
+    let get_codes = |s: &str| {
+        s.split(|c: char| c.is_whitespace() || c == '-')
+            .filter_map(soundex)
+            .collect::<Vec<_>>()
+    };
+    let (value_codes, name_codes) = (get_codes(value), get_codes(name));
+    !value_codes.is_empty() && value_codes.iter().all(|code| name_codes.contains(code))
+}
+
+/// The attribute an alias from the `ldap_*_attribute_aliases` configuration stands for, or the
+/// attribute itself, in lowercase. The keys and values of the aliases must be in lowercase.
+pub fn resolve_attribute_alias(aliases: &HashMap<String, String>, attribute: &str) -> String {
+    let attribute = attribute.to_ascii_lowercase();
+    aliases.get(&attribute).cloned().unwrap_or(attribute)
+}
+
 pub struct LdapInfo {
     pub base_dn: Vec<(String, String)>,
     pub base_dn_str: String,
     pub ignored_user_attributes: Vec<String>,
     pub ignored_group_attributes: Vec<String>,
+    /// How many levels of group nesting to follow for `memberOf`.
+    pub nested_groups_max_depth: u8,
+    /// Extra names for the user attributes, in lowercase: alias -> attribute.
+    pub user_attribute_aliases: HashMap<String, String>,
+    pub group_attribute_aliases: HashMap<String, String>,
+    /// The domain name of the base DN, if the Active Directory attributes are served.
+    pub active_directory_domain: Option<String>,
+    /// For the shadowMax and shadowWarning of the users, if the passwords expire.
+    pub password_expiry: Option<PasswordExpiry>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_distinguished_name_escaped() {
+        assert_eq!(
+            parse_distinguished_name(r#"uid=a\,b\+c\"\\\ ,ou=people,dc=example,dc=com"#)
+                .expect("parsing failed"),
+            vec![
+                ("uid".to_string(), r#"a,b+c"\ "#.to_string()),
+                ("ou".to_string(), "people".to_string()),
+                ("dc".to_string(), "example".to_string()),
+                ("dc".to_string(), "com".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_distinguished_name(r"cn=\23\c3\a9t\c3\a9,dc=com").expect("parsing failed"),
+            vec![
+                ("cn".to_string(), "#été".to_string()),
+                ("dc".to_string(), "com".to_string()),
+            ]
+        );
+        assert!(parse_distinguished_name(r"uid=bob\,dc=com\").is_err());
+        assert!(parse_distinguished_name(r"uid=bob\4,dc=com").is_err());
+    }
+
+    #[test]
+    fn test_soundex() {
+        for (word, code) in [
+            ("Robert", "R163"),
+            ("Rupert", "R163"),
+            ("Smith", "S530"),
+            ("smyth", "S530"),
+            ("Tymczak", "T522"),
+            ("Pfister", "P236"),
+            ("Ashcraft", "A261"),
+            ("Lee", "L000"),
+        ] {
+            assert_eq!(soundex(word).as_deref(), Some(code), "{}", word);
+        }
+        assert_eq!(soundex("42"), None);
+        assert!(sounds_like("smyth", "John Smith"));
+        assert!(!sounds_like("smith", "John Doe"));
+    }
+
+    #[test]
+    fn test_escape_dn_value_round_trip() {
+        for value in [
+            "bob",
+            "a,b",
+            "a+b=c",
+            r#"quote"back\slash"#,
+            "<angle>;semi",
+            "#hash",
+            " leading and trailing ",
+            "inner # and  spaces",
+            "été",
+        ] {
+            let dn = format!("uid={},ou=people,dc=example,dc=com", escape_dn_value(value));
+            assert_eq!(
+                parse_distinguished_name(&dn).expect("parsing failed")[0].1,
+                value,
+                "DN: {}",
+                dn
+            );
+        }
+        assert_eq!(escape_dn_value(" #a,b "), r"\ #a\,b\ ");
+        assert_eq!(escape_dn_value("#a#"), r"\#a#");
+    }
 }
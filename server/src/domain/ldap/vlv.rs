@@ -0,0 +1,197 @@
+//! Helpers for the virtual list view controls (draft-ietf-ldapext-ldapv3-vlv).
+//!
+//! The list is the result of a sorted user search, and only the requested window of it is read
+//! from the database. The target can only be given by offset: looking for the first entry
+//! greater than a value would need comparisons that the filters don't have.
+
+use super::ber::{self, Element, TagClass};
+
+pub const VLV_REQUEST_OID: &str = "2.16.840.1.113730.3.4.9";
+pub const VLV_RESPONSE_OID: &str = "2.16.840.1.113730.3.4.10";
+
+/// The result codes of the response control that we use. The ones that aren't LDAP result codes
+/// are also set on the search result.
+pub const VLV_SUCCESS: i64 = 0;
+pub const VLV_INSUFFICIENT_ACCESS_RIGHTS: i64 = 50;
+pub const VLV_UNWILLING_TO_PERFORM: i64 = 53;
+pub const VLV_SORT_CONTROL_MISSING: i64 = 60;
+pub const VLV_OFFSET_RANGE_ERROR: i64 = 61;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VlvTarget {
+    /// The 1-based position of the target, relative to the client's estimate of the size of the
+    /// list, or absolute if the estimate is 0.
+    ByOffset {
+        offset: u64,
+        content_count: u64,
+    },
+    GreaterThanOrEqual(Vec<u8>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VlvRequest {
+    pub before_count: u64,
+    pub after_count: u64,
+    pub target: VlvTarget,
+}
+
+/// The window to read, and the 1-based position of the target in the list.
+#[derive(Debug, PartialEq, Eq)]
+pub struct VlvWindow {
+    pub offset: u64,
+    pub limit: u64,
+    pub target_position: u64,
+}
+
+fn as_count(element: &Element) -> Option<u64> {
+    if !element.is(TagClass::Universal, ber::INTEGER) {
+        return None;
+    }
+    u64::try_from(element.as_integer()?).ok()
+}
+
+/// Parses the value of the request control: `SEQUENCE { beforeCount INTEGER, afterCount INTEGER,
+/// target CHOICE { byOffset [0] SEQUENCE { offset INTEGER, contentCount INTEGER },
+/// greaterThanOrEqual [1] AssertionValue }, contextID OCTET STRING OPTIONAL }`.
+pub fn parse_vlv_request(value: Option<&[u8]>) -> Option<VlvRequest> {
+    let value = ber::parse(value?).ok()?;
+    let (before_count, after_count, target) = match value.children()? {
+        [before_count, after_count, target] | [before_count, after_count, target, _] => {
+            (before_count, after_count, target)
+        }
+        _ => return None,
+    };
+    let target = if target.is(TagClass::Context, 0) {
+        match target.children()? {
+            [offset, content_count] => VlvTarget::ByOffset {
+                offset: as_count(offset)?,
+                content_count: as_count(content_count)?,
+            },
+            _ => return None,
+        }
+    } else if target.is(TagClass::Context, 1) {
+        VlvTarget::GreaterThanOrEqual(target.as_bytes()?.to_vec())
+    } else {
+        return None;
+    };
+    Some(VlvRequest {
+        before_count: as_count(before_count)?,
+        after_count: as_count(after_count)?,
+        target,
+    })
+}
+
+/// The value of the response control: `SEQUENCE { targetPosition INTEGER, contentCount INTEGER,
+/// virtualListViewResult ENUMERATED }`.
+pub fn make_vlv_response(target_position: u64, content_count: u64, code: i64) -> Element {
+    let to_integer = |count: u64| Element::integer(i64::try_from(count).unwrap_or(i64::MAX));
+    Element::sequence(vec![
+        to_integer(target_position),
+        to_integer(content_count),
+        Element::enumerated(code),
+    ])
+}
+
+/// Places the target by offset in a list of `total` entries, and returns the window around it.
+/// Fails with `VLV_OFFSET_RANGE_ERROR` for an offset of 0.
+pub fn get_vlv_window(
+    offset: u64,
+    content_count: u64,
+    before_count: u64,
+    after_count: u64,
+    total: u64,
+) -> Result<VlvWindow, i64> {
+    if offset == 0 {
+        return Err(VLV_OFFSET_RANGE_ERROR);
+    }
+    if total == 0 {
+        return Ok(VlvWindow {
+            offset: 0,
+            limit: 0,
+            target_position: 0,
+        });
+    }
+    // The offset is scaled from the client's estimate of the size to the real one, so that the
+    // last entry stays the last one: offset 1 is the first entry and `content_count` the last.
+    let target_position = if content_count == 0 {
+        offset
+    } else if offset >= content_count {
+        total
+    } else {
+        1 + (offset - 1).saturating_mul(total) / content_count
+    }
+    .min(total);
+    let start = target_position.saturating_sub(before_count).max(1);
+    let end = target_position.saturating_add(after_count).min(total);
+    Ok(VlvWindow {
+        offset: start - 1,
+        limit: end - start + 1,
+        target_position,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_vlv_request() {
+        let value = Element::sequence(vec![
+            Element::integer(1),
+            Element::integer(20),
+            Element::constructed(
+                TagClass::Context,
+                0,
+                vec![Element::integer(100), Element::integer(0)],
+            ),
+        ]);
+        assert_eq!(
+            parse_vlv_request(Some(&value.to_bytes())),
+            Some(VlvRequest {
+                before_count: 1,
+                after_count: 20,
+                target: VlvTarget::ByOffset {
+                    offset: 100,
+                    content_count: 0,
+                },
+            })
+        );
+        let value = Element::sequence(vec![
+            Element::integer(0),
+            Element::integer(5),
+            Element::primitive(TagClass::Context, 1, b"bob".to_vec()),
+            Element::octet_string("context"),
+        ]);
+        assert_eq!(
+            parse_vlv_request(Some(&value.to_bytes())),
+            Some(VlvRequest {
+                before_count: 0,
+                after_count: 5,
+                target: VlvTarget::GreaterThanOrEqual(b"bob".to_vec()),
+            })
+        );
+        assert_eq!(parse_vlv_request(None), None);
+    }
+
+    #[test]
+    fn test_get_vlv_window() {
+        let window = |offset, limit, target_position| -> Result<VlvWindow, i64> {
+            Ok(VlvWindow {
+                offset,
+                limit,
+                target_position,
+            })
+        };
+        assert_eq!(get_vlv_window(1, 0, 0, 9, 1000), window(0, 10, 1));
+        assert_eq!(get_vlv_window(500, 0, 5, 5, 1000), window(494, 11, 500));
+        // The client thinks there are 100 entries: the middle of the list is the 501st.
+        assert_eq!(get_vlv_window(51, 100, 0, 0, 1000), window(500, 1, 501));
+        assert_eq!(get_vlv_window(100, 100, 2, 2, 1000), window(997, 3, 1000));
+        assert_eq!(get_vlv_window(5000, 0, 0, 10, 1000), window(999, 1, 1000));
+        assert_eq!(get_vlv_window(1, 0, 0, 10, 0), window(0, 0, 0));
+        assert_eq!(
+            get_vlv_window(0, 0, 0, 10, 1000),
+            Err(VLV_OFFSET_RANGE_ERROR)
+        );
+    }
+}
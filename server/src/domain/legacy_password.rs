@@ -0,0 +1,110 @@
+//! Password hashes imported from another directory or application, e.g. when migrating from
+//! OpenLDAP, for which the clear passwords are not known.
+//!
+//! The supported formats are bcrypt (`$2a$`, `$2b$`, `$2y$`), argon2 (`$argon2i$`, `$argon2d$`,
+//! `$argon2id$`), optionally with the `{CRYPT}` prefix of OpenLDAP, and the `{SHA}`, `{SSHA}`,
+//! `{SHA256}`, `{SSHA256}`, `{SHA512}` and `{SSHA512}` schemes of `userPassword`. The imported hash
+//! is only kept until the first successful bind of the user, which replaces it with an OPAQUE
+//! password file.
+
+use super::error::{DomainError, Result};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+fn invalid_hash(e: impl std::fmt::Display) -> DomainError {
+    DomainError::InternalError(format!("Invalid password hash: {}", e))
+}
+
+/// Whether the password hashes to `decoded`: the digest, followed by the salt for the salted
+/// schemes.
+fn digest_matches<D: Digest>(password: &str, decoded: &[u8], salted: bool) -> Result<bool> {
+    let size = D::output_size();
+    if decoded.len() < size || (!salted && decoded.len() != size) {
+        return Err(invalid_hash("wrong digest length"));
+    }
+    let (expected, salt) = decoded.split_at(size);
+    let mut hasher = D::new();
+    hasher.update(password.as_bytes());
+    hasher.update(salt);
+    Ok(orion::util::secure_cmp(&hasher.finalize(), expected).is_ok())
+}
+
+/// Checks the password against the imported hash. Fails if the hash is not in a supported format.
+pub fn verify(hash: &str, password: &str) -> Result<bool> {
+    let hash = hash.trim();
+    let hash = match hash.get(..7) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("{CRYPT}") => &hash[7..],
+        _ => hash,
+    };
+    if hash.starts_with("$2") {
+        return bcrypt::verify(password, hash).map_err(invalid_hash);
+    }
+    if hash.starts_with("$argon2") {
+        return argon2::verify_encoded(hash, password.as_bytes()).map_err(invalid_hash);
+    }
+    let (scheme, encoded) = hash
+        .strip_prefix('{')
+        .and_then(|h| h.split_once('}'))
+        .ok_or_else(|| invalid_hash("unknown format"))?;
+    let decoded = base64::decode(encoded)?;
+    match scheme.to_ascii_uppercase().as_str() {
+        "SHA" => digest_matches::<Sha1>(password, &decoded, false),
+        "SSHA" => digest_matches::<Sha1>(password, &decoded, true),
+        "SHA256" => digest_matches::<Sha256>(password, &decoded, false),
+        "SSHA256" => digest_matches::<Sha256>(password, &decoded, true),
+        "SHA512" => digest_matches::<Sha512>(password, &decoded, false),
+        "SSHA512" => digest_matches::<Sha512>(password, &decoded, true),
+        _ => Err(invalid_hash(format!("unsupported scheme {{{}}}", scheme))),
+    }
+}
+
+/// Fails if the hash is not in a supported format, before importing it.
+pub fn check_format(hash: &str) -> Result<()> {
+    verify(hash, "").map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bcrypt() {
+        // From the test vectors of crypt_blowfish.
+        let hash = "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW";
+        assert!(verify(hash, "U*U").unwrap());
+        assert!(!verify(hash, "U*V").unwrap());
+        assert!(verify(&format!("{{CRYPT}}{}", hash), "U*U").unwrap());
+    }
+
+    #[test]
+    fn test_argon2() {
+        let hash =
+            argon2::hash_encoded(b"password", b"somesalt", &argon2::Config::default()).unwrap();
+        assert!(verify(&hash, "password").unwrap());
+        assert!(!verify(&hash, "Password").unwrap());
+    }
+
+    #[test]
+    fn test_sha_schemes() {
+        for hash in [
+            "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=",
+            "{SSHA}N+vXsN2ny8mTqd6ZYuHcJVHvE00BAgMEBQYHCA==",
+            "{ssha256}JDUXfxQQU2uq0qzBVcD5R4PVg4RXPLD3IVdENgYoXT8BAgMEBQYHCA==",
+            "{SSHA512}cwNVhOuAAA1oPn+CL+wS1f0tPZracezN0yYU0xqYornXQBQ9qBELs44UJoU10/Tk7xHjUDA1r9zldr\
+             cPUqHBNAECAwQFBgcI",
+        ] {
+            assert!(verify(hash, "password").unwrap(), "{}", hash);
+            assert!(!verify(hash, "wrong").unwrap(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn test_invalid_formats() {
+        check_format("{SSHA}N+vXsN2ny8mTqd6ZYuHcJVHvE00BAgMEBQYHCA==").unwrap();
+        check_format("password").unwrap_err();
+        check_format("{MD5}X03MO1qnZdYdgyfeuILPmQ==").unwrap_err();
+        check_format("{SHA}not base64").unwrap_err();
+        check_format("{SHA}AAAA").unwrap_err();
+        check_format("$2a$05$short").unwrap_err();
+    }
+}
@@ -1,6 +1,7 @@
 pub mod error;
 pub mod handler;
 pub mod ldap;
+pub mod legacy_password;
 pub mod model;
 pub mod opaque_handler;
 pub mod sql_backend_handler;
@@ -9,4 +10,5 @@ pub mod sql_migrations;
 pub mod sql_opaque_handler;
 pub mod sql_tables;
 pub mod sql_user_backend_handler;
+pub mod totp;
 pub mod types;
@@ -13,6 +13,11 @@ pub struct Model {
     pub display_name: String,
     pub creation_date: chrono::DateTime<chrono::Utc>,
     pub uuid: Uuid,
+    pub archived: bool,
+    pub dynamic_filter: Option<String>,
+    pub gid_number: Option<i32>,
+    pub modified_date: chrono::DateTime<chrono::Utc>,
+    pub email: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -35,8 +40,14 @@ impl From<Model> for crate::domain::types::Group {
             id: group.group_id,
             display_name: group.display_name,
             creation_date: group.creation_date,
+            modified_date: group.modified_date,
             uuid: group.uuid,
+            archived: group.archived,
+            dynamic_filter: group.dynamic_filter,
+            gid_number: group.gid_number,
+            email: group.email,
             users: vec![],
+            attributes: vec![],
         }
     }
 }
@@ -48,6 +59,10 @@ impl From<Model> for crate::domain::types::GroupDetails {
             display_name: group.display_name,
             creation_date: group.creation_date,
             uuid: group.uuid,
+            archived: group.archived,
+            dynamic_filter: group.dynamic_filter,
+            gid_number: group.gid_number,
+            email: group.email,
         }
     }
 }
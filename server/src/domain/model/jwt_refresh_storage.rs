@@ -12,6 +12,10 @@ pub struct Model {
     pub refresh_token_hash: i64,
     pub user_id: UserId,
     pub expiry_date: chrono::DateTime<chrono::Utc>,
+    /// `None` for the sessions created before it was recorded, like the IP and the user agent.
+    pub creation_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -33,3 +37,18 @@ impl Related<super::users::Entity> for Entity {
 }
 
 impl ActiveModelBehavior for ActiveModel {}
+
+impl From<Model> for crate::domain::types::Session {
+    fn from(session: Model) -> Self {
+        Self {
+            session_id: session.refresh_token_hash,
+            user_id: session.user_id,
+            creation_date: session.creation_date,
+            expiry_date: session.expiry_date,
+            metadata: crate::domain::types::SessionMetadata {
+                ip_address: session.ip_address,
+                user_agent: session.user_agent,
+            },
+        }
+    }
+}
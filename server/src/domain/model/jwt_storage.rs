@@ -13,6 +13,7 @@ pub struct Model {
     pub user_id: UserId,
     pub expiry_date: chrono::DateTime<chrono::Utc>,
     pub blacklisted: bool,
+    pub refresh_token_hash: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,11 +2,29 @@
 
 pub mod prelude;
 
+pub mod api_tokens;
+pub mod audit_log;
+pub mod group_attribute_schema;
+pub mod group_attributes;
+pub mod group_managers;
+pub mod group_nestings;
 pub mod groups;
 pub mod jwt_refresh_storage;
 pub mod jwt_storage;
+pub mod magic_login_tokens;
+pub mod membership_requests;
 pub mod memberships;
+pub mod password_history;
 pub mod password_reset_tokens;
+pub mod sso_users;
+pub mod sync_entries;
+pub mod totp_recovery_codes;
+pub mod user_attribute_schema;
+pub mod user_attributes;
+pub mod user_emails;
+pub mod user_ssh_keys;
 pub mod users;
+pub mod webauthn_credentials;
+pub mod webhooks;
 
 pub use prelude::*;
@@ -1,14 +1,50 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.10.3
 
+pub use super::api_tokens::Column as ApiTokenColumn;
+pub use super::api_tokens::Entity as ApiToken;
+pub use super::audit_log::Column as AuditLogColumn;
+pub use super::audit_log::Entity as AuditLog;
+pub use super::group_attribute_schema::Column as GroupAttributeSchemaColumn;
+pub use super::group_attribute_schema::Entity as GroupAttributeSchema;
+pub use super::group_attributes::Column as GroupAttributeColumn;
+pub use super::group_attributes::Entity as GroupAttribute;
+pub use super::group_managers::Column as GroupManagerColumn;
+pub use super::group_managers::Entity as GroupManager;
+pub use super::group_nestings::Column as GroupNestingColumn;
+pub use super::group_nestings::Entity as GroupNesting;
 pub use super::groups::Column as GroupColumn;
 pub use super::groups::Entity as Group;
 pub use super::jwt_refresh_storage::Column as JwtRefreshStorageColumn;
 pub use super::jwt_refresh_storage::Entity as JwtRefreshStorage;
 pub use super::jwt_storage::Column as JwtStorageColumn;
 pub use super::jwt_storage::Entity as JwtStorage;
+pub use super::magic_login_tokens::Column as MagicLoginTokensColumn;
+pub use super::magic_login_tokens::Entity as MagicLoginTokens;
+pub use super::membership_requests::Column as MembershipRequestColumn;
+pub use super::membership_requests::Entity as MembershipRequest;
 pub use super::memberships::Column as MembershipColumn;
 pub use super::memberships::Entity as Membership;
+pub use super::password_history::Column as PasswordHistoryColumn;
+pub use super::password_history::Entity as PasswordHistory;
 pub use super::password_reset_tokens::Column as PasswordResetTokensColumn;
 pub use super::password_reset_tokens::Entity as PasswordResetTokens;
+pub use super::sso_users::Column as SsoUserColumn;
+pub use super::sso_users::Entity as SsoUser;
+pub use super::sync_entries::Column as SyncEntryColumn;
+pub use super::sync_entries::Entity as SyncEntry;
+pub use super::totp_recovery_codes::Column as TotpRecoveryCodeColumn;
+pub use super::totp_recovery_codes::Entity as TotpRecoveryCode;
+pub use super::user_attribute_schema::Column as UserAttributeSchemaColumn;
+pub use super::user_attribute_schema::Entity as UserAttributeSchema;
+pub use super::user_attributes::Column as UserAttributeColumn;
+pub use super::user_attributes::Entity as UserAttribute;
+pub use super::user_emails::Column as UserEmailColumn;
+pub use super::user_emails::Entity as UserEmail;
+pub use super::user_ssh_keys::Column as UserSshKeyColumn;
+pub use super::user_ssh_keys::Entity as UserSshKey;
 pub use super::users::Column as UserColumn;
 pub use super::users::Entity as User;
+pub use super::webauthn_credentials::Column as WebauthnCredentialColumn;
+pub use super::webauthn_credentials::Entity as WebauthnCredential;
+pub use super::webhooks::Column as WebhookColumn;
+pub use super::webhooks::Entity as Webhook;
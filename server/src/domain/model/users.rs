@@ -23,6 +23,25 @@ pub struct Model {
     pub totp_secret: Option<String>,
     pub mfa_type: Option<String>,
     pub uuid: Uuid,
+    pub preferred_language: Option<String>,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    pub modified_date: chrono::DateTime<chrono::Utc>,
+    pub totp_last_used_step: Option<i64>,
+    pub password_modified_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub failed_login_attempts: i32,
+    pub locked_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub legacy_password_hash: Option<String>,
+    pub temporary_password_hash: Option<String>,
+    pub temporary_password_expiry_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub activation_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub expiration_date: Option<chrono::DateTime<chrono::Utc>>,
+    pub login_notifications: bool,
+    pub enabled: bool,
+    /// The logins since the password expired, reset when it's changed.
+    pub grace_logins_used: i32,
 }
 
 impl EntityName for Entity {
@@ -44,6 +63,24 @@ pub enum Column {
     TotpSecret,
     MfaType,
     Uuid,
+    PreferredLanguage,
+    UidNumber,
+    GidNumber,
+    HomeDirectory,
+    LoginShell,
+    ModifiedDate,
+    TotpLastUsedStep,
+    PasswordModifiedDate,
+    FailedLoginAttempts,
+    LockedDate,
+    LegacyPasswordHash,
+    TemporaryPasswordHash,
+    TemporaryPasswordExpiryDate,
+    ActivationDate,
+    ExpirationDate,
+    LoginNotifications,
+    Enabled,
+    GraceLoginsUsed,
 }
 
 impl ColumnTrait for Column {
@@ -62,6 +99,24 @@ impl ColumnTrait for Column {
             Column::TotpSecret => ColumnType::String(Some(64)),
             Column::MfaType => ColumnType::String(Some(64)),
             Column::Uuid => ColumnType::String(Some(36)),
+            Column::PreferredLanguage => ColumnType::String(Some(35)),
+            Column::UidNumber => ColumnType::Integer,
+            Column::GidNumber => ColumnType::Integer,
+            Column::HomeDirectory => ColumnType::String(Some(255)),
+            Column::LoginShell => ColumnType::String(Some(255)),
+            Column::ModifiedDate => ColumnType::DateTime,
+            Column::TotpLastUsedStep => ColumnType::BigInteger,
+            Column::PasswordModifiedDate => ColumnType::DateTime,
+            Column::FailedLoginAttempts => ColumnType::Integer,
+            Column::LockedDate => ColumnType::DateTime,
+            Column::LegacyPasswordHash => ColumnType::Text,
+            Column::TemporaryPasswordHash => ColumnType::String(Some(64)),
+            Column::TemporaryPasswordExpiryDate => ColumnType::DateTime,
+            Column::ActivationDate => ColumnType::DateTime,
+            Column::ExpirationDate => ColumnType::DateTime,
+            Column::LoginNotifications => ColumnType::Boolean,
+            Column::Enabled => ColumnType::Boolean,
+            Column::GraceLoginsUsed => ColumnType::Integer,
         }
         .def()
     }
@@ -69,14 +124,32 @@ impl ColumnTrait for Column {
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::api_tokens::Entity")]
+    ApiTokens,
     #[sea_orm(has_many = "super::memberships::Entity")]
     Memberships,
     #[sea_orm(has_many = "super::jwt_refresh_storage::Entity")]
     JwtRefreshStorage,
     #[sea_orm(has_many = "super::jwt_storage::Entity")]
     JwtStorage,
+    #[sea_orm(has_many = "super::magic_login_tokens::Entity")]
+    MagicLoginTokens,
+    #[sea_orm(has_many = "super::password_history::Entity")]
+    PasswordHistory,
     #[sea_orm(has_many = "super::password_reset_tokens::Entity")]
     PasswordResetTokens,
+    #[sea_orm(has_many = "super::sso_users::Entity")]
+    SsoUsers,
+    #[sea_orm(has_many = "super::totp_recovery_codes::Entity")]
+    TotpRecoveryCodes,
+    #[sea_orm(has_many = "super::user_attributes::Entity")]
+    UserAttributes,
+    #[sea_orm(has_many = "super::user_emails::Entity")]
+    UserEmails,
+    #[sea_orm(has_many = "super::user_ssh_keys::Entity")]
+    UserSshKeys,
+    #[sea_orm(has_many = "super::webauthn_credentials::Entity")]
+    WebauthnCredentials,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DerivePrimaryKey)]
@@ -92,6 +165,12 @@ impl PrimaryKeyTrait for PrimaryKey {
     }
 }
 
+impl Related<super::api_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiTokens.def()
+    }
+}
+
 impl Related<super::memberships::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Memberships.def()
@@ -110,12 +189,60 @@ impl Related<super::jwt_storage::Entity> for Entity {
     }
 }
 
+impl Related<super::magic_login_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MagicLoginTokens.def()
+    }
+}
+
+impl Related<super::password_history::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::PasswordHistory.def()
+    }
+}
+
 impl Related<super::password_reset_tokens::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::PasswordResetTokens.def()
     }
 }
 
+impl Related<super::sso_users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SsoUsers.def()
+    }
+}
+
+impl Related<super::totp_recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TotpRecoveryCodes.def()
+    }
+}
+
+impl Related<super::user_attributes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserAttributes.def()
+    }
+}
+
+impl Related<super::user_emails::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserEmails.def()
+    }
+}
+
+impl Related<super::user_ssh_keys::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserSshKeys.def()
+    }
+}
+
+impl Related<super::webauthn_credentials::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WebauthnCredentials.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl From<Model> for crate::domain::types::User {
@@ -127,8 +254,23 @@ impl From<Model> for crate::domain::types::User {
             first_name: user.first_name,
             last_name: user.last_name,
             creation_date: user.creation_date,
+            modified_date: user.modified_date,
+            password_modified_date: user.password_modified_date,
+            locked_date: user.locked_date,
+            activation_date: user.activation_date,
+            expiration_date: user.expiration_date,
+            login_notifications: user.login_notifications,
+            enabled: user.enabled,
             uuid: user.uuid,
             avatar: user.avatar,
+            preferred_language: user.preferred_language,
+            uid_number: user.uid_number,
+            gid_number: user.gid_number,
+            home_directory: user.home_directory,
+            login_shell: user.login_shell,
+            email_aliases: Vec::new(),
+            ssh_public_keys: Vec::new(),
+            attributes: Vec::new(),
         }
     }
 }
@@ -1,21 +1,1134 @@
-use super::{handler::BackendHandler, sql_tables::DbConnection};
-use crate::infra::configuration::Configuration;
+use super::{
+    error::{DomainError, Result},
+    handler::{
+        AuditEvent, AuditLogFilter, BackendHandler, BackendStats, DatabaseOptimizationReport,
+        MembershipRepairReport, Page, SyncEntries,
+    },
+    legacy_password,
+    model::{
+        self, ApiTokenColumn, AuditLogColumn, GroupColumn, JwtRefreshStorageColumn,
+        JwtStorageColumn, MagicLoginTokensColumn, MembershipColumn, SyncEntryColumn,
+        TotpRecoveryCodeColumn, UserColumn, WebauthnCredentialColumn, WebhookColumn,
+    },
+    sql_migrations::Metadata,
+    sql_opaque_handler::PendingLogins,
+    sql_tables::{DbConnection, SqlConnection},
+    sql_user_backend_handler::get_user_model,
+    totp,
+    types::{
+        ApiToken, ApiTokenScope, AuditLogEntry, DateTime, DirectoryChange, Session, UserId, Uuid,
+        WebauthnCredential, Webhook, WebhookEvent, API_TOKEN_PREFIX,
+    },
+};
+use crate::infra::{configuration::Configuration, sql_backend_handler::gen_random_string};
 use async_trait::async_trait;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseBackend, EntityTrait, FromQueryResult,
+    IntoActiveModel, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
+    Set, Statement,
+};
+use sea_query::{Cond, Expr, Query, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+use tracing::{debug, instrument};
+
+/// The `mfa_type` of the users who confirmed their TOTP enrollment.
+const TOTP_MFA_TYPE: &str = "totp";
+
+/// The secrets are random, so they don't need a slow hash.
+fn hash_api_token(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
 
 #[derive(Clone)]
 pub struct SqlBackendHandler {
     pub(crate) config: Configuration,
-    pub(crate) sql_pool: DbConnection,
+    pub(crate) sql_pool: SqlConnection,
+    /// Where the changes of the directory are sent, for the webhooks and the subscriptions.
+    changes: Option<broadcast::Sender<DirectoryChange>>,
+    /// In a transaction, the changes are only sent once it's committed.
+    pending_changes: Option<Arc<Mutex<Vec<DirectoryChange>>>>,
+    /// Shared by the clones, so that a login can be finished by any of them.
+    pub(crate) pending_logins: Arc<PendingLogins>,
 }
 
 impl SqlBackendHandler {
     pub fn new(config: Configuration, sql_pool: DbConnection) -> Self {
-        SqlBackendHandler { config, sql_pool }
+        SqlBackendHandler {
+            config,
+            sql_pool: SqlConnection::Pool(sql_pool),
+            changes: None,
+            pending_changes: None,
+            pending_logins: Arc::default(),
+        }
+    }
+
+    pub fn with_change_notifications(
+        mut self,
+        changes: broadcast::Sender<DirectoryChange>,
+    ) -> Self {
+        self.changes = Some(changes);
+        self
     }
+
+    /// Called once the change is committed. Nobody may be listening.
+    pub(crate) fn notify_change(&self, change: DirectoryChange) {
+        if let Some(pending_changes) = &self.pending_changes {
+            pending_changes.lock().unwrap().push(change);
+            return;
+        }
+        if let Some(changes) = &self.changes {
+            let _ = changes.send(change);
+        }
+    }
+}
+
+/// Returns the size of the database in bytes, if the database supports reporting it.
+async fn get_database_size(pool: &impl ConnectionTrait) -> Result<Option<u64>> {
+    let backend = pool.get_database_backend();
+    let query = match backend {
+        DatabaseBackend::Sqlite => {
+            "SELECT page_count * page_size AS size FROM pragma_page_count(), pragma_page_size()"
+        }
+        DatabaseBackend::Postgres => "SELECT pg_database_size(current_database()) AS size",
+        DatabaseBackend::MySql => {
+            "SELECT CAST(SUM(data_length + index_length) AS SIGNED) AS size \
+             FROM information_schema.tables WHERE table_schema = DATABASE()"
+        }
+    };
+    Ok(pool
+        .query_one(Statement::from_string(backend, query.to_owned()))
+        .await?
+        .and_then(|row| row.try_get::<Option<i64>>("", "size").ok().flatten())
+        .and_then(|size| u64::try_from(size).ok()))
+}
+
+/// Returns the statements to reclaim the free space and refresh the statistics and indices.
+async fn get_optimization_statements(pool: &impl ConnectionTrait) -> Result<Vec<String>> {
+    Ok(match pool.get_database_backend() {
+        // VACUUM rewrites the whole file, and blocks the writers until it's done.
+        DatabaseBackend::Sqlite => vec![
+            "VACUUM".to_owned(),
+            "ANALYZE".to_owned(),
+            "REINDEX".to_owned(),
+        ],
+        // Unlike VACUUM FULL and REINDEX, a plain VACUUM doesn't lock the tables, and the indices
+        // are kept up to date by autovacuum.
+        DatabaseBackend::Postgres => vec!["VACUUM ANALYZE".to_owned()],
+        // InnoDB rebuilds the tables online, only locking them briefly.
+        DatabaseBackend::MySql => {
+            let tables = pool
+                .query_all(Statement::from_string(
+                    DatabaseBackend::MySql,
+                    "SELECT table_name AS name FROM information_schema.tables \
+                     WHERE table_schema = DATABASE() AND table_type = 'BASE TABLE'"
+                        .to_owned(),
+                ))
+                .await?
+                .into_iter()
+                .map(|row| row.try_get::<String>("", "name"))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            if tables.is_empty() {
+                return Ok(Vec::new());
+            }
+            let tables = tables
+                .iter()
+                .map(|t| format!("`{}`", t))
+                .collect::<Vec<_>>()
+                .join(", ");
+            vec![
+                format!("OPTIMIZE TABLE {}", tables),
+                format!("ANALYZE TABLE {}", tables),
+            ]
+        }
+    })
+}
+
+async fn get_change_sequence(connection: &impl ConnectionTrait) -> Result<i64> {
+    let backend = connection.get_database_backend();
+    Ok(connection
+        .query_one(
+            backend.build(
+                Query::select()
+                    .from(Metadata::Table)
+                    .column(Metadata::ChangeSequence),
+            ),
+        )
+        .await?
+        .map(|row| row.try_get::<i64>("", "change_sequence"))
+        .transpose()?
+        .unwrap_or(0))
+}
+
+async fn set_change_sequence(
+    connection: &impl ConnectionTrait,
+    change_sequence: i64,
+) -> Result<()> {
+    let backend = connection.get_database_backend();
+    connection
+        .execute(
+            backend.build(
+                Query::update()
+                    .table(Metadata::Table)
+                    .value(Metadata::ChangeSequence, Value::from(change_sequence)),
+            ),
+        )
+        .await?;
+    Ok(())
 }
 
 #[async_trait]
-impl BackendHandler for SqlBackendHandler {}
+impl BackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_stats(&self) -> Result<BackendStats> {
+        Ok(BackendStats {
+            user_count: model::User::find().count(&self.sql_pool).await?,
+            group_count: model::Group::find().count(&self.sql_pool).await?,
+            archived_group_count: model::Group::find()
+                .filter(GroupColumn::Archived.eq(true))
+                .count(&self.sql_pool)
+                .await?,
+            database_size: get_database_size(&self.sql_pool).await?,
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn repair_memberships(&self) -> Result<MembershipRepairReport> {
+        let transaction = self.sql_pool.begin().await?;
+        let users = model::User::find()
+            .select_only()
+            .column(UserColumn::UserId)
+            .into_query();
+        let groups = model::Group::find()
+            .select_only()
+            .column(GroupColumn::GroupId)
+            .into_query();
+        let dangling_memberships_removed = model::Membership::delete_many()
+            .filter(
+                Cond::any()
+                    .add(MembershipColumn::UserId.not_in_subquery(users))
+                    .add(MembershipColumn::GroupId.not_in_subquery(groups)),
+            )
+            .exec(&transaction)
+            .await?
+            .rows_affected;
+        let dynamic_groups = model::Group::find()
+            .select_only()
+            .column(GroupColumn::GroupId)
+            .filter(GroupColumn::DynamicFilter.is_not_null())
+            .into_query();
+        let dynamic_group_memberships_removed = model::Membership::delete_many()
+            .filter(MembershipColumn::GroupId.in_subquery(dynamic_groups))
+            .exec(&transaction)
+            .await?
+            .rows_affected;
+        transaction.commit().await?;
+        Ok(MembershipRepairReport {
+            dangling_memberships_removed,
+            dynamic_group_memberships_removed,
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn optimize_database(&self) -> Result<DatabaseOptimizationReport> {
+        let size_before = get_database_size(&self.sql_pool).await?;
+        let backend = self.sql_pool.get_database_backend();
+        for statement in get_optimization_statements(&self.sql_pool).await? {
+            self.sql_pool
+                .execute(Statement::from_string(backend, statement))
+                .await?;
+        }
+        Ok(DatabaseOptimizationReport {
+            size_before,
+            size_after: get_database_size(&self.sql_pool).await?,
+        })
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn create_magic_login_token(
+        &self,
+        user_id: &UserId,
+        valid_for: chrono::Duration,
+    ) -> Result<String> {
+        let transaction = self.sql_pool.begin().await?;
+        if model::User::find_by_id(user_id.clone())
+            .one(&transaction)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        let pending_tokens = model::MagicLoginTokens::find()
+            .filter(MagicLoginTokensColumn::UserId.eq(user_id.clone()))
+            .filter(MagicLoginTokensColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .count(&transaction)
+            .await?;
+        if pending_tokens > 0 {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "A login link for '{}' is still pending",
+                user_id
+            )));
+        }
+        let token = gen_random_string(100);
+        model::magic_login_tokens::Model {
+            token: token.clone(),
+            user_id: user_id.clone(),
+            expiry_date: chrono::Utc::now() + valid_for,
+        }
+        .into_active_model()
+        .insert(&transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(token)
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn consume_magic_login_token(&self, token: &str) -> Result<UserId> {
+        let transaction = self.sql_pool.begin().await?;
+        let login_token = model::MagicLoginTokens::find_by_id(token.to_owned())
+            .one(&transaction)
+            .await?
+            .ok_or_else(|| DomainError::EntityNotFound("Invalid login token".to_owned()))?;
+        let user_id = login_token.user_id.clone();
+        let expired = login_token.expiry_date < chrono::Utc::now();
+        // Expired tokens are deleted as well, they can't be used anymore anyway.
+        login_token.delete(&transaction).await?;
+        transaction.commit().await?;
+        if expired {
+            return Err(DomainError::EntityNotFound(
+                "Invalid login token".to_owned(),
+            ));
+        }
+        Ok(user_id)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn create_password_reset_token(
+        &self,
+        user_id: &UserId,
+        valid_for: chrono::Duration,
+    ) -> Result<String> {
+        get_user_model(&self.sql_pool, user_id).await?;
+        let token = gen_random_string(100);
+        model::password_reset_tokens::Model {
+            token: token.clone(),
+            user_id: user_id.clone(),
+            expiry_date: chrono::Utc::now() + valid_for,
+        }
+        .into_active_model()
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(token)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<String> {
+        let transaction = self.sql_pool.begin().await?;
+        if get_user_model(&transaction, user_id)
+            .await?
+            .mfa_type
+            .is_some()
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "TOTP is already enabled for '{}'",
+                user_id
+            )));
+        }
+        // Starting over replaces the previous pending secret.
+        let secret = totp::generate_secret();
+        model::users::ActiveModel {
+            user_id: Set(user_id.clone()),
+            totp_secret: Set(Some(secret.clone())),
+            ..Default::default()
+        }
+        .update(&transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(secret)
+    }
+
+    #[instrument(skip(self, code), level = "debug", err)]
+    async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<Vec<String>> {
+        let transaction = self.sql_pool.begin().await?;
+        let user = get_user_model(&transaction, user_id).await?;
+        let secret = match (user.totp_secret, user.mfa_type) {
+            (_, Some(_)) => {
+                return Err(DomainError::EntityAlreadyExists(format!(
+                    "TOTP is already enabled for '{}'",
+                    user_id
+                )))
+            }
+            (None, None) => {
+                return Err(DomainError::EntityNotFound(format!(
+                    "No pending TOTP enrollment for '{}'",
+                    user_id
+                )))
+            }
+            (Some(secret), None) => secret,
+        };
+        let step = totp::verify_code(&secret, code, chrono::Utc::now().timestamp(), None)
+            .ok_or_else(|| DomainError::AuthenticationError("Invalid TOTP code".to_owned()))?;
+        model::users::ActiveModel {
+            user_id: Set(user_id.clone()),
+            mfa_type: Set(Some(TOTP_MFA_TYPE.to_owned())),
+            totp_last_used_step: Set(Some(step)),
+            ..Default::default()
+        }
+        .update(&transaction)
+        .await?;
+        let recovery_codes = totp::generate_recovery_codes();
+        model::TotpRecoveryCode::delete_many()
+            .filter(TotpRecoveryCodeColumn::UserId.eq(user_id.clone()))
+            .exec(&transaction)
+            .await?;
+        model::TotpRecoveryCode::insert_many(recovery_codes.iter().map(|code| {
+            model::totp_recovery_codes::ActiveModel {
+                user_id: Set(user_id.clone()),
+                code_hash: Set(totp::hash_recovery_code(code)),
+            }
+        }))
+        .exec(&transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(recovery_codes)
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn is_totp_enabled(&self, user_id: &UserId) -> Result<bool> {
+        Ok(get_user_model(&self.sql_pool, user_id)
+            .await?
+            .mfa_type
+            .is_some())
+    }
+
+    #[instrument(skip(self, code), level = "debug", ret, err)]
+    async fn check_totp_code(&self, user_id: &UserId, code: &str) -> Result<bool> {
+        // The wrong codes count toward the lockout like the wrong passwords.
+        if self.is_login_refused(user_id).await? {
+            return Ok(false);
+        }
+        let transaction = self.sql_pool.begin().await?;
+        let user = get_user_model(&transaction, user_id).await?;
+        let secret = match (user.totp_secret, user.mfa_type) {
+            (Some(secret), Some(_)) => secret,
+            _ => {
+                return Err(DomainError::EntityNotFound(format!(
+                    "TOTP is not enabled for '{}'",
+                    user_id
+                )))
+            }
+        };
+        let valid = match totp::verify_code(
+            &secret,
+            code,
+            chrono::Utc::now().timestamp(),
+            user.totp_last_used_step,
+        ) {
+            // Only succeeds if no concurrent login used the same or a later step.
+            Some(step) => {
+                model::User::update_many()
+                    .col_expr(UserColumn::TotpLastUsedStep, Expr::value(step))
+                    .filter(UserColumn::UserId.eq(user_id.clone()))
+                    .filter(
+                        Cond::any()
+                            .add(UserColumn::TotpLastUsedStep.is_null())
+                            .add(UserColumn::TotpLastUsedStep.lt(step)),
+                    )
+                    .exec(&transaction)
+                    .await?
+                    .rows_affected
+                    > 0
+            }
+            // The recovery codes are single-use.
+            None => {
+                model::TotpRecoveryCode::delete_many()
+                    .filter(TotpRecoveryCodeColumn::UserId.eq(user_id.clone()))
+                    .filter(TotpRecoveryCodeColumn::CodeHash.eq(totp::hash_recovery_code(code)))
+                    .exec(&transaction)
+                    .await?
+                    .rows_affected
+                    > 0
+            }
+        };
+        transaction.commit().await?;
+        if valid {
+            self.reset_failed_logins(user_id).await?;
+        } else {
+            self.record_failed_logins(user_id, 1).await?;
+        }
+        Ok(valid)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn disable_totp(&self, user_id: &UserId) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
+        get_user_model(&transaction, user_id).await?;
+        model::users::ActiveModel {
+            user_id: Set(user_id.clone()),
+            totp_secret: Set(None),
+            mfa_type: Set(None),
+            totp_last_used_step: Set(None),
+            ..Default::default()
+        }
+        .update(&transaction)
+        .await?;
+        model::TotpRecoveryCode::delete_many()
+            .filter(TotpRecoveryCodeColumn::UserId.eq(user_id.clone()))
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn unlock_user(&self, user_id: &UserId) -> Result<()> {
+        self.pending_logins.forget(user_id);
+        let result = model::User::update_many()
+            .col_expr(UserColumn::FailedLoginAttempts, Expr::value(0))
+            .col_expr(
+                UserColumn::LockedDate,
+                Expr::value(Option::<chrono::DateTime<chrono::Utc>>::None),
+            )
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn use_grace_login(
+        &self,
+        user_id: &UserId,
+        max_grace_logins: u32,
+    ) -> Result<Option<u32>> {
+        #[derive(FromQueryResult)]
+        struct OnlyGraceLoginsUsed {
+            grace_logins_used: i32,
+        }
+        let max_grace_logins = i32::try_from(max_grace_logins).unwrap_or(i32::MAX);
+        // A single update, so that concurrent logins can't use the same grace login.
+        let result = model::User::update_many()
+            .col_expr(
+                UserColumn::GraceLoginsUsed,
+                Expr::col(UserColumn::GraceLoginsUsed).add(1),
+            )
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .filter(UserColumn::GraceLoginsUsed.lt(max_grace_logins))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Ok(None);
+        }
+        let used = model::User::find_by_id(user_id.clone())
+            .select_only()
+            .column(UserColumn::GraceLoginsUsed)
+            .into_model::<OnlyGraceLoginsUsed>()
+            .one(&self.sql_pool)
+            .await?
+            .map_or(max_grace_logins, |u| u.grace_logins_used);
+        Ok(Some(u32::try_from(max_grace_logins - used).unwrap_or(0)))
+    }
+
+    #[instrument(skip(self, password_hash), level = "debug", err)]
+    async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()> {
+        legacy_password::check_format(password_hash)?;
+        let result = model::User::update_many()
+            .col_expr(
+                UserColumn::LegacyPasswordHash,
+                Expr::value(password_hash.trim().to_owned()),
+            )
+            .col_expr(
+                UserColumn::PasswordHash,
+                Expr::value(Option::<Vec<u8>>::None),
+            )
+            .col_expr(
+                UserColumn::PasswordModifiedDate,
+                Expr::value(chrono::Utc::now()),
+            )
+            .col_expr(UserColumn::GraceLoginsUsed, Expr::value(0))
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        self.notify_change(DirectoryChange::PasswordChanged(user_id.clone()));
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn create_temporary_password(
+        &self,
+        user_id: &UserId,
+        valid_for: chrono::Duration,
+    ) -> Result<String> {
+        let password = format!(
+            "{}{}",
+            lldap_auth::login::TEMPORARY_PASSWORD_PREFIX,
+            gen_random_string(16)
+        );
+        let result = model::User::update_many()
+            .col_expr(
+                UserColumn::TemporaryPasswordHash,
+                Expr::value(hash_api_token(&password)),
+            )
+            .col_expr(
+                UserColumn::TemporaryPasswordExpiryDate,
+                Expr::value(chrono::Utc::now() + valid_for),
+            )
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        Ok(password)
+    }
+
+    #[instrument(skip(self, password), level = "debug", err)]
+    async fn consume_temporary_password(&self, user_id: &UserId, password: &str) -> Result<()> {
+        let invalid_password =
+            || DomainError::AuthenticationError("Invalid or expired temporary password".to_owned());
+        let transaction = self.sql_pool.begin().await?;
+        let user = get_user_model(&transaction, user_id).await?;
+        let (hash, expiry_date) = match (
+            user.temporary_password_hash,
+            user.temporary_password_expiry_date,
+        ) {
+            (Some(hash), Some(expiry_date)) => (hash, expiry_date),
+            _ => return Err(invalid_password()),
+        };
+        if orion::util::secure_cmp(hash.as_bytes(), hash_api_token(password).as_bytes()).is_err() {
+            return Err(invalid_password());
+        }
+        // Expired passwords are cleared as well, they can't be used anymore anyway.
+        model::User::update_many()
+            .col_expr(
+                UserColumn::TemporaryPasswordHash,
+                Expr::value(Option::<String>::None),
+            )
+            .col_expr(
+                UserColumn::TemporaryPasswordExpiryDate,
+                Expr::value(Option::<chrono::DateTime<chrono::Utc>>::None),
+            )
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&transaction)
+            .await?;
+        transaction.commit().await?;
+        if expiry_date < chrono::Utc::now() {
+            return Err(invalid_password());
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn set_account_validity(
+        &self,
+        user_id: &UserId,
+        activation_date: Option<DateTime>,
+        expiration_date: Option<DateTime>,
+    ) -> Result<()> {
+        if let (Some(activation_date), Some(expiration_date)) = (activation_date, expiration_date) {
+            if expiration_date <= activation_date {
+                return Err(DomainError::InternalError(
+                    "The account must expire after its activation".to_owned(),
+                ));
+            }
+        }
+        let result = model::User::update_many()
+            .col_expr(UserColumn::ActivationDate, Expr::value(activation_date))
+            .col_expr(UserColumn::ExpirationDate, Expr::value(expiration_date))
+            .col_expr(UserColumn::ModifiedDate, Expr::value(chrono::Utc::now()))
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()> {
+        let result = model::User::update_many()
+            .col_expr(UserColumn::Enabled, Expr::value(enabled))
+            .col_expr(UserColumn::ModifiedDate, Expr::value(chrono::Utc::now()))
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        self.notify_change(DirectoryChange::UserUpdated(user_id.clone()));
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_webauthn_credential(&self, credential: WebauthnCredential) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
+        get_user_model(&transaction, &credential.user_id).await?;
+        if model::WebauthnCredential::find_by_id(credential.credential_id.clone())
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::EntityAlreadyExists(
+                "This credential is already registered".to_owned(),
+            ));
+        }
+        model::webauthn_credentials::Model {
+            credential_id: credential.credential_id,
+            user_id: credential.user_id,
+            name: credential.name,
+            credential: credential.credential,
+            creation_date: credential.creation_date,
+        }
+        .into_active_model()
+        .insert(&transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>> {
+        Ok(model::WebauthnCredential::find()
+            .filter(WebauthnCredentialColumn::UserId.eq(user_id.clone()))
+            .order_by_asc(WebauthnCredentialColumn::CreationDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip(self, credential), level = "debug", err)]
+    async fn update_webauthn_credential(
+        &self,
+        credential_id: &str,
+        credential: String,
+    ) -> Result<()> {
+        model::webauthn_credentials::ActiveModel {
+            credential_id: Set(credential_id.to_owned()),
+            credential: Set(credential),
+            ..Default::default()
+        }
+        .update(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_webauthn_credential(
+        &self,
+        user_id: &UserId,
+        credential_id: &str,
+    ) -> Result<()> {
+        let result = model::WebauthnCredential::delete_many()
+            .filter(WebauthnCredentialColumn::UserId.eq(user_id.clone()))
+            .filter(WebauthnCredentialColumn::CredentialId.eq(credential_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such credential for '{}'",
+                user_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn get_sso_user(&self, issuer: &str, subject: &str) -> Result<Option<UserId>> {
+        Ok(
+            model::SsoUser::find_by_id((issuer.to_owned(), subject.to_owned()))
+                .one(&self.sql_pool)
+                .await?
+                .map(|link| link.user_id),
+        )
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn link_sso_user(&self, issuer: &str, subject: &str, user_id: &UserId) -> Result<()> {
+        let transaction = self.sql_pool.begin().await?;
+        get_user_model(&transaction, user_id).await?;
+        if model::SsoUser::find_by_id((issuer.to_owned(), subject.to_owned()))
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::EntityAlreadyExists(
+                "This identity is already linked to a user".to_owned(),
+            ));
+        }
+        model::sso_users::Model {
+            issuer: issuer.to_owned(),
+            subject: subject.to_owned(),
+            user_id: user_id.clone(),
+        }
+        .into_active_model()
+        .insert(&transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn record_sync_entries(&self, digests: Vec<(Uuid, String)>) -> Result<SyncEntries> {
+        let transaction = self.sql_pool.begin().await?;
+        let mut known_entries = model::SyncEntry::find()
+            .all(&transaction)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.entry_uuid.clone(), entry))
+            .collect::<HashMap<_, _>>();
+        let previous_change_sequence = get_change_sequence(&transaction).await?;
+        // All the changes seen at once share the same number.
+        let change_sequence = previous_change_sequence + 1;
+        let mut entries = HashMap::new();
+        for (entry_uuid, digest) in digests {
+            let entry_change_sequence = match known_entries.remove(&entry_uuid) {
+                Some(entry) if entry.digest == digest => entry.change_sequence,
+                Some(entry) => {
+                    let mut entry = entry.into_active_model();
+                    entry.digest = Set(digest);
+                    entry.change_sequence = Set(change_sequence);
+                    entry.update(&transaction).await?;
+                    change_sequence
+                }
+                None => {
+                    model::sync_entries::Model {
+                        entry_uuid: entry_uuid.clone(),
+                        digest,
+                        change_sequence,
+                    }
+                    .into_active_model()
+                    .insert(&transaction)
+                    .await?;
+                    change_sequence
+                }
+            };
+            entries.insert(entry_uuid, entry_change_sequence);
+        }
+        // The deleted entries are simply not reported as present to the consumers anymore.
+        if !known_entries.is_empty() {
+            model::SyncEntry::delete_many()
+                .filter(SyncEntryColumn::EntryUuid.is_in(known_entries.into_keys()))
+                .exec(&transaction)
+                .await?;
+        }
+        let change_sequence = if entries.values().any(|c| *c == change_sequence) {
+            set_change_sequence(&transaction, change_sequence).await?;
+            change_sequence
+        } else {
+            previous_change_sequence
+        };
+        transaction.commit().await?;
+        Ok(SyncEntries {
+            change_sequence,
+            entries,
+        })
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn create_api_token(
+        &self,
+        user_id: &UserId,
+        name: &str,
+        scopes: &[ApiTokenScope],
+    ) -> Result<(ApiToken, String)> {
+        let secret = format!("{}{}", API_TOKEN_PREFIX, gen_random_string(40));
+        let token = model::api_tokens::Model {
+            token_id: gen_random_string(16),
+            token_hash: hash_api_token(&secret),
+            user_id: user_id.clone(),
+            name: name.to_owned(),
+            scopes: scopes
+                .iter()
+                .map(ApiTokenScope::as_str)
+                .collect::<Vec<_>>()
+                .join(","),
+            creation_date: chrono::Utc::now(),
+        }
+        .into_active_model()
+        .insert(&self.sql_pool)
+        .await?;
+        Ok((token.into(), secret))
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>> {
+        Ok(model::ApiToken::find()
+            .order_by_asc(ApiTokenColumn::CreationDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_api_token(&self, secret: &str) -> Result<Option<ApiToken>> {
+        Ok(model::ApiToken::find()
+            .filter(ApiTokenColumn::TokenHash.eq(hash_api_token(secret)))
+            .one(&self.sql_pool)
+            .await?
+            .map(Into::into))
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_api_token(&self, token_id: &str) -> Result<()> {
+        let result = model::ApiToken::delete_by_id(token_id.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such API token: '{}'",
+                token_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>> {
+        Ok(model::JwtRefreshStorage::find()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .filter(JwtRefreshStorageColumn::ExpiryDate.gt(chrono::Utc::now().naive_utc()))
+            .order_by_asc(JwtRefreshStorageColumn::CreationDate)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>> {
+        let transaction = self.sql_pool.begin().await?;
+        let result = model::JwtRefreshStorage::delete_by_id(session_id)
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .exec(&transaction)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such session for '{}': {}",
+                user_id, session_id
+            )));
+        }
+        let jwt_hashes = blacklist_jwts(
+            &transaction,
+            Cond::all()
+                .add(JwtStorageColumn::UserId.eq(user_id))
+                .add(JwtStorageColumn::RefreshTokenHash.eq(session_id)),
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(jwt_hashes)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>> {
+        let transaction = self.sql_pool.begin().await?;
+        model::JwtRefreshStorage::delete_many()
+            .filter(JwtRefreshStorageColumn::UserId.eq(user_id))
+            .exec(&transaction)
+            .await?;
+        let jwt_hashes = blacklist_jwts(
+            &transaction,
+            Cond::all().add(JwtStorageColumn::UserId.eq(user_id)),
+        )
+        .await?;
+        transaction.commit().await?;
+        Ok(jwt_hashes)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn record_audit_event(&self, event: AuditEvent) -> Result<()> {
+        model::audit_log::ActiveModel {
+            date: Set(chrono::Utc::now()),
+            actor: Set(event.actor),
+            action: Set(event.action),
+            target: Set(event.target),
+            ip_address: Set(event.ip_address),
+            success: Set(event.success),
+            details: Set(event.details),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_audit_logs(
+        &self,
+        filter: AuditLogFilter,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Page<AuditLogEntry>> {
+        let mut condition = Cond::all();
+        if let Some(actor) = filter.actor {
+            condition = condition.add(AuditLogColumn::Actor.eq(actor));
+        }
+        if let Some(action) = filter.action {
+            condition = condition.add(AuditLogColumn::Action.eq(action));
+        }
+        if let Some(target) = filter.target {
+            condition = condition.add(AuditLogColumn::Target.eq(target));
+        }
+        if let Some(success) = filter.success {
+            condition = condition.add(AuditLogColumn::Success.eq(success));
+        }
+        if let Some(since) = filter.since {
+            condition = condition.add(AuditLogColumn::Date.gte(since.naive_utc()));
+        }
+        if let Some(until) = filter.until {
+            condition = condition.add(AuditLogColumn::Date.lte(until.naive_utc()));
+        }
+        let query = model::AuditLog::find().filter(condition);
+        let total_count = query.clone().count(&self.sql_pool).await? as u64;
+        let items = query
+            .order_by_desc(AuditLogColumn::Date)
+            .order_by_desc(AuditLogColumn::EntryId)
+            .offset(offset)
+            .limit(limit)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(Page { items, total_count })
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn create_webhook(&self, url: &str, events: &[WebhookEvent]) -> Result<Webhook> {
+        Ok(model::webhooks::ActiveModel {
+            url: Set(url.to_owned()),
+            secret: Set(gen_random_string(32)),
+            events: Set(events
+                .iter()
+                .map(WebhookEvent::as_str)
+                .collect::<Vec<_>>()
+                .join(",")),
+            creation_date: Set(chrono::Utc::now()),
+            ..Default::default()
+        }
+        .insert(&self.sql_pool)
+        .await?
+        .into())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>> {
+        Ok(model::Webhook::find()
+            .order_by_asc(WebhookColumn::WebhookId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    async fn delete_webhook(&self, webhook_id: i32) -> Result<()> {
+        let result = model::Webhook::delete_by_id(webhook_id)
+            .exec(&self.sql_pool)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such webhook: {}",
+                webhook_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn begin_transaction(&self) -> Result<Self> {
+        let transaction = self.sql_pool.begin().await?;
+        Ok(SqlBackendHandler {
+            sql_pool: SqlConnection::Transaction(Arc::new(transaction)),
+            pending_changes: Some(Arc::default()),
+            ..self.clone()
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn end_transaction(self, commit: bool) -> Result<()> {
+        debug!(commit);
+        let transaction = match self.sql_pool {
+            SqlConnection::Transaction(transaction) => Arc::try_unwrap(transaction)
+                .map_err(|_| DomainError::InternalError("The transaction is in use".to_string()))?,
+            SqlConnection::Pool(_) => {
+                return Err(DomainError::InternalError(
+                    "No transaction in progress".to_string(),
+                ))
+            }
+        };
+        if !commit {
+            transaction.rollback().await?;
+            return Ok(());
+        }
+        transaction.commit().await?;
+        if let Some(changes) = &self.changes {
+            for change in self
+                .pending_changes
+                .iter()
+                .flat_map(|pending_changes| std::mem::take(&mut *pending_changes.lock().unwrap()))
+            {
+                let _ = changes.send(change);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Blacklists the JWTs that match the condition, and returns the ones that weren't already.
+async fn blacklist_jwts(
+    connection: &impl ConnectionTrait,
+    condition: Cond,
+) -> Result<HashSet<u64>> {
+    let jwt_hashes = model::JwtStorage::find()
+        .filter(condition.clone())
+        .filter(JwtStorageColumn::Blacklisted.eq(false))
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(|jwt| jwt.jwt_hash as u64)
+        .collect();
+    model::JwtStorage::update_many()
+        .col_expr(JwtStorageColumn::Blacklisted, Expr::value(true))
+        .filter(condition)
+        .exec(connection)
+        .await?;
+    Ok(jwt_hashes)
+}
 
 #[cfg(test)]
 pub mod tests {
@@ -26,7 +1139,7 @@ pub mod tests {
                 CreateUserRequest, GroupBackendHandler, UserBackendHandler, UserRequestFilter,
             },
             sql_tables::init_table,
-            types::{GroupId, UserId},
+            types::{GroupId, SessionMetadata, UserId},
         },
         infra::configuration::ConfigurationBuilder,
     };
@@ -76,6 +1189,7 @@ pub mod tests {
             .registration_finish(registration::ClientRegistrationFinishRequest {
                 server_data: response.server_data,
                 registration_upload: registration_upload.message,
+                password_history_digest: None,
             })
             .await
             .unwrap();
@@ -166,4 +1280,608 @@ pub mod tests {
             assert_eq!(user.user_id, user_name);
         }
     }
+
+    #[tokio::test]
+    async fn test_get_stats() {
+        let fixture = TestFixture::new().await;
+        let stats = fixture.handler.get_stats().await.unwrap();
+        assert_eq!(stats.user_count, 4);
+        assert_eq!(stats.group_count, 3);
+        assert_eq!(stats.archived_group_count, 0);
+        assert!(stats.database_size.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let fixture = TestFixture::new().await;
+        for commit in [false, true] {
+            let transaction = fixture.handler.begin_transaction().await.unwrap();
+            insert_user_no_password(&transaction, "alice").await;
+            transaction.delete_user(&UserId::new("bob")).await.unwrap();
+            transaction.end_transaction(commit).await.unwrap();
+            assert_eq!(
+                fixture
+                    .handler
+                    .get_user_details(&UserId::new("alice"))
+                    .await
+                    .is_ok(),
+                commit
+            );
+            assert_eq!(
+                fixture
+                    .handler
+                    .get_user_details(&UserId::new("bob"))
+                    .await
+                    .is_ok(),
+                !commit
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repair_memberships() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let dynamic_group = handler
+            .create_dynamic_group("Dynamic Group", "(objectClass=person)")
+            .await
+            .unwrap();
+        insert_membership(handler, dynamic_group, "bob").await;
+        // Simulate an import that bypassed the foreign keys.
+        handler
+            .sql_pool
+            .execute(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "PRAGMA foreign_keys = OFF".to_owned(),
+            ))
+            .await
+            .unwrap();
+        insert_membership(handler, GroupId(42), "bob").await;
+        insert_membership(handler, fixture.groups[2], "ghost").await;
+        handler
+            .sql_pool
+            .execute(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "PRAGMA foreign_keys = ON".to_owned(),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            handler.repair_memberships().await.unwrap(),
+            MembershipRepairReport {
+                dangling_memberships_removed: 2,
+                dynamic_group_memberships_removed: 1,
+            }
+        );
+        assert_eq!(
+            handler.repair_memberships().await.unwrap(),
+            MembershipRepairReport::default()
+        );
+        assert_eq!(
+            get_user_names(
+                handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0]))
+            )
+            .await,
+            vec!["bob", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_optimize_database() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        handler.delete_user(&UserId::new("patrick")).await.unwrap();
+        let report = handler.optimize_database().await.unwrap();
+        assert!(report.size_after.unwrap() <= report.size_before.unwrap());
+        assert_eq!(handler.get_stats().await.unwrap().user_count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_magic_login_token() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let token = handler
+            .create_magic_login_token(&bob, chrono::Duration::minutes(5))
+            .await
+            .unwrap();
+        // Only one pending token per user.
+        handler
+            .create_magic_login_token(&bob, chrono::Duration::minutes(5))
+            .await
+            .unwrap_err();
+        handler
+            .create_magic_login_token(&UserId::new("ghost"), chrono::Duration::minutes(5))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            handler.consume_magic_login_token(&token).await.unwrap(),
+            bob
+        );
+        // Single use.
+        handler.consume_magic_login_token(&token).await.unwrap_err();
+
+        let expired_token = handler
+            .create_magic_login_token(&bob, chrono::Duration::minutes(-1))
+            .await
+            .unwrap();
+        handler
+            .consume_magic_login_token(&expired_token)
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_create_password_reset_token() {
+        use crate::infra::tcp_backend_handler::TcpBackendHandler;
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let token = handler
+            .create_password_reset_token(&bob, chrono::Duration::hours(24))
+            .await
+            .unwrap();
+        assert_eq!(
+            handler
+                .get_user_id_for_password_reset_token(&token)
+                .await
+                .unwrap(),
+            bob
+        );
+        handler
+            .create_password_reset_token(&UserId::new("ghost"), chrono::Duration::hours(24))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_temporary_password() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        handler
+            .create_temporary_password(&UserId::new("ghost"), chrono::Duration::hours(1))
+            .await
+            .unwrap_err();
+        let first = handler
+            .create_temporary_password(&bob, chrono::Duration::hours(1))
+            .await
+            .unwrap();
+        let password = handler
+            .create_temporary_password(&bob, chrono::Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(password.starts_with(lldap_auth::login::TEMPORARY_PASSWORD_PREFIX));
+        // Replaced by the new one.
+        handler
+            .consume_temporary_password(&bob, &first)
+            .await
+            .unwrap_err();
+        handler
+            .consume_temporary_password(&UserId::new("patrick"), &password)
+            .await
+            .unwrap_err();
+        handler
+            .consume_temporary_password(&bob, &password)
+            .await
+            .unwrap();
+        // Single use.
+        handler
+            .consume_temporary_password(&bob, &password)
+            .await
+            .unwrap_err();
+
+        let expired = handler
+            .create_temporary_password(&bob, chrono::Duration::minutes(-1))
+            .await
+            .unwrap();
+        handler
+            .consume_temporary_password(&bob, &expired)
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_totp() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        assert!(!handler.is_totp_enabled(&bob).await.unwrap());
+        let secret = handler.start_totp_enrollment(&bob).await.unwrap();
+        // The secret isn't used until the enrollment is confirmed.
+        assert!(!handler.is_totp_enabled(&bob).await.unwrap());
+        handler.check_totp_code(&bob, "123456").await.unwrap_err();
+        handler
+            .confirm_totp_enrollment(&bob, "abcdef")
+            .await
+            .unwrap_err();
+        let now = chrono::Utc::now().timestamp();
+        let code = totp::get_code_at(&secret, now);
+        let recovery_codes = handler.confirm_totp_enrollment(&bob, &code).await.unwrap();
+        assert_eq!(recovery_codes.len(), 10);
+        assert!(handler.is_totp_enabled(&bob).await.unwrap());
+        handler.start_totp_enrollment(&bob).await.unwrap_err();
+        // The code of the enrollment can't be used again.
+        assert!(!handler.check_totp_code(&bob, &code).await.unwrap());
+        assert!(handler
+            .check_totp_code(&bob, &totp::get_code_at(&secret, now + 30))
+            .await
+            .unwrap());
+        // The recovery codes are single-use.
+        assert!(handler
+            .check_totp_code(&bob, &recovery_codes[0])
+            .await
+            .unwrap());
+        assert!(!handler
+            .check_totp_code(&bob, &recovery_codes[0])
+            .await
+            .unwrap());
+        handler.disable_totp(&bob).await.unwrap();
+        assert!(!handler.is_totp_enabled(&bob).await.unwrap());
+        handler
+            .check_totp_code(&bob, &recovery_codes[1])
+            .await
+            .unwrap_err();
+        handler
+            .disable_totp(&UserId::new("ghost"))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_webauthn_credentials() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let credential = |id: &str, user_id: &UserId| WebauthnCredential {
+            credential_id: id.to_owned(),
+            user_id: user_id.clone(),
+            name: "Security key".to_owned(),
+            credential: "{}".to_owned(),
+            creation_date: chrono::Utc::now(),
+        };
+        handler
+            .add_webauthn_credential(credential("key1", &bob))
+            .await
+            .unwrap();
+        handler
+            .add_webauthn_credential(credential("key2", &bob))
+            .await
+            .unwrap();
+        // The credential IDs are unique across users.
+        handler
+            .add_webauthn_credential(credential("key1", &UserId::new("patrick")))
+            .await
+            .unwrap_err();
+        handler
+            .add_webauthn_credential(credential("key3", &UserId::new("ghost")))
+            .await
+            .unwrap_err();
+        handler
+            .update_webauthn_credential("key1", "{\"counter\":1}".to_owned())
+            .await
+            .unwrap();
+        let credentials = handler.list_webauthn_credentials(&bob).await.unwrap();
+        assert_eq!(
+            credentials
+                .iter()
+                .map(|c| (c.credential_id.as_str(), c.credential.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("key1", "{\"counter\":1}"), ("key2", "{}")]
+        );
+        handler
+            .delete_webauthn_credential(&UserId::new("patrick"), "key1")
+            .await
+            .unwrap_err();
+        handler
+            .delete_webauthn_credential(&bob, "key1")
+            .await
+            .unwrap();
+        assert_eq!(
+            handler.list_webauthn_credentials(&bob).await.unwrap().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sso_users() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let issuer = "https://sso.example.com";
+        assert_eq!(handler.get_sso_user(issuer, "1234").await.unwrap(), None);
+        handler.link_sso_user(issuer, "1234", &bob).await.unwrap();
+        assert_eq!(
+            handler.get_sso_user(issuer, "1234").await.unwrap(),
+            Some(bob.clone())
+        );
+        // The subjects are only unique for an issuer.
+        assert_eq!(
+            handler
+                .get_sso_user("https://other.example.com", "1234")
+                .await
+                .unwrap(),
+            None
+        );
+        handler
+            .link_sso_user(issuer, "1234", &UserId::new("patrick"))
+            .await
+            .unwrap_err();
+        handler
+            .link_sso_user(issuer, "5678", &UserId::new("ghost"))
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_api_tokens() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let (token, secret) = handler
+            .create_api_token(
+                &bob,
+                "provisioning",
+                &[ApiTokenScope::ReadOnly, ApiTokenScope::UserManagement],
+            )
+            .await
+            .unwrap();
+        assert!(secret.starts_with(API_TOKEN_PREFIX));
+        assert_eq!(
+            token.scopes,
+            vec![ApiTokenScope::ReadOnly, ApiTokenScope::UserManagement]
+        );
+        assert_eq!(
+            handler.get_api_token(&secret).await.unwrap(),
+            Some(token.clone())
+        );
+        assert_eq!(handler.get_api_token("lldap_token_x").await.unwrap(), None);
+        assert_eq!(
+            handler.list_api_tokens().await.unwrap(),
+            vec![token.clone()]
+        );
+        handler.delete_api_token(&token.token_id).await.unwrap();
+        handler.delete_api_token(&token.token_id).await.unwrap_err();
+        assert_eq!(handler.get_api_token(&secret).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_sessions() {
+        use crate::infra::tcp_backend_handler::TcpBackendHandler;
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let bob = UserId::new("bob");
+        let mut session_ids = vec![];
+        for user_agent in ["Firefox", "Chrome"] {
+            let (refresh_token, _) = handler
+                .create_refresh_token(
+                    &bob,
+                    SessionMetadata {
+                        ip_address: Some("10.0.0.1".to_owned()),
+                        user_agent: Some(user_agent.to_owned()),
+                    },
+                )
+                .await
+                .unwrap();
+            let mut s = DefaultHasher::new();
+            refresh_token.hash(&mut s);
+            session_ids.push(s.finish());
+        }
+        let expiry_date = chrono::Utc::now() + chrono::Duration::days(1);
+        for (jwt_hash, session_id) in [
+            (1, session_ids[0]),
+            (2, session_ids[0]),
+            (3, session_ids[1]),
+        ] {
+            handler
+                .register_jwt(&bob, jwt_hash, session_id, expiry_date)
+                .await
+                .unwrap();
+        }
+        let mut sessions = handler.list_sessions(&bob).await.unwrap();
+        // Both sessions can be created in the same instant, so their order isn't checked.
+        sessions.sort_by_key(|s| s.session_id != session_ids[0] as i64);
+        assert_eq!(
+            sessions
+                .iter()
+                .map(|s| s.metadata.user_agent.as_deref().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["Firefox", "Chrome"]
+        );
+        assert!(handler
+            .list_sessions(&UserId::new("patrick"))
+            .await
+            .unwrap()
+            .is_empty());
+        // The sessions of a user can't be ended by ID for another user.
+        handler
+            .delete_session(&UserId::new("patrick"), sessions[0].session_id)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            handler
+                .delete_session(&bob, sessions[0].session_id)
+                .await
+                .unwrap(),
+            HashSet::from([1, 2])
+        );
+        handler
+            .delete_session(&bob, sessions[0].session_id)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            handler.list_sessions(&bob).await.unwrap(),
+            vec![sessions[1].clone()]
+        );
+        assert_eq!(
+            handler.delete_sessions(&bob).await.unwrap(),
+            HashSet::from([3])
+        );
+        assert!(handler.list_sessions(&bob).await.unwrap().is_empty());
+        assert_eq!(
+            handler.get_jwt_blacklist().await.unwrap(),
+            HashSet::from([1, 2, 3])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_sync_entries() {
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool);
+        let uuid = |s: &str| Uuid::try_from(s).unwrap();
+        let bob = uuid("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8");
+        let group = uuid("b1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8");
+        let john = uuid("c1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8");
+        let record = |digests: &[(&Uuid, &str)]| {
+            handler.record_sync_entries(
+                digests
+                    .iter()
+                    .map(|(uuid, digest)| ((*uuid).clone(), digest.to_string()))
+                    .collect(),
+            )
+        };
+        let first = record(&[(&bob, "1"), (&group, "1")]).await.unwrap();
+        assert_eq!(first.change_sequence, 1);
+        assert_eq!(first.entries[&bob], 1);
+        // Nothing changed.
+        assert_eq!(record(&[(&bob, "1"), (&group, "1")]).await.unwrap(), first);
+        let second = record(&[(&bob, "1"), (&group, "2"), (&john, "1")])
+            .await
+            .unwrap();
+        assert_eq!(second.change_sequence, 2);
+        assert_eq!(second.entries[&bob], 1);
+        assert_eq!(second.entries[&group], 2);
+        assert_eq!(second.entries[&john], 2);
+        // A deleted entry that comes back is new again.
+        record(&[(&bob, "1"), (&group, "2")]).await.unwrap();
+        let third = record(&[(&bob, "1"), (&group, "2"), (&john, "1")])
+            .await
+            .unwrap();
+        assert_eq!(third.change_sequence, 3);
+        assert_eq!(third.entries[&john], 3);
+    }
+
+    #[tokio::test]
+    async fn test_audit_logs() {
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool);
+        let event = |actor: &str, action: &str, success: bool| AuditEvent {
+            actor: Some(UserId::new(actor)),
+            action: action.to_string(),
+            target: Some("bob".to_string()),
+            ip_address: Some("192.0.2.1".to_string()),
+            success,
+            details: None,
+        };
+        handler
+            .record_audit_event(event("admin", "createUser", true))
+            .await
+            .unwrap();
+        handler
+            .record_audit_event(event("bob", "login", false))
+            .await
+            .unwrap();
+        handler
+            .record_audit_event(event("bob", "login", true))
+            .await
+            .unwrap();
+        handler
+            .record_audit_event(AuditEvent {
+                action: "login".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let all = handler
+            .list_audit_logs(AuditLogFilter::default(), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(all.total_count, 4);
+        // Newest first.
+        assert_eq!(all.items[0].actor, None);
+        assert_eq!(all.items[3].action, "createUser");
+        assert_eq!(all.items[3].ip_address.as_deref(), Some("192.0.2.1"));
+
+        let bob_logins = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    actor: Some(UserId::new("bob")),
+                    action: Some("login".to_string()),
+                    ..Default::default()
+                },
+                0,
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(bob_logins.total_count, 2);
+        assert_eq!(bob_logins.items.len(), 1);
+        assert!(bob_logins.items[0].success);
+
+        let failures = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    success: Some(false),
+                    until: Some(chrono::Utc::now()),
+                    ..Default::default()
+                },
+                0,
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(failures.total_count, 1);
+        assert_eq!(failures.items[0].actor, Some(UserId::new("bob")));
+        let future = handler
+            .list_audit_logs(
+                AuditLogFilter {
+                    since: Some(chrono::Utc::now() + chrono::Duration::days(1)),
+                    ..Default::default()
+                },
+                0,
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(future.total_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_webhooks() {
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlBackendHandler::new(get_default_config(), sql_pool);
+        let webhook = handler
+            .create_webhook(
+                "https://example.com/hook",
+                &[WebhookEvent::UserCreated, WebhookEvent::PasswordChanged],
+            )
+            .await
+            .unwrap();
+        assert_eq!(webhook.url, "https://example.com/hook");
+        assert_eq!(webhook.secret.len(), 32);
+        assert_eq!(
+            webhook.events,
+            vec![WebhookEvent::UserCreated, WebhookEvent::PasswordChanged]
+        );
+        let other = handler
+            .create_webhook("https://example.com/other", &[WebhookEvent::UserDeleted])
+            .await
+            .unwrap();
+        assert_ne!(webhook.secret, other.secret);
+        assert_eq!(
+            handler.list_webhooks().await.unwrap(),
+            vec![webhook.clone(), other.clone()]
+        );
+        handler.delete_webhook(webhook.webhook_id).await.unwrap();
+        handler
+            .delete_webhook(webhook.webhook_id)
+            .await
+            .unwrap_err();
+        assert_eq!(handler.list_webhooks().await.unwrap(), vec![other]);
+    }
 }
@@ -1,19 +1,58 @@
 use crate::domain::{
     error::{DomainError, Result},
-    handler::{GroupBackendHandler, GroupRequestFilter, UpdateGroupRequest},
-    model::{self, GroupColumn, MembershipColumn},
+    handler::{
+        GroupBackendHandler, GroupNesting, GroupRequestFilter, Page, PageRequest,
+        UpdateGroupRequest, UserRequestFilter,
+    },
+    ldap::filter::parse_user_filter,
+    model::{
+        self, GroupAttributeColumn, GroupAttributeSchemaColumn, GroupColumn, GroupManagerColumn,
+        GroupNestingColumn, MembershipColumn, MembershipRequestColumn, UserColumn, UserEmailColumn,
+    },
     sql_backend_handler::SqlBackendHandler,
-    types::{Group, GroupDetails, GroupId, Uuid},
+    sql_user_backend_handler::{
+        get_case_sensitive_substring_expr, get_users_condition, insert_membership,
+        normalize_attribute_values, touch_group,
+    },
+    types::{
+        AttributeSchema, AttributeValue, DirectoryChange, Group, GroupDetails, GroupId,
+        MembershipRequest, UserId, Uuid,
+    },
 };
 use async_trait::async_trait;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
-    QueryTrait,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbBackend, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait,
 };
-use sea_query::{Cond, IntoCondition, SimpleExpr};
+use sea_query::{Cond, Expr, Func, IntoCondition, LikeExpr, Order, SimpleExpr};
+use std::collections::{HashMap, HashSet};
 use tracing::{debug, instrument};
 
-fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
+/// Matches the values of the custom attributes, respecting the case only for the ones defined as
+/// case-sensitive in the schema.
+fn get_attribute_value_condition(case_sensitive: SimpleExpr, case_insensitive: SimpleExpr) -> Cond {
+    let case_sensitive_attributes = model::GroupAttributeSchema::find()
+        .select_only()
+        .column(GroupAttributeSchemaColumn::AttributeName)
+        .filter(GroupAttributeSchemaColumn::IsCaseSensitive.eq(true))
+        .into_query();
+    Cond::any()
+        .add(
+            Cond::all()
+                .add(
+                    GroupAttributeColumn::AttributeName
+                        .in_subquery(case_sensitive_attributes.clone()),
+                )
+                .add(case_sensitive),
+        )
+        .add(
+            Cond::all()
+                .add(GroupAttributeColumn::AttributeName.not_in_subquery(case_sensitive_attributes))
+                .add(case_insensitive),
+        )
+}
+
+fn get_group_filter_expr(filter: GroupRequestFilter, backend: DbBackend) -> Cond {
     use GroupRequestFilter::*;
     match filter {
         And(fs) => {
@@ -21,7 +60,7 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
                 SimpleExpr::Value(true.into()).into_condition()
             } else {
                 fs.into_iter()
-                    .fold(Cond::all(), |c, f| c.add(get_group_filter_expr(f)))
+                    .fold(Cond::all(), |c, f| c.add(get_group_filter_expr(f, backend)))
             }
         }
         Or(fs) => {
@@ -29,11 +68,16 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
                 SimpleExpr::Value(false.into()).into_condition()
             } else {
                 fs.into_iter()
-                    .fold(Cond::any(), |c, f| c.add(get_group_filter_expr(f)))
+                    .fold(Cond::any(), |c, f| c.add(get_group_filter_expr(f, backend)))
             }
         }
-        Not(f) => get_group_filter_expr(*f).not(),
+        Not(f) => get_group_filter_expr(*f, backend).not(),
         DisplayName(name) => GroupColumn::DisplayName.eq(name).into_condition(),
+        DisplayNameSubstring(filter) => {
+            Expr::expr(Func::lower(Expr::col(GroupColumn::DisplayName)))
+                .like(LikeExpr::str(&filter.to_sql_like_pattern()).escape('\\'))
+                .into_condition()
+        }
         GroupId(id) => GroupColumn::GroupId.eq(id.0).into_condition(),
         Uuid(uuid) => GroupColumn::Uuid.eq(uuid.to_string()).into_condition(),
         // WHERE (group_id in (SELECT group_id FROM memberships WHERE user_id = user))
@@ -46,37 +90,357 @@ fn get_group_filter_expr(filter: GroupRequestFilter) -> Cond {
                     .into_query(),
             )
             .into_condition(),
+        Manager(user) => GroupColumn::GroupId
+            .in_subquery(
+                model::GroupManager::find()
+                    .select_only()
+                    .column(GroupManagerColumn::GroupId)
+                    .filter(GroupManagerColumn::UserId.eq(user))
+                    .into_query(),
+            )
+            .into_condition(),
+        Archived(archived) => GroupColumn::Archived.eq(archived).into_condition(),
+        GidNumber(gid) => GroupColumn::GidNumber.eq(gid).into_condition(),
+        Email(email) => Expr::expr(Func::lower(Expr::col(GroupColumn::Email)))
+            .eq(email.to_lowercase())
+            .into_condition(),
+        AttributeEquality(name, value) => {
+            let owners = model::GroupAttribute::find()
+                .select_only()
+                .column(GroupAttributeColumn::GroupId)
+                .filter(GroupAttributeColumn::AttributeName.eq(name))
+                .filter(get_attribute_value_condition(
+                    GroupAttributeColumn::Value.eq(value.clone()),
+                    Expr::expr(Func::lower(Expr::col(GroupAttributeColumn::Value)))
+                        .eq(value.to_lowercase()),
+                ))
+                .into_query();
+            GroupColumn::GroupId.in_subquery(owners).into_condition()
+        }
+        AttributeSubstring(name, filter) => {
+            let owners = model::GroupAttribute::find()
+                .select_only()
+                .column(GroupAttributeColumn::GroupId)
+                .filter(GroupAttributeColumn::AttributeName.eq(name))
+                .filter(get_attribute_value_condition(
+                    get_case_sensitive_substring_expr(
+                        backend,
+                        GroupAttributeColumn::Value,
+                        &filter,
+                    ),
+                    Expr::expr(Func::lower(Expr::col(GroupAttributeColumn::Value)))
+                        .like(LikeExpr::str(&filter.to_sql_like_pattern()).escape('\\')),
+                ))
+                .into_query();
+            GroupColumn::GroupId.in_subquery(owners).into_condition()
+        }
+        AttributePresent(name) => {
+            let owners = model::GroupAttribute::find()
+                .select_only()
+                .column(GroupAttributeColumn::GroupId)
+                .filter(GroupAttributeColumn::AttributeName.eq(name))
+                .into_query();
+            GroupColumn::GroupId.in_subquery(owners).into_condition()
+        }
+    }
+}
+
+fn get_member_filter_users(filter: &GroupRequestFilter, users: &mut Vec<UserId>) {
+    use GroupRequestFilter::*;
+    match filter {
+        And(fs) | Or(fs) => fs.iter().for_each(|f| get_member_filter_users(f, users)),
+        Not(f) => get_member_filter_users(f, users),
+        Member(user) => users.push(user.clone()),
+        DisplayName(_)
+        | DisplayNameSubstring(_)
+        | Uuid(_)
+        | GroupId(_)
+        | Manager(_)
+        | Archived(_)
+        | GidNumber(_)
+        | Email(_)
+        | AttributeEquality(_, _)
+        | AttributeSubstring(_, _)
+        | AttributePresent(_) => (),
+    }
+}
+
+/// Makes the `Member` filters also match the dynamic groups of the user, given as a map from
+/// user ID to dynamic groups.
+fn add_dynamic_groups_to_filter(
+    filter: GroupRequestFilter,
+    dynamic_groups: &HashMap<String, Vec<GroupId>>,
+) -> GroupRequestFilter {
+    use GroupRequestFilter::*;
+    let rec = |f| add_dynamic_groups_to_filter(f, dynamic_groups);
+    match filter {
+        And(fs) => And(fs.into_iter().map(rec).collect()),
+        Or(fs) => Or(fs.into_iter().map(rec).collect()),
+        Not(f) => Not(Box::new(rec(*f))),
+        Member(user) => match dynamic_groups.get(user.as_str()) {
+            Some(group_ids) if !group_ids.is_empty() => Or(std::iter::once(Member(user))
+                .chain(group_ids.iter().copied().map(GroupId))
+                .collect()),
+            _ => Member(user),
+        },
+        f => f,
+    }
+}
+
+/// Checks that no other group, and no user, has the email address. The users' addresses include
+/// their aliases.
+async fn check_group_email_available(
+    connection: &impl ConnectionTrait,
+    group_id: GroupId,
+    email: &str,
+) -> Result<()> {
+    let email = email.to_lowercase();
+    if let Some(group) = model::Group::find()
+        .filter(Expr::expr(Func::lower(Expr::col(GroupColumn::Email))).eq(email.clone()))
+        .filter(GroupColumn::GroupId.ne(group_id))
+        .one(connection)
+        .await?
+    {
+        return Err(DomainError::EntityAlreadyExists(format!(
+            "Email address already used by the group '{}'",
+            group.display_name
+        )));
+    }
+    let user_owner = match model::User::find()
+        .filter(Expr::expr(Func::lower(Expr::col(UserColumn::Email))).eq(email.clone()))
+        .one(connection)
+        .await?
+    {
+        Some(user) => Some(user.user_id),
+        None => model::UserEmail::find()
+            .filter(Expr::expr(Func::lower(Expr::col(UserEmailColumn::Email))).eq(email))
+            .one(connection)
+            .await?
+            .map(|alias| alias.user_id),
+    };
+    match user_owner {
+        Some(owner) => Err(DomainError::EntityAlreadyExists(format!(
+            "Email address already used by '{}'",
+            owner
+        ))),
+        None => Ok(()),
     }
 }
 
+impl SqlBackendHandler {
+    /// The dynamic groups, with the filter defining their members.
+    pub(crate) async fn get_dynamic_groups(
+        &self,
+    ) -> Result<Vec<(GroupDetails, UserRequestFilter)>> {
+        model::Group::find()
+            .filter(GroupColumn::DynamicFilter.is_not_null())
+            .order_by_asc(GroupColumn::GroupId)
+            .into_model::<GroupDetails>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|group| {
+                let filter = parse_user_filter(group.dynamic_filter.as_deref().unwrap_or_default())
+                    .map_err(|e| {
+                        DomainError::InternalError(format!(
+                            "Invalid filter for dynamic group '{}': {}",
+                            group.display_name, e
+                        ))
+                    })?;
+                Ok((group, filter))
+            })
+            .collect()
+    }
+
+    pub(crate) async fn get_dynamic_group_members(
+        &self,
+        filter: &UserRequestFilter,
+    ) -> Result<Vec<UserId>> {
+        Ok(self
+            .list_users_without_dynamic_groups(Some(filter.clone()), false, &[])
+            .await?
+            .into_iter()
+            .map(|u| u.user.user_id)
+            .collect())
+    }
+
+    /// The dynamic groups whose filter matches the user, all evaluated in a single query.
+    pub(crate) async fn get_matching_dynamic_groups(
+        &self,
+        user_id: &UserId,
+        dynamic_groups: &[(GroupDetails, UserRequestFilter)],
+    ) -> Result<Vec<GroupDetails>> {
+        if dynamic_groups.is_empty() {
+            return Ok(Vec::new());
+        }
+        let backend = self.sql_pool.get_database_backend();
+        let is_matching_group =
+            dynamic_groups
+                .iter()
+                .fold(Cond::any(), |condition, (group, filter)| {
+                    let matching_user = model::User::find()
+                        .select_only()
+                        .column(UserColumn::UserId)
+                        .filter(UserColumn::UserId.eq(user_id.clone()))
+                        .filter(get_users_condition(Some(filter.clone()), backend))
+                        .into_query();
+                    condition.add(
+                        Cond::all()
+                            .add(GroupColumn::GroupId.eq(group.group_id))
+                            .add(Expr::exists(matching_user)),
+                    )
+                });
+        Ok(model::Group::find()
+            .filter(is_matching_group)
+            .order_by_asc(GroupColumn::GroupId)
+            .into_model::<GroupDetails>()
+            .all(&self.sql_pool)
+            .await?)
+    }
+
+    /// Adds the dynamic groups of the users in the membership filters, since they are not in the
+    /// memberships table.
+    async fn resolve_dynamic_memberships(
+        &self,
+        filters: Option<GroupRequestFilter>,
+        dynamic_groups: &[(GroupDetails, UserRequestFilter)],
+    ) -> Result<Option<GroupRequestFilter>> {
+        match filters {
+            Some(filter) if !dynamic_groups.is_empty() => {
+                let mut users = Vec::new();
+                get_member_filter_users(&filter, &mut users);
+                let mut user_dynamic_groups = HashMap::new();
+                for user in users {
+                    let groups = self
+                        .get_matching_dynamic_groups(&user, dynamic_groups)
+                        .await?;
+                    user_dynamic_groups.insert(
+                        user.into_string(),
+                        groups.into_iter().map(|g| g.group_id).collect(),
+                    );
+                }
+                Ok(Some(add_dynamic_groups_to_filter(
+                    filter,
+                    &user_dynamic_groups,
+                )))
+            }
+            filters => Ok(filters),
+        }
+    }
+}
+
+async fn get_attribute_schema(connection: &impl ConnectionTrait) -> Result<Vec<AttributeSchema>> {
+    model::GroupAttributeSchema::find()
+        .order_by_asc(GroupAttributeSchemaColumn::AttributeName)
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(AttributeSchema::try_from)
+        .collect()
+}
+
+/// The custom attributes of the groups, by group.
+async fn get_attributes(
+    connection: &impl ConnectionTrait,
+    group_ids: Vec<GroupId>,
+) -> Result<HashMap<GroupId, Vec<AttributeValue>>> {
+    let mut attributes = HashMap::<GroupId, Vec<AttributeValue>>::new();
+    for value in model::GroupAttribute::find()
+        .filter(GroupAttributeColumn::GroupId.is_in(group_ids))
+        .order_by_asc(GroupAttributeColumn::AttributeName)
+        .order_by_asc(GroupAttributeColumn::ValueId)
+        .all(connection)
+        .await?
+    {
+        let group_attributes = attributes.entry(value.group_id).or_default();
+        match group_attributes.last_mut() {
+            Some(last) if last.name == value.attribute_name => last.values.push(value.value),
+            _ => group_attributes.push(AttributeValue {
+                name: value.attribute_name,
+                values: vec![value.value],
+            }),
+        }
+    }
+    Ok(attributes)
+}
+
+/// Replaces the values of the given attributes, already normalized.
+async fn replace_attributes(
+    connection: &impl ConnectionTrait,
+    group_id: GroupId,
+    attributes: Vec<AttributeValue>,
+) -> Result<()> {
+    if attributes.is_empty() {
+        return Ok(());
+    }
+    model::GroupAttribute::delete_many()
+        .filter(GroupAttributeColumn::GroupId.eq(group_id))
+        .filter(
+            GroupAttributeColumn::AttributeName.is_in(attributes.iter().map(|a| a.name.clone())),
+        )
+        .exec(connection)
+        .await?;
+    let values = attributes
+        .into_iter()
+        .flat_map(|attribute| {
+            let name = attribute.name;
+            attribute
+                .values
+                .into_iter()
+                .map(move |value| model::group_attributes::ActiveModel {
+                    group_id: ActiveValue::Set(group_id),
+                    attribute_name: ActiveValue::Set(name.clone()),
+                    value: ActiveValue::Set(value),
+                    ..Default::default()
+                })
+        })
+        .collect::<Vec<_>>();
+    if !values.is_empty() {
+        model::GroupAttribute::insert_many(values)
+            .exec(connection)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The groups matching the filters, through a subquery since the filters can join the users.
+fn get_groups_condition(filters: Option<GroupRequestFilter>, backend: DbBackend) -> Cond {
+    filters
+        .map(|f| {
+            GroupColumn::GroupId
+                .in_subquery(
+                    model::Group::find()
+                        .find_also_linked(model::memberships::GroupToUser)
+                        .select_only()
+                        .column(GroupColumn::GroupId)
+                        .filter(get_group_filter_expr(f, backend))
+                        .into_query(),
+                )
+                .into_condition()
+        })
+        .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition())
+}
+
 #[async_trait]
 impl GroupBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", ret, err)]
     async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>> {
         debug!(?filters);
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        let filters = self
+            .resolve_dynamic_memberships(filters, &dynamic_groups)
+            .await?;
         let results = model::Group::find()
             // The order_by must be before find_with_related otherwise the primary order is by group_id.
             .order_by_asc(GroupColumn::DisplayName)
             .find_with_related(model::Membership)
-            .filter(
-                filters
-                    .map(|f| {
-                        GroupColumn::GroupId
-                            .in_subquery(
-                                model::Group::find()
-                                    .find_also_linked(model::memberships::GroupToUser)
-                                    .select_only()
-                                    .column(GroupColumn::GroupId)
-                                    .filter(get_group_filter_expr(f))
-                                    .into_query(),
-                            )
-                            .into_condition()
-                    })
-                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
-            )
+            .filter(get_groups_condition(
+                filters,
+                self.sql_pool.get_database_backend(),
+            ))
             .all(&self.sql_pool)
             .await?;
-        Ok(results
+        let mut groups = results
             .into_iter()
             .map(|(group, users)| {
                 let users: Vec<_> = users.into_iter().map(|u| u.user_id).collect();
@@ -85,7 +449,52 @@ impl GroupBackendHandler for SqlBackendHandler {
                     ..group.into()
                 }
             })
-            .collect())
+            .collect::<Vec<_>>();
+        let mut attributes =
+            get_attributes(&self.sql_pool, groups.iter().map(|g| g.id).collect()).await?;
+        for group in groups.iter_mut() {
+            let dynamic_group = dynamic_groups.iter().find(|(g, _)| g.group_id == group.id);
+            if let Some((_, filter)) = dynamic_group {
+                group.users = self.get_dynamic_group_members(filter).await?;
+            }
+            group.attributes = attributes.remove(&group.id).unwrap_or_default();
+        }
+        Ok(groups)
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_groups_page(
+        &self,
+        filters: Option<GroupRequestFilter>,
+        page: PageRequest<GroupColumn>,
+    ) -> Result<Page<GroupDetails>> {
+        debug!(?filters, ?page);
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        let filters = self
+            .resolve_dynamic_memberships(filters, &dynamic_groups)
+            .await?;
+        let query = model::Group::find().filter(get_groups_condition(
+            filters,
+            self.sql_pool.get_database_backend(),
+        ));
+        let total_count = query.clone().count(&self.sql_pool).await? as u64;
+        let order = if page.descending {
+            Order::Desc
+        } else {
+            Order::Asc
+        };
+        let groups = query
+            .order_by(page.order_by, order.clone())
+            .order_by(GroupColumn::GroupId, order)
+            .offset(page.offset)
+            .limit(page.limit)
+            .into_model::<GroupDetails>()
+            .all(&self.sql_pool)
+            .await?;
+        Ok(Page {
+            items: groups,
+            total_count,
+        })
     }
 
     #[instrument(skip_all, level = "debug", ret, err)]
@@ -101,15 +510,42 @@ impl GroupBackendHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", err)]
     async fn update_group(&self, request: UpdateGroupRequest) -> Result<()> {
         debug!(?request.group_id);
+        let transaction = self.sql_pool.begin().await?;
+        let attributes = if request.attributes.is_empty() {
+            Vec::new()
+        } else {
+            normalize_attribute_values(
+                &get_attribute_schema(&transaction).await?,
+                request.attributes,
+            )?
+        };
+        let email = match request.email {
+            None => ActiveValue::NotSet,
+            Some(email) if email.is_empty() => ActiveValue::Set(None),
+            Some(email) => {
+                check_group_email_available(&transaction, request.group_id, &email).await?;
+                ActiveValue::Set(Some(email))
+            }
+        };
         let update_group = model::groups::ActiveModel {
             group_id: ActiveValue::Set(request.group_id),
             display_name: request
                 .display_name
                 .map(ActiveValue::Set)
                 .unwrap_or_default(),
+            archived: request.archived.map(ActiveValue::Set).unwrap_or_default(),
+            gid_number: request
+                .gid_number
+                .map(|gid| ActiveValue::Set(Some(gid)))
+                .unwrap_or_default(),
+            email,
+            modified_date: ActiveValue::Set(chrono::Utc::now()),
             ..Default::default()
         };
-        update_group.update(&self.sql_pool).await?;
+        update_group.update(&transaction).await?;
+        replace_attributes(&transaction, request.group_id, attributes).await?;
+        transaction.commit().await?;
+        self.notify_change(DirectoryChange::GroupUpdated(request.group_id));
         Ok(())
     }
 
@@ -121,10 +557,34 @@ impl GroupBackendHandler for SqlBackendHandler {
         let new_group = model::groups::ActiveModel {
             display_name: ActiveValue::Set(group_name.to_owned()),
             creation_date: ActiveValue::Set(now),
+            modified_date: ActiveValue::Set(now),
+            uuid: ActiveValue::Set(uuid),
+            ..Default::default()
+        };
+        let group_id = new_group.insert(&self.sql_pool).await?.group_id;
+        self.notify_change(DirectoryChange::GroupCreated(group_id));
+        Ok(group_id)
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn create_dynamic_group(&self, group_name: &str, filter: &str) -> Result<GroupId> {
+        debug!(?group_name, ?filter);
+        parse_user_filter(filter).map_err(|e| {
+            DomainError::InvalidInput(format!("Invalid dynamic group filter: {}", e))
+        })?;
+        let now = chrono::Utc::now();
+        let uuid = Uuid::from_name_and_date(group_name, &now);
+        let new_group = model::groups::ActiveModel {
+            display_name: ActiveValue::Set(group_name.to_owned()),
+            creation_date: ActiveValue::Set(now),
+            modified_date: ActiveValue::Set(now),
             uuid: ActiveValue::Set(uuid),
+            dynamic_filter: ActiveValue::Set(Some(filter.trim().to_owned())),
             ..Default::default()
         };
-        Ok(new_group.insert(&self.sql_pool).await?.group_id)
+        let group_id = new_group.insert(&self.sql_pool).await?.group_id;
+        self.notify_change(DirectoryChange::GroupCreated(group_id));
+        Ok(group_id)
     }
 
     #[instrument(skip_all, level = "debug", err)]
@@ -139,6 +599,405 @@ impl GroupBackendHandler for SqlBackendHandler {
                 group_id
             )));
         }
+        self.notify_change(DirectoryChange::GroupDeleted(group_id));
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_groups(&self, group_ids: &[GroupId]) -> Result<()> {
+        debug!(?group_ids);
+        // Dropping the transaction without committing rolls back the deletions.
+        let transaction = self.sql_pool.begin().await?;
+        let mut missing_groups = Vec::new();
+        for &group_id in group_ids {
+            let res = model::Group::delete_by_id(group_id)
+                .exec(&transaction)
+                .await?;
+            if res.rows_affected == 0 {
+                missing_groups.push(group_id);
+            }
+        }
+        if !missing_groups.is_empty() {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such groups: '{:?}'",
+                missing_groups
+            )));
+        }
+        transaction.commit().await?;
+        for &group_id in group_ids {
+            self.notify_change(DirectoryChange::GroupDeleted(group_id));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_group_to_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()> {
+        debug!(?group_id, ?parent_group_id);
+        // Walk up from the parent: finding the child on the way means the nesting would create a
+        // cycle.
+        let nestings = model::GroupNesting::find().all(&self.sql_pool).await?;
+        if nestings
+            .iter()
+            .any(|n| n.child_group_id == group_id && n.parent_group_id == parent_group_id)
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "{:?} is already in {:?}",
+                group_id, parent_group_id
+            )));
+        }
+        let mut ancestors = HashSet::new();
+        let mut to_visit = vec![parent_group_id];
+        while let Some(current) = to_visit.pop() {
+            if current == group_id {
+                return Err(DomainError::InternalError(format!(
+                    "Cannot add group {:?} to group {:?}: it would contain itself",
+                    group_id, parent_group_id
+                )));
+            }
+            if ancestors.insert(current) {
+                to_visit.extend(
+                    nestings
+                        .iter()
+                        .filter(|n| n.child_group_id == current)
+                        .map(|n| n.parent_group_id),
+                );
+            }
+        }
+        let new_nesting = model::group_nestings::ActiveModel {
+            parent_group_id: ActiveValue::Set(parent_group_id),
+            child_group_id: ActiveValue::Set(group_id),
+        };
+        new_nesting.insert(&self.sql_pool).await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_group_from_group(
+        &self,
+        group_id: GroupId,
+        parent_group_id: GroupId,
+    ) -> Result<()> {
+        debug!(?group_id, ?parent_group_id);
+        let res = model::GroupNesting::delete_by_id((parent_group_id, group_id))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such nesting: {:?} -> {:?}",
+                group_id, parent_group_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_group_nestings(&self) -> Result<Vec<GroupNesting>> {
+        let nestings = model::GroupNesting::find()
+            .order_by_asc(GroupNestingColumn::ChildGroupId)
+            .order_by_asc(GroupNestingColumn::ParentGroupId)
+            .all(&self.sql_pool)
+            .await?;
+        if nestings.is_empty() {
+            return Ok(Vec::new());
+        }
+        let groups = model::Group::find()
+            .filter(GroupColumn::GroupId.is_in(nestings.iter().map(|n| n.parent_group_id)))
+            .into_model::<GroupDetails>()
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|g| (g.group_id, g))
+            .collect::<HashMap<_, _>>();
+        Ok(nestings
+            .into_iter()
+            .filter_map(|n| {
+                Some(GroupNesting {
+                    child_group_id: n.child_group_id,
+                    parent_group: groups.get(&n.parent_group_id)?.clone(),
+                })
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>> {
+        debug!(?group_id);
+        Ok(model::GroupManager::find()
+            .filter(GroupManagerColumn::GroupId.eq(group_id))
+            .order_by_asc(GroupManagerColumn::UserId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(|m| m.user_id)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        debug!(?group_id, ?user_id);
+        if model::Group::find_by_id(group_id)
+            .one(&self.sql_pool)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such group: {:?}",
+                group_id
+            )));
+        }
+        if model::User::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such user: '{}'",
+                user_id
+            )));
+        }
+        if model::GroupManager::find_by_id((group_id, user_id.clone()))
+            .one(&self.sql_pool)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "'{}' already manages {:?}",
+                user_id, group_id
+            )));
+        }
+        let new_manager = model::group_managers::ActiveModel {
+            group_id: ActiveValue::Set(group_id),
+            user_id: ActiveValue::Set(user_id.clone()),
+        };
+        new_manager.insert(&self.sql_pool).await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()> {
+        debug!(?group_id, ?user_id);
+        let res = model::GroupManager::delete_by_id((group_id, user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "'{}' doesn't manage {:?}",
+                user_id, group_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_membership_request(
+        &self,
+        group_id: GroupId,
+        user_id: &UserId,
+        requested_by: &UserId,
+    ) -> Result<MembershipRequest> {
+        debug!(?group_id, ?user_id, ?requested_by);
+        let now = chrono::Utc::now();
+        let transaction = self.sql_pool.begin().await?;
+        if model::Membership::find_by_id((user_id.clone(), group_id))
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "'{}' is already a member of {:?}",
+                user_id, group_id
+            )));
+        }
+        // An expired request doesn't prevent a new one.
+        model::MembershipRequest::delete_many()
+            .filter(MembershipRequestColumn::GroupId.eq(group_id))
+            .filter(MembershipRequestColumn::UserId.eq(user_id.clone()))
+            .filter(MembershipRequestColumn::ExpiryDate.lte(now))
+            .exec(&transaction)
+            .await?;
+        if model::MembershipRequest::find()
+            .filter(MembershipRequestColumn::GroupId.eq(group_id))
+            .filter(MembershipRequestColumn::UserId.eq(user_id.clone()))
+            .one(&transaction)
+            .await?
+            .is_some()
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "'{}' already asked to join {:?}",
+                user_id, group_id
+            )));
+        }
+        if model::Group::find_by_id(group_id)
+            .one(&transaction)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such group: {:?}",
+                group_id
+            )));
+        }
+        for user in [user_id, requested_by] {
+            if model::User::find_by_id(user.clone())
+                .one(&transaction)
+                .await?
+                .is_none()
+            {
+                return Err(DomainError::EntityNotFound(format!(
+                    "No such user: '{}'",
+                    user
+                )));
+            }
+        }
+        let validity = chrono::Duration::days(self.config.membership_request_validity_days.into());
+        let request = model::membership_requests::ActiveModel {
+            group_id: ActiveValue::Set(group_id),
+            user_id: ActiveValue::Set(user_id.clone()),
+            requested_by: ActiveValue::Set(requested_by.clone()),
+            creation_date: ActiveValue::Set(now),
+            expiry_date: ActiveValue::Set(now + validity),
+            ..Default::default()
+        }
+        .insert(&transaction)
+        .await?;
+        transaction.commit().await?;
+        Ok(request.into())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_membership_requests(
+        &self,
+        group_id: Option<GroupId>,
+    ) -> Result<Vec<MembershipRequest>> {
+        debug!(?group_id);
+        let mut query = model::MembershipRequest::find()
+            .filter(MembershipRequestColumn::ExpiryDate.gt(chrono::Utc::now()));
+        if let Some(group_id) = group_id {
+            query = query.filter(MembershipRequestColumn::GroupId.eq(group_id));
+        }
+        Ok(query
+            .order_by_asc(MembershipRequestColumn::CreationDate)
+            .order_by_asc(MembershipRequestColumn::RequestId)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_membership_request(&self, request_id: i32) -> Result<MembershipRequest> {
+        debug!(?request_id);
+        model::MembershipRequest::find_by_id(request_id)
+            .filter(MembershipRequestColumn::ExpiryDate.gt(chrono::Utc::now()))
+            .one(&self.sql_pool)
+            .await?
+            .map(Into::into)
+            .ok_or_else(|| {
+                DomainError::EntityNotFound(format!("No such membership request: {}", request_id))
+            })
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn approve_membership_request(&self, request_id: i32) -> Result<()> {
+        debug!(?request_id);
+        let transaction = self.sql_pool.begin().await?;
+        let request = model::MembershipRequest::find_by_id(request_id)
+            .filter(MembershipRequestColumn::ExpiryDate.gt(chrono::Utc::now()))
+            .one(&transaction)
+            .await?
+            .ok_or_else(|| {
+                DomainError::EntityNotFound(format!("No such membership request: {}", request_id))
+            })?;
+        model::MembershipRequest::delete_by_id(request_id)
+            .exec(&transaction)
+            .await?;
+        insert_membership(&transaction, &request.user_id, request.group_id).await?;
+        touch_group(&transaction, request.group_id).await?;
+        transaction.commit().await?;
+        self.notify_change(DirectoryChange::MembershipChanged {
+            user_id: request.user_id,
+            group_id: request.group_id,
+            added: true,
+        });
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_membership_request(&self, request_id: i32) -> Result<()> {
+        debug!(?request_id);
+        let res = model::MembershipRequest::delete_by_id(request_id)
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such membership request: {}",
+                request_id
+            )));
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn get_group_attributes(&self, group_id: GroupId) -> Result<Vec<AttributeValue>> {
+        debug!(?group_id);
+        Ok(get_attributes(&self.sql_pool, vec![group_id])
+            .await?
+            .remove(&group_id)
+            .unwrap_or_default())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_group_attribute_schema(&self) -> Result<Vec<AttributeSchema>> {
+        get_attribute_schema(&self.sql_pool).await
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()> {
+        debug!(?schema);
+        // The LDAP attribute names are case-insensitive.
+        if let Some(existing) = model::GroupAttributeSchema::find()
+            .filter(
+                Cond::any()
+                    .add(GroupAttributeSchemaColumn::AttributeName.eq(schema.name.clone()))
+                    .add(
+                        Expr::expr(Func::lower(Expr::col(GroupAttributeSchemaColumn::LdapName)))
+                            .eq(schema.ldap_name.to_lowercase()),
+                    ),
+            )
+            .one(&self.sql_pool)
+            .await?
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "The attribute '{}' already has this name or LDAP name",
+                existing.attribute_name
+            )));
+        }
+        model::group_attribute_schema::ActiveModel {
+            attribute_name: ActiveValue::Set(schema.name),
+            attribute_type: ActiveValue::Set(schema.attribute_type.as_str().to_owned()),
+            is_list: ActiveValue::Set(schema.is_list),
+            is_editable: ActiveValue::Set(schema.is_editable),
+            ldap_name: ActiveValue::Set(schema.ldap_name),
+            is_case_sensitive: ActiveValue::Set(schema.is_case_sensitive),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_group_attribute(&self, name: &str) -> Result<()> {
+        debug!(?name);
+        let res = model::GroupAttributeSchema::delete_by_id(name.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such attribute: '{}'",
+                name
+            )));
+        }
         Ok(())
     }
 }
@@ -146,7 +1005,11 @@ impl GroupBackendHandler for SqlBackendHandler {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::{sql_backend_handler::tests::*, types::UserId};
+    use crate::domain::{
+        handler::{SubStringFilter, UserBackendHandler},
+        sql_backend_handler::tests::*,
+        types::AttributeType,
+    };
 
     async fn get_group_ids(
         handler: &SqlBackendHandler,
@@ -187,6 +1050,47 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_groups_page() {
+        let fixture = TestFixture::new().await;
+        let page = fixture
+            .handler
+            .list_groups_page(
+                None,
+                PageRequest {
+                    order_by: GroupColumn::DisplayName,
+                    descending: false,
+                    offset: 1,
+                    limit: 1,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total_count, 3);
+        assert_eq!(
+            page.items
+                .into_iter()
+                .map(|g| g.display_name)
+                .collect::<Vec<_>>(),
+            vec!["Empty Group".to_owned()]
+        );
+        let page = fixture
+            .handler
+            .list_groups_page(
+                Some(GroupRequestFilter::Member(UserId::new("bob"))),
+                PageRequest {
+                    order_by: GroupColumn::GroupId,
+                    descending: true,
+                    offset: 0,
+                    limit: 10,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total_count, 1);
+        assert_eq!(page.items[0].group_id, fixture.groups[0]);
+    }
+
     #[tokio::test]
     async fn test_list_groups_simple_filter() {
         let fixture = TestFixture::new().await;
@@ -203,6 +1107,23 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_groups_substring_filter() {
+        let fixture = TestFixture::new().await;
+        assert_eq!(
+            get_group_names(
+                &fixture.handler,
+                Some(GroupRequestFilter::DisplayNameSubstring(SubStringFilter {
+                    any: vec!["st".to_owned()],
+                    final_: Some("group".to_owned()),
+                    ..Default::default()
+                }))
+            )
+            .await,
+            vec!["Best Group".to_owned(), "Worst Group".to_owned()]
+        );
+    }
+
     #[tokio::test]
     async fn test_list_groups_negation() {
         let fixture = TestFixture::new().await;
@@ -249,6 +1170,10 @@ mod tests {
             .update_group(UpdateGroupRequest {
                 group_id: fixture.groups[0],
                 display_name: Some("Awesomest Group".to_owned()),
+                archived: None,
+                gid_number: None,
+                email: None,
+                attributes: Vec::new(),
             })
             .await
             .unwrap();
@@ -258,6 +1183,182 @@ mod tests {
             .await
             .unwrap();
         assert_eq!(details.display_name, "Awesomest Group");
+        assert!(!details.archived);
+    }
+
+    #[tokio::test]
+    async fn test_update_group_email() {
+        let fixture = TestFixture::new().await;
+        let set_email = |group_id, email: &str| {
+            fixture.handler.update_group(UpdateGroupRequest {
+                group_id,
+                display_name: None,
+                archived: None,
+                gid_number: None,
+                email: Some(email.to_owned()),
+                attributes: Vec::new(),
+            })
+        };
+        set_email(fixture.groups[0], "Team@example.com")
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(
+                &fixture.handler,
+                Some(GroupRequestFilter::Email("team@EXAMPLE.com".to_owned()))
+            )
+            .await,
+            vec![fixture.groups[0]]
+        );
+        // The address is unique, regardless of the case.
+        assert!(matches!(
+            set_email(fixture.groups[1], "team@example.com").await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        assert!(matches!(
+            set_email(fixture.groups[1], "BOB@bob.bob").await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        // Setting the same address again is fine.
+        set_email(fixture.groups[0], "team@example.com")
+            .await
+            .unwrap();
+        set_email(fixture.groups[0], "").await.unwrap();
+        let details = fixture
+            .handler
+            .get_group_details(fixture.groups[0])
+            .await
+            .unwrap();
+        assert_eq!(details.email, None);
+    }
+
+    #[tokio::test]
+    async fn test_archive_group() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                display_name: None,
+                archived: Some(true),
+                gid_number: None,
+                email: None,
+                attributes: Vec::new(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(&fixture.handler, Some(GroupRequestFilter::Archived(false))).await,
+            vec![fixture.groups[2], fixture.groups[1]]
+        );
+        assert_eq!(
+            get_group_ids(&fixture.handler, Some(GroupRequestFilter::Archived(true))).await,
+            vec![fixture.groups[0]]
+        );
+        // Memberships are kept.
+        assert_eq!(
+            get_group_names(
+                &fixture.handler,
+                Some(GroupRequestFilter::Member(UserId::new("bob")))
+            )
+            .await,
+            vec!["Best Group"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_group() {
+        let fixture = TestFixture::new().await;
+        let filter = "(|(cn=Display Bob)(uid=john))";
+        let group_id = fixture
+            .handler
+            .create_dynamic_group("Bobs", filter)
+            .await
+            .unwrap();
+        let groups = fixture
+            .handler
+            .list_groups(Some(GroupRequestFilter::GroupId(group_id)))
+            .await
+            .unwrap();
+        assert_eq!(groups[0].dynamic_filter.as_deref(), Some(filter));
+        assert_eq!(
+            groups[0].users,
+            vec![UserId::new("bob"), UserId::new("john")]
+        );
+        assert_eq!(
+            get_group_names(
+                &fixture.handler,
+                Some(GroupRequestFilter::Member(UserId::new("bob")))
+            )
+            .await,
+            vec!["Best Group", "Bobs"]
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(group_id))
+            )
+            .await,
+            vec!["bob", "john"]
+        );
+        assert!(fixture
+            .handler
+            .get_user_groups(&UserId::new("john"))
+            .await
+            .unwrap()
+            .iter()
+            .any(|g| g.group_id == group_id));
+    }
+
+    #[tokio::test]
+    async fn test_several_dynamic_groups() {
+        let fixture = TestFixture::new().await;
+        let johns = fixture
+            .handler
+            .create_dynamic_group("Johns", "(uid=john)")
+            .await
+            .unwrap();
+        let everyone = fixture
+            .handler
+            .create_dynamic_group("Everyone", "(objectClass=person)")
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .create_dynamic_group("Nobody", "(uid=nobody)")
+            .await
+            .unwrap();
+        let dynamic_groups = fixture
+            .handler
+            .get_matching_dynamic_groups(
+                &UserId::new("john"),
+                &fixture.handler.get_dynamic_groups().await.unwrap(),
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|g| g.group_id)
+            .collect::<Vec<_>>();
+        assert_eq!(dynamic_groups, vec![johns, everyone]);
+    }
+
+    #[tokio::test]
+    async fn test_create_dynamic_group_invalid_filter() {
+        let fixture = TestFixture::new().await;
+        assert!(matches!(
+            fixture
+                .handler
+                .create_dynamic_group("Admins", "(memberOf=cn=admins,dc=example)")
+                .await,
+            Err(DomainError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            fixture
+                .handler
+                .create_dynamic_group("Sales", "(department=Sales)")
+                .await,
+            Err(DomainError::InvalidInput(_))
+        ));
     }
 
     #[tokio::test]
@@ -277,4 +1378,335 @@ mod tests {
             vec![fixture.groups[2], fixture.groups[1]]
         );
     }
+
+    #[tokio::test]
+    async fn test_delete_groups() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .delete_groups(&[fixture.groups[0], fixture.groups[2]])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_group_ids(&fixture.handler, None).await,
+            vec![fixture.groups[1]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_groups_is_atomic() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .delete_groups(&[fixture.groups[0], GroupId(1000)])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            get_group_ids(&fixture.handler, None).await,
+            vec![fixture.groups[0], fixture.groups[2], fixture.groups[1]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_nesting() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let [best, worst, empty] = [fixture.groups[0], fixture.groups[1], fixture.groups[2]];
+        handler.add_group_to_group(best, worst).await.unwrap();
+        handler.add_group_to_group(worst, empty).await.unwrap();
+        assert!(matches!(
+            handler.add_group_to_group(best, worst).await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        // Both would create a cycle.
+        handler.add_group_to_group(empty, best).await.unwrap_err();
+        handler.add_group_to_group(best, best).await.unwrap_err();
+        let nestings = handler
+            .list_group_nestings()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|n| (n.child_group_id, n.parent_group.display_name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nestings,
+            vec![
+                (best, "Worst Group".to_string()),
+                (worst, "Empty Group".to_string())
+            ]
+        );
+        handler.remove_group_from_group(worst, empty).await.unwrap();
+        handler
+            .remove_group_from_group(worst, empty)
+            .await
+            .unwrap_err();
+        // Deleting a group removes its nestings.
+        handler.delete_group(worst).await.unwrap();
+        assert!(handler.list_group_nestings().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_group_managers() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let [best, worst] = [fixture.groups[0], fixture.groups[1]];
+        let bob = UserId::new("bob");
+        let patrick = UserId::new("patrick");
+        handler.add_group_manager(best, &patrick).await.unwrap();
+        handler.add_group_manager(best, &bob).await.unwrap();
+        assert!(matches!(
+            handler.add_group_manager(best, &bob).await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        assert!(matches!(
+            handler
+                .add_group_manager(best, &UserId::new("nobody"))
+                .await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+        assert_eq!(
+            handler.list_group_managers(best).await.unwrap(),
+            vec![bob.clone(), patrick.clone()]
+        );
+        assert!(handler.list_group_managers(worst).await.unwrap().is_empty());
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::Manager(UserId::new("bob")))
+            )
+            .await,
+            vec![best]
+        );
+        handler.remove_group_manager(best, &bob).await.unwrap();
+        handler.remove_group_manager(best, &bob).await.unwrap_err();
+        assert_eq!(
+            handler.list_group_managers(best).await.unwrap(),
+            vec![patrick.clone()]
+        );
+        // Deleting the user removes their rights.
+        handler.delete_user(&patrick).await.unwrap();
+        assert!(handler.list_group_managers(best).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_group_custom_attributes() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let [best, worst] = [fixture.groups[0], fixture.groups[1]];
+        for (name, attribute_type, is_list) in [
+            ("channel", AttributeType::String, true),
+            ("quota", AttributeType::Integer, false),
+        ] {
+            handler
+                .add_group_attribute(AttributeSchema {
+                    name: name.to_string(),
+                    attribute_type,
+                    is_list,
+                    is_editable: false,
+                    ldap_name: name.to_string(),
+                    is_case_sensitive: false,
+                })
+                .await
+                .unwrap();
+        }
+        handler
+            .add_group_attribute(AttributeSchema {
+                name: "other".to_string(),
+                attribute_type: AttributeType::String,
+                is_list: false,
+                is_editable: false,
+                ldap_name: "Quota".to_string(),
+                is_case_sensitive: false,
+            })
+            .await
+            .unwrap_err();
+        // The group and user attributes are separate.
+        assert!(handler
+            .list_user_attribute_schema()
+            .await
+            .unwrap()
+            .is_empty());
+
+        let update = |group_id, attributes| {
+            handler.update_group(UpdateGroupRequest {
+                group_id,
+                display_name: None,
+                archived: None,
+                gid_number: None,
+                email: None,
+                attributes,
+            })
+        };
+        let attribute = |name: &str, values: &[&str]| AttributeValue {
+            name: name.to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        };
+        update(
+            best,
+            vec![
+                attribute("channel", &["#best", "#all", "#best"]),
+                attribute("quota", &[" 10 "]),
+            ],
+        )
+        .await
+        .unwrap();
+        update(worst, vec![attribute("quota", &["1"])])
+            .await
+            .unwrap();
+        assert!(matches!(
+            update(worst, vec![attribute("unknown", &["1"])]).await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+        update(worst, vec![attribute("quota", &["many"])])
+            .await
+            .unwrap_err();
+        update(worst, vec![attribute("quota", &["1", "2"])])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            handler.get_group_attributes(best).await.unwrap(),
+            vec![
+                attribute("channel", &["#best", "#all"]),
+                attribute("quota", &["10"])
+            ]
+        );
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::AttributeEquality(
+                    "channel".to_string(),
+                    "#ALL".to_string()
+                ))
+            )
+            .await,
+            vec![best]
+        );
+        assert_eq!(
+            get_group_ids(
+                handler,
+                Some(GroupRequestFilter::AttributePresent("quota".to_string()))
+            )
+            .await,
+            vec![best, worst]
+        );
+
+        // Only the listed attributes are replaced.
+        update(best, vec![attribute("channel", &[])]).await.unwrap();
+        let groups = handler.list_groups(None).await.unwrap();
+        let best_group = groups.iter().find(|g| g.id == best).unwrap();
+        assert_eq!(best_group.attributes, vec![attribute("quota", &["10"])]);
+
+        // Deleting the attribute deletes its values.
+        handler.delete_group_attribute("quota").await.unwrap();
+        handler.delete_group_attribute("quota").await.unwrap_err();
+        assert!(handler
+            .get_group_attributes(worst)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_membership_requests() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let [best, worst] = [fixture.groups[0], fixture.groups[1]];
+        let bob = UserId::new("bob");
+        let john = UserId::new("john");
+        // Already a member.
+        assert!(matches!(
+            handler.create_membership_request(best, &bob, &bob).await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        let request = handler
+            .create_membership_request(worst, &bob, &john)
+            .await
+            .unwrap();
+        assert_eq!(request.requested_by, john);
+        assert!(request.expiry_date > request.creation_date);
+        assert!(matches!(
+            handler.create_membership_request(worst, &bob, &bob).await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+        let other = handler
+            .create_membership_request(worst, &UserId::new("nogroup"), &john)
+            .await
+            .unwrap();
+        assert_eq!(
+            handler.list_membership_requests(Some(worst)).await.unwrap(),
+            vec![request.clone(), other.clone()]
+        );
+        assert!(handler
+            .list_membership_requests(Some(best))
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            handler
+                .get_membership_request(request.request_id)
+                .await
+                .unwrap(),
+            request
+        );
+        handler
+            .approve_membership_request(request.request_id)
+            .await
+            .unwrap();
+        assert!(
+            get_group_ids(handler, Some(GroupRequestFilter::Member(bob.clone())))
+                .await
+                .contains(&worst)
+        );
+        handler
+            .approve_membership_request(request.request_id)
+            .await
+            .unwrap_err();
+        handler
+            .delete_membership_request(other.request_id)
+            .await
+            .unwrap();
+        handler
+            .delete_membership_request(other.request_id)
+            .await
+            .unwrap_err();
+        assert!(handler
+            .list_membership_requests(None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_membership_requests_expiry() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        let empty = fixture.groups[2];
+        let bob = UserId::new("bob");
+        let request = handler
+            .create_membership_request(empty, &bob, &bob)
+            .await
+            .unwrap();
+        model::membership_requests::ActiveModel {
+            request_id: ActiveValue::Set(request.request_id),
+            expiry_date: ActiveValue::Set(chrono::Utc::now() - chrono::Duration::minutes(1)),
+            ..Default::default()
+        }
+        .update(&handler.sql_pool)
+        .await
+        .unwrap();
+        assert!(handler
+            .list_membership_requests(None)
+            .await
+            .unwrap()
+            .is_empty());
+        handler
+            .approve_membership_request(request.request_id)
+            .await
+            .unwrap_err();
+        // The expired request doesn't prevent a new one.
+        handler
+            .create_membership_request(empty, &bob, &bob)
+            .await
+            .unwrap();
+    }
 }
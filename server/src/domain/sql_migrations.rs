@@ -3,7 +3,7 @@ use crate::domain::{
     types::{GroupId, UserId, Uuid},
 };
 use sea_orm::{ConnectionTrait, FromQueryResult, Statement};
-use sea_query::{ColumnDef, Expr, ForeignKey, ForeignKeyAction, Iden, Query, Table, Value};
+use sea_query::{ColumnDef, Expr, ForeignKey, ForeignKeyAction, Iden, Index, Query, Table, Value};
 use serde::{Deserialize, Serialize};
 use tracing::{instrument, warn};
 
@@ -21,6 +21,24 @@ pub enum Users {
     TotpSecret,
     MfaType,
     Uuid,
+    PreferredLanguage,
+    UidNumber,
+    GidNumber,
+    HomeDirectory,
+    LoginShell,
+    ModifiedDate,
+    TotpLastUsedStep,
+    PasswordModifiedDate,
+    FailedLoginAttempts,
+    LockedDate,
+    LegacyPasswordHash,
+    TemporaryPasswordHash,
+    TemporaryPasswordExpiryDate,
+    ActivationDate,
+    ExpirationDate,
+    LoginNotifications,
+    Enabled,
+    GraceLoginsUsed,
 }
 
 #[derive(Iden, PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
@@ -30,6 +48,193 @@ pub enum Groups {
     DisplayName,
     CreationDate,
     Uuid,
+    Archived,
+    DynamicFilter,
+    GidNumber,
+    ModifiedDate,
+    Email,
+}
+
+#[derive(Iden)]
+pub enum UserEmails {
+    Table,
+    UserId,
+    Email,
+}
+
+#[derive(Iden)]
+pub enum UserSshKeys {
+    Table,
+    KeyId,
+    UserId,
+    SshKey,
+}
+
+/// The groups contained in other groups: the members of the child group are indirect members of
+/// the parent group.
+#[derive(Iden)]
+pub enum GroupNestings {
+    Table,
+    ParentGroupId,
+    ChildGroupId,
+}
+
+/// Contains the single-use login links created by the admins.
+#[derive(Iden)]
+pub enum MagicLoginTokens {
+    Table,
+    Token,
+    UserId,
+    ExpiryDate,
+}
+
+/// The hashes of the single-use codes that replace the TOTP code when the authenticator is lost.
+#[derive(Iden)]
+pub enum TotpRecoveryCodes {
+    Table,
+    UserId,
+    CodeHash,
+}
+
+/// The security keys and passkeys of the users, to log in to the web UI without a password.
+#[derive(Iden)]
+pub enum WebauthnCredentials {
+    Table,
+    CredentialId,
+    UserId,
+    Name,
+    Credential,
+    CreationDate,
+}
+
+/// The reuse-detection digests of the previous passwords of the users, see
+/// `lldap_auth::password_history`.
+#[derive(Iden)]
+pub enum PasswordHistory {
+    Table,
+    UserId,
+    Digest,
+    CreationDate,
+}
+
+/// The API tokens, identified by the hash of their secret.
+#[derive(Iden)]
+pub enum ApiTokens {
+    Table,
+    TokenId,
+    TokenHash,
+    UserId,
+    Name,
+    Scopes,
+    CreationDate,
+}
+
+/// The digest of the last known content of each LDAP entry, and the change sequence number at
+/// which it last changed, for the content synchronization (RFC 4533).
+#[derive(Iden)]
+pub enum SyncEntries {
+    Table,
+    EntryUuid,
+    Digest,
+    ChangeSequence,
+}
+
+/// The users who can change the members of a group without being admins.
+#[derive(Iden)]
+pub enum GroupManagers {
+    Table,
+    GroupId,
+    UserId,
+}
+
+/// The custom user attributes defined by the admins.
+#[derive(Iden)]
+pub enum UserAttributeSchema {
+    Table,
+    AttributeName,
+    AttributeType,
+    IsList,
+    IsEditable,
+    LdapName,
+    IsCaseSensitive,
+}
+
+/// The values of the custom attributes, one row per value.
+#[derive(Iden)]
+pub enum UserAttributes {
+    Table,
+    ValueId,
+    UserId,
+    AttributeName,
+    Value,
+}
+
+/// The custom group attributes, like [`UserAttributeSchema`].
+#[derive(Iden)]
+pub enum GroupAttributeSchema {
+    Table,
+    AttributeName,
+    AttributeType,
+    IsList,
+    IsEditable,
+    LdapName,
+    IsCaseSensitive,
+}
+
+#[derive(Iden)]
+pub enum GroupAttributes {
+    Table,
+    ValueId,
+    GroupId,
+    AttributeName,
+    Value,
+}
+
+/// The mutations and the authentication events, kept for `audit_log_retention_days`. The actor
+/// isn't a foreign key: the entries outlive the users.
+#[derive(Iden)]
+pub enum AuditLog {
+    Table,
+    EntryId,
+    Date,
+    Actor,
+    Action,
+    Target,
+    IpAddress,
+    Success,
+    Details,
+}
+
+/// The URLs to which the changes of the directory are posted. `events` is comma-separated.
+#[derive(Iden)]
+pub enum Webhooks {
+    Table,
+    WebhookId,
+    Url,
+    Secret,
+    Events,
+    CreationDate,
+}
+
+/// The pending requests to join the groups that require an approval.
+#[derive(Iden)]
+pub enum MembershipRequests {
+    Table,
+    RequestId,
+    GroupId,
+    UserId,
+    RequestedBy,
+    CreationDate,
+    ExpiryDate,
+}
+
+/// The identities of the upstream OpenID Connect provider that log in as each user.
+#[derive(Iden)]
+pub enum SsoUsers {
+    Table,
+    Issuer,
+    Subject,
+    UserId,
 }
 
 #[derive(Iden)]
@@ -45,6 +250,8 @@ pub enum Metadata {
     Table,
     // Which version of the schema we're at.
     Version,
+    // The last change sequence number of the content synchronization.
+    ChangeSequence,
 }
 
 #[derive(FromQueryResult, PartialEq, Eq, Debug)]
@@ -329,12 +536,1242 @@ pub async fn upgrade_to_v1(pool: &DbConnection) -> std::result::Result<(), sea_o
     Ok(())
 }
 
-pub async fn migrate_from_version(
-    _pool: &DbConnection,
+pub const LAST_SCHEMA_VERSION: SchemaVersion = SchemaVersion(33);
+
+async fn set_schema_version(
+    pool: &DbConnection,
     version: SchemaVersion,
-) -> anyhow::Result<()> {
-    if version.0 > 1 {
-        anyhow::bail!("DB version downgrading is not supported");
+) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Query::update()
+                .table(Metadata::Table)
+                .value(Metadata::Version, Value::from(version)),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn migrate_to_v2(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // BCP 47 language tags are at most 35 characters long in practice.
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::PreferredLanguage).string_len(35)),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(2)).await
+}
+
+async fn migrate_to_v3(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(Groups::Table).add_column(
+                ColumnDef::new(Groups::Archived)
+                    .boolean()
+                    .not_null()
+                    .default(false),
+            ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(3)).await
+}
+
+async fn migrate_to_v4(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // Additional email addresses of the users, on top of the primary one in the users table.
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(UserEmails::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(UserEmails::Email)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(UserEmails::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("UserEmailsUserForeignKey")
+                        .from(UserEmails::Table, UserEmails::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(4)).await
+}
+
+async fn migrate_to_v5(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Groups::Table)
+                .add_column(ColumnDef::new(Groups::DynamicFilter).text()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(5)).await
+}
+
+async fn migrate_to_v6(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(MagicLoginTokens::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(MagicLoginTokens::Token)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(MagicLoginTokens::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(MagicLoginTokens::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("MagicLoginTokensUserForeignKey")
+                        .from(MagicLoginTokens::Table, MagicLoginTokens::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(6)).await
+}
+
+async fn migrate_to_v7(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // The POSIX attributes, for posixAccount and posixGroup.
+    let columns = [
+        ColumnDef::new(Users::UidNumber).integer().to_owned(),
+        ColumnDef::new(Users::GidNumber).integer().to_owned(),
+        ColumnDef::new(Users::HomeDirectory)
+            .string_len(255)
+            .to_owned(),
+        ColumnDef::new(Users::LoginShell).string_len(255).to_owned(),
+    ];
+    for mut column in columns {
+        // SQLite can only add one column at a time.
+        pool.execute(builder.build(Table::alter().table(Users::Table).add_column(&mut column)))
+            .await?;
+    }
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Groups::Table)
+                .add_column(ColumnDef::new(Groups::GidNumber).integer()),
+        ),
+    )
+    .await?;
+    // Unique indices still allow several NULLs, for the users and groups without a number.
+    pool.execute(
+        builder.build(
+            Index::create()
+                .name("users_uid_number_unique")
+                .table(Users::Table)
+                .col(Users::UidNumber)
+                .unique(),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Index::create()
+                .name("groups_gid_number_unique")
+                .table(Groups::Table)
+                .col(Groups::GidNumber)
+                .unique(),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(7)).await
+}
+
+async fn migrate_to_v8(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // The public SSH keys of the users, for ldapPublicKey.
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(UserSshKeys::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(UserSshKeys::KeyId)
+                        .integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(UserSshKeys::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(UserSshKeys::SshKey).text().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("UserSshKeysUserForeignKey")
+                        .from(UserSshKeys::Table, UserSshKeys::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(8)).await
+}
+
+async fn migrate_to_v9(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupNestings::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(GroupNestings::ParentGroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(GroupNestings::ChildGroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(GroupNestings::ParentGroupId)
+                        .col(GroupNestings::ChildGroupId),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupNestingParentForeignKey")
+                        .from(GroupNestings::Table, GroupNestings::ParentGroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupNestingChildForeignKey")
+                        .from(GroupNestings::Table, GroupNestings::ChildGroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(9)).await
+}
+
+async fn migrate_to_v10(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // The last modification of the users and groups, for the LDAP modifyTimestamp. The column
+    // can't be NOT NULL without a default value in SQLite, but it's always set.
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::ModifiedDate).date_time()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Query::update()
+                .table(Users::Table)
+                .value_expr(Users::ModifiedDate, Expr::col(Users::CreationDate)),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Groups::Table)
+                .add_column(ColumnDef::new(Groups::ModifiedDate).date_time()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Query::update()
+                .table(Groups::Table)
+                .value_expr(Groups::ModifiedDate, Expr::col(Groups::CreationDate)),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(10)).await
+}
+
+async fn migrate_to_v11(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(Metadata::Table).add_column(
+                ColumnDef::new(Metadata::ChangeSequence)
+                    .big_integer()
+                    .not_null()
+                    .default(0),
+            ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(SyncEntries::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(SyncEntries::EntryUuid)
+                        .string_len(36)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(SyncEntries::Digest)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(SyncEntries::ChangeSequence)
+                        .big_integer()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(11)).await
+}
+
+async fn migrate_to_v12(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // The address of the group as a mailing list.
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Groups::Table)
+                .add_column(ColumnDef::new(Groups::Email).string_len(255)),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Index::create()
+                .name("groups_email_unique")
+                .table(Groups::Table)
+                .col(Groups::Email)
+                .unique(),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(12)).await
+}
+
+async fn migrate_to_v13(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // The TOTP secret goes in the `totp_secret` column, and `mfa_type` is set once the user
+    // confirmed the enrollment with a first code. The last used step prevents replays.
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::TotpLastUsedStep).big_integer()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(TotpRecoveryCodes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(TotpRecoveryCodes::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(TotpRecoveryCodes::CodeHash)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(TotpRecoveryCodes::UserId)
+                        .col(TotpRecoveryCodes::CodeHash),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("TotpRecoveryCodesUserForeignKey")
+                        .from(TotpRecoveryCodes::Table, TotpRecoveryCodes::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(13)).await
+}
+
+async fn migrate_to_v14(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    // The credential is serialized by the web server, the database only stores it.
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(WebauthnCredentials::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(WebauthnCredentials::CredentialId)
+                        .string_len(255)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::Name)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::Credential)
+                        .text()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(WebauthnCredentials::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("WebauthnCredentialsUserForeignKey")
+                        .from(WebauthnCredentials::Table, WebauthnCredentials::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(14)).await
+}
+
+async fn migrate_to_v15(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::PasswordModifiedDate).date_time()),
+        ),
+    )
+    .await?;
+    // The existing passwords get a full period before they expire.
+    pool.execute(
+        builder.build(
+            Query::update()
+                .table(Users::Table)
+                .value(Users::PasswordModifiedDate, chrono::Utc::now())
+                .and_where(Expr::col(Users::PasswordHash).is_not_null()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(15)).await
+}
+
+async fn migrate_to_v16(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(PasswordHistory::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(PasswordHistory::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(PasswordHistory::Digest)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(PasswordHistory::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(PasswordHistory::UserId)
+                        .col(PasswordHistory::Digest),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("PasswordHistoryUserForeignKey")
+                        .from(PasswordHistory::Table, PasswordHistory::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(16)).await
+}
+
+async fn migrate_to_v17(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(ApiTokens::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(ApiTokens::TokenId)
+                        .string_len(32)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(ApiTokens::TokenHash)
+                        .string_len(64)
+                        .not_null()
+                        .unique_key(),
+                )
+                .col(ColumnDef::new(ApiTokens::UserId).string_len(255).not_null())
+                .col(ColumnDef::new(ApiTokens::Name).string_len(255).not_null())
+                .col(ColumnDef::new(ApiTokens::Scopes).string_len(255).not_null())
+                .col(
+                    ColumnDef::new(ApiTokens::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("ApiTokensUserForeignKey")
+                        .from(ApiTokens::Table, ApiTokens::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(17)).await
+}
+
+async fn migrate_to_v18(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    use crate::infra::jwt_sql_tables::{self, JwtRefreshStorage, JwtStorage};
+    // The JWT tables are normally created after the migrations, so they don't exist yet for a new
+    // database.
+    jwt_sql_tables::init_table(pool).await?;
+    let builder = pool.get_database_backend();
+    // SQLite can only add one column at a time.
+    for column in [
+        ColumnDef::new(JwtRefreshStorage::CreationDate).date_time(),
+        ColumnDef::new(JwtRefreshStorage::IpAddress).string_len(255),
+        ColumnDef::new(JwtRefreshStorage::UserAgent).string_len(255),
+    ] {
+        pool.execute(
+            builder.build(
+                Table::alter()
+                    .table(JwtRefreshStorage::Table)
+                    .add_column(column),
+            ),
+        )
+        .await?;
+    }
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(JwtStorage::Table)
+                .add_column(ColumnDef::new(JwtStorage::RefreshTokenHash).big_integer()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(18)).await
+}
+
+async fn migrate_to_v19(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(Users::Table).add_column(
+                ColumnDef::new(Users::FailedLoginAttempts)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::LockedDate).date_time()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(19)).await
+}
+
+async fn migrate_to_v20(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::LegacyPasswordHash).text()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(20)).await
+}
+
+async fn migrate_to_v21(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::TemporaryPasswordHash).string_len(64)),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::TemporaryPasswordExpiryDate).date_time()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(21)).await
+}
+
+async fn migrate_to_v22(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::ActivationDate).date_time()),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(Users::Table)
+                .add_column(ColumnDef::new(Users::ExpirationDate).date_time()),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(22)).await
+}
+
+async fn migrate_to_v23(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(Users::Table).add_column(
+                ColumnDef::new(Users::LoginNotifications)
+                    .boolean()
+                    .not_null()
+                    .default(true),
+            ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(23)).await
+}
+
+async fn migrate_to_v24(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupManagers::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(GroupManagers::GroupId).integer().not_null())
+                .col(
+                    ColumnDef::new(GroupManagers::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .primary_key(
+                    Index::create()
+                        .col(GroupManagers::GroupId)
+                        .col(GroupManagers::UserId),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupManagersGroupForeignKey")
+                        .from(GroupManagers::Table, GroupManagers::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupManagersUserForeignKey")
+                        .from(GroupManagers::Table, GroupManagers::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(24)).await
+}
+
+async fn migrate_to_v25(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(UserAttributeSchema::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(UserAttributeSchema::AttributeName)
+                        .string_len(64)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributeSchema::AttributeType)
+                        .string_len(16)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributeSchema::IsList)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(
+                    ColumnDef::new(UserAttributeSchema::IsEditable)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(
+                    ColumnDef::new(UserAttributeSchema::LdapName)
+                        .string_len(64)
+                        .not_null()
+                        .unique_key(),
+                ),
+        ),
+    )
+    .await?;
+    // Deleting an attribute from the schema deletes its values.
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(UserAttributes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(UserAttributes::ValueId)
+                        .integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributes::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(UserAttributes::AttributeName)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(UserAttributes::Value).text().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("UserAttributesUserForeignKey")
+                        .from(UserAttributes::Table, UserAttributes::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("UserAttributesSchemaForeignKey")
+                        .from(UserAttributes::Table, UserAttributes::AttributeName)
+                        .to(
+                            UserAttributeSchema::Table,
+                            UserAttributeSchema::AttributeName,
+                        )
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(25)).await
+}
+
+async fn migrate_to_v26(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupAttributeSchema::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::AttributeName)
+                        .string_len(64)
+                        .not_null()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::AttributeType)
+                        .string_len(16)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::IsList)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::IsEditable)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributeSchema::LdapName)
+                        .string_len(64)
+                        .not_null()
+                        .unique_key(),
+                ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(GroupAttributes::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(GroupAttributes::ValueId)
+                        .integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributes::GroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(GroupAttributes::AttributeName)
+                        .string_len(64)
+                        .not_null(),
+                )
+                .col(ColumnDef::new(GroupAttributes::Value).text().not_null())
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupAttributesGroupForeignKey")
+                        .from(GroupAttributes::Table, GroupAttributes::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("GroupAttributesSchemaForeignKey")
+                        .from(GroupAttributes::Table, GroupAttributes::AttributeName)
+                        .to(
+                            GroupAttributeSchema::Table,
+                            GroupAttributeSchema::AttributeName,
+                        )
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(26)).await
+}
+
+async fn migrate_to_v27(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(AuditLog::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(AuditLog::EntryId)
+                        .integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(AuditLog::Date).date_time().not_null())
+                .col(ColumnDef::new(AuditLog::Actor).string_len(255))
+                .col(ColumnDef::new(AuditLog::Action).string_len(64).not_null())
+                .col(ColumnDef::new(AuditLog::Target).string_len(255))
+                .col(ColumnDef::new(AuditLog::IpAddress).string_len(64))
+                .col(ColumnDef::new(AuditLog::Success).boolean().not_null())
+                .col(ColumnDef::new(AuditLog::Details).text()),
+        ),
+    )
+    .await?;
+    // For the listing, newest first, and the cleanup of the old entries.
+    pool.execute(
+        builder.build(
+            Index::create()
+                .name("audit_log_date")
+                .table(AuditLog::Table)
+                .col(AuditLog::Date),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(27)).await
+}
+
+async fn migrate_to_v28(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(Webhooks::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(Webhooks::WebhookId)
+                        .integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(ColumnDef::new(Webhooks::Url).text().not_null())
+                .col(ColumnDef::new(Webhooks::Secret).string_len(255).not_null())
+                .col(ColumnDef::new(Webhooks::Events).string_len(255).not_null())
+                .col(
+                    ColumnDef::new(Webhooks::CreationDate)
+                        .date_time()
+                        .not_null(),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(28)).await
+}
+
+async fn migrate_to_v29(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(Users::Table).add_column(
+                ColumnDef::new(Users::Enabled)
+                    .boolean()
+                    .not_null()
+                    .default(true),
+            ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(29)).await
+}
+
+async fn migrate_to_v30(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(UserAttributeSchema::Table).add_column(
+                ColumnDef::new(UserAttributeSchema::IsCaseSensitive)
+                    .boolean()
+                    .not_null()
+                    .default(false),
+            ),
+        ),
+    )
+    .await?;
+    pool.execute(
+        builder.build(
+            Table::alter()
+                .table(GroupAttributeSchema::Table)
+                .add_column(
+                    ColumnDef::new(GroupAttributeSchema::IsCaseSensitive)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(30)).await
+}
+
+async fn migrate_to_v31(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(MembershipRequests::Table)
+                .if_not_exists()
+                .col(
+                    ColumnDef::new(MembershipRequests::RequestId)
+                        .integer()
+                        .not_null()
+                        .auto_increment()
+                        .primary_key(),
+                )
+                .col(
+                    ColumnDef::new(MembershipRequests::GroupId)
+                        .integer()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(MembershipRequests::UserId)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(MembershipRequests::RequestedBy)
+                        .string_len(255)
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(MembershipRequests::CreationDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .col(
+                    ColumnDef::new(MembershipRequests::ExpiryDate)
+                        .date_time()
+                        .not_null(),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("MembershipRequestsGroupForeignKey")
+                        .from(MembershipRequests::Table, MembershipRequests::GroupId)
+                        .to(Groups::Table, Groups::GroupId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("MembershipRequestsUserForeignKey")
+                        .from(MembershipRequests::Table, MembershipRequests::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                )
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("MembershipRequestsRequesterForeignKey")
+                        .from(MembershipRequests::Table, MembershipRequests::RequestedBy)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    // A user has at most one pending request per group.
+    pool.execute(
+        builder.build(
+            Index::create()
+                .name("membership_requests_group_user_unique")
+                .table(MembershipRequests::Table)
+                .col(MembershipRequests::GroupId)
+                .col(MembershipRequests::UserId)
+                .unique(),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(31)).await
+}
+
+async fn migrate_to_v32(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::alter().table(Users::Table).add_column(
+                ColumnDef::new(Users::GraceLoginsUsed)
+                    .integer()
+                    .not_null()
+                    .default(0),
+            ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(32)).await
+}
+
+async fn migrate_to_v33(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
+    let builder = pool.get_database_backend();
+    pool.execute(
+        builder.build(
+            Table::create()
+                .table(SsoUsers::Table)
+                .if_not_exists()
+                .col(ColumnDef::new(SsoUsers::Issuer).string_len(255).not_null())
+                .col(ColumnDef::new(SsoUsers::Subject).string_len(255).not_null())
+                .col(ColumnDef::new(SsoUsers::UserId).string_len(255).not_null())
+                .primary_key(Index::create().col(SsoUsers::Issuer).col(SsoUsers::Subject))
+                .foreign_key(
+                    ForeignKey::create()
+                        .name("SsoUsersUserForeignKey")
+                        .from(SsoUsers::Table, SsoUsers::UserId)
+                        .to(Users::Table, Users::UserId)
+                        .on_delete(ForeignKeyAction::Cascade)
+                        .on_update(ForeignKeyAction::Cascade),
+                ),
+        ),
+    )
+    .await?;
+    set_schema_version(pool, SchemaVersion(33)).await
+}
+
+pub async fn migrate_from_version(
+    pool: &DbConnection,
+    version: SchemaVersion,
+) -> anyhow::Result<()> {
+    if version.0 > LAST_SCHEMA_VERSION.0 {
+        anyhow::bail!("DB version downgrading is not supported");
+    }
+    if version.0 < 2 {
+        migrate_to_v2(pool).await?;
+    }
+    if version.0 < 3 {
+        migrate_to_v3(pool).await?;
+    }
+    if version.0 < 4 {
+        migrate_to_v4(pool).await?;
+    }
+    if version.0 < 5 {
+        migrate_to_v5(pool).await?;
+    }
+    if version.0 < 6 {
+        migrate_to_v6(pool).await?;
+    }
+    if version.0 < 7 {
+        migrate_to_v7(pool).await?;
+    }
+    if version.0 < 8 {
+        migrate_to_v8(pool).await?;
+    }
+    if version.0 < 9 {
+        migrate_to_v9(pool).await?;
+    }
+    if version.0 < 10 {
+        migrate_to_v10(pool).await?;
+    }
+    if version.0 < 11 {
+        migrate_to_v11(pool).await?;
+    }
+    if version.0 < 12 {
+        migrate_to_v12(pool).await?;
+    }
+    if version.0 < 13 {
+        migrate_to_v13(pool).await?;
+    }
+    if version.0 < 14 {
+        migrate_to_v14(pool).await?;
+    }
+    if version.0 < 15 {
+        migrate_to_v15(pool).await?;
+    }
+    if version.0 < 16 {
+        migrate_to_v16(pool).await?;
+    }
+    if version.0 < 17 {
+        migrate_to_v17(pool).await?;
+    }
+    if version.0 < 18 {
+        migrate_to_v18(pool).await?;
+    }
+    if version.0 < 19 {
+        migrate_to_v19(pool).await?;
+    }
+    if version.0 < 20 {
+        migrate_to_v20(pool).await?;
+    }
+    if version.0 < 21 {
+        migrate_to_v21(pool).await?;
+    }
+    if version.0 < 22 {
+        migrate_to_v22(pool).await?;
+    }
+    if version.0 < 23 {
+        migrate_to_v23(pool).await?;
+    }
+    if version.0 < 24 {
+        migrate_to_v24(pool).await?;
+    }
+    if version.0 < 25 {
+        migrate_to_v25(pool).await?;
+    }
+    if version.0 < 26 {
+        migrate_to_v26(pool).await?;
+    }
+    if version.0 < 27 {
+        migrate_to_v27(pool).await?;
+    }
+    if version.0 < 28 {
+        migrate_to_v28(pool).await?;
+    }
+    if version.0 < 29 {
+        migrate_to_v29(pool).await?;
+    }
+    if version.0 < 30 {
+        migrate_to_v30(pool).await?;
+    }
+    if version.0 < 31 {
+        migrate_to_v31(pool).await?;
+    }
+    if version.0 < 32 {
+        migrate_to_v32(pool).await?;
+    }
+    if version.0 < 33 {
+        migrate_to_v33(pool).await?;
     }
     Ok(())
 }
@@ -1,19 +1,95 @@
 use super::{
     error::{DomainError, Result},
-    handler::{BindRequest, LoginHandler},
-    model::{self, UserColumn},
+    handler::{BackendHandler, BindRequest, LoginHandler},
+    legacy_password,
+    model::{self, PasswordHistoryColumn, UserColumn},
     opaque_handler::{login, registration, OpaqueHandler},
     sql_backend_handler::SqlBackendHandler,
-    types::UserId,
+    types::{DirectoryChange, User, UserId},
 };
 use async_trait::async_trait;
-use lldap_auth::opaque;
-use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, FromQueryResult, QuerySelect};
+use hmac::{Hmac, Mac, NewMac};
+use lldap_auth::{opaque, password_history::password_history_digest};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseTransaction, EntityTrait,
+    FromQueryResult, QueryFilter, QueryOrder, QuerySelect,
+};
 use secstr::SecUtf8;
-use tracing::{debug, instrument};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, instrument, warn};
 
 type SqlOpaqueHandler = SqlBackendHandler;
 
+/// How long the client has to finish a login it started. Past that, it counts as a wrong password.
+const LOGIN_FINISH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The logins started and not finished yet, by user. With OPAQUE, only the client can tell that
+/// the password is wrong, and it then doesn't finish the login: an attempt only counts as failed
+/// when its finish fails or never arrives.
+#[derive(Default)]
+pub(crate) struct PendingLogins(Mutex<HashMap<UserId, Vec<Instant>>>);
+
+impl PendingLogins {
+    /// Past `max_attempts` pending logins, the account gets locked anyway when they expire: the
+    /// next ones aren't recorded, so that a flood of logins doesn't fill the memory.
+    fn start_at(&self, user_id: &UserId, max_attempts: u32, now: Instant) {
+        let mut pending = self.0.lock().unwrap();
+        let starts = pending.entry(user_id.clone()).or_default();
+        if starts.len() < max_attempts as usize {
+            starts.push(now);
+        }
+    }
+
+    /// Forgets the oldest pending login of the user, and returns whether there was one. There is
+    /// none if it expired, and was already counted as failed.
+    fn finish(&self, user_id: &UserId) -> bool {
+        let mut pending = self.0.lock().unwrap();
+        match pending.get_mut(user_id) {
+            Some(starts) if !starts.is_empty() => {
+                starts.remove(0);
+                if starts.is_empty() {
+                    pending.remove(user_id);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn forget(&self, user_id: &UserId) {
+        self.0.lock().unwrap().remove(user_id);
+    }
+
+    /// Removes the logins that weren't finished in time, and returns how many each user started.
+    fn take_expired_at(&self, now: Instant) -> Vec<(UserId, i32)> {
+        let mut pending = self.0.lock().unwrap();
+        let mut expired = Vec::new();
+        pending.retain(|user_id, starts| {
+            let count = starts
+                .iter()
+                .filter(|start| now.duration_since(**start) >= LOGIN_FINISH_TIMEOUT)
+                .count();
+            if count > 0 {
+                starts.drain(..count);
+                expired.push((user_id.clone(), i32::try_from(count).unwrap_or(i32::MAX)));
+            }
+            !starts.is_empty()
+        });
+        expired
+    }
+}
+
+enum StoredPassword {
+    Opaque(Vec<u8>),
+    /// See [`legacy_password`].
+    Legacy(String),
+}
+
 #[instrument(skip_all, level = "debug", err)]
 fn passwords_match(
     password_file_bytes: &[u8],
@@ -63,24 +139,280 @@ impl SqlBackendHandler {
             .await?
             .and_then(|u| u.password_hash))
     }
+
+    /// The OPAQUE password file of the user, or else the password hash imported for them.
+    #[instrument(skip_all, level = "debug", err)]
+    async fn get_stored_password(&self, user_id: &UserId) -> Result<Option<StoredPassword>> {
+        #[derive(FromQueryResult)]
+        struct OnlyPasswords {
+            password_hash: Option<Vec<u8>>,
+            legacy_password_hash: Option<String>,
+        }
+        Ok(model::User::find_by_id(user_id.clone())
+            .select_only()
+            .column(UserColumn::PasswordHash)
+            .column(UserColumn::LegacyPasswordHash)
+            .into_model::<OnlyPasswords>()
+            .one(&self.sql_pool)
+            .await?
+            .and_then(|u| match (u.password_hash, u.legacy_password_hash) {
+                (Some(password_file), _) => Some(StoredPassword::Opaque(password_file)),
+                (None, Some(hash)) => Some(StoredPassword::Legacy(hash)),
+                (None, None) => None,
+            }))
+    }
+
+    /// Locks the account if it reached `max_failed_login_attempts` wrong passwords, or unlocks it
+    /// if it was locked for longer than `account_lockout_minutes`, and returns whether it is
+    /// locked. `false` if the user doesn't exist.
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn lock_if_too_many_failures(&self, user_id: &UserId) -> Result<bool> {
+        #[derive(FromQueryResult)]
+        struct OnlyLockedDate {
+            locked_date: Option<chrono::DateTime<chrono::Utc>>,
+        }
+        let max_attempts = self.config.max_failed_login_attempts;
+        if max_attempts == 0 {
+            return Ok(false);
+        }
+        let lockout_minutes = self.config.account_lockout_minutes;
+        if lockout_minutes > 0 {
+            let unlocked = model::User::update_many()
+                .col_expr(UserColumn::FailedLoginAttempts, Expr::value(0))
+                .col_expr(
+                    UserColumn::LockedDate,
+                    Expr::value(Option::<chrono::DateTime<chrono::Utc>>::None),
+                )
+                .filter(UserColumn::UserId.eq(user_id.clone()))
+                .filter(UserColumn::LockedDate.lte(
+                    chrono::Utc::now() - chrono::Duration::minutes(i64::from(lockout_minutes)),
+                ))
+                .exec(&self.sql_pool)
+                .await?;
+            if unlocked.rows_affected > 0 {
+                info!(
+                    "Unlocked the account of {} after {} minutes",
+                    user_id, lockout_minutes
+                );
+            }
+        }
+        let locked = model::User::update_many()
+            .col_expr(UserColumn::LockedDate, Expr::value(chrono::Utc::now()))
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .filter(UserColumn::LockedDate.is_null())
+            .filter(
+                UserColumn::FailedLoginAttempts
+                    .gte(i32::try_from(max_attempts).unwrap_or(i32::MAX)),
+            )
+            .exec(&self.sql_pool)
+            .await?;
+        if locked.rows_affected > 0 {
+            warn!(
+                "Locked the account of {} after {} wrong passwords",
+                user_id, max_attempts
+            );
+        }
+        Ok(model::User::find_by_id(user_id.clone())
+            .select_only()
+            .column(UserColumn::LockedDate)
+            .into_model::<OnlyLockedDate>()
+            .one(&self.sql_pool)
+            .await?
+            .map_or(false, |u| u.locked_date.is_some()))
+    }
+
+    /// Whether the account is disabled, or outside of its activation and expiration dates. `false`
+    /// if the user doesn't exist.
+    #[instrument(skip(self), level = "debug", ret, err)]
+    async fn is_inactive(&self, user_id: &UserId) -> Result<bool> {
+        let now = chrono::Utc::now();
+        Ok(model::User::find_by_id(user_id.clone())
+            .one(&self.sql_pool)
+            .await?
+            .map_or(false, |u| !User::from(u).is_active_at(now)))
+    }
+
+    /// Counts wrong passwords for the user, and locks the account if they are too many.
+    #[instrument(skip(self), level = "debug", err)]
+    pub(crate) async fn record_failed_logins(&self, user_id: &UserId, count: i32) -> Result<()> {
+        if self.config.max_failed_login_attempts == 0 {
+            return Ok(());
+        }
+        model::User::update_many()
+            .col_expr(
+                UserColumn::FailedLoginAttempts,
+                Expr::col(UserColumn::FailedLoginAttempts).add(count),
+            )
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .exec(&self.sql_pool)
+            .await?;
+        // Lock it right away, so that the admins see it.
+        self.lock_if_too_many_failures(user_id).await?;
+        Ok(())
+    }
+
+    /// Counts the logins that weren't finished in time as wrong passwords.
+    #[instrument(skip(self), level = "debug", err)]
+    async fn record_abandoned_logins_at(&self, now: Instant) -> Result<()> {
+        if self.config.max_failed_login_attempts == 0 {
+            return Ok(());
+        }
+        for (user_id, count) in self.pending_logins.take_expired_at(now) {
+            debug!(r#"{} logins of "{}" weren't finished"#, count, &user_id);
+            self.record_failed_logins(&user_id, count).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the password of the user can't be used: the account is locked or inactive. The
+    /// reason is only logged, since telling it would confirm that the user exists, before the
+    /// password is even checked.
+    pub(crate) async fn is_login_refused(&self, user_id: &UserId) -> Result<bool> {
+        if self.lock_if_too_many_failures(user_id).await? {
+            debug!(r#"The account of "{}" is locked"#, user_id);
+            return Ok(true);
+        }
+        if self.is_inactive(user_id).await? {
+            debug!(r#"The account of "{}" is not active"#, user_id);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    #[instrument(skip(self), level = "debug", err)]
+    pub(crate) async fn reset_failed_logins(&self, user_id: &UserId) -> Result<()> {
+        if self.config.max_failed_login_attempts == 0 {
+            return Ok(());
+        }
+        model::User::update_many()
+            .col_expr(UserColumn::FailedLoginAttempts, Expr::value(0))
+            .filter(UserColumn::UserId.eq(user_id.clone()))
+            .filter(UserColumn::FailedLoginAttempts.gt(0))
+            .exec(&self.sql_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The value stored in the history for the digest sent by the client: an HMAC keyed with the
+    /// OPAQUE server setup, so that a copy of the database alone isn't enough to bruteforce the
+    /// previous passwords.
+    fn keyed_history_digest(&self, digest: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.config.get_server_setup().serialize()[..])
+            .expect("HMAC accepts keys of any size");
+        mac.update(digest.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    /// Refuses the new password if it is one of the last `password_history_size` passwords of the
+    /// user, and otherwise records it as the most recent one.
+    ///
+    /// This is advisory only: the digest is computed by the client and can't be checked against
+    /// the OPAQUE registration, so a client can send the digest of another password and reuse an
+    /// old one anyway. It only catches the reuse through the clients that compute it honestly.
+    #[instrument(skip(self, transaction, digest), level = "debug", err)]
+    async fn update_password_history(
+        &self,
+        transaction: &DatabaseTransaction,
+        user_id: &UserId,
+        digest: Option<String>,
+    ) -> Result<()> {
+        let history_size = self.config.password_history_size;
+        if history_size == 0 {
+            return Ok(());
+        }
+        let digest = digest.ok_or_else(|| {
+            DomainError::InvalidInput(
+                "The password history is enabled: the new password must come with its digest"
+                    .to_string(),
+            )
+        })?;
+        let digest = self.keyed_history_digest(&digest);
+        let history = model::PasswordHistory::find()
+            .filter(PasswordHistoryColumn::UserId.eq(user_id.clone()))
+            .order_by_desc(PasswordHistoryColumn::CreationDate)
+            .all(transaction)
+            .await?;
+        if history
+            .iter()
+            .take(history_size)
+            .any(|p| p.digest == digest)
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "The new password of '{}' was already used recently, choose another one",
+                user_id
+            )));
+        }
+        // Only the last `history_size` passwords are kept, including the new one.
+        let expired_digests = history
+            .into_iter()
+            .skip(history_size - 1)
+            .map(|p| p.digest)
+            .collect::<Vec<_>>();
+        if !expired_digests.is_empty() {
+            model::PasswordHistory::delete_many()
+                .filter(PasswordHistoryColumn::UserId.eq(user_id.clone()))
+                .filter(PasswordHistoryColumn::Digest.is_in(expired_digests))
+                .exec(transaction)
+                .await?;
+        }
+        model::password_history::ActiveModel {
+            user_id: ActiveValue::Set(user_id.clone()),
+            digest: ActiveValue::Set(digest),
+            creation_date: ActiveValue::Set(chrono::Utc::now()),
+        }
+        .insert(transaction)
+        .await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl LoginHandler for SqlBackendHandler {
     #[instrument(skip_all, level = "debug", err)]
     async fn bind(&self, request: BindRequest) -> Result<()> {
-        if let Some(password_hash) = self
-            .get_password_file_for_user(request.name.clone())
-            .await?
-        {
-            if let Err(e) = passwords_match(
-                &password_hash,
-                &request.password,
-                self.config.get_server_setup(),
-                &request.name,
-            ) {
+        self.record_abandoned_logins_at(Instant::now()).await?;
+        if let Some(stored_password) = self.get_stored_password(&request.name).await? {
+            if self.is_login_refused(&request.name).await? {
+                // Same error as a wrong password.
+                return Err(DomainError::AuthenticationError(format!(
+                    " for user '{}'",
+                    request.name
+                )));
+            }
+            let result = match &stored_password {
+                StoredPassword::Opaque(password_hash) => passwords_match(
+                    password_hash,
+                    &request.password,
+                    self.config.get_server_setup(),
+                    &request.name,
+                ),
+                StoredPassword::Legacy(hash) => {
+                    match legacy_password::verify(hash, &request.password) {
+                        Ok(true) => Ok(()),
+                        Ok(false) => Err(DomainError::AuthenticationError(
+                            "wrong password".to_string(),
+                        )),
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+            if let Err(e) = result {
                 debug!(r#"Invalid password for "{}": {}"#, &request.name, e);
+                self.record_failed_logins(&request.name, 1).await?;
             } else {
+                if let StoredPassword::Legacy(_) = stored_password {
+                    info!(
+                        "Replacing the imported password hash of {} with an OPAQUE password",
+                        &request.name
+                    );
+                    register_password(self, &request.name, &SecUtf8::from(request.password))
+                        .await?;
+                }
+                self.reset_failed_logins(&request.name).await?;
                 return Ok(());
             }
         } else {
@@ -103,9 +435,22 @@ impl OpaqueHandler for SqlOpaqueHandler {
         &self,
         request: login::ClientLoginStartRequest,
     ) -> Result<login::ServerLoginStartResponse> {
-        let maybe_password_file = self
-            .get_password_file_for_user(UserId::new(&request.username))
-            .await?
+        let user_id = UserId::new(&request.username);
+        let now = Instant::now();
+        self.record_abandoned_logins_at(now).await?;
+        // A refused account gets the dummy response of a user without a password: the client then
+        // fails like with a wrong password, and can't use it to guess the real one.
+        let maybe_password_file = if self.is_login_refused(&user_id).await? {
+            None
+        } else {
+            let password_file = self.get_password_file_for_user(user_id.clone()).await?;
+            let max_attempts = self.config.max_failed_login_attempts;
+            if max_attempts > 0 && password_file.is_some() {
+                self.pending_logins.start_at(&user_id, max_attempts, now);
+            }
+            password_file
+        };
+        let maybe_password_file = maybe_password_file
             .map(|bytes| {
                 opaque::server::ServerRegistration::deserialize(&bytes).map_err(|_| {
                     DomainError::InternalError(format!(
@@ -148,13 +493,25 @@ impl OpaqueHandler for SqlOpaqueHandler {
             &secret_key,
             &base64::decode(&request.server_data)?,
         )?)?;
+        let user_id = UserId::new(&username);
+        let was_pending = self.pending_logins.finish(&user_id);
         // Finish the login: this makes sure the client data is correct, and gives a session key we
         // don't need.
-        let _session_key =
-            opaque::server::login::finish_login(server_login, request.credential_finalization)?
-                .session_key;
-
-        Ok(UserId::new(&username))
+        if let Err(e) =
+            opaque::server::login::finish_login(server_login, request.credential_finalization)
+        {
+            // Unless it expired, and was already counted.
+            if was_pending {
+                self.record_failed_logins(&user_id, 1).await?;
+            }
+            return Err(e.into());
+        }
+        // With TOTP, the count is only reset once the code is right too, otherwise knowing the
+        // password would be enough to keep guessing the codes.
+        if !self.is_totp_enabled(&user_id).await? {
+            self.reset_failed_logins(&user_id).await?;
+        }
+        Ok(user_id)
     }
 
     #[instrument(skip_all, level = "debug", err)]
@@ -190,15 +547,26 @@ impl OpaqueHandler for SqlOpaqueHandler {
             &base64::decode(&request.server_data)?,
         )?)?;
 
+        let user_id = UserId::new(&username);
+        let transaction = self.sql_pool.begin().await?;
+        self.update_password_history(&transaction, &user_id, request.password_history_digest)
+            .await?;
         let password_file =
             opaque::server::registration::get_password_file(request.registration_upload);
         // Set the user password to the new password.
         let user_update = model::users::ActiveModel {
-            user_id: ActiveValue::Set(UserId::new(&username)),
+            user_id: ActiveValue::Set(user_id.clone()),
             password_hash: ActiveValue::Set(Some(password_file.serialize())),
+            password_modified_date: ActiveValue::Set(Some(chrono::Utc::now())),
+            grace_logins_used: ActiveValue::Set(0),
+            legacy_password_hash: ActiveValue::Set(None),
+            temporary_password_hash: ActiveValue::Set(None),
+            temporary_password_expiry_date: ActiveValue::Set(None),
             ..Default::default()
         };
-        user_update.update(&self.sql_pool).await?;
+        user_update.update(&transaction).await?;
+        transaction.commit().await?;
+        self.notify_change(DirectoryChange::PasswordChanged(user_id));
         Ok(())
     }
 }
@@ -229,14 +597,69 @@ pub(crate) async fn register_password(
         .registration_finish(ClientRegistrationFinishRequest {
             server_data: start_response.server_data,
             registration_upload: registration_finish.message,
+            password_history_digest: Some(
+                password_history_digest(username.as_str(), password.unsecure())
+                    .map_err(DomainError::InternalError)?,
+            ),
         })
         .await
 }
 
+/// The registered password files of all the users, e.g. to migrate them to another instance.
+/// They can only be verified with the server key that created them.
+#[instrument(skip_all, level = "debug", err)]
+pub(crate) async fn export_password_files(
+    handler: &SqlOpaqueHandler,
+) -> Result<Vec<(UserId, Vec<u8>)>> {
+    #[derive(FromQueryResult)]
+    struct UserAndPasswordHash {
+        user_id: UserId,
+        password_hash: Option<Vec<u8>>,
+    }
+    Ok(model::User::find()
+        .select_only()
+        .column(UserColumn::UserId)
+        .column(UserColumn::PasswordHash)
+        .order_by_asc(UserColumn::UserId)
+        .into_model::<UserAndPasswordHash>()
+        .all(&handler.sql_pool)
+        .await?
+        .into_iter()
+        .filter_map(|u| Some((u.user_id, u.password_hash?)))
+        .collect())
+}
+
+/// Sets the password file of an existing user, as exported by [`export_password_files`].
+#[instrument(skip(handler, password_file), level = "debug", err)]
+pub(crate) async fn import_password_file(
+    handler: &SqlOpaqueHandler,
+    user_id: &UserId,
+    password_file: &[u8],
+) -> Result<()> {
+    opaque::server::ServerRegistration::deserialize(password_file).map_err(|_| {
+        DomainError::InternalError(format!("Corrupted password file for {}", user_id))
+    })?;
+    let result = model::User::update_many()
+        .col_expr(
+            UserColumn::PasswordHash,
+            Expr::value(password_file.to_vec()),
+        )
+        .filter(UserColumn::UserId.eq(user_id))
+        .exec(&handler.sql_pool)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(DomainError::EntityNotFound(format!(
+            "No such user: '{}'",
+            user_id
+        )));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::sql_backend_handler::tests::*;
+    use crate::domain::{handler::UserBackendHandler, sql_backend_handler::tests::*, totp};
 
     async fn attempt_login(
         opaque_handler: &SqlOpaqueHandler,
@@ -250,6 +673,7 @@ mod tests {
             .login_start(ClientLoginStartRequest {
                 username: username.to_string(),
                 login_start_request: login_start.message,
+                proof_of_work: None,
             })
             .await?;
         let login_finish = opaque::client::login::finish_login(
@@ -277,6 +701,15 @@ mod tests {
         attempt_login(&opaque_handler, "bob", "bob00")
             .await
             .unwrap_err();
+        let bob = UserId::new("bob");
+        assert_eq!(
+            backend_handler
+                .get_user_details(&bob)
+                .await?
+                .password_modified_date,
+            None
+        );
+        let before = chrono::Utc::now();
         register_password(
             &opaque_handler,
             &UserId::new("bob"),
@@ -287,6 +720,79 @@ mod tests {
             .await
             .unwrap_err();
         attempt_login(&opaque_handler, "bob", "bob00").await?;
+        assert!(
+            backend_handler
+                .get_user_details(&bob)
+                .await?
+                .password_modified_date
+                .unwrap()
+                >= before
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_password_history() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.password_history_size = 2;
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user_no_password(&handler, "bob").await;
+        let set_password = |password: &str| {
+            let password = SecUtf8::from(password);
+            let handler = &handler;
+            async move { register_password(handler, &UserId::new("bob"), &password).await }
+        };
+        set_password("password1").await?;
+        set_password("password1").await.unwrap_err();
+        set_password("password2").await?;
+        set_password("password1").await.unwrap_err();
+        set_password("password3").await?;
+        // Only the last 2 passwords are remembered.
+        set_password("password1").await?;
+        attempt_login(&handler, "bob", "password1").await?;
+        set_password("password3").await.unwrap_err();
+        attempt_login(&handler, "bob", "password1").await?;
+
+        // A new password without its digest can't be compared with the history.
+        let mut rng = rand::rngs::OsRng;
+        use registration::*;
+        let registration_start =
+            opaque::client::registration::start_registration("password4", &mut rng)?;
+        let start_response = handler
+            .registration_start(ClientRegistrationStartRequest {
+                username: "bob".to_string(),
+                registration_start_request: registration_start.message,
+            })
+            .await?;
+        let registration_finish = opaque::client::registration::finish_registration(
+            registration_start.state,
+            start_response.registration_response,
+            &mut rng,
+        )?;
+        assert!(matches!(
+            handler
+                .registration_finish(ClientRegistrationFinishRequest {
+                    server_data: start_response.server_data,
+                    registration_upload: registration_finish.message,
+                    password_history_digest: None,
+                })
+                .await,
+            Err(DomainError::InvalidInput(_))
+        ));
+        attempt_login(&handler, "bob", "password1").await?;
+
+        // Only the keyed digests are stored, not the ones computed by the client.
+        let client_digest = password_history_digest("bob", "password1").unwrap();
+        let stored = model::PasswordHistory::find()
+            .filter(PasswordHistoryColumn::UserId.eq(UserId::new("bob")))
+            .all(&handler.sql_pool)
+            .await?;
+        assert_eq!(stored.len(), 2);
+        assert!(stored.iter().all(|p| p.digest != client_digest));
+        assert!(stored
+            .iter()
+            .any(|p| p.digest == handler.keyed_history_digest(&client_digest)));
         Ok(())
     }
 
@@ -320,6 +826,245 @@ mod tests {
             .unwrap_err();
     }
 
+    #[tokio::test]
+    async fn test_lockout() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.max_failed_login_attempts = 2;
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = |password: &str| {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: password.to_string(),
+            })
+        };
+        // A successful login resets the count.
+        bind("wrong_password").await.unwrap_err();
+        bind("bob00").await?;
+        bind("wrong_password").await.unwrap_err();
+        assert_eq!(handler.get_user_details(&bob).await?.locked_date, None);
+        // The web logins count too, once the client gave up on finishing them.
+        attempt_login(&handler, "bob", "wrong_password")
+            .await
+            .unwrap_err();
+        assert_eq!(handler.get_user_details(&bob).await?.locked_date, None);
+        handler
+            .record_abandoned_logins_at(Instant::now() + LOGIN_FINISH_TIMEOUT)
+            .await?;
+        // The right password is refused once locked.
+        attempt_login(&handler, "bob", "bob00").await.unwrap_err();
+        bind("bob00").await.unwrap_err();
+        assert!(handler.get_user_details(&bob).await?.locked_date.is_some());
+        handler.unlock_user(&bob).await?;
+        assert_eq!(handler.get_user_details(&bob).await?.locked_date, None);
+        attempt_login(&handler, "bob", "bob00").await?;
+        bind("bob00").await?;
+        handler
+            .unlock_user(&UserId::new("ghost"))
+            .await
+            .unwrap_err();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_totp_lockout() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.max_failed_login_attempts = 2;
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let secret = handler.start_totp_enrollment(&bob).await?;
+        let now = chrono::Utc::now().timestamp();
+        handler
+            .confirm_totp_enrollment(&bob, &totp::get_code_at(&secret, now))
+            .await?;
+        assert!(!handler.check_totp_code(&bob, "000000").await?);
+        // The right password doesn't reset the count of the wrong codes.
+        attempt_login(&handler, "bob", "bob00").await?;
+        assert!(!handler.check_totp_code(&bob, "000000").await?);
+        assert!(handler.get_user_details(&bob).await?.locked_date.is_some());
+        // The right code is refused once locked.
+        assert!(
+            !handler
+                .check_totp_code(&bob, &totp::get_code_at(&secret, now + 30))
+                .await?
+        );
+        attempt_login(&handler, "bob", "bob00").await.unwrap_err();
+        handler.unlock_user(&bob).await?;
+        attempt_login(&handler, "bob", "bob00").await?;
+        assert!(
+            handler
+                .check_totp_code(&bob, &totp::get_code_at(&secret, now + 30))
+                .await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lockout_expiry() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let mut config = get_default_config();
+        config.max_failed_login_attempts = 1;
+        config.account_lockout_minutes = 10;
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = |password: &str| {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: password.to_string(),
+            })
+        };
+        bind("wrong_password").await.unwrap_err();
+        bind("bob00").await.unwrap_err();
+        // Pretend the account was locked 11 minutes ago.
+        model::User::update_many()
+            .col_expr(
+                UserColumn::LockedDate,
+                Expr::value(chrono::Utc::now() - chrono::Duration::minutes(11)),
+            )
+            .filter(UserColumn::UserId.eq(bob.clone()))
+            .exec(&handler.sql_pool)
+            .await?;
+        bind("bob00").await?;
+        assert_eq!(handler.get_user_details(&bob).await?.locked_date, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_grace_logins() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let handler = SqlOpaqueHandler::new(get_default_config(), sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        assert_eq!(handler.use_grace_login(&bob, 2).await?, Some(1));
+        assert_eq!(handler.use_grace_login(&bob, 2).await?, Some(0));
+        assert_eq!(handler.use_grace_login(&bob, 2).await?, None);
+        // A new password resets the count.
+        register_password(&handler, &bob, &secstr::SecUtf8::from("bob01")).await?;
+        assert_eq!(handler.use_grace_login(&bob, 2).await?, Some(1));
+        assert_eq!(
+            handler.use_grace_login(&UserId::new("ghost"), 2).await?,
+            None
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_account_validity() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = || {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: "bob00".to_string(),
+            })
+        };
+        let now = chrono::Utc::now();
+        let day = chrono::Duration::days(1);
+        handler
+            .set_account_validity(&bob, Some(now + day), None)
+            .await?;
+        bind().await.unwrap_err();
+        attempt_login(&handler, "bob", "bob00").await.unwrap_err();
+        handler
+            .set_account_validity(&bob, Some(now - day), Some(now + day))
+            .await?;
+        bind().await?;
+        attempt_login(&handler, "bob", "bob00").await?;
+        handler
+            .set_account_validity(&bob, None, Some(now - day))
+            .await?;
+        bind().await.unwrap_err();
+        handler.set_account_validity(&bob, None, None).await?;
+        bind().await?;
+        handler
+            .set_account_validity(&bob, Some(now), Some(now - day))
+            .await
+            .unwrap_err();
+        handler
+            .set_account_validity(&UserId::new("ghost"), None, None)
+            .await
+            .unwrap_err();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disabled_user() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = || {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: "bob00".to_string(),
+            })
+        };
+        handler.set_user_enabled(&bob, false).await?;
+        assert!(!handler.get_user_details(&bob).await?.enabled);
+        bind().await.unwrap_err();
+        attempt_login(&handler, "bob", "bob00").await.unwrap_err();
+        handler.set_user_enabled(&bob, true).await?;
+        assert!(handler.get_user_details(&bob).await?.enabled);
+        bind().await?;
+        attempt_login(&handler, "bob", "bob00").await?;
+        handler
+            .set_user_enabled(&UserId::new("ghost"), false)
+            .await
+            .unwrap_err();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_imported_password_hash() -> Result<()> {
+        let sql_pool = get_initialized_db().await;
+        let config = get_default_config();
+        let handler = SqlOpaqueHandler::new(config, sql_pool);
+        insert_user(&handler, "bob", "bob00").await;
+        let bob = UserId::new("bob");
+        let bind = |password: &str| {
+            handler.bind(BindRequest {
+                name: bob.clone(),
+                password: password.to_string(),
+            })
+        };
+        handler
+            .import_password_hash(&bob, "{SSHA}not a hash")
+            .await
+            .unwrap_err();
+        handler
+            .import_password_hash(&UserId::new("ghost"), "{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=")
+            .await
+            .unwrap_err();
+        // The SSHA hash of "password": it replaces the current password.
+        handler
+            .import_password_hash(&bob, "{SSHA}N+vXsN2ny8mTqd6ZYuHcJVHvE00BAgMEBQYHCA==")
+            .await?;
+        bind("bob00").await.unwrap_err();
+        // The web UI can't check it.
+        attempt_login(&handler, "bob", "password")
+            .await
+            .unwrap_err();
+        bind("wrong_password").await.unwrap_err();
+        bind("password").await?;
+        // It was replaced with an OPAQUE password file.
+        assert!(handler
+            .get_password_file_for_user(bob.clone())
+            .await?
+            .is_some());
+        attempt_login(&handler, "bob", "password").await?;
+        bind("password").await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_user_no_password() {
         let sql_pool = get_initialized_db().await;
@@ -335,4 +1080,39 @@ mod tests {
             .await
             .unwrap_err();
     }
+
+    #[tokio::test]
+    async fn test_migrate_password_files() {
+        let config = get_default_config();
+        let old_handler = SqlOpaqueHandler::new(config.clone(), get_initialized_db().await);
+        insert_user(&old_handler, "bob", "bob00").await;
+        insert_user_no_password(&old_handler, "john").await;
+        let password_files = export_password_files(&old_handler).await.unwrap();
+        assert_eq!(
+            password_files.iter().map(|(u, _)| u).collect::<Vec<_>>(),
+            vec![&UserId::new("bob")]
+        );
+
+        let new_handler = SqlOpaqueHandler::new(config, get_initialized_db().await);
+        insert_user_no_password(&new_handler, "bob").await;
+        for (user_id, password_file) in &password_files {
+            import_password_file(&new_handler, user_id, password_file)
+                .await
+                .unwrap();
+        }
+        import_password_file(&new_handler, &UserId::new("john"), &password_files[0].1)
+            .await
+            .unwrap_err();
+        import_password_file(&new_handler, &UserId::new("bob"), b"garbage")
+            .await
+            .unwrap_err();
+        new_handler
+            .bind(BindRequest {
+                name: UserId::new("bob"),
+                password: "bob00".to_string(),
+            })
+            .await
+            .unwrap();
+        attempt_login(&new_handler, "bob", "bob00").await.unwrap();
+    }
 }
@@ -1,8 +1,69 @@
 use super::sql_migrations::{get_schema_version, migrate_from_version, upgrade_to_v1};
-use sea_orm::Value;
+use async_trait::async_trait;
+use sea_orm::{
+    ConnectionTrait, DatabaseTransaction, DbBackend, DbErr, ExecResult, QueryResult, Statement,
+    TransactionTrait, Value,
+};
+use std::sync::Arc;
 
 pub type DbConnection = sea_orm::DatabaseConnection;
 
+/// The connection of a backend handler: the pool, or a transaction shared by the clones of the
+/// handler until it's committed.
+#[derive(Clone)]
+pub enum SqlConnection {
+    Pool(DbConnection),
+    Transaction(Arc<DatabaseTransaction>),
+}
+
+impl SqlConnection {
+    /// Like [`TransactionTrait::begin`]: inside a transaction, this is a savepoint.
+    pub async fn begin(&self) -> Result<DatabaseTransaction, DbErr> {
+        match self {
+            SqlConnection::Pool(pool) => pool.begin().await,
+            SqlConnection::Transaction(transaction) => transaction.begin().await,
+        }
+    }
+}
+
+#[async_trait]
+impl ConnectionTrait for SqlConnection {
+    fn get_database_backend(&self) -> DbBackend {
+        match self {
+            SqlConnection::Pool(pool) => pool.get_database_backend(),
+            SqlConnection::Transaction(transaction) => transaction.get_database_backend(),
+        }
+    }
+
+    async fn execute(&self, statement: Statement) -> Result<ExecResult, DbErr> {
+        match self {
+            SqlConnection::Pool(pool) => pool.execute(statement).await,
+            SqlConnection::Transaction(transaction) => transaction.execute(statement).await,
+        }
+    }
+
+    async fn query_one(&self, statement: Statement) -> Result<Option<QueryResult>, DbErr> {
+        match self {
+            SqlConnection::Pool(pool) => pool.query_one(statement).await,
+            SqlConnection::Transaction(transaction) => transaction.query_one(statement).await,
+        }
+    }
+
+    async fn query_all(&self, statement: Statement) -> Result<Vec<QueryResult>, DbErr> {
+        match self {
+            SqlConnection::Pool(pool) => pool.query_all(statement).await,
+            SqlConnection::Transaction(transaction) => transaction.query_all(statement).await,
+        }
+    }
+
+    fn support_returning(&self) -> bool {
+        match self {
+            SqlConnection::Pool(pool) => pool.support_returning(),
+            SqlConnection::Transaction(transaction) => transaction.support_returning(),
+        }
+    }
+}
+
 #[derive(Copy, PartialEq, Eq, Debug, Clone)]
 pub struct SchemaVersion(pub u8);
 
@@ -180,7 +241,7 @@ mod tests {
             .unwrap()
             .unwrap(),
             sql_migrations::JustSchemaVersion {
-                version: SchemaVersion(1)
+                version: sql_migrations::LAST_SCHEMA_VERSION
             }
         );
     }
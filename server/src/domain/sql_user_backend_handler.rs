@@ -1,55 +1,219 @@
 use super::{
     error::{DomainError, Result},
-    handler::{CreateUserRequest, UpdateUserRequest, UserBackendHandler, UserRequestFilter},
-    model::{self, GroupColumn, UserColumn},
+    handler::{
+        CreateUserRequest, MergeUsersRequest, Page, PageRequest, SortKey, SubStringFilter,
+        UpdateUserRequest, UserBackendHandler, UserMergeReport, UserRequestFilter,
+    },
+    model::{
+        self, GroupColumn, MembershipColumn, UserAttributeColumn, UserAttributeSchemaColumn,
+        UserColumn, UserEmailColumn, UserSshKeyColumn,
+    },
     sql_backend_handler::SqlBackendHandler,
-    types::{GroupDetails, GroupId, User, UserAndGroups, UserId, Uuid},
+    types::{
+        AttributeSchema, AttributeValue, DirectoryChange, GroupDetails, GroupId, User,
+        UserAndGroups, UserId, Uuid,
+    },
 };
 use async_trait::async_trait;
 use sea_orm::{
     entity::IntoActiveValue,
     sea_query::{Cond, Expr, IntoCondition, SimpleExpr},
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, ModelTrait, QueryFilter, QueryOrder,
-    QuerySelect, QueryTrait, Set,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseTransaction, DbBackend,
+    EntityTrait, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set,
+    TransactionTrait,
 };
-use sea_query::{Alias, IntoColumnRef};
-use std::collections::HashSet;
-use tracing::{debug, instrument};
+use sea_query::{Alias, Func, Iden, IntoColumnRef, LikeExpr, Order};
+use std::collections::{HashMap, HashSet};
+use tracing::{debug, info, instrument};
 
-fn get_user_filter_expr(filter: UserRequestFilter) -> Cond {
+/// Whether equality filters on the built-in column should match the exact value.
+///
+/// Free-form text attributes (email, names) match case-insensitively, like LDAP's
+/// `caseIgnoreMatch`; identifiers and other values are compared as-is. The custom attributes have
+/// their own setting, see `get_attribute_value_condition`.
+fn is_case_sensitive(column: &UserColumn) -> bool {
+    !matches!(
+        column,
+        UserColumn::Email | UserColumn::DisplayName | UserColumn::FirstName | UserColumn::LastName
+    )
+}
+
+fn lowercase_expr(column: impl IntoColumnRef) -> Expr {
+    Expr::expr(Func::lower(Expr::col(column.into_column_ref())))
+}
+
+fn get_equality_expr(column: UserColumn, value: String, case_sensitive: bool) -> Cond {
+    if case_sensitive {
+        ColumnTrait::eq(&column, value).into_condition()
+    } else {
+        lowercase_expr(column)
+            .eq(value.to_lowercase())
+            .into_condition()
+    }
+}
+
+fn get_substring_expr(column: impl IntoColumnRef, filter: &SubStringFilter) -> SimpleExpr {
+    lowercase_expr(column).like(LikeExpr::str(&filter.to_sql_like_pattern()).escape('\\'))
+}
+
+/// Like `get_substring_expr`, respecting the case. `LIKE` ignores it with SQLite, and with the
+/// default collations of MySQL, so those use `GLOB` and a binary comparison instead.
+pub(crate) fn get_case_sensitive_substring_expr(
+    backend: DbBackend,
+    column: impl IntoColumnRef + Iden,
+    filter: &SubStringFilter,
+) -> SimpleExpr {
+    let name = Iden::to_string(&column);
+    match backend {
+        DbBackend::Sqlite => Expr::cust_with_values(
+            &format!("\"{}\" GLOB ?", name),
+            [filter.to_sql_glob_pattern()],
+        ),
+        DbBackend::MySql => Expr::cust_with_values(
+            &format!("`{}` LIKE BINARY ?", name),
+            [filter.to_case_sensitive_sql_like_pattern()],
+        ),
+        DbBackend::Postgres => Expr::col(column.into_column_ref())
+            .like(LikeExpr::str(&filter.to_case_sensitive_sql_like_pattern()).escape('\\')),
+    }
+}
+
+/// Matches the values of the custom attributes, respecting the case only for the ones defined as
+/// case-sensitive in the schema.
+fn get_attribute_value_condition(case_sensitive: SimpleExpr, case_insensitive: SimpleExpr) -> Cond {
+    let case_sensitive_attributes = model::UserAttributeSchema::find()
+        .select_only()
+        .column(UserAttributeSchemaColumn::AttributeName)
+        .filter(UserAttributeSchemaColumn::IsCaseSensitive.eq(true))
+        .into_query();
+    Cond::any()
+        .add(
+            Cond::all()
+                .add(
+                    UserAttributeColumn::AttributeName
+                        .in_subquery(case_sensitive_attributes.clone()),
+                )
+                .add(case_sensitive),
+        )
+        .add(
+            Cond::all()
+                .add(UserAttributeColumn::AttributeName.not_in_subquery(case_sensitive_attributes))
+                .add(case_insensitive),
+        )
+}
+
+fn get_user_filter_expr(filter: UserRequestFilter, backend: DbBackend) -> Cond {
     use UserRequestFilter::*;
     let group_table = Alias::new("r1");
-    fn get_repeated_filter(
-        fs: Vec<UserRequestFilter>,
-        condition: Cond,
-        default_value: bool,
-    ) -> Cond {
+    let get_repeated_filter = |fs: Vec<UserRequestFilter>, condition: Cond, default_value: bool| {
         if fs.is_empty() {
             SimpleExpr::Value(default_value.into()).into_condition()
         } else {
             fs.into_iter()
-                .map(get_user_filter_expr)
+                .map(|f| get_user_filter_expr(f, backend))
                 .fold(condition, Cond::add)
         }
-    }
+    };
     match filter {
         And(fs) => get_repeated_filter(fs, Cond::all(), true),
         Or(fs) => get_repeated_filter(fs, Cond::any(), false),
-        Not(f) => get_user_filter_expr(*f).not(),
+        Not(f) => get_user_filter_expr(*f, backend).not(),
         UserId(user_id) => ColumnTrait::eq(&UserColumn::UserId, user_id).into_condition(),
         Equality(s1, s2) => {
             if s1 == UserColumn::UserId {
                 panic!("User id should be wrapped")
+            } else if s1 == UserColumn::Email {
+                // Any of the email addresses of the user can match, not just the primary one.
+                let alias_owners = model::UserEmail::find()
+                    .select_only()
+                    .column(UserEmailColumn::UserId)
+                    .filter(lowercase_expr(UserEmailColumn::Email).eq(s2.to_lowercase()))
+                    .into_query();
+                Cond::any()
+                    .add(get_equality_expr(s1, s2, false))
+                    .add(UserColumn::UserId.in_subquery(alias_owners))
+            } else if s1 == UserColumn::UidNumber || s1 == UserColumn::GidNumber {
+                match s2.parse::<i32>() {
+                    Ok(number) => ColumnTrait::eq(&s1, number).into_condition(),
+                    Err(_) => SimpleExpr::Value(false.into()).into_condition(),
+                }
+            } else if s1 == UserColumn::CreationDate || s1 == UserColumn::ModifiedDate {
+                // Compare the timestamps rather than their string representations.
+                match chrono::DateTime::parse_from_rfc3339(&s2) {
+                    Ok(time) => {
+                        ColumnTrait::eq(&s1, time.with_timezone(&chrono::Utc)).into_condition()
+                    }
+                    Err(_) => SimpleExpr::Value(false.into()).into_condition(),
+                }
             } else {
-                ColumnTrait::eq(&s1, s2).into_condition()
+                let case_sensitive = is_case_sensitive(&s1);
+                get_equality_expr(s1, s2, case_sensitive)
             }
         }
+        Substring(column, filter) => {
+            let condition = get_substring_expr(column, &filter);
+            if column == UserColumn::Email {
+                let alias_owners = model::UserEmail::find()
+                    .select_only()
+                    .column(UserEmailColumn::UserId)
+                    .filter(get_substring_expr(UserEmailColumn::Email, &filter))
+                    .into_query();
+                Cond::any()
+                    .add(condition)
+                    .add(UserColumn::UserId.in_subquery(alias_owners))
+            } else {
+                condition.into_condition()
+            }
+        }
+        EmailAlias(email) => {
+            let alias_owners = model::UserEmail::find()
+                .select_only()
+                .column(UserEmailColumn::UserId)
+                .filter(lowercase_expr(UserEmailColumn::Email).eq(email.to_lowercase()))
+                .into_query();
+            UserColumn::UserId
+                .in_subquery(alias_owners)
+                .into_condition()
+        }
         MemberOf(group) => Expr::col((group_table, GroupColumn::DisplayName))
             .eq(group)
             .into_condition(),
         MemberOfId(group_id) => Expr::col((group_table, GroupColumn::GroupId))
             .eq(group_id)
             .into_condition(),
+        AttributeEquality(name, value) => {
+            let owners = model::UserAttribute::find()
+                .select_only()
+                .column(UserAttributeColumn::UserId)
+                .filter(UserAttributeColumn::AttributeName.eq(name))
+                .filter(get_attribute_value_condition(
+                    UserAttributeColumn::Value.eq(value.clone()),
+                    lowercase_expr(UserAttributeColumn::Value).eq(value.to_lowercase()),
+                ))
+                .into_query();
+            UserColumn::UserId.in_subquery(owners).into_condition()
+        }
+        AttributeSubstring(name, filter) => {
+            let owners = model::UserAttribute::find()
+                .select_only()
+                .column(UserAttributeColumn::UserId)
+                .filter(UserAttributeColumn::AttributeName.eq(name))
+                .filter(get_attribute_value_condition(
+                    get_case_sensitive_substring_expr(backend, UserAttributeColumn::Value, &filter),
+                    get_substring_expr(UserAttributeColumn::Value, &filter),
+                ))
+                .into_query();
+            UserColumn::UserId.in_subquery(owners).into_condition()
+        }
+        AttributePresent(name) => {
+            let owners = model::UserAttribute::find()
+                .select_only()
+                .column(UserAttributeColumn::UserId)
+                .filter(UserAttributeColumn::AttributeName.eq(name))
+                .into_query();
+            UserColumn::UserId.in_subquery(owners).into_condition()
+        }
+        Enabled(enabled) => UserColumn::Enabled.eq(enabled).into_condition(),
     }
 }
 fn to_value(opt_name: &Option<String>) -> ActiveValue<Option<String>> {
@@ -63,44 +227,510 @@ fn to_value(opt_name: &Option<String>) -> ActiveValue<Option<String>> {
     }
 }
 
-#[async_trait]
-impl UserBackendHandler for SqlBackendHandler {
-    #[instrument(skip_all, level = "debug", ret, err)]
-    async fn list_users(
+fn to_int_value(opt_value: Option<i32>) -> ActiveValue<Option<i32>> {
+    match opt_value {
+        None => ActiveValue::NotSet,
+        Some(value) => ActiveValue::Set(Some(value)),
+    }
+}
+
+/// Fetches the email aliases of the given users, keyed by user ID.
+async fn get_email_aliases(
+    connection: &impl ConnectionTrait,
+    user_ids: Vec<UserId>,
+) -> Result<HashMap<String, Vec<String>>> {
+    let mut aliases = HashMap::<String, Vec<String>>::new();
+    for alias in model::UserEmail::find()
+        .filter(UserEmailColumn::UserId.is_in(user_ids))
+        .order_by_asc(UserEmailColumn::Email)
+        .all(connection)
+        .await?
+    {
+        aliases
+            .entry(alias.user_id.into_string())
+            .or_default()
+            .push(alias.email);
+    }
+    Ok(aliases)
+}
+
+async fn fill_email_aliases(
+    connection: &impl ConnectionTrait,
+    users: Vec<&mut User>,
+) -> Result<()> {
+    let mut aliases = get_email_aliases(
+        connection,
+        users.iter().map(|u| u.user_id.clone()).collect(),
+    )
+    .await?;
+    for user in users {
+        user.email_aliases = aliases.remove(user.user_id.as_str()).unwrap_or_default();
+    }
+    Ok(())
+}
+
+async fn fill_ssh_public_keys(
+    connection: &impl ConnectionTrait,
+    users: Vec<&mut User>,
+) -> Result<()> {
+    let mut keys = HashMap::<String, Vec<String>>::new();
+    for key in model::UserSshKey::find()
+        .filter(UserSshKeyColumn::UserId.is_in(users.iter().map(|u| u.user_id.clone())))
+        .order_by_asc(UserSshKeyColumn::KeyId)
+        .all(connection)
+        .await?
+    {
+        keys.entry(key.user_id.into_string())
+            .or_default()
+            .push(key.ssh_key);
+    }
+    for user in users {
+        user.ssh_public_keys = keys.remove(user.user_id.as_str()).unwrap_or_default();
+    }
+    Ok(())
+}
+
+/// Removes the surrounding whitespace, the empty keys and the duplicates, keeping the order.
+fn normalize_ssh_public_keys(keys: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    keys.into_iter()
+        .map(|key| key.trim().to_owned())
+        .filter(|key| !key.is_empty() && seen.insert(key.clone()))
+        .collect()
+}
+
+async fn insert_ssh_public_keys(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    keys: Vec<String>,
+) -> Result<()> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    model::UserSshKey::insert_many(keys.into_iter().map(|ssh_key| {
+        model::user_ssh_keys::ActiveModel {
+            user_id: Set(user_id.clone()),
+            ssh_key: Set(ssh_key),
+            ..Default::default()
+        }
+    }))
+    .exec(connection)
+    .await?;
+    Ok(())
+}
+
+async fn get_attribute_schema(connection: &impl ConnectionTrait) -> Result<Vec<AttributeSchema>> {
+    model::UserAttributeSchema::find()
+        .order_by_asc(UserAttributeSchemaColumn::AttributeName)
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(AttributeSchema::try_from)
+        .collect()
+}
+
+async fn fill_attributes(connection: &impl ConnectionTrait, users: Vec<&mut User>) -> Result<()> {
+    let mut attributes = HashMap::<String, Vec<AttributeValue>>::new();
+    for value in model::UserAttribute::find()
+        .filter(UserAttributeColumn::UserId.is_in(users.iter().map(|u| u.user_id.clone())))
+        .order_by_asc(UserAttributeColumn::AttributeName)
+        .order_by_asc(UserAttributeColumn::ValueId)
+        .all(connection)
+        .await?
+    {
+        let user_attributes = attributes.entry(value.user_id.into_string()).or_default();
+        match user_attributes.last_mut() {
+            Some(last) if last.name == value.attribute_name => last.values.push(value.value),
+            _ => user_attributes.push(AttributeValue {
+                name: value.attribute_name,
+                values: vec![value.value],
+            }),
+        }
+    }
+    for user in users {
+        user.attributes = attributes.remove(user.user_id.as_str()).unwrap_or_default();
+    }
+    Ok(())
+}
+
+async fn normalize_attributes(
+    connection: &impl ConnectionTrait,
+    attributes: Vec<AttributeValue>,
+) -> Result<Vec<AttributeValue>> {
+    if attributes.is_empty() {
+        return Ok(attributes);
+    }
+    normalize_attribute_values(&get_attribute_schema(connection).await?, attributes)
+}
+
+/// Checks the values against the schema, and puts them in their canonical form without the empty
+/// values and the duplicates. Also used for the group attributes.
+pub(crate) fn normalize_attribute_values(
+    schema: &[AttributeSchema],
+    attributes: Vec<AttributeValue>,
+) -> Result<Vec<AttributeValue>> {
+    let mut seen_names = HashSet::new();
+    attributes
+        .into_iter()
+        .map(|attribute| {
+            let attribute_schema = schema
+                .iter()
+                .find(|s| s.name == attribute.name)
+                .ok_or_else(|| {
+                    DomainError::EntityNotFound(format!("No such attribute: '{}'", attribute.name))
+                })?;
+            if !seen_names.insert(attribute.name.clone()) {
+                return Err(DomainError::InternalError(format!(
+                    "The attribute '{}' is set twice",
+                    attribute.name
+                )));
+            }
+            let mut seen_values = HashSet::new();
+            let values = attribute
+                .values
+                .iter()
+                .filter(|value| !value.trim().is_empty())
+                .map(|value| {
+                    attribute_schema
+                        .attribute_type
+                        .normalize_value(value)
+                        .map_err(|e| {
+                            DomainError::InternalError(format!("{}: {:#}", attribute.name, e))
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|value| seen_values.insert(value.clone()))
+                .collect::<Vec<_>>();
+            if values.len() > 1 && !attribute_schema.is_list {
+                return Err(DomainError::InternalError(format!(
+                    "The attribute '{}' can only have one value",
+                    attribute.name
+                )));
+            }
+            Ok(AttributeValue {
+                name: attribute.name,
+                values,
+            })
+        })
+        .collect()
+}
+
+async fn insert_attributes(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    attributes: Vec<AttributeValue>,
+) -> Result<()> {
+    let values = attributes
+        .into_iter()
+        .flat_map(|attribute| {
+            let name = attribute.name;
+            attribute
+                .values
+                .into_iter()
+                .map(move |value| model::user_attributes::ActiveModel {
+                    user_id: Set(user_id.clone()),
+                    attribute_name: Set(name.clone()),
+                    value: Set(value),
+                    ..Default::default()
+                })
+        })
+        .collect::<Vec<_>>();
+    if values.is_empty() {
+        return Ok(());
+    }
+    model::UserAttribute::insert_many(values)
+        .exec(connection)
+        .await?;
+    Ok(())
+}
+
+/// Removes the empty aliases, the duplicates and the aliases equal to the primary email.
+fn normalize_email_aliases(primary_email: &str, aliases: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::from([primary_email.to_lowercase()]);
+    aliases
+        .into_iter()
+        .filter(|alias| !alias.is_empty() && seen.insert(alias.to_lowercase()))
+        .collect()
+}
+
+/// Checks that no group and no other user has one of the emails as an alias, or as their primary
+/// email if `check_primary_emails` is set. Primary emails alone are not required to be unique.
+async fn check_emails_available(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    emails: &[String],
+    check_primary_emails: bool,
+) -> Result<()> {
+    if emails.is_empty() {
+        return Ok(());
+    }
+    let lowercase_emails = emails.iter().map(|e| e.to_lowercase()).collect::<Vec<_>>();
+    if let Some(group) = model::Group::find()
+        .filter(lowercase_expr(GroupColumn::Email).is_in(lowercase_emails.clone()))
+        .one(connection)
+        .await?
+    {
+        return Err(DomainError::EntityAlreadyExists(format!(
+            "Email address already used by the group '{}'",
+            group.display_name
+        )));
+    }
+    let alias_owner = model::UserEmail::find()
+        .filter(lowercase_expr(UserEmailColumn::Email).is_in(lowercase_emails.clone()))
+        .filter(UserEmailColumn::UserId.ne(user_id.clone()))
+        .one(connection)
+        .await?
+        .map(|alias| alias.user_id);
+    let owner = match alias_owner {
+        None if check_primary_emails => model::User::find()
+            .filter(lowercase_expr(UserColumn::Email).is_in(lowercase_emails))
+            .filter(UserColumn::UserId.ne(user_id.clone()))
+            .one(connection)
+            .await?
+            .map(|user| user.user_id),
+        owner => owner,
+    };
+    match owner {
+        Some(owner) => Err(DomainError::EntityAlreadyExists(format!(
+            "Email address already used by '{}'",
+            owner
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks the emails of a new user: no one else has the primary email as an alias, and no one has
+/// one of the aliases. Returns the aliases without the duplicates.
+async fn check_new_user_emails(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    email: &str,
+    aliases: Vec<String>,
+) -> Result<Vec<String>> {
+    let aliases = normalize_email_aliases(email, aliases);
+    check_emails_available(connection, user_id, &[email.to_owned()], false).await?;
+    check_emails_available(connection, user_id, &aliases, true).await?;
+    Ok(aliases)
+}
+
+async fn insert_email_aliases(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    aliases: Vec<String>,
+) -> Result<()> {
+    if aliases.is_empty() {
+        return Ok(());
+    }
+    model::UserEmail::insert_many(aliases.into_iter().map(|email| {
+        model::user_emails::ActiveModel {
+            email: Set(email),
+            user_id: Set(user_id.clone()),
+        }
+    }))
+    .exec(connection)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn get_user_model(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+) -> Result<model::users::Model> {
+    model::User::find_by_id(user_id.clone())
+        .one(connection)
+        .await?
+        .ok_or_else(|| DomainError::EntityNotFound(format!("No such user: '{}'", user_id)))
+}
+
+async fn insert_user(
+    connection: &impl ConnectionTrait,
+    mut request: CreateUserRequest,
+    defaults: &[(UserColumn, String)],
+) -> Result<()> {
+    apply_user_attribute_defaults(&mut request, defaults.to_vec());
+    let now = chrono::Utc::now();
+    let uuid = Uuid::from_name_and_date(request.user_id.as_str(), &now);
+    let aliases = check_new_user_emails(
+        connection,
+        &request.user_id,
+        &request.email,
+        request.email_aliases,
+    )
+    .await?;
+    let attributes = normalize_attributes(connection, request.attributes).await?;
+    let new_user = model::users::ActiveModel {
+        user_id: Set(request.user_id.clone()),
+        email: Set(request.email),
+        display_name: to_value(&request.display_name),
+        first_name: to_value(&request.first_name),
+        last_name: to_value(&request.last_name),
+        avatar: request.avatar.into_active_value(),
+        preferred_language: to_value(&request.preferred_language),
+        uid_number: ActiveValue::Set(request.uid_number),
+        gid_number: ActiveValue::Set(request.gid_number),
+        home_directory: to_value(&request.home_directory),
+        login_shell: to_value(&request.login_shell),
+        creation_date: ActiveValue::Set(now),
+        modified_date: ActiveValue::Set(now),
+        uuid: ActiveValue::Set(uuid),
+        ..Default::default()
+    };
+    new_user.insert(connection).await?;
+    insert_email_aliases(connection, &request.user_id, aliases).await?;
+    let ssh_public_keys = normalize_ssh_public_keys(request.ssh_public_keys);
+    insert_ssh_public_keys(connection, &request.user_id, ssh_public_keys).await?;
+    insert_attributes(connection, &request.user_id, attributes).await?;
+    Ok(())
+}
+
+pub(crate) async fn insert_membership(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+    group_id: GroupId,
+) -> Result<()> {
+    if model::Membership::find_by_id((user_id.clone(), group_id))
+        .one(connection)
+        .await?
+        .is_some()
+    {
+        return Err(DomainError::EntityAlreadyExists(format!(
+            "'{}' is already a member of {:?}",
+            user_id, group_id
+        )));
+    }
+    let new_membership = model::memberships::ActiveModel {
+        user_id: ActiveValue::Set(user_id.clone()),
+        group_id: ActiveValue::Set(group_id),
+    };
+    new_membership.insert(connection).await?;
+    Ok(())
+}
+
+async fn delete_user_model(connection: &impl ConnectionTrait, user_id: &UserId) -> Result<()> {
+    let res = model::User::delete_by_id(user_id.clone())
+        .exec(connection)
+        .await?;
+    if res.rows_affected == 0 {
+        return Err(DomainError::EntityNotFound(format!(
+            "No such user: '{}'",
+            user_id
+        )));
+    }
+    Ok(())
+}
+
+/// Each operation of a batch runs in its own savepoint, so that a failed one doesn't abort the
+/// whole transaction: its changes are undone, and the next operations can still run.
+async fn end_savepoint(savepoint: DatabaseTransaction, result: &Result<()>) -> Result<()> {
+    if result.is_ok() {
+        savepoint.commit().await?;
+    } else {
+        savepoint.rollback().await?;
+    }
+    Ok(())
+}
+
+/// Commits the batch, unless one of the operations failed and partial batches aren't wanted.
+/// Returns whether it was committed.
+async fn end_batch(
+    transaction: DatabaseTransaction,
+    results: &[Result<()>],
+    best_effort: bool,
+) -> Result<bool> {
+    if best_effort || results.iter().all(Result::is_ok) {
+        transaction.commit().await?;
+        Ok(true)
+    } else {
+        transaction.rollback().await?;
+        Ok(false)
+    }
+}
+
+/// Records a change to the members of the group, for its modification date.
+pub(crate) async fn touch_group(
+    connection: &impl ConnectionTrait,
+    group_id: GroupId,
+) -> Result<()> {
+    model::groups::ActiveModel {
+        group_id: Set(group_id),
+        modified_date: Set(chrono::Utc::now()),
+        ..Default::default()
+    }
+    .update(connection)
+    .await?;
+    Ok(())
+}
+
+/// The groups the user is explicitly a member of, excluding the dynamic groups.
+async fn get_static_group_ids(
+    connection: &impl ConnectionTrait,
+    user_id: &UserId,
+) -> Result<Vec<GroupId>> {
+    Ok(model::Membership::find()
+        .filter(MembershipColumn::UserId.eq(user_id.clone()))
+        .order_by_asc(MembershipColumn::GroupId)
+        .all(connection)
+        .await?
+        .into_iter()
+        .map(|membership| membership.group_id)
+        .collect())
+}
+
+/// Copies the value of the source to the target if it has none, or if `overwrite` is set.
+fn merge_attribute<T: PartialEq>(
+    name: &str,
+    source: Option<T>,
+    target: &Option<T>,
+    overwrite: bool,
+    value: &mut ActiveValue<Option<T>>,
+    merged_attributes: &mut Vec<String>,
+) where
+    Option<T>: Into<sea_orm::Value>,
+{
+    if source.is_some() && (target.is_none() || overwrite) && &source != target {
+        *value = ActiveValue::Set(source);
+        merged_attributes.push(name.to_string());
+    }
+}
+
+impl SqlBackendHandler {
+    /// Lists the users, taking only the explicit memberships into account.
+    pub(crate) async fn list_users_without_dynamic_groups(
         &self,
         filters: Option<UserRequestFilter>,
         get_groups: bool,
+        sort_keys: &[SortKey<UserColumn>],
     ) -> Result<Vec<UserAndGroups>> {
-        debug!(?filters);
-        let query = model::User::find()
-            .filter(
-                filters
-                    .map(|f| {
-                        UserColumn::UserId
-                            .in_subquery(
-                                model::User::find()
-                                    .find_also_linked(model::memberships::UserToGroup)
-                                    .select_only()
-                                    .column(UserColumn::UserId)
-                                    .filter(get_user_filter_expr(f))
-                                    .into_query(),
-                            )
-                            .into_condition()
-                    })
-                    .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition()),
+        let query = sort_keys
+            .iter()
+            .fold(
+                model::User::find().filter(get_users_condition(
+                    filters,
+                    self.sql_pool.get_database_backend(),
+                )),
+                |query, key| {
+                    query.order_by(
+                        key.column,
+                        if key.descending {
+                            Order::Desc
+                        } else {
+                            Order::Asc
+                        },
+                    )
+                },
             )
             .order_by_asc(UserColumn::UserId);
-        if !get_groups {
-            Ok(query
-                .into_model::<User>()
+        let mut users = if !get_groups {
+            query
                 .all(&self.sql_pool)
                 .await?
                 .into_iter()
                 .map(|u| UserAndGroups {
-                    user: u,
+                    user: u.into(),
                     groups: None,
                 })
-                .collect())
+                .collect::<Vec<_>>()
         } else {
             let results = query
                 //find_with_linked?
@@ -111,7 +741,7 @@ impl UserBackendHandler for SqlBackendHandler {
                 .all(&self.sql_pool)
                 .await?;
             use itertools::Itertools;
-            Ok(results
+            results
                 .iter()
                 .group_by(|(u, _)| u)
                 .into_iter()
@@ -126,18 +756,182 @@ impl UserBackendHandler for SqlBackendHandler {
                         groups: Some(groups),
                     }
                 })
-                .collect())
+                .collect::<Vec<_>>()
+        };
+        fill_email_aliases(
+            &self.sql_pool,
+            users.iter_mut().map(|u| &mut u.user).collect(),
+        )
+        .await?;
+        fill_ssh_public_keys(
+            &self.sql_pool,
+            users.iter_mut().map(|u| &mut u.user).collect(),
+        )
+        .await?;
+        fill_attributes(
+            &self.sql_pool,
+            users.iter_mut().map(|u| &mut u.user).collect(),
+        )
+        .await?;
+        Ok(users)
+    }
+}
+
+/// The users matching the filters, through a subquery since the filters can join the groups.
+pub(crate) fn get_users_condition(filters: Option<UserRequestFilter>, backend: DbBackend) -> Cond {
+    filters
+        .map(|f| {
+            UserColumn::UserId
+                .in_subquery(
+                    model::User::find()
+                        .find_also_linked(model::memberships::UserToGroup)
+                        .select_only()
+                        .column(UserColumn::UserId)
+                        .filter(get_user_filter_expr(f, backend))
+                        .into_query(),
+                )
+                .into_condition()
+        })
+        .unwrap_or_else(|| SimpleExpr::Value(true.into()).into_condition())
+}
+
+/// Replaces the membership filters on dynamic groups with the filter defining the group.
+fn expand_dynamic_groups(
+    filter: UserRequestFilter,
+    dynamic_groups: &[(GroupDetails, UserRequestFilter)],
+) -> UserRequestFilter {
+    use UserRequestFilter::*;
+    let rec = |f| expand_dynamic_groups(f, dynamic_groups);
+    let find_filter = |is_group: &dyn Fn(&GroupDetails) -> bool| {
+        dynamic_groups
+            .iter()
+            .find(|(g, _)| is_group(g))
+            .map(|(_, f)| f.clone())
+    };
+    match filter {
+        And(fs) => And(fs.into_iter().map(rec).collect()),
+        Or(fs) => Or(fs.into_iter().map(rec).collect()),
+        Not(f) => Not(Box::new(rec(*f))),
+        MemberOf(name) => {
+            let group_filter = find_filter(&|g| g.display_name == name);
+            group_filter.unwrap_or(MemberOf(name))
+        }
+        MemberOfId(id) => find_filter(&|g| g.group_id == id).unwrap_or(MemberOfId(id)),
+        f => f,
+    }
+}
+
+/// Fills in the attributes that were not provided with their configured default value.
+fn apply_user_attribute_defaults(
+    request: &mut CreateUserRequest,
+    defaults: Vec<(UserColumn, String)>,
+) {
+    for (column, value) in defaults {
+        let attribute = match column {
+            UserColumn::DisplayName => &mut request.display_name,
+            UserColumn::FirstName => &mut request.first_name,
+            UserColumn::LastName => &mut request.last_name,
+            UserColumn::PreferredLanguage => &mut request.preferred_language,
+            _ => continue,
+        };
+        if attribute.as_deref().unwrap_or_default().is_empty() {
+            *attribute = Some(value);
+        }
+    }
+}
+
+#[async_trait]
+impl UserBackendHandler for SqlBackendHandler {
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_users(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+    ) -> Result<Vec<UserAndGroups>> {
+        self.list_users_sorted(filters, get_groups, Vec::new())
+            .await
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_users_sorted(
+        &self,
+        filters: Option<UserRequestFilter>,
+        get_groups: bool,
+        sort_keys: Vec<SortKey<UserColumn>>,
+    ) -> Result<Vec<UserAndGroups>> {
+        debug!(?filters, ?sort_keys);
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        let filters = filters.map(|f| expand_dynamic_groups(f, &dynamic_groups));
+        let mut users = self
+            .list_users_without_dynamic_groups(filters, get_groups, &sort_keys)
+            .await?;
+        if get_groups {
+            for (group, filter) in &dynamic_groups {
+                let members = self
+                    .get_dynamic_group_members(filter)
+                    .await?
+                    .into_iter()
+                    .map(UserId::into_string)
+                    .collect::<HashSet<_>>();
+                users
+                    .iter_mut()
+                    .filter(|u| members.contains(u.user.user_id.as_str()))
+                    .for_each(|u| u.groups.get_or_insert_with(Vec::new).push(group.clone()));
+            }
         }
+        Ok(users)
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_users_page(
+        &self,
+        filters: Option<UserRequestFilter>,
+        page: PageRequest<UserColumn>,
+    ) -> Result<Page<User>> {
+        debug!(?filters, ?page);
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        let filters = filters.map(|f| expand_dynamic_groups(f, &dynamic_groups));
+        let query = model::User::find().filter(get_users_condition(
+            filters,
+            self.sql_pool.get_database_backend(),
+        ));
+        let total_count = query.clone().count(&self.sql_pool).await? as u64;
+        let order = if page.descending {
+            Order::Desc
+        } else {
+            Order::Asc
+        };
+        let mut users = query
+            .order_by(page.order_by, order.clone())
+            .order_by(UserColumn::UserId, order)
+            .offset(page.offset)
+            .limit(page.limit)
+            .all(&self.sql_pool)
+            .await?
+            .into_iter()
+            .map(User::from)
+            .collect::<Vec<_>>();
+        fill_email_aliases(&self.sql_pool, users.iter_mut().collect()).await?;
+        fill_ssh_public_keys(&self.sql_pool, users.iter_mut().collect()).await?;
+        fill_attributes(&self.sql_pool, users.iter_mut().collect()).await?;
+        Ok(Page {
+            items: users,
+            total_count,
+        })
     }
 
     #[instrument(skip_all, level = "debug", ret)]
     async fn get_user_details(&self, user_id: &UserId) -> Result<User> {
         debug!(?user_id);
-        model::User::find_by_id(user_id.to_owned())
-            .into_model::<User>()
+        let mut user: User = model::User::find_by_id(user_id.to_owned())
             .one(&self.sql_pool)
             .await?
-            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))
+            .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?
+            .into();
+        fill_email_aliases(&self.sql_pool, vec![&mut user]).await?;
+        fill_ssh_public_keys(&self.sql_pool, vec![&mut user]).await?;
+        fill_attributes(&self.sql_pool, vec![&mut user]).await?;
+        Ok(user)
     }
 
     #[instrument(skip_all, level = "debug", ret, err)]
@@ -147,76 +941,255 @@ impl UserBackendHandler for SqlBackendHandler {
             .one(&self.sql_pool)
             .await?
             .ok_or_else(|| DomainError::EntityNotFound(user_id.to_string()))?;
-        Ok(HashSet::from_iter(
+        let mut groups = HashSet::from_iter(
             user.find_linked(model::memberships::UserToGroup)
                 .into_model::<GroupDetails>()
                 .all(&self.sql_pool)
                 .await?,
-        ))
+        );
+        let dynamic_groups = self.get_dynamic_groups().await?;
+        groups.extend(
+            self.get_matching_dynamic_groups(user_id, &dynamic_groups)
+                .await?,
+        );
+        Ok(groups)
     }
 
     #[instrument(skip_all, level = "debug", err)]
     async fn create_user(&self, request: CreateUserRequest) -> Result<()> {
         debug!(user_id = ?request.user_id);
-        let now = chrono::Utc::now();
-        let uuid = Uuid::from_name_and_date(request.user_id.as_str(), &now);
-        let new_user = model::users::ActiveModel {
-            user_id: Set(request.user_id),
-            email: Set(request.email),
-            display_name: to_value(&request.display_name),
-            first_name: to_value(&request.first_name),
-            last_name: to_value(&request.last_name),
-            avatar: request.avatar.into_active_value(),
-            creation_date: ActiveValue::Set(now),
-            uuid: ActiveValue::Set(uuid),
-            ..Default::default()
-        };
-        new_user.insert(&self.sql_pool).await?;
+        let defaults = self
+            .config
+            .get_user_attribute_defaults()
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        let user_id = request.user_id.clone();
+        let transaction = self.sql_pool.begin().await?;
+        insert_user(&transaction, request, &defaults).await?;
+        transaction.commit().await?;
+        self.notify_change(DirectoryChange::UserCreated(user_id));
         Ok(())
     }
 
+    #[instrument(skip(self), level = "debug", err)]
+    async fn check_emails_available(
+        &self,
+        user_id: &UserId,
+        email: &str,
+        email_aliases: &[String],
+    ) -> Result<()> {
+        check_new_user_emails(&self.sql_pool, user_id, email, email_aliases.to_vec())
+            .await
+            .map(|_| ())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn create_users(
+        &self,
+        requests: Vec<CreateUserRequest>,
+        best_effort: bool,
+    ) -> Result<Vec<Result<()>>> {
+        debug!(count = requests.len(), best_effort);
+        let defaults = self
+            .config
+            .get_user_attribute_defaults()
+            .map_err(|e| DomainError::InternalError(e.to_string()))?;
+        let user_ids = requests
+            .iter()
+            .map(|request| request.user_id.clone())
+            .collect::<Vec<_>>();
+        let transaction = self.sql_pool.begin().await?;
+        let mut results = Vec::with_capacity(requests.len());
+        for request in requests {
+            let savepoint = transaction.begin().await?;
+            let result = insert_user(&savepoint, request, &defaults).await;
+            end_savepoint(savepoint, &result).await?;
+            results.push(result);
+        }
+        if end_batch(transaction, &results, best_effort).await? {
+            for (user_id, _) in user_ids
+                .into_iter()
+                .zip(&results)
+                .filter(|(_, r)| r.is_ok())
+            {
+                self.notify_change(DirectoryChange::UserCreated(user_id));
+            }
+        }
+        Ok(results)
+    }
+
     #[instrument(skip_all, level = "debug", err)]
     async fn update_user(&self, request: UpdateUserRequest) -> Result<()> {
         debug!(user_id = ?request.user_id);
+        let transaction = self.sql_pool.begin().await?;
+        if let Some(email) = &request.email {
+            let emails = std::slice::from_ref(email);
+            check_emails_available(&transaction, &request.user_id, emails, false).await?;
+        }
+        let aliases = match request.email_aliases {
+            None => None,
+            Some(aliases) => {
+                let primary_email = match &request.email {
+                    Some(email) => email.clone(),
+                    None => {
+                        model::User::find_by_id(request.user_id.clone())
+                            .one(&transaction)
+                            .await?
+                            .ok_or_else(|| {
+                                DomainError::EntityNotFound(request.user_id.to_string())
+                            })?
+                            .email
+                    }
+                };
+                let aliases = normalize_email_aliases(&primary_email, aliases);
+                check_emails_available(&transaction, &request.user_id, &aliases, true).await?;
+                Some(aliases)
+            }
+        };
+        let attributes = normalize_attributes(&transaction, request.attributes).await?;
+        // Replacing the aliases, the keys or the custom attributes also counts as a modification
+        // of the user.
         let update_user = model::users::ActiveModel {
-            user_id: ActiveValue::Set(request.user_id),
+            user_id: ActiveValue::Set(request.user_id.clone()),
             email: request.email.map(ActiveValue::Set).unwrap_or_default(),
             display_name: to_value(&request.display_name),
             first_name: to_value(&request.first_name),
             last_name: to_value(&request.last_name),
             avatar: request.avatar.into_active_value(),
+            preferred_language: to_value(&request.preferred_language),
+            uid_number: to_int_value(request.uid_number),
+            gid_number: to_int_value(request.gid_number),
+            home_directory: to_value(&request.home_directory),
+            login_shell: to_value(&request.login_shell),
+            login_notifications: request
+                .login_notifications
+                .map(ActiveValue::Set)
+                .unwrap_or_default(),
+            modified_date: ActiveValue::Set(chrono::Utc::now()),
             ..Default::default()
         };
-        update_user.update(&self.sql_pool).await?;
+        update_user.update(&transaction).await?;
+        if let Some(aliases) = aliases {
+            model::UserEmail::delete_many()
+                .filter(UserEmailColumn::UserId.eq(request.user_id.clone()))
+                .exec(&transaction)
+                .await?;
+            insert_email_aliases(&transaction, &request.user_id, aliases).await?;
+        }
+        if let Some(ssh_public_keys) = request.ssh_public_keys {
+            model::UserSshKey::delete_many()
+                .filter(UserSshKeyColumn::UserId.eq(request.user_id.clone()))
+                .exec(&transaction)
+                .await?;
+            let ssh_public_keys = normalize_ssh_public_keys(ssh_public_keys);
+            insert_ssh_public_keys(&transaction, &request.user_id, ssh_public_keys).await?;
+        }
+        if !attributes.is_empty() {
+            model::UserAttribute::delete_many()
+                .filter(UserAttributeColumn::UserId.eq(request.user_id.clone()))
+                .filter(
+                    UserAttributeColumn::AttributeName
+                        .is_in(attributes.iter().map(|a| a.name.clone())),
+                )
+                .exec(&transaction)
+                .await?;
+            insert_attributes(&transaction, &request.user_id, attributes).await?;
+        }
+        transaction.commit().await?;
+        self.notify_change(DirectoryChange::UserUpdated(request.user_id));
         Ok(())
     }
 
     #[instrument(skip_all, level = "debug", err)]
     async fn delete_user(&self, user_id: &UserId) -> Result<()> {
         debug!(?user_id);
-        let res = model::User::delete_by_id(user_id.clone())
-            .exec(&self.sql_pool)
-            .await?;
-        if res.rows_affected == 0 {
-            return Err(DomainError::EntityNotFound(format!(
-                "No such user: '{}'",
-                user_id
-            )));
-        }
+        delete_user_model(&self.sql_pool, user_id).await?;
+        self.notify_change(DirectoryChange::UserDeleted(user_id.clone()));
         Ok(())
     }
 
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_users(
+        &self,
+        user_ids: &[UserId],
+        best_effort: bool,
+    ) -> Result<Vec<Result<()>>> {
+        debug!(?user_ids, best_effort);
+        let transaction = self.sql_pool.begin().await?;
+        let mut results = Vec::with_capacity(user_ids.len());
+        for user_id in user_ids {
+            let savepoint = transaction.begin().await?;
+            let result = delete_user_model(&savepoint, user_id).await;
+            end_savepoint(savepoint, &result).await?;
+            results.push(result);
+        }
+        if end_batch(transaction, &results, best_effort).await? {
+            for (user_id, _) in user_ids.iter().zip(&results).filter(|(_, r)| r.is_ok()) {
+                self.notify_change(DirectoryChange::UserDeleted(user_id.clone()));
+            }
+        }
+        Ok(results)
+    }
+
     #[instrument(skip_all, level = "debug", err)]
     async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
         debug!(?user_id, ?group_id);
-        let new_membership = model::memberships::ActiveModel {
-            user_id: ActiveValue::Set(user_id.clone()),
-            group_id: ActiveValue::Set(group_id),
-        };
-        new_membership.insert(&self.sql_pool).await?;
+        insert_membership(&self.sql_pool, user_id, group_id).await?;
+        touch_group(&self.sql_pool, group_id).await?;
+        self.notify_change(DirectoryChange::MembershipChanged {
+            user_id: user_id.clone(),
+            group_id,
+            added: true,
+        });
         Ok(())
     }
 
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_users_to_group(
+        &self,
+        user_ids: &[UserId],
+        group_id: GroupId,
+        best_effort: bool,
+    ) -> Result<Vec<Result<()>>> {
+        debug!(?user_ids, ?group_id, best_effort);
+        if model::Group::find_by_id(group_id)
+            .one(&self.sql_pool)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!("{:?}", group_id)));
+        }
+        let transaction = self.sql_pool.begin().await?;
+        let mut results = Vec::with_capacity(user_ids.len());
+        for user_id in user_ids {
+            let savepoint = transaction.begin().await?;
+            let result = match model::User::find_by_id(user_id.clone())
+                .one(&savepoint)
+                .await?
+            {
+                None => Err(DomainError::EntityNotFound(format!(
+                    "No such user: '{}'",
+                    user_id
+                ))),
+                Some(_) => insert_membership(&savepoint, user_id, group_id).await,
+            };
+            end_savepoint(savepoint, &result).await?;
+            results.push(result);
+        }
+        if results.iter().any(Result::is_ok) {
+            touch_group(&transaction, group_id).await?;
+        }
+        if end_batch(transaction, &results, best_effort).await? {
+            for (user_id, _) in user_ids.iter().zip(&results).filter(|(_, r)| r.is_ok()) {
+                self.notify_change(DirectoryChange::MembershipChanged {
+                    user_id: user_id.clone(),
+                    group_id,
+                    added: true,
+                });
+            }
+        }
+        Ok(results)
+    }
+
     #[instrument(skip_all, level = "debug", err)]
     async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()> {
         debug!(?user_id, ?group_id);
@@ -229,34 +1202,436 @@ impl UserBackendHandler for SqlBackendHandler {
                 user_id, group_id
             )));
         }
+        touch_group(&self.sql_pool, group_id).await?;
+        self.notify_change(DirectoryChange::MembershipChanged {
+            user_id: user_id.clone(),
+            group_id,
+            added: false,
+        });
         Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::domain::{
-        sql_backend_handler::tests::*,
-        types::{JpegPhoto, UserColumn},
-    };
-
-    #[tokio::test]
-    async fn test_list_users_no_filter() {
-        let fixture = TestFixture::new().await;
-        let users = get_user_names(&fixture.handler, None).await;
-        assert_eq!(users, vec!["bob", "john", "nogroup", "patrick"]);
-    }
 
-    #[tokio::test]
-    async fn test_list_users_user_id_filter() {
-        let fixture = TestFixture::new().await;
-        let users = get_user_names(
-            &fixture.handler,
-            Some(UserRequestFilter::UserId(UserId::new("bob"))),
-        )
-        .await;
-        assert_eq!(users, vec!["bob"]);
+    #[instrument(skip_all, level = "debug", err)]
+    async fn set_group_members(&self, group_id: GroupId, user_ids: &[UserId]) -> Result<()> {
+        debug!(?group_id, ?user_ids);
+        let transaction = self.sql_pool.begin().await?;
+        if model::Group::find_by_id(group_id)
+            .one(&transaction)
+            .await?
+            .is_none()
+        {
+            return Err(DomainError::EntityNotFound(format!("{:?}", group_id)));
+        }
+        let current_members = model::Membership::find()
+            .filter(MembershipColumn::GroupId.eq(group_id))
+            .all(&transaction)
+            .await?
+            .into_iter()
+            .map(|membership| membership.user_id)
+            .collect::<Vec<_>>();
+        let mut added = Vec::new();
+        for user_id in user_ids {
+            if current_members.contains(user_id) || added.contains(user_id) {
+                continue;
+            }
+            if model::User::find_by_id(user_id.clone())
+                .one(&transaction)
+                .await?
+                .is_none()
+            {
+                return Err(DomainError::EntityNotFound(format!(
+                    "No such user: '{}'",
+                    user_id
+                )));
+            }
+            insert_membership(&transaction, user_id, group_id).await?;
+            added.push(user_id.clone());
+        }
+        let removed = current_members
+            .into_iter()
+            .filter(|user_id| !user_ids.contains(user_id))
+            .collect::<Vec<_>>();
+        for user_id in &removed {
+            model::Membership::delete_by_id((user_id.clone(), group_id))
+                .exec(&transaction)
+                .await?;
+        }
+        if added.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+        touch_group(&transaction, group_id).await?;
+        transaction.commit().await?;
+        for (user_id, was_added) in added
+            .into_iter()
+            .map(|u| (u, true))
+            .chain(removed.into_iter().map(|u| (u, false)))
+        {
+            self.notify_change(DirectoryChange::MembershipChanged {
+                user_id,
+                group_id,
+                added: was_added,
+            });
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn create_group_with_members(
+        &self,
+        group_name: &str,
+        user_ids: &[UserId],
+    ) -> Result<GroupId> {
+        debug!(?group_name, ?user_ids);
+        let now = chrono::Utc::now();
+        let transaction = self.sql_pool.begin().await?;
+        let group_id = model::groups::ActiveModel {
+            display_name: Set(group_name.to_owned()),
+            creation_date: Set(now),
+            modified_date: Set(now),
+            uuid: Set(Uuid::from_name_and_date(group_name, &now)),
+            ..Default::default()
+        }
+        .insert(&transaction)
+        .await?
+        .group_id;
+        let mut added = Vec::new();
+        for user_id in user_ids {
+            if added.contains(user_id) {
+                continue;
+            }
+            if model::User::find_by_id(user_id.clone())
+                .one(&transaction)
+                .await?
+                .is_none()
+            {
+                return Err(DomainError::EntityNotFound(format!(
+                    "No such user: '{}'",
+                    user_id
+                )));
+            }
+            insert_membership(&transaction, user_id, group_id).await?;
+            added.push(user_id.clone());
+        }
+        transaction.commit().await?;
+        self.notify_change(DirectoryChange::GroupCreated(group_id));
+        for user_id in added {
+            self.notify_change(DirectoryChange::MembershipChanged {
+                user_id,
+                group_id,
+                added: true,
+            });
+        }
+        Ok(group_id)
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn merge_users(&self, request: MergeUsersRequest) -> Result<UserMergeReport> {
+        debug!(?request);
+        if request.source == request.target {
+            return Err(DomainError::InternalError(
+                "Cannot merge a user into itself".to_string(),
+            ));
+        }
+        let transaction = self.sql_pool.begin().await?;
+        let source = get_user_model(&transaction, &request.source).await?;
+        let target = get_user_model(&transaction, &request.target).await?;
+        let target_groups = get_static_group_ids(&transaction, &request.target).await?;
+        let added_groups = get_static_group_ids(&transaction, &request.source)
+            .await?
+            .into_iter()
+            .filter(|group_id| !target_groups.contains(group_id))
+            .collect::<Vec<_>>();
+        if !added_groups.is_empty() {
+            model::Membership::insert_many(added_groups.iter().map(|group_id| {
+                model::memberships::ActiveModel {
+                    user_id: Set(request.target.clone()),
+                    group_id: Set(*group_id),
+                }
+            }))
+            .exec(&transaction)
+            .await?;
+        }
+
+        let mut merged_attributes = Vec::new();
+        let mut update_target = model::users::ActiveModel {
+            user_id: ActiveValue::Set(request.target.clone()),
+            ..Default::default()
+        };
+        let overwrite = request.overwrite_attributes;
+        merge_attribute(
+            "cn",
+            source.display_name,
+            &target.display_name,
+            overwrite,
+            &mut update_target.display_name,
+            &mut merged_attributes,
+        );
+        merge_attribute(
+            "givenName",
+            source.first_name,
+            &target.first_name,
+            overwrite,
+            &mut update_target.first_name,
+            &mut merged_attributes,
+        );
+        merge_attribute(
+            "sn",
+            source.last_name,
+            &target.last_name,
+            overwrite,
+            &mut update_target.last_name,
+            &mut merged_attributes,
+        );
+        merge_attribute(
+            "jpegPhoto",
+            source.avatar,
+            &target.avatar,
+            overwrite,
+            &mut update_target.avatar,
+            &mut merged_attributes,
+        );
+        merge_attribute(
+            "preferredLanguage",
+            source.preferred_language,
+            &target.preferred_language,
+            overwrite,
+            &mut update_target.preferred_language,
+            &mut merged_attributes,
+        );
+        if !merged_attributes.is_empty() {
+            update_target.modified_date = Set(chrono::Utc::now());
+            update_target.update(&transaction).await?;
+        }
+
+        let mut moved_emails = Vec::new();
+        if request.delete_source {
+            let mut aliases = get_email_aliases(
+                &transaction,
+                vec![request.source.clone(), request.target.clone()],
+            )
+            .await?;
+            let mut known_emails = std::iter::once(target.email)
+                .chain(aliases.remove(request.target.as_str()).unwrap_or_default())
+                .map(|email| email.to_lowercase())
+                .collect::<HashSet<_>>();
+            moved_emails = std::iter::once(source.email)
+                .chain(aliases.remove(request.source.as_str()).unwrap_or_default())
+                .filter(|email| !email.is_empty() && known_emails.insert(email.to_lowercase()))
+                .collect();
+            // Free the aliases of the source before giving them to the target.
+            model::UserEmail::delete_many()
+                .filter(UserEmailColumn::UserId.eq(request.source.clone()))
+                .exec(&transaction)
+                .await?;
+            model::User::delete_by_id(request.source.clone())
+                .exec(&transaction)
+                .await?;
+            check_emails_available(&transaction, &request.target, &moved_emails, true).await?;
+            insert_email_aliases(&transaction, &request.target, moved_emails.clone()).await?;
+        }
+        transaction.commit().await?;
+        info!(
+            "Merged user {} into {} (source deleted: {})",
+            request.source, request.target, request.delete_source
+        );
+        for group_id in &added_groups {
+            self.notify_change(DirectoryChange::MembershipChanged {
+                user_id: request.target.clone(),
+                group_id: *group_id,
+                added: true,
+            });
+        }
+        if request.delete_source {
+            self.notify_change(DirectoryChange::UserDeleted(request.source.clone()));
+        }
+        Ok(UserMergeReport {
+            added_groups,
+            merged_attributes,
+            moved_emails,
+        })
+    }
+
+    #[instrument(skip_all, level = "debug", ret, err)]
+    async fn list_user_attribute_schema(&self) -> Result<Vec<AttributeSchema>> {
+        get_attribute_schema(&self.sql_pool).await
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()> {
+        debug!(?schema);
+        // The LDAP attribute names are case-insensitive.
+        if let Some(existing) = model::UserAttributeSchema::find()
+            .filter(
+                Cond::any()
+                    .add(UserAttributeSchemaColumn::AttributeName.eq(schema.name.clone()))
+                    .add(
+                        lowercase_expr(UserAttributeSchemaColumn::LdapName)
+                            .eq(schema.ldap_name.to_lowercase()),
+                    ),
+            )
+            .one(&self.sql_pool)
+            .await?
+        {
+            return Err(DomainError::EntityAlreadyExists(format!(
+                "The attribute '{}' already has this name or LDAP name",
+                existing.attribute_name
+            )));
+        }
+        model::user_attribute_schema::ActiveModel {
+            attribute_name: Set(schema.name),
+            attribute_type: Set(schema.attribute_type.as_str().to_owned()),
+            is_list: Set(schema.is_list),
+            is_editable: Set(schema.is_editable),
+            ldap_name: Set(schema.ldap_name),
+            is_case_sensitive: Set(schema.is_case_sensitive),
+        }
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(skip_all, level = "debug", err)]
+    async fn delete_user_attribute(&self, name: &str) -> Result<()> {
+        debug!(?name);
+        let res = model::UserAttributeSchema::delete_by_id(name.to_owned())
+            .exec(&self.sql_pool)
+            .await?;
+        if res.rows_affected == 0 {
+            return Err(DomainError::EntityNotFound(format!(
+                "No such attribute: '{}'",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::{GroupBackendHandler, GroupRequestFilter, UpdateGroupRequest},
+        sql_backend_handler::tests::*,
+        types::{AttributeType, JpegPhoto, UserColumn},
+    };
+
+    #[tokio::test]
+    async fn test_list_users_no_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(&fixture.handler, None).await;
+        assert_eq!(users, vec!["bob", "john", "nogroup", "patrick"]);
+    }
+
+    async fn get_user_page(
+        handler: &SqlBackendHandler,
+        filters: Option<UserRequestFilter>,
+        offset: u64,
+        descending: bool,
+    ) -> (u64, Vec<String>) {
+        let page = handler
+            .list_users_page(
+                filters,
+                PageRequest {
+                    order_by: UserColumn::UserId,
+                    descending,
+                    offset,
+                    limit: 3,
+                },
+            )
+            .await
+            .unwrap();
+        let ids = page
+            .items
+            .into_iter()
+            .map(|u| u.user_id.into_string())
+            .collect();
+        (page.total_count, ids)
+    }
+
+    #[tokio::test]
+    async fn test_list_users_page() {
+        let fixture = TestFixture::new().await;
+        let get_page = |filters, offset, descending| {
+            get_user_page(&fixture.handler, filters, offset, descending)
+        };
+        assert_eq!(
+            get_page(None, 0, false).await,
+            (
+                4,
+                vec!["bob".to_owned(), "john".to_owned(), "nogroup".to_owned()]
+            )
+        );
+        assert_eq!(
+            get_page(None, 3, false).await,
+            (4, vec!["patrick".to_owned()])
+        );
+        assert_eq!(
+            get_page(None, 0, true).await,
+            (
+                4,
+                vec![
+                    "patrick".to_owned(),
+                    "nogroup".to_owned(),
+                    "john".to_owned()
+                ]
+            )
+        );
+        assert_eq!(
+            get_page(
+                Some(UserRequestFilter::UserId(UserId::new("bob"))),
+                0,
+                false
+            )
+            .await,
+            (1, vec!["bob".to_owned()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_users_sorted() {
+        let fixture = TestFixture::new().await;
+        let users = fixture
+            .handler
+            .list_users_sorted(
+                None,
+                true,
+                vec![
+                    // All the users have the same email, the next key breaks the ties.
+                    SortKey {
+                        column: UserColumn::Email,
+                        descending: false,
+                    },
+                    SortKey {
+                        column: UserColumn::DisplayName,
+                        descending: true,
+                    },
+                ],
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| (u.user.user_id.into_string(), u.groups.unwrap().len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            users,
+            vec![
+                ("patrick".to_owned(), 2),
+                ("bob".to_owned(), 1),
+                ("nogroup".to_owned(), 0),
+                ("john".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_users_user_id_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::UserId(UserId::new("bob"))),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
     }
 
     #[tokio::test]
@@ -273,6 +1648,70 @@ mod tests {
         assert_eq!(users, vec!["bob"]);
     }
 
+    #[tokio::test]
+    async fn test_list_users_filter_case_insensitive() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Equality(
+                UserColumn::DisplayName,
+                "Display BOB".to_string(),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_users_substring_filter() {
+        let fixture = TestFixture::new().await;
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Substring(
+                UserColumn::DisplayName,
+                SubStringFilter::contains("Y J"),
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["john"]);
+        // Wildcards in the value are matched literally.
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Substring(
+                UserColumn::DisplayName,
+                SubStringFilter::contains("disp_ay"),
+            )),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Substring(
+                UserColumn::FirstName,
+                SubStringFilter {
+                    initial: Some("First".to_string()),
+                    any: vec!["r".to_string()],
+                    final_: Some("K".to_string()),
+                },
+            )),
+        )
+        .await;
+        assert_eq!(users, vec!["patrick"]);
+        // The initial part must be at the start.
+        let users = get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::Substring(
+                UserColumn::LastName,
+                SubStringFilter {
+                    initial: Some("bob".to_string()),
+                    ..Default::default()
+                },
+            )),
+        )
+        .await;
+        assert_eq!(users, Vec::<String>::new());
+    }
+
     #[tokio::test]
     async fn test_list_users_other_filter() {
         let fixture = TestFixture::new().await;
@@ -571,6 +2010,15 @@ mod tests {
                 first_name: Some("first_name".to_string()),
                 last_name: Some("last_name".to_string()),
                 avatar: Some(JpegPhoto::for_tests()),
+                preferred_language: Some("fr-CA".to_string()),
+                email_aliases: Some(vec!["other@email".to_string()]),
+                ssh_public_keys: Some(vec!["ssh-ed25519 AAAA bob@laptop".to_string()]),
+                uid_number: Some(1000),
+                gid_number: Some(100),
+                home_directory: Some("/home/bob".to_string()),
+                login_shell: Some("/bin/bash".to_string()),
+                login_notifications: Some(false),
+                attributes: Vec::new(),
             })
             .await
             .unwrap();
@@ -585,11 +2033,24 @@ mod tests {
         assert_eq!(user.first_name.unwrap(), "first_name");
         assert_eq!(user.last_name.unwrap(), "last_name");
         assert_eq!(user.avatar, Some(JpegPhoto::for_tests()));
+        assert_eq!(user.preferred_language.unwrap(), "fr-CA");
+        assert_eq!(user.email_aliases, vec!["other@email"]);
+        assert_eq!(user.ssh_public_keys, vec!["ssh-ed25519 AAAA bob@laptop"]);
+        assert_eq!(user.uid_number, Some(1000));
+        assert_eq!(user.gid_number, Some(100));
+        assert_eq!(user.home_directory.as_deref(), Some("/home/bob"));
+        assert_eq!(user.login_shell.as_deref(), Some("/bin/bash"));
+        assert!(!user.login_notifications);
     }
 
     #[tokio::test]
     async fn test_update_user_some_values() {
         let fixture = TestFixture::new().await;
+        let before = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
 
         fixture
             .handler
@@ -611,25 +2072,819 @@ mod tests {
         assert_eq!(user.first_name.unwrap(), "first_name");
         assert_eq!(user.last_name, None);
         assert_eq!(user.avatar, None);
+        assert!(user.login_notifications);
+        assert_eq!(user.creation_date, before.creation_date);
+        assert!(user.modified_date >= before.modified_date);
     }
 
     #[tokio::test]
-    async fn test_remove_user_from_group() {
+    async fn test_email_aliases() {
         let fixture = TestFixture::new().await;
 
         fixture
             .handler
-            .remove_user_from_group(&UserId::new("bob"), fixture.groups[0])
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email_aliases: Some(vec![
+                    "bob@work.com".to_string(),
+                    "BOB@bob.bob".to_string(),
+                    "bob@WORK.com".to_string(),
+                    "alias@bob.bob".to_string(),
+                ]),
+                ..Default::default()
+            })
             .await
             .unwrap();
 
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("bob"))
+            .await
+            .unwrap();
+        assert_eq!(user.email, "bob@bob.bob");
+        assert_eq!(user.display_name.unwrap(), "display bob");
+        assert_eq!(user.email_aliases, vec!["alias@bob.bob", "bob@work.com"]);
         assert_eq!(
             get_user_names(
                 &fixture.handler,
-                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+                Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "Bob@Work.com".to_string()
+                ))
             )
             .await,
-            vec!["patrick"]
+            vec!["bob"]
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::EmailAlias("Bob@Work.com".to_string()))
+            )
+            .await,
+            vec!["bob"]
         );
+        // The primary email is not an alias.
+        assert!(get_user_names(
+            &fixture.handler,
+            Some(UserRequestFilter::EmailAlias("bob@bob.bob".to_string()))
+        )
+        .await
+        .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_email_aliases_are_unique() {
+        let fixture = TestFixture::new().await;
+
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email_aliases: Some(vec!["shared@bob.bob".to_string()]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("patrick"),
+                email_aliases: Some(vec!["Shared@bob.bob".to_string()]),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("patrick"),
+                email: Some("shared@bob.bob".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@bob.bob".to_string(),
+                email_aliases: vec!["shared@bob.bob".to_string()],
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("patrick"))
+            .await
+            .unwrap();
+        assert_eq!(user.email, "bob@bob.bob");
+        assert!(user.email_aliases.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_email_aliases_cannot_use_group_email() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .update_group(UpdateGroupRequest {
+                group_id: fixture.groups[0],
+                display_name: None,
+                archived: None,
+                gid_number: None,
+                email: Some("team@bob.bob".to_string()),
+                attributes: Vec::new(),
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new("bob"),
+                    email_aliases: Some(vec!["Team@bob.bob".to_string()]),
+                    ..Default::default()
+                })
+                .await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ssh_public_keys() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@jim.jim".to_string(),
+                ssh_public_keys: vec![
+                    " ssh-ed25519 AAAA jim@laptop ".to_string(),
+                    String::new(),
+                    "ssh-rsa BBBB".to_string(),
+                    "ssh-ed25519 AAAA jim@laptop".to_string(),
+                ],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("jim"))
+            .await
+            .unwrap();
+        assert_eq!(
+            user.ssh_public_keys,
+            vec!["ssh-ed25519 AAAA jim@laptop", "ssh-rsa BBBB"]
+        );
+
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("jim"),
+                ssh_public_keys: Some(vec!["ssh-rsa CCCC".to_string()]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let users = fixture
+            .handler
+            .list_users(Some(UserRequestFilter::UserId(UserId::new("jim"))), false)
+            .await
+            .unwrap();
+        assert_eq!(users[0].user.ssh_public_keys, vec!["ssh-rsa CCCC"]);
+
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("unknown"),
+                ssh_public_keys: Some(vec![]),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+    }
+
+    fn attribute(name: &str, values: &[&str]) -> AttributeValue {
+        AttributeValue {
+            name: name.to_string(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_attributes() {
+        let fixture = TestFixture::new().await;
+        for (name, attribute_type, is_list) in [
+            ("nickname", AttributeType::String, true),
+            ("birthday", AttributeType::Date, false),
+        ] {
+            fixture
+                .handler
+                .add_user_attribute(AttributeSchema {
+                    name: name.to_string(),
+                    attribute_type,
+                    is_list,
+                    is_editable: true,
+                    ldap_name: name.to_string(),
+                    is_case_sensitive: false,
+                })
+                .await
+                .unwrap();
+        }
+        // The LDAP names can't collide, regardless of the case.
+        fixture
+            .handler
+            .add_user_attribute(AttributeSchema {
+                name: "other".to_string(),
+                attribute_type: AttributeType::String,
+                is_list: false,
+                is_editable: true,
+                ldap_name: "NickName".to_string(),
+                is_case_sensitive: false,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            fixture
+                .handler
+                .list_user_attribute_schema()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect::<Vec<_>>(),
+            vec!["birthday", "nickname"]
+        );
+
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@jim.jim".to_string(),
+                attributes: vec![
+                    attribute("nickname", &["Jimmy", " ", "Jim", "Jimmy"]),
+                    attribute("birthday", &["2000-02-29"]),
+                ],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("jim"))
+            .await
+            .unwrap();
+        assert_eq!(
+            user.attributes,
+            vec![
+                attribute("birthday", &["2000-02-29"]),
+                attribute("nickname", &["Jimmy", "Jim"]),
+            ]
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::AttributeEquality(
+                    "nickname".to_string(),
+                    "jim".to_string()
+                ))
+            )
+            .await,
+            vec!["jim"]
+        );
+
+        // Only the listed attributes are replaced.
+        fixture
+            .handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("jim"),
+                attributes: vec![attribute("birthday", &[])],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("jim"))
+            .await
+            .unwrap();
+        assert_eq!(
+            user.attributes,
+            vec![attribute("nickname", &["Jimmy", "Jim"])]
+        );
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::AttributePresent("birthday".to_string()))
+            )
+            .await,
+            Vec::<String>::new()
+        );
+
+        for attributes in [
+            vec![attribute("unknown", &["a"])],
+            vec![attribute("birthday", &["2000-02-30"])],
+            vec![attribute("birthday", &["2000-01-01", "2000-01-02"])],
+            vec![attribute("nickname", &["a"]), attribute("nickname", &["b"])],
+        ] {
+            fixture
+                .handler
+                .update_user(UpdateUserRequest {
+                    user_id: UserId::new("jim"),
+                    attributes,
+                    ..Default::default()
+                })
+                .await
+                .unwrap_err();
+        }
+
+        fixture
+            .handler
+            .delete_user_attribute("nickname")
+            .await
+            .unwrap();
+        fixture
+            .handler
+            .delete_user_attribute("nickname")
+            .await
+            .unwrap_err();
+        let user = fixture
+            .handler
+            .get_user_details(&UserId::new("jim"))
+            .await
+            .unwrap();
+        assert!(user.attributes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_case_sensitive_custom_attributes() {
+        let fixture = TestFixture::new().await;
+        for (name, is_case_sensitive) in [("nickname", false), ("badge", true)] {
+            fixture
+                .handler
+                .add_user_attribute(AttributeSchema {
+                    name: name.to_string(),
+                    attribute_type: AttributeType::String,
+                    is_list: false,
+                    is_editable: true,
+                    ldap_name: name.to_string(),
+                    is_case_sensitive,
+                })
+                .await
+                .unwrap();
+        }
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@jim.jim".to_string(),
+                attributes: vec![
+                    attribute("nickname", &["Jimmy"]),
+                    attribute("badge", &["AbC-1"]),
+                ],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let matches = |filter| {
+            let handler = &fixture.handler;
+            async move { !get_user_names(handler, Some(filter)).await.is_empty() }
+        };
+        let equality = |name: &str, value: &str| {
+            UserRequestFilter::AttributeEquality(name.to_string(), value.to_string())
+        };
+        let substring = |name: &str, value: &str| {
+            UserRequestFilter::AttributeSubstring(
+                name.to_string(),
+                SubStringFilter::contains(value),
+            )
+        };
+        assert!(matches(equality("nickname", "JIMMY")).await);
+        assert!(matches(substring("nickname", "IMM")).await);
+        assert!(matches(equality("badge", "AbC-1")).await);
+        assert!(!matches(equality("badge", "abc-1")).await);
+        assert!(matches(substring("badge", "bC")).await);
+        assert!(!matches(substring("badge", "bc")).await);
+    }
+
+    #[tokio::test]
+    async fn test_posix_attributes() {
+        let fixture = TestFixture::new().await;
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("jim"),
+                email: "jim@jim.jim".to_string(),
+                uid_number: Some(1000),
+                gid_number: Some(100),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let get_user_ids = |column, value: &str| {
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::Equality(column, value.to_string())),
+            )
+        };
+        assert_eq!(
+            get_user_ids(UserColumn::UidNumber, "1000").await,
+            vec!["jim"]
+        );
+        assert_eq!(
+            get_user_ids(UserColumn::GidNumber, "100").await,
+            vec!["jim"]
+        );
+        assert!(get_user_ids(UserColumn::UidNumber, "abc").await.is_empty());
+        // The UID numbers are unique.
+        fixture
+            .handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("james"),
+                email: "james@jim.jim".to_string(),
+                uid_number: Some(1000),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_create_user_attribute_defaults() {
+        let mut config = get_default_config();
+        config.user_attribute_defaults = [
+            ("preferredLanguage".to_string(), "fr".to_string()),
+            ("givenName".to_string(), "Anonymous".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let handler = SqlBackendHandler::new(config, get_initialized_db().await);
+        handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "bob@bob.bob".to_string(),
+                first_name: Some("Bob".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert_eq!(user.first_name.as_deref(), Some("Bob"));
+        assert_eq!(user.preferred_language.as_deref(), Some("fr"));
+        assert_eq!(user.last_name, None);
+
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                preferred_language: Some(String::new()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let user = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert_eq!(user.preferred_language, None);
+    }
+
+    #[tokio::test]
+    async fn test_add_user_to_group_already_member() {
+        let fixture = TestFixture::new().await;
+
+        assert!(matches!(
+            fixture
+                .handler
+                .add_user_to_group(&UserId::new("bob"), fixture.groups[0])
+                .await,
+            Err(DomainError::EntityAlreadyExists(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_users_batch() {
+        let fixture = TestFixture::new().await;
+        let request = |name: &str| CreateUserRequest {
+            user_id: UserId::new(name),
+            email: format!("{}@example.com", name),
+            ..Default::default()
+        };
+        let requests = || vec![request("alice"), request("bob"), request("carol")];
+        // "bob" already exists: nothing is created.
+        let results = fixture
+            .handler
+            .create_users(requests(), false)
+            .await
+            .unwrap();
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+
+        let results = fixture
+            .handler
+            .create_users(requests(), true)
+            .await
+            .unwrap();
+        assert!(results[1].is_err());
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["alice", "bob", "carol", "john", "nogroup", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_users_batch() {
+        let fixture = TestFixture::new().await;
+        let user_ids = [UserId::new("bob"), UserId::new("unknown")];
+        let results = fixture
+            .handler
+            .delete_users(&user_ids, false)
+            .await
+            .unwrap();
+        assert!(matches!(results[1], Err(DomainError::EntityNotFound(_))));
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["bob", "john", "nogroup", "patrick"]
+        );
+
+        fixture.handler.delete_users(&user_ids, true).await.unwrap();
+        assert_eq!(
+            get_user_names(&fixture.handler, None).await,
+            vec!["john", "nogroup", "patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_users_to_group_batch() {
+        let fixture = TestFixture::new().await;
+        let empty_group = fixture.groups[2];
+        let user_ids = [UserId::new("bob"), UserId::new("unknown")];
+        let results = fixture
+            .handler
+            .add_users_to_group(&user_ids, empty_group, false)
+            .await
+            .unwrap();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(DomainError::EntityNotFound(_))));
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(empty_group))
+            )
+            .await,
+            Vec::<String>::new()
+        );
+
+        fixture
+            .handler
+            .add_users_to_group(&user_ids, empty_group, true)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(empty_group))
+            )
+            .await,
+            vec!["bob"]
+        );
+        assert!(matches!(
+            fixture
+                .handler
+                .add_users_to_group(&user_ids, GroupId(1000), true)
+                .await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_change_notifications() {
+        let fixture = TestFixture::new().await;
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(16);
+        let handler = fixture.handler.clone().with_change_notifications(sender);
+        let jim = UserId::new("jim");
+        insert_user_no_password(&handler, "jim").await;
+        handler
+            .update_user(UpdateUserRequest {
+                user_id: jim.clone(),
+                display_name: Some("Jim".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        handler
+            .add_user_to_group(&jim, fixture.groups[2])
+            .await
+            .unwrap();
+        handler
+            .remove_user_from_group(&jim, fixture.groups[2])
+            .await
+            .unwrap();
+        handler.delete_user(&jim).await.unwrap();
+        // The failed changes aren't sent.
+        handler.delete_user(&jim).await.unwrap_err();
+        // Nor the ones of a batch that was rolled back.
+        handler
+            .delete_users(&[UserId::new("bob"), jim.clone()], false)
+            .await
+            .unwrap();
+        let mut changes = Vec::new();
+        while let Ok(change) = receiver.try_recv() {
+            changes.push(change);
+        }
+        assert_eq!(
+            changes,
+            vec![
+                DirectoryChange::UserCreated(jim.clone()),
+                DirectoryChange::UserUpdated(jim.clone()),
+                DirectoryChange::MembershipChanged {
+                    user_id: jim.clone(),
+                    group_id: fixture.groups[2],
+                    added: true,
+                },
+                DirectoryChange::MembershipChanged {
+                    user_id: jim.clone(),
+                    group_id: fixture.groups[2],
+                    added: false,
+                },
+                DirectoryChange::UserDeleted(jim),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_merge_users() {
+        let fixture = TestFixture::new().await;
+        let handler = &fixture.handler;
+        assert_eq!(
+            handler
+                .merge_users(MergeUsersRequest {
+                    source: UserId::new("patrick"),
+                    target: UserId::new("John"),
+                    overwrite_attributes: false,
+                    delete_source: false,
+                })
+                .await
+                .unwrap(),
+            UserMergeReport {
+                added_groups: vec![fixture.groups[0]],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            get_user_names(
+                handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0]))
+            )
+            .await,
+            vec!["bob", "john", "patrick"]
+        );
+        // The target keeps its own attributes.
+        let john = handler
+            .get_user_details(&UserId::new("john"))
+            .await
+            .unwrap();
+        assert_eq!(john.display_name.as_deref(), Some("display John"));
+
+        handler
+            .create_user(CreateUserRequest {
+                user_id: UserId::new("newbob"),
+                email: "new@bob.bob".to_string(),
+                display_name: Some("New Bob".to_string()),
+                preferred_language: Some("fr".to_string()),
+                email_aliases: vec!["other@bob.bob".to_string()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            handler
+                .merge_users(MergeUsersRequest {
+                    source: UserId::new("newbob"),
+                    target: UserId::new("bob"),
+                    overwrite_attributes: true,
+                    delete_source: true,
+                })
+                .await
+                .unwrap(),
+            UserMergeReport {
+                added_groups: vec![],
+                merged_attributes: vec!["cn".to_string(), "preferredLanguage".to_string()],
+                moved_emails: vec!["new@bob.bob".to_string(), "other@bob.bob".to_string()],
+            }
+        );
+        let bob = handler.get_user_details(&UserId::new("bob")).await.unwrap();
+        assert_eq!(bob.display_name.as_deref(), Some("New Bob"));
+        assert_eq!(bob.first_name.as_deref(), Some("first bob"));
+        assert_eq!(bob.preferred_language.as_deref(), Some("fr"));
+        assert_eq!(bob.email_aliases, vec!["new@bob.bob", "other@bob.bob"]);
+        handler
+            .get_user_details(&UserId::new("newbob"))
+            .await
+            .unwrap_err();
+
+        for (source, target) in [("bob", "bob"), ("ghost", "bob"), ("bob", "ghost")] {
+            handler
+                .merge_users(MergeUsersRequest {
+                    source: UserId::new(source),
+                    target: UserId::new(target),
+                    overwrite_attributes: false,
+                    delete_source: true,
+                })
+                .await
+                .unwrap_err();
+        }
+        // Nothing changed.
+        assert!(handler.get_user_details(&UserId::new("bob")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_remove_user_from_group() {
+        let fixture = TestFixture::new().await;
+
+        fixture
+            .handler
+            .remove_user_from_group(&UserId::new("bob"), fixture.groups[0])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+            )
+            .await,
+            vec!["patrick"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_group_members() {
+        let fixture = TestFixture::new().await;
+        let get_members = || {
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(fixture.groups[0])),
+            )
+        };
+
+        fixture
+            .handler
+            .set_group_members(
+                fixture.groups[0],
+                &[UserId::new("patrick"), UserId::new("John")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(get_members().await, vec!["john", "patrick"]);
+
+        // One unknown user rolls back all the changes.
+        assert!(matches!(
+            fixture
+                .handler
+                .set_group_members(
+                    fixture.groups[0],
+                    &[UserId::new("bob"), UserId::new("unknown")],
+                )
+                .await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+        assert_eq!(get_members().await, vec!["john", "patrick"]);
+    }
+
+    #[tokio::test]
+    async fn test_create_group_with_members() {
+        let fixture = TestFixture::new().await;
+        let group_id = fixture
+            .handler
+            .create_group_with_members("Family", &[UserId::new("bob"), UserId::new("patrick")])
+            .await
+            .unwrap();
+        assert_eq!(
+            get_user_names(
+                &fixture.handler,
+                Some(UserRequestFilter::MemberOfId(group_id))
+            )
+            .await,
+            vec!["bob", "patrick"]
+        );
+
+        // One unknown user and the group isn't created.
+        assert!(matches!(
+            fixture
+                .handler
+                .create_group_with_members("Friends", &[UserId::new("bob"), UserId::new("unknown")])
+                .await,
+            Err(DomainError::EntityNotFound(_))
+        ));
+        assert!(fixture
+            .handler
+            .list_groups(Some(GroupRequestFilter::DisplayName("Friends".to_owned())))
+            .await
+            .unwrap()
+            .is_empty());
     }
 }
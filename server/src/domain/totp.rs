@@ -0,0 +1,201 @@
+//! Time-based one-time passwords (RFC 6238), the optional second factor of the web UI logins.
+//!
+//! The codes are the ones of the usual authenticator apps: 6 digits, HMAC-SHA1 and 30 second
+//! steps. The codes of the previous and next steps are accepted too, to tolerate the clock drift
+//! of the phone, but each step can only be used once. LDAP binds don't use the second factor.
+
+use hmac::{Hmac, Mac, NewMac};
+use rand::Rng;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+const STEP_SECONDS: i64 = 30;
+const CODE_DIGITS: usize = 6;
+/// How many steps before and after the current one are accepted.
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+/// 160 bits, the size recommended by RFC 4226 for HMAC-SHA1.
+const SECRET_LENGTH: usize = 20;
+/// The name shown by the authenticator apps, next to the user ID.
+const ISSUER: &str = "LLDAP";
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Without the characters that are easy to mistake for one another, like `1` and `l`.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// A new random secret, encoded in base32 like in the provisioning URI.
+pub fn generate_secret() -> String {
+    let secret: [u8; SECRET_LENGTH] = rand::thread_rng().gen();
+    data_encoding::BASE32_NOPAD.encode(&secret)
+}
+
+fn get_code(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_varkey(secret).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // The dynamic truncation of RFC 4226.
+    let offset = usize::from(hash[hash.len() - 1] & 0xf);
+    let value = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+    value % 10u32.pow(CODE_DIGITS as u32)
+}
+
+/// Returns the step of the code if it is valid at the given Unix time, and more recent than the
+/// last step used by the user.
+pub fn verify_code(
+    secret: &str,
+    code: &str,
+    timestamp: i64,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let secret = data_encoding::BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != CODE_DIGITS || !code.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    let current_step = timestamp.div_euclid(STEP_SECONDS);
+    (current_step - ALLOWED_DRIFT_STEPS..=current_step + ALLOWED_DRIFT_STEPS)
+        .filter(|step| last_used_step.map_or(true, |last| *step > last))
+        .find(|step| get_code(&secret, *step) == code)
+}
+
+/// The code of the secret at the given Unix time, as entered by the user.
+#[cfg(test)]
+pub fn get_code_at(secret: &str, timestamp: i64) -> String {
+    let secret = data_encoding::BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .unwrap();
+    format!(
+        "{:0width$}",
+        get_code(&secret, timestamp.div_euclid(STEP_SECONDS)),
+        width = CODE_DIGITS
+    )
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The `otpauth://` URI of the secret, that the authenticator apps import from the QR code.
+pub fn get_provisioning_uri(user_id: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{user}?secret={secret}&issuer={issuer}&algorithm=SHA1\
+         &digits={}&period={}",
+        CODE_DIGITS,
+        STEP_SECONDS,
+        issuer = ISSUER,
+        user = percent_encode(user_id),
+        secret = secret,
+    )
+}
+
+/// The QR code of the provisioning URI, as a `data:` URI of an SVG image.
+pub fn get_qr_code(uri: &str) -> Result<String, String> {
+    let svg = qrcode::QrCode::new(uri.as_bytes())
+        .map_err(|e| format!("Could not generate the QR code: {}", e))?
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Ok(format!("data:image/svg+xml;base64,{}", base64::encode(svg)))
+}
+
+/// The single-use codes that replace the TOTP code when the authenticator is lost. They are only
+/// shown once, and only their hashes are stored.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code = (0..10)
+                .map(|_| {
+                    char::from(
+                        RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())],
+                    )
+                })
+                .collect::<String>();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// The recovery codes are compared case-insensitively, with or without the dash.
+pub fn hash_recovery_code(code: &str) -> String {
+    let code = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The secret of the test vectors of RFC 6238.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_get_code() {
+        // The last 6 digits of the 8-digit test vectors.
+        assert_eq!(get_code(RFC_SECRET, 59 / 30), 287082);
+        assert_eq!(get_code(RFC_SECRET, 1111111109 / 30), 81804);
+        assert_eq!(get_code(RFC_SECRET, 1234567890 / 30), 5924);
+        assert_eq!(get_code(RFC_SECRET, 2000000000 / 30), 279037);
+    }
+
+    #[test]
+    fn test_verify_code() {
+        let secret = data_encoding::BASE32_NOPAD.encode(RFC_SECRET);
+        let step = 1234567890 / 30;
+        assert_eq!(verify_code(&secret, "005924", 1234567890, None), Some(step));
+        // The previous and next steps are accepted.
+        assert_eq!(
+            verify_code(&secret, "005924", 1234567890 + 30, None),
+            Some(step)
+        );
+        assert_eq!(
+            verify_code(&secret, "005924", 1234567890 - 30, None),
+            Some(step)
+        );
+        assert_eq!(verify_code(&secret, "005924", 1234567890 + 60, None), None);
+        // Replays are rejected.
+        assert_eq!(verify_code(&secret, "005924", 1234567890, Some(step)), None);
+        assert_eq!(verify_code(&secret, "5924", 1234567890, None), None);
+        assert_eq!(verify_code(&secret, "+05924", 1234567890, None), None);
+        assert_eq!(verify_code("not base32!", "005924", 1234567890, None), None);
+    }
+
+    #[test]
+    fn test_get_provisioning_uri() {
+        assert_eq!(
+            get_provisioning_uri("bob smith", "ABCDEF"),
+            "otpauth://totp/LLDAP:bob%20smith?secret=ABCDEF&issuer=LLDAP&algorithm=SHA1\
+             &digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes
+            .iter()
+            .all(|c| c.len() == 11 && c.as_bytes()[5] == b'-'));
+        assert_eq!(
+            hash_recovery_code(&codes[0]),
+            hash_recovery_code(&codes[0].replace('-', "").to_ascii_uppercase())
+        );
+        assert_ne!(hash_recovery_code(&codes[0]), hash_recovery_code(&codes[1]));
+    }
+}
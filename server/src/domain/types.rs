@@ -52,6 +52,12 @@ impl TryGetable for Uuid {
     }
 }
 
+impl TryFromU64 for Uuid {
+    fn try_from_u64(_n: u64) -> Result<Self, DbErr> {
+        Err(DbErr::ConvertFromU64("Uuid cannot be constructed from u64"))
+    }
+}
+
 impl ValueType for Uuid {
     fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
         <Self as std::convert::TryFrom<_>>::try_from(
@@ -93,7 +99,7 @@ macro_rules! uuid {
     };
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(from = "String")]
 pub struct UserId(String);
 
@@ -167,6 +173,13 @@ impl ValueType for UserId {
     }
 }
 
+/// For the optional references to users, like the actor of an audit log entry.
+impl Nullable for UserId {
+    fn null() -> Value {
+        Value::String(None)
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub struct JpegPhoto(#[serde(with = "serde_bytes")] Vec<u8>);
 
@@ -300,7 +313,115 @@ impl IntoActiveValue<JpegPhoto> for JpegPhoto {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize, FromQueryResult)]
+/// Checks that the key looks like an OpenSSH public key: the key type and the base64-encoded key,
+/// optionally followed by a comment.
+pub fn check_ssh_public_key(key: &str) -> anyhow::Result<()> {
+    let mut parts = key.split_whitespace();
+    let key_type = parts.next().unwrap_or_default();
+    if !["ssh-", "ecdsa-", "sk-"]
+        .iter()
+        .any(|prefix| key_type.starts_with(prefix))
+    {
+        anyhow::bail!("Unknown SSH key type `{}`", key_type);
+    }
+    base64::decode(parts.next().unwrap_or_default())
+        .ok()
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid SSH key data"))?;
+    Ok(())
+}
+
+/// The type of the values of a custom user attribute. The values are stored and exchanged as
+/// strings, in the format returned by [`AttributeType::normalize_value`].
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum AttributeType {
+    String,
+    Integer,
+    /// A calendar date, as `YYYY-MM-DD`.
+    Date,
+    Boolean,
+    /// Arbitrary bytes, in base64.
+    Binary,
+}
+
+impl AttributeType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttributeType::String => "string",
+            AttributeType::Integer => "integer",
+            AttributeType::Date => "date",
+            AttributeType::Boolean => "boolean",
+            AttributeType::Binary => "binary",
+        }
+    }
+
+    /// Checks the value and returns it in its canonical form, e.g. `42` for ` 042`.
+    pub fn normalize_value(&self, value: &str) -> anyhow::Result<String> {
+        let trimmed = value.trim();
+        Ok(match self {
+            AttributeType::String => value.to_owned(),
+            AttributeType::Integer => trimmed
+                .parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("Invalid integer `{}`", value))?
+                .to_string(),
+            AttributeType::Date => chrono::NaiveDate::parse_from_str(trimmed, "%Y-%m-%d")
+                .map_err(|_| anyhow::anyhow!("Invalid date `{}`, expected YYYY-MM-DD", value))?
+                .format("%Y-%m-%d")
+                .to_string(),
+            AttributeType::Boolean => match trimmed.to_ascii_lowercase().as_str() {
+                "true" => "true".to_owned(),
+                "false" => "false".to_owned(),
+                _ => anyhow::bail!("Invalid boolean `{}`", value),
+            },
+            AttributeType::Binary => base64::encode(
+                base64::decode(trimmed).map_err(|_| anyhow::anyhow!("Invalid base64 value"))?,
+            ),
+        })
+    }
+}
+
+impl std::str::FromStr for AttributeType {
+    type Err = String;
+
+    fn from_str(attribute_type: &str) -> Result<Self, Self::Err> {
+        match attribute_type {
+            "string" => Ok(AttributeType::String),
+            "integer" => Ok(AttributeType::Integer),
+            "date" => Ok(AttributeType::Date),
+            "boolean" => Ok(AttributeType::Boolean),
+            "binary" => Ok(AttributeType::Binary),
+            _ => Err(format!("Unknown attribute type: '{}'", attribute_type)),
+        }
+    }
+}
+
+/// A user or group attribute defined by the admins, on top of the built-in ones.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeSchema {
+    /// The name in the GraphQL API.
+    pub name: String,
+    pub attribute_type: AttributeType,
+    /// Whether the attribute can have several values.
+    pub is_list: bool,
+    /// Whether the users can change their own value, or for the group attributes the managers
+    /// of the group, not only the admins.
+    pub is_editable: bool,
+    /// The name of the attribute in the LDAP entries.
+    pub ldap_name: String,
+    /// Whether the equality and substring filters compare the values as-is. Otherwise they
+    /// ignore the case, like for the names and emails.
+    pub is_case_sensitive: bool,
+}
+
+/// The values of a custom attribute for a user or a group. A single-valued attribute has one
+/// value.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct AttributeValue {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub user_id: UserId,
     pub email: String,
@@ -309,7 +430,33 @@ pub struct User {
     pub last_name: Option<String>,
     pub avatar: Option<JpegPhoto>,
     pub creation_date: DateTime,
+    /// The last change to the attributes of the user.
+    pub modified_date: DateTime,
+    /// The last change to the password, `None` if the user has no password or if it was set by a
+    /// version that didn't record it.
+    pub password_modified_date: Option<DateTime>,
+    /// When the account was locked after too many wrong passwords, `None` if it isn't.
+    pub locked_date: Option<DateTime>,
+    /// The account can't be used before that date, e.g. for a contractor who starts later.
+    pub activation_date: Option<DateTime>,
+    /// The account can't be used from that date on.
+    pub expiration_date: Option<DateTime>,
+    /// Whether to email the user when they log in to the web UI from a new device.
+    pub login_notifications: bool,
+    /// A disabled account can't log in, but is kept with its memberships, unlike a deleted one.
+    pub enabled: bool,
     pub uuid: Uuid,
+    pub preferred_language: Option<String>,
+    pub uid_number: Option<i32>,
+    pub gid_number: Option<i32>,
+    pub home_directory: Option<String>,
+    pub login_shell: Option<String>,
+    /// Additional email addresses, on top of the primary `email`.
+    pub email_aliases: Vec<String>,
+    /// The public SSH keys of the user, for ldapPublicKey.
+    pub ssh_public_keys: Vec<String>,
+    /// The values of the custom attributes the user has, ordered by name.
+    pub attributes: Vec<AttributeValue>,
 }
 
 #[cfg(test)]
@@ -325,7 +472,272 @@ impl Default for User {
             last_name: None,
             avatar: None,
             creation_date: epoch,
+            modified_date: epoch,
+            password_modified_date: None,
+            locked_date: None,
+            activation_date: None,
+            expiration_date: None,
+            login_notifications: true,
+            enabled: true,
             uuid: Uuid::from_name_and_date("", &epoch),
+            preferred_language: None,
+            uid_number: None,
+            gid_number: None,
+            home_directory: None,
+            login_shell: None,
+            email_aliases: Vec::new(),
+            ssh_public_keys: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+}
+
+impl User {
+    /// Whether the account can be used at that date: it is enabled, and between its activation
+    /// and expiration dates.
+    pub fn is_active_at(&self, now: DateTime) -> bool {
+        self.enabled
+            && self.activation_date.map_or(true, |date| date <= now)
+            && self.expiration_date.map_or(true, |date| now < date)
+    }
+}
+
+/// How long the passwords are valid before they have to be changed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct PasswordExpiry {
+    pub max_age_days: u32,
+    /// How many days before the expiration the users are warned.
+    pub warning_days: u32,
+    /// The logins allowed once the password expired, the LDAP binds included. Without it, only
+    /// the web UI refuses the expired passwords.
+    pub grace_logins: Option<u32>,
+}
+
+impl PasswordExpiry {
+    /// `None` if the user has no password, or if its change wasn't recorded.
+    pub fn expiration_date(&self, user: &User) -> Option<DateTime> {
+        user.password_modified_date
+            .map(|date| date + chrono::Duration::days(i64::from(self.max_age_days)))
+    }
+
+    pub fn is_expired(&self, user: &User, now: DateTime) -> bool {
+        self.expiration_date(user).map_or(false, |date| date <= now)
+    }
+}
+
+/// A security key or passkey of a user, to log in to the web UI.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct WebauthnCredential {
+    /// The ID chosen by the authenticator, in URL-safe base64.
+    pub credential_id: String,
+    pub user_id: UserId,
+    /// Chosen by the user, to tell their credentials apart.
+    pub name: String,
+    /// The public key and the signature counter, serialized by the web server.
+    pub credential: String,
+    pub creation_date: DateTime,
+}
+
+/// What an API token can do. All the tokens can read everything, like a read-only admin.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum ApiTokenScope {
+    ReadOnly,
+    /// Create, modify and delete users.
+    UserManagement,
+    /// Create, modify and delete groups, and change their members.
+    GroupManagement,
+}
+
+impl ApiTokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiTokenScope::ReadOnly => "read_only",
+            ApiTokenScope::UserManagement => "user_management",
+            ApiTokenScope::GroupManagement => "group_management",
+        }
+    }
+}
+
+impl std::str::FromStr for ApiTokenScope {
+    type Err = String;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "read_only" => Ok(ApiTokenScope::ReadOnly),
+            "user_management" => Ok(ApiTokenScope::UserManagement),
+            "group_management" => Ok(ApiTokenScope::GroupManagement),
+            _ => Err(format!("Unknown API token scope: '{}'", scope)),
+        }
+    }
+}
+
+/// The secret of the API tokens starts with this, to tell them apart from the JWTs.
+pub const API_TOKEN_PREFIX: &str = "lldap_token_";
+
+/// A named token for scripts to call the GraphQL API, created by an admin. Only a hash of the
+/// secret is stored.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub token_id: String,
+    /// The admin who created the token.
+    pub user_id: UserId,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub creation_date: DateTime,
+}
+
+/// Where a session was opened from, as reported by the browser.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct SessionMetadata {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// A login to the web UI, which lasts as long as its refresh token.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Session {
+    /// The hash of the refresh token.
+    pub session_id: i64,
+    pub user_id: UserId,
+    /// `None` for the sessions created before it was recorded, like the metadata.
+    pub creation_date: Option<DateTime>,
+    pub expiry_date: DateTime,
+    pub metadata: SessionMetadata,
+}
+
+/// A mutation or an authentication event, as recorded in the audit log.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AuditLogEntry {
+    pub entry_id: i32,
+    pub date: DateTime,
+    /// Who did it, or who tried to log in. The user may not exist (anymore).
+    pub actor: Option<UserId>,
+    /// The GraphQL mutation, e.g. `createUser`, or the authentication event, e.g. `login`.
+    pub action: String,
+    /// What the action was about, e.g. the ID of the user that was created.
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub success: bool,
+    pub details: Option<String>,
+}
+
+/// The changes of the directory that the webhooks can subscribe to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WebhookEvent {
+    UserCreated,
+    UserDeleted,
+    /// A user was added to a group or removed from it.
+    GroupMembershipChanged,
+    PasswordChanged,
+}
+
+impl WebhookEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::UserCreated => "user_created",
+            WebhookEvent::UserDeleted => "user_deleted",
+            WebhookEvent::GroupMembershipChanged => "group_membership_changed",
+            WebhookEvent::PasswordChanged => "password_changed",
+        }
+    }
+}
+
+impl std::str::FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(event: &str) -> Result<Self, Self::Err> {
+        match event {
+            "user_created" => Ok(WebhookEvent::UserCreated),
+            "user_deleted" => Ok(WebhookEvent::UserDeleted),
+            "group_membership_changed" => Ok(WebhookEvent::GroupMembershipChanged),
+            "password_changed" => Ok(WebhookEvent::PasswordChanged),
+            _ => Err(format!("Unknown webhook event: '{}'", event)),
+        }
+    }
+}
+
+/// A URL registered by an admin, to which the changes matching `events` are posted. The payloads
+/// are signed with the secret, which is stored as is since the server needs it.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Webhook {
+    pub webhook_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<WebhookEvent>,
+    pub creation_date: DateTime,
+}
+
+/// A pending request to add a user to a group that requires an approval, see
+/// `membership_approval_groups` in the configuration.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct MembershipRequest {
+    pub request_id: i32,
+    pub group_id: GroupId,
+    pub user_id: UserId,
+    /// The user themselves, or a manager of the group.
+    pub requested_by: UserId,
+    pub creation_date: DateTime,
+    /// Past this date, the request can't be approved anymore.
+    pub expiry_date: DateTime,
+}
+
+/// A change of the directory, streamed to the GraphQL subscriptions and sent to the webhooks
+/// subscribed to its event.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum DirectoryChange {
+    UserCreated(UserId),
+    UserUpdated(UserId),
+    UserDeleted(UserId),
+    MembershipChanged {
+        user_id: UserId,
+        group_id: GroupId,
+        /// Whether the user joined the group, or left it.
+        added: bool,
+    },
+    PasswordChanged(UserId),
+    GroupCreated(GroupId),
+    GroupUpdated(GroupId),
+    GroupDeleted(GroupId),
+}
+
+impl DirectoryChange {
+    /// The webhook event of the change, if the webhooks can subscribe to it.
+    pub fn event(&self) -> Option<WebhookEvent> {
+        match self {
+            DirectoryChange::UserCreated(_) => Some(WebhookEvent::UserCreated),
+            DirectoryChange::UserDeleted(_) => Some(WebhookEvent::UserDeleted),
+            DirectoryChange::MembershipChanged { .. } => Some(WebhookEvent::GroupMembershipChanged),
+            DirectoryChange::PasswordChanged(_) => Some(WebhookEvent::PasswordChanged),
+            DirectoryChange::UserUpdated(_)
+            | DirectoryChange::GroupCreated(_)
+            | DirectoryChange::GroupUpdated(_)
+            | DirectoryChange::GroupDeleted(_) => None,
+        }
+    }
+
+    pub fn user_id(&self) -> Option<&UserId> {
+        match self {
+            DirectoryChange::UserCreated(user_id)
+            | DirectoryChange::UserUpdated(user_id)
+            | DirectoryChange::UserDeleted(user_id)
+            | DirectoryChange::MembershipChanged { user_id, .. }
+            | DirectoryChange::PasswordChanged(user_id) => Some(user_id),
+            DirectoryChange::GroupCreated(_)
+            | DirectoryChange::GroupUpdated(_)
+            | DirectoryChange::GroupDeleted(_) => None,
+        }
+    }
+
+    pub fn group_id(&self) -> Option<GroupId> {
+        match self {
+            DirectoryChange::MembershipChanged { group_id, .. }
+            | DirectoryChange::GroupCreated(group_id)
+            | DirectoryChange::GroupUpdated(group_id)
+            | DirectoryChange::GroupDeleted(group_id) => Some(*group_id),
+            DirectoryChange::UserCreated(_)
+            | DirectoryChange::UserUpdated(_)
+            | DirectoryChange::UserDeleted(_)
+            | DirectoryChange::PasswordChanged(_) => None,
         }
     }
 }
@@ -374,8 +786,20 @@ pub struct Group {
     pub id: GroupId,
     pub display_name: String,
     pub creation_date: DateTime,
+    /// The last change to the attributes or the members of the group.
+    pub modified_date: DateTime,
     pub uuid: Uuid,
+    /// Archived groups are hidden by default and don't grant any permission.
+    pub archived: bool,
+    /// For dynamic groups, the LDAP filter defining the members, instead of explicit memberships.
+    pub dynamic_filter: Option<String>,
+    /// The POSIX group ID, for posixGroup.
+    pub gid_number: Option<i32>,
+    /// The address of the group as a mailing list, unique among the groups and users.
+    pub email: Option<String>,
     pub users: Vec<UserId>,
+    /// The values of the custom group attributes, ordered by name.
+    pub attributes: Vec<AttributeValue>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromQueryResult)]
@@ -384,6 +808,10 @@ pub struct GroupDetails {
     pub display_name: String,
     pub creation_date: DateTime,
     pub uuid: Uuid,
+    pub archived: bool,
+    pub dynamic_filter: Option<String>,
+    pub gid_number: Option<i32>,
+    pub email: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
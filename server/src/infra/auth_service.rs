@@ -1,12 +1,13 @@
 use std::collections::{hash_map::DefaultHasher, HashSet};
 use std::hash::{Hash, Hasher};
 use std::pin::Pin;
+use std::sync::RwLock;
 use std::task::{Context, Poll};
 
 use actix_web::{
     cookie::{Cookie, SameSite},
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    error::{ErrorBadRequest, ErrorUnauthorized},
+    error::{ErrorBadRequest, ErrorInternalServerError, ErrorUnauthorized},
     web, HttpRequest, HttpResponse,
 };
 use actix_web_httpauth::extractors::bearer::BearerAuth;
@@ -14,55 +15,118 @@ use anyhow::Result;
 use chrono::prelude::*;
 use futures::future::{ok, Ready};
 use futures_util::FutureExt;
-use hmac::Hmac;
-use jwt::{SignWithKey, VerifyWithKey};
-use sha2::Sha512;
 use time::ext::NumericalDuration;
-use tracing::{debug, instrument, warn};
+use tracing::{debug, info, instrument, warn};
 
-use lldap_auth::{login, password_reset, registration, JWTClaims};
+use lldap_auth::{login, password_reset, proof_of_work, registration, webauthn, JWTClaims};
 
 use crate::{
     domain::{
         error::DomainError,
-        handler::{BackendHandler, BindRequest, LoginHandler, UserRequestFilter},
+        handler::{AuditEvent, BackendHandler, BindRequest, LoginHandler, UserRequestFilter},
         opaque_handler::OpaqueHandler,
-        types::{GroupDetails, UserColumn, UserId},
+        types::{
+            ApiTokenScope, GroupDetails, SessionMetadata, UserColumn, UserId, API_TOKEN_PREFIX,
+        },
     },
     infra::{
+        jwt_keys::JwtKeys,
         tcp_backend_handler::*,
         tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+        webauthn::WebauthnState,
     },
 };
 
-type Token<S> = jwt::Token<jwt::Header, JWTClaims, S>;
-type SignedToken = Token<jwt::token::Signed>;
-
-fn create_jwt(key: &Hmac<Sha512>, user: String, groups: HashSet<GroupDetails>) -> SignedToken {
+/// The signed JWT, and its expiry date.
+fn create_jwt(
+    keys: &JwtKeys,
+    user: String,
+    groups: HashSet<GroupDetails>,
+) -> (String, DateTime<Utc>) {
     let claims = JWTClaims {
         exp: Utc::now() + chrono::Duration::days(1),
         iat: Utc::now(),
         user,
-        groups: groups.into_iter().map(|g| g.display_name).collect(),
+        // Archived groups don't grant any permission.
+        groups: groups
+            .into_iter()
+            .filter(|g| !g.archived)
+            .map(|g| g.display_name)
+            .collect(),
     };
-    let header = jwt::Header {
-        algorithm: jwt::AlgorithmType::Hs512,
-        ..Default::default()
-    };
-    jwt::Token::new(header, claims).sign_with_key(key).unwrap()
+    (keys.sign(&claims), claims.exp)
+}
+
+/// How the JWTs are stored in the blacklist, and the refresh tokens in the database.
+fn hash_token(token: &str) -> u64 {
+    let mut s = DefaultHasher::new();
+    token.hash(&mut s);
+    s.finish()
 }
 
 fn parse_refresh_token(token: &str) -> TcpResult<(u64, UserId)> {
     match token.split_once('+') {
         None => Err(DomainError::AuthenticationError("Invalid refresh token".to_string()).into()),
-        Some((token, u)) => {
-            let refresh_token_hash = {
-                let mut s = DefaultHasher::new();
-                token.hash(&mut s);
-                s.finish()
-            };
-            Ok((refresh_token_hash, UserId::new(u)))
-        }
+        Some((token, u)) => Ok((hash_token(token), UserId::new(u))),
+    }
+}
+
+/// Creates a JWT for the session of the refresh token, so that it is blacklisted when the session
+/// is ended.
+async fn create_session_jwt<Backend>(
+    data: &AppState<Backend>,
+    user: &UserId,
+    refresh_token_hash: u64,
+) -> TcpResult<String>
+where
+    Backend: TcpBackendHandler + BackendHandler,
+{
+    let groups = data.backend_handler.get_user_groups(user).await?;
+    let (token, expiry_date) = create_jwt(&data.jwt_keys, user.to_string(), groups);
+    data.backend_handler
+        .register_jwt(
+            user,
+            hash_token(token.as_str()),
+            refresh_token_hash,
+            expiry_date,
+        )
+        .await?;
+    Ok(token)
+}
+
+fn get_session_metadata(request: &HttpRequest) -> SessionMetadata {
+    SessionMetadata {
+        ip_address: request
+            .connection_info()
+            .realip_remote_addr()
+            .map(str::to_owned),
+        user_agent: request
+            .headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_owned),
+    }
+}
+
+/// Records a login or a logout in the audit log, with the error of the failed attempts. A failure
+/// to record it is only logged.
+async fn record_auth_event<Backend: BackendHandler>(
+    data: &web::Data<AppState<Backend>>,
+    http_request: &HttpRequest,
+    action: &str,
+    user_id: Option<&UserId>,
+    error: Option<String>,
+) {
+    let event = AuditEvent {
+        actor: user_id.cloned(),
+        action: action.to_owned(),
+        target: None,
+        ip_address: get_session_metadata(http_request).ip_address,
+        success: error.is_none(),
+        details: error,
+    };
+    if let Err(e) = data.backend_handler.record_audit_event(event).await {
+        warn!("Could not record the {} in the audit log: {}", action, e);
     }
 }
 
@@ -87,8 +151,6 @@ async fn get_refresh<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
-    let backend_handler = &data.backend_handler;
-    let jwt_key = &data.jwt_key;
     let (refresh_token_hash, user) = get_refresh_token(request)?;
     let found = data
         .backend_handler
@@ -99,25 +161,28 @@ where
             "Invalid refresh token".to_string(),
         )));
     }
-    Ok(backend_handler
-        .get_user_groups(&user)
-        .await
-        .map(|groups| create_jwt(jwt_key, user.to_string(), groups))
-        .map(|token| {
-            HttpResponse::Ok()
-                .cookie(
-                    Cookie::build("token", token.as_str())
-                        .max_age(1.days())
-                        .path("/")
-                        .http_only(true)
-                        .same_site(SameSite::Strict)
-                        .finish(),
-                )
-                .json(&login::ServerLoginResponse {
-                    token: token.as_str().to_owned(),
-                    refresh_token: None,
-                })
-        })?)
+    check_account_is_active(&data, &user).await?;
+    if is_password_expired(&data, &user).await? {
+        return Err(TcpError::UnauthorizedError(
+            "The password expired, log in again to change it".to_string(),
+        ));
+    }
+    let token = create_session_jwt(&data, &user, refresh_token_hash).await?;
+    let language = get_user_language(&data, &user).await?;
+    Ok(HttpResponse::Ok()
+        .cookie(
+            Cookie::build("token", token.as_str())
+                .max_age(1.days())
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Strict)
+                .finish(),
+        )
+        .json(&login::ServerLoginResponse {
+            token: token.as_str().to_owned(),
+            refresh_token: None,
+            language: Some(language),
+        }))
 }
 
 async fn get_refresh_handler<Backend>(
@@ -175,6 +240,9 @@ where
             .as_deref()
             .unwrap_or_else(|| user.user_id.as_str()),
         &user.email,
+        user.preferred_language
+            .as_deref()
+            .unwrap_or(&data.default_language),
         &token,
         &data.server_url,
         &data.mail_options,
@@ -224,7 +292,7 @@ where
         .delete_password_reset_token(token)
         .await;
     let groups = HashSet::new();
-    let token = create_jwt(&data.jwt_key, user_id.to_string(), groups);
+    let (token, _) = create_jwt(&data.jwt_keys, user_id.to_string(), groups);
     Ok(HttpResponse::Ok()
         .cookie(
             Cookie::build("token", token.as_str())
@@ -261,15 +329,22 @@ async fn get_logout<Backend>(
 where
     Backend: TcpBackendHandler + BackendHandler + 'static,
 {
-    let (refresh_token_hash, user) = get_refresh_token(request)?;
-    data.backend_handler
-        .delete_refresh_token(refresh_token_hash)
-        .await?;
-    let new_blacklisted_jwts = data.backend_handler.blacklist_jwts(&user).await?;
-    let mut jwt_blacklist = data.jwt_blacklist.write().unwrap();
-    for jwt in new_blacklisted_jwts {
-        jwt_blacklist.insert(jwt);
-    }
+    let (refresh_token_hash, user) = get_refresh_token(request.clone())?;
+    // Only this session is ended, the user stays logged in on their other devices.
+    let new_blacklisted_jwts = match data
+        .backend_handler
+        .delete_session(&user, refresh_token_hash as i64)
+        .await
+    {
+        // The session was already revoked, the cookies still have to be cleared.
+        Err(DomainError::EntityNotFound(_)) => HashSet::new(),
+        result => result?,
+    };
+    data.jwt_blacklist
+        .write()
+        .unwrap()
+        .extend(new_blacklisted_jwts);
+    record_auth_event(&data, &request, "logout", Some(&user), None).await;
     Ok(HttpResponse::Ok()
         .cookie(
             Cookie::build("token", "")
@@ -308,6 +383,24 @@ pub(crate) fn error_to_api_response<T, E: Into<TcpError>>(error: E) -> ApiResult
 
 pub type ApiResult<M> = actix_web::Either<web::Json<M>, HttpResponse>;
 
+/// A new challenge to solve before sending a password, or `null` if they are disabled.
+async fn get_login_challenge_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    HttpResponse::Ok().json(data.login_challenges.as_ref().map(|c| c.create()))
+}
+
+/// Rejects the password logins without a solved challenge, when `login_challenge` is enabled.
+fn check_login_challenge<Backend>(
+    data: &AppState<Backend>,
+    solution: Option<&proof_of_work::Solution>,
+) -> TcpResult<()> {
+    match &data.login_challenges {
+        Some(challenges) if !challenges.check(solution) => Err(TcpError::UnauthorizedError(
+            "Missing or invalid login challenge".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn opaque_login_start<Backend>(
     data: web::Data<AppState<Backend>>,
@@ -316,6 +409,9 @@ async fn opaque_login_start<Backend>(
 where
     Backend: OpaqueHandler + 'static,
 {
+    if let Err(e) = check_login_challenge(&data, request.proof_of_work.as_ref()) {
+        return error_to_api_response(e);
+    }
     data.backend_handler
         .login_start(request.into_inner())
         .await
@@ -323,20 +419,209 @@ where
         .unwrap_or_else(error_to_api_response)
 }
 
+/// Finishes a login once the password was verified: with the tokens, or with a challenge to
+/// complete with the TOTP code if the user enabled it.
 #[instrument(skip_all, level = "debug")]
 async fn get_login_successful_response<Backend>(
     data: &web::Data<AppState<Backend>>,
     name: &UserId,
+    http_request: &HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler,
+{
+    if data.backend_handler.is_totp_enabled(name).await? {
+        debug!("TOTP code required");
+        return Ok(
+            HttpResponse::Ok().json(&login::ServerTotpChallengeResponse {
+                totp_challenge: data.totp_challenges.create(name.clone()),
+            }),
+        );
+    }
+    get_tokens_response(data, name, http_request).await
+}
+
+/// Whether the password of the user is past its maximum age, in which case they have to change it
+/// before getting a session.
+async fn is_password_expired<Backend>(data: &AppState<Backend>, user_id: &UserId) -> TcpResult<bool>
+where
+    Backend: BackendHandler,
+{
+    Ok(match &data.password_expiry {
+        None => false,
+        Some(expiry) => expiry.is_expired(
+            &data.backend_handler.get_user_details(user_id).await?,
+            Utc::now(),
+        ),
+    })
+}
+
+/// Refuses the sessions of the disabled users, and of those outside of the activation and
+/// expiration dates of their account.
+pub(crate) async fn check_account_is_active<Backend>(
+    data: &AppState<Backend>,
+    user_id: &UserId,
+) -> TcpResult<()>
+where
+    Backend: BackendHandler,
+{
+    let user = data.backend_handler.get_user_details(user_id).await?;
+    if !user.is_active_at(Utc::now()) {
+        return Err(TcpError::UnauthorizedError(
+            "The account is disabled, isn't active yet or has expired".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Enables or disables the account. Disabling it also ends all its sessions, whose JWTs are
+/// blacklisted to be rejected right away.
+pub(crate) async fn set_account_enabled<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    jwt_blacklist: &RwLock<HashSet<u64>>,
+    user_id: &UserId,
+    enabled: bool,
+) -> crate::domain::error::Result<()> {
+    backend_handler.set_user_enabled(user_id, enabled).await?;
+    if !enabled {
+        let revoked_jwts = backend_handler.delete_sessions(user_id).await?;
+        jwt_blacklist.write().unwrap().extend(revoked_jwts);
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_tokens_response<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    name: &UserId,
+    http_request: &HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler,
 {
-    // The authentication was successful, we need to fetch the groups to create the JWT
-    // token.
-    let groups = data.backend_handler.get_user_groups(name).await?;
-    let (refresh_token, max_age) = data.backend_handler.create_refresh_token(name).await?;
-    let token = create_jwt(&data.jwt_key, name.to_string(), groups);
+    // The disabled and expired accounts don't get a reset token either.
+    check_account_is_active(data, name).await?;
+    if is_password_expired(data, name).await? {
+        let grace_logins_left = match data.password_expiry.and_then(|expiry| expiry.grace_logins) {
+            Some(max_grace_logins) => {
+                data.backend_handler
+                    .use_grace_login(name, max_grace_logins)
+                    .await?
+            }
+            None => None,
+        };
+        if let Some(grace_logins_left) = grace_logins_left {
+            info!(
+                "The password of {} expired, {} grace logins left",
+                name, grace_logins_left
+            );
+            return open_session(data, name, http_request).await;
+        }
+        info!("The password of {} expired, it has to be changed", name);
+        let password_reset_token = data
+            .backend_handler
+            .start_password_reset(name)
+            .await?
+            .ok_or_else(|| TcpError::UnauthorizedError("User not found".to_string()))?;
+        return Ok(
+            HttpResponse::Ok().json(&login::ServerPasswordExpiredResponse {
+                password_reset_token,
+            }),
+        );
+    }
+    open_session(data, name, http_request).await
+}
+
+/// Emails the user if they log in from a device that has no open session, i.e. with another IP
+/// address or browser, unless they turned the notifications off. The email is sent in the
+/// background: a slow or broken mail server doesn't delay the login.
+async fn notify_new_device<Backend>(
+    data: &AppState<Backend>,
+    user_id: &UserId,
+    metadata: &SessionMetadata,
+) -> Result<()>
+where
+    Backend: BackendHandler,
+{
+    let user = data.backend_handler.get_user_details(user_id).await?;
+    if !user.login_notifications {
+        return Ok(());
+    }
+    let sessions = data.backend_handler.list_sessions(user_id).await?;
+    if sessions.iter().any(|session| &session.metadata == metadata) {
+        return Ok(());
+    }
+    debug!("New device for {}, sending a login notification", user_id);
+    let metadata = metadata.clone();
+    let server_url = data.server_url.clone();
+    let mail_options = data.mail_options.clone();
+    let language = user
+        .preferred_language
+        .clone()
+        .unwrap_or_else(|| data.default_language.clone());
+    tokio::spawn(async move {
+        if let Err(e) = super::mail::send_login_notification_email(
+            user.display_name
+                .as_deref()
+                .unwrap_or_else(|| user.user_id.as_str()),
+            user.user_id.as_str(),
+            &user.email,
+            &language,
+            &metadata,
+            &server_url,
+            &mail_options,
+        )
+        .await
+        {
+            warn!("Error sending the login notification email: {:#}", e);
+        }
+    });
+    Ok(())
+}
+
+/// The preferred language of the user, or the default language if they didn't set one.
+async fn get_user_language<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    user_id: &UserId,
+) -> TcpResult<String>
+where
+    Backend: BackendHandler,
+{
+    Ok(data
+        .backend_handler
+        .get_user_details(user_id)
+        .await?
+        .preferred_language
+        .unwrap_or_else(|| data.default_language.clone()))
+}
+
+/// Creates the session of a user who was authenticated, and sets its cookies.
+pub(crate) async fn open_session<Backend>(
+    data: &web::Data<AppState<Backend>>,
+    name: &UserId,
+    http_request: &HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler,
+{
+    check_account_is_active(data, name).await?;
+    let metadata = get_session_metadata(http_request);
+    if data.mail_options.enable_login_notifications {
+        if let Err(e) = notify_new_device(data, name, &metadata).await {
+            warn!(
+                "Error checking the device of {} for a notification: {:#}",
+                name, e
+            );
+        }
+    }
+    let (refresh_token, max_age) = data
+        .backend_handler
+        .create_refresh_token(name, metadata)
+        .await?;
+    let token = create_session_jwt(data, name, hash_token(&refresh_token)).await?;
+    record_auth_event(data, http_request, "login", Some(name), None).await;
     let refresh_token_plus_name = refresh_token + "+" + name.as_str();
+    let language = get_user_language(data, name).await?;
 
     Ok(HttpResponse::Ok()
         .cookie(
@@ -358,32 +643,263 @@ where
         .json(&login::ServerLoginResponse {
             token: token.as_str().to_owned(),
             refresh_token: Some(refresh_token_plus_name),
+            language: Some(language),
         }))
 }
 
+#[instrument(skip_all, level = "debug")]
+async fn totp_login<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<login::ClientTotpLoginRequest>,
+    http_request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let user_id = data
+        .totp_challenges
+        .start_attempt(&request.totp_challenge)
+        .ok_or_else(|| {
+            TcpError::UnauthorizedError(
+                "Invalid or expired login, start over with the password".to_string(),
+            )
+        })?;
+    if !data
+        .backend_handler
+        .check_totp_code(&user_id, &request.code)
+        .await?
+    {
+        warn!("Invalid TOTP code for {}", user_id);
+        record_auth_event(
+            &data,
+            &http_request,
+            "login",
+            Some(&user_id),
+            Some("Invalid TOTP code".to_owned()),
+        )
+        .await;
+        return Err(TcpError::UnauthorizedError("Invalid TOTP code".to_string()));
+    }
+    data.totp_challenges.remove(&request.totp_challenge);
+    get_tokens_response(&data, &user_id, &http_request).await
+}
+
+async fn totp_login_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<login::ClientTotpLoginRequest>,
+    http_request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    totp_login(data, request, http_request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+fn get_webauthn<Backend>(data: &AppState<Backend>) -> TcpResult<&WebauthnState> {
+    data.webauthn
+        .as_deref()
+        .ok_or_else(|| TcpError::BadRequest("Security key logins are disabled".to_string()))
+}
+
+/// The user of the JWT of the request.
+pub(crate) async fn get_authenticated_user<Backend>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+) -> TcpResult<UserId> {
+    use actix_web::FromRequest;
+    let bearer = BearerAuth::from_request(request, &mut actix_web::dev::Payload::None)
+        .await
+        .map_err(|_| TcpError::UnauthorizedError("Missing bearer token".to_string()))?;
+    Ok(check_if_token_is_valid(data, bearer.token())
+        .map_err(|e| TcpError::UnauthorizedError(e.to_string()))?
+        .user)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_login_start<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<webauthn::ClientLoginStartRequest>,
+) -> TcpResult<webauthn::ServerLoginStartResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let webauthn = get_webauthn(&data)?;
+    let user_id = UserId::new(&request.username);
+    let credentials = data
+        .backend_handler
+        .list_webauthn_credentials(&user_id)
+        .await?;
+    // Same answer whether the user exists or not.
+    if credentials.is_empty() {
+        return Err(TcpError::UnauthorizedError(
+            "No security key registered for this user".to_string(),
+        ));
+    }
+    let (webauthn_challenge, options) = webauthn.start_authentication(user_id, &credentials)?;
+    Ok(webauthn::ServerLoginStartResponse {
+        webauthn_challenge,
+        options,
+    })
+}
+
+async fn webauthn_login_start_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<webauthn::ClientLoginStartRequest>,
+) -> ApiResult<webauthn::ServerLoginStartResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    webauthn_login_start(data, request)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+/// The security keys replace both the password and the TOTP code.
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_login_finish<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<webauthn::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let (user_id, result) = get_webauthn(&data)?
+        .finish_authentication(&request.webauthn_challenge, &request.credential)?;
+    for credential in data
+        .backend_handler
+        .list_webauthn_credentials(&user_id)
+        .await?
+    {
+        if let Some(updated) = WebauthnState::get_updated_credential(&credential, &result)? {
+            data.backend_handler
+                .update_webauthn_credential(&credential.credential_id, updated)
+                .await?;
+        }
+    }
+    info!("{} logged in with a security key", user_id);
+    get_tokens_response(&data, &user_id, &http_request).await
+}
+
+async fn webauthn_login_finish_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<webauthn::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    webauthn_login_finish(data, request, http_request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// Users can only register security keys for themselves.
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_register_start<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<webauthn::CreationChallengeResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let webauthn = get_webauthn(&data)?;
+    let user_id = get_authenticated_user(&data, &request).await?;
+    let user = data.backend_handler.get_user_details(&user_id).await?;
+    let credentials = data
+        .backend_handler
+        .list_webauthn_credentials(&user_id)
+        .await?;
+    webauthn.start_registration(&user, &credentials)
+}
+
+async fn webauthn_register_start_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> ApiResult<webauthn::CreationChallengeResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    webauthn_register_start(data, request)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn webauthn_register_finish<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Json<webauthn::ClientRegistrationFinishRequest>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let webauthn = get_webauthn(&data)?;
+    let user_id = get_authenticated_user(&data, &request).await?;
+    let name = body.name.trim();
+    if name.is_empty() || name.len() > 255 {
+        return Err(TcpError::BadRequest(
+            "The name of the security key should have 1 to 255 characters".to_string(),
+        ));
+    }
+    let credential = webauthn.finish_registration(&user_id, name.to_owned(), &body.credential)?;
+    data.backend_handler
+        .add_webauthn_credential(credential)
+        .await?;
+    info!("{} registered the security key '{}'", user_id, name);
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn webauthn_register_finish_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Json<webauthn::ClientRegistrationFinishRequest>,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    webauthn_register_finish(data, request, body)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
 #[instrument(skip_all, level = "debug")]
 async fn opaque_login_finish<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    let name = data
+    let name = match data
         .backend_handler
         .login_finish(request.into_inner())
-        .await?;
-    get_login_successful_response(&data, &name).await
+        .await
+    {
+        Ok(name) => name,
+        Err(e) => {
+            // The name of the user is in the state of the login, which couldn't be read.
+            record_auth_event(&data, &http_request, "login", None, Some(e.to_string())).await;
+            return Err(e.into());
+        }
+    };
+    get_login_successful_response(&data, &name, &http_request).await
 }
 
 async fn opaque_login_finish_handler<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientLoginFinishRequest>,
+    http_request: HttpRequest,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + 'static,
 {
-    opaque_login_finish(data, request)
+    opaque_login_finish(data, request, http_request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -392,27 +908,211 @@ where
 async fn simple_login<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientSimpleLoginRequest>,
+    http_request: HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
 {
+    check_login_challenge(&data, request.proof_of_work.as_ref())?;
     let user_id = UserId::new(&request.username);
     let bind_request = BindRequest {
         name: user_id.clone(),
         password: request.password.clone(),
     };
-    data.backend_handler.bind(bind_request).await?;
-    get_login_successful_response(&data, &user_id).await
+    if let Err(e) = data.backend_handler.bind(bind_request).await {
+        record_auth_event(
+            &data,
+            &http_request,
+            "login",
+            Some(&user_id),
+            Some(e.to_string()),
+        )
+        .await;
+        return Err(e.into());
+    }
+    get_login_successful_response(&data, &user_id, &http_request).await
 }
 
 async fn simple_login_handler<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<login::ClientSimpleLoginRequest>,
+    http_request: HttpRequest,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + OpaqueHandler + LoginHandler + 'static,
 {
-    simple_login(data, request)
+    simple_login(data, request, http_request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// Logs in with a temporary password created by an admin: the user has to choose their own
+/// password right away, with the reset token sent instead of the session tokens.
+#[instrument(skip_all, level = "debug")]
+async fn temporary_login<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<login::ClientSimpleLoginRequest>,
+    http_request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    check_login_challenge(&data, request.proof_of_work.as_ref())?;
+    let user_id = UserId::new(&request.username);
+    if let Err(e) = data
+        .backend_handler
+        .consume_temporary_password(&user_id, &request.password)
+        .await
+    {
+        warn!("Rejected a temporary password for {}: {}", user_id, e);
+        record_auth_event(
+            &data,
+            &http_request,
+            "temporaryLogin",
+            Some(&user_id),
+            Some(e.to_string()),
+        )
+        .await;
+        return Err(TcpError::UnauthorizedError(
+            "Invalid or expired temporary password".to_string(),
+        ));
+    }
+    if let Err(e) = check_account_is_active(&data, &user_id).await {
+        record_auth_event(
+            &data,
+            &http_request,
+            "temporaryLogin",
+            Some(&user_id),
+            Some(e.to_string()),
+        )
+        .await;
+        return Err(e);
+    }
+    info!("{} logged in with a temporary password", user_id);
+    record_auth_event(&data, &http_request, "temporaryLogin", Some(&user_id), None).await;
+    let password_reset_token = data
+        .backend_handler
+        .start_password_reset(&user_id)
+        .await?
+        .ok_or_else(|| TcpError::UnauthorizedError("User not found".to_string()))?;
+    Ok(
+        HttpResponse::Ok().json(&login::ServerPasswordExpiredResponse {
+            password_reset_token,
+        }),
+    )
+}
+
+async fn temporary_login_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: web::Json<login::ClientSimpleLoginRequest>,
+    http_request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    temporary_login(data, request, http_request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_magic_login<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    // Don't tell whether the link expired, was already used or never existed.
+    let invalid_link = || TcpError::UnauthorizedError("Invalid or expired login link".to_string());
+    if !data.enable_magic_login_links {
+        return Err(invalid_link());
+    }
+    let token = request
+        .match_info()
+        .get("token")
+        .ok_or_else(|| TcpError::BadRequest("Missing login token".to_string()))?;
+    let user_id = data
+        .backend_handler
+        .consume_magic_login_token(token)
+        .await
+        .map_err(|e| {
+            warn!("Rejected a login link: {}", e);
+            invalid_link()
+        })?;
+    info!("{} logged in with a login link", user_id);
+    get_login_successful_response(&data, &user_id, &request).await
+}
+
+async fn get_magic_login_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_magic_login(data, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+/// The public keys verifying the JWTs, with `jwt_algorithm = "RS256"`.
+async fn get_jwks_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    HttpResponse::Ok().json(data.jwt_keys.jwks())
+}
+
+/// Logs in the user named in the header set by the trusted reverse proxy, see `proxy_auth`.
+async fn get_proxy_login<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let options = &data.proxy_auth;
+    if !options.enabled {
+        return Err(TcpError::UnauthorizedError(
+            "Proxy authentication is disabled".to_string(),
+        ));
+    }
+    // The actual peer, not the forwarded address: that one is set by the client.
+    let peer = request.peer_addr().map(|addr| addr.ip());
+    if !peer.map_or(false, |ip| options.trusted_proxies.contains(&ip)) {
+        warn!(
+            "Ignored a proxy login from an untrusted address: {:?}",
+            peer
+        );
+        return Err(TcpError::UnauthorizedError(
+            "Not coming from a trusted proxy".to_string(),
+        ));
+    }
+    let user_id = request
+        .headers()
+        .get(options.header.as_str())
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(UserId::new)
+        .ok_or_else(|| TcpError::UnauthorizedError(format!("Missing {} header", options.header)))?;
+    data.backend_handler
+        .get_user_details(&user_id)
+        .await
+        .map_err(|e| {
+            warn!("Rejected the proxy login of {}: {}", user_id, e);
+            TcpError::UnauthorizedError("Unknown user".to_string())
+        })?;
+    info!("{} logged in through the reverse proxy", user_id);
+    open_session(&data, &user_id, &request).await
+}
+
+async fn get_proxy_login_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_proxy_login(data, request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -421,24 +1121,42 @@ where
 async fn post_authorize<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<BindRequest>,
+    http_request: HttpRequest,
 ) -> TcpResult<HttpResponse>
 where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
 {
+    // The request has no room for a solved challenge: `/auth/simple/login` replaces it.
+    if data.login_challenges.is_some() {
+        return Err(TcpError::UnauthorizedError(
+            "The login challenge is enabled, use /auth/simple/login".to_string(),
+        ));
+    }
     let name = request.name.clone();
     debug!(%name);
-    data.backend_handler.bind(request.into_inner()).await?;
-    get_login_successful_response(&data, &name).await
+    if let Err(e) = data.backend_handler.bind(request.into_inner()).await {
+        record_auth_event(
+            &data,
+            &http_request,
+            "login",
+            Some(&name),
+            Some(e.to_string()),
+        )
+        .await;
+        return Err(e.into());
+    }
+    get_login_successful_response(&data, &name, &http_request).await
 }
 
 async fn post_authorize_handler<Backend>(
     data: web::Data<AppState<Backend>>,
     request: web::Json<BindRequest>,
+    http_request: HttpRequest,
 ) -> HttpResponse
 where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + 'static,
 {
-    post_authorize(data, request)
+    post_authorize(data, request, http_request)
         .await
         .unwrap_or_else(error_to_http_response)
 }
@@ -588,6 +1306,12 @@ pub enum Permission {
     PasswordManager,
     Readonly,
     Regular,
+    /// An API token, which can read everything and has some of the admin rights. It's only valid
+    /// while its creator is an admin.
+    ApiToken {
+        user_management: bool,
+        group_management: bool,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -610,31 +1334,69 @@ impl ValidationResults {
         self.permission == Permission::Admin
     }
 
+    #[must_use]
+    pub fn is_api_token(&self) -> bool {
+        matches!(self.permission, Permission::ApiToken { .. })
+    }
+
+    /// The user, unless this is an API token: they don't act on behalf of their creator.
+    fn is_user(&self, user: &UserId) -> bool {
+        &self.user == user && !self.is_api_token()
+    }
+
+    /// Whether the sessions and the credentials of the user are visible.
+    #[must_use]
+    pub fn is_admin_or_user(&self, user: &UserId) -> bool {
+        self.is_admin() || self.is_user(user)
+    }
+
     #[must_use]
     pub fn is_admin_or_readonly(&self) -> bool {
         self.permission == Permission::Admin
             || self.permission == Permission::Readonly
             || self.permission == Permission::PasswordManager
+            || self.is_api_token()
     }
 
     #[must_use]
     pub fn can_read(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin
-            || self.permission == Permission::PasswordManager
-            || self.permission == Permission::Readonly
-            || &self.user == user
+        self.is_admin_or_readonly() || &self.user == user
     }
 
     #[must_use]
     pub fn can_change_password(&self, user: &UserId, user_is_admin: bool) -> bool {
         self.permission == Permission::Admin
             || (self.permission == Permission::PasswordManager && !user_is_admin)
-            || &self.user == user
+            || self.is_user(user)
     }
 
     #[must_use]
     pub fn can_write(&self, user: &UserId) -> bool {
-        self.permission == Permission::Admin || &self.user == user
+        self.can_manage_users() || self.is_user(user)
+    }
+
+    /// Whether users can be created, modified and deleted.
+    #[must_use]
+    pub fn can_manage_users(&self) -> bool {
+        match self.permission {
+            Permission::Admin => true,
+            Permission::ApiToken {
+                user_management, ..
+            } => user_management,
+            _ => false,
+        }
+    }
+
+    /// Whether groups can be created, modified and deleted, and their members changed.
+    #[must_use]
+    pub fn can_manage_groups(&self) -> bool {
+        match self.permission {
+            Permission::Admin => true,
+            Permission::ApiToken {
+                group_management, ..
+            } => group_management,
+            _ => false,
+        }
     }
 }
 
@@ -643,45 +1405,146 @@ pub(crate) fn check_if_token_is_valid<Backend>(
     state: &AppState<Backend>,
     token_str: &str,
 ) -> Result<ValidationResults, actix_web::Error> {
-    let token: Token<_> = VerifyWithKey::verify_with_key(token_str, &state.jwt_key)
-        .map_err(|_| ErrorUnauthorized("Invalid JWT"))?;
-    if token.claims().exp.lt(&Utc::now()) {
+    let claims = state
+        .jwt_keys
+        .verify(token_str)
+        .ok_or_else(|| ErrorUnauthorized("Invalid JWT"))?;
+    if claims.exp.lt(&Utc::now()) {
         return Err(ErrorUnauthorized("Expired JWT"));
     }
-    if token.header().algorithm != jwt::AlgorithmType::Hs512 {
-        return Err(ErrorUnauthorized(format!(
-            "Unsupported JWT algorithm: '{:?}'. Supported ones are: ['HS512']",
-            token.header().algorithm
-        )));
-    }
-    let jwt_hash = {
-        let mut s = DefaultHasher::new();
-        token_str.hash(&mut s);
-        s.finish()
-    };
-    if state.jwt_blacklist.read().unwrap().contains(&jwt_hash) {
+    if state
+        .jwt_blacklist
+        .read()
+        .unwrap()
+        .contains(&hash_token(token_str))
+    {
         return Err(ErrorUnauthorized("JWT was logged out"));
     }
-    let is_in_group = |name| token.claims().groups.contains(name);
     Ok(ValidationResults {
-        user: UserId::new(&token.claims().user),
-        permission: if is_in_group("lldap_admin") {
-            Permission::Admin
-        } else if is_in_group("lldap_password_manager") {
-            Permission::PasswordManager
-        } else if is_in_group("lldap_strict_readonly") {
-            Permission::Readonly
-        } else {
-            Permission::Regular
-        },
+        user: UserId::new(&claims.user),
+        permission: permission_of_groups(|name| claims.groups.contains(name)),
     })
 }
 
+fn permission_of_groups(is_in_group: impl Fn(&str) -> bool) -> Permission {
+    if is_in_group("lldap_admin") {
+        Permission::Admin
+    } else if is_in_group("lldap_password_manager") {
+        Permission::PasswordManager
+    } else if is_in_group("lldap_strict_readonly") {
+        Permission::Readonly
+    } else {
+        Permission::Regular
+    }
+}
+
+/// The token of a connection that outlives the request, like a subscription: it's checked again
+/// before sending anything, in case it expired, was logged out or revoked in the meantime.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SessionToken {
+    Jwt { hash: u64, expiry: DateTime<Utc> },
+    ApiToken(String),
+}
+
+impl SessionToken {
+    /// The session of a valid JWT or API token.
+    pub(crate) fn from_token<Backend>(state: &AppState<Backend>, token_str: &str) -> Option<Self> {
+        if token_str.starts_with(API_TOKEN_PREFIX) {
+            return Some(Self::ApiToken(token_str.to_owned()));
+        }
+        state.jwt_keys.verify(token_str).map(|claims| Self::Jwt {
+            hash: hash_token(token_str),
+            expiry: claims.exp,
+        })
+    }
+
+    /// The current permissions of the user of the token, or `None` if the token isn't valid
+    /// anymore. The groups of a JWT are read again, since its claims can be outdated.
+    pub async fn validate<Backend: BackendHandler>(
+        &self,
+        backend_handler: &Backend,
+        jwt_blacklist: &RwLock<HashSet<u64>>,
+        user: &UserId,
+    ) -> crate::domain::error::Result<Option<Permission>> {
+        match self {
+            Self::Jwt { hash, expiry } => {
+                if *expiry < Utc::now() || jwt_blacklist.read().unwrap().contains(hash) {
+                    return Ok(None);
+                }
+                let groups = backend_handler.get_user_groups(user).await?;
+                Ok(Some(permission_of_groups(|name| {
+                    groups.iter().any(|g| !g.archived && g.display_name == name)
+                })))
+            }
+            Self::ApiToken(secret) => Ok(validate_api_token(backend_handler, secret)
+                .await?
+                .ok()
+                .map(|validation_result| validation_result.permission)),
+        }
+    }
+}
+
+/// The permissions of an API token, or the reason why it's refused.
+async fn validate_api_token<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    token_str: &str,
+) -> crate::domain::error::Result<std::result::Result<ValidationResults, &'static str>> {
+    let token = match backend_handler.get_api_token(token_str).await? {
+        Some(token) => token,
+        None => return Ok(Err("Invalid API token")),
+    };
+    // The token acts on behalf of its creator, who may have been disabled or demoted since.
+    match backend_handler.get_user_details(&token.user_id).await {
+        Ok(user) if user.is_active_at(Utc::now()) => (),
+        Ok(_) | Err(DomainError::EntityNotFound(_)) => {
+            return Ok(Err("The account of the API token is disabled"))
+        }
+        Err(e) => return Err(e),
+    }
+    let is_admin = backend_handler
+        .get_user_groups(&token.user_id)
+        .await?
+        .iter()
+        .any(|g| !g.archived && g.display_name == "lldap_admin");
+    if !is_admin {
+        return Ok(Err("The creator of the API token is no longer an admin"));
+    }
+    Ok(Ok(ValidationResults {
+        user: token.user_id,
+        permission: Permission::ApiToken {
+            user_management: token.scopes.contains(&ApiTokenScope::UserManagement),
+            group_management: token.scopes.contains(&ApiTokenScope::GroupManagement),
+        },
+    }))
+}
+
+/// Checks the bearer token of the API endpoints, which can also be an API token.
+#[instrument(skip_all, level = "debug", err, ret)]
+pub(crate) async fn check_if_token_or_api_token_is_valid<Backend>(
+    state: &AppState<Backend>,
+    token_str: &str,
+) -> Result<ValidationResults, actix_web::Error>
+where
+    Backend: BackendHandler,
+{
+    if !token_str.starts_with(API_TOKEN_PREFIX) {
+        return check_if_token_is_valid(state, token_str);
+    }
+    validate_api_token(&state.backend_handler, token_str)
+        .await
+        .map_err(|e| ErrorInternalServerError(e.to_string()))?
+        .map_err(ErrorUnauthorized)
+}
+
 pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
 where
     Backend: TcpBackendHandler + LoginHandler + OpaqueHandler + BackendHandler + 'static,
 {
     cfg.service(web::resource("").route(web::post().to(post_authorize_handler::<Backend>)))
+        .service(
+            web::resource("/login/challenge")
+                .route(web::get().to(get_login_challenge_handler::<Backend>)),
+        )
         .service(
             web::resource("/opaque/login/start")
                 .route(web::post().to(opaque_login_start::<Backend>)),
@@ -693,6 +1556,15 @@ where
         .service(
             web::resource("/simple/login").route(web::post().to(simple_login_handler::<Backend>)),
         )
+        .service(web::resource("/totp/login").route(web::post().to(totp_login_handler::<Backend>)))
+        .service(
+            web::resource("/webauthn/login/start")
+                .route(web::post().to(webauthn_login_start_handler::<Backend>)),
+        )
+        .service(
+            web::resource("/webauthn/login/finish")
+                .route(web::post().to(webauthn_login_finish_handler::<Backend>)),
+        )
         .service(web::resource("/refresh").route(web::get().to(get_refresh_handler::<Backend>)))
         .service(
             web::resource("/reset/step1/{user_id}")
@@ -702,6 +1574,17 @@ where
             web::resource("/reset/step2/{token}")
                 .route(web::get().to(get_password_reset_step2_handler::<Backend>)),
         )
+        .service(
+            web::resource("/temporary/login")
+                .route(web::post().to(temporary_login_handler::<Backend>)),
+        )
+        .service(
+            web::resource("/magic/{token}")
+                .route(web::get().to(get_magic_login_handler::<Backend>)),
+        )
+        .service(web::resource("/jwks").route(web::get().to(get_jwks_handler::<Backend>)))
+        .service(web::resource("/proxy").route(web::get().to(get_proxy_login_handler::<Backend>)))
+        .service(web::scope("/sso").configure(super::sso_login::configure_server::<Backend>))
         .service(web::resource("/logout").route(web::get().to(get_logout_handler::<Backend>)))
         .service(
             web::scope("/opaque/register")
@@ -714,5 +1597,17 @@ where
                     web::resource("/finish")
                         .route(web::post().to(opaque_register_finish_handler::<Backend>)),
                 ),
+        )
+        .service(
+            web::scope("/webauthn/register")
+                .wrap(CookieToHeaderTranslatorFactory)
+                .service(
+                    web::resource("/start")
+                        .route(web::get().to(webauthn_register_start_handler::<Backend>)),
+                )
+                .service(
+                    web::resource("/finish")
+                        .route(web::post().to(webauthn_register_finish_handler::<Backend>)),
+                ),
         );
 }
@@ -0,0 +1,232 @@
+//! Throttling of the LDAP simple binds against password guessing, configured by the
+//! `ldap_bind_rate_limit` options.
+//!
+//! The failures are counted per client address and per user. Past the free attempts, the next
+//! bind has to wait for a delay that doubles with every failure; with a lockout threshold, a user
+//! with too many failures can't bind at all for a while, from any address.
+//!
+//! This only lives in memory, and is independent of the lock of the accounts after
+//! `max_failed_login_attempts` wrong passwords: the binds refused here don't reach the password
+//! check, so they don't count towards it.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{domain::types::UserId, infra::configuration::LdapBindRateLimitOptions};
+
+/// Above this many records, the expired ones are dropped before adding a new one.
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum BindKey {
+    Address(IpAddr),
+    User(UserId),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// Why a bind is refused without checking the password, and for how long.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindThrottle {
+    Backoff(Duration),
+    LockedOut(Duration),
+}
+
+impl BindThrottle {
+    pub fn remaining(&self) -> Duration {
+        match self {
+            BindThrottle::Backoff(remaining) | BindThrottle::LockedOut(remaining) => *remaining,
+        }
+    }
+}
+
+pub struct BindLimiter {
+    options: LdapBindRateLimitOptions,
+    failures: Mutex<HashMap<BindKey, Failures>>,
+}
+
+impl BindLimiter {
+    pub fn new(options: LdapBindRateLimitOptions) -> Self {
+        Self {
+            options,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The end of the period during which binds for that key are refused, if any.
+    fn blocked_until(&self, key: &BindKey, failures: &Failures) -> Option<(Instant, bool)> {
+        let options = &self.options;
+        if let BindKey::User(_) = key {
+            if options.lockout_threshold > 0 && failures.count >= options.lockout_threshold {
+                let until = failures.last + Duration::from_secs(options.lockout_seconds);
+                return Some((until, true));
+            }
+        }
+        if failures.count <= options.free_attempts {
+            return None;
+        }
+        let doublings = (failures.count - options.free_attempts - 1).min(31);
+        let backoff = options
+            .initial_backoff_seconds
+            .saturating_mul(1 << doublings)
+            .min(options.max_backoff_seconds);
+        Some((failures.last + Duration::from_secs(backoff), false))
+    }
+
+    fn is_expired(&self, key: &BindKey, failures: &Failures, now: Instant) -> bool {
+        let forgotten = failures.last + Duration::from_secs(self.options.failure_window_seconds);
+        let unblocked = self
+            .blocked_until(key, failures)
+            .map(|(until, _)| until)
+            .unwrap_or(forgotten);
+        now >= forgotten.max(unblocked)
+    }
+
+    fn keys(address: Option<IpAddr>, user_id: &UserId) -> impl Iterator<Item = BindKey> {
+        address
+            .map(BindKey::Address)
+            .into_iter()
+            .chain(std::iter::once(BindKey::User(user_id.clone())))
+    }
+
+    fn check_at(
+        &self,
+        address: Option<IpAddr>,
+        user_id: &UserId,
+        now: Instant,
+    ) -> Option<BindThrottle> {
+        let failures = self.failures.lock().unwrap();
+        let mut remaining = None;
+        let mut locked_out = false;
+        for key in Self::keys(address, user_id) {
+            let blocked = failures
+                .get(&key)
+                .and_then(|f| self.blocked_until(&key, f))
+                .filter(|(until, _)| *until > now);
+            if let Some((until, lockout)) = blocked {
+                remaining = remaining.max(Some(until - now));
+                locked_out |= lockout;
+            }
+        }
+        remaining.map(|remaining| {
+            if locked_out {
+                BindThrottle::LockedOut(remaining)
+            } else {
+                BindThrottle::Backoff(remaining)
+            }
+        })
+    }
+
+    fn record_failure_at(&self, address: Option<IpAddr>, user_id: &UserId, now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() >= PRUNE_THRESHOLD {
+            failures.retain(|key, f| !self.is_expired(key, f, now));
+        }
+        for key in Self::keys(address, user_id) {
+            let count = match failures.get(&key) {
+                Some(f) if !self.is_expired(&key, f, now) => f.count.saturating_add(1),
+                _ => 1,
+            };
+            failures.insert(key, Failures { count, last: now });
+        }
+    }
+
+    /// Returns whether the bind has to be refused, without checking the password.
+    pub fn check(&self, address: Option<IpAddr>, user_id: &UserId) -> Option<BindThrottle> {
+        self.check_at(address, user_id, Instant::now())
+    }
+
+    pub fn record_failure(&self, address: Option<IpAddr>, user_id: &UserId) {
+        self.record_failure_at(address, user_id, Instant::now())
+    }
+
+    /// Forgets the failures of the user. Those of the address are kept, so that an attacker can't
+    /// reset them by binding to their own account.
+    pub fn record_success(&self, user_id: &UserId) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&BindKey::User(user_id.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_limiter(lockout_threshold: u32) -> BindLimiter {
+        BindLimiter::new(LdapBindRateLimitOptions {
+            enabled: true,
+            free_attempts: 2,
+            initial_backoff_seconds: 1,
+            max_backoff_seconds: 4,
+            lockout_threshold,
+            lockout_seconds: 60,
+            failure_window_seconds: 30,
+        })
+    }
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let limiter = make_limiter(0);
+        let address = Some("10.0.0.1".parse().unwrap());
+        let bob = UserId::new("bob");
+        let start = Instant::now();
+        for _ in 0..2 {
+            limiter.record_failure_at(address, &bob, start);
+            assert_eq!(limiter.check_at(address, &bob, start), None);
+        }
+        for (i, backoff) in [1, 2, 4, 4].into_iter().enumerate() {
+            let now = start + secs(i as u64);
+            limiter.record_failure_at(address, &bob, now);
+            assert_eq!(
+                limiter.check_at(address, &bob, now),
+                Some(BindThrottle::Backoff(secs(backoff)))
+            );
+        }
+        // The address is throttled for the other users too.
+        assert!(limiter
+            .check_at(address, &UserId::new("alice"), start + secs(3))
+            .is_some());
+        assert_eq!(limiter.check_at(None, &UserId::new("alice"), start), None);
+        // A success only resets the failures of the user.
+        limiter.record_success(&bob);
+        assert!(limiter.check_at(None, &bob, start + secs(3)).is_none());
+        assert!(limiter.check_at(address, &bob, start + secs(3)).is_some());
+        // The failures are eventually forgotten.
+        limiter.record_failure_at(address, &bob, start + secs(40));
+        assert_eq!(limiter.check_at(address, &bob, start + secs(40)), None);
+    }
+
+    #[test]
+    fn test_lockout() {
+        let limiter = make_limiter(3);
+        let bob = UserId::new("bob");
+        let start = Instant::now();
+        for i in 0..3 {
+            let address = Some(IpAddr::from([10, 0, 0, i]));
+            limiter.record_failure_at(address, &bob, start);
+        }
+        let other_address = Some(IpAddr::from([10, 0, 0, 42]));
+        assert_eq!(
+            limiter.check_at(other_address, &bob, start + secs(10)),
+            Some(BindThrottle::LockedOut(secs(50)))
+        );
+        assert_eq!(
+            limiter.check_at(other_address, &bob, start + secs(60)),
+            None
+        );
+    }
+}
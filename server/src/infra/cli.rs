@@ -26,6 +26,22 @@ pub enum Command {
     /// Send a test email.
     #[clap(name = "send_test_email")]
     SendTestEmail(TestEmailOpts),
+    /// Create a user directly in the database, e.g. from a provisioning script.
+    #[clap(name = "create_user", alias = "create-user")]
+    CreateUser(CreateUserOpts),
+    /// Export the passwords of all the users, to migrate them to another instance.
+    #[clap(name = "export_credentials", alias = "export-credentials")]
+    ExportCredentials(CredentialsOpts),
+    /// Import the passwords exported from another instance with the same server key.
+    #[clap(name = "import_credentials", alias = "import-credentials")]
+    ImportCredentials(CredentialsOpts),
+    /// Reclaim the free space and refresh the statistics and indices of the database.
+    #[clap(name = "optimize_database", alias = "optimize-database")]
+    OptimizeDatabase(OptimizeDatabaseOpts),
+    /// Replace the RSA key signing the JWTs, with `jwt_algorithm = "RS256"`. The server has to be
+    /// restarted to use the new one.
+    #[clap(name = "rotate_jwt_key", alias = "rotate-jwt-key")]
+    RotateJwtKey(RotateJwtKeyOpts),
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -74,6 +90,11 @@ pub struct RunOpts {
     #[clap(long, env = "LLDAP_HTTP_URL")]
     pub http_url: Option<String>,
 
+    /// Replace the generated JWT secret with a new one, logging out all the users.
+    /// Has no effect if jwt_secret is set in the configuration.
+    #[clap(long)]
+    pub regenerate_jwt_secret: bool,
+
     #[clap(flatten)]
     pub smtp_opts: SmtpOpts,
 
@@ -94,6 +115,82 @@ pub struct TestEmailOpts {
     pub smtp_opts: SmtpOpts,
 }
 
+#[derive(Debug, Parser, Clone)]
+pub struct CreateUserOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Path to the file that contains the private server key.
+    #[clap(long, env = "LLDAP_SERVER_KEY_FILE")]
+    pub server_key_file: Option<String>,
+
+    /// ID of the new user.
+    #[clap(long)]
+    pub uid: String,
+
+    /// Email address of the new user.
+    #[clap(long)]
+    pub email: String,
+
+    #[clap(long)]
+    pub display_name: Option<String>,
+
+    #[clap(long)]
+    pub first_name: Option<String>,
+
+    #[clap(long)]
+    pub last_name: Option<String>,
+
+    /// Password of the new user. Prefer `--password-file`: this one is visible in the process
+    /// list.
+    #[clap(long, conflicts_with = "password_file")]
+    pub password: Option<String>,
+
+    /// Read the password from this file, or from the standard input if the path is "-".
+    #[clap(long)]
+    pub password_file: Option<String>,
+
+    /// A bcrypt, argon2 or `{SSHA}` hash of the password from another system, e.g. OpenLDAP,
+    /// instead of the password. It is upgraded on the first successful LDAP bind.
+    #[clap(long, conflicts_with_all = &["password", "password_file"])]
+    pub password_hash: Option<String>,
+
+    /// Add the user to this existing group. Can be repeated.
+    #[clap(long = "group")]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct CredentialsOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Path to the file that contains the private server key. The passwords can only be imported
+    /// with the key of the instance they were exported from.
+    #[clap(long, env = "LLDAP_SERVER_KEY_FILE")]
+    pub server_key_file: Option<String>,
+
+    /// The file to write the passwords to, or to read them from.
+    #[clap(long)]
+    pub file: String,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct OptimizeDatabaseOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct RotateJwtKeyOpts {
+    #[clap(flatten)]
+    pub general_config: GeneralConfigOpts,
+
+    /// Path to the file that contains the private server key. The JWT keys are next to it.
+    #[clap(long, env = "LLDAP_SERVER_KEY_FILE")]
+    pub server_key_file: Option<String>,
+}
+
 #[derive(Debug, Parser, Clone)]
 #[clap(next_help_heading = Some("LDAPS"), setting = clap::AppSettings::DeriveDisplayOrder)]
 pub struct LdapsOpts {
@@ -1,16 +1,26 @@
 use crate::{
-    domain::types::UserId,
-    infra::cli::{GeneralConfigOpts, LdapsOpts, RunOpts, SmtpEncryption, SmtpOpts, TestEmailOpts},
+    domain::{
+        ldap::utils::map_user_field,
+        types::{PasswordExpiry, UserColumn, UserId},
+    },
+    infra::cli::{
+        CreateUserOpts, CredentialsOpts, GeneralConfigOpts, LdapsOpts, OptimizeDatabaseOpts,
+        RotateJwtKeyOpts, RunOpts, SmtpEncryption, SmtpOpts, TestEmailOpts,
+    },
 };
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use figment::{
     providers::{Env, Format, Serialized, Toml},
     Figment,
 };
 use lettre::message::Mailbox;
-use lldap_auth::opaque::{server::ServerSetup, KeyPair};
+use lldap_auth::{
+    opaque::{server::ServerSetup, KeyPair},
+    password_strength::{PasswordPolicy, CHARACTER_CLASSES, MIN_PASSWORD_LENGTH},
+};
 use secstr::SecUtf8;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
 #[builder(pattern = "owned")]
@@ -31,6 +41,23 @@ pub struct MailOptions {
     pub password: SecUtf8,
     #[builder(default = "SmtpEncryption::TLS")]
     pub smtp_encryption: SmtpEncryption,
+    #[builder(default = "None")]
+    pub password_reset_template: Option<String>,
+    #[builder(default = "None")]
+    pub test_email_template: Option<String>,
+    /// Email the users when they log in to the web UI from a device without an open session.
+    #[builder(default = "false")]
+    pub enable_login_notifications: bool,
+    #[builder(default = "None")]
+    pub login_notification_template: Option<String>,
+    /// Email the approvers of the groups that require an approval when a user asks to join one,
+    /// and the user once their request is approved or denied.
+    #[builder(default = "false")]
+    pub enable_membership_request_notifications: bool,
+    #[builder(default = "None")]
+    pub membership_request_template: Option<String>,
+    #[builder(default = "None")]
+    pub membership_decision_template: Option<String>,
     /// Deprecated.
     #[builder(default = "None")]
     pub tls_required: Option<bool>,
@@ -53,6 +80,248 @@ pub struct LdapsOptions {
     pub cert_file: String,
     #[builder(default = r#"String::from("key.pem")"#)]
     pub key_file: String,
+    /// CA bundle used to verify client certificates. Client certificates are ignored if unset.
+    #[builder(default = "None")]
+    pub client_ca_file: Option<String>,
+    #[builder(default = "false")]
+    pub require_client_cert: bool,
+    #[builder(default)]
+    pub client_cert_user_source: ClientCertUserSource,
+    /// Offer StartTLS on the plain LDAP port, with the same certificate as LDAPS.
+    #[builder(default = "false")]
+    pub start_tls: bool,
+    /// Refuse the binds on the plain LDAP port until the connection is upgraded with StartTLS.
+    #[builder(default = "false")]
+    pub require_tls: bool,
+    /// How often to check whether the certificate files changed, to reload them. 0 disables it;
+    /// SIGHUP reloads them either way.
+    #[builder(default = "0")]
+    pub cert_reload_interval_seconds: u64,
+    /// Certificates for other server names, picked with SNI.
+    #[builder(default)]
+    pub sni_certificates: Vec<SniCertificate>,
+}
+
+/// A certificate served to the clients asking for `server_name`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SniCertificate {
+    pub server_name: String,
+    pub cert_file: String,
+    pub key_file: String,
+}
+
+/// Throttling of the failed LDAP binds, see [`crate::infra::bind_limiter`].
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LdapBindRateLimitOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// Failures allowed before the backoff starts.
+    #[builder(default = "3")]
+    pub free_attempts: u32,
+    #[builder(default = "1")]
+    pub initial_backoff_seconds: u64,
+    #[builder(default = "300")]
+    pub max_backoff_seconds: u64,
+    /// Failures after which binds to the user are refused for `lockout_seconds`. 0 disables it.
+    #[builder(default = "0")]
+    pub lockout_threshold: u32,
+    #[builder(default = "900")]
+    pub lockout_seconds: u64,
+    /// How long the failures are remembered after the last one.
+    #[builder(default = "900")]
+    pub failure_window_seconds: u64,
+}
+
+impl std::default::Default for LdapBindRateLimitOptions {
+    fn default() -> Self {
+        LdapBindRateLimitOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Login to the web UI of the users authenticated by a reverse proxy in front of LLDAP.
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct ProxyAuthOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// The header containing the user ID set by the proxy.
+    #[builder(default = r#"String::from("Remote-User")"#)]
+    pub header: String,
+    /// The addresses of the proxies: the header is ignored in the requests coming from others.
+    #[builder(default)]
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+impl std::default::Default for ProxyAuthOptions {
+    fn default() -> Self {
+        ProxyAuthOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// The proof of work of the password logins, see [`crate::infra::login_challenges`].
+#[derive(Clone, Debug, Deserialize, Serialize, derive_builder::Builder)]
+#[builder(pattern = "owned")]
+pub struct LoginChallengeOptions {
+    #[builder(default = "false")]
+    pub enabled: bool,
+    /// The number of leading zero bits of the digest: each one doubles the work of the client.
+    #[builder(default = "18")]
+    pub difficulty: u8,
+}
+
+impl std::default::Default for LoginChallengeOptions {
+    fn default() -> Self {
+        LoginChallengeOptionsBuilder::default().build().unwrap()
+    }
+}
+
+/// Adds the users matching `filter` (an LDAP filter, see
+/// [`crate::domain::ldap::filter::parse_user_filter`]) to the static group `group`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GroupAssignmentRule {
+    pub filter: String,
+    pub group: String,
+}
+
+/// Gives the members of `group` read access to all the entries over LDAP, like the
+/// `lldap_strict_readonly` group, but only to the listed attributes.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LdapServiceAccountGroup {
+    pub group: String,
+    pub attributes: Vec<String>,
+}
+
+/// An application that logs its users in with the OpenID Connect provider, see
+/// [`crate::infra::oidc`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OidcClient {
+    pub client_id: String,
+    /// `None` for the applications that can't keep a secret, like single-page apps: they have to
+    /// use PKCE instead.
+    #[serde(default)]
+    pub client_secret: Option<SecUtf8>,
+    /// The exact URLs that the users can be sent back to with their authorization code.
+    pub redirect_uris: Vec<String>,
+}
+
+/// Login to the web UI with an upstream OpenID Connect provider, see
+/// [`crate::infra::sso_login`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SsoLoginOptions {
+    /// The `iss` of the ID tokens of the provider.
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub client_id: String,
+    pub client_secret: SecUtf8,
+    /// Create the users that log in for the first time from the claims of their ID token, and
+    /// update their email and names on the next logins.
+    #[serde(default)]
+    pub jit_provisioning: bool,
+    /// The groups that the provisioned users are added to.
+    #[serde(default)]
+    pub default_groups: Vec<String>,
+}
+
+/// A field of the user details page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserDetailsField {
+    UserId,
+    CreationDate,
+    Uuid,
+    Email,
+    EmailAliases,
+    DisplayName,
+    FirstName,
+    LastName,
+    PreferredLanguage,
+    SshPublicKeys,
+    PosixAttributes,
+    Avatar,
+    /// All the custom attributes defined by the admins.
+    CustomAttributes,
+}
+
+impl UserDetailsField {
+    /// The name of the field, as written in the configuration.
+    pub fn name(self) -> &'static str {
+        match self {
+            UserDetailsField::UserId => "user_id",
+            UserDetailsField::CreationDate => "creation_date",
+            UserDetailsField::Uuid => "uuid",
+            UserDetailsField::Email => "email",
+            UserDetailsField::EmailAliases => "email_aliases",
+            UserDetailsField::DisplayName => "display_name",
+            UserDetailsField::FirstName => "first_name",
+            UserDetailsField::LastName => "last_name",
+            UserDetailsField::PreferredLanguage => "preferred_language",
+            UserDetailsField::SshPublicKeys => "ssh_public_keys",
+            UserDetailsField::PosixAttributes => "posix_attributes",
+            UserDetailsField::Avatar => "avatar",
+            UserDetailsField::CustomAttributes => "custom_attributes",
+        }
+    }
+}
+
+/// A group of fields on the user details page. The fields that don't appear in any section are
+/// not displayed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct UserDetailsSection {
+    #[serde(default)]
+    pub title: Option<String>,
+    pub fields: Vec<UserDetailsField>,
+}
+
+impl UserDetailsSection {
+    /// The layout used when none is configured: all the fields, in a single section.
+    pub fn default_layout() -> Vec<Self> {
+        use UserDetailsField::*;
+        vec![Self {
+            title: None,
+            fields: vec![
+                UserId,
+                CreationDate,
+                Uuid,
+                Email,
+                EmailAliases,
+                DisplayName,
+                FirstName,
+                LastName,
+                PreferredLanguage,
+                SshPublicKeys,
+                PosixAttributes,
+                Avatar,
+                CustomAttributes,
+            ],
+        }]
+    }
+}
+
+/// How the JWTs are signed, see [`crate::infra::jwt_keys`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum JwtAlgorithm {
+    /// With `jwt_secret`.
+    #[default]
+    #[serde(rename = "HS512")]
+    Hs512,
+    /// With an RSA key, published by the JWKS endpoint.
+    #[serde(rename = "RS256")]
+    Rs256,
+}
+
+/// Which part of a client certificate identifies the lldap user.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientCertUserSource {
+    /// The common name of the subject is the user ID.
+    #[default]
+    SubjectCn,
+    /// A DNS name in the subject alternative names is the user ID.
+    SanDns,
+    /// An email address in the subject alternative names is the email of the user.
+    SanEmail,
 }
 
 impl std::default::Default for LdapsOptions {
@@ -72,8 +341,14 @@ pub struct Configuration {
     pub http_host: String,
     #[builder(default = "17170")]
     pub http_port: u16,
-    #[builder(default = r#"SecUtf8::from("secretjwtsecret")"#)]
-    pub jwt_secret: SecUtf8,
+    #[builder(default = "None")]
+    pub jwt_secret: Option<SecUtf8>,
+    #[builder(default)]
+    pub jwt_algorithm: JwtAlgorithm,
+    #[builder(default)]
+    pub jwt_previous_secrets: Vec<SecUtf8>,
+    #[builder(default = "24")]
+    pub jwt_key_grace_period_hours: u32,
     #[builder(default = r#"String::from("dc=example,dc=com")"#)]
     pub ldap_base_dn: String,
     #[builder(default = r#"UserId::new("admin")"#)]
@@ -89,6 +364,94 @@ pub struct Configuration {
     #[builder(default)]
     pub ignored_group_attributes: Vec<String>,
     #[builder(default = "false")]
+    pub ldap_empty_password_bind_unwilling_to_perform: bool,
+    #[builder(default = "false")]
+    pub ldap_anonymous_bind: bool,
+    #[builder(
+        default = r#"vec!["cn".to_string(), "mail".to_string(), "displayName".to_string()]"#
+    )]
+    pub ldap_anonymous_bind_attributes: Vec<String>,
+    #[builder(default)]
+    pub ldap_service_account_groups: Vec<LdapServiceAccountGroup>,
+    #[builder(default = "false")]
+    pub ldap_active_directory_compatibility: bool,
+    #[builder(default = "false")]
+    pub ldap_query_log: bool,
+    #[builder(default = "0")]
+    pub ldap_slow_query_threshold_ms: u64,
+    #[builder(default = "0")]
+    pub ldap_idle_timeout_seconds: u64,
+    #[builder(default = "0")]
+    pub ldap_max_connections: usize,
+    #[builder(default = "None")]
+    pub ldap_kerberos_realm: Option<String>,
+    #[builder(default)]
+    pub ldap_bind_rate_limit: LdapBindRateLimitOptions,
+    #[builder(default)]
+    pub ldap_user_attribute_aliases: HashMap<String, String>,
+    #[builder(default)]
+    pub ldap_group_attribute_aliases: HashMap<String, String>,
+    #[builder(default = "10")]
+    pub nested_groups_max_depth: u8,
+    #[builder(default = "0")]
+    pub min_password_strength: u8,
+    #[builder(default = "lldap_auth::password_strength::MIN_PASSWORD_LENGTH")]
+    pub min_password_length: usize,
+    #[builder(default = "0")]
+    pub min_password_character_classes: u8,
+    #[builder(default = "None")]
+    pub banned_passwords_file: Option<String>,
+    #[builder(default = "0")]
+    pub max_password_age_days: u32,
+    #[builder(default = "14")]
+    pub password_expiry_warning_days: u32,
+    #[builder(default = "None")]
+    pub password_grace_logins: Option<u32>,
+    #[builder(default = "0")]
+    pub password_history_size: usize,
+    #[builder(default = "0")]
+    pub max_failed_login_attempts: u32,
+    #[builder(default = "0")]
+    pub account_lockout_minutes: u32,
+    #[builder(default)]
+    pub group_assignment_rules: Vec<GroupAssignmentRule>,
+    #[builder(default)]
+    pub user_details_layout: Vec<UserDetailsSection>,
+    #[builder(default = "false")]
+    pub enable_magic_login_links: bool,
+    #[builder(default)]
+    pub proxy_auth: ProxyAuthOptions,
+    #[builder(default)]
+    pub login_challenge: LoginChallengeOptions,
+    #[builder(default = r#"vec!["uid".to_owned(), "mail".to_owned(), "cn".to_owned()]"#)]
+    pub user_search_attributes: Vec<String>,
+    #[builder(default = "None")]
+    pub everyone_group: Option<String>,
+    #[builder(default = "false")]
+    pub strict_group_membership_adds: bool,
+    #[builder(default)]
+    pub membership_approval_groups: Vec<String>,
+    #[builder(default = "7")]
+    pub membership_request_validity_days: u32,
+    #[builder(default)]
+    pub user_attribute_defaults: HashMap<String, String>,
+    #[builder(default = r#"String::from("en")"#)]
+    pub default_language: String,
+    #[builder(default = "None")]
+    pub max_groups_per_user: Option<usize>,
+    #[builder(default = "true")]
+    pub max_groups_per_user_exempts_admins: bool,
+    #[builder(default = "90")]
+    pub audit_log_retention_days: u32,
+    #[builder(default = "None")]
+    pub persisted_queries_dir: Option<String>,
+    #[builder(default = "false")]
+    pub persisted_queries_only: bool,
+    #[builder(default)]
+    pub oidc_clients: Vec<OidcClient>,
+    #[builder(default = "None")]
+    pub sso_login: Option<SsoLoginOptions>,
+    #[builder(default = "false")]
     pub verbose: bool,
     #[builder(default = r#"String::from("server_key")"#)]
     pub key_file: String,
@@ -101,6 +464,13 @@ pub struct Configuration {
     #[serde(skip)]
     #[builder(field(private), default = "None")]
     server_setup: Option<ServerSetup>,
+    #[serde(skip)]
+    #[builder(field(private), default = "false")]
+    regenerate_jwt_secret: bool,
+    /// The contents of `banned_passwords_file`, loaded by [`init`].
+    #[serde(skip)]
+    #[builder(field(private), default)]
+    banned_passwords: Vec<String>,
 }
 
 impl std::default::Default for Configuration {
@@ -133,6 +503,82 @@ impl Configuration {
     pub fn get_server_keys(&self) -> &KeyPair {
         self.get_server_setup().keypair()
     }
+
+    /// The configured JWT secret, or the generated one. Only unset before [`init`].
+    pub fn get_jwt_secret(&self) -> &SecUtf8 {
+        self.jwt_secret.as_ref().unwrap()
+    }
+
+    /// Whether the JWT secret was just regenerated, invalidating the existing sessions.
+    pub fn jwt_secret_regenerated(&self) -> bool {
+        self.regenerate_jwt_secret
+    }
+
+    /// The requirements on new passwords, checked whenever the server sees one.
+    pub fn get_password_policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: self.min_password_length,
+            min_character_classes: self.min_password_character_classes,
+            min_strength: self.min_password_strength,
+            banned_passwords: self.banned_passwords.clone(),
+        }
+    }
+
+    /// `None` if the passwords don't expire.
+    pub fn get_password_expiry(&self) -> Option<PasswordExpiry> {
+        Some(PasswordExpiry {
+            max_age_days: self.max_password_age_days,
+            warning_days: self.password_expiry_warning_days,
+            grace_logins: self.password_grace_logins,
+        })
+        .filter(|expiry| expiry.max_age_days > 0)
+    }
+
+    /// The user columns matched by the `searchUsers` query, in order of priority.
+    pub fn get_user_search_columns(&self) -> Result<Vec<UserColumn>> {
+        self.user_search_attributes
+            .iter()
+            .map(
+                |attribute| match map_user_field(&attribute.to_ascii_lowercase()) {
+                    Some(
+                        column @ (UserColumn::UserId
+                        | UserColumn::Email
+                        | UserColumn::DisplayName
+                        | UserColumn::FirstName
+                        | UserColumn::LastName
+                        | UserColumn::PreferredLanguage),
+                    ) => Ok(column),
+                    _ => Err(anyhow!("Cannot search users by `{}`", attribute)),
+                },
+            )
+            .collect()
+    }
+
+    /// The default values of the optional user attributes, applied when creating a user without
+    /// them.
+    pub fn get_user_attribute_defaults(&self) -> Result<Vec<(UserColumn, String)>> {
+        self.user_attribute_defaults
+            .iter()
+            .map(|(attribute, value)| {
+                let column = match map_user_field(&attribute.to_ascii_lowercase()) {
+                    Some(
+                        column @ (UserColumn::DisplayName
+                        | UserColumn::FirstName
+                        | UserColumn::LastName
+                        | UserColumn::PreferredLanguage),
+                    ) => column,
+                    _ => anyhow::bail!("`{}` cannot have a default value", attribute),
+                };
+                if value.is_empty() {
+                    anyhow::bail!("The default value of `{}` is empty", attribute);
+                }
+                if column == UserColumn::PreferredLanguage && value.len() > 35 {
+                    anyhow::bail!("The default language tag `{}` is too long", value);
+                }
+                Ok((column, value.clone()))
+            })
+            .collect()
+    }
 }
 
 fn generate_random_private_key() -> ServerSetup {
@@ -140,7 +586,7 @@ fn generate_random_private_key() -> ServerSetup {
     ServerSetup::new(&mut rng)
 }
 
-fn write_to_readonly_file(path: &std::path::Path, buffer: &[u8]) -> Result<()> {
+pub(crate) fn write_to_readonly_file(path: &std::path::Path, buffer: &[u8]) -> Result<()> {
     use std::{fs::File, io::Write};
     assert!(!path.exists());
     let mut file = File::create(path)?;
@@ -154,6 +600,16 @@ fn write_to_readonly_file(path: &std::path::Path, buffer: &[u8]) -> Result<()> {
     Ok(file.write_all(buffer)?)
 }
 
+/// One password per line; the empty lines and the lines starting with `#` are skipped.
+fn read_banned_passwords(path: &str) -> Result<Vec<String>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect())
+}
+
 fn get_server_setup(file_path: &str) -> Result<ServerSetup> {
     use std::fs::read;
     let path = std::path::Path::new(file_path);
@@ -170,6 +626,37 @@ fn get_server_setup(file_path: &str) -> Result<ServerSetup> {
     }
 }
 
+/// Reads the JWT secret generated on a previous start, next to the server key, or generates and
+/// stores a new one. If it can't be stored, the secret only lasts until the server restarts.
+fn get_generated_jwt_secret(key_file: &str, regenerate: bool) -> Result<SecUtf8> {
+    use rand::{distributions::Alphanumeric, Rng};
+    let path = std::path::Path::new(key_file).with_file_name("jwt_secret");
+    if path.exists() && !regenerate {
+        let secret = std::fs::read_to_string(&path).context(format!(
+            "Could not read JWT secret file `{}`",
+            path.display()
+        ))?;
+        return Ok(SecUtf8::from(secret.trim()));
+    }
+    let secret = rand::rngs::OsRng
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .map(char::from)
+        .collect::<String>();
+    let stored = (!path.exists() || std::fs::remove_file(&path).is_ok())
+        && write_to_readonly_file(&path, secret.as_bytes()).is_ok();
+    if stored {
+        println!("Generated a new JWT secret in `{}`", path.display());
+    } else {
+        println!(
+            "WARNING: Could not store the generated JWT secret in `{}`! All the sessions will be \
+             invalidated when the server restarts. Set jwt_secret to avoid this.",
+            path.display()
+        );
+    }
+    Ok(SecUtf8::from(secret))
+}
+
 pub trait ConfigOverrider {
     fn override_config(&self, config: &mut Configuration);
 }
@@ -190,6 +677,12 @@ impl TopLevelCommandOpts for TestEmailOpts {
     }
 }
 
+impl TopLevelCommandOpts for CreateUserOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
 impl ConfigOverrider for RunOpts {
     fn override_config(&self, config: &mut Configuration) {
         self.general_config.override_config(config);
@@ -209,11 +702,69 @@ impl ConfigOverrider for RunOpts {
         if let Some(url) = self.http_url.as_ref() {
             config.http_url = url.to_string();
         }
+
+        if self.regenerate_jwt_secret {
+            config.regenerate_jwt_secret = true;
+        }
         self.smtp_opts.override_config(config);
         self.ldaps_opts.override_config(config);
     }
 }
 
+impl ConfigOverrider for CreateUserOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+
+        if let Some(path) = self.server_key_file.as_ref() {
+            config.key_file = path.to_string();
+        }
+    }
+}
+
+impl TopLevelCommandOpts for CredentialsOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl ConfigOverrider for CredentialsOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+
+        if let Some(path) = self.server_key_file.as_ref() {
+            config.key_file = path.to_string();
+        }
+    }
+}
+
+impl TopLevelCommandOpts for OptimizeDatabaseOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl ConfigOverrider for OptimizeDatabaseOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+    }
+}
+
+impl TopLevelCommandOpts for RotateJwtKeyOpts {
+    fn general_config(&self) -> &GeneralConfigOpts {
+        &self.general_config
+    }
+}
+
+impl ConfigOverrider for RotateJwtKeyOpts {
+    fn override_config(&self, config: &mut Configuration) {
+        self.general_config.override_config(config);
+
+        if let Some(path) = self.server_key_file.as_ref() {
+            config.key_file = path.to_string();
+        }
+    }
+}
+
 impl ConfigOverrider for TestEmailOpts {
     fn override_config(&self, config: &mut Configuration) {
         self.general_config.override_config(config);
@@ -296,8 +847,113 @@ where
     if config.verbose {
         println!("Configuration: {:#?}", &config);
     }
+    for rule in &config.group_assignment_rules {
+        crate::infra::group_assignment::validate_rule(rule)
+            .map_err(|e| anyhow!("Invalid group assignment rule: {}", e))?;
+    }
+    for service_group in &config.ldap_service_account_groups {
+        if service_group.group.starts_with("lldap_") {
+            anyhow::bail!(
+                "Invalid LDAP service account group: `{}` already has its own permissions",
+                service_group.group
+            );
+        }
+    }
+    if let Some(group) = &config.everyone_group {
+        if group.trim().is_empty() || group.starts_with("lldap_") {
+            anyhow::bail!("Invalid everyone_group name: `{}`", group);
+        }
+    }
+    config
+        .get_user_search_columns()
+        .context("Invalid user_search_attributes")?;
+    config
+        .get_user_attribute_defaults()
+        .context("Invalid user_attribute_defaults")?;
+    if config.persisted_queries_only && config.persisted_queries_dir.is_none() {
+        anyhow::bail!("persisted_queries_only requires a persisted_queries_dir");
+    }
+    for (i, client) in config.oidc_clients.iter().enumerate() {
+        if client.redirect_uris.is_empty() {
+            anyhow::bail!("OIDC client `{}` has no redirect_uris", client.client_id);
+        }
+        for uri in &client.redirect_uris {
+            reqwest::Url::parse(uri).with_context(|| {
+                format!(
+                    "Invalid redirect_uri for OIDC client `{}`",
+                    client.client_id
+                )
+            })?;
+        }
+        if config.oidc_clients[..i]
+            .iter()
+            .any(|c| c.client_id == client.client_id)
+        {
+            anyhow::bail!("Duplicate OIDC client_id: `{}`", client.client_id);
+        }
+    }
+    if let Some(sso_login) = &config.sso_login {
+        for (name, url) in [
+            ("authorization_endpoint", &sso_login.authorization_endpoint),
+            ("token_endpoint", &sso_login.token_endpoint),
+        ] {
+            let url = reqwest::Url::parse(url)
+                .with_context(|| format!("Invalid sso_login.{}: `{}`", name, url))?;
+            // The ID tokens are trusted because they come from the token endpoint over TLS.
+            if url.scheme() != "https" {
+                anyhow::bail!("sso_login.{} must be an https URL", name);
+            }
+        }
+        if let Some(group) = sso_login
+            .default_groups
+            .iter()
+            .find(|group| group.starts_with("lldap_"))
+        {
+            anyhow::bail!(
+                "Cannot add the SSO users to the `{}` group automatically",
+                group
+            );
+        }
+    }
+    if config.proxy_auth.enabled {
+        if config.proxy_auth.trusted_proxies.is_empty() {
+            anyhow::bail!("proxy_auth requires at least one address in trusted_proxies");
+        }
+        actix_web::http::header::HeaderName::from_bytes(config.proxy_auth.header.as_bytes())
+            .with_context(|| {
+                format!("Invalid proxy_auth header: `{}`", config.proxy_auth.header)
+            })?;
+    }
+    if config.login_challenge.enabled && !(1..=32).contains(&config.login_challenge.difficulty) {
+        anyhow::bail!("login_challenge.difficulty must be between 1 and 32");
+    }
+    if config.min_password_length < MIN_PASSWORD_LENGTH {
+        anyhow::bail!(
+            "min_password_length cannot be lower than {}",
+            MIN_PASSWORD_LENGTH
+        );
+    }
+    if config.min_password_character_classes > CHARACTER_CLASSES {
+        anyhow::bail!(
+            "min_password_character_classes cannot be higher than {}",
+            CHARACTER_CLASSES
+        );
+    }
+    if let Some(path) = &config.banned_passwords_file {
+        config.banned_passwords = read_banned_passwords(path)
+            .with_context(|| format!("Could not read banned_passwords_file `{}`", path))?;
+    }
     config.server_setup = Some(get_server_setup(&config.key_file)?);
-    if config.jwt_secret == SecUtf8::from("secretjwtsecret") {
+    if config.jwt_secret.is_none() {
+        config.jwt_secret = Some(get_generated_jwt_secret(
+            &config.key_file,
+            config.regenerate_jwt_secret,
+        )?);
+    } else if config.regenerate_jwt_secret {
+        println!("WARNING: jwt_secret is set in the configuration, not regenerating it.");
+        config.regenerate_jwt_secret = false;
+    }
+    if config.get_jwt_secret() == &SecUtf8::from("secretjwtsecret") {
         println!("WARNING: Default JWT secret used! This is highly unsafe and can allow attackers to log in as admin.");
     }
     if config.ldap_user_pass == SecUtf8::from("password") {
@@ -0,0 +1,95 @@
+//! Export and import of the password material alone, to migrate the credentials of the users to
+//! another instance independently of the rest of the data.
+//!
+//! OPAQUE registrations can only be verified with the server key that created them, so the
+//! exported file records a fingerprint of the server key, and the import refuses to proceed with
+//! a different key.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    domain::{
+        error::DomainError,
+        sql_backend_handler::SqlBackendHandler,
+        sql_opaque_handler::{export_password_files, import_password_file},
+        types::UserId,
+    },
+    infra::configuration::write_to_readonly_file,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct ExportedCredential {
+    user_id: String,
+    /// The base64-encoded OPAQUE password file.
+    password_file: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct CredentialsFile {
+    /// SHA-256 of the public server key, in hexadecimal.
+    server_key_fingerprint: String,
+    credentials: Vec<ExportedCredential>,
+}
+
+fn get_server_key_fingerprint(handler: &SqlBackendHandler) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(handler.config.get_server_keys().public())
+    )
+}
+
+/// Writes the password files of all the users with a password, and returns how many there were.
+pub async fn export_credentials(handler: &SqlBackendHandler, path: &str) -> Result<usize> {
+    let credentials = export_password_files(handler)
+        .await?
+        .into_iter()
+        .map(|(user_id, password_file)| ExportedCredential {
+            user_id: user_id.into_string(),
+            password_file: base64::encode(password_file),
+        })
+        .collect::<Vec<_>>();
+    let count = credentials.len();
+    let file = CredentialsFile {
+        server_key_fingerprint: get_server_key_fingerprint(handler),
+        credentials,
+    };
+    let path = std::path::Path::new(path);
+    if path.exists() {
+        bail!("`{}` already exists", path.display());
+    }
+    // Anyone with the file and the server key can check password guesses offline.
+    write_to_readonly_file(path, serde_json::to_string_pretty(&file)?.as_bytes())
+        .with_context(|| format!("while writing `{}`", path.display()))?;
+    Ok(count)
+}
+
+/// Sets the passwords of the existing users from an exported file, and returns how many were
+/// imported. Users that don't exist in this instance are skipped with a warning.
+pub async fn import_credentials(handler: &SqlBackendHandler, path: &str) -> Result<usize> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("while reading `{}`", path))?;
+    let file: CredentialsFile =
+        serde_json::from_str(&contents).with_context(|| format!("while parsing `{}`", path))?;
+    if file.server_key_fingerprint != get_server_key_fingerprint(handler) {
+        bail!(
+            "The credentials were exported with a different server key: copy the server key \
+             file (key_file) of the original instance to import them"
+        );
+    }
+    let mut count = 0;
+    for credential in file.credentials {
+        let user_id = UserId::new(&credential.user_id);
+        let password_file = base64::decode(&credential.password_file)
+            .with_context(|| format!("Invalid password file for `{}`", user_id))?;
+        match import_password_file(handler, &user_id, &password_file).await {
+            Ok(()) => count += 1,
+            Err(DomainError::EntityNotFound(_)) => {
+                println!("WARNING: Skipping unknown user `{}`", user_id)
+            }
+            Err(e) => return Err(e).with_context(|| format!("while importing `{}`", user_id)),
+        }
+    }
+    Ok(count)
+}
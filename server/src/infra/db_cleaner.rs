@@ -1,5 +1,8 @@
 use crate::domain::{
-    model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
+    model::{
+        self, AuditLogColumn, JwtRefreshStorageColumn, JwtStorageColumn, MagicLoginTokensColumn,
+        MembershipRequestColumn, PasswordResetTokensColumn,
+    },
     sql_tables::DbConnection,
 };
 use actix::prelude::{Actor, AsyncContext, Context};
@@ -12,6 +15,8 @@ use tracing::{error, info, instrument};
 pub struct Scheduler {
     schedule: Schedule,
     sql_pool: DbConnection,
+    /// How long the audit log entries are kept, forever if `None`.
+    audit_log_retention: Option<chrono::Duration>,
 }
 
 // Provide Actor implementation for our actor
@@ -32,13 +37,24 @@ impl Actor for Scheduler {
 }
 
 impl Scheduler {
-    pub fn new(cron_expression: &str, sql_pool: DbConnection) -> Self {
+    pub fn new(
+        cron_expression: &str,
+        sql_pool: DbConnection,
+        audit_log_retention: Option<chrono::Duration>,
+    ) -> Self {
         let schedule = Schedule::from_str(cron_expression).unwrap();
-        Self { schedule, sql_pool }
+        Self {
+            schedule,
+            sql_pool,
+            audit_log_retention,
+        }
     }
 
     fn schedule_task(&self, ctx: &mut Context<Self>) {
-        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(self.sql_pool.clone()));
+        let future = actix::fut::wrap_future::<_, Self>(Self::cleanup_db(
+            self.sql_pool.clone(),
+            self.audit_log_retention,
+        ));
         ctx.spawn(future);
 
         ctx.run_later(self.duration_until_next(), move |this, ctx| {
@@ -47,7 +63,7 @@ impl Scheduler {
     }
 
     #[instrument(skip_all)]
-    async fn cleanup_db(sql_pool: DbConnection) {
+    async fn cleanup_db(sql_pool: DbConnection, audit_log_retention: Option<chrono::Duration>) {
         info!("Cleaning DB");
         if let Err(e) = model::JwtRefreshStorage::delete_many()
             .filter(JwtRefreshStorageColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
@@ -70,6 +86,29 @@ impl Scheduler {
         {
             error!("DB error while cleaning up password reset tokens: {}", e);
         };
+        if let Err(e) = model::MagicLoginTokens::delete_many()
+            .filter(MagicLoginTokensColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up magic login tokens: {}", e);
+        };
+        if let Err(e) = model::MembershipRequest::delete_many()
+            .filter(MembershipRequestColumn::ExpiryDate.lt(chrono::Utc::now().naive_utc()))
+            .exec(&sql_pool)
+            .await
+        {
+            error!("DB error while cleaning up membership requests: {}", e);
+        };
+        if let Some(retention) = audit_log_retention {
+            if let Err(e) = model::AuditLog::delete_many()
+                .filter(AuditLogColumn::Date.lt((chrono::Utc::now() - retention).naive_utc()))
+                .exec(&sql_pool)
+                .await
+            {
+                error!("DB error while cleaning up the audit log: {}", e);
+            };
+        }
         info!("DB cleaned!");
     }
 
@@ -1,33 +1,144 @@
 use crate::{
-    domain::handler::BackendHandler,
+    domain::{
+        handler::{AuditEvent, BackendHandler, GroupRequestFilter},
+        types::{DirectoryChange, GroupId, PasswordExpiry, UserColumn},
+    },
     infra::{
-        auth_service::{check_if_token_is_valid, ValidationResults},
+        auth_service::{check_if_token_or_api_token_is_valid, SessionToken, ValidationResults},
         cli::ExportGraphQLSchemaOpts,
+        configuration::{GroupAssignmentRule, MailOptions, UserDetailsSection},
+        stats,
         tcp_server::AppState,
     },
 };
-use actix_web::{web, Error, HttpResponse};
+use actix_web::{http::Method, web, Error, HttpResponse};
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use juniper::{EmptySubscription, RootNode};
-use juniper_actix::{graphiql_handler, graphql_handler, playground_handler};
+use juniper::{http::GraphQLRequest, FieldResult, InputValue, RootNode, Variables};
+use juniper_actix::{
+    graphiql_handler, graphql_handler, playground_handler, subscriptions::subscriptions_handler,
+};
+use juniper_graphql_ws::ConnectionConfig;
+use lldap_auth::password_strength::PasswordPolicy;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+use tracing::warn;
 
-use super::{mutation::Mutation, query::Query};
+use super::{
+    mutation::{GroupLimit, Mutation},
+    persisted_queries::{PersistedQueryError, RequestExtensions},
+    query::Query,
+    subscription::Subscription,
+};
+
+/// The interval of the keep-alive messages of the subscriptions.
+const SUBSCRIPTION_KEEP_ALIVE: Duration = Duration::from_secs(15);
 
 pub struct Context<Handler: BackendHandler> {
     pub handler: Box<Handler>,
     pub validation_result: ValidationResults,
+    /// The LDAP base DN, in lowercase.
+    pub ldap_base_dn: String,
+    pub password_policy: Arc<PasswordPolicy>,
+    /// `None` if the passwords don't expire.
+    pub password_expiry: Option<PasswordExpiry>,
+    pub group_assignment_rules: Vec<GroupAssignmentRule>,
+    pub user_details_layout: Vec<UserDetailsSection>,
+    pub server_url: String,
+    pub enable_magic_login_links: bool,
+    pub user_search_columns: Vec<UserColumn>,
+    /// The name of the group containing all the users, managed by the server.
+    pub everyone_group: Option<String>,
+    /// Whether adding a user to a group they're already in is an error.
+    pub strict_group_membership_adds: bool,
+    pub group_limit: Option<GroupLimit>,
+    /// The names of the groups whose new members have to be approved, see `membership_requests`.
+    pub membership_approval_groups: Vec<String>,
+    /// For the notifications of the membership requests.
+    pub mail_options: MailOptions,
+    /// The language of the users who didn't set a preferred language.
+    pub default_language: String,
+    /// Where the JWTs of the revoked sessions are added, to reject them immediately.
+    pub jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
+    /// The token of a subscription, checked again for each change. `None` for the single
+    /// requests.
+    pub session_token: Option<SessionToken>,
+    /// The address of the client, for the audit log.
+    pub ip_address: Option<String>,
+    /// The changes of the directory, for the subscriptions.
+    pub changes: broadcast::Sender<DirectoryChange>,
 }
 
 impl<Handler: BackendHandler> juniper::Context for Context<Handler> {}
 
-type Schema<Handler> =
-    RootNode<'static, Query<Handler>, Mutation<Handler>, EmptySubscription<Context<Handler>>>;
+impl<Handler: BackendHandler> Context<Handler> {
+    /// Whether the logged in user manages the group: they can see it and change its members
+    /// without being an admin. The API tokens don't act on behalf of their creator.
+    pub async fn manages_group(&self, group_id: GroupId) -> FieldResult<bool> {
+        if self.validation_result.is_api_token() {
+            return Ok(false);
+        }
+        Ok(self
+            .handler
+            .list_group_managers(group_id)
+            .await?
+            .contains(&self.validation_result.user))
+    }
+
+    /// Whether the new members of the group have to be approved, instead of being added directly.
+    pub fn requires_approval(&self, group_name: &str) -> bool {
+        self.membership_approval_groups
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(group_name))
+    }
+
+    /// Whether the logged in user manages any group, and can list the users to pick the members.
+    pub async fn manages_any_group(&self) -> FieldResult<bool> {
+        if self.validation_result.is_api_token() {
+            return Ok(false);
+        }
+        Ok(!self
+            .handler
+            .list_groups(Some(GroupRequestFilter::Manager(
+                self.validation_result.user.clone(),
+            )))
+            .await?
+            .is_empty())
+    }
 
-fn schema<Handler: BackendHandler + Sync>() -> Schema<Handler> {
+    /// Records a successful mutation in the audit log, as done by the logged in user. A failure
+    /// to record it is only logged: the mutation itself is done.
+    pub async fn audit(&self, action: &str, target: Option<String>, details: Option<String>) {
+        let details = match (details, self.validation_result.is_api_token()) {
+            (details, false) => details,
+            (None, true) => Some("With an API token".to_owned()),
+            (Some(details), true) => Some(format!("{}, with an API token", details)),
+        };
+        let event = AuditEvent {
+            actor: Some(self.validation_result.user.clone()),
+            action: action.to_owned(),
+            target,
+            ip_address: self.ip_address.clone(),
+            success: true,
+            details,
+        };
+        if let Err(e) = self.handler.record_audit_event(event).await {
+            warn!("Could not record {} in the audit log: {}", action, e);
+        }
+    }
+}
+
+type Schema<Handler> = RootNode<'static, Query<Handler>, Mutation<Handler>, Subscription<Handler>>;
+
+fn schema<Handler: BackendHandler + Sync + 'static>() -> Schema<Handler> {
     Schema::new(
         Query::<Handler>::new(),
         Mutation::<Handler>::new(),
-        EmptySubscription::<Context<Handler>>::new(),
+        Subscription::<Handler>::new(),
     )
 }
 
@@ -58,19 +169,143 @@ async fn playground_route() -> Result<HttpResponse, Error> {
     playground_handler("/api/graphql", None).await
 }
 
-async fn graphql_route<Handler: BackendHandler + Sync>(
+/// A GraphQL request, where the query can be replaced by its hash in the extensions.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PersistedGraphQLRequest {
+    query: Option<String>,
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+    extensions: Option<RequestExtensions>,
+}
+
+fn persisted_query_error_response(error: PersistedQueryError) -> HttpResponse {
+    let body = match error {
+        PersistedQueryError::NotFound => serde_json::json!({
+            "errors": [{
+                "message": error.to_string(),
+                "extensions": { "code": "PERSISTED_QUERY_NOT_FOUND" },
+            }]
+        }),
+        _ => serde_json::json!({ "errors": [{ "message": error.to_string() }] }),
+    };
+    match error {
+        PersistedQueryError::NotFound => HttpResponse::Ok().json(body),
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+fn new_context<Handler: BackendHandler>(
+    data: &AppState<Handler>,
+    validation_result: ValidationResults,
+    ip_address: Option<String>,
+) -> Context<Handler> {
+    Context::<Handler> {
+        handler: Box::new(data.backend_handler.clone()),
+        validation_result,
+        ldap_base_dn: data.ldap_base_dn.clone(),
+        password_policy: data.password_policy.clone(),
+        password_expiry: data.password_expiry,
+        group_assignment_rules: data.group_assignment_rules.clone(),
+        user_details_layout: data.user_details_layout.clone(),
+        server_url: data.server_url.clone(),
+        enable_magic_login_links: data.enable_magic_login_links,
+        user_search_columns: data.user_search_columns.clone(),
+        everyone_group: data.everyone_group.clone(),
+        strict_group_membership_adds: data.strict_group_membership_adds,
+        group_limit: data.group_limit,
+        membership_approval_groups: data.membership_approval_groups.clone(),
+        mail_options: data.mail_options.clone(),
+        default_language: data.default_language.clone(),
+        jwt_blacklist: data.jwt_blacklist.clone(),
+        session_token: None,
+        ip_address,
+        changes: data.changes.clone(),
+    }
+}
+
+fn get_ip_address(req: &actix_web::HttpRequest) -> Option<String> {
+    req.connection_info()
+        .realip_remote_addr()
+        .map(str::to_owned)
+}
+
+async fn graphql_route<Handler: BackendHandler + Sync + 'static>(
     req: actix_web::HttpRequest,
     mut payload: actix_web::web::Payload,
     data: web::Data<AppState<Handler>>,
 ) -> Result<HttpResponse, Error> {
     use actix_web::FromRequest;
     let bearer = BearerAuth::from_request(&req, &mut payload.0).await?;
-    let validation_result = check_if_token_is_valid(&data, bearer.token())?;
-    let context = Context::<Handler> {
-        handler: Box::new(data.backend_handler.clone()),
-        validation_result,
+    let validation_result = check_if_token_or_api_token_is_valid(&data, bearer.token()).await?;
+    stats::record_graphql_request();
+    let context = new_context(&data, validation_result, get_ip_address(&req));
+    if req.method() != Method::POST {
+        if data.persisted_queries.is_persisted_only() {
+            return Ok(HttpResponse::BadRequest().body("Only persisted queries are allowed"));
+        }
+        return graphql_handler(&schema(), &context, req, payload).await;
+    }
+    let body = web::Bytes::from_request(&req, &mut payload.0).await?;
+    let request = serde_json::from_slice::<PersistedGraphQLRequest>(&body)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let query = match data
+        .persisted_queries
+        .resolve(request.query, request.extensions.as_ref())
+    {
+        Ok(query) => query,
+        Err(e) => return Ok(persisted_query_error_response(e)),
     };
-    graphql_handler(&schema(), &context, req, payload).await
+    let response = GraphQLRequest::new(query, request.operation_name, request.variables)
+        .execute(&schema(), &context)
+        .await;
+    Ok(if response.is_ok() {
+        HttpResponse::Ok().json(response)
+    } else {
+        HttpResponse::BadRequest().json(response)
+    })
+}
+
+/// The subscriptions, over a WebSocket with the `graphql-ws` protocol. The web UI is authenticated
+/// by its cookie, like for the other requests; the other clients can also send their token as
+/// `Authorization` in the payload of `connection_init`, since the browsers can't set the headers
+/// of a WebSocket.
+async fn graphql_subscriptions_route<Handler: BackendHandler + Sync + 'static>(
+    req: actix_web::HttpRequest,
+    mut payload: actix_web::web::Payload,
+    data: web::Data<AppState<Handler>>,
+) -> Result<HttpResponse, Error> {
+    use actix_web::FromRequest;
+    let ip_address = get_ip_address(&req);
+    let schema = Arc::new(schema());
+    if req
+        .headers()
+        .contains_key(actix_web::http::header::AUTHORIZATION)
+    {
+        let bearer = BearerAuth::from_request(&req, &mut payload.0).await?;
+        let validation_result = check_if_token_or_api_token_is_valid(&data, bearer.token()).await?;
+        let mut context = new_context(&data, validation_result, ip_address);
+        context.session_token = SessionToken::from_token(&data, bearer.token());
+        let config =
+            ConnectionConfig::new(context).with_keep_alive_interval(SUBSCRIPTION_KEEP_ALIVE);
+        return subscriptions_handler(req, payload, schema, config).await;
+    }
+    let init = move |params: Variables| async move {
+        let token = params
+            .get("Authorization")
+            .and_then(InputValue::as_string_value)
+            .map(|token| token.strip_prefix("Bearer ").unwrap_or(token).to_owned())
+            .ok_or_else(|| "Missing Authorization in the connection parameters".to_owned())?;
+        let validation_result = check_if_token_or_api_token_is_valid(&data, &token)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut context = new_context(&data, validation_result, ip_address);
+        context.session_token = SessionToken::from_token(&data, &token);
+        Ok::<_, String>(
+            ConnectionConfig::new(context).with_keep_alive_interval(SUBSCRIPTION_KEEP_ALIVE),
+        )
+    };
+    subscriptions_handler(req, payload, schema, init).await
 }
 
 pub fn configure_endpoint<Backend>(cfg: &mut web::ServiceConfig)
@@ -96,6 +331,9 @@ where
             .route(web::post().to(graphql_route::<Backend>))
             .route(web::get().to(graphql_route::<Backend>)),
     );
+    cfg.service(
+        web::resource("/graphql/ws").route(web::get().to(graphql_subscriptions_route::<Backend>)),
+    );
     cfg.service(web::resource("/graphql/playground").route(web::get().to(playground_route)));
     cfg.service(web::resource("/graphql/graphiql").route(web::get().to(graphiql_route)));
 }
@@ -1,3 +1,5 @@
 pub mod api;
 pub mod mutation;
+pub mod persisted_queries;
 pub mod query;
+pub mod subscription;
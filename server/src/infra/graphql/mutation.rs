@@ -1,12 +1,42 @@
-use crate::domain::{
-    handler::{BackendHandler, CreateUserRequest, UpdateGroupRequest, UpdateUserRequest},
-    types::{GroupId, JpegPhoto, UserId},
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{
+            BackendHandler, CreateUserRequest,
+            DatabaseOptimizationReport as DomainDatabaseOptimizationReport, GroupRequestFilter,
+            MembershipRepairReport as DomainMembershipRepairReport, MergeUsersRequest,
+            UpdateGroupRequest, UpdateUserRequest, UserMergeReport as DomainUserMergeReport,
+            UserRequestFilter,
+        },
+        ldap::{
+            filter::parse_user_filter, group::is_builtin_group_attribute,
+            user::is_builtin_user_attribute,
+        },
+        totp,
+        types::{
+            check_ssh_public_key, ApiTokenScope, AttributeSchema, AttributeValue, GroupDetails,
+            GroupId, JpegPhoto, MembershipRequest, UserColumn, UserId, WebhookEvent,
+        },
+    },
+    infra::{auth_service::set_account_enabled, group_assignment::apply_group_assignment_rules},
 };
 use anyhow::Context as AnyhowContext;
 use juniper::{graphql_object, FieldResult, GraphQLInputObject, GraphQLObject};
-use tracing::{debug, debug_span, Instrument};
+use tracing::{debug, debug_span, info, warn, Instrument};
 
-use super::api::Context;
+use super::{
+    api::Context,
+    query::{ApiToken, AttributeType, Webhook},
+};
+
+/// Magic login links bypass the password, so they shouldn't linger for long.
+const MAX_MAGIC_LOGIN_LINK_VALIDITY_MINUTES: i32 = 24 * 60;
+
+/// Like the temporary passwords, the reset links created by the admins are shared out of band.
+const MAX_PASSWORD_RESET_LINK_VALIDITY_HOURS: i32 = 7 * 24;
+
+/// Temporary passwords are shared out of band with new users, who may not log in right away.
+const MAX_TEMPORARY_PASSWORD_VALIDITY_HOURS: i32 = 7 * 24;
 
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL mutation type.
@@ -32,6 +62,17 @@ pub struct CreateUserInput {
     last_name: Option<String>,
     // Base64 encoded JpegPhoto.
     avatar: Option<String>,
+    preferred_language: Option<String>,
+    /// Additional email addresses, on top of the primary one.
+    email_aliases: Option<Vec<String>>,
+    /// The public SSH keys, in the OpenSSH format.
+    ssh_public_keys: Option<Vec<String>>,
+    uid_number: Option<i32>,
+    gid_number: Option<i32>,
+    home_directory: Option<String>,
+    login_shell: Option<String>,
+    /// The values of the custom attributes.
+    attributes: Option<Vec<AttributeValueInput>>,
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -44,6 +85,73 @@ pub struct UpdateUserInput {
     last_name: Option<String>,
     // Base64 encoded JpegPhoto.
     avatar: Option<String>,
+    preferred_language: Option<String>,
+    /// Replaces all the additional email addresses of the user.
+    email_aliases: Option<Vec<String>>,
+    /// Replaces all the public SSH keys of the user.
+    ssh_public_keys: Option<Vec<String>>,
+    /// The POSIX attributes can only be changed by an admin.
+    uid_number: Option<i32>,
+    gid_number: Option<i32>,
+    home_directory: Option<String>,
+    login_shell: Option<String>,
+    /// Whether to email the user when they log in to the web UI from a new device.
+    login_notifications: Option<bool>,
+    /// Replaces the values of the listed custom attributes; no values remove the attribute.
+    /// Users can only change the editable ones.
+    attributes: Option<Vec<AttributeValueInput>>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// The values of a custom user or group attribute, in the format of its type.
+pub struct AttributeValueInput {
+    name: String,
+    values: Vec<String>,
+}
+
+fn to_attribute_values(attributes: Option<Vec<AttributeValueInput>>) -> Vec<AttributeValue> {
+    attributes
+        .unwrap_or_default()
+        .into_iter()
+        .map(|attribute| AttributeValue {
+            name: attribute.name,
+            values: attribute.values,
+        })
+        .collect()
+}
+
+/// Checks the name and the LDAP name of a new custom attribute, and returns them trimmed. The LDAP
+/// name defaults to the name.
+fn check_attribute_names(
+    name: String,
+    ldap_name: Option<String>,
+    is_builtin: fn(&str) -> bool,
+) -> FieldResult<(String, String)> {
+    let name = name.trim().to_owned();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("The attribute name can only contain letters, digits, '_' and '-'".into());
+    }
+    let ldap_name = ldap_name.map_or_else(|| name.clone(), |n| n.trim().to_owned());
+    if !ldap_name.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !ldap_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(format!(
+            "Invalid LDAP name `{}`: it must start with a letter, followed by letters, \
+             digits and '-'",
+            ldap_name
+        )
+        .into());
+    }
+    if is_builtin(&ldap_name) {
+        return Err(format!("`{}` is a built-in LDAP attribute", ldap_name).into());
+    }
+    Ok((name, ldap_name))
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -51,6 +159,12 @@ pub struct UpdateUserInput {
 pub struct UpdateGroupInput {
     id: i32,
     display_name: Option<String>,
+    gid_number: Option<i32>,
+    /// An empty string removes the email address.
+    email: Option<String>,
+    /// Replaces the values of the listed custom attributes. The group managers can change the
+    /// editable ones.
+    attributes: Option<Vec<AttributeValueInput>>,
 }
 
 #[derive(PartialEq, Eq, Debug, GraphQLObject)]
@@ -64,6 +178,753 @@ impl Success {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+pub struct MembershipRequestResult {
+    id: i32,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A pending TOTP enrollment, to add to an authenticator app.
+pub struct TotpEnrollment {
+    /// The secret in base32, for the apps that can't scan the QR code.
+    secret: String,
+    /// The `otpauth://` URI of the secret.
+    uri: String,
+    /// The QR code of the URI, as a `data:` URI of an SVG image.
+    qr_code: String,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The outcome of deleting one group as part of a bulk deletion.
+pub struct DeleteGroupResult {
+    id: i32,
+    ok: bool,
+    error: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The outcome for one user of a bulk mutation: `createUsers`, `deleteUsers` or
+/// `addUsersToGroup`. A created user can have an error with `ok` if the group assignment rules
+/// could not be applied.
+pub struct BulkUserResult {
+    id: String,
+    ok: bool,
+    error: Option<String>,
+}
+
+/// The results of a bulk mutation, from the error of each user, if any. Without `best_effort`,
+/// nothing was done as soon as one user failed, which the other users report.
+fn to_bulk_results(
+    ids: Vec<String>,
+    errors: Vec<Option<String>>,
+    best_effort: bool,
+    action: &str,
+) -> Vec<BulkUserResult> {
+    let aborted = !best_effort && errors.iter().any(Option::is_some);
+    ids.into_iter()
+        .zip(errors)
+        .map(|(id, error)| {
+            let error = match error {
+                None if aborted => Some(format!(
+                    "Not {}: another user could not be {}",
+                    action, action
+                )),
+                error => error,
+            };
+            BulkUserResult {
+                id,
+                ok: error.is_none(),
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Fills in the errors of the users that were sent to the backend, in order.
+fn merge_backend_errors(
+    errors: &mut [Option<String>],
+    results: Vec<std::result::Result<(), DomainError>>,
+) {
+    let pending = errors.iter_mut().filter(|e| e.is_none());
+    for (error, result) in pending.zip(results) {
+        *error = result.err().map(|e| e.to_string());
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// What was fixed by a membership repair.
+pub struct MembershipRepairReport {
+    /// Memberships pointing to a user or a group that doesn't exist.
+    dangling_memberships_removed: i32,
+    /// Explicit memberships of dynamic groups, whose members are defined by their filter.
+    dynamic_group_memberships_removed: i32,
+}
+
+impl From<DomainMembershipRepairReport> for MembershipRepairReport {
+    fn from(report: DomainMembershipRepairReport) -> Self {
+        let to_int = |value: u64| i32::try_from(value).unwrap_or(i32::MAX);
+        Self {
+            dangling_memberships_removed: to_int(report.dangling_memberships_removed),
+            dynamic_group_memberships_removed: to_int(report.dynamic_group_memberships_removed),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, GraphQLObject)]
+/// The size of the database around an optimization.
+pub struct DatabaseOptimizationReport {
+    /// Size of the database on disk before the optimization, in bytes, if it can be reported.
+    database_size_before: Option<f64>,
+    /// Size of the database on disk after the optimization, in bytes.
+    database_size_after: Option<f64>,
+}
+
+impl From<DomainDatabaseOptimizationReport> for DatabaseOptimizationReport {
+    fn from(report: DomainDatabaseOptimizationReport) -> Self {
+        Self {
+            database_size_before: report.size_before.map(|size| size as f64),
+            database_size_after: report.size_after.map(|size| size as f64),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// What was moved to the target of a user merge.
+pub struct UserMergeReport {
+    /// The groups the target was added to, excluding those it was already a member of.
+    added_group_ids: Vec<i32>,
+    /// The LDAP names of the attributes copied from the source.
+    merged_attributes: Vec<String>,
+    /// The emails of the source that became aliases of the target.
+    moved_emails: Vec<String>,
+}
+
+impl From<DomainUserMergeReport> for UserMergeReport {
+    fn from(report: DomainUserMergeReport) -> Self {
+        Self {
+            added_group_ids: report.added_groups.into_iter().map(|id| id.0).collect(),
+            merged_attributes: report.merged_attributes,
+            moved_emails: report.moved_emails,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A new API token, with its secret. The secret can't be retrieved later.
+pub struct CreatedApiToken {
+    token: ApiToken,
+    secret: String,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A new webhook, with the secret of its signatures. The secret can't be retrieved later.
+pub struct CreatedWebhook {
+    webhook: Webhook,
+    secret: String,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A problem with one of the fields of an input.
+pub struct ValidationError {
+    field: String,
+    message: String,
+}
+
+impl ValidationError {
+    fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Checks the SSH public keys, ignoring the empty ones that are dropped anyway.
+fn check_ssh_public_keys(keys: Option<&[String]>) -> anyhow::Result<()> {
+    for key in keys.into_iter().flatten() {
+        if !key.trim().is_empty() {
+            check_ssh_public_key(key)
+                .with_context(|| format!("Invalid SSH public key `{}`", key))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_avatar(avatar: Option<&str>) -> anyhow::Result<Option<JpegPhoto>> {
+    avatar
+        .map(base64::decode)
+        .transpose()
+        .context("Invalid base64 image")?
+        .map(JpegPhoto::try_from)
+        .transpose()
+        .context("Provided image is not a valid JPEG")
+}
+
+pub(crate) fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && !domain.is_empty() && !domain.contains('@'),
+        None => false,
+    }
+}
+
+/// The user other than `user_id` who already has the UID number, if any.
+async fn find_uid_number_owner<Handler: BackendHandler>(
+    handler: &Handler,
+    user_id: &UserId,
+    uid_number: i32,
+) -> FieldResult<Option<UserId>> {
+    Ok(handler
+        .list_users(
+            Some(UserRequestFilter::Equality(
+                UserColumn::UidNumber,
+                uid_number.to_string(),
+            )),
+            false,
+        )
+        .await?
+        .into_iter()
+        .map(|u| u.user.user_id)
+        .find(|owner| owner != user_id))
+}
+
+/// The group other than `group_id` which already has the GID number, if any.
+async fn find_gid_number_owner<Handler: BackendHandler>(
+    handler: &Handler,
+    group_id: GroupId,
+    gid_number: i32,
+) -> FieldResult<Option<String>> {
+    Ok(handler
+        .list_groups(Some(GroupRequestFilter::GidNumber(gid_number)))
+        .await?
+        .into_iter()
+        .find(|g| g.id != group_id)
+        .map(|g| g.display_name))
+}
+
+/// Runs all the checks needed before creating the user, without modifying anything. Only the admins
+/// can set the attributes that aren't editable.
+async fn validate_create_user_input<Handler: BackendHandler>(
+    handler: &Handler,
+    user: &CreateUserInput,
+    is_admin: bool,
+) -> FieldResult<Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    if user.id.is_empty() {
+        errors.push(ValidationError::new("id", "The user ID cannot be empty"));
+    } else if user.id.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        errors.push(ValidationError::new(
+            "id",
+            "The user ID cannot contain whitespace or control characters",
+        ));
+    } else {
+        match handler.get_user_details(&UserId::new(&user.id)).await {
+            Ok(_) => errors.push(ValidationError::new(
+                "id",
+                "A user with this ID already exists",
+            )),
+            Err(DomainError::EntityNotFound(_)) => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let email_aliases = user.email_aliases.as_deref().unwrap_or_default();
+    let is_email_valid = is_valid_email(&user.email);
+    if !is_email_valid {
+        errors.push(ValidationError::new("email", "Invalid email address"));
+    }
+    let are_aliases_valid = email_aliases.iter().all(|e| is_valid_email(e));
+    if !are_aliases_valid {
+        errors.push(ValidationError::new(
+            "emailAliases",
+            "Invalid email address",
+        ));
+    }
+    if is_email_valid && are_aliases_valid {
+        match handler
+            .check_emails_available(&UserId::new(&user.id), &user.email, email_aliases)
+            .await
+        {
+            Ok(()) => (),
+            Err(DomainError::EntityAlreadyExists(message)) => {
+                errors.push(ValidationError::new("email", &message))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    // The GID number is the primary group of the user, which is usually shared.
+    if let Some(uid_number) = user.uid_number {
+        if let Some(owner) =
+            find_uid_number_owner(handler, &UserId::new(&user.id), uid_number).await?
+        {
+            errors.push(ValidationError::new(
+                "uidNumber",
+                &format!("The UID number is already used by '{}'", owner),
+            ));
+        }
+    }
+    if let Err(e) = parse_avatar(user.avatar.as_deref()) {
+        errors.push(ValidationError::new("avatar", &e.to_string()));
+    }
+    if let Err(e) = check_ssh_public_keys(user.ssh_public_keys.as_deref()) {
+        errors.push(ValidationError::new("sshPublicKeys", &format!("{:#}", e)));
+    }
+    if let Some(language) = &user.preferred_language {
+        if language.len() > 35 {
+            errors.push(ValidationError::new(
+                "preferredLanguage",
+                "The language tag is too long",
+            ));
+        }
+    }
+    if let Some(attributes) = &user.attributes {
+        let schema = handler.list_user_attribute_schema().await?;
+        errors.extend(
+            check_attribute_values(&schema, attributes, is_admin)
+                .into_iter()
+                .map(|message| ValidationError::new("attributes", &message)),
+        );
+    }
+    Ok(errors)
+}
+
+/// Checks the custom attributes against their schema: each must exist, be set at most once, have a
+/// single value unless it's a list, and have values of its type.
+fn check_attribute_values(
+    schema: &[AttributeSchema],
+    attributes: &[AttributeValueInput],
+    is_admin: bool,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut seen_names = std::collections::HashSet::new();
+    for attribute in attributes {
+        let attribute_schema = match schema.iter().find(|s| s.name == attribute.name) {
+            Some(s) => s,
+            None => {
+                errors.push(format!("Unknown attribute {}", attribute.name));
+                continue;
+            }
+        };
+        if !seen_names.insert(&attribute.name) {
+            errors.push(format!(
+                "The attribute {} is set several times",
+                attribute.name
+            ));
+        }
+        if !is_admin && !attribute_schema.is_editable {
+            errors.push(format!(
+                "Only admins can set the attribute {}",
+                attribute.name
+            ));
+        }
+        if !attribute_schema.is_list && attribute.values.len() > 1 {
+            errors.push(format!(
+                "The attribute {} is not a list and takes a single value",
+                attribute.name
+            ));
+        }
+        errors.extend(attribute.values.iter().filter_map(|value| {
+            attribute_schema
+                .attribute_type
+                .normalize_value(value)
+                .err()
+                .map(|e| format!("Attribute {}: {}", attribute.name, e))
+        }));
+    }
+    errors
+}
+
+fn format_validation_errors(errors: Vec<ValidationError>) -> String {
+    errors
+        .into_iter()
+        .map(|e| format!("{}: {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Expects a validated input.
+fn to_create_user_request(user: CreateUserInput) -> FieldResult<CreateUserRequest> {
+    let avatar = parse_avatar(user.avatar.as_deref())?;
+    Ok(CreateUserRequest {
+        user_id: UserId::new(&user.id),
+        email: user.email,
+        display_name: user.display_name,
+        first_name: user.first_name,
+        last_name: user.last_name,
+        avatar,
+        preferred_language: user.preferred_language,
+        email_aliases: user.email_aliases.unwrap_or_default(),
+        ssh_public_keys: user.ssh_public_keys.unwrap_or_default(),
+        uid_number: user.uid_number,
+        gid_number: user.gid_number,
+        home_directory: user.home_directory,
+        login_shell: user.login_shell,
+        attributes: to_attribute_values(user.attributes),
+    })
+}
+
+async fn set_group_archived<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group_id: i32,
+    archived: bool,
+) -> FieldResult<Success> {
+    debug!(?group_id, archived);
+    if !context.validation_result.can_manage_groups() {
+        debug!("Unauthorized");
+        return Err("Unauthorized group update".into());
+    }
+    if group_id == 1 {
+        debug!("Cannot archive admin group");
+        return Err("Cannot archive admin group".into());
+    }
+    check_not_everyone_group(context, group_id).await?;
+    context
+        .handler
+        .update_group(UpdateGroupRequest {
+            group_id: GroupId(group_id),
+            display_name: None,
+            archived: Some(archived),
+            gid_number: None,
+            email: None,
+            attributes: Vec::new(),
+        })
+        .await?;
+    Ok(Success::new())
+}
+
+/// The API tokens can't modify the admins, who could otherwise be taken over, e.g. by changing
+/// their email address before a password reset.
+async fn check_not_admin_for_api_token<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    user_id: &UserId,
+) -> FieldResult<()> {
+    if !context.validation_result.is_api_token() {
+        return Ok(());
+    }
+    let groups = context.handler.get_user_groups(user_id).await?;
+    if groups.iter().any(|g| g.display_name == "lldap_admin") {
+        debug!("API tokens cannot modify admins");
+        return Err("API tokens cannot modify admins".into());
+    }
+    Ok(())
+}
+
+/// The `lldap_` groups grant permissions, so only the admins can name a group that way: an API
+/// token could otherwise create or rename a group into one of them.
+fn check_not_lldap_group_name<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    name: &str,
+) -> FieldResult<()> {
+    if !context.validation_result.is_admin() && is_lldap_group(name) {
+        debug!("Unauthorized lldap_ group name");
+        return Err("Only admins can name a group lldap_*".into());
+    }
+    Ok(())
+}
+
+/// Whether the group is one of the `lldap_` groups, which grant permissions.
+fn is_lldap_group(name: &str) -> bool {
+    name.to_ascii_lowercase().starts_with("lldap_")
+}
+
+/// Only the admins can change the members of the `lldap_` groups, or delete them: the API tokens
+/// and the group managers could otherwise grant admin rights, or take them from the admins.
+fn check_can_change_lldap_group<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group_name: &str,
+    message: &str,
+) -> FieldResult<()> {
+    if !context.validation_result.is_admin() && is_lldap_group(group_name) {
+        debug!(?group_name, "Unauthorized lldap_ group modification");
+        return Err(message.into());
+    }
+    Ok(())
+}
+
+/// In the groups that require an approval, the managers can only ask for new members.
+fn check_no_approval_required<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group_name: &str,
+) -> FieldResult<()> {
+    if !context.validation_result.can_manage_groups() && context.requires_approval(group_name) {
+        debug!(?group_name, "Approval required");
+        return Err(
+            "The new members of this group have to be approved, use requestGroupMembership".into(),
+        );
+    }
+    Ok(())
+}
+
+/// Emails the managers of the group, or the admins if it has none, about a new membership
+/// request. The emails are sent in the background, and the failures are only logged: the request
+/// is recorded anyway.
+async fn notify_membership_request<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group: &GroupDetails,
+    request: &MembershipRequest,
+) {
+    if !context.mail_options.enable_membership_request_notifications {
+        return;
+    }
+    let approvers = match context.handler.list_group_managers(group.group_id).await {
+        Ok(managers) if !managers.is_empty() => Ok(managers),
+        Ok(_) => context
+            .handler
+            .list_users(
+                Some(UserRequestFilter::MemberOf("lldap_admin".to_owned())),
+                false,
+            )
+            .await
+            .map(|users| users.into_iter().map(|u| u.user.user_id).collect()),
+        Err(e) => Err(e),
+    };
+    let approvers = match approvers {
+        Ok(approvers) => approvers,
+        Err(e) => {
+            warn!(
+                "Could not list the approvers of {}: {}",
+                group.display_name, e
+            );
+            return;
+        }
+    };
+    let url = format!("{}/group/{}", context.server_url, group.group_id.0);
+    for approver in approvers
+        .into_iter()
+        .filter(|approver| approver != &request.requested_by && approver != &request.user_id)
+    {
+        let approver = match context.handler.get_user_details(&approver).await {
+            Ok(approver) => approver,
+            Err(e) => {
+                warn!("Could not get the details of {}: {}", approver, e);
+                continue;
+            }
+        };
+        let language = approver
+            .preferred_language
+            .unwrap_or_else(|| context.default_language.clone());
+        let mail_options = context.mail_options.clone();
+        let (username, group_name, requested_by, url) = (
+            request.user_id.to_string(),
+            group.display_name.clone(),
+            request.requested_by.to_string(),
+            url.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = crate::infra::mail::send_membership_request_email(
+                &approver.email,
+                &language,
+                &username,
+                &group_name,
+                &requested_by,
+                &url,
+                &mail_options,
+            )
+            .await
+            {
+                warn!("Error sending the membership request email: {:#}", e);
+            }
+        });
+    }
+}
+
+/// Emails the user whether they were added to the group, in the background.
+async fn notify_membership_decision<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group: &GroupDetails,
+    user_id: &UserId,
+    approved: bool,
+) {
+    if !context.mail_options.enable_membership_request_notifications {
+        return;
+    }
+    let user = match context.handler.get_user_details(user_id).await {
+        Ok(user) => user,
+        Err(e) => {
+            warn!("Could not get the details of {}: {}", user_id, e);
+            return;
+        }
+    };
+    let language = user
+        .preferred_language
+        .clone()
+        .unwrap_or_else(|| context.default_language.clone());
+    let mail_options = context.mail_options.clone();
+    let group_name = group.display_name.clone();
+    tokio::spawn(async move {
+        if let Err(e) = crate::infra::mail::send_membership_decision_email(
+            user.display_name
+                .as_deref()
+                .unwrap_or_else(|| user.user_id.as_str()),
+            &user.email,
+            &language,
+            &group_name,
+            approved,
+            &mail_options,
+        )
+        .await
+        {
+            warn!("Error sending the membership decision email: {:#}", e);
+        }
+    });
+}
+
+/// Who can approve or deny the request: the admins, and the managers of the group. Only the
+/// admins can approve the requests to join the `lldap_` groups.
+async fn check_can_decide_membership_request<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    request: &MembershipRequest,
+    group: &GroupDetails,
+) -> FieldResult<()> {
+    if !context.validation_result.can_manage_groups()
+        && !context.manages_group(request.group_id).await?
+    {
+        debug!("Unauthorized");
+        return Err("Unauthorized membership request decision".into());
+    }
+    check_can_change_lldap_group(
+        context,
+        &group.display_name,
+        "Only admins can add users to the lldap_ groups",
+    )
+}
+
+/// Disabling the account also logs the user out of the web UI.
+async fn set_user_enabled<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    user_id: String,
+    enabled: bool,
+) -> FieldResult<Success> {
+    let user_id = UserId::new(&user_id);
+    if !context.validation_result.can_manage_users() {
+        debug!("Unauthorized");
+        return Err("Unauthorized change of the account status".into());
+    }
+    if !enabled && user_id == context.validation_result.user {
+        return Err("You can't disable your own account".into());
+    }
+    check_not_admin_for_api_token(context, &user_id).await?;
+    set_account_enabled(&*context.handler, &context.jwt_blacklist, &user_id, enabled).await?;
+    let action = if enabled { "enableUser" } else { "disableUser" };
+    info!(
+        "{} {} the account of {}",
+        &context.validation_result.user,
+        if enabled { "enabled" } else { "disabled" },
+        user_id
+    );
+    context.audit(action, Some(user_id.to_string()), None).await;
+    Ok(Success::new())
+}
+
+/// The "everyone" group is managed by the server, from the configuration.
+async fn check_not_everyone_group<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    group_id: i32,
+) -> FieldResult<()> {
+    if let Some(everyone_group) = &context.everyone_group {
+        let group = context.handler.get_group_details(GroupId(group_id)).await?;
+        if &group.display_name == everyone_group {
+            return Err(format!("Cannot change the {} group", everyone_group).into());
+        }
+    }
+    Ok(())
+}
+
+/// Dynamic groups are read-only for membership: their members are defined by their filter.
+async fn check_not_dynamic_group<Handler: BackendHandler>(
+    handler: &Handler,
+    group_id: i32,
+) -> FieldResult<()> {
+    let group = handler.get_group_details(GroupId(group_id)).await?;
+    if group.dynamic_filter.is_some() {
+        return Err("Cannot change the members of a dynamic group".into());
+    }
+    Ok(())
+}
+
+/// The maximum number of groups a user can belong to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupLimit {
+    pub max_groups: usize,
+    /// Whether the members of `lldap_admin` can belong to more groups.
+    pub exempt_admins: bool,
+}
+
+/// Checks that the user can join one more group. `group_id` is `None` for a group being created.
+pub(crate) async fn check_group_limit<Handler: BackendHandler>(
+    handler: &Handler,
+    user_id: &UserId,
+    group_id: Option<GroupId>,
+    limit: GroupLimit,
+) -> FieldResult<()> {
+    let groups = handler.get_user_groups(user_id).await?;
+    let is_new_group = !groups.iter().any(|g| Some(g.group_id) == group_id);
+    let is_exempt = limit.exempt_admins && groups.iter().any(|g| g.display_name == "lldap_admin");
+    if is_new_group && !is_exempt && groups.len() >= limit.max_groups {
+        return Err(format!(
+            "{} is already a member of {} groups, the maximum is {}",
+            user_id,
+            groups.len(),
+            limit.max_groups
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Adds the user to the group. Adding a user to a group they're already a member of succeeds
+/// without doing anything, unless `strict` is set.
+pub(crate) async fn add_membership<Handler: BackendHandler>(
+    handler: &Handler,
+    user_id: &UserId,
+    group_id: GroupId,
+    strict: bool,
+    limit: Option<GroupLimit>,
+) -> FieldResult<()> {
+    if let Some(limit) = limit {
+        check_group_limit(handler, user_id, Some(group_id), limit).await?;
+    }
+    match handler.add_user_to_group(user_id, group_id).await {
+        Err(DomainError::EntityAlreadyExists(_)) if !strict => {
+            debug!("Already a member");
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Checks that the target of a merge doesn't end up in more groups than allowed. The dynamic
+/// groups of the source are not moved, so they don't count.
+async fn check_merge_group_limit<Handler: BackendHandler>(
+    handler: &Handler,
+    source: &UserId,
+    target: &UserId,
+    limit: GroupLimit,
+) -> FieldResult<()> {
+    let target_groups = handler.get_user_groups(target).await?;
+    let source_groups = handler.get_user_groups(source).await?;
+    let merged_groups = target_groups
+        .iter()
+        .chain(source_groups.iter().filter(|g| g.dynamic_filter.is_none()))
+        .map(|g| g.group_id)
+        .collect::<std::collections::HashSet<_>>();
+    let is_exempt = limit.exempt_admins
+        && target_groups
+            .iter()
+            .any(|g| g.display_name == "lldap_admin");
+    if !is_exempt
+        && merged_groups.len() > target_groups.len()
+        && merged_groups.len() > limit.max_groups
+    {
+        return Err(format!(
+            "{} would be a member of {} groups, the maximum is {}",
+            target,
+            merged_groups.len(),
+            limit.max_groups
+        )
+        .into());
+    }
+    Ok(())
+}
+
 #[graphql_object(context = Context<Handler>)]
 impl<Handler: BackendHandler + Sync> Mutation<Handler> {
     async fn create_user(
@@ -74,31 +935,33 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
         span.in_scope(|| {
             debug!(?user.id);
         });
-        if !context.validation_result.is_admin() {
+        if !context.validation_result.can_manage_users() {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized user creation".into());
         }
+        let errors = validate_create_user_input(
+            &*context.handler,
+            &user,
+            context.validation_result.is_admin(),
+        )
+        .instrument(span.clone())
+        .await?;
+        if !errors.is_empty() {
+            span.in_scope(|| debug!(?errors));
+            return Err(format_validation_errors(errors).into());
+        }
         let user_id = UserId::new(&user.id);
-        let avatar = user
-            .avatar
-            .map(base64::decode)
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
         context
             .handler
-            .create_user(CreateUserRequest {
-                user_id: user_id.clone(),
-                email: user.email,
-                display_name: user.display_name,
-                first_name: user.first_name,
-                last_name: user.last_name,
-                avatar,
-            })
+            .create_user(to_create_user_request(user)?)
+            .instrument(span.clone())
+            .await?;
+        apply_group_assignment_rules(&*context.handler, &context.group_assignment_rules, &user_id)
             .instrument(span.clone())
             .await?;
+        context
+            .audit("createUser", Some(user_id.to_string()), None)
+            .await;
         Ok(context
             .handler
             .get_user_details(&user_id)
@@ -107,6 +970,112 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             .map(Into::into)?)
     }
 
+    /// Check whether the user could be created, without creating it. An empty list means the
+    /// creation would succeed.
+    async fn validate_user(
+        context: &Context<Handler>,
+        user: CreateUserInput,
+    ) -> FieldResult<Vec<ValidationError>> {
+        let span = debug_span!("[GraphQL mutation] validate_user");
+        span.in_scope(|| {
+            debug!(?user.id);
+        });
+        if !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user validation".into());
+        }
+        validate_create_user_input(
+            &*context.handler,
+            &user,
+            context.validation_result.is_admin(),
+        )
+        .instrument(span)
+        .await
+    }
+
+    /// Create several users in a single transaction. If one of them can't be created, none are,
+    /// unless `bestEffort` is set: then the others are still created.
+    async fn create_users(
+        context: &Context<Handler>,
+        users: Vec<CreateUserInput>,
+        best_effort: Option<bool>,
+    ) -> FieldResult<Vec<BulkUserResult>> {
+        let span = debug_span!("[GraphQL mutation] create_users");
+        let best_effort = best_effort.unwrap_or(false);
+        span.in_scope(|| {
+            debug!(count = users.len(), best_effort);
+        });
+        if !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user creation".into());
+        }
+        let ids = users.iter().map(|u| u.id.clone()).collect::<Vec<_>>();
+        let mut errors = Vec::with_capacity(users.len());
+        let mut requests = Vec::new();
+        let mut seen_ids = std::collections::HashSet::new();
+        for user in users {
+            let validation_errors = validate_create_user_input(
+                &*context.handler,
+                &user,
+                context.validation_result.is_admin(),
+            )
+            .instrument(span.clone())
+            .await?;
+            if !validation_errors.is_empty() {
+                errors.push(Some(format_validation_errors(validation_errors)));
+            } else if !seen_ids.insert(UserId::new(&user.id)) {
+                errors.push(Some("The user ID appears several times".to_string()));
+            } else {
+                match to_create_user_request(user) {
+                    Ok(request) => {
+                        errors.push(None);
+                        requests.push(request);
+                    }
+                    Err(e) => errors.push(Some(e.message().to_string())),
+                }
+            }
+        }
+        if !best_effort && errors.iter().any(Option::is_some) {
+            span.in_scope(|| debug!(?errors, "Aborting bulk creation"));
+            return Ok(to_bulk_results(ids, errors, best_effort, "created"));
+        }
+        let results = context
+            .handler
+            .create_users(requests, best_effort)
+            .instrument(span.clone())
+            .await?;
+        merge_backend_errors(&mut errors, results);
+        let mut results = to_bulk_results(ids, errors, best_effort, "created");
+        // The rules can only match once the user exists, so they are applied after the transaction.
+        // A failure is reported on the user, which stays created, and the others still get theirs.
+        for result in results.iter_mut().filter(|r| r.ok) {
+            let user_id = UserId::new(&result.id);
+            context
+                .audit("createUsers", Some(user_id.to_string()), None)
+                .await;
+            if let Err(e) = apply_group_assignment_rules(
+                &*context.handler,
+                &context.group_assignment_rules,
+                &user_id,
+            )
+            .instrument(span.clone())
+            .await
+            {
+                span.in_scope(|| {
+                    warn!(
+                        "Could not apply the group assignment rules to {}: {}",
+                        user_id, e
+                    )
+                });
+                result.error = Some(format!(
+                    "Created, but the group assignment rules could not be applied: {}",
+                    e
+                ));
+            }
+        }
+        Ok(results)
+    }
+
     async fn create_group(
         context: &Context<Handler>,
         name: String,
@@ -115,11 +1084,60 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
         span.in_scope(|| {
             debug!(?name);
         });
-        if !context.validation_result.is_admin() {
+        if !context.validation_result.can_manage_groups() {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized group creation".into());
         }
+        span.in_scope(|| check_not_lldap_group_name(context, &name))?;
         let group_id = context.handler.create_group(&name).await?;
+        context
+            .audit(
+                "createGroup",
+                Some(format!("group {}", group_id.0)),
+                Some(name),
+            )
+            .await;
+        Ok(context
+            .handler
+            .get_group_details(group_id)
+            .instrument(span)
+            .await
+            .map(Into::into)?)
+    }
+
+    /// Create a group whose members are the users matching the LDAP filter, e.g.
+    /// `(&(objectClass=person)(preferredLanguage=fr))`. Only equality and presence filters on
+    /// user attributes are supported; the group can't be referenced in the filter.
+    async fn create_dynamic_group(
+        context: &Context<Handler>,
+        name: String,
+        filter: String,
+    ) -> FieldResult<super::query::Group<Handler>> {
+        let span = debug_span!("[GraphQL mutation] create_dynamic_group");
+        span.in_scope(|| {
+            debug!(?name, ?filter);
+        });
+        if !context.validation_result.can_manage_groups() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group creation".into());
+        }
+        span.in_scope(|| check_not_lldap_group_name(context, &name))?;
+        if let Err(e) = parse_user_filter(&filter) {
+            span.in_scope(|| debug!(?e));
+            return Err(format!("Invalid filter: {}", e).into());
+        }
+        let group_id = context
+            .handler
+            .create_dynamic_group(&name, &filter)
+            .instrument(span.clone())
+            .await?;
+        context
+            .audit(
+                "createDynamicGroup",
+                Some(format!("group {}", group_id.0)),
+                Some(filter),
+            )
+            .await;
         Ok(context
             .handler
             .get_group_details(group_id)
@@ -141,26 +1159,73 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized user update".into());
         }
-        let avatar = user
-            .avatar
-            .map(base64::decode)
-            .transpose()
-            .context("Invalid base64 image")?
-            .map(JpegPhoto::try_from)
-            .transpose()
-            .context("Provided image is not a valid JPEG")?;
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        // Users could otherwise take over the files of another account on the POSIX systems.
+        let changes_posix_attributes = user.uid_number.is_some()
+            || user.gid_number.is_some()
+            || user.home_directory.is_some()
+            || user.login_shell.is_some();
+        if changes_posix_attributes && !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized POSIX attributes update"));
+            return Err("Only admins can change the POSIX attributes of a user".into());
+        }
+        if !context.validation_result.can_manage_users() {
+            if let Some(attributes) = &user.attributes {
+                let schema = context
+                    .handler
+                    .list_user_attribute_schema()
+                    .instrument(span.clone())
+                    .await?;
+                if let Some(attribute) = attributes
+                    .iter()
+                    .find(|a| !schema.iter().any(|s| s.name == a.name && s.is_editable))
+                {
+                    span.in_scope(|| debug!(?attribute.name, "Unauthorized attribute update"));
+                    return Err(
+                        format!("Only admins can change the attribute {}", attribute.name).into(),
+                    );
+                }
+            }
+        }
+        if let Some(uid_number) = user.uid_number {
+            if let Some(owner) = find_uid_number_owner(&*context.handler, &user_id, uid_number)
+                .instrument(span.clone())
+                .await?
+            {
+                return Err(format!("The UID number is already used by '{}'", owner).into());
+            }
+        }
+        let avatar = parse_avatar(user.avatar.as_deref())?;
+        check_ssh_public_keys(user.ssh_public_keys.as_deref())?;
         context
             .handler
             .update_user(UpdateUserRequest {
-                user_id,
+                user_id: user_id.clone(),
                 email: user.email,
                 display_name: user.display_name,
                 first_name: user.first_name,
                 last_name: user.last_name,
                 avatar,
+                preferred_language: user.preferred_language,
+                email_aliases: user.email_aliases,
+                ssh_public_keys: user.ssh_public_keys,
+                uid_number: user.uid_number,
+                gid_number: user.gid_number,
+                home_directory: user.home_directory,
+                login_shell: user.login_shell,
+                login_notifications: user.login_notifications,
+                attributes: to_attribute_values(user.attributes),
             })
+            .instrument(span.clone())
+            .await?;
+        apply_group_assignment_rules(&*context.handler, &context.group_assignment_rules, &user_id)
             .instrument(span)
             .await?;
+        context
+            .audit("updateUser", Some(user_id.to_string()), None)
+            .await;
         Ok(Success::new())
     }
 
@@ -172,44 +1237,282 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
         span.in_scope(|| {
             debug!(?group.id);
         });
-        if !context.validation_result.is_admin() {
-            span.in_scope(|| debug!("Unauthorized"));
-            return Err("Unauthorized group update".into());
+        if !context.validation_result.can_manage_groups() {
+            // The managers can only change the editable custom attributes.
+            let only_attributes =
+                group.display_name.is_none() && group.gid_number.is_none() && group.email.is_none();
+            if !only_attributes
+                || !context
+                    .manages_group(GroupId(group.id))
+                    .instrument(span.clone())
+                    .await?
+            {
+                span.in_scope(|| debug!("Unauthorized"));
+                return Err("Unauthorized group update".into());
+            }
+            if let Some(attributes) = &group.attributes {
+                let schema = context
+                    .handler
+                    .list_group_attribute_schema()
+                    .instrument(span.clone())
+                    .await?;
+                if let Some(attribute) = attributes
+                    .iter()
+                    .find(|a| !schema.iter().any(|s| s.name == a.name && s.is_editable))
+                {
+                    span.in_scope(|| debug!(?attribute.name, "Unauthorized attribute update"));
+                    return Err(
+                        format!("Only admins can change the attribute {}", attribute.name).into(),
+                    );
+                }
+            }
         }
         if group.id == 1 {
             span.in_scope(|| debug!("Cannot change admin group details"));
             return Err("Cannot change admin group details".into());
         }
+        check_not_everyone_group(context, group.id)
+            .instrument(span.clone())
+            .await?;
+        if let Some(display_name) = &group.display_name {
+            if !context.validation_result.is_admin() {
+                let current_name = context
+                    .handler
+                    .get_group_details(GroupId(group.id))
+                    .instrument(span.clone())
+                    .await?
+                    .display_name;
+                if is_lldap_group(current_name) {
+                    span.in_scope(|| debug!("Unauthorized lldap_ group rename"));
+                    return Err("Only admins can rename the lldap_ groups".into());
+                }
+            }
+            span.in_scope(|| check_not_lldap_group_name(context, display_name))?;
+        }
+        if let Some(gid_number) = group.gid_number {
+            if let Some(owner) =
+                find_gid_number_owner(&*context.handler, GroupId(group.id), gid_number)
+                    .instrument(span.clone())
+                    .await?
+            {
+                return Err(format!("The GID number is already used by '{}'", owner).into());
+            }
+        }
         context
             .handler
             .update_group(UpdateGroupRequest {
                 group_id: GroupId(group.id),
                 display_name: group.display_name,
+                archived: None,
+                gid_number: group.gid_number,
+                email: group.email,
+                attributes: to_attribute_values(group.attributes),
             })
             .instrument(span)
             .await?;
+        context
+            .audit("updateGroup", Some(format!("group {}", group.id)), None)
+            .await;
         Ok(Success::new())
     }
 
-    async fn add_user_to_group(
-        context: &Context<Handler>,
-        user_id: String,
-        group_id: i32,
-    ) -> FieldResult<Success> {
+    /// Archive a group: it is hidden from LDAP and from the group list, and its members lose
+    /// the permissions it grants, but the memberships are kept.
+    async fn archive_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] archive_group");
+        set_group_archived(context, group_id, true)
+            .instrument(span)
+            .await?;
+        context
+            .audit("archiveGroup", Some(format!("group {}", group_id)), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    async fn unarchive_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] unarchive_group");
+        set_group_archived(context, group_id, false)
+            .instrument(span)
+            .await?;
+        context
+            .audit("unarchiveGroup", Some(format!("group {}", group_id)), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    async fn add_user_to_group(
+        context: &Context<Handler>,
+        user_id: String,
+        group_id: i32,
+    ) -> FieldResult<Success> {
         let span = debug_span!("[GraphQL mutation] add_user_to_group");
         span.in_scope(|| {
             debug!(?user_id, ?group_id);
         });
-        if !context.validation_result.is_admin() {
+        if !context.validation_result.can_manage_groups()
+            && !context
+                .manages_group(GroupId(group_id))
+                .instrument(span.clone())
+                .await?
+        {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized group membership modification".into());
         }
+        if !context.validation_result.is_admin() {
+            let group = context
+                .handler
+                .get_group_details(GroupId(group_id))
+                .instrument(span.clone())
+                .await?;
+            span.in_scope(|| {
+                check_can_change_lldap_group(
+                    context,
+                    &group.display_name,
+                    "Only admins can add users to the lldap_ groups",
+                )?;
+                check_no_approval_required(context, &group.display_name)
+            })?;
+        }
+        check_not_dynamic_group(&*context.handler, group_id)
+            .instrument(span.clone())
+            .await?;
+        add_membership(
+            &*context.handler,
+            &UserId::new(&user_id),
+            GroupId(group_id),
+            context.strict_group_membership_adds,
+            context.group_limit,
+        )
+        .instrument(span)
+        .await?;
         context
+            .audit(
+                "addUserToGroup",
+                Some(UserId::new(&user_id).to_string()),
+                Some(format!("group {}", group_id)),
+            )
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Add several users to a group in a single transaction, with the same rules as
+    /// `addUserToGroup`. If one of them can't be added, none are, unless `bestEffort` is set.
+    async fn add_users_to_group(
+        context: &Context<Handler>,
+        user_ids: Vec<String>,
+        group_id: i32,
+        best_effort: Option<bool>,
+    ) -> FieldResult<Vec<BulkUserResult>> {
+        let span = debug_span!("[GraphQL mutation] add_users_to_group");
+        let best_effort = best_effort.unwrap_or(false);
+        span.in_scope(|| {
+            debug!(?user_ids, ?group_id, best_effort);
+        });
+        if !context.validation_result.can_manage_groups()
+            && !context
+                .manages_group(GroupId(group_id))
+                .instrument(span.clone())
+                .await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group membership modification".into());
+        }
+        let group = context
             .handler
-            .add_user_to_group(&UserId::new(&user_id), GroupId(group_id))
+            .get_group_details(GroupId(group_id))
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            check_can_change_lldap_group(
+                context,
+                &group.display_name,
+                "Only admins can add users to the lldap_ groups",
+            )?;
+            check_no_approval_required(context, &group.display_name)
+        })?;
+        if group.dynamic_filter.is_some() {
+            return Err("Cannot change the members of a dynamic group".into());
+        }
+        let members = context
+            .handler
+            .list_users(
+                Some(UserRequestFilter::MemberOfId(GroupId(group_id))),
+                false,
+            )
+            .instrument(span.clone())
+            .await?
+            .into_iter()
+            .map(|u| u.user.user_id)
+            .collect::<std::collections::HashSet<_>>();
+        let mut seen_ids = std::collections::HashSet::new();
+        let mut errors = Vec::with_capacity(user_ids.len());
+        let mut new_members = Vec::new();
+        // The index of each new member in the results.
+        let mut new_member_indices = Vec::new();
+        for (index, id) in user_ids.iter().enumerate() {
+            let user_id = UserId::new(id);
+            if !seen_ids.insert(user_id.clone()) {
+                errors.push(Some("The user ID appears several times".to_string()));
+            } else if members.contains(&user_id) {
+                // Not sent to the backend, nothing to do.
+                errors.push(
+                    context
+                        .strict_group_membership_adds
+                        .then(|| format!("{} is already a member of the group", user_id)),
+                );
+            } else {
+                let limit_check = match context.group_limit {
+                    Some(limit) => {
+                        check_group_limit(
+                            &*context.handler,
+                            &user_id,
+                            Some(GroupId(group_id)),
+                            limit,
+                        )
+                        .instrument(span.clone())
+                        .await
+                    }
+                    None => Ok(()),
+                };
+                match limit_check {
+                    Ok(()) => {
+                        errors.push(None);
+                        new_members.push(user_id);
+                        new_member_indices.push(index);
+                    }
+                    Err(e) => errors.push(Some(e.message().to_string())),
+                }
+            }
+        }
+        if !best_effort && errors.iter().any(Option::is_some) {
+            span.in_scope(|| debug!(?errors, "Aborting bulk membership addition"));
+            return Ok(to_bulk_results(user_ids, errors, best_effort, "added"));
+        }
+        let results = context
+            .handler
+            .add_users_to_group(&new_members, GroupId(group_id), best_effort)
             .instrument(span)
             .await?;
-        Ok(Success::new())
+        // Nothing was added if one user failed, without best effort.
+        let committed = best_effort || results.iter().all(Result::is_ok);
+        // The users that were already members stay successful.
+        for ((index, user_id), result) in new_member_indices
+            .into_iter()
+            .zip(&new_members)
+            .zip(results)
+        {
+            if committed && result.is_ok() {
+                context
+                    .audit(
+                        "addUsersToGroup",
+                        Some(user_id.to_string()),
+                        Some(format!("group {}", group_id)),
+                    )
+                    .await;
+            }
+            errors[index] = result.err().map(|e| e.to_string());
+        }
+        Ok(to_bulk_results(user_ids, errors, best_effort, "added"))
     }
 
     async fn remove_user_from_group(
@@ -221,7 +1524,12 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
         span.in_scope(|| {
             debug!(?user_id, ?group_id);
         });
-        if !context.validation_result.is_admin() {
+        if !context.validation_result.can_manage_groups()
+            && !context
+                .manages_group(GroupId(group_id))
+                .instrument(span.clone())
+                .await?
+        {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized group membership modification".into());
         }
@@ -230,11 +1538,339 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             span.in_scope(|| debug!("Cannot remove admin rights for current user"));
             return Err("Cannot remove admin rights for current user".into());
         }
+        if !context.validation_result.is_admin() {
+            let group = context
+                .handler
+                .get_group_details(GroupId(group_id))
+                .instrument(span.clone())
+                .await?;
+            span.in_scope(|| {
+                check_can_change_lldap_group(
+                    context,
+                    &group.display_name,
+                    "Only admins can remove users from the lldap_ groups",
+                )
+            })?;
+        }
+        check_not_dynamic_group(&*context.handler, group_id)
+            .instrument(span.clone())
+            .await?;
         context
             .handler
             .remove_user_from_group(&user_id, GroupId(group_id))
             .instrument(span)
             .await?;
+        context
+            .audit(
+                "removeUserFromGroup",
+                Some(user_id.to_string()),
+                Some(format!("group {}", group_id)),
+            )
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Ask for the user to be added to a group that requires an approval. The users can ask for
+    /// themselves, and the managers of the group for anybody.
+    async fn request_group_membership(
+        context: &Context<Handler>,
+        user_id: String,
+        group_id: i32,
+    ) -> FieldResult<MembershipRequestResult> {
+        let span = debug_span!("[GraphQL mutation] request_group_membership");
+        span.in_scope(|| {
+            debug!(?user_id, ?group_id);
+        });
+        let user_id = UserId::new(&user_id);
+        let is_self =
+            user_id == context.validation_result.user && !context.validation_result.is_api_token();
+        if !is_self
+            && !context.validation_result.can_manage_groups()
+            && !context
+                .manages_group(GroupId(group_id))
+                .instrument(span.clone())
+                .await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized membership request".into());
+        }
+        let group = context
+            .handler
+            .get_group_details(GroupId(group_id))
+            .instrument(span.clone())
+            .await?;
+        if !context.requires_approval(&group.display_name) {
+            return Err("This group doesn't take membership requests".into());
+        }
+        if group.dynamic_filter.is_some() {
+            return Err("Cannot change the members of a dynamic group".into());
+        }
+        check_not_everyone_group(context, group_id)
+            .instrument(span.clone())
+            .await?;
+        let request = context
+            .handler
+            .create_membership_request(GroupId(group_id), &user_id, &context.validation_result.user)
+            .instrument(span.clone())
+            .await?;
+        context
+            .audit(
+                "requestGroupMembership",
+                Some(user_id.to_string()),
+                Some(format!("group {}", group_id)),
+            )
+            .await;
+        notify_membership_request(context, &group, &request)
+            .instrument(span)
+            .await;
+        Ok(MembershipRequestResult {
+            id: request.request_id,
+        })
+    }
+
+    /// Add the user of the request to the group. Nobody can approve a request they made, or that
+    /// adds them to the group.
+    async fn approve_membership_request(
+        context: &Context<Handler>,
+        request_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] approve_membership_request");
+        span.in_scope(|| {
+            debug!(?request_id);
+        });
+        let request = context
+            .handler
+            .get_membership_request(request_id)
+            .instrument(span.clone())
+            .await?;
+        let group = context
+            .handler
+            .get_group_details(request.group_id)
+            .instrument(span.clone())
+            .await?;
+        check_can_decide_membership_request(context, &request, &group)
+            .instrument(span.clone())
+            .await?;
+        let approver = &context.validation_result.user;
+        if approver == &request.requested_by || approver == &request.user_id {
+            span.in_scope(|| debug!("Self-approval"));
+            return Err("Another approver has to approve this request".into());
+        }
+        if let Some(limit) = context.group_limit {
+            check_group_limit(
+                &*context.handler,
+                &request.user_id,
+                Some(request.group_id),
+                limit,
+            )
+            .instrument(span.clone())
+            .await?;
+        }
+        context
+            .handler
+            .approve_membership_request(request_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .audit(
+                "approveMembershipRequest",
+                Some(request.user_id.to_string()),
+                Some(format!("group {}", request.group_id.0)),
+            )
+            .await;
+        notify_membership_decision(context, &group, &request.user_id, true)
+            .instrument(span)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Drop the request. The user and whoever made the request can also withdraw it.
+    async fn deny_membership_request(
+        context: &Context<Handler>,
+        request_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] deny_membership_request");
+        span.in_scope(|| {
+            debug!(?request_id);
+        });
+        let request = context
+            .handler
+            .get_membership_request(request_id)
+            .instrument(span.clone())
+            .await?;
+        let group = context
+            .handler
+            .get_group_details(request.group_id)
+            .instrument(span.clone())
+            .await?;
+        let user = &context.validation_result.user;
+        let is_withdrawn = !context.validation_result.is_api_token()
+            && (user == &request.user_id || user == &request.requested_by);
+        if !is_withdrawn {
+            check_can_decide_membership_request(context, &request, &group)
+                .instrument(span.clone())
+                .await?;
+        }
+        context
+            .handler
+            .delete_membership_request(request_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .audit(
+                "denyMembershipRequest",
+                Some(request.user_id.to_string()),
+                Some(format!("group {}", request.group_id.0)),
+            )
+            .await;
+        if user != &request.user_id {
+            notify_membership_decision(context, &group, &request.user_id, false)
+                .instrument(span)
+                .await;
+        }
+        Ok(Success::new())
+    }
+
+    /// Let the user change the members of the group without being an admin.
+    async fn add_group_manager(
+        context: &Context<Handler>,
+        group_id: i32,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_manager");
+        span.in_scope(|| {
+            debug!(?group_id, ?user_id);
+        });
+        if !context.validation_result.can_manage_groups() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group manager modification".into());
+        }
+        let group = context
+            .handler
+            .get_group_details(GroupId(group_id))
+            .instrument(span.clone())
+            .await?;
+        if group.display_name.starts_with("lldap_") {
+            span.in_scope(|| debug!("Cannot delegate the lldap_ groups"));
+            return Err("The lldap_ groups can't have managers".into());
+        }
+        check_not_everyone_group(context, group_id)
+            .instrument(span.clone())
+            .await?;
+        check_not_dynamic_group(&*context.handler, group_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .add_group_manager(GroupId(group_id), &UserId::new(&user_id))
+            .instrument(span)
+            .await?;
+        context
+            .audit(
+                "addGroupManager",
+                Some(format!("group {}", group_id)),
+                Some(UserId::new(&user_id).to_string()),
+            )
+            .await;
+        Ok(Success::new())
+    }
+
+    async fn remove_group_manager(
+        context: &Context<Handler>,
+        group_id: i32,
+        user_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_manager");
+        span.in_scope(|| {
+            debug!(?group_id, ?user_id);
+        });
+        if !context.validation_result.can_manage_groups() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group manager modification".into());
+        }
+        context
+            .handler
+            .remove_group_manager(GroupId(group_id), &UserId::new(&user_id))
+            .instrument(span)
+            .await?;
+        context
+            .audit(
+                "removeGroupManager",
+                Some(format!("group {}", group_id)),
+                Some(UserId::new(&user_id).to_string()),
+            )
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Nest a group in another one: in LDAP, the members of the group are also members of the
+    /// parent group.
+    async fn add_group_to_group(
+        context: &Context<Handler>,
+        group_id: i32,
+        parent_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_to_group");
+        span.in_scope(|| {
+            debug!(?group_id, ?parent_group_id);
+        });
+        if !context.validation_result.can_manage_groups() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group nesting modification".into());
+        }
+        check_not_dynamic_group(&*context.handler, parent_group_id)
+            .instrument(span.clone())
+            .await?;
+        let parent_group = context
+            .handler
+            .get_group_details(GroupId(parent_group_id))
+            .instrument(span.clone())
+            .await?;
+        // Nesting doesn't grant permissions, so it would only be misleading.
+        if parent_group.display_name.starts_with("lldap_") {
+            span.in_scope(|| debug!("Cannot nest groups in the lldap_ groups"));
+            return Err("Cannot nest groups in the lldap_ groups".into());
+        }
+        context
+            .handler
+            .add_group_to_group(GroupId(group_id), GroupId(parent_group_id))
+            .instrument(span)
+            .await?;
+        context
+            .audit(
+                "addGroupToGroup",
+                Some(format!("group {}", group_id)),
+                Some(format!("In group {}", parent_group_id)),
+            )
+            .await;
+        Ok(Success::new())
+    }
+
+    async fn remove_group_from_group(
+        context: &Context<Handler>,
+        group_id: i32,
+        parent_group_id: i32,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] remove_group_from_group");
+        span.in_scope(|| {
+            debug!(?group_id, ?parent_group_id);
+        });
+        if !context.validation_result.can_manage_groups() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group nesting modification".into());
+        }
+        context
+            .handler
+            .remove_group_from_group(GroupId(group_id), GroupId(parent_group_id))
+            .instrument(span)
+            .await?;
+        context
+            .audit(
+                "removeGroupFromGroup",
+                Some(format!("group {}", group_id)),
+                Some(format!("From group {}", parent_group_id)),
+            )
+            .await;
         Ok(Success::new())
     }
 
@@ -244,7 +1880,7 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             debug!(?user_id);
         });
         let user_id = UserId::new(&user_id);
-        if !context.validation_result.is_admin() {
+        if !context.validation_result.can_manage_users() {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized user deletion".into());
         }
@@ -252,32 +1888,1606 @@ impl<Handler: BackendHandler + Sync> Mutation<Handler> {
             span.in_scope(|| debug!("Cannot delete current user"));
             return Err("Cannot delete current user".into());
         }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
         context
             .handler
             .delete_user(&user_id)
             .instrument(span)
             .await?;
+        context
+            .audit("deleteUser", Some(user_id.to_string()), None)
+            .await;
         Ok(Success::new())
     }
 
-    async fn delete_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
-        let span = debug_span!("[GraphQL mutation] delete_group");
+    /// Delete several users in a single transaction. If one of them can't be deleted, none are,
+    /// unless `bestEffort` is set.
+    async fn delete_users(
+        context: &Context<Handler>,
+        user_ids: Vec<String>,
+        best_effort: Option<bool>,
+    ) -> FieldResult<Vec<BulkUserResult>> {
+        let span = debug_span!("[GraphQL mutation] delete_users");
+        let best_effort = best_effort.unwrap_or(false);
         span.in_scope(|| {
-            debug!(?group_id);
+            debug!(?user_ids, best_effort);
         });
-        if !context.validation_result.is_admin() {
+        if !context.validation_result.can_manage_users() {
             span.in_scope(|| debug!("Unauthorized"));
-            return Err("Unauthorized group deletion".into());
+            return Err("Unauthorized user deletion".into());
         }
-        if group_id == 1 {
-            span.in_scope(|| debug!("Cannot delete admin group"));
-            return Err("Cannot delete admin group".into());
+        let mut seen_ids = std::collections::HashSet::new();
+        let mut errors = Vec::with_capacity(user_ids.len());
+        let mut to_delete = Vec::new();
+        for id in &user_ids {
+            let user_id = UserId::new(id);
+            let error = if !seen_ids.insert(user_id.clone()) {
+                Some("The user ID appears several times".to_string())
+            } else if context.validation_result.user == user_id {
+                Some("Cannot delete current user".to_string())
+            } else {
+                check_not_admin_for_api_token(context, &user_id)
+                    .instrument(span.clone())
+                    .await
+                    .err()
+                    .map(|e| e.message().to_string())
+            };
+            if error.is_none() {
+                to_delete.push(user_id);
+            }
+            errors.push(error);
         }
-        context
+        if !best_effort && errors.iter().any(Option::is_some) {
+            span.in_scope(|| debug!(?errors, "Aborting bulk deletion"));
+            return Ok(to_bulk_results(user_ids, errors, best_effort, "deleted"));
+        }
+        let results = context
             .handler
-            .delete_group(GroupId(group_id))
+            .delete_users(&to_delete, best_effort)
             .instrument(span)
             .await?;
+        // Nothing was deleted if one user failed, without best effort.
+        if best_effort || results.iter().all(Result::is_ok) {
+            for (user_id, _) in to_delete.iter().zip(&results).filter(|(_, r)| r.is_ok()) {
+                context
+                    .audit("deleteUsers", Some(user_id.to_string()), None)
+                    .await;
+            }
+        }
+        merge_backend_errors(&mut errors, results);
+        Ok(to_bulk_results(user_ids, errors, best_effort, "deleted"))
+    }
+
+    /// Replace the password of a user with a bcrypt, argon2 or `{SSHA}` hash from another system,
+    /// e.g. when migrating from OpenLDAP. It is upgraded on the first successful LDAP bind.
+    async fn import_password_hash(
+        context: &Context<Handler>,
+        user_id: String,
+        password_hash: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] import_password_hash");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized password import".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .import_password_hash(&user_id, &password_hash)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} imported a password hash for {}",
+                &context.validation_result.user, user_id
+            )
+        });
+        context
+            .audit("importPasswordHash", Some(user_id.to_string()), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Set the dates between which the account can be used to log in, e.g. for a contractor. A
+    /// null date removes the limit.
+    async fn set_account_validity(
+        context: &Context<Handler>,
+        user_id: String,
+        activation_date: Option<chrono::DateTime<chrono::Utc>>,
+        expiration_date: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] set_account_validity");
+        span.in_scope(|| {
+            debug!(?user_id, ?activation_date, ?expiration_date);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized account validity change".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .set_account_validity(&user_id, activation_date, expiration_date)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} set the validity of the account of {}",
+                &context.validation_result.user, user_id
+            )
+        });
+        context
+            .audit("setAccountValidity", Some(user_id.to_string()), None)
+            .await;
         Ok(Success::new())
     }
+
+    /// Disable the account instead of deleting it: the user can't log in, and is logged out of
+    /// the web UI, but keeps their groups and attributes.
+    async fn disable_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] disable_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        set_user_enabled(context, user_id, false)
+            .instrument(span)
+            .await
+    }
+
+    /// Enable a disabled account again.
+    async fn enable_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] enable_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        set_user_enabled(context, user_id, true)
+            .instrument(span)
+            .await
+    }
+
+    /// Unlock an account locked after too many wrong passwords.
+    async fn unlock_user(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] unlock_user");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user unlock".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .unlock_user(&user_id)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} unlocked the account of {}",
+                &context.validation_result.user, user_id
+            )
+        });
+        context
+            .audit("unlockUser", Some(user_id.to_string()), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Merge a duplicate account into another one: the target is added to the groups of the source,
+    /// and gets the attributes it doesn't have yet, or all of them with `overwriteAttributes`.
+    /// With `deleteSource`, the source is then deleted and its emails become aliases of the
+    /// target. Either everything is merged, or nothing is.
+    async fn merge_users(
+        context: &Context<Handler>,
+        source_user_id: String,
+        target_user_id: String,
+        delete_source: Option<bool>,
+        overwrite_attributes: Option<bool>,
+    ) -> FieldResult<UserMergeReport> {
+        let span = debug_span!("[GraphQL mutation] merge_users");
+        span.in_scope(|| {
+            debug!(?source_user_id, ?target_user_id, ?delete_source);
+        });
+        if !context.validation_result.can_manage_users() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user merge".into());
+        }
+        let source = UserId::new(&source_user_id);
+        let target = UserId::new(&target_user_id);
+        let delete_source = delete_source.unwrap_or(false);
+        if delete_source && context.validation_result.user == source {
+            span.in_scope(|| debug!("Cannot delete current user"));
+            return Err("Cannot delete current user".into());
+        }
+        for user_id in [&source, &target] {
+            check_not_admin_for_api_token(context, user_id)
+                .instrument(span.clone())
+                .await?;
+        }
+        if let Some(limit) = context.group_limit {
+            check_merge_group_limit(&*context.handler, &source, &target, limit)
+                .instrument(span.clone())
+                .await?;
+        }
+        let report = context
+            .handler
+            .merge_users(MergeUsersRequest {
+                source,
+                target,
+                overwrite_attributes: overwrite_attributes.unwrap_or(false),
+                delete_source,
+            })
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| debug!(?report));
+        context
+            .audit(
+                "mergeUsers",
+                Some(UserId::new(&target_user_id).to_string()),
+                Some(format!("Merged {}", UserId::new(&source_user_id))),
+            )
+            .await;
+        Ok(report.into())
+    }
+
+    async fn delete_group(context: &Context<Handler>, group_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_group");
+        span.in_scope(|| {
+            debug!(?group_id);
+        });
+        if !context.validation_result.can_manage_groups() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group deletion".into());
+        }
+        if group_id == 1 {
+            span.in_scope(|| debug!("Cannot delete admin group"));
+            return Err("Cannot delete admin group".into());
+        }
+        if !context.validation_result.is_admin() {
+            let group = context
+                .handler
+                .get_group_details(GroupId(group_id))
+                .instrument(span.clone())
+                .await?;
+            span.in_scope(|| {
+                check_can_change_lldap_group(
+                    context,
+                    &group.display_name,
+                    "Only admins can delete the lldap_ groups",
+                )
+            })?;
+        }
+        check_not_everyone_group(context, group_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .delete_group(GroupId(group_id))
+            .instrument(span)
+            .await?;
+        context
+            .audit("deleteGroup", Some(format!("group {}", group_id)), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Delete several groups at once. Either all the groups are deleted, or none are.
+    async fn delete_groups(
+        context: &Context<Handler>,
+        group_ids: Vec<i32>,
+    ) -> FieldResult<Vec<DeleteGroupResult>> {
+        let span = debug_span!("[GraphQL mutation] delete_groups");
+        span.in_scope(|| {
+            debug!(?group_ids);
+        });
+        if !context.validation_result.can_manage_groups() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group deletion".into());
+        }
+        let mut group_ids = group_ids;
+        group_ids.sort_unstable();
+        group_ids.dedup();
+        let existing_groups = context
+            .handler
+            .list_groups(Some(GroupRequestFilter::Or(
+                group_ids
+                    .iter()
+                    .map(|&id| GroupRequestFilter::GroupId(GroupId(id)))
+                    .collect(),
+            )))
+            .instrument(span.clone())
+            .await?
+            .into_iter()
+            .map(|g| (g.id.0, g.display_name))
+            .collect::<std::collections::HashMap<_, _>>();
+        let errors = group_ids
+            .iter()
+            .map(|&id| {
+                if id == 1 {
+                    Some("Cannot delete admin group".to_string())
+                } else if !existing_groups.contains_key(&id) {
+                    Some(format!("No such group: {}", id))
+                } else if existing_groups.get(&id) == context.everyone_group.as_ref() {
+                    Some(format!("Cannot delete the {} group", existing_groups[&id]))
+                } else if !context.validation_result.is_admin()
+                    && is_lldap_group(&existing_groups[&id])
+                {
+                    Some("Only admins can delete the lldap_ groups".to_string())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if errors.iter().any(Option::is_some) {
+            span.in_scope(|| debug!(?errors, "Aborting bulk deletion"));
+            return Ok(group_ids
+                .into_iter()
+                .zip(errors)
+                .map(|(id, error)| DeleteGroupResult {
+                    id,
+                    ok: false,
+                    error: Some(error.unwrap_or_else(|| {
+                        "Not deleted: another group could not be deleted".to_string()
+                    })),
+                })
+                .collect());
+        }
+        context
+            .handler
+            .delete_groups(&group_ids.iter().copied().map(GroupId).collect::<Vec<_>>())
+            .instrument(span)
+            .await?;
+        for id in &group_ids {
+            context
+                .audit("deleteGroups", Some(format!("group {}", id)), None)
+                .await;
+        }
+        Ok(group_ids
+            .into_iter()
+            .map(|id| DeleteGroupResult {
+                id,
+                ok: true,
+                error: None,
+            })
+            .collect())
+    }
+
+    /// Remove the memberships that shouldn't exist, e.g. after an import that bypassed the
+    /// database constraints. Safe to run repeatedly.
+    async fn repair_memberships(context: &Context<Handler>) -> FieldResult<MembershipRepairReport> {
+        let span = debug_span!("[GraphQL mutation] repair_memberships");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized membership repair".into());
+        }
+        let report = context
+            .handler
+            .repair_memberships()
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| debug!(?report));
+        context.audit("repairMemberships", None, None).await;
+        Ok(report.into())
+    }
+
+    /// Reclaim the free space and refresh the statistics and indices of the database. On SQLite,
+    /// the writes are blocked until it completes, which can take a while on a large database.
+    async fn optimize_database(
+        context: &Context<Handler>,
+    ) -> FieldResult<DatabaseOptimizationReport> {
+        let span = debug_span!("[GraphQL mutation] optimize_database");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized database optimization".into());
+        }
+        let report = context
+            .handler
+            .optimize_database()
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| debug!(?report));
+        context.audit("optimizeDatabase", None, None).await;
+        Ok(report.into())
+    }
+
+    /// Create a single-use link that logs the user in without a password, for support purposes.
+    /// Only one link per user can be pending at a time.
+    async fn create_magic_login_link(
+        context: &Context<Handler>,
+        user_id: String,
+        valid_for_minutes: i32,
+    ) -> FieldResult<String> {
+        let span = debug_span!("[GraphQL mutation] create_magic_login_link");
+        span.in_scope(|| {
+            debug!(?user_id, ?valid_for_minutes);
+        });
+        if !context.enable_magic_login_links {
+            span.in_scope(|| debug!("Disabled"));
+            return Err("Magic login links are disabled".into());
+        }
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| {
+                warn!(
+                    "{} tried to create a login link for {} without being an admin",
+                    &context.validation_result.user, user_id
+                )
+            });
+            return Err("Unauthorized login link creation".into());
+        }
+        if !(1..=MAX_MAGIC_LOGIN_LINK_VALIDITY_MINUTES).contains(&valid_for_minutes) {
+            return Err(format!(
+                "The validity must be between 1 and {} minutes",
+                MAX_MAGIC_LOGIN_LINK_VALIDITY_MINUTES
+            )
+            .into());
+        }
+        let user_id = UserId::new(&user_id);
+        let token = context
+            .handler
+            .create_magic_login_token(
+                &user_id,
+                chrono::Duration::minutes(valid_for_minutes.into()),
+            )
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} created a login link for {}, valid for {} minutes",
+                &context.validation_result.user, user_id, valid_for_minutes
+            )
+        });
+        context
+            .audit("createMagicLoginLink", Some(user_id.to_string()), None)
+            .await;
+        Ok(format!("{}/magic-login/{}", context.server_url, token))
+    }
+
+    /// Create a link for the user to choose a new password, to hand over to them when no mail
+    /// server is configured to send the password reset emails.
+    async fn create_password_reset_link(
+        context: &Context<Handler>,
+        user_id: String,
+        valid_for_hours: i32,
+    ) -> FieldResult<String> {
+        let span = debug_span!("[GraphQL mutation] create_password_reset_link");
+        span.in_scope(|| {
+            debug!(?user_id, ?valid_for_hours);
+        });
+        let user_id = UserId::new(&user_id);
+        let user_is_admin = context
+            .handler
+            .get_user_groups(&user_id)
+            .instrument(span.clone())
+            .await?
+            .iter()
+            .any(|g| g.display_name == "lldap_admin");
+        if !context
+            .validation_result
+            .can_change_password(&user_id, user_is_admin)
+        {
+            span.in_scope(|| {
+                warn!(
+                    "{} tried to create a password reset link for {} without the permission",
+                    &context.validation_result.user, user_id
+                )
+            });
+            return Err("Unauthorized password reset link creation".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        if !(1..=MAX_PASSWORD_RESET_LINK_VALIDITY_HOURS).contains(&valid_for_hours) {
+            return Err(format!(
+                "The validity must be between 1 and {} hours",
+                MAX_PASSWORD_RESET_LINK_VALIDITY_HOURS
+            )
+            .into());
+        }
+        let token = context
+            .handler
+            .create_password_reset_token(&user_id, chrono::Duration::hours(valid_for_hours.into()))
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} created a password reset link for {}, valid for {} hours",
+                &context.validation_result.user, user_id, valid_for_hours
+            )
+        });
+        context
+            .audit("createPasswordResetLink", Some(user_id.to_string()), None)
+            .await;
+        Ok(format!(
+            "{}/reset-password/step2/{}",
+            context.server_url, token
+        ))
+    }
+
+    /// Create a single-use password for a new user to log in once and choose their own password.
+    /// It replaces the previous temporary password of the user, if any.
+    async fn create_temporary_password(
+        context: &Context<Handler>,
+        user_id: String,
+        valid_for_hours: i32,
+    ) -> FieldResult<String> {
+        let span = debug_span!("[GraphQL mutation] create_temporary_password");
+        span.in_scope(|| {
+            debug!(?user_id, ?valid_for_hours);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| {
+                warn!(
+                    "{} tried to create a temporary password for {} without being an admin",
+                    &context.validation_result.user, user_id
+                )
+            });
+            return Err("Unauthorized temporary password creation".into());
+        }
+        if !(1..=MAX_TEMPORARY_PASSWORD_VALIDITY_HOURS).contains(&valid_for_hours) {
+            return Err(format!(
+                "The validity must be between 1 and {} hours",
+                MAX_TEMPORARY_PASSWORD_VALIDITY_HOURS
+            )
+            .into());
+        }
+        let user_id = UserId::new(&user_id);
+        let password = context
+            .handler
+            .create_temporary_password(&user_id, chrono::Duration::hours(valid_for_hours.into()))
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} created a temporary password for {}, valid for {} hours",
+                &context.validation_result.user, user_id, valid_for_hours
+            )
+        });
+        context
+            .audit("createTemporaryPassword", Some(user_id.to_string()), None)
+            .await;
+        Ok(password)
+    }
+
+    /// Generate a new TOTP secret for the user, to confirm with `confirmTotpEnrollment`. Users can
+    /// only enroll their own account, since the secret shouldn't leave their authenticator.
+    async fn start_totp_enrollment(
+        context: &Context<Handler>,
+        user_id: String,
+    ) -> FieldResult<TotpEnrollment> {
+        let span = debug_span!("[GraphQL mutation] start_totp_enrollment");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if context.validation_result.user != user_id {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Users can only enroll their own account in TOTP".into());
+        }
+        let secret = context
+            .handler
+            .start_totp_enrollment(&user_id)
+            .instrument(span)
+            .await?;
+        let uri = totp::get_provisioning_uri(user_id.as_str(), &secret);
+        let qr_code = totp::get_qr_code(&uri)?;
+        context
+            .audit("startTotpEnrollment", Some(user_id.to_string()), None)
+            .await;
+        Ok(TotpEnrollment {
+            secret,
+            uri,
+            qr_code,
+        })
+    }
+
+    /// Enable TOTP with the first code of the authenticator, and return the recovery codes, which
+    /// can replace a TOTP code once each. They can't be retrieved later.
+    async fn confirm_totp_enrollment(
+        context: &Context<Handler>,
+        user_id: String,
+        code: String,
+    ) -> FieldResult<Vec<String>> {
+        let span = debug_span!("[GraphQL mutation] confirm_totp_enrollment");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if context.validation_result.user != user_id {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Users can only enroll their own account in TOTP".into());
+        }
+        let recovery_codes = context
+            .handler
+            .confirm_totp_enrollment(&user_id, &code)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| info!("{} enabled TOTP", user_id));
+        context
+            .audit("confirmTotpEnrollment", Some(user_id.to_string()), None)
+            .await;
+        Ok(recovery_codes)
+    }
+
+    /// Remove the TOTP secret of the user, e.g. when they lost their authenticator and their
+    /// recovery codes.
+    async fn disable_totp(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] disable_totp");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized TOTP removal".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .disable_totp(&user_id)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} disabled TOTP for {}",
+                &context.validation_result.user, user_id
+            )
+        });
+        context
+            .audit("disableTotp", Some(user_id.to_string()), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Remove a security key or passkey of the user, e.g. when it was lost.
+    async fn delete_webauthn_credential(
+        context: &Context<Handler>,
+        user_id: String,
+        credential_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_webauthn_credential");
+        span.in_scope(|| {
+            debug!(?user_id, ?credential_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized security key removal".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        context
+            .handler
+            .delete_webauthn_credential(&user_id, &credential_id)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} removed a security key of {}",
+                &context.validation_result.user, user_id
+            )
+        });
+        context
+            .audit("deleteWebauthnCredential", Some(user_id.to_string()), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Log the user out of one session of the web UI, e.g. on a lost device. Its tokens are
+    /// rejected immediately.
+    async fn delete_session(
+        context: &Context<Handler>,
+        user_id: String,
+        session_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_session");
+        span.in_scope(|| {
+            debug!(?user_id, ?session_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized session revocation".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        let session_id = session_id
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid session ID: '{}'", session_id))?;
+        let revoked_jwts = context
+            .handler
+            .delete_session(&user_id, session_id)
+            .instrument(span.clone())
+            .await?;
+        context.jwt_blacklist.write().unwrap().extend(revoked_jwts);
+        span.in_scope(|| {
+            info!(
+                "{} revoked a session of {}",
+                &context.validation_result.user, user_id
+            )
+        });
+        context
+            .audit("deleteSession", Some(user_id.to_string()), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Log the user out of all their sessions of the web UI.
+    async fn delete_sessions(context: &Context<Handler>, user_id: String) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_sessions");
+        span.in_scope(|| {
+            debug!(?user_id);
+        });
+        let user_id = UserId::new(&user_id);
+        if !context.validation_result.can_write(&user_id) {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized session revocation".into());
+        }
+        check_not_admin_for_api_token(context, &user_id)
+            .instrument(span.clone())
+            .await?;
+        let revoked_jwts = context
+            .handler
+            .delete_sessions(&user_id)
+            .instrument(span.clone())
+            .await?;
+        context.jwt_blacklist.write().unwrap().extend(revoked_jwts);
+        span.in_scope(|| {
+            info!(
+                "{} revoked all the sessions of {}",
+                &context.validation_result.user, user_id
+            )
+        });
+        context
+            .audit("deleteSessions", Some(user_id.to_string()), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    /// Create a token for scripts to call the API. The scopes are `read_only`, `user_management`
+    /// and `group_management`.
+    async fn create_api_token(
+        context: &Context<Handler>,
+        name: String,
+        scopes: Vec<String>,
+    ) -> FieldResult<CreatedApiToken> {
+        let span = debug_span!("[GraphQL mutation] create_api_token");
+        span.in_scope(|| {
+            debug!(?name, ?scopes);
+        });
+        // Tokens can't create more tokens.
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized API token creation".into());
+        }
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("The name of the token is required".into());
+        }
+        let scopes = scopes
+            .iter()
+            .map(|scope| scope.parse::<ApiTokenScope>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if scopes.is_empty() {
+            return Err("At least one scope is required".into());
+        }
+        let (token, secret) = context
+            .handler
+            .create_api_token(&context.validation_result.user, name, &scopes)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} created the API token {} ({})",
+                &context.validation_result.user, token.token_id, token.name
+            )
+        });
+        context
+            .audit(
+                "createApiToken",
+                Some(token.token_id.clone()),
+                Some(token.name.clone()),
+            )
+            .await;
+        Ok(CreatedApiToken {
+            token: token.into(),
+            secret,
+        })
+    }
+
+    /// Define a custom user attribute. The LDAP name defaults to the name, and can't be one of
+    /// the built-in attributes. The filters ignore the case of the values unless
+    /// `isCaseSensitive` is true.
+    async fn add_user_attribute(
+        context: &Context<Handler>,
+        name: String,
+        attribute_type: AttributeType,
+        is_list: bool,
+        is_editable: bool,
+        ldap_name: Option<String>,
+        is_case_sensitive: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_user_attribute");
+        span.in_scope(|| {
+            debug!(?name, ?attribute_type, ?ldap_name, ?is_case_sensitive);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user attribute creation".into());
+        }
+        let (name, ldap_name) = check_attribute_names(name, ldap_name, is_builtin_user_attribute)?;
+        context
+            .handler
+            .add_user_attribute(AttributeSchema {
+                name: name.clone(),
+                attribute_type: attribute_type.into(),
+                is_list,
+                is_editable,
+                ldap_name,
+                is_case_sensitive: is_case_sensitive.unwrap_or(false),
+            })
+            .instrument(span)
+            .await?;
+        context.audit("addUserAttribute", Some(name), None).await;
+        Ok(Success::new())
+    }
+
+    /// Delete a custom user attribute, with its values for all the users.
+    async fn delete_user_attribute(
+        context: &Context<Handler>,
+        name: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_user_attribute");
+        span.in_scope(|| {
+            debug!(?name);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized user attribute deletion".into());
+        }
+        context
+            .handler
+            .delete_user_attribute(&name)
+            .instrument(span)
+            .await?;
+        context.audit("deleteUserAttribute", Some(name), None).await;
+        Ok(Success::new())
+    }
+
+    /// Define a custom group attribute, like the user ones. The editable attributes can be changed
+    /// by the group managers.
+    async fn add_group_attribute(
+        context: &Context<Handler>,
+        name: String,
+        attribute_type: AttributeType,
+        is_list: bool,
+        is_editable: bool,
+        ldap_name: Option<String>,
+        is_case_sensitive: Option<bool>,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] add_group_attribute");
+        span.in_scope(|| {
+            debug!(?name, ?attribute_type, ?ldap_name, ?is_case_sensitive);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group attribute creation".into());
+        }
+        let (name, ldap_name) = check_attribute_names(name, ldap_name, is_builtin_group_attribute)?;
+        context
+            .handler
+            .add_group_attribute(AttributeSchema {
+                name: name.clone(),
+                attribute_type: attribute_type.into(),
+                is_list,
+                is_editable,
+                ldap_name,
+                is_case_sensitive: is_case_sensitive.unwrap_or(false),
+            })
+            .instrument(span)
+            .await?;
+        context.audit("addGroupAttribute", Some(name), None).await;
+        Ok(Success::new())
+    }
+
+    /// Delete a custom group attribute, with its values for all the groups.
+    async fn delete_group_attribute(
+        context: &Context<Handler>,
+        name: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_group_attribute");
+        span.in_scope(|| {
+            debug!(?name);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized group attribute deletion".into());
+        }
+        context
+            .handler
+            .delete_group_attribute(&name)
+            .instrument(span)
+            .await?;
+        context
+            .audit("deleteGroupAttribute", Some(name), None)
+            .await;
+        Ok(Success::new())
+    }
+
+    async fn delete_api_token(
+        context: &Context<Handler>,
+        token_id: String,
+    ) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_api_token");
+        span.in_scope(|| {
+            debug!(?token_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized API token deletion".into());
+        }
+        context
+            .handler
+            .delete_api_token(&token_id)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} deleted the API token {}",
+                &context.validation_result.user, token_id
+            )
+        });
+        context.audit("deleteApiToken", Some(token_id), None).await;
+        Ok(Success::new())
+    }
+
+    /// Post the given events to the URL, as JSON. The body is signed with the returned secret:
+    /// the `X-Lldap-Signature` header is `sha256=` followed by its hex-encoded HMAC-SHA256.
+    async fn create_webhook(
+        context: &Context<Handler>,
+        url: String,
+        events: Vec<String>,
+    ) -> FieldResult<CreatedWebhook> {
+        let span = debug_span!("[GraphQL mutation] create_webhook");
+        span.in_scope(|| {
+            debug!(?url, ?events);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized webhook creation".into());
+        }
+        let url = url.trim();
+        match reqwest::Url::parse(url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => (),
+            _ => return Err(format!("Invalid webhook URL: '{}'", url).into()),
+        }
+        let events = events
+            .iter()
+            .map(|event| event.parse::<WebhookEvent>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if events.is_empty() {
+            return Err("At least one event is required".into());
+        }
+        let webhook = context
+            .handler
+            .create_webhook(url, &events)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} created the webhook {} for {}",
+                &context.validation_result.user, webhook.webhook_id, webhook.url
+            )
+        });
+        context
+            .audit(
+                "createWebhook",
+                Some(format!("webhook {}", webhook.webhook_id)),
+                Some(webhook.url.clone()),
+            )
+            .await;
+        Ok(CreatedWebhook {
+            secret: webhook.secret.clone(),
+            webhook: webhook.into(),
+        })
+    }
+
+    async fn delete_webhook(context: &Context<Handler>, webhook_id: i32) -> FieldResult<Success> {
+        let span = debug_span!("[GraphQL mutation] delete_webhook");
+        span.in_scope(|| {
+            debug!(?webhook_id);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized webhook deletion".into());
+        }
+        context
+            .handler
+            .delete_webhook(webhook_id)
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            info!(
+                "{} deleted the webhook {}",
+                &context.validation_result.user, webhook_id
+            )
+        });
+        context
+            .audit(
+                "deleteWebhook",
+                Some(format!("webhook {}", webhook_id)),
+                None,
+            )
+            .await;
+        Ok(Success::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::MockTestBackendHandler,
+        types::{GroupDetails, User, UserAndGroups},
+    };
+    use crate::infra::auth_service::{Permission, ValidationResults};
+    use chrono::TimeZone;
+    use juniper::{execute, graphql_value, EmptySubscription, RootNode, Variables};
+    use mockall::predicate::eq;
+
+    fn make_group_details(id: i32, name: &str) -> GroupDetails {
+        GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        }
+    }
+
+    fn make_api_token_context(mock: MockTestBackendHandler) -> Context<MockTestBackendHandler> {
+        Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            validation_result: ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::ApiToken {
+                    user_management: false,
+                    group_management: true,
+                },
+            },
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
+        }
+    }
+
+    fn make_input(id: &str, email: &str) -> CreateUserInput {
+        CreateUserInput {
+            id: id.to_string(),
+            email: email.to_string(),
+            display_name: None,
+            first_name: None,
+            last_name: None,
+            avatar: None,
+            preferred_language: None,
+            email_aliases: None,
+            ssh_public_keys: None,
+            uid_number: None,
+            gid_number: None,
+            home_directory: None,
+            login_shell: None,
+            attributes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_valid() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Err(DomainError::EntityNotFound("bob".to_string())));
+        mock.expect_check_emails_available()
+            .with(
+                eq(UserId::new("bob")),
+                eq("bob@bob.bob"),
+                eq(Vec::<String>::new()),
+            )
+            .return_once(|_, _, _| Ok(()));
+        assert_eq!(
+            validate_create_user_input(&mock, &make_input("bob", "bob@bob.bob"), true)
+                .await
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_email_taken() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Err(DomainError::EntityNotFound("bob".to_string())));
+        mock.expect_check_emails_available().return_once(|_, _, _| {
+            Err(DomainError::EntityAlreadyExists(
+                "Email address already used by 'robert'".to_string(),
+            ))
+        });
+        let mut input = make_input("bob", "bob@bob.bob");
+        input.email_aliases = Some(vec!["robert@bob.bob".to_string()]);
+        assert_eq!(
+            validate_create_user_input(&mock, &input, true)
+                .await
+                .unwrap(),
+            vec![ValidationError::new(
+                "email",
+                "Email address already used by 'robert'"
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_invalid() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(User::default()));
+        let mut input = make_input("bob", "bob.bob");
+        input.avatar = Some("not base64!".to_string());
+        input.ssh_public_keys = Some(vec!["ssh-rsa".to_string()]);
+        assert_eq!(
+            validate_create_user_input(&mock, &input, true)
+                .await
+                .unwrap(),
+            vec![
+                ValidationError::new("id", "A user with this ID already exists"),
+                ValidationError::new("email", "Invalid email address"),
+                ValidationError::new("avatar", "Invalid base64 image"),
+                ValidationError::new(
+                    "sshPublicKeys",
+                    "Invalid SSH public key `ssh-rsa`: Invalid SSH key data"
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_attributes() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .return_once(|_| Err(DomainError::EntityNotFound("bob".to_string())));
+        mock.expect_check_emails_available()
+            .return_once(|_, _, _| Ok(()));
+        mock.expect_list_user_attribute_schema().return_once(|| {
+            Ok(vec![
+                AttributeSchema {
+                    name: "birthday".to_string(),
+                    attribute_type: crate::domain::types::AttributeType::Date,
+                    is_list: false,
+                    is_editable: false,
+                    ldap_name: "birthday".to_string(),
+                    is_case_sensitive: false,
+                },
+                AttributeSchema {
+                    name: "nickname".to_string(),
+                    attribute_type: crate::domain::types::AttributeType::String,
+                    is_list: false,
+                    is_editable: true,
+                    ldap_name: "nickname".to_string(),
+                    is_case_sensitive: false,
+                },
+            ])
+        });
+        let mut input = make_input("bob", "bob@bob.bob");
+        input.attributes = Some(vec![
+            AttributeValueInput {
+                name: "birthday".to_string(),
+                values: vec!["yesterday".to_string()],
+            },
+            AttributeValueInput {
+                name: "nickname".to_string(),
+                values: vec!["bobby".to_string(), "rob".to_string()],
+            },
+            AttributeValueInput {
+                name: "unknown".to_string(),
+                values: vec!["value".to_string()],
+            },
+        ]);
+        assert_eq!(
+            validate_create_user_input(&mock, &input, false)
+                .await
+                .unwrap(),
+            vec![
+                ValidationError::new("attributes", "Only admins can set the attribute birthday"),
+                ValidationError::new(
+                    "attributes",
+                    "Attribute birthday: Invalid date `yesterday`, expected YYYY-MM-DD"
+                ),
+                ValidationError::new(
+                    "attributes",
+                    "The attribute nickname is not a list and takes a single value"
+                ),
+                ValidationError::new("attributes", "Unknown attribute unknown"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_uid_number_taken() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Err(DomainError::EntityNotFound("bob".to_string())));
+        mock.expect_check_emails_available()
+            .return_once(|_, _, _| Ok(()));
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::UidNumber,
+                    "1000".to_string(),
+                ))),
+                eq(false),
+            )
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("robert"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut input = make_input("bob", "bob@bob.bob");
+        input.uid_number = Some(1000);
+        assert_eq!(
+            validate_create_user_input(&mock, &input, true)
+                .await
+                .unwrap(),
+            vec![ValidationError::new(
+                "uidNumber",
+                "The UID number is already used by 'robert'"
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_bad_id() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_check_emails_available()
+            .return_once(|_, _, _| Ok(()));
+        assert_eq!(
+            validate_create_user_input(&mock, &make_input("bob smith", "bob@bob.bob"), true)
+                .await
+                .unwrap(),
+            vec![ValidationError::new(
+                "id",
+                "The user ID cannot contain whitespace or control characters"
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_membership_already_member() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(3)))
+            .times(2)
+            .returning(|_, _| Err(DomainError::EntityAlreadyExists("bob".to_string())));
+        assert!(
+            add_membership(&mock, &UserId::new("bob"), GroupId(3), false, None)
+                .await
+                .is_ok()
+        );
+        assert!(
+            add_membership(&mock, &UserId::new("bob"), GroupId(3), true, None)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_membership_group_limit() {
+        let make_group = |id, name: &str| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        };
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(move |_| Ok([make_group(1, "a"), make_group(2, "b")].into()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("admin")))
+            .returning(move |_| Ok([make_group(1, "a"), make_group(2, "lldap_admin")].into()));
+        mock.expect_add_user_to_group()
+            .times(2)
+            .returning(|_, _| Ok(()));
+        let limit = Some(GroupLimit {
+            max_groups: 2,
+            exempt_admins: true,
+        });
+        let error = add_membership(&mock, &UserId::new("bob"), GroupId(3), false, limit)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.message(),
+            "bob is already a member of 2 groups, the maximum is 2"
+        );
+        // Already a member: not a new group.
+        add_membership(&mock, &UserId::new("bob"), GroupId(2), false, limit)
+            .await
+            .unwrap();
+        add_membership(&mock, &UserId::new("admin"), GroupId(3), false, limit)
+            .await
+            .unwrap();
+        let limit = Some(GroupLimit {
+            max_groups: 2,
+            exempt_admins: false,
+        });
+        add_membership(&mock, &UserId::new("admin"), GroupId(3), false, limit)
+            .await
+            .unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_api_token_cannot_change_lldap_groups() {
+        let schema = RootNode::new(
+            super::super::query::Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_group_details()
+            .with(eq(GroupId(2)))
+            .returning(|_| Ok(make_group_details(2, "lldap_password_manager")));
+        mock.expect_list_groups().times(1).return_once(|_| {
+            Ok(vec![crate::domain::types::Group {
+                id: GroupId(2),
+                display_name: "lldap_password_manager".to_string(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                users: vec![],
+                archived: false,
+                dynamic_filter: None,
+                gid_number: None,
+                email: None,
+                attributes: vec![],
+            }])
+        });
+        mock.expect_remove_user_from_group().never();
+        mock.expect_delete_group().never();
+        let context = make_api_token_context(mock);
+
+        let (_, errors) = execute(
+            r#"mutation { removeUserFromGroup(userId: "admin", groupId: 2) { ok } }"#,
+            None,
+            &schema,
+            &Variables::new(),
+            &context,
+        )
+        .await
+        .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().message(),
+            "Only admins can remove users from the lldap_ groups"
+        );
+
+        let (_, errors) = execute(
+            "mutation { deleteGroup(groupId: 2) { ok } }",
+            None,
+            &schema,
+            &Variables::new(),
+            &context,
+        )
+        .await
+        .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().message(),
+            "Only admins can delete the lldap_ groups"
+        );
+
+        assert_eq!(
+            execute(
+                "mutation { deleteGroups(groupIds: [2]) { id ok error } }",
+                None,
+                &schema,
+                &Variables::new(),
+                &context,
+            )
+            .await,
+            Ok((
+                graphql_value!({
+                    "deleteGroups": [{
+                        "id": 2,
+                        "ok": false,
+                        "error": "Only admins can delete the lldap_ groups",
+                    }]
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    fn make_manager_context(mock: MockTestBackendHandler) -> Context<MockTestBackendHandler> {
+        let mut context = make_api_token_context(mock);
+        context.validation_result = ValidationResults {
+            user: UserId::new("bob"),
+            permission: Permission::Regular,
+        };
+        context.membership_approval_groups = vec!["vault".to_string()];
+        context
+    }
+
+    fn make_membership_request(requested_by: &str) -> MembershipRequest {
+        MembershipRequest {
+            request_id: 1,
+            group_id: GroupId(3),
+            user_id: UserId::new("john"),
+            requested_by: UserId::new(requested_by),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            expiry_date: chrono::Utc.timestamp_opt(84, 42).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_membership_approval() {
+        let schema = RootNode::new(
+            super::super::query::Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_group_managers()
+            .with(eq(GroupId(3)))
+            .returning(|_| Ok(vec![UserId::new("bob")]));
+        mock.expect_get_group_details()
+            .with(eq(GroupId(3)))
+            .returning(|_| Ok(make_group_details(3, "Vault")));
+        mock.expect_add_user_to_group().never();
+        mock.expect_create_membership_request()
+            .with(
+                eq(GroupId(3)),
+                eq(UserId::new("john")),
+                eq(UserId::new("bob")),
+            )
+            .return_once(|_, _, _| Ok(make_membership_request("bob")));
+        mock.expect_get_membership_request()
+            .with(eq(1))
+            .returning(|_| Ok(make_membership_request("bob")));
+        mock.expect_approve_membership_request().never();
+        mock.expect_record_audit_event()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let context = make_manager_context(mock);
+
+        let (_, errors) = execute(
+            r#"mutation { addUserToGroup(userId: "john", groupId: 3) { ok } }"#,
+            None,
+            &schema,
+            &Variables::new(),
+            &context,
+        )
+        .await
+        .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().message(),
+            "The new members of this group have to be approved, use requestGroupMembership"
+        );
+
+        assert_eq!(
+            execute(
+                r#"mutation { requestGroupMembership(userId: "john", groupId: 3) { id } }"#,
+                None,
+                &schema,
+                &Variables::new(),
+                &context,
+            )
+            .await,
+            Ok((
+                graphql_value!({"requestGroupMembership": {"id": 1}}),
+                vec![]
+            ))
+        );
+
+        // The manager made the request, another approver has to approve it.
+        let (_, errors) = execute(
+            "mutation { approveMembershipRequest(requestId: 1) { ok } }",
+            None,
+            &schema,
+            &Variables::new(),
+            &context,
+        )
+        .await
+        .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].error().message(),
+            "Another approver has to approve this request"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_membership_request_approved_by_manager() {
+        let schema = RootNode::new(
+            super::super::query::Query::<MockTestBackendHandler>::new(),
+            Mutation::<MockTestBackendHandler>::new(),
+            EmptySubscription::<Context<MockTestBackendHandler>>::new(),
+        );
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_membership_request()
+            .with(eq(1))
+            .returning(|_| Ok(make_membership_request("john")));
+        mock.expect_get_group_details()
+            .with(eq(GroupId(3)))
+            .returning(|_| Ok(make_group_details(3, "Vault")));
+        mock.expect_list_group_managers()
+            .with(eq(GroupId(3)))
+            .returning(|_| Ok(vec![UserId::new("bob")]));
+        mock.expect_approve_membership_request()
+            .with(eq(1))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_record_audit_event()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let context = make_manager_context(mock);
+
+        assert_eq!(
+            execute(
+                "mutation { approveMembershipRequest(requestId: 1) { ok } }",
+                None,
+                &schema,
+                &Variables::new(),
+                &context,
+            )
+            .await,
+            Ok((
+                graphql_value!({"approveMembershipRequest": {"ok": true}}),
+                vec![]
+            ))
+        );
+    }
 }
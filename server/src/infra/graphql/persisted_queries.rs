@@ -0,0 +1,211 @@
+//! Persisted queries, following the "automatic persisted queries" protocol: clients can send the
+//! SHA-256 hash of a query in `extensions.persistedQuery.sha256Hash` instead of the query itself.
+//!
+//! Queries can be loaded from the `persisted_queries_dir` at startup, or registered by a client
+//! sending both the query and its hash. In `persisted_queries_only` mode, only the queries loaded
+//! at startup are accepted.
+
+use std::{collections::HashMap, path::Path, sync::RwLock};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Bound on the number of queries registered by clients, so that they can't fill up the memory.
+const MAX_REGISTERED_QUERIES: usize = 1000;
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PersistedQueryExtension {
+    pub version: i32,
+    pub sha256_hash: String,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestExtensions {
+    pub persisted_query: Option<PersistedQueryExtension>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PersistedQueryError {
+    // The message is part of the protocol: clients retry with the full query when they see it.
+    #[error("PersistedQueryNotFound")]
+    NotFound,
+    #[error("Unsupported persisted query version: {0}")]
+    UnsupportedVersion(i32),
+    #[error("The provided sha256Hash does not match the query")]
+    HashMismatch,
+    #[error("Only persisted queries are allowed")]
+    NotPersisted,
+    #[error("Missing query")]
+    MissingQuery,
+}
+
+pub fn hash_query(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
+
+/// Reads all the `.graphql` files of the directory, indexed by the hash of their contents.
+pub fn load_queries_from_directory(dir: &Path) -> Result<HashMap<String, String>> {
+    let mut queries = HashMap::new();
+    for entry in
+        std::fs::read_dir(dir).with_context(|| format!("Could not read {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().map(|e| e == "graphql") != Some(true) {
+            continue;
+        }
+        let query = std::fs::read_to_string(&path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        queries.insert(hash_query(&query), query);
+    }
+    Ok(queries)
+}
+
+pub struct PersistedQueries {
+    /// The queries loaded at startup, which are always accepted.
+    persisted: HashMap<String, String>,
+    /// The queries registered by clients.
+    registered: RwLock<HashMap<String, String>>,
+    persisted_only: bool,
+}
+
+impl PersistedQueries {
+    pub fn new(persisted: HashMap<String, String>, persisted_only: bool) -> Self {
+        Self {
+            persisted,
+            registered: RwLock::new(HashMap::new()),
+            persisted_only,
+        }
+    }
+
+    pub fn is_persisted_only(&self) -> bool {
+        self.persisted_only
+    }
+
+    fn get(&self, hash: &str) -> Option<String> {
+        self.persisted.get(hash).cloned().or_else(|| {
+            if self.persisted_only {
+                None
+            } else {
+                self.registered.read().unwrap().get(hash).cloned()
+            }
+        })
+    }
+
+    /// Returns the query to execute for a request, registering it if it comes with its hash.
+    pub fn resolve(
+        &self,
+        query: Option<String>,
+        extensions: Option<&RequestExtensions>,
+    ) -> std::result::Result<String, PersistedQueryError> {
+        let persisted_query = extensions.and_then(|e| e.persisted_query.as_ref());
+        let hash = match persisted_query {
+            None => {
+                return match query {
+                    None => Err(PersistedQueryError::MissingQuery),
+                    Some(_) if self.persisted_only => Err(PersistedQueryError::NotPersisted),
+                    Some(query) => Ok(query),
+                }
+            }
+            Some(PersistedQueryExtension { version, .. }) if *version != 1 => {
+                return Err(PersistedQueryError::UnsupportedVersion(*version))
+            }
+            Some(PersistedQueryExtension { sha256_hash, .. }) => sha256_hash.to_ascii_lowercase(),
+        };
+        if let Some(known_query) = self.get(&hash) {
+            return Ok(known_query);
+        }
+        let query = match query {
+            None if self.persisted_only => return Err(PersistedQueryError::NotPersisted),
+            None => return Err(PersistedQueryError::NotFound),
+            Some(query) => query,
+        };
+        if self.persisted_only {
+            return Err(PersistedQueryError::NotPersisted);
+        }
+        if hash_query(&query) != hash {
+            return Err(PersistedQueryError::HashMismatch);
+        }
+        let mut registered = self.registered.write().unwrap();
+        if registered.len() < MAX_REGISTERED_QUERIES {
+            registered.insert(hash, query.clone());
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY: &str = "query { apiVersion }";
+
+    fn make_extensions(hash: &str) -> RequestExtensions {
+        RequestExtensions {
+            persisted_query: Some(PersistedQueryExtension {
+                version: 1,
+                sha256_hash: hash.to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_automatic_registration() {
+        let queries = PersistedQueries::new(HashMap::new(), false);
+        let hash = hash_query(QUERY);
+        let extensions = make_extensions(&hash);
+        assert_eq!(
+            queries.resolve(None, Some(&extensions)),
+            Err(PersistedQueryError::NotFound)
+        );
+        assert_eq!(
+            queries.resolve(Some("query { other }".to_string()), Some(&extensions)),
+            Err(PersistedQueryError::HashMismatch)
+        );
+        assert_eq!(
+            queries.resolve(Some(QUERY.to_string()), Some(&extensions)),
+            Ok(QUERY.to_string())
+        );
+        assert_eq!(
+            queries.resolve(None, Some(&extensions)),
+            Ok(QUERY.to_string())
+        );
+        assert_eq!(
+            queries.resolve(Some("query { other }".to_string()), None),
+            Ok("query { other }".to_string())
+        );
+        assert_eq!(
+            queries.resolve(None, None),
+            Err(PersistedQueryError::MissingQuery)
+        );
+    }
+
+    #[test]
+    fn test_persisted_only() {
+        let hash = hash_query(QUERY);
+        let queries = PersistedQueries::new(
+            std::iter::once((hash.clone(), QUERY.to_string())).collect(),
+            true,
+        );
+        assert_eq!(
+            queries.resolve(None, Some(&make_extensions(&hash))),
+            Ok(QUERY.to_string())
+        );
+        let other = "query { other }".to_string();
+        let other_extensions = make_extensions(&hash_query(&other));
+        assert_eq!(
+            queries.resolve(Some(other.clone()), Some(&other_extensions)),
+            Err(PersistedQueryError::NotPersisted)
+        );
+        assert_eq!(
+            queries.resolve(None, Some(&other_extensions)),
+            Err(PersistedQueryError::NotPersisted)
+        );
+        assert_eq!(
+            queries.resolve(Some(QUERY.to_string()), None),
+            Err(PersistedQueryError::NotPersisted)
+        );
+    }
+}
@@ -1,9 +1,23 @@
-use crate::domain::{
-    handler::BackendHandler,
-    ldap::utils::map_user_field,
-    types::{GroupDetails, GroupId, UserColumn, UserId},
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{
+            AuditLogFilter, BackendHandler, GroupRequestFilter, PageRequest, SubStringFilter,
+        },
+        ldap::utils::{
+            get_group_id_from_distinguished_name, get_user_id_from_distinguished_name,
+            map_user_field, parse_distinguished_name,
+        },
+        types::{GroupColumn, GroupDetails, GroupId, UserColumn, UserId},
+    },
+    infra::{
+        membership_report::{
+            get_group_view_rows, get_user_view_rows, MembershipReportRow, MembershipReportView,
+        },
+        stats,
+    },
 };
-use juniper::{graphql_object, FieldResult, GraphQLInputObject};
+use juniper::{graphql_object, FieldResult, GraphQLEnum, GraphQLInputObject, GraphQLObject};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, debug_span, Instrument};
 
@@ -11,6 +25,13 @@ type DomainRequestFilter = crate::domain::handler::UserRequestFilter;
 type DomainUser = crate::domain::types::User;
 type DomainGroup = crate::domain::types::Group;
 type DomainUserAndGroups = crate::domain::types::UserAndGroups;
+type DomainApiToken = crate::domain::types::ApiToken;
+type DomainAttributeType = crate::domain::types::AttributeType;
+type DomainAttributeSchema = crate::domain::types::AttributeSchema;
+type DomainAttributeValue = crate::domain::types::AttributeValue;
+type DomainAuditLogEntry = crate::domain::types::AuditLogEntry;
+type DomainWebhook = crate::domain::types::Webhook;
+type DomainMembershipRequest = crate::domain::types::MembershipRequest;
 use super::api::Context;
 
 #[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
@@ -96,6 +117,391 @@ pub struct EqualityConstraint {
     value: String,
 }
 
+#[derive(PartialEq, Debug, GraphQLObject)]
+/// Operational statistics about the server. Counters are reset when the server restarts.
+pub struct Stats {
+    active_ldap_connections: i32,
+    successful_ldap_binds: i32,
+    failed_ldap_binds: i32,
+    graphql_requests: i32,
+    user_count: i32,
+    group_count: i32,
+    /// Size of the database in bytes, if the database can report it.
+    database_size: Option<f64>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The requirements for new passwords.
+pub struct PasswordPolicy {
+    /// Minimum length of new passwords, in bytes.
+    min_length: i32,
+    /// Minimum number of character classes (lowercase and uppercase letters, digits, symbols) in
+    /// new passwords, from 0 to 4.
+    min_character_classes: i32,
+    /// Minimum strength score of new passwords, from 0 to 4, as estimated by zxcvbn.
+    min_strength: i32,
+    /// Refused passwords, in lowercase. The comparison ignores the case.
+    banned_passwords: Vec<String>,
+    /// Number of days after which the passwords have to be changed, 0 if they don't expire.
+    max_age_days: i32,
+    /// Number of days before the expiration of their password from which the users are warned.
+    expiry_warning_days: i32,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A security key or passkey, to log in to the web UI without a password.
+pub struct WebauthnCredential {
+    credential_id: String,
+    name: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A login to the web UI, valid until it expires or is revoked.
+pub struct Session {
+    session_id: String,
+    /// Unknown for the sessions opened before it was recorded, like the IP and user agent.
+    creation_date: Option<chrono::DateTime<chrono::Utc>>,
+    expiry_date: chrono::DateTime<chrono::Utc>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A token for scripts to call the API, as `Authorization: Bearer <secret>`.
+pub struct ApiToken {
+    token_id: String,
+    name: String,
+    /// The admin who created the token.
+    creator_id: String,
+    /// `read_only`, `user_management` or `group_management`. All the tokens can read everything.
+    scopes: Vec<String>,
+    creation_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainApiToken> for ApiToken {
+    fn from(token: DomainApiToken) -> Self {
+        Self {
+            token_id: token.token_id,
+            name: token.name,
+            creator_id: token.user_id.into_string(),
+            scopes: token
+                .scopes
+                .iter()
+                .map(|scope| scope.as_str().to_owned())
+                .collect(),
+            creation_date: token.creation_date,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A URL to which the changes of the directory are posted.
+pub struct Webhook {
+    id: i32,
+    url: String,
+    /// `user_created`, `user_deleted`, `group_membership_changed` or `password_changed`.
+    events: Vec<String>,
+    creation_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainWebhook> for Webhook {
+    fn from(webhook: DomainWebhook) -> Self {
+        Self {
+            id: webhook.webhook_id,
+            url: webhook.url,
+            events: webhook
+                .events
+                .iter()
+                .map(|event| event.as_str().to_owned())
+                .collect(),
+            creation_date: webhook.creation_date,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A pending request to add a user to a group that requires an approval.
+pub struct MembershipRequest {
+    id: i32,
+    group_id: i32,
+    user_id: String,
+    /// The user themselves, or a manager of the group.
+    requested_by: String,
+    creation_date: chrono::DateTime<chrono::Utc>,
+    /// Past this date, the request is dropped.
+    expiry_date: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<DomainMembershipRequest> for MembershipRequest {
+    fn from(request: DomainMembershipRequest) -> Self {
+        Self {
+            id: request.request_id,
+            group_id: request.group_id.0,
+            user_id: request.user_id.into_string(),
+            requested_by: request.requested_by.into_string(),
+            creation_date: request.creation_date,
+            expiry_date: request.expiry_date,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A group of fields of the user details page.
+pub struct UserDetailsSection {
+    title: Option<String>,
+    /// The names of the fields, in order, e.g. `display_name`.
+    fields: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+pub enum AttributeType {
+    String,
+    Integer,
+    /// As `YYYY-MM-DD`.
+    Date,
+    /// As `true` or `false`.
+    Boolean,
+    /// In base64.
+    Binary,
+}
+
+impl From<DomainAttributeType> for AttributeType {
+    fn from(attribute_type: DomainAttributeType) -> Self {
+        match attribute_type {
+            DomainAttributeType::String => AttributeType::String,
+            DomainAttributeType::Integer => AttributeType::Integer,
+            DomainAttributeType::Date => AttributeType::Date,
+            DomainAttributeType::Boolean => AttributeType::Boolean,
+            DomainAttributeType::Binary => AttributeType::Binary,
+        }
+    }
+}
+
+impl From<AttributeType> for DomainAttributeType {
+    fn from(attribute_type: AttributeType) -> Self {
+        match attribute_type {
+            AttributeType::String => DomainAttributeType::String,
+            AttributeType::Integer => DomainAttributeType::Integer,
+            AttributeType::Date => DomainAttributeType::Date,
+            AttributeType::Boolean => DomainAttributeType::Boolean,
+            AttributeType::Binary => DomainAttributeType::Binary,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A custom user or group attribute, defined by the admins.
+pub struct AttributeSchema {
+    name: String,
+    attribute_type: AttributeType,
+    /// Whether the attribute can have several values.
+    is_list: bool,
+    /// Whether the users can change their own value, or the managers the value of their group.
+    is_editable: bool,
+    /// The name of the attribute in LDAP.
+    ldap_name: String,
+    /// Whether the filters compare the values as-is, rather than ignoring the case.
+    is_case_sensitive: bool,
+}
+
+impl From<DomainAttributeSchema> for AttributeSchema {
+    fn from(schema: DomainAttributeSchema) -> Self {
+        Self {
+            name: schema.name,
+            attribute_type: schema.attribute_type.into(),
+            is_list: schema.is_list,
+            is_editable: schema.is_editable,
+            ldap_name: schema.ldap_name,
+            is_case_sensitive: schema.is_case_sensitive,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// The values of a custom attribute for a user or a group.
+pub struct AttributeValue {
+    name: String,
+    values: Vec<String>,
+}
+
+impl From<DomainAttributeValue> for AttributeValue {
+    fn from(attribute: DomainAttributeValue) -> Self {
+        Self {
+            name: attribute.name,
+            values: attribute.values,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLInputObject)]
+/// Which entries of the audit log to list: all the set fields have to match.
+pub struct AuditLogFilterInput {
+    /// The user who did it, or who tried to log in.
+    actor: Option<String>,
+    /// E.g. `createUser`, `login` or `ldapBind`.
+    action: Option<String>,
+    target: Option<String>,
+    success: Option<bool>,
+    /// Inclusive.
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    /// Inclusive.
+    until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<AuditLogFilterInput> for AuditLogFilter {
+    fn from(filter: AuditLogFilterInput) -> Self {
+        Self {
+            actor: filter.actor.as_deref().map(UserId::new),
+            action: filter.action,
+            target: filter.target,
+            success: filter.success,
+            since: filter.since,
+            until: filter.until,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A mutation or an authentication event. The mutations are only recorded when they succeed.
+pub struct AuditLogEntry {
+    id: i32,
+    date: chrono::DateTime<chrono::Utc>,
+    /// Null for the logins of unknown users.
+    actor: Option<String>,
+    /// The GraphQL mutation, e.g. `createUser`, or `login`, `logout`, `ldapBind` or `ldapModify`.
+    action: String,
+    /// What the action was about, e.g. the ID of the user or `group 3`.
+    target: Option<String>,
+    ip_address: Option<String>,
+    success: bool,
+    details: Option<String>,
+}
+
+impl From<DomainAuditLogEntry> for AuditLogEntry {
+    fn from(entry: DomainAuditLogEntry) -> Self {
+        Self {
+            id: entry.entry_id,
+            date: entry.date,
+            actor: entry.actor.map(UserId::into_string),
+            action: entry.action,
+            target: entry.target,
+            ip_address: entry.ip_address,
+            success: entry.success,
+            details: entry.details,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A page of `auditLogs`.
+pub struct AuditLogPage {
+    nodes: Vec<AuditLogEntry>,
+    /// The number of entries matching the filters, in all the pages.
+    total_count: i32,
+    page_info: PageInfo,
+}
+
+fn to_graphql_int(value: u64) -> i32 {
+    i32::try_from(value).unwrap_or(i32::MAX)
+}
+
+/// The number of entries in a page of `usersPage`, `groupsPage` or `auditLogs` without `first`.
+const DEFAULT_PAGE_SIZE: i32 = 50;
+const MAX_PAGE_SIZE: i32 = 500;
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// Where a page ends, to get the next one.
+pub struct PageInfo {
+    /// To pass as `after` to get the next page. Null if the page is empty.
+    end_cursor: Option<String>,
+    has_next_page: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+pub enum UserOrderBy {
+    UserId,
+    Email,
+    DisplayName,
+    FirstName,
+    LastName,
+    CreationDate,
+}
+
+impl From<UserOrderBy> for UserColumn {
+    fn from(order_by: UserOrderBy) -> Self {
+        match order_by {
+            UserOrderBy::UserId => UserColumn::UserId,
+            UserOrderBy::Email => UserColumn::Email,
+            UserOrderBy::DisplayName => UserColumn::DisplayName,
+            UserOrderBy::FirstName => UserColumn::FirstName,
+            UserOrderBy::LastName => UserColumn::LastName,
+            UserOrderBy::CreationDate => UserColumn::CreationDate,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+pub enum GroupOrderBy {
+    GroupId,
+    DisplayName,
+    CreationDate,
+}
+
+impl From<GroupOrderBy> for GroupColumn {
+    fn from(order_by: GroupOrderBy) -> Self {
+        match order_by {
+            GroupOrderBy::GroupId => GroupColumn::GroupId,
+            GroupOrderBy::DisplayName => GroupColumn::DisplayName,
+            GroupOrderBy::CreationDate => GroupColumn::CreationDate,
+        }
+    }
+}
+
+/// The cursors are opaque to the clients, but they only hold the offset of the next page.
+fn encode_cursor(offset: u64) -> String {
+    base64::encode(format!("offset:{}", offset))
+}
+
+fn decode_cursor(cursor: &str) -> FieldResult<u64> {
+    base64::decode(cursor)
+        .ok()
+        .and_then(|cursor| String::from_utf8(cursor).ok())
+        .and_then(|cursor| cursor.strip_prefix("offset:")?.parse().ok())
+        .ok_or_else(|| "Invalid cursor".into())
+}
+
+/// The `first` entries after the `after` cursor, or from the start.
+fn get_page_request<Column>(
+    order_by: Column,
+    descending: Option<bool>,
+    first: Option<i32>,
+    after: Option<String>,
+) -> FieldResult<PageRequest<Column>> {
+    let first = first.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&first) {
+        return Err(format!("`first` must be between 1 and {}", MAX_PAGE_SIZE).into());
+    }
+    Ok(PageRequest {
+        order_by,
+        descending: descending.unwrap_or(false),
+        offset: after
+            .as_deref()
+            .map(decode_cursor)
+            .transpose()?
+            .unwrap_or(0),
+        limit: first as u64,
+    })
+}
+
+fn get_page_info(offset: u64, page_len: usize, total_count: u64) -> PageInfo {
+    let end = offset + page_len as u64;
+    PageInfo {
+        end_cursor: (page_len > 0).then(|| encode_cursor(end)),
+        has_next_page: end < total_count,
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
 /// The top-level GraphQL query type.
 pub struct Query<Handler: BackendHandler> {
@@ -134,46 +540,224 @@ impl<Handler: BackendHandler + Sync> Query<Handler> {
             .map(Into::into)?)
     }
 
+    /// Disabled users are left out, unless `includeDisabled` is true. Only the admins and the
+    /// read-only users can include them.
     async fn users(
         context: &Context<Handler>,
         #[graphql(name = "where")] filters: Option<RequestFilter>,
+        include_disabled: Option<bool>,
     ) -> FieldResult<Vec<User<Handler>>> {
         let span = debug_span!("[GraphQL query] users");
         span.in_scope(|| {
-            debug!(?filters);
+            debug!(?filters, ?include_disabled);
         });
-        if !context.validation_result.is_admin_or_readonly() {
+        // The group managers pick the new members among all the users.
+        if !context.validation_result.is_admin_or_readonly()
+            && !context.manages_any_group().instrument(span.clone()).await?
+        {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized access to user list".into());
         }
+        let filters = exclude_disabled_users(
+            context,
+            filters.map(TryInto::try_into).transpose()?,
+            include_disabled,
+        )?;
         Ok(context
             .handler
-            .list_users(filters.map(TryInto::try_into).transpose()?, false)
+            .list_users(filters, false)
             .instrument(span)
             .await
             .map(|v| v.into_iter().map(Into::into).collect())?)
     }
 
-    async fn groups(context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+    /// Like `users`, one page at a time, sorted by `orderBy` (the user ID by default).
+    async fn users_page(
+        context: &Context<Handler>,
+        filters: Option<RequestFilter>,
+        include_disabled: Option<bool>,
+        first: Option<i32>,
+        after: Option<String>,
+        order_by: Option<UserOrderBy>,
+        descending: Option<bool>,
+    ) -> FieldResult<UserPage<Handler>> {
+        let span = debug_span!("[GraphQL query] users_page");
+        span.in_scope(|| {
+            debug!(
+                ?filters,
+                ?include_disabled,
+                ?first,
+                ?after,
+                ?order_by,
+                ?descending
+            );
+        });
+        if !context.validation_result.is_admin_or_readonly()
+            && !context.manages_any_group().instrument(span.clone()).await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to user list".into());
+        }
+        let page = get_page_request(
+            order_by.unwrap_or(UserOrderBy::UserId).into(),
+            descending,
+            first,
+            after,
+        )?;
+        let offset = page.offset;
+        let filters = exclude_disabled_users(
+            context,
+            filters.map(TryInto::try_into).transpose()?,
+            include_disabled,
+        )?;
+        let users = context
+            .handler
+            .list_users_page(filters, page)
+            .instrument(span)
+            .await?;
+        Ok(UserPage {
+            page_info: get_page_info(offset, users.items.len(), users.total_count),
+            total_count: users.total_count,
+            nodes: users.items.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// Find the users whose id, email or name (depending on the configuration) contain the query,
+    /// case-insensitively. The best matches come first: exact matches, then prefixes. Like in
+    /// `users`, the disabled users are left out unless `includeDisabled` is true.
+    async fn search_users(
+        context: &Context<Handler>,
+        query: String,
+        include_disabled: Option<bool>,
+    ) -> FieldResult<Vec<User<Handler>>> {
+        let span = debug_span!("[GraphQL query] search_users");
+        span.in_scope(|| {
+            debug!(?query, ?include_disabled);
+        });
+        // The group managers pick the new members among all the users.
+        if !context.validation_result.is_admin_or_readonly()
+            && !context.manages_any_group().instrument(span.clone()).await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to user list".into());
+        }
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let filter = DomainRequestFilter::Or(
+            context
+                .user_search_columns
+                .iter()
+                .map(|&column| {
+                    DomainRequestFilter::Substring(column, SubStringFilter::contains(&query))
+                })
+                .collect(),
+        );
+        let filter = exclude_disabled_users(context, Some(filter), include_disabled)?;
+        let mut users = context
+            .handler
+            .list_users(filter, false)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .filter_map(|u| {
+                get_search_rank(&u.user, &query, &context.user_search_columns).map(|r| (r, u))
+            })
+            .collect::<Vec<_>>();
+        users.sort_by(|(r1, u1), (r2, u2)| {
+            r1.cmp(r2)
+                .then_with(|| u1.user.user_id.as_str().cmp(u2.user.user_id.as_str()))
+        });
+        Ok(users.into_iter().map(|(_, u)| u.into()).collect())
+    }
+
+    /// List the groups. Archived groups are hidden unless `archived` is true, in which case only
+    /// the archived groups are returned.
+    async fn groups(
+        context: &Context<Handler>,
+        archived: Option<bool>,
+    ) -> FieldResult<Vec<Group<Handler>>> {
         let span = debug_span!("[GraphQL query] groups");
+        span.in_scope(|| {
+            debug!(?archived);
+        });
         if !context.validation_result.is_admin_or_readonly() {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized access to group list".into());
         }
         Ok(context
             .handler
-            .list_groups(None)
+            .list_groups(Some(GroupRequestFilter::Archived(
+                archived.unwrap_or(false),
+            )))
             .instrument(span)
             .await
             .map(|v| v.into_iter().map(Into::into).collect())?)
     }
 
+    /// Like `groups`, one page at a time, sorted by `orderBy` (the display name by default).
+    /// `displayNameContains` only keeps the groups whose name contains it, case-insensitively.
+    async fn groups_page(
+        context: &Context<Handler>,
+        archived: Option<bool>,
+        display_name_contains: Option<String>,
+        first: Option<i32>,
+        after: Option<String>,
+        order_by: Option<GroupOrderBy>,
+        descending: Option<bool>,
+    ) -> FieldResult<GroupPage<Handler>> {
+        let span = debug_span!("[GraphQL query] groups_page");
+        span.in_scope(|| {
+            debug!(
+                ?archived,
+                ?display_name_contains,
+                ?first,
+                ?after,
+                ?order_by,
+                ?descending
+            );
+        });
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to group list".into());
+        }
+        let page = get_page_request(
+            order_by.unwrap_or(GroupOrderBy::DisplayName).into(),
+            descending,
+            first,
+            after,
+        )?;
+        let offset = page.offset;
+        let mut filters = vec![GroupRequestFilter::Archived(archived.unwrap_or(false))];
+        if let Some(name) = display_name_contains {
+            filters.push(GroupRequestFilter::DisplayNameSubstring(
+                SubStringFilter::contains(&name.to_lowercase()),
+            ));
+        }
+        let groups = context
+            .handler
+            .list_groups_page(Some(GroupRequestFilter::And(filters)), page)
+            .instrument(span)
+            .await?;
+        Ok(GroupPage {
+            page_info: get_page_info(offset, groups.items.len(), groups.total_count),
+            total_count: groups.total_count,
+            nodes: groups.items.into_iter().map(Into::into).collect(),
+        })
+    }
+
     async fn group(context: &Context<Handler>, group_id: i32) -> FieldResult<Group<Handler>> {
         let span = debug_span!("[GraphQL query] group");
         span.in_scope(|| {
             debug!(?group_id);
         });
-        if !context.validation_result.is_admin_or_readonly() {
+        if !context.validation_result.is_admin_or_readonly()
+            && !context
+                .manages_group(GroupId(group_id))
+                .instrument(span.clone())
+                .await?
+        {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized access to group data".into());
         }
@@ -184,6 +768,379 @@ impl<Handler: BackendHandler + Sync> Query<Handler> {
             .await
             .map(Into::into)?)
     }
+
+    fn password_policy(context: &Context<Handler>) -> PasswordPolicy {
+        let policy = &context.password_policy;
+        PasswordPolicy {
+            min_length: policy.min_length as i32,
+            min_character_classes: i32::from(policy.min_character_classes),
+            min_strength: i32::from(policy.min_strength),
+            banned_passwords: policy.banned_passwords.clone(),
+            max_age_days: context
+                .password_expiry
+                .map_or(0, |expiry| expiry.max_age_days as i32),
+            expiry_warning_days: context
+                .password_expiry
+                .map_or(0, |expiry| expiry.warning_days as i32),
+        }
+    }
+
+    /// The sections of the user details page, in order.
+    fn user_details_layout(context: &Context<Handler>) -> Vec<UserDetailsSection> {
+        context
+            .user_details_layout
+            .iter()
+            .map(|section| UserDetailsSection {
+                title: section.title.clone(),
+                fields: section.fields.iter().map(|f| f.name().to_owned()).collect(),
+            })
+            .collect()
+    }
+
+    /// The custom user attributes, by name.
+    async fn user_attribute_schema(
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<AttributeSchema>> {
+        let span = debug_span!("[GraphQL query] user_attribute_schema");
+        Ok(context
+            .handler
+            .list_user_attribute_schema()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The custom group attributes, by name.
+    async fn group_attribute_schema(
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<AttributeSchema>> {
+        let span = debug_span!("[GraphQL query] group_attribute_schema");
+        Ok(context
+            .handler
+            .list_group_attribute_schema()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Find the user or group with the given LDAP DN. Returns null if the DN is malformed, outside
+    /// of the base DN, or doesn't match any entry.
+    async fn resolve_dn(
+        context: &Context<Handler>,
+        dn: String,
+    ) -> FieldResult<Option<DnEntity<Handler>>> {
+        let span = debug_span!("[GraphQL query] resolve_dn");
+        span.in_scope(|| {
+            debug!(?dn);
+        });
+        let dn = dn.to_ascii_lowercase();
+        let base_dn_str = &context.ldap_base_dn;
+        let base_dn = parse_distinguished_name(base_dn_str)?;
+        if let Ok(user_id) = get_user_id_from_distinguished_name(&dn, &base_dn, base_dn_str) {
+            if !context.validation_result.can_read(&user_id) {
+                span.in_scope(|| debug!("Unauthorized"));
+                return Err("Unauthorized access to user data".into());
+            }
+            return match context
+                .handler
+                .get_user_details(&user_id)
+                .instrument(span)
+                .await
+            {
+                Ok(user) => Ok(Some(DnEntity {
+                    user: Some(user.into()),
+                    group: None,
+                })),
+                Err(DomainError::EntityNotFound(_)) => Ok(None),
+                Err(e) => Err(e.into()),
+            };
+        }
+        if let Ok(group_name) = get_group_id_from_distinguished_name(&dn, &base_dn, base_dn_str) {
+            if !context.validation_result.is_admin_or_readonly() {
+                span.in_scope(|| debug!("Unauthorized"));
+                return Err("Unauthorized access to group data".into());
+            }
+            let groups = context
+                .handler
+                .list_groups(Some(GroupRequestFilter::DisplayName(group_name)))
+                .instrument(span)
+                .await?;
+            return Ok(groups.into_iter().next().map(|group| DnEntity {
+                user: None,
+                group: Some(group.into()),
+            }));
+        }
+        span.in_scope(|| debug!("Not a user or group DN"));
+        Ok(None)
+    }
+
+    /// The API tokens of all the admins.
+    async fn api_tokens(context: &Context<Handler>) -> FieldResult<Vec<ApiToken>> {
+        let span = debug_span!("[GraphQL query] api_tokens");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the API tokens".into());
+        }
+        Ok(context
+            .handler
+            .list_api_tokens()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn webhooks(context: &Context<Handler>) -> FieldResult<Vec<Webhook>> {
+        let span = debug_span!("[GraphQL query] webhooks");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the webhooks".into());
+        }
+        Ok(context
+            .handler
+            .list_webhooks()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// The pending membership requests: all of them for the admins, and otherwise the ones for
+    /// or by the logged in user. The managers see the requests of their groups on the groups.
+    async fn membership_requests(
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<MembershipRequest>> {
+        let span = debug_span!("[GraphQL query] membership_requests");
+        let is_admin = context.validation_result.is_admin_or_readonly();
+        let user = &context.validation_result.user;
+        Ok(context
+            .handler
+            .list_membership_requests(None)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .filter(|r| is_admin || &r.user_id == user || &r.requested_by == user)
+            .map(Into::into)
+            .collect())
+    }
+
+    async fn stats(context: &Context<Handler>) -> FieldResult<Stats> {
+        let span = debug_span!("[GraphQL query] stats");
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to server statistics".into());
+        }
+        let backend_stats = context.handler.get_stats().instrument(span).await?;
+        let counters = stats::get_counters();
+        Ok(Stats {
+            active_ldap_connections: to_graphql_int(counters.active_ldap_connections),
+            successful_ldap_binds: to_graphql_int(counters.successful_ldap_binds),
+            failed_ldap_binds: to_graphql_int(counters.failed_ldap_binds),
+            graphql_requests: to_graphql_int(counters.graphql_requests),
+            user_count: to_graphql_int(backend_stats.user_count),
+            group_count: to_graphql_int(backend_stats.group_count),
+            database_size: backend_stats.database_size.map(|size| size as f64),
+        })
+    }
+
+    /// Who is in which group, for the access reviews.
+    async fn membership_report(
+        context: &Context<Handler>,
+        view: Option<MembershipReportView>,
+    ) -> FieldResult<Vec<MembershipReportRow>> {
+        let view = view.unwrap_or_default();
+        let span = debug_span!("[GraphQL query] membership_report", ?view);
+        if !context.validation_result.is_admin_or_readonly() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the membership report".into());
+        }
+        let rows = match view {
+            MembershipReportView::Group => {
+                let groups = context
+                    .handler
+                    .list_groups(None)
+                    .instrument(span.clone())
+                    .await?;
+                let users = context
+                    .handler
+                    .list_users(None, false)
+                    .instrument(span.clone())
+                    .await?
+                    .into_iter()
+                    .map(|u| (u.user.user_id.as_str().to_owned(), u.user))
+                    .collect();
+                get_group_view_rows(&groups, &users)
+            }
+            MembershipReportView::User => {
+                let users = context
+                    .handler
+                    .list_users(None, true)
+                    .instrument(span.clone())
+                    .await?;
+                get_user_view_rows(&users)
+            }
+        };
+        span.in_scope(|| debug!(rows = rows.len()));
+        Ok(rows)
+    }
+
+    /// The entries of the audit log, newest first.
+    async fn audit_logs(
+        context: &Context<Handler>,
+        filters: Option<AuditLogFilterInput>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<AuditLogPage> {
+        let span = debug_span!("[GraphQL query] audit_logs");
+        span.in_scope(|| {
+            debug!(?filters, ?first, ?after);
+        });
+        if !context.validation_result.is_admin() {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the audit log".into());
+        }
+        // Always sorted by date.
+        let page = get_page_request((), None, first, after)?;
+        let entries = context
+            .handler
+            .list_audit_logs(
+                filters.map(Into::into).unwrap_or_default(),
+                page.offset,
+                page.limit,
+            )
+            .instrument(span)
+            .await?;
+        Ok(AuditLogPage {
+            page_info: get_page_info(page.offset, entries.items.len(), entries.total_count),
+            total_count: to_graphql_int(entries.total_count),
+            nodes: entries.items.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+/// Adds the exclusion of the disabled users to the filter, unless they are asked for by an admin or
+/// a read-only user.
+fn exclude_disabled_users<Handler: BackendHandler>(
+    context: &Context<Handler>,
+    filter: Option<DomainRequestFilter>,
+    include_disabled: Option<bool>,
+) -> FieldResult<Option<DomainRequestFilter>> {
+    if include_disabled == Some(true) {
+        if !context.validation_result.is_admin_or_readonly() {
+            return Err("Unauthorized access to the disabled users".into());
+        }
+        return Ok(filter);
+    }
+    Ok(Some(match filter {
+        None => DomainRequestFilter::Enabled(true),
+        Some(filter) => DomainRequestFilter::And(vec![filter, DomainRequestFilter::Enabled(true)]),
+    }))
+}
+
+/// How well the user matches the lowercase search query, lower is better: exact matches first,
+/// then prefixes, then substrings, each ordered by the priority of the column. `None` if the user
+/// doesn't match.
+fn get_search_rank(user: &DomainUser, query: &str, columns: &[UserColumn]) -> Option<(u8, usize)> {
+    columns
+        .iter()
+        .enumerate()
+        .flat_map(|(index, column)| {
+            let values = match column {
+                UserColumn::UserId => vec![user.user_id.as_str()],
+                UserColumn::Email => std::iter::once(user.email.as_str())
+                    .chain(user.email_aliases.iter().map(String::as_str))
+                    .collect(),
+                UserColumn::DisplayName => user.display_name.as_deref().into_iter().collect(),
+                UserColumn::FirstName => user.first_name.as_deref().into_iter().collect(),
+                UserColumn::LastName => user.last_name.as_deref().into_iter().collect(),
+                UserColumn::PreferredLanguage => {
+                    user.preferred_language.as_deref().into_iter().collect()
+                }
+                _ => vec![],
+            };
+            values.into_iter().filter_map(move |value| {
+                let value = value.to_lowercase();
+                if value == query {
+                    Some((0, index))
+                } else if value.starts_with(query) {
+                    Some((1, index))
+                } else if value.contains(query) {
+                    Some((2, index))
+                } else {
+                    None
+                }
+            })
+        })
+        .min()
+}
+
+/// A page of `usersPage`.
+pub struct UserPage<Handler: BackendHandler> {
+    nodes: Vec<User<Handler>>,
+    total_count: u64,
+    page_info: PageInfo,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> UserPage<Handler> {
+    fn nodes(&self) -> &[User<Handler>] {
+        &self.nodes
+    }
+
+    /// The number of users matching the filters, in all the pages.
+    fn total_count(&self) -> i32 {
+        to_graphql_int(self.total_count)
+    }
+
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+}
+
+/// A page of `groupsPage`.
+pub struct GroupPage<Handler: BackendHandler> {
+    nodes: Vec<Group<Handler>>,
+    total_count: u64,
+    page_info: PageInfo,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> GroupPage<Handler> {
+    fn nodes(&self) -> &[Group<Handler>] {
+        &self.nodes
+    }
+
+    /// The number of groups matching the filters, in all the pages.
+    fn total_count(&self) -> i32 {
+        to_graphql_int(self.total_count)
+    }
+
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+}
+
+/// The entry matching an LDAP DN: either a user or a group.
+pub struct DnEntity<Handler: BackendHandler> {
+    user: Option<User<Handler>>,
+    group: Option<Group<Handler>>,
+}
+
+#[graphql_object(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync> DnEntity<Handler> {
+    fn user(&self) -> Option<&User<Handler>> {
+        self.user.as_ref()
+    }
+
+    fn group(&self) -> Option<&Group<Handler>> {
+        self.group.as_ref()
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
@@ -213,6 +1170,11 @@ impl<Handler: BackendHandler + Sync> User<Handler> {
         &self.user.email
     }
 
+    /// Additional email addresses, on top of the primary `email`.
+    fn email_aliases(&self) -> Vec<String> {
+        self.user.email_aliases.clone()
+    }
+
     fn display_name(&self) -> &str {
         self.user.display_name.as_deref().unwrap_or("")
     }
@@ -229,14 +1191,161 @@ impl<Handler: BackendHandler + Sync> User<Handler> {
         self.user.avatar.as_ref().map(String::from)
     }
 
+    /// The user's preferred language, as a BCP 47 tag. Unset means the server default.
+    fn preferred_language(&self) -> Option<&str> {
+        self.user.preferred_language.as_deref()
+    }
+
+    /// The public SSH keys of the user, in the OpenSSH format.
+    fn ssh_public_keys(&self) -> Vec<String> {
+        self.user.ssh_public_keys.clone()
+    }
+
+    /// The values of the custom attributes that are set, by name.
+    fn attributes(&self) -> Vec<AttributeValue> {
+        self.user
+            .attributes
+            .iter()
+            .cloned()
+            .map(Into::into)
+            .collect()
+    }
+
+    /// The POSIX user ID, for posixAccount.
+    fn uid_number(&self) -> Option<i32> {
+        self.user.uid_number
+    }
+
+    /// The ID of the primary POSIX group of the user.
+    fn gid_number(&self) -> Option<i32> {
+        self.user.gid_number
+    }
+
+    fn home_directory(&self) -> Option<&str> {
+        self.user.home_directory.as_deref()
+    }
+
+    fn login_shell(&self) -> Option<&str> {
+        self.user.login_shell.as_deref()
+    }
+
     fn creation_date(&self) -> chrono::DateTime<chrono::Utc> {
         self.user.creation_date
     }
 
+    /// When the password has to be changed, if the passwords expire and the user has one.
+    fn password_expiration_date(
+        &self,
+        context: &Context<Handler>,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        context.password_expiry?.expiration_date(&self.user)
+    }
+
+    /// When the account was locked after too many wrong passwords, null if it isn't. A locked
+    /// user can't log in with their password until an admin unlocks them, or until their next
+    /// login after `account_lockout_minutes`.
+    fn locked_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user.locked_date
+    }
+
+    /// The account can't be used to log in before that date.
+    fn activation_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user.activation_date
+    }
+
+    /// The account can't be used to log in from that date on.
+    fn expiration_date(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.user.expiration_date
+    }
+
+    /// Whether the user is emailed when they log in to the web UI from a new device.
+    fn login_notifications(&self) -> bool {
+        self.user.login_notifications
+    }
+
+    /// A disabled user can't log in, with LDAP or the web UI, until an admin enables them again.
+    fn enabled(&self) -> bool {
+        self.user.enabled
+    }
+
     fn uuid(&self) -> &str {
         self.user.uuid.as_str()
     }
 
+    /// Whether the user needs a TOTP code to log in to the web UI.
+    async fn totp_enabled(&self, context: &Context<Handler>) -> FieldResult<bool> {
+        let span = debug_span!("[GraphQL query] user::totp_enabled");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        Ok(context
+            .handler
+            .is_totp_enabled(&self.user.user_id)
+            .instrument(span)
+            .await?)
+    }
+
+    /// The security keys and passkeys of the user, registered from the web UI. Only visible to the
+    /// user and the admins.
+    async fn webauthn_credentials(
+        &self,
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<WebauthnCredential>> {
+        let span = debug_span!("[GraphQL query] user::webauthn_credentials");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        if !context
+            .validation_result
+            .is_admin_or_user(&self.user.user_id)
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the credentials of the user".into());
+        }
+        Ok(context
+            .handler
+            .list_webauthn_credentials(&self.user.user_id)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(|c| WebauthnCredential {
+                credential_id: c.credential_id,
+                name: c.name,
+                creation_date: c.creation_date,
+            })
+            .collect())
+    }
+
+    /// The sessions of the user in the web UI that haven't expired, oldest first. Only visible to
+    /// the user and the admins.
+    async fn sessions(&self, context: &Context<Handler>) -> FieldResult<Vec<Session>> {
+        let span = debug_span!("[GraphQL query] user::sessions");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        if !context
+            .validation_result
+            .is_admin_or_user(&self.user.user_id)
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the sessions of the user".into());
+        }
+        Ok(context
+            .handler
+            .list_sessions(&self.user.user_id)
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(|s| Session {
+                session_id: s.session_id.to_string(),
+                creation_date: s.creation_date,
+                expiry_date: s.expiry_date,
+                ip_address: s.metadata.ip_address,
+                user_agent: s.metadata.user_agent,
+            })
+            .collect())
+    }
+
     /// The groups to which this user belongs.
     async fn groups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
         let span = debug_span!("[GraphQL query] user::groups");
@@ -250,6 +1359,20 @@ impl<Handler: BackendHandler + Sync> User<Handler> {
             .await
             .map(|set| set.into_iter().map(Into::into).collect())?)
     }
+
+    /// The groups of which this user can change the members without being an admin.
+    async fn managed_groups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+        let span = debug_span!("[GraphQL query] user::managed_groups");
+        span.in_scope(|| {
+            debug!(user_id = ?self.user.user_id);
+        });
+        Ok(context
+            .handler
+            .list_groups(Some(GroupRequestFilter::Manager(self.user.user_id.clone())))
+            .instrument(span)
+            .await
+            .map(|v| v.into_iter().map(Into::into).collect())?)
+    }
 }
 
 impl<Handler: BackendHandler> From<DomainUser> for User<Handler> {
@@ -277,7 +1400,13 @@ pub struct Group<Handler: BackendHandler> {
     display_name: String,
     creation_date: chrono::DateTime<chrono::Utc>,
     uuid: String,
+    archived: bool,
+    dynamic_filter: Option<String>,
+    gid_number: Option<i32>,
+    email: Option<String>,
     members: Option<Vec<String>>,
+    /// Only known when built from a `DomainGroup`, fetched on demand otherwise.
+    attributes: Option<Vec<DomainAttributeValue>>,
     _phantom: std::marker::PhantomData<Box<Handler>>,
 }
 
@@ -295,13 +1424,34 @@ impl<Handler: BackendHandler + Sync> Group<Handler> {
     fn uuid(&self) -> String {
         self.uuid.clone()
     }
+    /// Archived groups are hidden from LDAP and don't grant any permission.
+    fn archived(&self) -> bool {
+        self.archived
+    }
+    /// For dynamic groups, the LDAP filter defining the members.
+    fn dynamic_filter(&self) -> Option<&str> {
+        self.dynamic_filter.as_deref()
+    }
+    /// The POSIX group ID, for posixGroup.
+    fn gid_number(&self) -> Option<i32> {
+        self.gid_number
+    }
+    /// The address of the group as a mailing list.
+    fn email(&self) -> Option<&str> {
+        self.email.as_deref()
+    }
     /// The groups to which this user belongs.
     async fn users(&self, context: &Context<Handler>) -> FieldResult<Vec<User<Handler>>> {
         let span = debug_span!("[GraphQL query] group::users");
         span.in_scope(|| {
             debug!(name = %self.display_name);
         });
-        if !context.validation_result.is_admin_or_readonly() {
+        if !context.validation_result.is_admin_or_readonly()
+            && !context
+                .manages_group(GroupId(self.group_id))
+                .instrument(span.clone())
+                .await?
+        {
             span.in_scope(|| debug!("Unauthorized"));
             return Err("Unauthorized access to group data".into());
         }
@@ -315,6 +1465,105 @@ impl<Handler: BackendHandler + Sync> Group<Handler> {
             .await
             .map(|v| v.into_iter().map(Into::into).collect())?)
     }
+    /// The groups this group is directly nested in.
+    async fn parent_groups(&self, context: &Context<Handler>) -> FieldResult<Vec<Group<Handler>>> {
+        let span = debug_span!("[GraphQL query] group::parent_groups");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        if !context.validation_result.is_admin_or_readonly()
+            && !context
+                .manages_group(GroupId(self.group_id))
+                .instrument(span.clone())
+                .await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to group data".into());
+        }
+        Ok(context
+            .handler
+            .list_group_nestings()
+            .instrument(span)
+            .await?
+            .into_iter()
+            .filter(|n| n.child_group_id.0 == self.group_id)
+            .map(|n| n.parent_group.into())
+            .collect())
+    }
+    /// The users who can change the members of the group without being admins.
+    async fn managers(&self, context: &Context<Handler>) -> FieldResult<Vec<String>> {
+        let span = debug_span!("[GraphQL query] group::managers");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        if !context.validation_result.is_admin_or_readonly()
+            && !context
+                .manages_group(GroupId(self.group_id))
+                .instrument(span.clone())
+                .await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to group data".into());
+        }
+        Ok(context
+            .handler
+            .list_group_managers(GroupId(self.group_id))
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(UserId::into_string)
+            .collect())
+    }
+    /// Whether the new members have to be approved: the managers can only ask for them to be
+    /// added, with `requestGroupMembership`.
+    fn requires_approval(&self, context: &Context<Handler>) -> bool {
+        context.requires_approval(&self.display_name)
+    }
+    /// The pending requests to join the group, oldest first.
+    async fn membership_requests(
+        &self,
+        context: &Context<Handler>,
+    ) -> FieldResult<Vec<MembershipRequest>> {
+        let span = debug_span!("[GraphQL query] group::membership_requests");
+        span.in_scope(|| {
+            debug!(name = %self.display_name);
+        });
+        if !context.validation_result.is_admin_or_readonly()
+            && !context
+                .manages_group(GroupId(self.group_id))
+                .instrument(span.clone())
+                .await?
+        {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to group data".into());
+        }
+        Ok(context
+            .handler
+            .list_membership_requests(Some(GroupId(self.group_id)))
+            .instrument(span)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+    /// The values of the custom attributes that are set, by name.
+    async fn attributes(&self, context: &Context<Handler>) -> FieldResult<Vec<AttributeValue>> {
+        let attributes = match &self.attributes {
+            Some(attributes) => attributes.clone(),
+            None => {
+                let span = debug_span!("[GraphQL query] group::attributes");
+                span.in_scope(|| {
+                    debug!(name = %self.display_name);
+                });
+                context
+                    .handler
+                    .get_group_attributes(GroupId(self.group_id))
+                    .instrument(span)
+                    .await?
+            }
+        };
+        Ok(attributes.into_iter().map(Into::into).collect())
+    }
 }
 
 impl<Handler: BackendHandler> From<GroupDetails> for Group<Handler> {
@@ -324,7 +1573,12 @@ impl<Handler: BackendHandler> From<GroupDetails> for Group<Handler> {
             display_name: group_details.display_name,
             creation_date: group_details.creation_date,
             uuid: group_details.uuid.into_string(),
+            archived: group_details.archived,
+            dynamic_filter: group_details.dynamic_filter,
+            gid_number: group_details.gid_number,
+            email: group_details.email,
             members: None,
+            attributes: None,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -337,7 +1591,12 @@ impl<Handler: BackendHandler> From<DomainGroup> for Group<Handler> {
             display_name: group.display_name,
             creation_date: group.creation_date,
             uuid: group.uuid.into_string(),
+            archived: group.archived,
+            dynamic_filter: group.dynamic_filter,
+            gid_number: group.gid_number,
+            email: group.email,
             members: Some(group.users.into_iter().map(UserId::into_string).collect()),
+            attributes: Some(group.attributes),
             _phantom: std::marker::PhantomData,
         }
     }
@@ -346,7 +1605,10 @@ impl<Handler: BackendHandler> From<DomainGroup> for Group<Handler> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{domain::handler::MockTestBackendHandler, infra::auth_service::ValidationResults};
+    use crate::{
+        domain::handler::MockTestBackendHandler,
+        infra::auth_service::{Permission, ValidationResults},
+    };
     use chrono::TimeZone;
     use juniper::{
         execute, graphql_value, DefaultScalarValue, EmptyMutation, EmptySubscription, GraphQLType,
@@ -400,6 +1662,10 @@ mod tests {
             display_name: "Bobbersons".to_string(),
             creation_date: chrono::Utc.timestamp_nanos(42),
             uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
         });
         mock.expect_get_user_groups()
             .with(eq(UserId::new("bob")))
@@ -408,6 +1674,24 @@ mod tests {
         let context = Context::<MockTestBackendHandler> {
             handler: Box::new(mock),
             validation_result: ValidationResults::admin(),
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
         };
 
         let schema = schema(Query::<MockTestBackendHandler>::new());
@@ -455,12 +1739,15 @@ mod tests {
         let mut mock = MockTestBackendHandler::new();
         mock.expect_list_users()
             .with(
-                eq(Some(DomainRequestFilter::Or(vec![
-                    DomainRequestFilter::UserId(UserId::new("bob")),
-                    DomainRequestFilter::Equality(
-                        UserColumn::Email,
-                        "robert@bobbers.on".to_string(),
-                    ),
+                eq(Some(DomainRequestFilter::And(vec![
+                    DomainRequestFilter::Or(vec![
+                        DomainRequestFilter::UserId(UserId::new("bob")),
+                        DomainRequestFilter::Equality(
+                            UserColumn::Email,
+                            "robert@bobbers.on".to_string(),
+                        ),
+                    ]),
+                    DomainRequestFilter::Enabled(true),
                 ]))),
                 eq(false),
             )
@@ -488,6 +1775,24 @@ mod tests {
         let context = Context::<MockTestBackendHandler> {
             handler: Box::new(mock),
             validation_result: ValidationResults::admin(),
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
         };
 
         let schema = schema(Query::<MockTestBackendHandler>::new());
@@ -511,4 +1816,486 @@ mod tests {
             ))
         );
     }
+
+    #[tokio::test]
+    async fn users_page() {
+        const QUERY: &str = r#"{
+          usersPage(first: 2, after: "b2Zmc2V0OjI=", orderBy: EMAIL, descending: true) {
+            nodes {
+              id
+            }
+            totalCount
+            pageInfo {
+              endCursor
+              hasNextPage
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users_page()
+            .with(
+                eq(Some(DomainRequestFilter::Enabled(true))),
+                eq(PageRequest {
+                    order_by: UserColumn::Email,
+                    descending: true,
+                    offset: 2,
+                    limit: 2,
+                }),
+            )
+            .return_once(|_, _| {
+                Ok(crate::domain::handler::Page {
+                    items: vec![DomainUser {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    }],
+                    total_count: 3,
+                })
+            });
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            validation_result: ValidationResults::admin(),
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "usersPage": {
+                        "nodes": [{"id": "bob"}],
+                        "totalCount": 3,
+                        "pageInfo": {
+                            "endCursor": "b2Zmc2V0OjM=",
+                            "hasNextPage": false,
+                        },
+                    }
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_cursor() {
+        assert_eq!(decode_cursor(&encode_cursor(42)).unwrap(), 42);
+        assert!(decode_cursor("42").is_err());
+        assert!(decode_cursor(&base64::encode("offset:-1")).is_err());
+    }
+
+    #[tokio::test]
+    async fn audit_logs() {
+        const QUERY: &str = r#"{
+          auditLogs(filters: {actor: "Bob", success: false}, first: 10) {
+            nodes {
+              id
+              date
+              actor
+              action
+              ipAddress
+              success
+              details
+            }
+            totalCount
+            pageInfo {
+              endCursor
+              hasNextPage
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_audit_logs()
+            .with(
+                eq(AuditLogFilter {
+                    actor: Some(UserId::new("bob")),
+                    success: Some(false),
+                    ..Default::default()
+                }),
+                eq(0),
+                eq(10),
+            )
+            .return_once(|_, _, _| {
+                Ok(crate::domain::handler::Page {
+                    items: vec![DomainAuditLogEntry {
+                        entry_id: 7,
+                        date: chrono::Utc.timestamp_millis_opt(42).unwrap(),
+                        actor: Some(UserId::new("bob")),
+                        action: "login".to_string(),
+                        target: None,
+                        ip_address: Some("192.0.2.1".to_string()),
+                        success: false,
+                        details: Some("Invalid TOTP code".to_string()),
+                    }],
+                    total_count: 1,
+                })
+            });
+
+        let mut context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            validation_result: ValidationResults::admin(),
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "auditLogs": {
+                        "nodes": [{
+                            "id": 7,
+                            "date": "1970-01-01T00:00:00.042+00:00",
+                            "actor": "bob",
+                            "action": "login",
+                            "ipAddress": "192.0.2.1",
+                            "success": false,
+                            "details": "Invalid TOTP code",
+                        }],
+                        "totalCount": 1,
+                        "pageInfo": {
+                            "endCursor": "b2Zmc2V0OjE=",
+                            "hasNextPage": false,
+                        },
+                    }
+                }),
+                vec![]
+            ))
+        );
+
+        // Only the admins can read it.
+        context.validation_result = ValidationResults {
+            user: UserId::new("bob"),
+            permission: Permission::Regular,
+        };
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn search_users() {
+        const QUERY: &str = r#"{
+          searchUsers(query: " Bob") {
+            id
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_users()
+            .with(
+                eq(Some(DomainRequestFilter::And(vec![
+                    DomainRequestFilter::Or(vec![
+                        DomainRequestFilter::Substring(
+                            UserColumn::UserId,
+                            SubStringFilter::contains("bob"),
+                        ),
+                        DomainRequestFilter::Substring(
+                            UserColumn::Email,
+                            SubStringFilter::contains("bob"),
+                        ),
+                    ]),
+                    DomainRequestFilter::Enabled(true),
+                ]))),
+                eq(false),
+            )
+            .return_once(|_, _| {
+                let make_user = |id: &str, email: &str| DomainUserAndGroups {
+                    user: DomainUser {
+                        user_id: UserId::new(id),
+                        email: email.to_string(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                };
+                Ok(vec![
+                    make_user("robert", "Bob@bobbers.on"),
+                    make_user("alice", "alice@bobbers.on"),
+                    make_user("bobby", "bobby@bobbers.on"),
+                    make_user("bob", "bob@bobbers.on"),
+                    make_user("jimbob", "jim@bobbers.on"),
+                ])
+            });
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            validation_result: ValidationResults::admin(),
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "searchUsers": [
+                        {"id": "bob"},
+                        {"id": "bobby"},
+                        {"id": "robert"},
+                        {"id": "jimbob"},
+                    ]
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn group_manager_can_read_group() {
+        const QUERY: &str = r#"{
+          group(groupId: 3) {
+            displayName
+            managers
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_group_managers()
+            .with(eq(GroupId(3)))
+            .times(2)
+            .returning(|_| Ok(vec![UserId::new("bob")]));
+        mock.expect_get_group_details()
+            .with(eq(GroupId(3)))
+            .return_once(|_| {
+                Ok(GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: "Bobbersons".to_string(),
+                    creation_date: chrono::Utc.timestamp_nanos(42),
+                    uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                })
+            });
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            validation_result: ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+            },
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "group": {
+                        "displayName": "Bobbersons",
+                        "managers": ["bob"],
+                    }
+                }),
+                vec![]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn sessions_are_private() {
+        const QUERY: &str = r#"{
+          user(userId: "bob") {
+            id
+            sessions {
+              sessionId
+            }
+            webauthnCredentials {
+              name
+            }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(DomainUser {
+                    user_id: UserId::new("bob"),
+                    email: "bob@bobbers.on".to_string(),
+                    ..Default::default()
+                })
+            });
+        mock.expect_list_sessions().never();
+        mock.expect_list_webauthn_credentials().never();
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            validation_result: ValidationResults {
+                user: UserId::new("alice"),
+                permission: Permission::Readonly,
+            },
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        let (_, errors) = execute(QUERY, None, &schema, &Variables::new(), &context)
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].error().message(),
+            "Unauthorized access to the sessions of the user"
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_dn() {
+        const QUERY: &str = r#"{
+          user: resolveDn(dn: "uid=Bob,ou=people,dc=example,dc=com") {
+            user { id }
+            group { id }
+          }
+          unknown: resolveDn(dn: "uid=bob,ou=people,dc=other,dc=com") {
+            user { id }
+          }
+          malformed: resolveDn(dn: "not a dn") {
+            user { id }
+          }
+        }"#;
+
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| {
+                Ok(DomainUser {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+
+        let context = Context::<MockTestBackendHandler> {
+            handler: Box::new(mock),
+            validation_result: ValidationResults::admin(),
+            ldap_base_dn: "dc=example,dc=com".to_string(),
+            password_policy: Default::default(),
+            password_expiry: None,
+            group_assignment_rules: vec![],
+            user_details_layout: vec![],
+            server_url: "http://localhost".to_string(),
+            enable_magic_login_links: false,
+            user_search_columns: vec![UserColumn::UserId, UserColumn::Email],
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            group_limit: None,
+            membership_approval_groups: vec![],
+            mail_options: Default::default(),
+            default_language: "en".to_string(),
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            ip_address: None,
+            changes: tokio::sync::broadcast::channel(1).0,
+        };
+
+        let schema = schema(Query::<MockTestBackendHandler>::new());
+        assert_eq!(
+            execute(QUERY, None, &schema, &Variables::new(), &context).await,
+            Ok((
+                graphql_value!(
+                {
+                    "user": {
+                        "user": {"id": "bob"},
+                        "group": None,
+                    },
+                    "unknown": None,
+                    "malformed": None,
+                }),
+                vec![]
+            ))
+        );
+    }
 }
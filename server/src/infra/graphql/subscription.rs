@@ -0,0 +1,341 @@
+use crate::{
+    domain::{
+        handler::{BackendHandler, GroupRequestFilter},
+        types::DirectoryChange,
+    },
+    infra::auth_service::{SessionToken, ValidationResults},
+};
+use futures::Stream;
+use juniper::{graphql_subscription, FieldError, FieldResult, GraphQLEnum, GraphQLObject};
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, debug_span, Instrument};
+
+use super::api::Context;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, GraphQLEnum)]
+pub enum DirectoryChangeType {
+    UserCreated,
+    UserUpdated,
+    UserDeleted,
+    PasswordChanged,
+    GroupCreated,
+    GroupUpdated,
+    GroupDeleted,
+    MembershipChanged,
+}
+
+#[derive(PartialEq, Eq, Debug, GraphQLObject)]
+/// A change of the directory, to refresh what is displayed without polling.
+pub struct DirectoryChangeEvent {
+    change_type: DirectoryChangeType,
+    user_id: Option<String>,
+    group_id: Option<i32>,
+    /// For a membership change, whether the user joined the group or left it.
+    added: Option<bool>,
+}
+
+impl From<DirectoryChange> for DirectoryChangeEvent {
+    fn from(change: DirectoryChange) -> Self {
+        let (change_type, added) = match &change {
+            DirectoryChange::UserCreated(_) => (DirectoryChangeType::UserCreated, None),
+            DirectoryChange::UserUpdated(_) => (DirectoryChangeType::UserUpdated, None),
+            DirectoryChange::UserDeleted(_) => (DirectoryChangeType::UserDeleted, None),
+            DirectoryChange::PasswordChanged(_) => (DirectoryChangeType::PasswordChanged, None),
+            DirectoryChange::GroupCreated(_) => (DirectoryChangeType::GroupCreated, None),
+            DirectoryChange::GroupUpdated(_) => (DirectoryChangeType::GroupUpdated, None),
+            DirectoryChange::GroupDeleted(_) => (DirectoryChangeType::GroupDeleted, None),
+            DirectoryChange::MembershipChanged { added, .. } => {
+                (DirectoryChangeType::MembershipChanged, Some(*added))
+            }
+        };
+        Self {
+            change_type,
+            user_id: change.user_id().map(|user_id| user_id.to_string()),
+            group_id: change.group_id().map(|group_id| group_id.0),
+            added,
+        }
+    }
+}
+
+type DirectoryChangeStream = Pin<Box<dyn Stream<Item = FieldResult<DirectoryChangeEvent>> + Send>>;
+
+/// Decides which changes a subscriber gets. The connection outlives the token and the permissions
+/// it was opened with, so they are checked again for each change.
+struct ChangeFilter<Handler> {
+    handler: Handler,
+    jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
+    /// `None` keeps the permissions of `validation_result`.
+    session_token: Option<SessionToken>,
+    validation_result: ValidationResults,
+}
+
+impl<Handler: BackendHandler> ChangeFilter<Handler> {
+    /// Whether to send the change, or `None` once the subscriber isn't allowed anymore: the token
+    /// expired, was logged out or revoked, or the user doesn't manage any group. The admins and the
+    /// read-only users get every change. The group managers only get the changes of the groups
+    /// they manage and of their members, without the password changes.
+    async fn check(&self, change: &DirectoryChange) -> FieldResult<Option<bool>> {
+        let user = &self.validation_result.user;
+        let permission = match &self.session_token {
+            None => self.validation_result.permission.clone(),
+            Some(token) => match token
+                .validate(&self.handler, &self.jwt_blacklist, user)
+                .await?
+            {
+                None => return Ok(None),
+                Some(permission) => permission,
+            },
+        };
+        let validation_result = ValidationResults {
+            user: user.clone(),
+            permission,
+        };
+        if validation_result.is_admin_or_readonly() {
+            return Ok(Some(true));
+        }
+        let managed_groups = self
+            .handler
+            .list_groups(Some(GroupRequestFilter::Manager(user.clone())))
+            .await?;
+        if managed_groups.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(match (change.group_id(), change.user_id()) {
+            (Some(group_id), _) => managed_groups.iter().any(|g| g.id == group_id),
+            (None, _) if matches!(change, DirectoryChange::PasswordChanged(_)) => false,
+            // The deleted users aren't members of any group anymore.
+            (None, Some(user_id)) => managed_groups.iter().any(|g| g.users.contains(user_id)),
+            (None, None) => false,
+        }))
+    }
+}
+
+/// The next change to send, skipping the filtered ones. `None` ends the subscription.
+async fn next_change<Handler: BackendHandler>(
+    changes: &mut broadcast::Receiver<DirectoryChange>,
+    filter: &ChangeFilter<Handler>,
+) -> Option<FieldResult<DirectoryChangeEvent>> {
+    loop {
+        let change = match changes.recv().await {
+            Ok(change) => change,
+            // The client should reload everything.
+            Err(RecvError::Lagged(missed)) => {
+                return Some(Err(FieldError::from(format!(
+                    "{} changes were missed",
+                    missed
+                ))))
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        match filter.check(&change).await {
+            Ok(Some(true)) => return Some(Ok(change.into())),
+            Ok(Some(false)) => continue,
+            Ok(None) => {
+                debug!("Ending the subscription: not allowed anymore");
+                return None;
+            }
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+/// The top-level GraphQL subscription type, served over a WebSocket.
+pub struct Subscription<Handler: BackendHandler> {
+    _phantom: std::marker::PhantomData<Box<Handler>>,
+}
+
+impl<Handler: BackendHandler> Subscription<Handler> {
+    pub fn new() -> Self {
+        Self {
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+#[graphql_subscription(context = Context<Handler>)]
+impl<Handler: BackendHandler + Sync + 'static> Subscription<Handler> {
+    /// The changes of the users, groups and memberships, as they are committed. The permissions
+    /// are checked like for the user list, and again for each change: the group managers only get
+    /// the changes of their groups and of their members, and the subscription ends when the token
+    /// isn't valid anymore.
+    async fn directory_changes(context: &Context<Handler>) -> FieldResult<DirectoryChangeStream> {
+        let span = debug_span!("[GraphQL subscription] directory_changes");
+        let is_admin_or_readonly = context.validation_result.is_admin_or_readonly();
+        if !is_admin_or_readonly && !context.manages_any_group().instrument(span.clone()).await? {
+            span.in_scope(|| debug!("Unauthorized"));
+            return Err("Unauthorized access to the directory changes".into());
+        }
+        let filter = ChangeFilter {
+            handler: (*context.handler).clone(),
+            jwt_blacklist: context.jwt_blacklist.clone(),
+            session_token: context.session_token.clone(),
+            validation_result: context.validation_result.clone(),
+        };
+        let stream = futures::stream::unfold(
+            (context.changes.subscribe(), filter),
+            |(mut changes, filter)| async move {
+                let item = next_change(&mut changes, &filter).await?;
+                Some((item, (changes, filter)))
+            },
+        );
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uuid;
+    use crate::{
+        domain::{
+            handler::MockTestBackendHandler,
+            types::{Group, GroupDetails, GroupId, UserId},
+        },
+        infra::auth_service::Permission,
+    };
+    use chrono::TimeZone;
+    use mockall::predicate::eq;
+
+    #[tokio::test]
+    async fn test_group_manager_filter() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Manager(UserId::new("bob")))))
+            .returning(|_| {
+                Ok(vec![Group {
+                    id: GroupId(3),
+                    display_name: "Family".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    users: vec![UserId::new("alice")],
+                    attributes: vec![],
+                }])
+            });
+        let filter = ChangeFilter {
+            handler: mock,
+            jwt_blacklist: Default::default(),
+            session_token: None,
+            validation_result: ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+            },
+        };
+        let membership_change = |group_id| DirectoryChange::MembershipChanged {
+            user_id: UserId::new("alice"),
+            group_id: GroupId(group_id),
+            added: true,
+        };
+        assert_eq!(
+            filter.check(&membership_change(3)).await.unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            filter.check(&membership_change(4)).await.unwrap(),
+            Some(false)
+        );
+        assert_eq!(
+            filter
+                .check(&DirectoryChange::UserUpdated(UserId::new("alice")))
+                .await
+                .unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            filter
+                .check(&DirectoryChange::UserUpdated(UserId::new("carol")))
+                .await
+                .unwrap(),
+            Some(false)
+        );
+        assert_eq!(
+            filter
+                .check(&DirectoryChange::PasswordChanged(UserId::new("alice")))
+                .await
+                .unwrap(),
+            Some(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_token_is_checked_again() {
+        let mut mock = MockTestBackendHandler::new();
+        let admin_group = GroupDetails {
+            group_id: GroupId(1),
+            display_name: "lldap_admin".to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        };
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(move |_| Ok([admin_group].into()));
+        // Then bob is demoted, and doesn't manage any group.
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| Ok(Default::default()));
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Manager(UserId::new("bob")))))
+            .times(1)
+            .return_once(|_| Ok(vec![]));
+        let filter = ChangeFilter {
+            handler: mock,
+            jwt_blacklist: Default::default(),
+            session_token: Some(SessionToken::Jwt {
+                hash: 42,
+                expiry: chrono::Utc::now() + chrono::Duration::days(1),
+            }),
+            validation_result: ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Admin,
+            },
+        };
+        let change = DirectoryChange::PasswordChanged(UserId::new("alice"));
+        assert_eq!(filter.check(&change).await.unwrap(), Some(true));
+        assert_eq!(filter.check(&change).await.unwrap(), None);
+        // A logged out session is refused without reading anything.
+        filter.jwt_blacklist.write().unwrap().insert(42);
+        assert_eq!(filter.check(&change).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_directory_change_event() {
+        assert_eq!(
+            DirectoryChangeEvent::from(DirectoryChange::MembershipChanged {
+                user_id: UserId::new("bob"),
+                group_id: GroupId(3),
+                added: true,
+            }),
+            DirectoryChangeEvent {
+                change_type: DirectoryChangeType::MembershipChanged,
+                user_id: Some("bob".to_owned()),
+                group_id: Some(3),
+                added: Some(true),
+            }
+        );
+        assert_eq!(
+            DirectoryChangeEvent::from(DirectoryChange::GroupDeleted(GroupId(3))),
+            DirectoryChangeEvent {
+                change_type: DirectoryChangeType::GroupDeleted,
+                user_id: None,
+                group_id: Some(3),
+                added: None,
+            }
+        );
+    }
+}
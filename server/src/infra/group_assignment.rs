@@ -0,0 +1,201 @@
+//! Automatic assignment of users to static groups, based on the `group_assignment_rules` from the
+//! configuration. Rules only ever add memberships: removing a user from an assigned group by hand
+//! sticks until the user is updated again and still matches the rule.
+
+use tracing::{info, instrument, warn};
+
+use crate::{
+    domain::{
+        error::{DomainError, Result},
+        handler::{BackendHandler, GroupRequestFilter, UserRequestFilter},
+        ldap::filter::parse_user_filter,
+        types::UserId,
+    },
+    infra::configuration::GroupAssignmentRule,
+};
+
+/// Checks that the rule can be applied, before starting the server.
+pub fn validate_rule(rule: &GroupAssignmentRule) -> std::result::Result<(), String> {
+    parse_user_filter(&rule.filter)
+        .map_err(|e| format!("Invalid filter `{}`: {}", rule.filter, e))?;
+    // Otherwise, regular users could grant themselves permissions by editing their own details.
+    if rule.group.starts_with("lldap_") {
+        return Err(format!(
+            "Cannot automatically assign users to the `{}` group",
+            rule.group
+        ));
+    }
+    Ok(())
+}
+
+/// Adds the user to the groups of all the rules it matches, and returns the names of the groups
+/// it was added to. Groups the user is already a member of are skipped, so this is idempotent.
+#[instrument(skip(handler, rules), level = "debug")]
+pub async fn apply_group_assignment_rules<Handler: BackendHandler>(
+    handler: &Handler,
+    rules: &[GroupAssignmentRule],
+    user_id: &UserId,
+) -> Result<Vec<String>> {
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut user_groups = handler
+        .get_user_groups(user_id)
+        .await?
+        .into_iter()
+        .map(|g| g.display_name)
+        .collect::<std::collections::HashSet<_>>();
+    let mut assigned_groups = Vec::new();
+    for rule in rules {
+        if user_groups.contains(&rule.group) {
+            continue;
+        }
+        let filter = parse_user_filter(&rule.filter).map_err(DomainError::InternalError)?;
+        let matches = !handler
+            .list_users(
+                Some(UserRequestFilter::And(vec![
+                    filter,
+                    UserRequestFilter::UserId(user_id.clone()),
+                ])),
+                false,
+            )
+            .await?
+            .is_empty();
+        if !matches {
+            continue;
+        }
+        let groups = handler
+            .list_groups(Some(GroupRequestFilter::DisplayName(rule.group.clone())))
+            .await?;
+        match groups.first() {
+            None => warn!(
+                "Group assignment rule refers to unknown group `{}`",
+                rule.group
+            ),
+            Some(group) if group.dynamic_filter.is_some() => {
+                warn!(
+                    "Group assignment rule refers to dynamic group `{}`",
+                    rule.group
+                )
+            }
+            Some(group) => {
+                handler.add_user_to_group(user_id, group.id).await?;
+                user_groups.insert(rule.group.clone());
+                assigned_groups.push(rule.group.clone());
+            }
+        }
+    }
+    if !assigned_groups.is_empty() {
+        info!("Automatically added {} to {:?}", user_id, assigned_groups);
+    }
+    Ok(assigned_groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{
+        handler::MockTestBackendHandler,
+        types::{Group, GroupDetails, GroupId, User, UserAndGroups, UserColumn},
+    };
+    use crate::uuid;
+    use chrono::TimeZone;
+    use mockall::predicate::eq;
+    use std::collections::HashSet;
+
+    fn make_rule(filter: &str, group: &str) -> GroupAssignmentRule {
+        GroupAssignmentRule {
+            filter: filter.to_string(),
+            group: group.to_string(),
+        }
+    }
+
+    fn make_group(id: i32, name: &str) -> Group {
+        Group {
+            id: GroupId(id),
+            display_name: name.to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            users: vec![],
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate_rule() {
+        assert!(validate_rule(&make_rule("(preferredLanguage=fr)", "french")).is_ok());
+        assert!(validate_rule(&make_rule("(preferredLanguage>=fr)", "french")).is_err());
+        assert!(validate_rule(&make_rule("(preferredLanguage=fr)", "lldap_admin")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_group_assignment_rules() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                let mut groups = HashSet::new();
+                groups.insert(GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: "already".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                });
+                Ok(groups)
+            });
+        let user_filter = |column, value: &str| {
+            Some(UserRequestFilter::And(vec![
+                UserRequestFilter::Equality(column, value.to_string()),
+                UserRequestFilter::UserId(UserId::new("bob")),
+            ]))
+        };
+        mock.expect_list_users()
+            .with(
+                eq(user_filter(UserColumn::PreferredLanguage, "fr")),
+                eq(false),
+            )
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        mock.expect_list_users()
+            .with(eq(user_filter(UserColumn::LastName, "Smith")), eq(false))
+            .return_once(|_, _| Ok(vec![]));
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName(
+                "french".to_string(),
+            ))))
+            .return_once(|_| Ok(vec![make_group(2, "french")]));
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(2)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        let rules = vec![
+            make_rule("(preferredLanguage=fr)", "french"),
+            make_rule("(preferredLanguage=fr)", "already"),
+            make_rule("(sn=Smith)", "smiths"),
+            // The user was just added to the group by the first rule.
+            make_rule("(preferredLanguage=fr)", "french"),
+        ];
+        assert_eq!(
+            apply_group_assignment_rules(&mock, &rules, &UserId::new("bob"))
+                .await
+                .unwrap(),
+            vec!["french".to_string()]
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! The keys signing the JWTs of the sessions.
+//!
+//! With HS512, the default, the JWTs are signed with `jwt_secret`, that the applications verifying
+//! them have to know. To change it, the old secret is moved to `jwt_previous_secrets`, where it
+//! is still accepted for `jwt_key_grace_period_hours` after the first start that found it there.
+//! That date is recorded next to the server key, in `jwt_previous_secrets.dates`, so that the
+//! restarts don't extend it.
+//!
+//! With RS256, they are signed with an RSA key generated on the first start next to the server
+//! key, and the applications get the public keys from `/auth/jwks`. `lldap rotate_jwt_key`
+//! replaces it: the previous keys are still published and accepted for
+//! `jwt_key_grace_period_hours` after the rotation, then deleted by the next rotation.
+//!
+//! The JWTs name their key in the `kid` header. The `jwt` crate reads and writes them; it only
+//! supports RSA through OpenSSL, so the RS256 signatures themselves are computed with `rsa`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{prelude::*, Duration};
+use hmac::{Hmac, Mac, NewMac};
+use jwt::{
+    algorithm::{SigningAlgorithm, VerifyingAlgorithm},
+    header::HeaderType,
+    AlgorithmType, Header, SignWithKey, Token, VerifyWithKey,
+};
+use rsa::{Hash, PaddingScheme, PublicKey, PublicKeyParts, RsaPrivateKey};
+use secstr::SecUtf8;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256, Sha512};
+use tracing::{debug, info};
+
+use lldap_auth::JWTClaims;
+
+use crate::infra::{
+    configuration::{Configuration, JwtAlgorithm},
+    oidc::get_signing_key,
+};
+
+const KEY_FILE_PREFIX: &str = "jwt_key";
+const SECRET_DATES_FILE: &str = "jwt_previous_secrets.dates";
+
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn rsa_padding() -> PaddingScheme {
+    PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256))
+}
+
+enum SigningKey {
+    Hmac(Hmac<Sha512>),
+    Rsa(RsaPrivateKey),
+}
+
+struct JwtKey {
+    key_id: String,
+    key: SigningKey,
+    /// Past this date, the JWTs signed by this previous key aren't accepted anymore.
+    expiry_date: Option<DateTime<Utc>>,
+}
+
+impl JwtKey {
+    fn hmac(secret: &SecUtf8) -> Self {
+        let key = Hmac::<Sha512>::new_varkey(secret.unsecure().as_bytes())
+            .expect("HMAC accepts keys of any size");
+        // Derived from the secret, but doesn't reveal anything about it.
+        let mut mac = key.clone();
+        mac.update(b"lldap jwt key id");
+        let key_id = base64_url(&mac.finalize().into_bytes())[..16].to_string();
+        Self {
+            key_id,
+            key: SigningKey::Hmac(key),
+            expiry_date: None,
+        }
+    }
+
+    fn rsa(key: RsaPrivateKey) -> Self {
+        let key_id = base64_url(&Sha256::digest(&key.n().to_bytes_be()))[..16].to_string();
+        Self {
+            key_id,
+            key: SigningKey::Rsa(key),
+            expiry_date: None,
+        }
+    }
+
+    fn expiring_at(self, expiry_date: DateTime<Utc>) -> Self {
+        Self {
+            expiry_date: Some(expiry_date),
+            ..self
+        }
+    }
+
+    fn algorithm(&self) -> &'static str {
+        match self.key {
+            SigningKey::Hmac(_) => "HS512",
+            SigningKey::Rsa(_) => "RS256",
+        }
+    }
+}
+
+impl SigningAlgorithm for JwtKey {
+    fn algorithm_type(&self) -> AlgorithmType {
+        match self.key {
+            SigningKey::Hmac(_) => AlgorithmType::Hs512,
+            SigningKey::Rsa(_) => AlgorithmType::Rs256,
+        }
+    }
+
+    fn sign(&self, header: &str, claims: &str) -> Result<String, jwt::Error> {
+        match &self.key {
+            SigningKey::Hmac(key) => key.sign(header, claims),
+            SigningKey::Rsa(key) => {
+                let message = format!("{}.{}", header, claims);
+                let signature = key
+                    .sign(rsa_padding(), &Sha256::digest(message.as_bytes()))
+                    .expect("Could not sign the JWT");
+                Ok(base64_url(&signature))
+            }
+        }
+    }
+}
+
+impl VerifyingAlgorithm for JwtKey {
+    fn algorithm_type(&self) -> AlgorithmType {
+        SigningAlgorithm::algorithm_type(self)
+    }
+
+    fn verify_bytes(
+        &self,
+        header: &str,
+        claims: &str,
+        signature: &[u8],
+    ) -> Result<bool, jwt::Error> {
+        match &self.key {
+            SigningKey::Hmac(key) => key.verify_bytes(header, claims, signature),
+            SigningKey::Rsa(key) => {
+                let message = format!("{}.{}", header, claims);
+                Ok(key
+                    .to_public_key()
+                    .verify(
+                        rsa_padding(),
+                        &Sha256::digest(message.as_bytes()),
+                        signature,
+                    )
+                    .is_ok())
+            }
+        }
+    }
+}
+
+fn get_key_path(key_file: &str) -> PathBuf {
+    Path::new(key_file).with_file_name(format!("{}.pem", KEY_FILE_PREFIX))
+}
+
+/// The keys replaced by [`rotate_key`], renamed `jwt_key.<rotation timestamp>.pem`, with their
+/// rotation date.
+fn get_previous_key_files(key_path: &Path) -> Result<Vec<(DateTime<Utc>, PathBuf)>> {
+    let directory = match key_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory)
+        .with_context(|| format!("Could not list the directory `{}`", directory.display()))?
+    {
+        let path = entry?.path();
+        let rotation_date = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(KEY_FILE_PREFIX)?.strip_prefix('.'))
+            .and_then(|name| name.strip_suffix(".pem")?.parse::<i64>().ok())
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single());
+        if let Some(rotation_date) = rotation_date {
+            files.push((rotation_date, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The dates at which the previous secrets with these key IDs were replaced, from the contents of
+/// the dates file: one `<key id> <timestamp>` per line. The secrets not listed yet were replaced
+/// `now`. Also returns the new contents of the file, without the secrets that were removed from
+/// the configuration.
+fn update_replacement_dates(
+    contents: &str,
+    key_ids: &[&str],
+    now: DateTime<Utc>,
+) -> (Vec<DateTime<Utc>>, String) {
+    let known_dates = contents
+        .lines()
+        .filter_map(|line| {
+            let (key_id, timestamp) = line.trim().split_once(' ')?;
+            let date = Utc.timestamp_opt(timestamp.parse().ok()?, 0).single()?;
+            Some((key_id, date))
+        })
+        .collect::<std::collections::HashMap<_, _>>();
+    let dates = key_ids
+        .iter()
+        .map(|key_id| known_dates.get(key_id).copied().unwrap_or(now))
+        .collect::<Vec<_>>();
+    let contents = key_ids
+        .iter()
+        .zip(dates.iter())
+        .map(|(key_id, date)| format!("{} {}\n", key_id, date.timestamp()))
+        .collect();
+    (dates, contents)
+}
+
+/// The dates at which `jwt_previous_secrets` were replaced, recorded in the dates file the first
+/// time the server starts with them.
+fn get_replacement_dates(key_file: &str, key_ids: &[&str]) -> Result<Vec<DateTime<Utc>>> {
+    let path = Path::new(key_file).with_file_name(SECRET_DATES_FILE);
+    let contents = if path.exists() {
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("Could not read `{}`", path.display()))?
+    } else {
+        String::new()
+    };
+    let (dates, new_contents) = update_replacement_dates(&contents, key_ids, Utc::now());
+    if new_contents != contents {
+        std::fs::write(&path, new_contents)
+            .with_context(|| format!("Could not write `{}`", path.display()))?;
+    }
+    Ok(dates)
+}
+
+pub struct JwtKeys {
+    /// Signs the new JWTs.
+    current: JwtKey,
+    /// Only verify the JWTs they signed before being replaced.
+    previous: Vec<JwtKey>,
+}
+
+impl JwtKeys {
+    pub fn from_config(config: &Configuration) -> Result<Self> {
+        let grace_period = Duration::hours(config.jwt_key_grace_period_hours.into());
+        Ok(match config.jwt_algorithm {
+            JwtAlgorithm::Hs512 => {
+                let previous = config
+                    .jwt_previous_secrets
+                    .iter()
+                    .map(JwtKey::hmac)
+                    .collect::<Vec<_>>();
+                // The server had to restart to use the new secret, so no JWT signed with a
+                // previous one is more recent than the first start that found it there.
+                let key_ids = previous
+                    .iter()
+                    .map(|k| k.key_id.as_str())
+                    .collect::<Vec<_>>();
+                let dates = get_replacement_dates(&config.key_file, &key_ids)?;
+                Self {
+                    current: JwtKey::hmac(config.get_jwt_secret()),
+                    previous: previous
+                        .into_iter()
+                        .zip(dates)
+                        .map(|(key, date)| key.expiring_at(date + grace_period))
+                        .collect(),
+                }
+            }
+            JwtAlgorithm::Rs256 => {
+                let key_path = get_key_path(&config.key_file);
+                let current = JwtKey::rsa(get_signing_key(&key_path, "JWT")?);
+                let mut previous = Vec::new();
+                for (rotation_date, path) in get_previous_key_files(&key_path)? {
+                    let expiry_date = rotation_date + grace_period;
+                    if expiry_date < Utc::now() {
+                        debug!("Ignoring the expired JWT key `{}`", path.display());
+                        continue;
+                    }
+                    previous
+                        .push(JwtKey::rsa(get_signing_key(&path, "JWT")?).expiring_at(expiry_date));
+                }
+                Self { current, previous }
+            }
+        })
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &JwtKey> {
+        std::iter::once(&self.current).chain(self.previous.iter())
+    }
+
+    pub fn sign(&self, claims: &JWTClaims) -> String {
+        let header = Header {
+            algorithm: SigningAlgorithm::algorithm_type(&self.current),
+            key_id: Some(self.current.key_id.clone()),
+            type_: Some(HeaderType::JsonWebToken),
+            ..Default::default()
+        };
+        Token::new(header, claims)
+            .sign_with_key(&self.current)
+            .expect("Could not sign the JWT")
+            .as_str()
+            .to_owned()
+    }
+
+    /// The claims of the JWT, if it was signed by one of the keys, and that key is still valid.
+    /// The expiry of the JWT itself is not checked.
+    pub fn verify(&self, token: &str) -> Option<JWTClaims> {
+        let token =
+            Token::<Header, JWTClaims, jwt::token::Unverified<'_>>::parse_unverified(token).ok()?;
+        let key = match &token.header().key_id {
+            Some(key_id) => self.keys().find(|k| &k.key_id == key_id)?,
+            // Signed before the key IDs were added.
+            None => &self.current,
+        };
+        if let Some(expiry_date) = key.expiry_date {
+            if expiry_date < Utc::now() {
+                debug!("The JWT key `{}` expired on {}", key.key_id, expiry_date);
+                return None;
+            }
+        }
+        // Also checks that the JWT was signed with the algorithm of the key.
+        let token: Token<Header, JWTClaims, jwt::token::Verified> =
+            token.verify_with_key(key).ok()?;
+        Some(token.claims().clone())
+    }
+
+    /// The public keys, to verify the JWTs without the secret. Empty with HS512.
+    pub fn jwks(&self) -> Value {
+        let keys = self
+            .keys()
+            .filter_map(|k| match &k.key {
+                SigningKey::Hmac(_) => None,
+                SigningKey::Rsa(key) => Some(json!({
+                    "kty": "RSA",
+                    "use": "sig",
+                    "alg": k.algorithm(),
+                    "kid": k.key_id,
+                    "n": base64_url(&key.n().to_bytes_be()),
+                    "e": base64_url(&key.e().to_bytes_be()),
+                })),
+            })
+            .collect::<Vec<_>>();
+        json!({ "keys": keys })
+    }
+}
+
+/// Replaces the RSA key with a new one. The current one is kept as a previous key, and the ones
+/// past their grace period are deleted.
+pub fn rotate_key(config: &Configuration) -> Result<()> {
+    let key_path = get_key_path(&config.key_file);
+    let grace_period = Duration::hours(config.jwt_key_grace_period_hours.into());
+    let now = Utc::now();
+    for (rotation_date, path) in get_previous_key_files(&key_path)? {
+        if rotation_date + grace_period < now {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Could not delete `{}`", path.display()))?;
+            info!("Deleted the expired JWT key `{}`", path.display());
+        }
+    }
+    if key_path.exists() {
+        let previous_path =
+            key_path.with_file_name(format!("{}.{}.pem", KEY_FILE_PREFIX, now.timestamp()));
+        std::fs::rename(&key_path, &previous_path).with_context(|| {
+            format!(
+                "Could not move `{}` to `{}`",
+                key_path.display(),
+                previous_path.display()
+            )
+        })?;
+        info!(
+            "Kept the previous JWT key in `{}`, until {}",
+            previous_path.display(),
+            now + grace_period
+        );
+    }
+    get_signing_key(&key_path, "JWT")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_claims() -> JWTClaims {
+        JWTClaims {
+            exp: Utc::now() + Duration::days(1),
+            iat: Utc::now(),
+            user: "bob".to_string(),
+            groups: ["lldap_admin".to_string()].into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn test_hmac_keys() {
+        let old = JwtKeys {
+            current: JwtKey::hmac(&SecUtf8::from("old secret")),
+            previous: Vec::new(),
+        };
+        let keys = JwtKeys {
+            current: JwtKey::hmac(&SecUtf8::from("new secret")),
+            previous: vec![JwtKey::hmac(&SecUtf8::from("old secret"))],
+        };
+        let token = keys.sign(&make_claims());
+        assert_eq!(keys.verify(&token).unwrap().user, "bob");
+        assert!(old.verify(&token).is_none());
+        // The previous secret is still accepted.
+        let old_token = old.sign(&make_claims());
+        assert_eq!(keys.verify(&old_token).unwrap().user, "bob");
+        assert!(keys.jwks()["keys"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_previous_tokens_without_key_id() {
+        use jwt::SignWithKey;
+        let secret = SecUtf8::from("secret");
+        let keys = JwtKeys {
+            current: JwtKey::hmac(&secret),
+            previous: Vec::new(),
+        };
+        let key = Hmac::<Sha512>::new_varkey(secret.unsecure().as_bytes()).unwrap();
+        let header = jwt::Header {
+            algorithm: jwt::AlgorithmType::Hs512,
+            ..Default::default()
+        };
+        let token = jwt::Token::new(header, make_claims())
+            .sign_with_key(&key)
+            .unwrap();
+        assert_eq!(keys.verify(token.as_str()).unwrap().user, "bob");
+    }
+
+    #[test]
+    fn test_rsa_keys() {
+        let key = RsaPrivateKey::new(&mut rand::rngs::OsRng, 1024).unwrap();
+        let keys = JwtKeys {
+            current: JwtKey::rsa(key),
+            previous: vec![JwtKey::hmac(&SecUtf8::from("secret"))],
+        };
+        let token = keys.sign(&make_claims());
+        assert_eq!(keys.verify(&token).unwrap().user, "bob");
+        let jwks = keys.jwks();
+        assert_eq!(jwks["keys"].as_array().unwrap().len(), 1);
+        assert_eq!(jwks["keys"][0]["kid"], keys.current.key_id);
+        // Tampered claims.
+        let parts = token.split('.').collect::<Vec<_>>();
+        let mut claims = make_claims();
+        claims.user = "admin".to_string();
+        let forged = format!(
+            "{}.{}.{}",
+            parts[0],
+            base64_url(&serde_json::to_vec(&claims).unwrap()),
+            parts[2]
+        );
+        assert!(keys.verify(&forged).is_none());
+        // The HMAC key can't be picked for an RS256 token.
+        let header = json!({ "alg": "HS512", "typ": "JWT", "kid": keys.current.key_id });
+        let message = format!("{}.{}", base64_url(header.to_string().as_bytes()), parts[1]);
+        let (header, claims) = message.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}",
+            message,
+            SigningAlgorithm::sign(&keys.previous[0], header, claims).unwrap()
+        );
+        assert!(keys.verify(&forged).is_none());
+    }
+
+    #[test]
+    fn test_expired_previous_key() {
+        let old = JwtKeys {
+            current: JwtKey::hmac(&SecUtf8::from("old secret")),
+            previous: Vec::new(),
+        };
+        let old_token = old.sign(&make_claims());
+        let keys = JwtKeys {
+            current: JwtKey::hmac(&SecUtf8::from("new secret")),
+            previous: vec![JwtKey::hmac(&SecUtf8::from("old secret"))
+                .expiring_at(Utc::now() + Duration::hours(1))],
+        };
+        assert_eq!(keys.verify(&old_token).unwrap().user, "bob");
+        let keys = JwtKeys {
+            current: JwtKey::hmac(&SecUtf8::from("new secret")),
+            previous: vec![JwtKey::hmac(&SecUtf8::from("old secret"))
+                .expiring_at(Utc::now() - Duration::hours(1))],
+        };
+        assert!(keys.verify(&old_token).is_none());
+    }
+
+    #[test]
+    fn test_update_replacement_dates() {
+        let now = Utc.timestamp_opt(2_000_000_000, 0).unwrap();
+        let (dates, contents) = update_replacement_dates("", &["a", "b"], now);
+        assert_eq!(dates, vec![now, now]);
+        assert_eq!(contents, "a 2000000000\nb 2000000000\n");
+        // The restarts keep the recorded dates, and forget the removed secrets.
+        let later = now + Duration::hours(30);
+        let (dates, contents) = update_replacement_dates(&contents, &["b", "c"], later);
+        assert_eq!(dates, vec![now, later]);
+        assert_eq!(contents, "b 2000000000\nc 2000108000\n");
+        let (dates, _) = update_replacement_dates("garbage\nb x\n", &["b"], later);
+        assert_eq!(dates, vec![later]);
+    }
+}
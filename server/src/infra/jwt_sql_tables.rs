@@ -3,16 +3,19 @@ use sea_query::{ColumnDef, ForeignKey, ForeignKeyAction, Iden, Table};
 
 pub use crate::domain::{sql_migrations::Users, sql_tables::DbConnection};
 
-/// Contains the refresh tokens for a given user.
+/// Contains the refresh tokens for a given user: each of them is a session of the web UI.
 #[derive(Iden)]
 pub enum JwtRefreshStorage {
     Table,
     RefreshTokenHash,
     UserId,
     ExpiryDate,
+    CreationDate,
+    IpAddress,
+    UserAgent,
 }
 
-/// Contains the blacklisted JWT that haven't expired yet.
+/// Contains the JWTs that haven't expired yet, and whether they are blacklisted.
 #[derive(Iden)]
 pub enum JwtStorage {
     Table,
@@ -20,6 +23,8 @@ pub enum JwtStorage {
     UserId,
     ExpiryDate,
     Blacklisted,
+    /// The refresh token of the session that the JWT was created for.
+    RefreshTokenHash,
 }
 
 /// Contains the temporary tokens to reset the password, sent by email.
@@ -31,7 +36,8 @@ pub enum PasswordResetTokens {
     ExpiryDate,
 }
 
-/// This needs to be initialized after the domain tables are.
+/// This needs to be initialized after the domain tables are. The columns added later are created
+/// by the domain migrations, see `migrate_to_v18`.
 pub async fn init_table(pool: &DbConnection) -> std::result::Result<(), sea_orm::DbErr> {
     let builder = pool.get_database_backend();
 
@@ -0,0 +1,459 @@
+//! The codec of `ldap3_proto` rejects a whole message for any part of it that it doesn't know: a
+//! control other than the synchronization ones, a SASL bind, an approximate match filter, etc.
+//! This codec reads the messages first, takes these parts out into [`RequestExtras`], and hands
+//! the rest to it. The other way around, it adds the [`ResponseExtras`] to the encoded responses.
+
+use crate::domain::ldap::{
+    ber::{self, Element, TagClass},
+    utils::APPROX_MATCH_PREFIX,
+};
+use bytes::BytesMut;
+use ldap3_proto::{proto::LdapMsg, LdapCodec};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The largest request accepted, to bound the memory a client can make us buffer.
+const MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+/// The request controls that `ldap3_proto` decodes itself: the synchronization request.
+const KNOWN_CONTROLS: &[&str] = &["1.3.6.1.4.1.4203.1.9.1.1"];
+
+/// A control, as sent on the wire: its value is BER-encoded, if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawControl {
+    pub oid: String,
+    pub criticality: bool,
+    pub value: Option<Vec<u8>>,
+}
+
+impl RawControl {
+    pub fn new(oid: &str, value: Element) -> Self {
+        Self {
+            oid: oid.to_string(),
+            criticality: false,
+            value: Some(value.to_bytes()),
+        }
+    }
+
+    fn from_element(element: &Element) -> Option<Self> {
+        let (oid, rest) = element.children()?.split_first()?;
+        let mut control = Self {
+            oid: oid.as_str()?.to_string(),
+            criticality: false,
+            value: None,
+        };
+        for field in rest {
+            if field.is(TagClass::Universal, ber::BOOLEAN) {
+                control.criticality = field.as_boolean()?;
+            } else if field.is(TagClass::Universal, ber::OCTET_STRING) {
+                control.value = Some(field.as_bytes()?.to_vec());
+            } else {
+                return None;
+            }
+        }
+        Some(control)
+    }
+
+    fn to_element(&self) -> Element {
+        let mut fields = vec![Element::octet_string(self.oid.as_bytes())];
+        if self.criticality {
+            fields.push(Element::boolean(true));
+        }
+        if let Some(value) = &self.value {
+            fields.push(Element::octet_string(value.clone()));
+        }
+        Element::sequence(fields)
+    }
+}
+
+/// The credentials of a SASL bind (RFC 4511, section 4.2).
+#[derive(Clone, PartialEq, Eq)]
+pub struct SaslCredentials {
+    pub mechanism: String,
+    pub credentials: Option<Vec<u8>>,
+}
+
+// The credentials can be a password: only their length is logged.
+impl std::fmt::Debug for SaslCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SaslCredentials")
+            .field("mechanism", &self.mechanism)
+            .field("credentials", &self.credentials.as_ref().map(Vec::len))
+            .finish()
+    }
+}
+
+/// The parts of a request taken out before decoding it with `ldap3_proto`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestExtras {
+    /// The controls that `ldap3_proto` doesn't know.
+    pub controls: Vec<RawControl>,
+    /// For a SASL bind, which is passed on as a simple bind with an empty password.
+    pub sasl: Option<SaslCredentials>,
+}
+
+impl RequestExtras {
+    pub fn find_control(&self, oid: &str) -> Option<&RawControl> {
+        self.controls.iter().find(|c| c.oid == oid)
+    }
+}
+
+/// What to add to a response once it's encoded by `ldap3_proto`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResponseExtras {
+    pub controls: Vec<RawControl>,
+    /// Replaces the result code of the response, for the codes `ldap3_proto` doesn't have.
+    pub result_code: Option<i64>,
+}
+
+impl ResponseExtras {
+    pub fn with_control(control: RawControl) -> Self {
+        Self {
+            controls: vec![control],
+            result_code: None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.controls.is_empty() && self.result_code.is_none()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn from_ber_error(error: ber::Error) -> io::Error {
+    match error {
+        ber::Error::Incomplete => invalid_data("Truncated message"),
+        ber::Error::Invalid(message) => invalid_data(message),
+    }
+}
+
+/// Rewrites the approximate matches of the filter into equality filters on the prefixed
+/// attribute.
+fn rewrite_approx_matches(filter: &mut Element) {
+    if filter.class != TagClass::Context {
+        return;
+    }
+    match filter.tag {
+        // The "and", "or" and "not" filters.
+        0 | 1 | 2 => {
+            for child in filter.children_mut().into_iter().flatten() {
+                rewrite_approx_matches(child);
+            }
+        }
+        8 => {
+            let attribute = match filter
+                .children_mut()
+                .and_then(|assertion| assertion.first_mut())
+            {
+                Some(attribute) => attribute,
+                None => return,
+            };
+            if let Some(name) = attribute.as_bytes() {
+                let mut prefixed = APPROX_MATCH_PREFIX.as_bytes().to_vec();
+                prefixed.extend_from_slice(name);
+                *attribute = Element::octet_string(prefixed);
+                filter.tag = 3;
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Takes the SASL credentials out of a bind request, leaving an empty simple password.
+fn extract_sasl_credentials(bind: &mut Element) -> io::Result<Option<SaslCredentials>> {
+    let authentication = match bind.children_mut().and_then(|fields| fields.get_mut(2)) {
+        Some(authentication) if authentication.is(TagClass::Context, 3) => authentication,
+        _ => return Ok(None),
+    };
+    let fields = authentication
+        .children()
+        .ok_or_else(|| invalid_data("Invalid SASL credentials"))?;
+    let mechanism = fields
+        .first()
+        .and_then(Element::as_str)
+        .ok_or_else(|| invalid_data("Invalid SASL mechanism"))?
+        .to_string();
+    let credentials = match fields.get(1) {
+        None => None,
+        Some(credentials) => Some(
+            credentials
+                .as_bytes()
+                .ok_or_else(|| invalid_data("Invalid SASL credentials"))?
+                .to_vec(),
+        ),
+    };
+    *authentication = Element::primitive(TagClass::Context, 0, Vec::new());
+    Ok(Some(SaslCredentials {
+        mechanism,
+        credentials,
+    }))
+}
+
+fn extract_extras(message: &mut Element) -> io::Result<RequestExtras> {
+    let fields = message
+        .children_mut()
+        .ok_or_else(|| invalid_data("Invalid message"))?;
+    let mut extras = RequestExtras::default();
+    if let Some(position) = fields.iter().position(|f| f.is(TagClass::Context, 0)) {
+        let mut kept = Vec::new();
+        for control in fields[position].children().into_iter().flatten() {
+            let raw =
+                RawControl::from_element(control).ok_or_else(|| invalid_data("Invalid control"))?;
+            if KNOWN_CONTROLS.contains(&raw.oid.as_str()) {
+                kept.push(control.clone());
+            } else {
+                extras.controls.push(raw);
+            }
+        }
+        if kept.is_empty() {
+            fields.remove(position);
+        } else {
+            fields[position] = Element::constructed(TagClass::Context, 0, kept);
+        }
+    }
+    if let Some(operation) = fields.get_mut(1) {
+        match (operation.class, operation.tag) {
+            (TagClass::Application, 0) => extras.sasl = extract_sasl_credentials(operation)?,
+            (TagClass::Application, 3) => {
+                if let Some(filter) = operation.children_mut().and_then(|f| f.get_mut(6)) {
+                    rewrite_approx_matches(filter);
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(extras)
+}
+
+fn add_extras(message: &mut Element, extras: ResponseExtras) -> io::Result<()> {
+    let fields = message
+        .children_mut()
+        .ok_or_else(|| invalid_data("Invalid response"))?;
+    if let Some(code) = extras.result_code {
+        // All the responses with a result start with its code.
+        match fields
+            .get_mut(1)
+            .and_then(Element::children_mut)
+            .and_then(|result| result.first_mut())
+        {
+            Some(result_code) => *result_code = Element::enumerated(code),
+            None => return Err(invalid_data("The response has no result code")),
+        }
+    }
+    if !extras.controls.is_empty() {
+        if !fields.iter().any(|f| f.is(TagClass::Context, 0)) {
+            fields.push(Element::constructed(TagClass::Context, 0, Vec::new()));
+        }
+        let controls = fields
+            .iter_mut()
+            .find(|f| f.is(TagClass::Context, 0))
+            .and_then(Element::children_mut)
+            .ok_or_else(|| invalid_data("Invalid response controls"))?;
+        controls.extend(extras.controls.iter().map(RawControl::to_element));
+    }
+    Ok(())
+}
+
+/// Wraps [`LdapCodec`], see the module documentation.
+pub struct ExtendedLdapCodec;
+
+impl Decoder for ExtendedLdapCodec {
+    type Item = (LdapMsg, RequestExtras);
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        let length = match ber::element_length(src) {
+            Ok(length) => length,
+            Err(ber::Error::Incomplete) => return Ok(None),
+            Err(e) => return Err(from_ber_error(e)),
+        };
+        if length > MAX_REQUEST_SIZE {
+            return Err(invalid_data("Request too large"));
+        }
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+        let bytes = src.split_to(length);
+        let mut message = ber::parse(&bytes).map_err(from_ber_error)?;
+        let extras = extract_extras(&mut message)?;
+        let mut rewritten = BytesMut::from(message.to_bytes().as_slice());
+        match LdapCodec.decode(&mut rewritten)? {
+            Some(msg) => Ok(Some((msg, extras))),
+            None => Err(invalid_data("Could not decode the request")),
+        }
+    }
+}
+
+impl Encoder<(LdapMsg, ResponseExtras)> for ExtendedLdapCodec {
+    type Error = io::Error;
+
+    fn encode(
+        &mut self,
+        (msg, extras): (LdapMsg, ResponseExtras),
+        dst: &mut BytesMut,
+    ) -> io::Result<()> {
+        if extras.is_empty() {
+            return LdapCodec.encode(msg, dst);
+        }
+        let mut encoded = BytesMut::new();
+        LdapCodec.encode(msg, &mut encoded)?;
+        let mut message = ber::parse(&encoded).map_err(from_ber_error)?;
+        add_extras(&mut message, extras)?;
+        dst.extend_from_slice(&message.to_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldap3_proto::proto::{
+        LdapBindCred, LdapFilter, LdapOp, LdapResult as LdapResultOp, LdapResultCode,
+    };
+
+    fn make_message(msgid: i64, operation: Element, controls: Vec<RawControl>) -> BytesMut {
+        let mut fields = vec![Element::integer(msgid), operation];
+        if !controls.is_empty() {
+            fields.push(Element::constructed(
+                TagClass::Context,
+                0,
+                controls.iter().map(RawControl::to_element).collect(),
+            ));
+        }
+        BytesMut::from(Element::sequence(fields).to_bytes().as_slice())
+    }
+
+    fn make_search(filter: Element) -> Element {
+        Element::constructed(
+            TagClass::Application,
+            3,
+            vec![
+                Element::octet_string("dc=example,dc=com"),
+                Element::enumerated(2),
+                Element::enumerated(0),
+                Element::integer(0),
+                Element::integer(0),
+                Element::boolean(false),
+                filter,
+                Element::sequence(vec![]),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_decode_unknown_control_and_approx_match() {
+        let paged_results = RawControl {
+            oid: "1.2.840.113556.1.4.319".to_string(),
+            criticality: true,
+            value: Some(Element::sequence(vec![Element::integer(10)]).to_bytes()),
+        };
+        let approx_match = Element::constructed(
+            TagClass::Context,
+            8,
+            vec![Element::octet_string("sn"), Element::octet_string("smith")],
+        );
+        let filter = Element::constructed(TagClass::Context, 0, vec![approx_match]);
+        let mut bytes = make_message(3, make_search(filter), vec![paged_results.clone()]);
+        // Only part of the message: wait for the rest.
+        let mut partial = BytesMut::from(&bytes[..10]);
+        assert!(ExtendedLdapCodec.decode(&mut partial).unwrap().is_none());
+        let (msg, extras) = ExtendedLdapCodec.decode(&mut bytes).unwrap().unwrap();
+        assert!(bytes.is_empty());
+        assert_eq!(msg.msgid, 3);
+        assert!(msg.ctrl.is_empty());
+        match msg.op {
+            LdapOp::SearchRequest(request) => assert_eq!(
+                request.filter,
+                LdapFilter::And(vec![LdapFilter::Equality(
+                    "~sn".to_string(),
+                    "smith".to_string()
+                )])
+            ),
+            op => panic!("Unexpected operation: {:?}", op),
+        }
+        assert_eq!(
+            extras,
+            RequestExtras {
+                controls: vec![paged_results],
+                sasl: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_sasl_bind() {
+        let bind = Element::constructed(
+            TagClass::Application,
+            0,
+            vec![
+                Element::integer(3),
+                Element::octet_string(""),
+                Element::constructed(
+                    TagClass::Context,
+                    3,
+                    vec![Element::octet_string("EXTERNAL")],
+                ),
+            ],
+        );
+        let (msg, extras) = ExtendedLdapCodec
+            .decode(&mut make_message(1, bind, vec![]))
+            .unwrap()
+            .unwrap();
+        match msg.op {
+            LdapOp::BindRequest(request) => {
+                assert_eq!(request.cred, LdapBindCred::Simple(String::new()))
+            }
+            op => panic!("Unexpected operation: {:?}", op),
+        }
+        assert_eq!(
+            extras.sasl,
+            Some(SaslCredentials {
+                mechanism: "EXTERNAL".to_string(),
+                credentials: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_encode_extras() {
+        let control = RawControl::new(
+            "1.2.840.113556.1.4.319",
+            Element::sequence(vec![Element::integer(0), Element::octet_string("")]),
+        );
+        let mut bytes = BytesMut::new();
+        ExtendedLdapCodec
+            .encode(
+                (
+                    LdapMsg {
+                        msgid: 2,
+                        op: LdapOp::SearchResultDone(LdapResultOp {
+                            code: LdapResultCode::Success,
+                            matcheddn: "".to_string(),
+                            message: "".to_string(),
+                            referral: vec![],
+                        }),
+                        ctrl: vec![],
+                    },
+                    ResponseExtras {
+                        controls: vec![control.clone()],
+                        result_code: Some(118),
+                    },
+                ),
+                &mut bytes,
+            )
+            .unwrap();
+        let message = ber::parse(&bytes).unwrap();
+        let fields = message.children().unwrap();
+        assert_eq!(fields[0].as_integer(), Some(2));
+        assert_eq!(fields[1].children().unwrap()[0].as_integer(), Some(118));
+        assert!(fields[2].is(TagClass::Context, 0));
+        assert_eq!(
+            RawControl::from_element(&fields[2].children().unwrap()[0]),
+            Some(control)
+        );
+    }
+}
@@ -1,28 +1,74 @@
 use crate::{
     domain::{
-        handler::{BackendHandler, BindRequest, CreateUserRequest, LoginHandler},
+        handler::{
+            AuditEvent, BackendHandler, BindRequest, CreateUserRequest, LoginHandler, PageRequest,
+            SortKey, UpdateUserRequest,
+        },
         ldap::{
+            active_directory::{get_domain_name, get_user_id_from_logon_name},
+            cancel::{parse_cancel_request, CANCEL_OID, NO_SUCH_OPERATION},
             error::{LdapError, LdapResult},
             group::get_groups_list,
-            user::get_user_list,
+            paging::{
+                get_page_key, make_page_cookie, make_paged_results_response, parse_page_cookie,
+                parse_paged_results_request, PAGED_RESULTS_OID,
+            },
+            ppolicy::{make_password_policy_response, PasswordPolicyState, PASSWORD_POLICY_OID},
+            schema::{make_subschema_entry, SUBSCHEMA_DN},
+            sort::{
+                get_user_sort_keys, make_sort_response, parse_sort_request, SORT_REQUEST_OID,
+                SORT_RESPONSE_OID, SORT_SUCCESS, SORT_UNWILLING_TO_PERFORM,
+            },
+            sync::{get_entry_digest, get_entry_uuid, make_cookie, parse_cookie},
+            transaction::{
+                parse_end_transaction_request, END_TRANSACTION_OID, START_TRANSACTION_OID,
+                TRANSACTION_SPECIFICATION_OID,
+            },
+            user::{get_user_list, get_user_page},
             utils::{
-                get_user_id_from_distinguished_name, is_subtree, parse_distinguished_name, LdapInfo,
+                escape_dn_value, format_filter, get_group_id_from_distinguished_name,
+                get_user_id_from_distinguished_name, is_subtree, parse_distinguished_name,
+                LdapInfo, APPROX_MATCH_PREFIX,
+            },
+            vlv::{
+                get_vlv_window, make_vlv_response, parse_vlv_request, VlvTarget,
+                VLV_INSUFFICIENT_ACCESS_RIGHTS, VLV_REQUEST_OID, VLV_RESPONSE_OID,
+                VLV_SORT_CONTROL_MISSING, VLV_SUCCESS, VLV_UNWILLING_TO_PERFORM,
             },
         },
         opaque_handler::OpaqueHandler,
-        types::{JpegPhoto, UserId},
+        types::{
+            check_ssh_public_key, Group, GroupId, JpegPhoto, PasswordExpiry, UserColumn, UserId,
+        },
+    },
+    infra::{
+        auth_service::{Permission, ValidationResults},
+        bind_limiter::BindLimiter,
+        configuration::{GroupAssignmentRule, LdapServiceAccountGroup},
+        graphql::mutation::{check_group_limit, GroupLimit},
+        group_assignment::apply_group_assignment_rules,
+        ldap_codec::{RawControl, RequestExtras, ResponseExtras, SaslCredentials},
+        sasl_gssapi::{get_user_id_from_principal, GssapiBind, GssapiStep, KerberosOptions},
+        sql_backend_handler::gen_random_string,
+        stats,
     },
-    infra::auth_service::{Permission, ValidationResults},
 };
 use anyhow::Result;
 use ldap3_proto::proto::{
-    LdapAddRequest, LdapBindCred, LdapBindRequest, LdapBindResponse, LdapExtendedRequest,
-    LdapExtendedResponse, LdapFilter, LdapOp, LdapPartialAttribute, LdapPasswordModifyRequest,
-    LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest, LdapSearchResultEntry,
-    LdapSearchScope,
+    LdapAddRequest, LdapBindCred, LdapBindRequest, LdapBindResponse, LdapCompareRequest,
+    LdapControl, LdapDerefAliases, LdapExtendedRequest, LdapExtendedResponse, LdapFilter,
+    LdapModify, LdapModifyRequest, LdapModifyType, LdapOp, LdapPartialAttribute,
+    LdapPasswordModifyRequest, LdapResult as LdapResultOp, LdapResultCode, LdapSearchRequest,
+    LdapSearchResultEntry, LdapSearchScope, SyncRequestMode, SyncStateValue,
+};
+use lldap_auth::password_strength::PasswordPolicy;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::collections::HashMap;
-use tracing::{debug, instrument, warn};
+use tracing::{debug, info, instrument, warn};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 struct LdapDn(String);
@@ -71,6 +117,14 @@ fn get_search_scope(base_dn: &[(String, String)], dn_parts: &[(String, String)])
     }
 }
 
+/// Whether the search asks for the number of children of the entry, as directory browsers do to
+/// display the tree.
+fn requests_subordinate_count(attrs: &[String]) -> bool {
+    attrs.iter().any(|a| {
+        a.eq_ignore_ascii_case("numsubordinates") || a.eq_ignore_ascii_case("subordinatecount")
+    })
+}
+
 fn make_search_success() -> LdapOp {
     make_search_error(LdapResultCode::Success, "".to_string())
 }
@@ -93,6 +147,160 @@ fn make_add_error(code: LdapResultCode, message: String) -> LdapOp {
     })
 }
 
+fn make_modify_response(code: LdapResultCode, message: String) -> LdapOp {
+    LdapOp::ModifyResponse(LdapResultOp {
+        code,
+        matcheddn: "".to_string(),
+        message,
+        referral: vec![],
+    })
+}
+
+fn make_delete_response(code: LdapResultCode, message: String) -> LdapOp {
+    LdapOp::DelResponse(LdapResultOp {
+        code,
+        matcheddn: "".to_string(),
+        message,
+        referral: vec![],
+    })
+}
+
+fn make_compare_response(code: LdapResultCode, message: String) -> LdapOp {
+    LdapOp::CompareResult(LdapResultOp {
+        code,
+        matcheddn: "".to_string(),
+        message,
+        referral: vec![],
+    })
+}
+
+fn decode_attribute_value(val: &[u8]) -> LdapResult<String> {
+    std::str::from_utf8(val)
+        .map_err(|e| LdapError {
+            code: LdapResultCode::ConstraintViolation,
+            message: format!(
+                "Attribute value is invalid UTF-8: {:#?} (value {:?})",
+                e, val
+            ),
+        })
+        .map(str::to_owned)
+}
+
+fn decode_number_value(val: &[u8]) -> LdapResult<i32> {
+    let value = decode_attribute_value(val)?;
+    value.parse().map_err(|_| LdapError {
+        code: LdapResultCode::ConstraintViolation,
+        message: format!("Attribute value is not a number: {:?}", value),
+    })
+}
+
+fn decode_ssh_public_key(val: &[u8]) -> LdapResult<String> {
+    let key = decode_attribute_value(val)?;
+    check_ssh_public_key(&key).map_err(|e| LdapError {
+        code: LdapResultCode::ConstraintViolation,
+        message: format!("Invalid SSH public key: {:#}", e),
+    })?;
+    Ok(key)
+}
+
+/// The entry targeted by a write request.
+#[derive(Debug, PartialEq, Eq)]
+enum WriteTarget {
+    User(UserId),
+    /// The lowercase name of the group.
+    Group(String),
+}
+
+/// An update request queued in an LDAP transaction.
+enum TransactionUpdate {
+    Add(LdapAddRequest),
+    Modify(LdapModifyRequest),
+    Delete(String),
+}
+
+/// The LDAP transaction in progress in a session.
+struct LdapTransaction {
+    identifier: Vec<u8>,
+    updates: Vec<TransactionUpdate>,
+}
+
+/// OID of the "Who am I?" extended operation (RFC 4532).
+const WHOAMI_OID: &str = "1.3.6.1.4.1.4203.1.11.3";
+
+/// OID of the synchronization request control (RFC 4533).
+const SYNC_REQUEST_OID: &str = "1.3.6.1.4.1.4203.1.9.1.1";
+
+/// The request controls we act on. A client marking any other one as critical gets an
+/// unavailableCriticalExtension error (RFC 4511, section 4.1.11).
+const SUPPORTED_CONTROLS: &[&str] = &[
+    SYNC_REQUEST_OID,
+    PAGED_RESULTS_OID,
+    SORT_REQUEST_OID,
+    VLV_REQUEST_OID,
+    TRANSACTION_SPECIFICATION_OID,
+    PASSWORD_POLICY_OID,
+];
+
+/// The unavailableCriticalExtension result code, which `ldap3_proto` doesn't have.
+const UNAVAILABLE_CRITICAL_EXTENSION: i64 = 12;
+
+/// The authMethodNotSupported result code, for the unknown SASL mechanisms.
+const AUTH_METHOD_NOT_SUPPORTED: i64 = 7;
+
+/// The saslBindInProgress result code, while a SASL bind needs more requests.
+const SASL_BIND_IN_PROGRESS: i64 = 14;
+
+/// The SASL mechanisms of the binds, advertised in the root DSE. GSSAPI is added when Kerberos
+/// is configured.
+const SUPPORTED_SASL_MECHANISMS: &[&str] = &["EXTERNAL"];
+
+/// The response to a failed request, if the request has one.
+fn make_error_response(request: &LdapOp, code: LdapResultCode, message: String) -> Option<LdapOp> {
+    let result = LdapResultOp {
+        code,
+        matcheddn: "".to_string(),
+        message,
+        referral: vec![],
+    };
+    Some(match request {
+        LdapOp::BindRequest(_) => LdapOp::BindResponse(LdapBindResponse {
+            res: result,
+            saslcreds: None,
+        }),
+        LdapOp::SearchRequest(_) => LdapOp::SearchResultDone(result),
+        LdapOp::AddRequest(_) => LdapOp::AddResponse(result),
+        LdapOp::ModifyRequest(_) => LdapOp::ModifyResponse(result),
+        LdapOp::DelRequest(_) => LdapOp::DelResponse(result),
+        LdapOp::CompareRequest(_) => LdapOp::CompareResult(result),
+        LdapOp::UnbindRequest | LdapOp::AbandonRequest(_) => return None,
+        _ => LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: result,
+            name: None,
+            value: None,
+        }),
+    })
+}
+
+/// The failed response to a search with the virtual list view control. The codes specific to
+/// the control are also set on the response.
+fn make_vlv_error(message: &str, code: i64) -> (LdapOp, Vec<LdapControl>, ResponseExtras) {
+    (
+        make_search_error(LdapResultCode::UnwillingToPerform, message.to_string()),
+        Vec::new(),
+        ResponseExtras {
+            controls: vec![RawControl::new(
+                VLV_RESPONSE_OID,
+                make_vlv_response(0, 0, code),
+            )],
+            result_code: if code == VLV_UNWILLING_TO_PERFORM {
+                None
+            } else {
+                Some(code)
+            },
+        },
+    )
+}
+
 fn make_extended_response(code: LdapResultCode, message: String) -> LdapOp {
     LdapOp::ExtendedResponse(LdapExtendedResponse {
         res: LdapResultOp {
@@ -106,7 +314,7 @@ fn make_extended_response(code: LdapResultCode, message: String) -> LdapOp {
     })
 }
 
-fn root_dse_response(base_dn: &str) -> LdapOp {
+fn root_dse_response(base_dn: &str, sasl_mechanisms: &[&str]) -> LdapOp {
     LdapOp::SearchResultEntry(LdapSearchResultEntry {
         dn: "".to_string(),
         attributes: vec![
@@ -130,12 +338,28 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
             },
             LdapPartialAttribute {
                 atype: "supportedExtension".to_string(),
-                // Password modification extension.
-                vals: vec![b"1.3.6.1.4.1.4203.1.11.1".to_vec()],
+                // Password modification, "Who am I?", Cancel and transaction extensions.
+                vals: vec![
+                    b"1.3.6.1.4.1.4203.1.11.1".to_vec(),
+                    WHOAMI_OID.as_bytes().to_vec(),
+                    CANCEL_OID.as_bytes().to_vec(),
+                    START_TRANSACTION_OID.as_bytes().to_vec(),
+                    END_TRANSACTION_OID.as_bytes().to_vec(),
+                ],
+            },
+            LdapPartialAttribute {
+                atype: "supportedSASLMechanisms".to_string(),
+                vals: sasl_mechanisms
+                    .iter()
+                    .map(|mechanism| mechanism.as_bytes().to_vec())
+                    .collect(),
             },
             LdapPartialAttribute {
                 atype: "supportedControl".to_string(),
-                vals: vec![],
+                vals: SUPPORTED_CONTROLS
+                    .iter()
+                    .map(|oid| oid.as_bytes().to_vec())
+                    .collect(),
             },
             LdapPartialAttribute {
                 atype: "supportedFeatures".to_string(),
@@ -154,14 +378,77 @@ fn root_dse_response(base_dn: &str) -> LdapOp {
                 atype: "isGlobalCatalogReady".to_string(),
                 vals: vec![b"false".to_vec()],
             },
+            LdapPartialAttribute {
+                atype: "subschemaSubentry".to_string(),
+                vals: vec![SUBSCHEMA_DN.as_bytes().to_vec()],
+            },
         ],
     })
 }
 
+/// Whether all the attributes the filter refers to are allowed.
+fn filter_uses_only(filter: &LdapFilter, is_allowed: &impl Fn(&str) -> bool) -> bool {
+    match filter {
+        LdapFilter::And(filters) | LdapFilter::Or(filters) => {
+            filters.iter().all(|f| filter_uses_only(f, is_allowed))
+        }
+        LdapFilter::Not(filter) => filter_uses_only(filter, is_allowed),
+        LdapFilter::Equality(attribute, _) => is_allowed(
+            attribute
+                .strip_prefix(APPROX_MATCH_PREFIX)
+                .unwrap_or(attribute),
+        ),
+        LdapFilter::Substring(attribute, _) | LdapFilter::Present(attribute) => {
+            is_allowed(attribute)
+        }
+        // Fail closed: a type of filter whose attribute isn't checked here is not allowed.
+        #[allow(unreachable_patterns)]
+        _ => false,
+    }
+}
+
 pub struct LdapHandler<Backend: BackendHandler + LoginHandler + OpaqueHandler> {
     user_info: Option<ValidationResults>,
     backend_handler: Backend,
     ldap_info: LdapInfo,
+    /// Whether to answer binds with a DN but an empty password with `unwillingToPerform` rather
+    /// than `invalidCredentials`. Either way, they never succeed.
+    empty_password_bind_unwilling_to_perform: bool,
+    /// The attributes readable without binding, if anonymous binds are allowed.
+    anonymous_bind_attributes: Option<Vec<String>>,
+    /// The groups of the read-only service accounts, with the attributes their members can read.
+    service_account_groups: Vec<LdapServiceAccountGroup>,
+    /// The only attributes readable by the bound user, if it's a service account.
+    bound_user_attributes: Option<Vec<String>>,
+    /// The requirements on the passwords set through the password modify operation.
+    password_policy: Arc<PasswordPolicy>,
+    /// Whether binds are refused because the connection is not encrypted yet.
+    bind_requires_tls: bool,
+    /// Shared by all the sessions, if the failed binds are throttled.
+    bind_limiter: Option<Arc<BindLimiter>>,
+    /// The address of the client, for the throttling and the logs.
+    peer_address: Option<IpAddr>,
+    /// Whether every search is logged.
+    log_searches: bool,
+    /// The searches taking longer than this are logged as warnings.
+    slow_search_threshold: Option<Duration>,
+    /// Applied to the users created or modified through LDAP, like through the other APIs.
+    group_assignment_rules: Vec<GroupAssignmentRule>,
+    /// The group containing all the users, managed by the server.
+    everyone_group: Option<String>,
+    /// Whether adding a member to a group they're already in is an error.
+    strict_group_membership_adds: bool,
+    /// The order of the user search being served, from the server-side sorting control.
+    user_sort_keys: Vec<SortKey<UserColumn>>,
+    /// The user identified by the TLS client certificate, if any, for the SASL EXTERNAL binds.
+    client_certificate_user: Option<UserId>,
+    /// The settings of the SASL GSSAPI binds, if they're enabled.
+    kerberos: Option<KerberosOptions>,
+    /// The GSSAPI bind in progress, across several bind requests.
+    gssapi_bind: Option<GssapiBind>,
+    /// The LDAP transaction started in this session, if any.
+    transaction: Option<LdapTransaction>,
+    group_limit: Option<GroupLimit>,
 }
 
 impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend> {
@@ -185,22 +472,492 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 base_dn_str: ldap_base_dn,
                 ignored_user_attributes,
                 ignored_group_attributes,
+                nested_groups_max_depth: 0,
+                user_attribute_aliases: HashMap::new(),
+                group_attribute_aliases: HashMap::new(),
+                active_directory_domain: None,
+                password_expiry: None,
             },
+            empty_password_bind_unwilling_to_perform: false,
+            anonymous_bind_attributes: None,
+            service_account_groups: Vec::new(),
+            bound_user_attributes: None,
+            password_policy: Arc::default(),
+            bind_requires_tls: false,
+            bind_limiter: None,
+            peer_address: None,
+            log_searches: false,
+            slow_search_threshold: None,
+            group_assignment_rules: Vec::new(),
+            everyone_group: None,
+            strict_group_membership_adds: false,
+            user_sort_keys: Vec::new(),
+            client_certificate_user: None,
+            kerberos: None,
+            gssapi_bind: None,
+            transaction: None,
+            group_limit: None,
+        }
+    }
+
+    pub fn set_password_policy(&mut self, password_policy: Arc<PasswordPolicy>) {
+        self.password_policy = password_policy;
+    }
+
+    pub fn set_nested_groups_max_depth(&mut self, nested_groups_max_depth: u8) {
+        self.ldap_info.nested_groups_max_depth = nested_groups_max_depth;
+    }
+
+    pub fn set_attribute_aliases(
+        &mut self,
+        user_attribute_aliases: HashMap<String, String>,
+        group_attribute_aliases: HashMap<String, String>,
+    ) {
+        self.ldap_info.user_attribute_aliases = user_attribute_aliases;
+        self.ldap_info.group_attribute_aliases = group_attribute_aliases;
+    }
+
+    pub fn set_empty_password_bind_unwilling_to_perform(&mut self, unwilling: bool) {
+        self.empty_password_bind_unwilling_to_perform = unwilling;
+    }
+
+    pub fn set_anonymous_bind_attributes(&mut self, attributes: Option<Vec<String>>) {
+        self.anonymous_bind_attributes = attributes;
+    }
+
+    /// Serves the attributes of Active Directory, and accepts its logon names in binds.
+    pub fn set_active_directory_compatibility(&mut self, enabled: bool) {
+        self.ldap_info.active_directory_domain =
+            enabled.then(|| get_domain_name(&self.ldap_info.base_dn));
+    }
+
+    pub fn set_password_expiry(&mut self, password_expiry: Option<PasswordExpiry>) {
+        self.ldap_info.password_expiry = password_expiry;
+    }
+
+    pub fn set_service_account_groups(&mut self, groups: Vec<LdapServiceAccountGroup>) {
+        self.service_account_groups = groups;
+    }
+
+    pub fn set_bind_requires_tls(&mut self, bind_requires_tls: bool) {
+        self.bind_requires_tls = bind_requires_tls;
+    }
+
+    pub fn set_bind_limiter(&mut self, bind_limiter: Option<Arc<BindLimiter>>) {
+        self.bind_limiter = bind_limiter;
+    }
+
+    pub fn set_peer_address(&mut self, peer_address: Option<IpAddr>) {
+        self.peer_address = peer_address;
+    }
+
+    pub fn set_query_log(&mut self, log_searches: bool, slow_search_threshold: Option<Duration>) {
+        self.log_searches = log_searches;
+        self.slow_search_threshold = slow_search_threshold;
+    }
+
+    pub fn set_group_assignment_rules(&mut self, rules: Vec<GroupAssignmentRule>) {
+        self.group_assignment_rules = rules;
+    }
+
+    /// The same rules as for the memberships changed through the other APIs.
+    pub fn set_membership_rules(
+        &mut self,
+        everyone_group: Option<String>,
+        strict_group_membership_adds: bool,
+        group_limit: Option<GroupLimit>,
+    ) {
+        self.everyone_group = everyone_group;
+        self.strict_group_membership_adds = strict_group_membership_adds;
+        self.group_limit = group_limit;
+    }
+
+    pub fn set_kerberos_options(&mut self, kerberos: Option<KerberosOptions>) {
+        self.kerberos = kerberos;
+    }
+
+    fn get_sasl_mechanisms(&self) -> Vec<&'static str> {
+        let mut mechanisms = SUPPORTED_SASL_MECHANISMS.to_vec();
+        if self.kerberos.is_some() {
+            mechanisms.push("GSSAPI");
+        }
+        mechanisms
+    }
+
+    /// Authenticates the session as `user_id`, with the permissions given by their groups.
+    async fn set_bound_user(&mut self, user_id: UserId) {
+        let user_groups = self.backend_handler.get_user_groups(&user_id).await;
+        let is_in_group = |name: &str| {
+            user_groups
+                .as_ref()
+                .map(|groups| groups.iter().any(|g| g.display_name == name && !g.archived))
+                .unwrap_or(false)
+        };
+        let mut permission = if is_in_group("lldap_admin") {
+            Permission::Admin
+        } else if is_in_group("lldap_password_manager") {
+            Permission::PasswordManager
+        } else if is_in_group("lldap_strict_readonly") {
+            Permission::Readonly
+        } else {
+            Permission::Regular
+        };
+        // The lldap_* groups take precedence, so that a service account group can't restrict
+        // what an admin sees.
+        self.bound_user_attributes = None;
+        if permission == Permission::Regular {
+            for service_group in &self.service_account_groups {
+                if is_in_group(&service_group.group) {
+                    self.bound_user_attributes
+                        .get_or_insert_with(Vec::new)
+                        .extend(service_group.attributes.iter().cloned());
+                    permission = Permission::Readonly;
+                }
+            }
+        }
+        self.user_info = Some(ValidationResults {
+            user: user_id,
+            permission,
+        });
+    }
+
+    /// Records a bind in the audit log. A failure to record it is only logged.
+    async fn record_bind(&self, user_id: &UserId, success: bool, details: Option<&str>) {
+        let event = AuditEvent {
+            actor: Some(user_id.clone()),
+            action: "ldapBind".to_owned(),
+            target: None,
+            ip_address: self.peer_address.map(|address| address.to_string()),
+            success,
+            details: details.map(str::to_owned),
+        };
+        if let Err(e) = self.backend_handler.record_audit_event(event).await {
+            warn!("Could not record the LDAP bind in the audit log: {}", e);
+        }
+    }
+
+    /// Records a successful add, modify or delete request in the audit log, as done by the bound
+    /// user. A failure to record it is only logged: the change itself is done.
+    async fn record_write(&self, action: &str, dn: &str) {
+        let event = AuditEvent {
+            actor: self.user_info.as_ref().map(|u| u.user.clone()),
+            action: action.to_owned(),
+            target: Some(dn.to_owned()),
+            ip_address: self.peer_address.map(|address| address.to_string()),
+            success: true,
+            details: None,
+        };
+        if let Err(e) = self.backend_handler.record_audit_event(event).await {
+            warn!("Could not record the {} in the audit log: {}", action, e);
+        }
+    }
+
+    /// Binds the session as the user identified by a verified TLS client certificate, before any
+    /// bind request. A later simple bind replaces this identity. Like with a password, the account
+    /// has to be active and not locked: returns whether the session is bound.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn bind_with_client_certificate(&mut self, user_id: UserId) -> bool {
+        debug!(?user_id);
+        self.client_certificate_user = Some(user_id.clone());
+        self.bind_verified_user(user_id, "client certificate").await
+    }
+
+    /// Binds the session as a user authenticated by other means than a password, e.g. "client
+    /// certificate". The account has to be active and not locked: returns whether the session is
+    /// bound.
+    async fn bind_verified_user(&mut self, user_id: UserId, method: &str) -> bool {
+        let refusal = match self.backend_handler.get_user_details(&user_id).await {
+            Ok(user) if user.locked_date.is_some() => Some("The account is locked"),
+            Ok(user) if !user.is_active_at(chrono::Utc::now()) => {
+                Some("The account is disabled, isn't active yet or has expired")
+            }
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Could not check the account of {}: {}", user_id, e);
+                Some("The account could not be checked")
+            }
+        };
+        if let Some(reason) = refusal {
+            warn!("Refused the {} of user \"{}\": {}", method, user_id, reason);
+            self.record_bind(&user_id, false, Some(reason)).await;
+            stats::record_ldap_bind(false);
+            return false;
         }
+        self.record_bind(&user_id, true, Some(format!("With a {}", method).as_str()))
+            .await;
+        self.set_bound_user(user_id).await;
+        stats::record_ldap_bind(true);
+        true
     }
 
+    /// Binds with a SASL mechanism. Returns the result code to send instead of the one of the
+    /// response, if `ldap3_proto` doesn't have it.
     #[instrument(skip_all, level = "debug")]
+    async fn do_sasl_bind(&mut self, sasl: &SaslCredentials) -> (LdapOp, Option<i64>) {
+        debug!(?sasl);
+        if sasl.mechanism != "GSSAPI" {
+            self.gssapi_bind = None;
+        }
+        let mut saslcreds = None;
+        let ((code, message), result_code) = match (sasl.mechanism.as_str(), self.kerberos.clone())
+        {
+            ("EXTERNAL", _) => (
+                self.do_sasl_external_bind(sasl.credentials.as_deref())
+                    .await,
+                None,
+            ),
+            ("GSSAPI", Some(kerberos)) => {
+                let (result, token) = self
+                    .do_sasl_gssapi_bind(&kerberos, sasl.credentials.as_deref().unwrap_or_default())
+                    .await;
+                let result_code = token.as_ref().map(|_| SASL_BIND_IN_PROGRESS);
+                saslcreds = token;
+                (result, result_code)
+            }
+            (mechanism, _) => (
+                (
+                    LdapResultCode::UnwillingToPerform,
+                    format!("Unsupported SASL mechanism: {}", mechanism),
+                ),
+                Some(AUTH_METHOD_NOT_SUPPORTED),
+            ),
+        };
+        let response = LdapOp::BindResponse(LdapBindResponse {
+            res: LdapResultOp {
+                code,
+                matcheddn: "".to_string(),
+                message,
+                referral: vec![],
+            },
+            saslcreds,
+        });
+        (response, result_code)
+    }
+
+    /// The SASL EXTERNAL mechanism (RFC 4422, appendix A): the session is bound as the user of
+    /// the TLS client certificate. The client can only ask for that same identity.
+    async fn do_sasl_external_bind(&mut self, authz_id: Option<&[u8]>) -> (LdapResultCode, String) {
+        let user_id = match self.client_certificate_user.clone() {
+            Some(user_id) => user_id,
+            None => {
+                stats::record_ldap_bind(false);
+                return (
+                    LdapResultCode::InvalidCredentials,
+                    "No TLS client certificate to bind with".to_string(),
+                );
+            }
+        };
+        if !self.is_own_authz_id(authz_id, &user_id) {
+            stats::record_ldap_bind(false);
+            return (
+                LdapResultCode::InvalidCredentials,
+                "The authorization identity has to be the one of the client certificate"
+                    .to_string(),
+            );
+        }
+        if self.bind_with_client_certificate(user_id).await {
+            (LdapResultCode::Success, "".to_string())
+        } else {
+            (
+                LdapResultCode::InvalidCredentials,
+                "The account of the client certificate can't be used".to_string(),
+            )
+        }
+    }
+
+    /// Whether the SASL authorization identity, of the form "u:bob" or "dn:uid=bob,...", is
+    /// missing or the one of the authenticated user: acting as another user isn't supported.
+    fn is_own_authz_id(&self, authz_id: Option<&[u8]>, user_id: &UserId) -> bool {
+        let authz_id = match authz_id.filter(|authz_id| !authz_id.is_empty()) {
+            Some(authz_id) => String::from_utf8_lossy(authz_id).to_ascii_lowercase(),
+            None => return true,
+        };
+        let requested_user = match (authz_id.strip_prefix("u:"), authz_id.strip_prefix("dn:")) {
+            (Some(user_id), _) => Some(UserId::new(user_id)),
+            (_, Some(dn)) => get_user_id_from_distinguished_name(
+                dn,
+                &self.ldap_info.base_dn,
+                &self.ldap_info.base_dn_str,
+            )
+            .ok(),
+            _ => None,
+        };
+        if requested_user.as_ref() != Some(user_id) {
+            debug!(?authz_id, "Authorization identity of another user");
+            return false;
+        }
+        true
+    }
+
+    /// The SASL GSSAPI mechanism (RFC 4752): the client authenticates with a Kerberos ticket,
+    /// over several bind requests. Returns the token to send back while the exchange goes on.
+    async fn do_sasl_gssapi_bind(
+        &mut self,
+        kerberos: &KerberosOptions,
+        token: &[u8],
+    ) -> ((LdapResultCode, String), Option<Vec<u8>>) {
+        let mut bind = match self.gssapi_bind.take() {
+            Some(bind) => bind,
+            None => match (kerberos.new_context)() {
+                Ok(context) => GssapiBind::new(context),
+                Err(e) => {
+                    warn!("Could not set up the Kerberos context: {:#}", e);
+                    return (
+                        (
+                            LdapResultCode::OperationsError,
+                            "Kerberos is not available".to_string(),
+                        ),
+                        None,
+                    );
+                }
+            },
+        };
+        let (principal, authz_id) = match bind.step(token) {
+            Ok(GssapiStep::Continue(token)) => {
+                self.gssapi_bind = Some(bind);
+                return ((LdapResultCode::Success, "".to_string()), Some(token));
+            }
+            Ok(GssapiStep::Done {
+                principal,
+                authz_id,
+            }) => (principal, authz_id),
+            Err(e) => {
+                debug!("GSSAPI negotiation failed: {:#}", e);
+                stats::record_ldap_bind(false);
+                return (
+                    (
+                        LdapResultCode::InvalidCredentials,
+                        "GSSAPI negotiation failed".to_string(),
+                    ),
+                    None,
+                );
+            }
+        };
+        let user_id = match get_user_id_from_principal(&principal, &kerberos.realm) {
+            Some(user_id) => user_id,
+            None => {
+                warn!("No user for the Kerberos principal \"{}\"", principal);
+                stats::record_ldap_bind(false);
+                return (
+                    (
+                        LdapResultCode::InvalidCredentials,
+                        format!("No user for the Kerberos principal {}", principal),
+                    ),
+                    None,
+                );
+            }
+        };
+        if !self.is_own_authz_id(authz_id.as_deref(), &user_id) {
+            stats::record_ldap_bind(false);
+            return (
+                (
+                    LdapResultCode::InvalidCredentials,
+                    "The authorization identity has to be the one of the Kerberos principal"
+                        .to_string(),
+                ),
+                None,
+            );
+        }
+        if self.bind_verified_user(user_id, "Kerberos ticket").await {
+            ((LdapResultCode::Success, "".to_string()), None)
+        } else {
+            (
+                (
+                    LdapResultCode::InvalidCredentials,
+                    "The account of the Kerberos principal can't be used".to_string(),
+                ),
+                None,
+            )
+        }
+    }
+
     pub async fn do_bind(&mut self, request: &LdapBindRequest) -> (LdapResultCode, String) {
+        let (code, message, _) = self.do_bind_with_password_policy(request).await;
+        (code, message)
+    }
+
+    /// Like [`Self::do_bind`], with the state of the password to send in the password policy
+    /// response control, if there is one to report.
+    #[instrument(skip_all, level = "debug")]
+    async fn do_bind_with_password_policy(
+        &mut self,
+        request: &LdapBindRequest,
+    ) -> (LdapResultCode, String, Option<PasswordPolicyState>) {
         debug!("DN: {}", &request.dn);
+        self.gssapi_bind = None;
+        // The updates were checked against the rights of the previous user.
+        self.transaction = None;
+        if self.bind_requires_tls {
+            debug!("Connection not encrypted");
+            stats::record_ldap_bind(false);
+            return (
+                LdapResultCode::ConfidentialityRequired,
+                "Binds require TLS: use StartTLS or LDAPS".to_string(),
+                None,
+            );
+        }
+        let LdapBindCred::Simple(password) = &request.cred;
+        if !request.dn.is_empty() && password.is_empty() {
+            // RFC 4513, section 5.1.2: this is an unauthenticated bind, not a successful login.
+            debug!("Empty password");
+            stats::record_ldap_bind(false);
+            let code = if self.empty_password_bind_unwilling_to_perform {
+                LdapResultCode::UnwillingToPerform
+            } else {
+                LdapResultCode::InvalidCredentials
+            };
+            return (
+                code,
+                "Unauthenticated binds are not allowed".to_string(),
+                None,
+            );
+        }
+        if request.dn.is_empty() && password.is_empty() && self.anonymous_bind_attributes.is_some()
+        {
+            // RFC 4513, section 5.1.1: the session goes back to the anonymous state.
+            debug!("Anonymous bind");
+            self.user_info = None;
+            return (LdapResultCode::Success, "".to_string(), None);
+        }
         let user_id = match get_user_id_from_distinguished_name(
             &request.dn.to_ascii_lowercase(),
             &self.ldap_info.base_dn,
             &self.ldap_info.base_dn_str,
         ) {
             Ok(s) => s,
-            Err(e) => return (LdapResultCode::NamingViolation, e.to_string()),
+            Err(e) => match self
+                .ldap_info
+                .active_directory_domain
+                .as_deref()
+                .and_then(|domain| get_user_id_from_logon_name(&request.dn, domain))
+            {
+                Some(user_id) => user_id,
+                None => return (LdapResultCode::NamingViolation, e.to_string(), None),
+            },
         };
-        let LdapBindCred::Simple(password) = &request.cred;
+        let peer_address = self
+            .peer_address
+            .map(|address| address.to_string())
+            .unwrap_or_else(|| "an unknown address".to_string());
+        if let Some(throttle) = self
+            .bind_limiter
+            .as_ref()
+            .and_then(|limiter| limiter.check(self.peer_address, &user_id))
+        {
+            warn!(
+                "Throttled LDAP bind for user \"{}\" from {}: {:?}",
+                user_id, peer_address, throttle
+            );
+            stats::record_ldap_bind(false);
+            return (
+                LdapResultCode::UnwillingToPerform,
+                format!(
+                    "Too many failed binds, try again in {} seconds",
+                    throttle.remaining().as_secs_f64().ceil()
+                ),
+                None,
+            );
+        }
         match self
             .backend_handler
             .bind(BindRequest {
@@ -210,32 +967,90 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             .await
         {
             Ok(()) => {
-                let user_groups = self.backend_handler.get_user_groups(&user_id).await;
-                let is_in_group = |name| {
-                    user_groups
-                        .as_ref()
-                        .map(|groups| groups.iter().any(|g| g.display_name == name))
-                        .unwrap_or(false)
+                if let Some(limiter) = &self.bind_limiter {
+                    limiter.record_success(&user_id);
+                }
+                let password_policy_state = match self.check_password_expiry(&user_id).await {
+                    Ok(state) => state,
+                    Err(e) => {
+                        warn!("Could not check the password expiry of {}: {}", user_id, e);
+                        stats::record_ldap_bind(false);
+                        return (
+                            LdapResultCode::OperationsError,
+                            "The password could not be checked".to_string(),
+                            None,
+                        );
+                    }
                 };
-                self.user_info = Some(ValidationResults {
-                    user: user_id,
-                    permission: if is_in_group("lldap_admin") {
-                        Permission::Admin
-                    } else if is_in_group("lldap_password_manager") {
-                        Permission::PasswordManager
-                    } else if is_in_group("lldap_strict_readonly") {
-                        Permission::Readonly
-                    } else {
-                        Permission::Regular
-                    },
-                });
+                if password_policy_state == Some(PasswordPolicyState::Expired) {
+                    warn!(
+                        "Refused the LDAP bind of user \"{}\": the password expired",
+                        user_id
+                    );
+                    self.record_bind(&user_id, false, Some("The password expired"))
+                        .await;
+                    stats::record_ldap_bind(false);
+                    return (
+                        LdapResultCode::InvalidCredentials,
+                        "The password expired".to_string(),
+                        password_policy_state,
+                    );
+                }
+                self.record_bind(&user_id, true, None).await;
+                self.set_bound_user(user_id).await;
                 debug!("Success!");
-                (LdapResultCode::Success, "".to_string())
+                stats::record_ldap_bind(true);
+                (
+                    LdapResultCode::Success,
+                    "".to_string(),
+                    password_policy_state,
+                )
+            }
+            Err(_) => {
+                // Keep the format stable: fail2ban filters rely on it.
+                warn!(
+                    "Failed LDAP bind for user \"{}\" from {}",
+                    user_id, peer_address
+                );
+                if let Some(limiter) = &self.bind_limiter {
+                    limiter.record_failure(self.peer_address, &user_id);
+                }
+                self.record_bind(&user_id, false, Some("Invalid credentials"))
+                    .await;
+                stats::record_ldap_bind(false);
+                (LdapResultCode::InvalidCredentials, "".to_string(), None)
             }
-            Err(_) => (LdapResultCode::InvalidCredentials, "".to_string()),
         }
     }
 
+    /// With grace logins, a bind with an expired password uses one of them, and is refused once
+    /// there are none left. Without them, the expiration is left to the LDAP clients.
+    async fn check_password_expiry(&self, user_id: &UserId) -> Result<Option<PasswordPolicyState>> {
+        let (expiry, max_grace_logins) = match self.ldap_info.password_expiry {
+            Some(
+                expiry @ PasswordExpiry {
+                    grace_logins: Some(max_grace_logins),
+                    ..
+                },
+            ) => (expiry, max_grace_logins),
+            _ => return Ok(None),
+        };
+        let user = self.backend_handler.get_user_details(user_id).await?;
+        if !expiry.is_expired(&user, chrono::Utc::now()) {
+            return Ok(None);
+        }
+        Ok(Some(
+            match self
+                .backend_handler
+                .use_grace_login(user_id, max_grace_logins)
+                .await?
+            {
+                Some(remaining) => PasswordPolicyState::GraceLogins(remaining),
+                None => PasswordPolicyState::Expired,
+            },
+        ))
+    }
+
     async fn change_password(&mut self, user: &UserId, password: &str) -> Result<()> {
         use lldap_auth::*;
         let mut rng = rand::rngs::OsRng;
@@ -254,6 +1069,10 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         let req = registration::ClientRegistrationFinishRequest {
             server_data: registration_start_response.server_data,
             registration_upload: registration_finish.message,
+            password_history_digest: Some(
+                password_history::password_history_digest(user.as_str(), password)
+                    .map_err(anyhow::Error::msg)?,
+            ),
         };
         self.backend_handler.registration_finish(req).await?;
         Ok(())
@@ -267,69 +1086,271 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
             code: LdapResultCode::InsufficentAccessRights,
             message: "No user currently bound".to_string(),
         })?;
-        match (&request.user_identity, &request.new_password) {
-            (Some(user), Some(password)) => {
-                match get_user_id_from_distinguished_name(
-                    user,
-                    &self.ldap_info.base_dn,
-                    &self.ldap_info.base_dn_str,
-                ) {
-                    Ok(uid) => {
-                        let user_is_admin = self
-                            .backend_handler
-                            .get_user_groups(&uid)
-                            .await
-                            .map_err(|e| LdapError {
-                                code: LdapResultCode::OperationsError,
-                                message: format!(
-                                    "Internal error while requesting user's groups: {:#?}",
-                                    e
-                                ),
-                            })?
-                            .iter()
-                            .any(|g| g.display_name == "lldap_admin");
-                        if !credentials.can_change_password(&uid, user_is_admin) {
-                            Err(LdapError {
-                                code: LdapResultCode::InsufficentAccessRights,
-                                message: format!(
-                                    r#"User `{}` cannot modify the password of user `{}`"#,
-                                    &credentials.user, &uid
-                                ),
-                            })
-                        } else if let Err(e) = self.change_password(&uid, password).await {
-                            Err(LdapError {
-                                code: LdapResultCode::Other,
-                                message: format!("Error while changing the password: {:#?}", e),
-                            })
-                        } else {
-                            Ok(vec![make_extended_response(
-                                LdapResultCode::Success,
-                                "".to_string(),
-                            )])
-                        }
-                    }
-                    Err(e) => Err(LdapError {
-                        code: LdapResultCode::InvalidDNSyntax,
-                        message: format!("Invalid username: {}", e),
-                    }),
-                }
+        let password = request.new_password.as_ref().ok_or_else(|| LdapError {
+            code: LdapResultCode::UnwillingToPerform,
+            message: "Missing the new password, generating one is not supported".to_string(),
+        })?;
+        // RFC 3062, section 2: without an identity, the password of the bound user is changed.
+        let uid = match &request.user_identity {
+            None => credentials.user.clone(),
+            Some(user) => get_user_id_from_distinguished_name(
+                user,
+                &self.ldap_info.base_dn,
+                &self.ldap_info.base_dn_str,
+            )
+            .map_err(|e| LdapError {
+                code: LdapResultCode::InvalidDNSyntax,
+                message: format!("Invalid username: {}", e),
+            })?,
+        };
+        if uid != credentials.user {
+            let user_is_admin = self
+                .backend_handler
+                .get_user_groups(&uid)
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::OperationsError,
+                    message: format!("Internal error while requesting user's groups: {:#?}", e),
+                })?
+                .iter()
+                .any(|g| g.display_name == "lldap_admin");
+            if !credentials.can_change_password(&uid, user_is_admin) {
+                return Err(LdapError {
+                    code: LdapResultCode::InsufficentAccessRights,
+                    message: format!(
+                        r#"User `{}` cannot modify the password of user `{}`"#,
+                        &credentials.user, &uid
+                    ),
+                });
             }
-            _ => Err(LdapError {
+        }
+        if let Some(old_password) = &request.old_password {
+            self.backend_handler
+                .bind(BindRequest {
+                    name: uid.clone(),
+                    password: old_password.clone(),
+                })
+                .await
+                .map_err(|_| LdapError {
+                    code: LdapResultCode::InvalidCredentials,
+                    message: "Wrong old password".to_string(),
+                })?;
+        }
+        if let Err(message) = self.password_policy.check(password, &[uid.as_str()]) {
+            return Err(LdapError {
                 code: LdapResultCode::ConstraintViolation,
-                message: "Missing either user_id or password".to_string(),
-            }),
+                message,
+            });
         }
+        self.change_password(&uid, password)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::Other,
+                message: format!("Error while changing the password: {:#?}", e),
+            })?;
+        Ok(vec![make_extended_response(
+            LdapResultCode::Success,
+            "".to_string(),
+        )])
     }
 
-    async fn do_extended_request(&mut self, request: &LdapExtendedRequest) -> Vec<LdapOp> {
-        match LdapPasswordModifyRequest::try_from(request) {
-            Ok(password_request) => self
-                .do_password_modification(&password_request)
-                .await
-                .unwrap_or_else(|e: LdapError| vec![make_extended_response(e.code, e.message)]),
-            Err(_) => vec![make_extended_response(
-                LdapResultCode::UnwillingToPerform,
-                format!("Unsupported extended operation: {}", &request.name),
+    /// The authorization identity of the session (RFC 4532, section 2.2): empty if anonymous.
+    fn do_whoami(&self) -> LdapOp {
+        let authz_id = match &self.user_info {
+            None => String::new(),
+            Some(user_info) => format!(
+                "dn:uid={},ou=people,{}",
+                escape_dn_value(user_info.user.as_str()),
+                self.ldap_info.base_dn_str
+            ),
+        };
+        LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code: LdapResultCode::Success,
+                matcheddn: "".to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            },
+            name: None,
+            value: Some(authz_id.into_bytes()),
+        })
+    }
+
+    fn do_start_transaction(&mut self) -> LdapOp {
+        if let Err(e) = self.check_can_write() {
+            return make_extended_response(e.code, e.message);
+        }
+        if self.transaction.is_some() {
+            return make_extended_response(
+                LdapResultCode::UnwillingToPerform,
+                "A transaction is already in progress".to_string(),
+            );
+        }
+        let identifier = gen_random_string(16).into_bytes();
+        self.transaction = Some(LdapTransaction {
+            identifier: identifier.clone(),
+            updates: Vec::new(),
+        });
+        LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code: LdapResultCode::Success,
+                matcheddn: "".to_string(),
+                message: "".to_string(),
+                referral: vec![],
+            },
+            name: None,
+            value: Some(identifier),
+        })
+    }
+
+    /// Queues an update request with the transaction specification control. It's answered with
+    /// success right away: the result of the update is the one of the End Transaction request.
+    fn add_to_transaction(&mut self, ldap_op: LdapOp, identifier: Option<&[u8]>) -> Option<LdapOp> {
+        let checked = self
+            .check_can_write()
+            .and_then(|()| match &self.transaction {
+                Some(transaction) if Some(transaction.identifier.as_slice()) == identifier => {
+                    Ok(())
+                }
+                _ => Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: "Unknown transaction".to_string(),
+                }),
+            });
+        if let Err(e) = checked {
+            return make_error_response(&ldap_op, e.code, e.message);
+        }
+        let response = make_error_response(&ldap_op, LdapResultCode::Success, "".to_string());
+        let update = match ldap_op {
+            LdapOp::AddRequest(request) => TransactionUpdate::Add(request),
+            LdapOp::ModifyRequest(request) => TransactionUpdate::Modify(request),
+            LdapOp::DelRequest(dn) => TransactionUpdate::Delete(dn),
+            ldap_op => {
+                return make_error_response(
+                    &ldap_op,
+                    LdapResultCode::UnwillingToPerform,
+                    "Only the add, modify and delete requests can be part of a transaction"
+                        .to_string(),
+                )
+            }
+        };
+        if let Some(transaction) = &mut self.transaction {
+            transaction.updates.push(update);
+        }
+        response
+    }
+
+    /// Ends the transaction of the session: its updates are applied only if it's committed.
+    #[instrument(skip_all, level = "debug")]
+    async fn do_end_transaction(&mut self, request: &LdapExtendedRequest) -> LdapOp {
+        let (commit, identifier) = match parse_end_transaction_request(request.value.as_deref()) {
+            Some(end) => end,
+            None => {
+                return make_extended_response(
+                    LdapResultCode::ProtocolError,
+                    "Invalid End Transaction request".to_string(),
+                )
+            }
+        };
+        let transaction = match self.transaction.take() {
+            Some(transaction) if transaction.identifier == identifier => transaction,
+            transaction => {
+                self.transaction = transaction;
+                return make_extended_response(
+                    LdapResultCode::UnwillingToPerform,
+                    "Unknown transaction".to_string(),
+                );
+            }
+        };
+        debug!(commit, updates = transaction.updates.len());
+        if commit {
+            if let Err(e) = self.commit_transaction(transaction.updates).await {
+                return make_extended_response(e.code, e.message);
+            }
+        }
+        make_extended_response(LdapResultCode::Success, "".to_string())
+    }
+
+    /// Applies the updates of a transaction in a single database transaction, which is rolled
+    /// back if any of them fails.
+    async fn commit_transaction(&mut self, updates: Vec<TransactionUpdate>) -> LdapResult<()> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+        let transaction = self
+            .backend_handler
+            .begin_transaction()
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Could not start the transaction: {:#}", e),
+            })?;
+        let backend_handler = std::mem::replace(&mut self.backend_handler, transaction);
+        let mut result = Ok(());
+        for (index, update) in updates.into_iter().enumerate() {
+            let update_result = match update {
+                TransactionUpdate::Add(request) => self.do_add_request(request).await,
+                TransactionUpdate::Modify(request) => self.do_modify_request(request).await,
+                TransactionUpdate::Delete(dn) => self.do_delete_request(&dn).await,
+            };
+            if let Err(e) = update_result {
+                result = Err(LdapError {
+                    code: e.code,
+                    message: format!("Update {} of the transaction failed: {}", index + 1, e),
+                });
+                break;
+            }
+        }
+        let transaction = std::mem::replace(&mut self.backend_handler, backend_handler);
+        let end_result = transaction.end_transaction(result.is_ok()).await;
+        result?;
+        end_result.map_err(|e| LdapError {
+            code: LdapResultCode::OperationsError,
+            message: format!("Could not commit the transaction: {:#}", e),
+        })
+    }
+
+    /// A Cancel request that reaches the handler doesn't match any search in progress: those are
+    /// cancelled by the server, which reads the requests while they run.
+    fn do_cancel(request: &LdapExtendedRequest) -> (LdapOp, Vec<LdapControl>, ResponseExtras) {
+        let (response, result_code) = match parse_cancel_request(request.value.as_deref()) {
+            Some(cancel_id) => (
+                make_extended_response(
+                    LdapResultCode::Other,
+                    format!("No operation in progress with the message ID {}", cancel_id),
+                ),
+                Some(NO_SUCH_OPERATION),
+            ),
+            None => (
+                make_extended_response(
+                    LdapResultCode::ProtocolError,
+                    "Invalid Cancel request".to_string(),
+                ),
+                None,
+            ),
+        };
+        (
+            response,
+            Vec::new(),
+            ResponseExtras {
+                controls: Vec::new(),
+                result_code,
+            },
+        )
+    }
+
+    async fn do_extended_request(&mut self, request: &LdapExtendedRequest) -> Vec<LdapOp> {
+        if request.name == WHOAMI_OID {
+            return vec![self.do_whoami()];
+        }
+        match LdapPasswordModifyRequest::try_from(request) {
+            Ok(password_request) => self
+                .do_password_modification(&password_request)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_extended_response(e.code, e.message)]),
+            Err(_) => vec![make_extended_response(
+                LdapResultCode::UnwillingToPerform,
+                format!("Unsupported extended operation: {}", &request.name),
             )],
         }
     }
@@ -343,16 +1364,34 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 if attribute.to_ascii_lowercase() == "objectclass" {
                     debug!("rootDSE request");
                     return Ok(vec![
-                        root_dse_response(&self.ldap_info.base_dn_str),
+                        root_dse_response(&self.ldap_info.base_dn_str, &self.get_sasl_mechanisms()),
                         make_search_success(),
                     ]);
                 }
             }
         }
+        // Like the root DSE, the schema is readable before binding, so that clients can discover
+        // it.
+        if request.base.eq_ignore_ascii_case(SUBSCHEMA_DN) && request.scope == LdapSearchScope::Base
+        {
+            debug!("Subschema request");
+            return Ok(vec![
+                LdapOp::SearchResultEntry(make_subschema_entry(&request.attrs)),
+                make_search_success(),
+            ]);
+        }
+        if self.user_info.is_none() {
+            if let Some(attributes) = self.anonymous_bind_attributes.clone() {
+                return self.do_restricted_search(request, &attributes).await;
+            }
+        }
         let user_info = self.user_info.as_ref().ok_or_else(|| LdapError {
             code: LdapResultCode::InsufficentAccessRights,
             message: "No user currently bound".to_string(),
         })?;
+        if let Some(attributes) = self.bound_user_attributes.clone() {
+            return self.do_restricted_search(request, &attributes).await;
+        }
         let user_filter = if user_info.is_admin_or_readonly() {
             None
         } else {
@@ -361,6 +1400,132 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         self.do_search(request, user_filter).await
     }
 
+    /// Anonymous sessions and service accounts can read all the entries, but only the given
+    /// attributes. They can only filter on these attributes, otherwise the values of the others
+    /// could be guessed.
+    async fn do_restricted_search(
+        &mut self,
+        request: &LdapSearchRequest,
+        attributes: &[String],
+    ) -> LdapResult<Vec<LdapOp>> {
+        let is_allowed = |attribute: &str| {
+            attribute.eq_ignore_ascii_case("objectclass")
+                || attributes.iter().any(|a| a.eq_ignore_ascii_case(attribute))
+        };
+        if !filter_uses_only(&request.filter, &is_allowed) {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: if self.user_info.is_none() {
+                    "Anonymous searches can only filter on the readable attributes"
+                } else {
+                    "Service accounts can only filter on the readable attributes"
+                }
+                .to_string(),
+            });
+        }
+        let mut results = self.do_search(request, None).await?;
+        for result in results.iter_mut() {
+            if let LdapOp::SearchResultEntry(entry) = result {
+                entry
+                    .attributes
+                    .retain(|a| attributes.iter().any(|x| x.eq_ignore_ascii_case(&a.atype)));
+            }
+        }
+        Ok(results)
+    }
+
+    /// Builds the entry of the base DN, `ou=people` or `ou=groups`, with its number of children.
+    /// The counts only include the entries visible to the user.
+    async fn get_container_entry(
+        &self,
+        scope: &SearchScope,
+        attrs: &[String],
+        user_filter: Option<&UserId>,
+    ) -> LdapResult<Option<LdapOp>> {
+        let get_stats = || async {
+            self.backend_handler
+                .get_stats()
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::OperationsError,
+                    message: format!("Internal error while counting entries: {:#?}", e),
+                })
+        };
+        let base_dn_str = &self.ldap_info.base_dn_str;
+        let (dn, object_class, (rdn_attr, rdn_value), num_subordinates) = match scope {
+            // The only children of the base are the two containers.
+            SearchScope::Global => (
+                base_dn_str.clone(),
+                "domain",
+                self.ldap_info.base_dn[0].clone(),
+                2,
+            ),
+            SearchScope::Users => (
+                format!("ou=people,{}", base_dn_str),
+                "organizationalUnit",
+                ("ou".to_string(), "people".to_string()),
+                match user_filter {
+                    Some(_) => 1,
+                    None => get_stats().await?.user_count,
+                },
+            ),
+            SearchScope::Groups => (
+                format!("ou=groups,{}", base_dn_str),
+                "organizationalUnit",
+                ("ou".to_string(), "groups".to_string()),
+                match user_filter {
+                    Some(user) => self
+                        .backend_handler
+                        .get_user_groups(user)
+                        .await
+                        .map_err(|e| LdapError {
+                            code: LdapResultCode::OperationsError,
+                            message: format!("Internal error while counting entries: {:#?}", e),
+                        })?
+                        .iter()
+                        .filter(|g| !g.archived)
+                        .count() as u64,
+                    None => {
+                        let stats = get_stats().await?;
+                        stats.group_count.saturating_sub(stats.archived_group_count)
+                    }
+                },
+            ),
+            _ => return Ok(None),
+        };
+        let is_requested = |name: &str| {
+            attrs
+                .iter()
+                .any(|a| a == "*" || a.eq_ignore_ascii_case(name))
+        };
+        let mut attributes = Vec::new();
+        if is_requested("objectClass") {
+            attributes.push(LdapPartialAttribute {
+                atype: "objectClass".to_string(),
+                vals: vec![b"top".to_vec(), object_class.as_bytes().to_vec()],
+            });
+        }
+        if is_requested(&rdn_attr) {
+            attributes.push(LdapPartialAttribute {
+                atype: rdn_attr,
+                vals: vec![rdn_value.into_bytes()],
+            });
+        }
+        // Operational attributes, only returned when explicitly requested.
+        for name in ["numSubordinates", "subordinateCount"] {
+            if attrs.iter().any(|a| a.eq_ignore_ascii_case(name)) {
+                attributes.push(LdapPartialAttribute {
+                    atype: name.to_string(),
+                    vals: vec![num_subordinates.to_string().into_bytes()],
+                });
+            }
+        }
+        Ok(Some(LdapOp::SearchResultEntry(LdapSearchResultEntry {
+            dn,
+            attributes,
+        })))
+    }
+
     #[instrument(skip_all, level = "debug")]
     pub async fn do_search(
         &mut self,
@@ -370,7 +1535,16 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         let user_filter = user_filter.as_ref();
         let dn_parts = parse_distinguished_name(&request.base.to_ascii_lowercase())?;
         let scope = get_search_scope(&self.ldap_info.base_dn, &dn_parts);
+        // There are no aliases, so `request.aliases` doesn't change the results whatever its value.
         debug!(?request.base, ?scope);
+        if request.scope == LdapSearchScope::Base && requests_subordinate_count(&request.attrs) {
+            if let Some(entry) = self
+                .get_container_entry(&scope, &request.attrs, user_filter)
+                .await?
+            {
+                return Ok(vec![entry, make_search_success()]);
+            }
+        }
         // Disambiguate the lifetimes.
         fn cast<T, R, B: 'a, 'a>(x: T) -> T
         where
@@ -386,6 +1560,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 &request.attrs,
                 &request.base,
                 &user_filter,
+                &self.user_sort_keys,
                 backend_handler,
             )
             .await
@@ -443,7 +1618,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         Ok(results)
     }
 
-    async fn do_create_user(&self, request: LdapAddRequest) -> LdapResult<Vec<LdapOp>> {
+    fn check_can_write(&self) -> LdapResult<()> {
         if !self
             .user_info
             .as_ref()
@@ -455,8 +1630,107 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 message: "Unauthorized write".to_string(),
             });
         }
+        Ok(())
+    }
+
+    fn get_write_target(&self, dn: &str) -> LdapResult<WriteTarget> {
+        let dn = dn.to_ascii_lowercase();
+        let parts = parse_distinguished_name(&dn)?;
+        if parts.get(1) == Some(&("ou".to_string(), "groups".to_string())) {
+            get_group_id_from_distinguished_name(
+                &dn,
+                &self.ldap_info.base_dn,
+                &self.ldap_info.base_dn_str,
+            )
+            .map(WriteTarget::Group)
+        } else {
+            get_user_id_from_distinguished_name(
+                &dn,
+                &self.ldap_info.base_dn,
+                &self.ldap_info.base_dn_str,
+            )
+            .map(WriteTarget::User)
+        }
+    }
+
+    /// Parses a `member` value, the DN of a user.
+    fn get_member_id(&self, value: &[u8]) -> LdapResult<UserId> {
+        get_user_id_from_distinguished_name(
+            &decode_attribute_value(value)?.to_ascii_lowercase(),
+            &self.ldap_info.base_dn,
+            &self.ldap_info.base_dn_str,
+        )
+    }
+
+    /// Finds a group from its lowercase name, as found in a DN.
+    async fn get_group_by_name(&self, name: &str) -> LdapResult<Group> {
+        self.backend_handler
+            .list_groups(None)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Internal error while listing the groups: {:#?}", e),
+            })?
+            .into_iter()
+            .find(|g| g.display_name.to_ascii_lowercase() == name)
+            .ok_or_else(|| LdapError {
+                code: LdapResultCode::NoSuchObject,
+                message: format!("Group not found: {}", name),
+            })
+    }
+
+    /// The group managed by the server can't be modified or deleted.
+    fn check_not_everyone_group(&self, group: &Group) -> LdapResult<()> {
+        if self.everyone_group.as_ref() == Some(&group.display_name) {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: format!("Cannot change the {} group", group.display_name),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the user can join the group, `None` if it is being created.
+    async fn check_group_limit(
+        &self,
+        user_id: &UserId,
+        group_id: Option<GroupId>,
+    ) -> LdapResult<()> {
+        match self.group_limit {
+            None => Ok(()),
+            Some(limit) => check_group_limit(&self.backend_handler, user_id, group_id, limit)
+                .await
+                .map_err(|e| LdapError {
+                    code: LdapResultCode::ConstraintViolation,
+                    message: e.message().to_owned(),
+                }),
+        }
+    }
+
+    async fn apply_group_assignment_rules(&self, user_id: &UserId) -> LdapResult<()> {
+        apply_group_assignment_rules(&self.backend_handler, &self.group_assignment_rules, user_id)
+            .await
+            .map(|_| ())
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Could not apply the group assignment rules: {:#?}", e),
+            })
+    }
+
+    async fn do_add_request(&self, request: LdapAddRequest) -> LdapResult<Vec<LdapOp>> {
+        let dn = request.dn.clone();
+        let response = match self.get_write_target(&dn)? {
+            WriteTarget::User(_) => self.do_create_user(request).await?,
+            WriteTarget::Group(name) => self.do_create_group(&name, request).await?,
+        };
+        self.record_write("ldapAdd", &dn).await;
+        Ok(response)
+    }
+
+    async fn do_create_user(&self, request: LdapAddRequest) -> LdapResult<Vec<LdapOp>> {
+        self.check_can_write()?;
         let user_id = get_user_id_from_distinguished_name(
-            &request.dn,
+            &request.dn.to_ascii_lowercase(),
             &self.ldap_info.base_dn,
             &self.ldap_info.base_dn_str,
         )?;
@@ -477,22 +1751,20 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 }
             }
         }
-        let attributes: HashMap<String, Vec<u8>> = request
+        // The SSH keys are the only multi-valued attribute.
+        let (ssh_key_attributes, attributes): (Vec<_>, Vec<_>) = request
             .attributes
+            .into_iter()
+            .partition(|attr| attr.atype.eq_ignore_ascii_case("sshpublickey"));
+        let ssh_public_keys = ssh_key_attributes
+            .iter()
+            .flat_map(|attr| &attr.vals)
+            .map(|val| decode_ssh_public_key(val))
+            .collect::<LdapResult<Vec<_>>>()?;
+        let attributes: HashMap<String, Vec<u8>> = attributes
             .into_iter()
             .map(parse_attribute)
             .collect::<LdapResult<_>>()?;
-        fn decode_attribute_value(val: &[u8]) -> LdapResult<String> {
-            std::str::from_utf8(val)
-                .map_err(|e| LdapError {
-                    code: LdapResultCode::ConstraintViolation,
-                    message: format!(
-                        "Attribute value is invalid UTF-8: {:#?} (value {:?})",
-                        e, val
-                    ),
-                })
-                .map(str::to_owned)
-        }
         let get_attribute = |name| {
             attributes
                 .get(name)
@@ -501,7 +1773,7 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
         };
         self.backend_handler
             .create_user(CreateUserRequest {
-                user_id,
+                user_id: user_id.clone(),
                 email: get_attribute("mail")
                     .or_else(|| get_attribute("email"))
                     .transpose()?
@@ -509,8 +1781,22 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 display_name: get_attribute("cn").transpose()?,
                 first_name: get_attribute("givenname").transpose()?,
                 last_name: get_attribute("sn").transpose()?,
+                preferred_language: get_attribute("preferredlanguage").transpose()?,
+                email_aliases: Vec::new(),
+                ssh_public_keys,
+                uid_number: attributes
+                    .get("uidnumber")
+                    .map(|v| decode_number_value(v))
+                    .transpose()?,
+                gid_number: attributes
+                    .get("gidnumber")
+                    .map(|v| decode_number_value(v))
+                    .transpose()?,
+                home_directory: get_attribute("homedirectory").transpose()?,
+                login_shell: get_attribute("loginshell").transpose()?,
                 avatar: attributes
-                    .get("avatar")
+                    .get("jpegphoto")
+                    .or_else(|| attributes.get("avatar"))
                     .map(Vec::as_slice)
                     .map(JpegPhoto::try_from)
                     .transpose()
@@ -524,262 +1810,3569 @@ impl<Backend: BackendHandler + LoginHandler + OpaqueHandler> LdapHandler<Backend
                 code: LdapResultCode::OperationsError,
                 message: format!("Could not create user: {:#?}", e),
             })?;
+        self.apply_group_assignment_rules(&user_id).await?;
         Ok(vec![make_add_error(LdapResultCode::Success, String::new())])
     }
 
-    pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
-        Some(match ldap_op {
-            LdapOp::BindRequest(request) => {
-                let (code, message) = self.do_bind(&request).await;
-                vec![LdapOp::BindResponse(LdapBindResponse {
-                    res: LdapResultOp {
-                        code,
-                        matcheddn: "".to_string(),
-                        message,
-                        referral: vec![],
-                    },
-                    saslcreds: None,
-                })]
+    async fn do_create_group(
+        &self,
+        name: &str,
+        request: LdapAddRequest,
+    ) -> LdapResult<Vec<LdapOp>> {
+        self.check_can_write()?;
+        // The name from the DN is lowercase: keep the case of the `cn` attribute if it matches.
+        let mut display_name = name.to_string();
+        let mut members = Vec::new();
+        for attribute in request.attributes {
+            match attribute.atype.to_ascii_lowercase().as_str() {
+                "cn" => {
+                    if let Some(cn) = attribute
+                        .vals
+                        .first()
+                        .and_then(|v| std::str::from_utf8(v).ok())
+                        .filter(|cn| cn.to_ascii_lowercase() == name)
+                    {
+                        display_name = cn.to_string();
+                    }
+                }
+                "member" | "uniquemember" => {
+                    for value in &attribute.vals {
+                        let user_id = self.get_member_id(value)?;
+                        if members.contains(&user_id) {
+                            if self.strict_group_membership_adds {
+                                return Err(LdapError {
+                                    code: LdapResultCode::AttributeOrValueExists,
+                                    message: format!("{} is listed twice", user_id),
+                                });
+                            }
+                            continue;
+                        }
+                        members.push(user_id);
+                    }
+                }
+                _ => (),
             }
-            LdapOp::SearchRequest(request) => self
-                .do_search_or_dse(&request)
-                .await
-                .unwrap_or_else(|e: LdapError| vec![make_search_error(e.code, e.message)]),
-            LdapOp::UnbindRequest => {
-                self.user_info = None;
-                // No need to notify on unbind (per rfc4511)
-                return None;
+        }
+        for user_id in &members {
+            self.check_group_limit(user_id, None).await?;
+        }
+        self.backend_handler
+            .create_group_with_members(&display_name, &members)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Could not create group: {:#?}", e),
+            })?;
+        Ok(vec![make_add_error(LdapResultCode::Success, String::new())])
+    }
+
+    async fn do_modify_request(&self, request: LdapModifyRequest) -> LdapResult<Vec<LdapOp>> {
+        self.check_can_write()?;
+        match self.get_write_target(&request.dn)? {
+            WriteTarget::User(user_id) => self.do_modify_user(user_id, request.changes).await?,
+            WriteTarget::Group(name) => self.do_modify_group(&name, request.changes).await?,
+        }
+        self.record_write("ldapModify", &request.dn).await;
+        Ok(vec![make_modify_response(
+            LdapResultCode::Success,
+            String::new(),
+        )])
+    }
+
+    /// Applies all the changes in a single update, so either all of them are made or none are.
+    async fn do_modify_user(&self, user_id: UserId, changes: Vec<LdapModify>) -> LdapResult<()> {
+        let mut request = UpdateUserRequest {
+            user_id,
+            ..Default::default()
+        };
+        for change in changes {
+            let attribute = change.modification.atype.to_ascii_lowercase();
+            if attribute == "sshpublickey" {
+                let keys = change
+                    .modification
+                    .vals
+                    .iter()
+                    .map(|val| decode_ssh_public_key(val))
+                    .collect::<LdapResult<Vec<_>>>()?;
+                let mut current_keys = match request.ssh_public_keys.take() {
+                    Some(current_keys) => current_keys,
+                    None => self.get_user_ssh_public_keys(&request.user_id).await?,
+                };
+                match change.operation {
+                    LdapModifyType::Add => current_keys.extend(keys),
+                    // Deleting the attribute without values removes all the keys.
+                    LdapModifyType::Delete if keys.is_empty() => current_keys.clear(),
+                    LdapModifyType::Delete => current_keys.retain(|key| !keys.contains(key)),
+                    LdapModifyType::Replace => current_keys = keys,
+                }
+                request.ssh_public_keys = Some(current_keys);
+                continue;
             }
-            LdapOp::ExtendedRequest(request) => self.do_extended_request(&request).await,
-            LdapOp::AddRequest(request) => self
-                .do_create_user(request)
-                .await
-                .unwrap_or_else(|e: LdapError| vec![make_add_error(e.code, e.message)]),
-            op => vec![make_extended_response(
-                LdapResultCode::UnwillingToPerform,
-                format!("Unsupported operation: {:#?}", op),
-            )],
-        })
+            // The other user attributes are single-valued and most can't be empty.
+            let value = match (change.operation, change.modification.vals.as_slice()) {
+                (LdapModifyType::Add | LdapModifyType::Replace, [value]) => value.clone(),
+                _ => {
+                    return Err(LdapError {
+                        code: LdapResultCode::ConstraintViolation,
+                        message: format!("Expected a single new value for attribute {}", attribute),
+                    })
+                }
+            };
+            match attribute.as_str() {
+                "mail" | "email" => request.email = Some(decode_attribute_value(&value)?),
+                "cn" | "displayname" => {
+                    request.display_name = Some(decode_attribute_value(&value)?)
+                }
+                "givenname" => request.first_name = Some(decode_attribute_value(&value)?),
+                "sn" => request.last_name = Some(decode_attribute_value(&value)?),
+                "preferredlanguage" => {
+                    request.preferred_language = Some(decode_attribute_value(&value)?)
+                }
+                "uidnumber" => request.uid_number = Some(decode_number_value(&value)?),
+                "gidnumber" => request.gid_number = Some(decode_number_value(&value)?),
+                "homedirectory" => request.home_directory = Some(decode_attribute_value(&value)?),
+                "loginshell" => request.login_shell = Some(decode_attribute_value(&value)?),
+                "jpegphoto" | "avatar" => {
+                    request.avatar =
+                        Some(
+                            JpegPhoto::try_from(value.as_slice()).map_err(|e| LdapError {
+                                code: LdapResultCode::ConstraintViolation,
+                                message: format!("Invalid JPEG photo: {:#?}", e),
+                            })?,
+                        )
+                }
+                "userpassword" => {
+                    return Err(LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: "Use the password modify extended operation to change passwords"
+                            .to_string(),
+                    })
+                }
+                _ => {
+                    return Err(LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: format!("Cannot modify the attribute {} of a user", attribute),
+                    })
+                }
+            }
+        }
+        let user_id = request.user_id.clone();
+        self.backend_handler
+            .update_user(request)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Could not update user: {:#?}", e),
+            })?;
+        self.apply_group_assignment_rules(&user_id).await
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{
-        domain::{error::Result, handler::*, opaque_handler::*, types::*},
-        uuid,
-    };
-    use async_trait::async_trait;
-    use chrono::TimeZone;
-    use ldap3_proto::proto::{LdapDerefAliases, LdapSearchScope};
-    use mockall::predicate::eq;
-    use std::collections::HashSet;
-    use tokio;
+    async fn get_user_ssh_public_keys(&self, user_id: &UserId) -> LdapResult<Vec<String>> {
+        self.backend_handler
+            .get_user_details(user_id)
+            .await
+            .map(|user| user.ssh_public_keys)
+            .map_err(|e| LdapError {
+                code: LdapResultCode::NoSuchObject,
+                message: format!("Could not get the user {}: {:#?}", user_id, e),
+            })
+    }
 
-    mockall::mock! {
-        pub TestBackendHandler{}
-        impl Clone for TestBackendHandler {
-            fn clone(&self) -> Self;
+    /// Only the members of a group can be modified. Renaming a group would change its DN. Like for
+    /// the users, the changes are applied together: either all of them are made or none are. The
+    /// new members are checked like with `addUserToGroup`: the group limit, and the strict adds.
+    async fn do_modify_group(&self, name: &str, changes: Vec<LdapModify>) -> LdapResult<()> {
+        let group = self.get_group_by_name(name).await?;
+        self.check_not_everyone_group(&group)?;
+        if group.dynamic_filter.is_some() {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "Cannot change the members of a dynamic group".to_string(),
+            });
         }
-        #[async_trait]
-        impl LoginHandler for TestBackendHandler {
-            async fn bind(&self, request: BindRequest) -> Result<()>;
+        let mut members = group.users.clone();
+        for change in changes {
+            let attribute = change.modification.atype.to_ascii_lowercase();
+            if attribute != "member" && attribute != "uniquemember" {
+                return Err(LdapError {
+                    code: LdapResultCode::UnwillingToPerform,
+                    message: format!("Cannot modify the attribute {} of a group", attribute),
+                });
+            }
+            let users = change
+                .modification
+                .vals
+                .iter()
+                .map(|v| self.get_member_id(v))
+                .collect::<LdapResult<Vec<_>>>()?;
+            match change.operation {
+                LdapModifyType::Add => {
+                    for user_id in users {
+                        if !members.contains(&user_id) {
+                            members.push(user_id);
+                        } else if self.strict_group_membership_adds {
+                            return Err(LdapError {
+                                code: LdapResultCode::AttributeOrValueExists,
+                                message: format!("{} is already a member of the group", user_id),
+                            });
+                        }
+                    }
+                }
+                // Deleting the attribute without values removes all the members.
+                LdapModifyType::Delete if users.is_empty() => members.clear(),
+                LdapModifyType::Delete => members.retain(|u| !users.contains(u)),
+                LdapModifyType::Replace => {
+                    members.clear();
+                    for user_id in users {
+                        if !members.contains(&user_id) {
+                            members.push(user_id);
+                        }
+                    }
+                }
+            }
         }
-        #[async_trait]
-        impl GroupBackendHandler for TestBackendHandler {
-            async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
-            async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
-            async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
-            async fn create_group(&self, group_name: &str) -> Result<GroupId>;
-            async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+        if group.display_name == "lldap_admin" {
+            if let Some(current_user) = self.user_info.as_ref().map(|u| &u.user) {
+                if group.users.contains(current_user) && !members.contains(current_user) {
+                    return Err(LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: "Cannot remove the current user from the admin group".to_string(),
+                    });
+                }
+            }
         }
-        #[async_trait]
-        impl UserBackendHandler for TestBackendHandler {
-            async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
-            async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
-            async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
-            async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
-            async fn delete_user(&self, user_id: &UserId) -> Result<()>;
-            async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
-            async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
-            async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        for user_id in members.iter().filter(|u| !group.users.contains(u)) {
+            self.check_group_limit(user_id, Some(group.id)).await?;
         }
-        #[async_trait]
-        impl BackendHandler for TestBackendHandler {}
-        #[async_trait]
-        impl OpaqueHandler for TestBackendHandler {
-            async fn login_start(
-                &self,
-                request: login::ClientLoginStartRequest
-            ) -> Result<login::ServerLoginStartResponse>;
-            async fn login_finish(&self, request: login::ClientLoginFinishRequest) -> Result<UserId>;
-            async fn registration_start(
-                &self,
-                request: registration::ClientRegistrationStartRequest
-            ) -> Result<registration::ServerRegistrationStartResponse>;
-            async fn registration_finish(
-                &self,
-                request: registration::ClientRegistrationFinishRequest
-            ) -> Result<()>;
+        self.backend_handler
+            .set_group_members(group.id, &members)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Could not update the members of the group: {:#?}", e),
+            })
+    }
+
+    async fn do_delete_request(&self, dn: &str) -> LdapResult<Vec<LdapOp>> {
+        self.check_can_write()?;
+        match self.get_write_target(dn)? {
+            WriteTarget::User(user_id) => {
+                if self.user_info.as_ref().map(|u| &u.user) == Some(&user_id) {
+                    return Err(LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: "Cannot delete current user".to_string(),
+                    });
+                }
+                self.backend_handler
+                    .delete_user(&user_id)
+                    .await
+                    .map_err(|e| LdapError {
+                        code: LdapResultCode::OperationsError,
+                        message: format!("Could not delete user: {:#?}", e),
+                    })?;
+            }
+            WriteTarget::Group(name) => {
+                let group = self.get_group_by_name(&name).await?;
+                if group.display_name == "lldap_admin" {
+                    return Err(LdapError {
+                        code: LdapResultCode::UnwillingToPerform,
+                        message: "Cannot delete admin group".to_string(),
+                    });
+                }
+                self.check_not_everyone_group(&group)?;
+                self.backend_handler
+                    .delete_group(group.id)
+                    .await
+                    .map_err(|e| LdapError {
+                        code: LdapResultCode::OperationsError,
+                        message: format!("Could not delete group: {:#?}", e),
+                    })?;
+            }
         }
+        self.record_write("ldapDelete", dn).await;
+        Ok(vec![make_delete_response(
+            LdapResultCode::Success,
+            String::new(),
+        )])
     }
 
-    fn make_search_request<S: Into<String>>(
-        base: &str,
-        filter: LdapFilter,
-        attrs: Vec<S>,
-    ) -> LdapSearchRequest {
-        LdapSearchRequest {
-            base: base.to_string(),
-            scope: LdapSearchScope::Base,
+    /// Checks the password of a user for a compare request, like a bind but without changing the
+    /// identity of the session. The failures count towards the throttling of the binds.
+    async fn compare_password(&self, dn: &str, password: &[u8]) -> LdapResult<bool> {
+        if !self
+            .user_info
+            .as_ref()
+            .map(|u| u.is_admin_or_readonly())
+            .unwrap_or(false)
+        {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Only the admins and read-only users can compare passwords".to_string(),
+            });
+        }
+        let user_id = match self.get_write_target(dn)? {
+            WriteTarget::User(user_id) => user_id,
+            WriteTarget::Group(_) => return Ok(false),
+        };
+        if let Some(throttle) = self
+            .bind_limiter
+            .as_ref()
+            .and_then(|limiter| limiter.check(self.peer_address, &user_id))
+        {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: format!(
+                    "Too many failed binds, try again in {} seconds",
+                    throttle.remaining().as_secs_f64().ceil()
+                ),
+            });
+        }
+        let result = self
+            .backend_handler
+            .bind(BindRequest {
+                name: user_id.clone(),
+                password: decode_attribute_value(password)?,
+            })
+            .await;
+        if let Some(limiter) = &self.bind_limiter {
+            match result {
+                Ok(()) => limiter.record_success(&user_id),
+                Err(_) => limiter.record_failure(self.peer_address, &user_id),
+            }
+        }
+        Ok(result.is_ok())
+    }
+
+    /// Compares the value of an attribute with the same matching rules as the search filters, and
+    /// with the same visibility of the entries. Passwords are verified like for a bind.
+    #[instrument(skip_all, level = "debug")]
+    async fn do_compare(&mut self, request: LdapCompareRequest) -> LdapResult<Vec<LdapOp>> {
+        debug!(?request.dn, ?request.atype);
+        let matches = if request.atype.eq_ignore_ascii_case("userPassword") {
+            self.compare_password(&request.dn, &request.val).await?
+        } else {
+            let mut search = LdapSearchRequest {
+                base: request.dn.clone(),
+                scope: LdapSearchScope::Base,
+                aliases: LdapDerefAliases::Never,
+                sizelimit: 0,
+                timelimit: 0,
+                typesonly: false,
+                filter: LdapFilter::Present("objectClass".to_string()),
+                attrs: vec!["1.1".to_string()],
+            };
+            let is_found = |results: &[LdapOp]| {
+                results
+                    .iter()
+                    .any(|r| matches!(r, LdapOp::SearchResultEntry(_)))
+            };
+            if !is_found(&self.do_search_or_dse(&search).await?) {
+                return Err(LdapError {
+                    code: LdapResultCode::NoSuchObject,
+                    message: format!("No such entry: {}", request.dn),
+                });
+            }
+            search.filter =
+                LdapFilter::Equality(request.atype.clone(), decode_attribute_value(&request.val)?);
+            is_found(&self.do_search_or_dse(&search).await?)
+        };
+        let code = if matches {
+            LdapResultCode::CompareTrue
+        } else {
+            LdapResultCode::CompareFalse
+        };
+        Ok(vec![make_compare_response(code, String::new())])
+    }
+
+    /// Serves a search with the sync request control of RFC 4533, in the refreshOnly mode. The
+    /// entries that changed since the cookie are sent in full, and the others are only reported as
+    /// present: the consumer deletes the ones that weren't mentioned at all.
+    async fn do_sync_search(
+        &mut self,
+        request: &LdapSearchRequest,
+        mode: &SyncRequestMode,
+        cookie: Option<&[u8]>,
+    ) -> LdapResult<Vec<(LdapOp, Vec<LdapControl>)>> {
+        if !self
+            .user_info
+            .as_ref()
+            .map(|u| u.is_admin_or_readonly())
+            .unwrap_or(false)
+        {
+            return Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Only the admins and read-only users can synchronize the directory"
+                    .to_string(),
+            });
+        }
+        if let SyncRequestMode::RefreshAndPersist = mode {
+            return Err(LdapError {
+                code: LdapResultCode::UnwillingToPerform,
+                message: "Only the refreshOnly synchronization mode is supported".to_string(),
+            });
+        }
+        // The digests cover every attribute, whatever the consumer asked for, so that all the
+        // consumers share the same change sequence numbers.
+        let snapshot = LdapSearchRequest {
+            base: self.ldap_info.base_dn_str.clone(),
+            scope: LdapSearchScope::Subtree,
             aliases: LdapDerefAliases::Never,
             sizelimit: 0,
             timelimit: 0,
             typesonly: false,
-            filter,
-            attrs: attrs.into_iter().map(Into::into).collect(),
+            filter: LdapFilter::Present("objectClass".to_string()),
+            attrs: vec!["*".to_string(), "+".to_string()],
+        };
+        let mut entry_uuids = HashMap::new();
+        let mut digests = Vec::new();
+        for result in self.do_search(&snapshot, None).await? {
+            if let LdapOp::SearchResultEntry(entry) = result {
+                let uuid = match get_entry_uuid(&entry) {
+                    Some(uuid) => uuid,
+                    None => continue,
+                };
+                if let Ok(entry_uuid) = uuid::Uuid::parse_str(uuid.as_str()) {
+                    digests.push((uuid.clone(), get_entry_digest(&entry)));
+                    entry_uuids.insert(entry.dn.to_ascii_lowercase(), (uuid, entry_uuid));
+                }
+            }
+        }
+        let sync_entries = self
+            .backend_handler
+            .record_sync_entries(digests)
+            .await
+            .map_err(|e| LdapError {
+                code: LdapResultCode::OperationsError,
+                message: format!("Internal error while recording the entries: {:#?}", e),
+            })?;
+        // A cookie we don't know, or from the future (e.g. after restoring a backup), gets a full
+        // refresh.
+        let since = cookie
+            .and_then(parse_cookie)
+            .filter(|since| *since <= sync_entries.change_sequence);
+        debug!(?since, sync_entries.change_sequence);
+        let results = match self.bound_user_attributes.clone() {
+            Some(attributes) => self.do_restricted_search(request, &attributes).await?,
+            None => self.do_search(request, None).await?,
+        };
+        let mut responses = Vec::new();
+        for result in results {
+            match result {
+                LdapOp::SearchResultEntry(mut entry) => {
+                    let (uuid, entry_uuid) = match entry_uuids.get(&entry.dn.to_ascii_lowercase()) {
+                        Some(uuids) => uuids,
+                        None => {
+                            debug!(?entry.dn, "Not a synchronized entry");
+                            continue;
+                        }
+                    };
+                    let is_changed = match (since, sync_entries.entries.get(uuid)) {
+                        (Some(since), Some(change_sequence)) => *change_sequence > since,
+                        _ => true,
+                    };
+                    let state = if is_changed {
+                        SyncStateValue::Add
+                    } else {
+                        entry.attributes.clear();
+                        SyncStateValue::Present
+                    };
+                    responses.push((
+                        LdapOp::SearchResultEntry(entry),
+                        vec![LdapControl::SyncState {
+                            state,
+                            entry_uuid: *entry_uuid,
+                            cookie: None,
+                        }],
+                    ));
+                }
+                LdapOp::SearchResultDone(result) if result.code == LdapResultCode::Success => {
+                    responses.push((
+                        LdapOp::SearchResultDone(result),
+                        vec![LdapControl::SyncDone {
+                            cookie: Some(make_cookie(sync_entries.change_sequence)),
+                            refresh_deletes: false,
+                        }],
+                    ));
+                }
+                result => responses.push((result, Vec::new())),
+            }
         }
+        Ok(responses)
     }
 
-    fn make_user_search_request<S: Into<String>>(
-        filter: LdapFilter,
-        attrs: Vec<S>,
-    ) -> LdapSearchRequest {
-        make_search_request::<S>("ou=people,Dc=example,dc=com", filter, attrs)
+    pub async fn handle_ldap_message(&mut self, ldap_op: LdapOp) -> Option<Vec<LdapOp>> {
+        Some(match ldap_op {
+            LdapOp::BindRequest(request) => {
+                let (code, message) = self.do_bind(&request).await;
+                vec![LdapOp::BindResponse(LdapBindResponse {
+                    res: LdapResultOp {
+                        code,
+                        matcheddn: "".to_string(),
+                        message,
+                        referral: vec![],
+                    },
+                    saslcreds: None,
+                })]
+            }
+            LdapOp::SearchRequest(request) => self
+                .do_search_or_dse(&request)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_search_error(e.code, e.message)]),
+            LdapOp::UnbindRequest => {
+                self.user_info = None;
+                // No need to notify on unbind (per rfc4511)
+                return None;
+            }
+            // The search being abandoned, if any, is cancelled by the server loop: the request
+            // itself never gets a response (RFC 4511, section 4.11).
+            LdapOp::AbandonRequest(_) => Vec::new(),
+            LdapOp::ExtendedRequest(request) => self.do_extended_request(&request).await,
+            LdapOp::AddRequest(request) => self
+                .do_add_request(request)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_add_error(e.code, e.message)]),
+            LdapOp::ModifyRequest(request) => self
+                .do_modify_request(request)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_modify_response(e.code, e.message)]),
+            LdapOp::DelRequest(dn) => self
+                .do_delete_request(&dn)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_delete_response(e.code, e.message)]),
+            LdapOp::CompareRequest(request) => self
+                .do_compare(request)
+                .await
+                .unwrap_or_else(|e: LdapError| vec![make_compare_response(e.code, e.message)]),
+            op => vec![make_extended_response(
+                LdapResultCode::UnwillingToPerform,
+                format!("Unsupported operation: {:#?}", op),
+            )],
+        })
     }
 
-    async fn setup_bound_handler_with_group(
-        mut mock: MockTestBackendHandler,
-        group: &str,
-    ) -> LdapHandler<MockTestBackendHandler> {
-        mock.expect_bind()
-            .with(eq(BindRequest {
-                name: UserId::new("test"),
-                password: "pass".to_string(),
-            }))
-            .return_once(|_| Ok(()));
-        let group = group.to_string();
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("test")))
-            .return_once(|_| {
-                let mut set = HashSet::new();
-                set.insert(GroupDetails {
-                    group_id: GroupId(42),
-                    display_name: group,
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
-                });
-                Ok(set)
+    /// Logs the search at the info level if all the searches are logged, or at the warning level
+    /// if it was slow.
+    fn log_search(
+        &self,
+        request: &LdapSearchRequest,
+        duration: Duration,
+        responses: &[(LdapOp, Vec<LdapControl>)],
+    ) {
+        let is_slow = self
+            .slow_search_threshold
+            .map(|threshold| duration >= threshold)
+            .unwrap_or(false);
+        if !is_slow && !self.log_searches {
+            return;
+        }
+        let entries = responses
+            .iter()
+            .filter(|(op, _)| matches!(op, LdapOp::SearchResultEntry(_)))
+            .count();
+        let code = responses.iter().find_map(|(op, _)| match op {
+            LdapOp::SearchResultDone(result) => Some(&result.code),
+            _ => None,
+        });
+        let user = self
+            .user_info
+            .as_ref()
+            .map(|u| u.user.to_string())
+            .unwrap_or_else(|| "anonymous".to_string());
+        let message = format!(
+            "LDAP search by {} from {}: base \"{}\", scope {:?}, filter {}, attributes {:?}: \
+             {} entries, {:?} in {} ms",
+            user,
+            self.peer_address
+                .map(|address| address.to_string())
+                .unwrap_or_else(|| "an unknown address".to_string()),
+            request.base,
+            request.scope,
+            format_filter(&request.filter),
+            request.attrs,
+            entries,
+            code,
+            duration.as_millis()
+        );
+        if is_slow {
+            warn!("Slow {}", message);
+        } else {
+            info!("{}", message);
+        }
+    }
+
+    /// Like [`Self::handle_ldap_message`], with the controls of the request. Each response comes
+    /// with its own controls.
+    pub async fn handle_ldap_message_with_controls(
+        &mut self,
+        ldap_op: LdapOp,
+        controls: &[LdapControl],
+    ) -> Option<Vec<(LdapOp, Vec<LdapControl>)>> {
+        let search = match &ldap_op {
+            LdapOp::SearchRequest(request)
+                if self.log_searches || self.slow_search_threshold.is_some() =>
+            {
+                Some(request.clone())
+            }
+            _ => None,
+        };
+        let start = Instant::now();
+        let responses = self.dispatch_ldap_message(ldap_op, controls).await;
+        if let Some(request) = search {
+            self.log_search(
+                &request,
+                start.elapsed(),
+                responses.as_deref().unwrap_or_default(),
+            );
+        }
+        responses
+    }
+
+    /// Like [`Self::handle_ldap_message_with_controls`], with the parts of the request that
+    /// `ldap3_proto` couldn't decode. The responses can also have such parts.
+    pub async fn handle_ldap_request(
+        &mut self,
+        ldap_op: LdapOp,
+        controls: &[LdapControl],
+        extras: &RequestExtras,
+    ) -> Option<Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)>> {
+        if let Some(control) = extras
+            .controls
+            .iter()
+            .find(|c| c.criticality && !SUPPORTED_CONTROLS.contains(&c.oid.as_str()))
+        {
+            debug!(?control.oid, "Unsupported critical control");
+            let response = make_error_response(
+                &ldap_op,
+                LdapResultCode::UnwillingToPerform,
+                format!("Unsupported critical control: {}", control.oid),
+            )?;
+            return Some(vec![(
+                response,
+                Vec::new(),
+                ResponseExtras {
+                    controls: Vec::new(),
+                    result_code: Some(UNAVAILABLE_CRITICAL_EXTENSION),
+                },
+            )]);
+        }
+        if let LdapOp::ExtendedRequest(request) = &ldap_op {
+            if request.name == CANCEL_OID {
+                return Some(vec![Self::do_cancel(request)]);
+            }
+            let response = if request.name == START_TRANSACTION_OID {
+                Some(self.do_start_transaction())
+            } else if request.name == END_TRANSACTION_OID {
+                Some(self.do_end_transaction(request).await)
+            } else {
+                None
+            };
+            if let Some(response) = response {
+                return Some(vec![(response, Vec::new(), ResponseExtras::default())]);
+            }
+        }
+        if let Some(control) = extras.find_control(TRANSACTION_SPECIFICATION_OID) {
+            if !matches!(ldap_op, LdapOp::UnbindRequest | LdapOp::AbandonRequest(_)) {
+                let response = self.add_to_transaction(ldap_op, control.value.as_deref())?;
+                return Some(vec![(response, Vec::new(), ResponseExtras::default())]);
+            }
+        }
+        if let (LdapOp::BindRequest(_), Some(sasl)) = (&ldap_op, &extras.sasl) {
+            let (response, result_code) = self.do_sasl_bind(sasl).await;
+            return Some(vec![(
+                response,
+                Vec::new(),
+                ResponseExtras {
+                    controls: Vec::new(),
+                    result_code,
+                },
+            )]);
+        }
+        if let (LdapOp::BindRequest(request), Some(_)) =
+            (&ldap_op, extras.find_control(PASSWORD_POLICY_OID))
+        {
+            let (code, message, state) = self.do_bind_with_password_policy(request).await;
+            let response = make_error_response(&ldap_op, code, message)?;
+            let controls = state
+                .map(|state| {
+                    RawControl::new(PASSWORD_POLICY_OID, make_password_policy_response(state))
+                })
+                .into_iter()
+                .collect();
+            return Some(vec![(
+                response,
+                Vec::new(),
+                ResponseExtras {
+                    controls,
+                    result_code: None,
+                },
+            )]);
+        }
+        if let LdapOp::SearchRequest(request) = &ldap_op {
+            match (
+                extras.find_control(SORT_REQUEST_OID),
+                extras.find_control(VLV_REQUEST_OID),
+            ) {
+                (Some(control), _) => {
+                    let request = request.clone();
+                    return Some(
+                        self.do_sorted_search(request, controls, extras, control)
+                            .await,
+                    );
+                }
+                (None, Some(_)) => {
+                    return Some(vec![make_vlv_error(
+                        "The virtual list view needs the server-side sorting control",
+                        VLV_SORT_CONTROL_MISSING,
+                    )])
+                }
+                (None, None) => (),
+            }
+        }
+        self.dispatch_ldap_request(ldap_op, controls, extras).await
+    }
+
+    async fn dispatch_ldap_request(
+        &mut self,
+        ldap_op: LdapOp,
+        controls: &[LdapControl],
+        extras: &RequestExtras,
+    ) -> Option<Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)>> {
+        if let (LdapOp::SearchRequest(request), Some(control)) =
+            (&ldap_op, extras.find_control(PAGED_RESULTS_OID))
+        {
+            let request = request.clone();
+            return Some(self.do_paged_search(request, controls, control).await);
+        }
+        self.handle_ldap_message_with_controls(ldap_op, controls)
+            .await
+            .map(|responses| {
+                responses
+                    .into_iter()
+                    .map(|(response, controls)| (response, controls, ResponseExtras::default()))
+                    .collect()
+            })
+    }
+
+    /// Serves a search with the server-side sorting control (RFC 2891). Only the user searches
+    /// can be sorted, and not together with the paged results: the pages follow the DNs.
+    async fn do_sorted_search(
+        &mut self,
+        request: LdapSearchRequest,
+        controls: &[LdapControl],
+        extras: &RequestExtras,
+        control: &RawControl,
+    ) -> Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)> {
+        let keys = match parse_sort_request(control.value.as_deref()) {
+            Some(keys) => keys,
+            None => {
+                return vec![(
+                    make_search_error(
+                        LdapResultCode::ProtocolError,
+                        "Invalid server-side sorting control".to_string(),
+                    ),
+                    Vec::new(),
+                    ResponseExtras::default(),
+                )]
+            }
+        };
+        let is_user_search = parse_distinguished_name(&request.base.to_ascii_lowercase())
+            .map(|dn_parts| {
+                matches!(
+                    get_search_scope(&self.ldap_info.base_dn, &dn_parts),
+                    SearchScope::Users | SearchScope::User(_)
+                )
+            })
+            .unwrap_or(false);
+        let sort_keys = if !is_user_search || extras.find_control(PAGED_RESULTS_OID).is_some() {
+            Err((SORT_UNWILLING_TO_PERFORM, None))
+        } else {
+            get_user_sort_keys(&keys).map_err(|(code, attribute)| (code, Some(attribute)))
+        };
+        let sort_response = match &sort_keys {
+            Ok(_) => make_sort_response(SORT_SUCCESS, None),
+            Err((code, attribute)) => make_sort_response(*code, attribute.as_deref()),
+        };
+        let sort_response = RawControl::new(SORT_RESPONSE_OID, sort_response);
+        let mut responses = match sort_keys {
+            Ok(sort_keys) if extras.find_control(VLV_REQUEST_OID).is_some() => {
+                let control = extras.find_control(VLV_REQUEST_OID).unwrap();
+                self.do_vlv_search(&request, sort_keys, control).await
+            }
+            Err(_) if extras.find_control(VLV_REQUEST_OID).is_some() => {
+                // Unlike the sorting, the view can't be skipped.
+                let mut response = make_vlv_error(
+                    "The results of this search can't be sorted",
+                    VLV_UNWILLING_TO_PERFORM,
+                );
+                response.2.controls.push(sort_response);
+                return vec![response];
+            }
+            Ok(sort_keys) => {
+                debug!(?sort_keys, "Sorted search");
+                self.user_sort_keys = sort_keys;
+                let responses = self
+                    .dispatch_ldap_request(LdapOp::SearchRequest(request), controls, extras)
+                    .await;
+                self.user_sort_keys.clear();
+                responses.unwrap_or_default()
+            }
+            Err(_) if control.criticality => {
+                return vec![(
+                    make_search_error(
+                        LdapResultCode::UnwillingToPerform,
+                        "The results of this search can't be sorted".to_string(),
+                    ),
+                    Vec::new(),
+                    ResponseExtras {
+                        controls: vec![sort_response],
+                        result_code: Some(UNAVAILABLE_CRITICAL_EXTENSION),
+                    },
+                )]
+            }
+            // Not critical: the results are returned unsorted.
+            Err(_) => self
+                .dispatch_ldap_request(LdapOp::SearchRequest(request), controls, extras)
+                .await
+                .unwrap_or_default(),
+        };
+        if let Some((LdapOp::SearchResultDone(_), _, extras)) = responses.last_mut() {
+            extras.controls.push(sort_response);
+        }
+        responses
+    }
+
+    /// Serves a window of a sorted user search, with the virtual list view control. Only the
+    /// admins and the read-only users can browse the directory this way.
+    async fn do_vlv_search(
+        &mut self,
+        request: &LdapSearchRequest,
+        mut sort_keys: Vec<SortKey<UserColumn>>,
+        control: &RawControl,
+    ) -> Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)> {
+        let vlv_request = match parse_vlv_request(control.value.as_deref()) {
+            Some(vlv_request) => vlv_request,
+            None => {
+                return vec![(
+                    make_search_error(
+                        LdapResultCode::ProtocolError,
+                        "Invalid virtual list view control".to_string(),
+                    ),
+                    Vec::new(),
+                    ResponseExtras::default(),
+                )]
+            }
+        };
+        let is_allowed = self.bound_user_attributes.is_none()
+            && self
+                .user_info
+                .as_ref()
+                .map(ValidationResults::is_admin_or_readonly)
+                .unwrap_or(false);
+        if !is_allowed {
+            let message = "Only the admins and read-only users can use the virtual list view";
+            let mut response = make_vlv_error(message, VLV_INSUFFICIENT_ACCESS_RIGHTS);
+            response.0 =
+                make_search_error(LdapResultCode::InsufficentAccessRights, message.to_string());
+            response.2.result_code = None;
+            return vec![response];
+        }
+        let (offset, content_count) = match vlv_request.target {
+            VlvTarget::ByOffset {
+                offset,
+                content_count,
+            } => (offset, content_count),
+            VlvTarget::GreaterThanOrEqual(_) => {
+                return vec![make_vlv_error(
+                    "The virtual list view can only be positioned by offset",
+                    VLV_UNWILLING_TO_PERFORM,
+                )]
+            }
+        };
+        // The pages are sorted in the database, by a single column.
+        let sort_key = match (sort_keys.pop(), sort_keys.is_empty()) {
+            (Some(sort_key), true) => sort_key,
+            _ => {
+                return vec![make_vlv_error(
+                    "The virtual list view can only be sorted by one attribute",
+                    VLV_UNWILLING_TO_PERFORM,
+                )]
+            }
+        };
+        let filter = match parse_distinguished_name(&request.base.to_ascii_lowercase())
+            .map(|dn_parts| get_search_scope(&self.ldap_info.base_dn, &dn_parts))
+        {
+            Ok(SearchScope::User(filter)) => LdapFilter::And(vec![request.filter.clone(), filter]),
+            _ => request.filter.clone(),
+        };
+        let make_page = |offset, limit| PageRequest {
+            order_by: sort_key.column,
+            descending: sort_key.descending,
+            offset,
+            limit,
+        };
+        let search_error = |e: LdapError| {
+            vec![(
+                make_search_error(e.code, e.message),
+                Vec::new(),
+                ResponseExtras::default(),
+            )]
+        };
+        let total = match get_user_page(
+            &self.ldap_info,
+            &filter,
+            &request.attrs,
+            &request.base,
+            &None,
+            make_page(0, 0),
+            &mut self.backend_handler,
+        )
+        .await
+        {
+            Ok((_, total)) => total,
+            Err(e) => return search_error(e),
+        };
+        let window = match get_vlv_window(
+            offset,
+            content_count,
+            vlv_request.before_count,
+            vlv_request.after_count,
+            total,
+        ) {
+            Ok(window) => window,
+            Err(code) => return vec![make_vlv_error("Invalid virtual list view offset", code)],
+        };
+        let entries = match get_user_page(
+            &self.ldap_info,
+            &filter,
+            &request.attrs,
+            &request.base,
+            &None,
+            make_page(window.offset, window.limit),
+            &mut self.backend_handler,
+        )
+        .await
+        {
+            Ok((entries, _)) => entries,
+            Err(e) => return search_error(e),
+        };
+        debug!(?window, total, "Virtual list view");
+        entries
+            .into_iter()
+            .map(|entry| (entry, Vec::new(), ResponseExtras::default()))
+            .chain(std::iter::once((
+                make_search_success(),
+                Vec::new(),
+                ResponseExtras::with_control(RawControl::new(
+                    VLV_RESPONSE_OID,
+                    make_vlv_response(window.target_position, total, VLV_SUCCESS),
+                )),
+            )))
+            .collect()
+    }
+
+    /// Serves one page of a search with the paged results control (RFC 2696).
+    async fn do_paged_search(
+        &mut self,
+        request: LdapSearchRequest,
+        controls: &[LdapControl],
+        control: &RawControl,
+    ) -> Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)> {
+        let make_done = |response: LdapOp, total: usize, cookie: Vec<u8>| {
+            (
+                response,
+                Vec::new(),
+                ResponseExtras::with_control(RawControl::new(
+                    PAGED_RESULTS_OID,
+                    make_paged_results_response(total, cookie),
+                )),
+            )
+        };
+        let paged_request = match parse_paged_results_request(control.value.as_deref()) {
+            Some(paged_request) => paged_request,
+            None => {
+                return vec![(
+                    make_search_error(
+                        LdapResultCode::ProtocolError,
+                        "Invalid paged results control".to_string(),
+                    ),
+                    Vec::new(),
+                    ResponseExtras::default(),
+                )]
+            }
+        };
+        let last_key = if paged_request.cookie.is_empty() {
+            None
+        } else {
+            match parse_page_cookie(&request, &paged_request.cookie) {
+                Some(last_key) => Some(last_key),
+                None => {
+                    return vec![make_done(
+                        make_search_error(
+                            LdapResultCode::UnwillingToPerform,
+                            "Invalid paged results cookie".to_string(),
+                        ),
+                        0,
+                        Vec::new(),
+                    )]
+                }
+            }
+        };
+        // A size of zero abandons the search: there's nothing to free on our side.
+        if paged_request.size == 0 {
+            return vec![make_done(make_search_success(), 0, Vec::new())];
+        }
+        let responses = self
+            .handle_ldap_message_with_controls(LdapOp::SearchRequest(request.clone()), controls)
+            .await
+            .unwrap_or_default();
+        let (entries, mut others): (Vec<_>, Vec<_>) = responses
+            .into_iter()
+            .partition(|(op, _)| matches!(op, LdapOp::SearchResultEntry(_)));
+        let done = match others.pop() {
+            Some((done @ LdapOp::SearchResultDone(_), _)) => done,
+            done => {
+                // An error: no page to cut.
+                return entries
+                    .into_iter()
+                    .chain(others)
+                    .chain(done)
+                    .map(|(op, controls)| (op, controls, ResponseExtras::default()))
+                    .collect();
+            }
+        };
+        let total = entries.len();
+        let mut entries = entries
+            .into_iter()
+            .map(|entry| match &entry.0 {
+                LdapOp::SearchResultEntry(e) => (get_page_key(&e.dn), entry),
+                _ => unreachable!(),
+            })
+            .filter(|(key, _)| last_key.as_ref().map(|last| key > last).unwrap_or(true))
+            .collect::<Vec<_>>();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let remaining = entries.len();
+        entries.truncate(paged_request.size);
+        let cookie = match entries.last() {
+            Some((key, _)) if remaining > entries.len() => make_page_cookie(&request, key),
+            _ => Vec::new(),
+        };
+        debug!(?last_key, remaining, total, "Paged search");
+        entries
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .chain(others)
+            .map(|(op, controls)| (op, controls, ResponseExtras::default()))
+            .chain(std::iter::once(make_done(done, total, cookie)))
+            .collect()
+    }
+
+    async fn dispatch_ldap_message(
+        &mut self,
+        ldap_op: LdapOp,
+        controls: &[LdapControl],
+    ) -> Option<Vec<(LdapOp, Vec<LdapControl>)>> {
+        if let LdapOp::SearchRequest(request) = &ldap_op {
+            let sync_request = controls.iter().find_map(|control| match control {
+                LdapControl::SyncRequest { mode, cookie, .. } => Some((mode, cookie.as_deref())),
+                _ => None,
+            });
+            if let Some((mode, cookie)) = sync_request {
+                return Some(
+                    self.do_sync_search(request, mode, cookie)
+                        .await
+                        .unwrap_or_else(|e: LdapError| {
+                            vec![(make_search_error(e.code, e.message), Vec::new())]
+                        }),
+                );
+            }
+        }
+        self.handle_ldap_message(ldap_op).await.map(|responses| {
+            responses
+                .into_iter()
+                .map(|response| (response, Vec::new()))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            error::Result,
+            handler::*,
+            ldap::{
+                ber::{Element, TagClass},
+                utils::{parse_generalized_time, to_generalized_time},
+            },
+            opaque_handler::*,
+            types::*,
+        },
+        infra::sasl_gssapi::tests::make_fake_context,
+        uuid,
+    };
+    use async_trait::async_trait;
+    use chrono::TimeZone;
+    use mockall::predicate::eq;
+    use std::collections::HashSet;
+    use tokio;
+
+    mockall::mock! {
+        pub TestBackendHandler{}
+        impl Clone for TestBackendHandler {
+            fn clone(&self) -> Self;
+        }
+        #[async_trait]
+        impl LoginHandler for TestBackendHandler {
+            async fn bind(&self, request: BindRequest) -> Result<()>;
+        }
+        #[async_trait]
+        impl GroupBackendHandler for TestBackendHandler {
+            async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+            async fn list_groups_page(&self, filters: Option<GroupRequestFilter>, page: PageRequest<GroupColumn>) -> Result<Page<GroupDetails>>;
+            async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
+            async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
+            async fn create_group(&self, group_name: &str) -> Result<GroupId>;
+            async fn create_dynamic_group(&self, group_name: &str, filter: &str) -> Result<GroupId>;
+            async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+            async fn delete_groups(&self, group_ids: &[GroupId]) -> Result<()>;
+            async fn add_group_to_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()>;
+            async fn remove_group_from_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()>;
+            async fn list_group_nestings(&self) -> Result<Vec<GroupNesting>>;
+            async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+            async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+            async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+            async fn create_membership_request(&self, group_id: GroupId, user_id: &UserId, requested_by: &UserId) -> Result<MembershipRequest>;
+            async fn list_membership_requests(&self, group_id: Option<GroupId>) -> Result<Vec<MembershipRequest>>;
+            async fn get_membership_request(&self, request_id: i32) -> Result<MembershipRequest>;
+            async fn approve_membership_request(&self, request_id: i32) -> Result<()>;
+            async fn delete_membership_request(&self, request_id: i32) -> Result<()>;
+            async fn get_group_attributes(&self, group_id: GroupId) -> Result<Vec<AttributeValue>>;
+            async fn list_group_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+            async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+            async fn delete_group_attribute(&self, name: &str) -> Result<()>;
+        }
+        #[async_trait]
+        impl UserBackendHandler for TestBackendHandler {
+            async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
+            async fn list_users_sorted(&self, filters: Option<UserRequestFilter>, get_groups: bool, sort_keys: Vec<SortKey<UserColumn>>) -> Result<Vec<UserAndGroups>>;
+            async fn list_users_page(&self, filters: Option<UserRequestFilter>, page: PageRequest<UserColumn>) -> Result<Page<User>>;
+            async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
+            async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+            async fn check_emails_available(&self, user_id: &UserId, email: &str, email_aliases: &[String]) -> Result<()>;
+            async fn create_users(&self, requests: Vec<CreateUserRequest>, best_effort: bool) -> Result<Vec<Result<()>>>;
+            async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
+            async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+            async fn delete_users(&self, user_ids: &[UserId], best_effort: bool) -> Result<Vec<Result<()>>>;
+            async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
+            async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+            async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId, best_effort: bool) -> Result<Vec<Result<()>>>;
+            async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+            async fn set_group_members(&self, group_id: GroupId, user_ids: &[UserId]) -> Result<()>;
+            async fn create_group_with_members(&self, group_name: &str, user_ids: &[UserId]) -> Result<GroupId>;
+            async fn merge_users(&self, request: MergeUsersRequest) -> Result<UserMergeReport>;
+            async fn list_user_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+            async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+            async fn delete_user_attribute(&self, name: &str) -> Result<()>;
+        }
+        #[async_trait]
+        impl BackendHandler for TestBackendHandler {
+            async fn get_stats(&self) -> Result<BackendStats>;
+            async fn repair_memberships(&self) -> Result<MembershipRepairReport>;
+            async fn optimize_database(&self) -> Result<DatabaseOptimizationReport>;
+            async fn create_magic_login_token(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+            async fn consume_magic_login_token(&self, token: &str) -> Result<UserId>;
+            async fn create_password_reset_token(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+            async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<String>;
+            async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<Vec<String>>;
+            async fn is_totp_enabled(&self, user_id: &UserId) -> Result<bool>;
+            async fn check_totp_code(&self, user_id: &UserId, code: &str) -> Result<bool>;
+            async fn disable_totp(&self, user_id: &UserId) -> Result<()>;
+            async fn unlock_user(&self, user_id: &UserId) -> Result<()>;
+            async fn use_grace_login(&self, user_id: &UserId, max_grace_logins: u32) -> Result<Option<u32>>;
+            async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()>;
+            async fn create_temporary_password(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+            async fn consume_temporary_password(&self, user_id: &UserId, password: &str) -> Result<()>;
+            async fn set_account_validity(&self, user_id: &UserId, activation_date: Option<DateTime>, expiration_date: Option<DateTime>) -> Result<()>;
+            async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()>;
+            async fn add_webauthn_credential(&self, credential: WebauthnCredential) -> Result<()>;
+            async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+            async fn update_webauthn_credential(&self, credential_id: &str, credential: String) -> Result<()>;
+            async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str) -> Result<()>;
+            async fn get_sso_user(&self, issuer: &str, subject: &str) -> Result<Option<UserId>>;
+            async fn link_sso_user(&self, issuer: &str, subject: &str, user_id: &UserId) -> Result<()>;
+            async fn record_sync_entries(&self, digests: Vec<(Uuid, String)>) -> Result<SyncEntries>;
+            async fn create_api_token(&self, user_id: &UserId, name: &str, scopes: &[ApiTokenScope]) -> Result<(ApiToken, String)>;
+            async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+            async fn get_api_token(&self, secret: &str) -> Result<Option<ApiToken>>;
+            async fn delete_api_token(&self, token_id: &str) -> Result<()>;
+            async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+            async fn delete_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>>;
+            async fn delete_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>>;
+            async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+            async fn list_audit_logs(&self, filter: AuditLogFilter, offset: u64, limit: u64) -> Result<Page<AuditLogEntry>>;
+            async fn create_webhook(&self, url: &str, events: &[WebhookEvent]) -> Result<Webhook>;
+            async fn list_webhooks(&self) -> Result<Vec<Webhook>>;
+            async fn delete_webhook(&self, webhook_id: i32) -> Result<()>;
+            async fn begin_transaction(&self) -> Result<Self>;
+            async fn end_transaction(self, commit: bool) -> Result<()>;
+        }
+        #[async_trait]
+        impl OpaqueHandler for TestBackendHandler {
+            async fn login_start(
+                &self,
+                request: login::ClientLoginStartRequest
+            ) -> Result<login::ServerLoginStartResponse>;
+            async fn login_finish(&self, request: login::ClientLoginFinishRequest) -> Result<UserId>;
+            async fn registration_start(
+                &self,
+                request: registration::ClientRegistrationStartRequest
+            ) -> Result<registration::ServerRegistrationStartResponse>;
+            async fn registration_finish(
+                &self,
+                request: registration::ClientRegistrationFinishRequest
+            ) -> Result<()>;
+        }
+    }
+
+    /// A mock without custom attributes, which the user searches always list.
+    fn mock_backend_handler() -> MockTestBackendHandler {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_user_attribute_schema()
+            .returning(|| Ok(Vec::new()));
+        mock.expect_list_group_attribute_schema()
+            .returning(|| Ok(Vec::new()));
+        mock.expect_record_audit_event().returning(|_| Ok(()));
+        mock
+    }
+
+    fn make_search_request<S: Into<String>>(
+        base: &str,
+        filter: LdapFilter,
+        attrs: Vec<S>,
+    ) -> LdapSearchRequest {
+        LdapSearchRequest {
+            base: base.to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter,
+            attrs: attrs.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    fn make_user_search_request<S: Into<String>>(
+        filter: LdapFilter,
+        attrs: Vec<S>,
+    ) -> LdapSearchRequest {
+        make_search_request::<S>("ou=people,Dc=example,dc=com", filter, attrs)
+    }
+
+    async fn setup_bound_handler_with_group(
+        mut mock: MockTestBackendHandler,
+        group: &str,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .return_once(|_| Ok(()));
+        mock.expect_record_audit_event().returning(|_| Ok(()));
+        let group = group.to_string();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
+                let mut set = HashSet::new();
+                set.insert(GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: group,
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                });
+                Ok(set)
+            });
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=Example,dc=com".to_string(), vec![], vec![]);
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=coM".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        ldap_handler
+    }
+
+    async fn setup_bound_readonly_handler(
+        mock: MockTestBackendHandler,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        setup_bound_handler_with_group(mock, "lldap_strict_readonly").await
+    }
+
+    async fn setup_bound_password_manager_handler(
+        mock: MockTestBackendHandler,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        setup_bound_handler_with_group(mock, "lldap_password_manager").await
+    }
+
+    async fn setup_bound_admin_handler(
+        mock: MockTestBackendHandler,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        setup_bound_handler_with_group(mock, "lldap_admin").await
+    }
+
+    #[tokio::test]
+    async fn test_bind() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=eXample,dc=com".to_string(), vec![], vec![]);
+
+        let request = LdapOp::BindRequest(LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![LdapOp::BindResponse(LdapBindResponse {
+                res: LdapResultOp {
+                    code: LdapResultCode::Success,
+                    matcheddn: "".to_string(),
+                    message: "".to_string(),
+                    referral: vec![],
+                },
+                saslcreds: None,
+            })]),
+        );
+    }
+
+    async fn bind_with_expired_password(
+        grace_logins_left: Option<u32>,
+    ) -> Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)> {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind().times(1).return_once(|_| Ok(()));
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    password_modified_date: Some(chrono::Utc::now() - chrono::Duration::days(100)),
+                    ..Default::default()
+                })
+            });
+        mock.expect_use_grace_login()
+            .with(eq(UserId::new("bob")), eq(3))
+            .times(1)
+            .return_once(move |_, _| Ok(grace_logins_left));
+        mock.expect_get_user_groups()
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        ldap_handler.set_password_expiry(Some(PasswordExpiry {
+            max_age_days: 90,
+            warning_days: 14,
+            grace_logins: Some(3),
+        }));
+        let extras = RequestExtras {
+            controls: vec![RawControl {
+                oid: PASSWORD_POLICY_OID.to_string(),
+                criticality: false,
+                value: None,
+            }],
+            sasl: None,
+        };
+        let request = LdapOp::BindRequest(LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        });
+        ldap_handler
+            .handle_ldap_request(request, &[], &extras)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bind_with_grace_login() {
+        let make_response = |code, message: &str, state| {
+            vec![(
+                LdapOp::BindResponse(LdapBindResponse {
+                    res: LdapResultOp {
+                        code,
+                        matcheddn: "".to_string(),
+                        message: message.to_string(),
+                        referral: vec![],
+                    },
+                    saslcreds: None,
+                }),
+                vec![],
+                ResponseExtras::with_control(RawControl::new(
+                    PASSWORD_POLICY_OID,
+                    make_password_policy_response(state),
+                )),
+            )]
+        };
+        assert_eq!(
+            bind_with_expired_password(Some(2)).await,
+            make_response(
+                LdapResultCode::Success,
+                "",
+                PasswordPolicyState::GraceLogins(2)
+            )
+        );
+        assert_eq!(
+            bind_with_expired_password(None).await,
+            make_response(
+                LdapResultCode::InvalidCredentials,
+                "The password expired",
+                PasswordPolicyState::Expired
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_bind() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("test"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("test")))
+            .return_once(|_| {
+                let mut set = HashSet::new();
+                set.insert(GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: "lldap_admin".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                });
+                Ok(set)
+            });
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_empty_password() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind().never();
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+
+        let request = LdapBindRequest {
+            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::InvalidCredentials
+        );
+        // The bind failed, so the session is still not authenticated.
+        assert_eq!(ldap_handler.user_info, None);
+
+        ldap_handler.set_empty_password_bind_unwilling_to_perform(true);
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::UnwillingToPerform
+        );
+        assert_eq!(ldap_handler.user_info, None);
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_bind() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind().never();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "bob@bob.bob".to_string(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@bob.bob".to_string(),
+                        display_name: Some("Bob".to_string()),
+                        uid_number: Some(1000),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        let request = LdapBindRequest {
+            dn: "".to_string(),
+            cred: LdapBindCred::Simple("".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation
+        );
+        ldap_handler
+            .set_anonymous_bind_attributes(Some(vec!["cn".to_string(), "mail".to_string()]));
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+        assert_eq!(ldap_handler.user_info, None);
+
+        let request = make_user_search_request(
+            LdapFilter::Equality("mail".to_string(), "bob@bob.bob".to_string()),
+            vec!["*"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"bob@bob.bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"Bob".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+        // The other attributes can't be guessed through the filter either.
+        let request = make_user_search_request(
+            LdapFilter::Equality("uidNumber".to_string(), "1000".to_string()),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Anonymous searches can only filter on the readable attributes"
+                    .to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_service_account_search() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("wifi")))
+            .return_once(|_| {
+                Ok(HashSet::from([GroupDetails {
+                    group_id: GroupId(42),
+                    display_name: "wifi_controllers".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                }]))
+            });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::UserId(UserId::new("bob")))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@bob.bob".to_string(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        ldap_handler.set_service_account_groups(vec![LdapServiceAccountGroup {
+            group: "wifi_controllers".to_string(),
+            attributes: vec!["uid".to_string()],
+        }]);
+        ldap_handler.set_bound_user(UserId::new("wifi")).await;
+        assert_eq!(
+            ldap_handler.user_info.as_ref().unwrap().permission,
+            Permission::Readonly
+        );
+
+        // Other users can be read, but only the listed attributes.
+        let request = make_user_search_request(
+            LdapFilter::Equality("uid".to_string(), "bob".to_string()),
+            vec!["uid", "mail"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "uid".to_string(),
+                        vals: vec![b"bob".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ])
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality("mail".to_string(), "bob@bob.bob".to_string()),
+            vec!["uid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::InsufficentAccessRights,
+                message: "Service accounts can only filter on the readable attributes".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_requires_tls() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        ldap_handler.set_bind_requires_tls(true);
+
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::ConfidentialityRequired
+        );
+        assert_eq!(ldap_handler.user_info, None);
+
+        // After StartTLS.
+        ldap_handler.set_bind_requires_tls(false);
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::Success
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_throttled() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(crate::domain::handler::BindRequest {
+                name: UserId::new("bob"),
+                password: "wrong".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::AuthenticationError(
+                    "wrong".to_string(),
+                ))
+            });
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        ldap_handler.set_bind_limiter(Some(Arc::new(BindLimiter::new(
+            crate::infra::configuration::LdapBindRateLimitOptionsBuilder::default()
+                .enabled(true)
+                .free_attempts(0)
+                .initial_backoff_seconds(60)
+                .build()
+                .unwrap(),
+        ))));
+        ldap_handler.set_peer_address(Some("192.0.2.1".parse().unwrap()));
+
+        let make_request = |password: &str| LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple(password.to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&make_request("wrong")).await.0,
+            LdapResultCode::InvalidCredentials
+        );
+        // The password isn't even checked during the backoff.
+        assert_eq!(
+            ldap_handler.do_bind(&make_request("pass")).await,
+            (
+                LdapResultCode::UnwillingToPerform,
+                "Too many failed binds, try again in 60 seconds".to_string()
+            )
+        );
+        assert_eq!(ldap_handler.user_info, None);
+    }
+
+    #[tokio::test]
+    async fn test_bind_failure_audit_event() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_bind().times(1).return_once(|_| {
+            Err(crate::domain::error::DomainError::AuthenticationError(
+                "wrong".to_string(),
+            ))
+        });
+        mock.expect_record_audit_event()
+            .withf(|event| {
+                event.actor == Some(UserId::new("bob"))
+                    && event.action == "ldapBind"
+                    && event.ip_address.as_deref() == Some("192.0.2.1")
+                    && !event.success
+            })
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        ldap_handler.set_peer_address(Some("192.0.2.1".parse().unwrap()));
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("wrong".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::InvalidCredentials
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_with_client_certificate() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind().never();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+
+        assert!(
+            ldap_handler
+                .bind_with_client_certificate(UserId::new("bob"))
+                .await
+        );
+        assert_eq!(
+            ldap_handler.user_info,
+            Some(ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+            })
+        );
+    }
+
+    fn make_sasl_bind(mechanism: &str, credentials: Option<&str>) -> (LdapOp, RequestExtras) {
+        let request = LdapOp::BindRequest(LdapBindRequest {
+            dn: "".to_string(),
+            cred: LdapBindCred::Simple("".to_string()),
+        });
+        let extras = RequestExtras {
+            controls: vec![],
+            sasl: Some(SaslCredentials {
+                mechanism: mechanism.to_string(),
+                credentials: credentials.map(|c| c.as_bytes().to_vec()),
+            }),
+        };
+        (request, extras)
+    }
+
+    fn make_sasl_bind_response(
+        code: LdapResultCode,
+        message: &str,
+        result_code: Option<i64>,
+    ) -> Option<Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)>> {
+        Some(vec![(
+            LdapOp::BindResponse(LdapBindResponse {
+                res: LdapResultOp {
+                    code,
+                    matcheddn: "".to_string(),
+                    message: message.to_string(),
+                    referral: vec![],
+                },
+                saslcreds: None,
+            }),
+            vec![],
+            ResponseExtras {
+                controls: vec![],
+                result_code,
+            },
+        )])
+    }
+
+    #[tokio::test]
+    async fn test_sasl_external_bind() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .times(2)
+            .returning(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .times(2)
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        assert!(
+            ldap_handler
+                .bind_with_client_certificate(UserId::new("bob"))
+                .await
+        );
+        ldap_handler
+            .handle_ldap_message(LdapOp::UnbindRequest)
+            .await;
+        assert_eq!(ldap_handler.user_info, None);
+
+        let (request, extras) = make_sasl_bind("EXTERNAL", Some("u:alice"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(
+                LdapResultCode::InvalidCredentials,
+                "The authorization identity has to be the one of the client certificate",
+                None
+            )
+        );
+        let (request, extras) =
+            make_sasl_bind("EXTERNAL", Some("dn:uid=bob,ou=people,dc=example,dc=com"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(LdapResultCode::Success, "", None)
+        );
+        assert_eq!(
+            ldap_handler.user_info,
+            Some(ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sasl_external_bind_without_certificate() {
+        let mut ldap_handler = LdapHandler::new(
+            mock_backend_handler(),
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+        );
+        let (request, extras) = make_sasl_bind("EXTERNAL", None);
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(
+                LdapResultCode::InvalidCredentials,
+                "No TLS client certificate to bind with",
+                None
+            )
+        );
+        assert_eq!(ldap_handler.user_info, None);
+    }
+
+    #[tokio::test]
+    async fn test_sasl_bind_unsupported_mechanism() {
+        let mut ldap_handler = LdapHandler::new(
+            mock_backend_handler(),
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+        );
+        let (request, extras) = make_sasl_bind("DIGEST-MD5", None);
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(
+                LdapResultCode::UnwillingToPerform,
+                "Unsupported SASL mechanism: DIGEST-MD5",
+                Some(AUTH_METHOD_NOT_SUPPORTED)
+            )
+        );
+        // Without Kerberos.
+        let (request, extras) = make_sasl_bind("GSSAPI", Some("first"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(
+                LdapResultCode::UnwillingToPerform,
+                "Unsupported SASL mechanism: GSSAPI",
+                Some(AUTH_METHOD_NOT_SUPPORTED)
+            )
+        );
+    }
+
+    fn make_gssapi_handler(
+        mock: MockTestBackendHandler,
+        principal: &'static str,
+    ) -> LdapHandler<MockTestBackendHandler> {
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+        ldap_handler.set_kerberos_options(Some(KerberosOptions {
+            realm: "EXAMPLE.COM".to_string(),
+            new_context: Arc::new(move || -> anyhow::Result<_> {
+                Ok(make_fake_context(principal))
+            }),
+        }));
+        ldap_handler
+    }
+
+    fn make_sasl_bind_in_progress(
+        token: &[u8],
+    ) -> Option<Vec<(LdapOp, Vec<LdapControl>, ResponseExtras)>> {
+        let mut response =
+            make_sasl_bind_response(LdapResultCode::Success, "", Some(SASL_BIND_IN_PROGRESS));
+        if let Some(LdapOp::BindResponse(response)) =
+            response.as_mut().map(|responses| &mut responses[0].0)
+        {
+            response.saslcreds = Some(token.to_vec());
+        }
+        response
+    }
+
+    #[tokio::test]
+    async fn test_sasl_gssapi_bind() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .returning(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                })
+            });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = make_gssapi_handler(mock, "bob@EXAMPLE.COM");
+        let (request, extras) = make_sasl_bind("GSSAPI", Some("first"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_in_progress(b"challenge")
+        );
+        let (request, extras) = make_sasl_bind("GSSAPI", Some("second"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_in_progress(&[1, 0, 0, 0])
+        );
+        assert_eq!(ldap_handler.user_info, None);
+        let (request, extras) = make_sasl_bind("GSSAPI", Some("\x01\0\0\0u:bob"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(LdapResultCode::Success, "", None)
+        );
+        assert_eq!(
+            ldap_handler.user_info,
+            Some(ValidationResults {
+                user: UserId::new("bob"),
+                permission: Permission::Regular,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sasl_gssapi_bind_other_realm() {
+        let mut ldap_handler = make_gssapi_handler(mock_backend_handler(), "bob@OTHER.COM");
+        for token in ["first", "second"] {
+            let (request, extras) = make_sasl_bind("GSSAPI", Some(token));
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await;
+        }
+        let (request, extras) = make_sasl_bind("GSSAPI", Some("\x01\0\0\0"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(
+                LdapResultCode::InvalidCredentials,
+                "No user for the Kerberos principal bob@OTHER.COM",
+                None
+            )
+        );
+        assert_eq!(ldap_handler.user_info, None);
+    }
+
+    #[tokio::test]
+    async fn test_sasl_gssapi_bind_interrupted() {
+        let mut ldap_handler = make_gssapi_handler(mock_backend_handler(), "bob@EXAMPLE.COM");
+        let (request, extras) = make_sasl_bind("GSSAPI", Some("first"));
+        ldap_handler
+            .handle_ldap_request(request, &[], &extras)
+            .await;
+        // Another bind starts over.
+        let (request, extras) = make_sasl_bind("EXTERNAL", None);
+        ldap_handler
+            .handle_ldap_request(request, &[], &extras)
+            .await;
+        let (request, extras) = make_sasl_bind("GSSAPI", Some("second"));
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(request, &[], &extras)
+                .await,
+            make_sasl_bind_response(
+                LdapResultCode::InvalidCredentials,
+                "GSSAPI negotiation failed",
+                None
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_with_client_certificate_disabled_user() {
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    enabled: false,
+                    ..Default::default()
+                })
+            });
+        mock.expect_get_user_groups().never();
+        mock.expect_record_audit_event()
+            .withf(|event| {
+                event.action == "ldapBind"
+                    && !event.success
+                    && event.details.as_deref()
+                        == Some("The account is disabled, isn't active yet or has expired")
+            })
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+
+        assert!(
+            !ldap_handler
+                .bind_with_client_certificate(UserId::new("bob"))
+                .await
+        );
+        assert_eq!(ldap_handler.user_info, None);
+    }
+
+    #[tokio::test]
+    async fn test_search_num_subordinates() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_stats().times(2).returning(|| {
+            Ok(BackendStats {
+                user_count: 4,
+                group_count: 3,
+                archived_group_count: 1,
+                database_size: None,
+            })
+        });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let expected_entry = |dn: &str, object_class: &str, rdn: (&str, &str), count: &str| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: dn.to_string(),
+                attributes: vec![
+                    LdapPartialAttribute {
+                        atype: "objectClass".to_string(),
+                        vals: vec![b"top".to_vec(), object_class.as_bytes().to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: rdn.0.to_string(),
+                        vals: vec![rdn.1.as_bytes().to_vec()],
+                    },
+                    LdapPartialAttribute {
+                        atype: "numSubordinates".to_string(),
+                        vals: vec![count.as_bytes().to_vec()],
+                    },
+                ],
+            })
+        };
+        for (base, entry) in [
+            (
+                "dc=example,dc=com",
+                expected_entry("dc=example,dc=com", "domain", ("dc", "example"), "2"),
+            ),
+            (
+                "ou=people,dc=example,dc=com",
+                expected_entry(
+                    "ou=people,dc=example,dc=com",
+                    "organizationalUnit",
+                    ("ou", "people"),
+                    "4",
+                ),
+            ),
+            (
+                "ou=groups,dc=example,dc=com",
+                expected_entry(
+                    "ou=groups,dc=example,dc=com",
+                    "organizationalUnit",
+                    ("ou", "groups"),
+                    "2",
+                ),
+            ),
+        ] {
+            let request = make_search_request(
+                base,
+                LdapFilter::Present("objectClass".to_string()),
+                vec!["*", "numSubordinates"],
+            );
+            assert_eq!(
+                ldap_handler.do_search_or_dse(&request).await,
+                Ok(vec![entry, make_search_success()]),
+                "{}",
+                base
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_regular_user() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::UserId(UserId::new("test")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("test"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+
+        let request =
+            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success()
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_readonly_user() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request =
+            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_excludes_disabled_users() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("jim"),
+                            enabled: false,
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request =
+            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_disabled_users() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::Enabled(false))), eq(false))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("jim"),
+                        enabled: false,
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request::<String>(
+            LdapFilter::Equality("nsAccountLock".to_string(), "TRUE".to_string()),
+            vec!["1.1".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_approximate_match() {
+        let make_user = |user_id: &str, last_name: &str| UserAndGroups {
+            user: User {
+                user_id: UserId::new(user_id),
+                last_name: Some(last_name.to_string()),
+                ..Default::default()
+            },
+            groups: None,
+        };
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(eq(None), eq(false))
+            .times(1)
+            .return_once(move |_, _| {
+                Ok(vec![
+                    make_user("bob", "Smith"),
+                    make_user("john", "Doe"),
+                    make_user("jim", "Smythe"),
+                ])
+            });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                    UserRequestFilter::UserId(UserId::new("jim")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(move |_, _| {
+                Ok(vec![make_user("bob", "Smith"), make_user("jim", "Smythe")])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        // As decoded by the codec from "(sn~=smyth)".
+        let request = make_user_search_request(
+            LdapFilter::Equality("~sn".to_string(), "smyth".to_string()),
+            vec!["sn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "sn".to_string(),
+                        vals: vec![b"Smith".to_vec()]
+                    }],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "sn".to_string(),
+                        vals: vec![b"Smythe".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_approximate_match_without_phonetics() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "Bob@Example.com".to_string(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality("~mail".to_string(), "Bob@Example.com".to_string()),
+            vec!["mail"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_member_of() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(true))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: Some(vec![GroupDetails {
+                        group_id: GroupId(42),
+                        display_name: "rockstars".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                        archived: false,
+                        dynamic_filter: None,
+                        gid_number: None,
+                        email: None,
+                    }]),
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = make_user_search_request::<String>(
+            LdapFilter::And(vec![]),
+            vec!["memberOf".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "memberOf".to_string(),
+                        vals: vec![b"uid=rockstars,ou=groups,dc=example,dc=com".to_vec()]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_member_of_nested_groups() {
+        let make_group = |id, name: &str| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        };
+        // rockstars <- musicians <- artists <- humans
+        let nestings = vec![
+            GroupNesting {
+                child_group_id: GroupId(42),
+                parent_group: make_group(43, "musicians"),
+            },
+            GroupNesting {
+                child_group_id: GroupId(43),
+                parent_group: make_group(44, "artists"),
+            },
+            GroupNesting {
+                child_group_id: GroupId(44),
+                parent_group: make_group(45, "humans"),
+            },
+        ];
+        let mut mock = mock_backend_handler();
+        mock.expect_list_group_nestings()
+            .times(1)
+            .return_once(|| Ok(nestings));
+        let rockstars = make_group(42, "rockstars");
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::MemberOf("artists".to_string()),
+                    UserRequestFilter::MemberOfId(GroupId(43)),
+                    UserRequestFilter::MemberOfId(GroupId(42)),
+                ]))),
+                eq(true),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: Some(vec![rockstars]),
+                }])
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        ldap_handler.set_nested_groups_max_depth(2);
+
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "memberOf".to_string(),
+                "uid=artists,ou=groups,dc=example,dc=com".to_string(),
+            ),
+            vec!["memberOf".to_string()],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "memberOf".to_string(),
+                        vals: vec![
+                            b"uid=rockstars,ou=groups,dc=example,dc=com".to_vec(),
+                            b"uid=musicians,ou=groups,dc=example,dc=com".to_vec(),
+                            b"uid=artists,ou=groups,dc=example,dc=com".to_vec(),
+                        ]
+                    }],
+                }),
+                make_search_success(),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_user_as_scope() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::UserId(UserId::new("bob")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = LdapSearchRequest {
+            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: vec!["1.1".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_invalid_dn() {
+        let mock = mock_backend_handler();
+        let mut ldap_handler =
+            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
+
+        let request = LdapBindRequest {
+            dn: "cn=bob,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=groups,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=fr".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+        let request = LdapBindRequest {
+            dn: "uid=bob=test,ou=people,dc=example,dc=com".to_string(),
+            cred: LdapBindCred::Simple("pass".to_string()),
+        };
+        assert_eq!(
+            ldap_handler.do_bind(&request).await.0,
+            LdapResultCode::NamingViolation,
+        );
+    }
+
+    #[test]
+    fn test_is_subtree() {
+        let subtree1 = &[
+            ("ou".to_string(), "people".to_string()),
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        let root = &[
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        assert!(is_subtree(subtree1, root));
+        assert!(!is_subtree(&[], root));
+    }
+
+    #[test]
+    fn test_parse_distinguished_name() {
+        let parsed_dn = &[
+            ("ou".to_string(), "people".to_string()),
+            ("dc".to_string(), "example".to_string()),
+            ("dc".to_string(), "com".to_string()),
+        ];
+        assert_eq!(
+            parse_distinguished_name("ou=people,dc=example,dc=com").expect("parsing failed"),
+            parsed_dn
+        );
+        assert_eq!(
+            parse_distinguished_name(" ou  = people , dc = example , dc =  com ")
+                .expect("parsing failed"),
+            parsed_dn
+        );
+    }
+
+    #[test]
+    fn test_format_filter() {
+        assert_eq!(
+            format_filter(&LdapFilter::And(vec![
+                LdapFilter::Equality("uid".to_string(), "b*b (\\)".to_string()),
+                LdapFilter::Not(Box::new(LdapFilter::Present("mail".to_string()))),
+                LdapFilter::Or(vec![LdapFilter::Substring(
+                    "cn".to_string(),
+                    ldap3_proto::proto::LdapSubstringFilter {
+                        initial: Some("a".to_string()),
+                        any: vec!["b".to_string(), "c".to_string()],
+                        final_: None,
+                    },
+                )]),
+            ])),
+            r"(&(uid=b\2ab \28\5c\29)(!(mail=*))(|(cn=a*b*c*)))"
+        );
+    }
+
+    #[test]
+    fn test_generalized_time() {
+        use chrono::prelude::*;
+        let time = Utc.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap();
+        assert_eq!(to_generalized_time(&time), "20240131120000Z");
+        for value in [
+            "20240131120000Z",
+            "202401311200Z",
+            "2024013112Z",
+            "20240131130000+0100",
+            "20240131063000-0530",
+            "20240131140000+02",
+        ] {
+            assert_eq!(parse_generalized_time(value), Some(time), "{}", value);
+        }
+        assert_eq!(
+            parse_generalized_time("20240131120000.5Z"),
+            Some(time + chrono::Duration::milliseconds(500))
+        );
+        assert_eq!(
+            parse_generalized_time("20240131120000,123456789Z"),
+            Some(time + chrono::Duration::nanoseconds(123456789))
+        );
+        for value in [
+            "",
+            "20240131120000",
+            "2024-01-31T12:00:00Z",
+            "20241331120000Z",
+            "202401311200.5Z",
+            "20240131120000.Z",
+            "20240131120000+2400",
+            "20240131120000+1",
+        ] {
+            assert_eq!(parse_generalized_time(value), None, "{}", value);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_users() {
+        use chrono::prelude::*;
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob_1"),
+                        email: "bob@bobmail.bob".to_string(),
+                        display_name: Some("Bôb Böbberson".to_string()),
+                        first_name: Some("Bôb".to_string()),
+                        last_name: Some("Böbberson".to_string()),
+                        uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+                UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("jim"),
+                        email: "jim@cricket.jim".to_string(),
+                        display_name: Some("Jimminy Cricket".to_string()),
+                        first_name: Some("Jim".to_string()),
+                        last_name: Some("Cricket".to_string()),
+                        avatar: Some(JpegPhoto::for_tests()),
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        creation_date: Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+                        modified_date: Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
+                        preferred_language: None,
+                        uid_number: None,
+                        gid_number: None,
+                        home_directory: None,
+                        login_shell: None,
+                        email_aliases: vec!["jiminy@cricket.jim".to_string()],
+                        ssh_public_keys: Vec::new(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                },
+            ])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![]),
+            vec![
+                "objectClass",
+                "dn",
+                "uid",
+                "mail",
+                "givenName",
+                "sn",
+                "cn",
+                "createTimestamp",
+                "entryUuid",
+                "jpegPhoto",
+            ],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec()
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"bob_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"bob@bobmail.bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "givenName".to_string(),
+                            vals: vec!["Bôb".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "sn".to_string(),
+                            vals: vec!["Böbberson".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec!["Bôb Böbberson".to_string().into_bytes()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"19700101000000Z".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"698e1d5f-7a40-3151-8745-b9b8a37839da".to_vec()]
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![
+                                b"inetOrgPerson".to_vec(),
+                                b"posixAccount".to_vec(),
+                                b"mailAccount".to_vec(),
+                                b"person".to_vec()
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"jim@cricket.jim".to_vec(), b"jiminy@cricket.jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "givenName".to_string(),
+                            vals: vec![b"Jim".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "sn".to_string(),
+                            vals: vec![b"Cricket".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"Jimminy Cricket".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "createTimestamp".to_string(),
+                            vals: vec![b"20140708091011Z".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "jpegPhoto".to_string(),
+                            vals: vec![JpegPhoto::for_tests().into_bytes()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![
+                    Group {
+                        id: GroupId(1),
+                        display_name: "group_1".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        users: vec![UserId::new("bob"), UserId::new("john")],
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        archived: false,
+                        dynamic_filter: None,
+                        gid_number: None,
+                        email: None,
+                        attributes: Vec::new(),
+                    },
+                    Group {
+                        id: GroupId(3),
+                        display_name: "BestGroup".to_string(),
+                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                        users: vec![UserId::new("john")],
+                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                        archived: false,
+                        dynamic_filter: None,
+                        gid_number: None,
+                        email: None,
+                        attributes: Vec::new(),
+                    },
+                ])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=cOm",
+            LdapFilter::And(vec![]),
+            vec!["objectClass", "dn", "cn", "uniqueMember", "entryUuid"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"group_1".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![
+                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
+                            ]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(),]
+                        },
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"BestGroup".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "uniqueMember".to_string(),
+                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "entryUuid".to_string(),
+                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_posix_attributes() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Equality(
+                    UserColumn::UidNumber,
+                    "1000".to_string(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        uid_number: Some(1000),
+                        gid_number: Some(100),
+                        home_directory: Some("/home/bob".to_string()),
+                        login_shell: Some("/bin/bash".to_string()),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::GidNumber(100))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "group_1".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: Some(100),
+                    email: None,
+                    attributes: Vec::new(),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality("uidNumber".to_string(), "1000".to_string()),
+            vec!["uidNumber", "gidNumber", "homeDirectory", "loginShell"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uidNumber".to_string(),
+                            vals: vec![b"1000".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "gidNumber".to_string(),
+                            vals: vec![b"100".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "homeDirectory".to_string(),
+                            vals: vec![b"/home/bob".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "loginShell".to_string(),
+                            vals: vec![b"/bin/bash".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Equality("gidNumber".to_string(), "100".to_string()),
+            vec!["objectClass", "gidNumber"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "objectClass".to_string(),
+                            vals: vec![b"groupOfUniqueNames".to_vec(), b"posixGroup".to_vec()]
+                        },
+                        LdapPartialAttribute {
+                            atype: "gidNumber".to_string(),
+                            vals: vec![b"100".to_vec()]
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::DisplayName("group_1".to_string()),
+                GroupRequestFilter::Member(UserId::new("bob")),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::Not(Box::new(
+                    GroupRequestFilter::And(vec![]),
+                )))),
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![]))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    display_name: "group_1".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    attributes: Vec::new(),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![
+                LdapFilter::Equality("cN".to_string(), "Group_1".to_string()),
+                LdapFilter::Equality(
+                    "uniqueMember".to_string(),
+                    "uid=bob,ou=peopLe,Dc=eXample,dc=com".to_string(),
+                ),
+                LdapFilter::Equality("obJEctclass".to_string(), "groupofUniqueNames".to_string()),
+                LdapFilter::Equality("objectclass".to_string(), "groupOfNames".to_string()),
+                LdapFilter::Present("objectclass".to_string()),
+                LdapFilter::Present("dn".to_string()),
+                LdapFilter::Not(Box::new(LdapFilter::Present(
+                    "random_attribUte".to_string(),
+                ))),
+                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
+            ]),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_by_mail() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Email(
+                "Team@Example.com".to_string(),
+            ))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    display_name: "team".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![UserId::new("bob")],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: Some("Team@example.com".to_string()),
+                    attributes: Vec::new(),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Equality("mail".to_string(), "Team@Example.com".to_string()),
+            vec!["mail", "member"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=team,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"Team@example.com".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "member".to_string(),
+                            vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_hides_archived() {
+        let make_groups = || {
+            Ok(vec![
+                Group {
+                    display_name: "current".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    attributes: Vec::new(),
+                },
+                Group {
+                    display_name: "seasonal".to_string(),
+                    id: GroupId(2),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: true,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    attributes: Vec::new(),
+                },
+            ])
+        };
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![]))))
+            .times(1)
+            .return_once(move |_| make_groups());
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Archived(true))))
+            .times(1)
+            .return_once(move |_| make_groups());
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=current,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ])
+        );
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Equality("archived".to_string(), "TRUE".to_string()),
+            vec!["1.1"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=current,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=seasonal,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_filter_2() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
+                    "group_2".to_string(),
+                ))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    display_name: "group_1".to_string(),
+                    id: GroupId(1),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    attributes: Vec::new(),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
+                "displayname".to_string(),
+                "group_2".to_string(),
+            )))]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"group_1".to_vec()]
+                    },],
+                }),
+                make_search_success(),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_group_as_scope() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::And(vec![]),
+                GroupRequestFilter::DisplayName("rockstars".to_string()),
+            ]))))
+            .times(1)
+            .return_once(|_| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+
+        let request = LdapSearchRequest {
+            base: "uid=rockstars,ou=groups,Dc=example,dc=com".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::And(vec![]),
+            attrs: vec!["1.1".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()]),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_groups_error() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::Or(vec![
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
+                    "group_2".to_string(),
+                ))),
+            ]))))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::InternalError(
+                    "Error getting groups".to_string(),
+                ))
             });
-        let mut ldap_handler =
-            LdapHandler::new(mock, "dc=Example,dc=com".to_string(), vec![], vec![]);
-        let request = LdapBindRequest {
-            dn: "uid=test,ou=people,dc=example,dc=coM".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
+                "displayname".to_string(),
+                "group_2".to_string(),
+            )))]),
+            vec!["cn"],
+        );
         assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::Success
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError{
+                code: LdapResultCode::Other,
+                message: r#"Error while listing groups "ou=groups,dc=example,dc=com": Internal error: `Error getting groups`"#.to_string()
+            })
         );
-        ldap_handler
-    }
-
-    async fn setup_bound_readonly_handler(
-        mock: MockTestBackendHandler,
-    ) -> LdapHandler<MockTestBackendHandler> {
-        setup_bound_handler_with_group(mock, "lldap_strict_readonly").await
     }
 
-    async fn setup_bound_password_manager_handler(
-        mock: MockTestBackendHandler,
-    ) -> LdapHandler<MockTestBackendHandler> {
-        setup_bound_handler_with_group(mock, "lldap_password_manager").await
+    #[tokio::test]
+    async fn test_search_groups_substring_filter() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::DisplayNameSubstring(SubStringFilter {
+                    initial: Some("Group_".to_string()),
+                    ..Default::default()
+                }),
+                GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![]))),
+            ]))))
+            .times(1)
+            .return_once(|_| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![
+                LdapFilter::Substring(
+                    "cn".to_string(),
+                    ldap3_proto::proto::LdapSubstringFilter {
+                        initial: Some("Group_".to_string()),
+                        ..Default::default()
+                    },
+                ),
+                LdapFilter::Substring(
+                    "whatever".to_string(),
+                    ldap3_proto::proto::LdapSubstringFilter::default(),
+                ),
+            ]),
+            vec!["cn"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
     }
 
-    async fn setup_bound_admin_handler(
-        mock: MockTestBackendHandler,
-    ) -> LdapHandler<MockTestBackendHandler> {
-        setup_bound_handler_with_group(mock, "lldap_admin").await
+    #[tokio::test]
+    async fn test_search_filters() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
+                    vec![
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(UserId::new(
+                            "bob",
+                        )))),
+                        UserRequestFilter::And(vec![]),
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                        UserRequestFilter::And(vec![]),
+                        UserRequestFilter::And(vec![]),
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                    ],
+                )]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![LdapFilter::Or(vec![
+                LdapFilter::Not(Box::new(LdapFilter::Equality(
+                    "uid".to_string(),
+                    "bob".to_string(),
+                ))),
+                LdapFilter::Equality("objectclass".to_string(), "persOn".to_string()),
+                LdapFilter::Equality("objectclass".to_string(), "other".to_string()),
+                LdapFilter::Present("objectClass".to_string()),
+                LdapFilter::Present("uid".to_string()),
+                LdapFilter::Present("unknown".to_string()),
+                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
+            ])]),
+            vec!["objectClass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
     }
 
     #[tokio::test]
-    async fn test_bind() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_bind()
-            .with(eq(crate::domain::handler::BindRequest {
-                name: UserId::new("bob"),
-                password: "pass".to_string(),
-            }))
+    async fn test_search_attribute_aliases() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::UserId(UserId::new("bob")))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| Ok(()));
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("bob")))
-            .return_once(|_| Ok(HashSet::new()));
-        let mut ldap_handler =
-            LdapHandler::new(mock, "dc=eXample,dc=com".to_string(), vec![], vec![]);
-
-        let request = LdapOp::BindRequest(LdapBindRequest {
-            dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        });
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        email: "bob@bobmail.bob".to_string(),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.set_attribute_aliases(
+            HashMap::from([
+                ("samaccountname".to_string(), "uid".to_string()),
+                ("userprincipalname".to_string(), "mail".to_string()),
+            ]),
+            HashMap::new(),
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality("sAMAccountName".to_string(), "bob".to_string()),
+            vec!["sAMAccountName", "userPrincipalName"],
+        );
         assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![LdapOp::BindResponse(LdapBindResponse {
-                res: LdapResultOp {
-                    code: LdapResultCode::Success,
-                    matcheddn: "".to_string(),
-                    message: "".to_string(),
-                    referral: vec![],
-                },
-                saslcreds: None,
-            })]),
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "sAMAccountName".to_string(),
+                            vals: vec![b"bob".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "userPrincipalName".to_string(),
+                            vals: vec![b"bob@bobmail.bob".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
         );
     }
 
     #[tokio::test]
-    async fn test_admin_bind() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_bind()
-            .with(eq(crate::domain::handler::BindRequest {
-                name: UserId::new("test"),
-                password: "pass".to_string(),
-            }))
+    async fn test_search_mail_alias() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Or(vec![
+                    UserRequestFilter::Equality(
+                        UserColumn::Email,
+                        "jiminy@cricket.jim".to_string(),
+                    ),
+                    UserRequestFilter::EmailAlias("jiminy@cricket.jim".to_string()),
+                ]))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| Ok(()));
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("test")))
-            .return_once(|_| {
-                let mut set = HashSet::new();
-                set.insert(GroupDetails {
-                    group_id: GroupId(42),
-                    display_name: "lldap_admin".to_string(),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
-                });
-                Ok(set)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("jim"),
+                        email: "jim@cricket.jim".to_string(),
+                        email_aliases: vec!["jiminy@cricket.jim".to_string()],
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
             });
-        let mut ldap_handler =
-            LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
-
-        let request = LdapBindRequest {
-            dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Or(vec![
+                LdapFilter::Equality("mail".to_string(), "jiminy@cricket.jim".to_string()),
+                LdapFilter::Equality("mailAlias".to_string(), "jiminy@cricket.jim".to_string()),
+            ]),
+            vec!["mail", "mailAlias"],
+        );
         assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::Success
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "mail".to_string(),
+                            vals: vec![b"jim@cricket.jim".to_vec(), b"jiminy@cricket.jim".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "mailAlias".to_string(),
+                            vals: vec![b"jiminy@cricket.jim".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
         );
     }
 
     #[tokio::test]
-    async fn test_search_regular_user() {
+    async fn test_search_custom_attributes() {
         let mut mock = MockTestBackendHandler::new();
+        mock.expect_list_user_attribute_schema().returning(|| {
+            Ok(vec![
+                AttributeSchema {
+                    name: "badge".to_string(),
+                    attribute_type: AttributeType::Integer,
+                    is_list: false,
+                    is_editable: false,
+                    ldap_name: "employeeNumber".to_string(),
+                    is_case_sensitive: false,
+                },
+                AttributeSchema {
+                    name: "active".to_string(),
+                    attribute_type: AttributeType::Boolean,
+                    is_list: false,
+                    is_editable: true,
+                    ldap_name: "isActive".to_string(),
+                    is_case_sensitive: false,
+                },
+                AttributeSchema {
+                    name: "birthday".to_string(),
+                    attribute_type: AttributeType::Date,
+                    is_list: false,
+                    is_editable: true,
+                    ldap_name: "birthDate".to_string(),
+                    is_case_sensitive: false,
+                },
+            ])
+        });
         mock.expect_list_users()
             .with(
                 eq(Some(UserRequestFilter::And(vec![
-                    UserRequestFilter::And(vec![]),
-                    UserRequestFilter::UserId(UserId::new("test")),
+                    UserRequestFilter::AttributeEquality("badge".to_string(), "42".to_string()),
+                    UserRequestFilter::AttributePresent("active".to_string()),
+                    UserRequestFilter::AttributeEquality(
+                        "birthday".to_string(),
+                        "2000-02-29".to_string(),
+                    ),
                 ]))),
                 eq(false),
             )
@@ -787,246 +5380,438 @@ mod tests {
             .return_once(|_, _| {
                 Ok(vec![UserAndGroups {
                     user: User {
-                        user_id: UserId::new("test"),
+                        user_id: UserId::new("jim"),
+                        attributes: vec![
+                            AttributeValue {
+                                name: "active".to_string(),
+                                values: vec!["true".to_string()],
+                            },
+                            AttributeValue {
+                                name: "badge".to_string(),
+                                values: vec!["42".to_string()],
+                            },
+                            AttributeValue {
+                                name: "birthday".to_string(),
+                                values: vec!["2000-02-29".to_string()],
+                            },
+                        ],
                         ..Default::default()
                     },
                     groups: None,
                 }])
             });
-        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
-
-        let request =
-            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("employeenumber".to_string(), "042".to_string()),
+                LdapFilter::Present("isActive".to_string()),
+                LdapFilter::Equality("birthDate".to_string(), "20000229000000Z".to_string()),
+            ]),
+            vec!["uid", "employeeNumber", "isActive", "birthDate"],
+        );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=test,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![],
+                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "uid".to_string(),
+                            vals: vec![b"jim".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "employeeNumber".to_string(),
+                            vals: vec![b"42".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "isActive".to_string(),
+                            vals: vec![b"TRUE".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "birthDate".to_string(),
+                            vals: vec![b"20000229000000Z".to_vec()],
+                        },
+                    ],
                 }),
-                make_search_success()
-            ]),
+                make_search_success(),
+            ])
         );
     }
 
     #[tokio::test]
-    async fn test_search_readonly_user() {
+    async fn test_search_group_custom_attributes() {
         let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
+        mock.expect_list_group_attribute_schema().returning(|| {
+            Ok(vec![AttributeSchema {
+                name: "channel".to_string(),
+                attribute_type: AttributeType::String,
+                is_list: true,
+                is_editable: true,
+                ldap_name: "chatChannel".to_string(),
+                is_case_sensitive: false,
+            }])
+        });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![
+                GroupRequestFilter::AttributeEquality("channel".to_string(), "#Team".to_string()),
+                GroupRequestFilter::AttributePresent("channel".to_string()),
+            ]))))
             .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request =
-            make_user_search_request::<String>(LdapFilter::And(vec![]), vec!["1.1".to_string()]);
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "team".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    attributes: vec![AttributeValue {
+                        name: "channel".to_string(),
+                        values: vec!["#team".to_string(), "#all".to_string()],
+                    }],
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![
+                LdapFilter::Equality("chatchannel".to_string(), "#Team".to_string()),
+                LdapFilter::Present("chatChannel".to_string()),
+            ]),
+            vec!["cn", "chatChannel"],
+        );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "cn=team,ou=groups,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "cn".to_string(),
+                            vals: vec![b"team".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "chatChannel".to_string(),
+                            vals: vec![b"#team".to_vec(), b"#all".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
         );
     }
 
     #[tokio::test]
-    async fn test_search_member_of() {
-        let mut mock = MockTestBackendHandler::new();
+    async fn test_search_password_expiry() {
+        let mut mock = mock_backend_handler();
         mock.expect_list_users()
-            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(true))
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
             .times(1)
             .return_once(|_, _| {
-                Ok(vec![UserAndGroups {
-                    user: User {
-                        user_id: UserId::new("bob"),
-                        ..Default::default()
+                Ok(vec![
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("bob"),
+                            password_modified_date: Some(
+                                chrono::Utc
+                                    .timestamp_opt(19000 * 24 * 3600 + 42, 0)
+                                    .unwrap(),
+                            ),
+                            expiration_date: Some(
+                                chrono::Utc.timestamp_opt(19100 * 24 * 3600, 0).unwrap(),
+                            ),
+                            ..Default::default()
+                        },
+                        groups: None,
                     },
-                    groups: Some(vec![GroupDetails {
-                        group_id: GroupId(42),
-                        display_name: "rockstars".to_string(),
-                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                        uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
-                    }]),
-                }])
+                    UserAndGroups {
+                        user: User {
+                            user_id: UserId::new("john"),
+                            ..Default::default()
+                        },
+                        groups: None,
+                    },
+                ])
             });
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = make_user_search_request::<String>(
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.set_password_expiry(Some(PasswordExpiry {
+            max_age_days: 90,
+            warning_days: 14,
+            grace_logins: None,
+        }));
+        let request = make_user_search_request(
             LdapFilter::And(vec![]),
-            vec!["memberOf".to_string()],
+            vec![
+                "shadowLastChange",
+                "shadowMax",
+                "shadowWarning",
+                "shadowExpire",
+            ],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
             Ok(vec![
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
                     dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![LdapPartialAttribute {
-                        atype: "memberOf".to_string(),
-                        vals: vec![b"uid=rockstars,ou=groups,dc=example,dc=com".to_vec()]
-                    }],
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "shadowLastChange".to_string(),
+                            vals: vec![b"19000".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "shadowMax".to_string(),
+                            vals: vec![b"90".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "shadowWarning".to_string(),
+                            vals: vec![b"14".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "shadowExpire".to_string(),
+                            vals: vec![b"19100".to_vec()],
+                        },
+                    ],
+                }),
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=john,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![],
                 }),
                 make_search_success(),
-            ]),
+            ])
         );
     }
 
     #[tokio::test]
-    async fn test_search_user_as_scope() {
-        let mut mock = MockTestBackendHandler::new();
+    async fn test_active_directory_compatibility() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "pass".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .return_once(|_| Ok(HashSet::new()));
         mock.expect_list_users()
             .with(
                 eq(Some(UserRequestFilter::And(vec![
-                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::And(vec![
+                        UserRequestFilter::And(vec![]),
+                        UserRequestFilter::UserId(UserId::new("bob")),
+                    ]),
                     UserRequestFilter::UserId(UserId::new("bob")),
                 ]))),
                 eq(false),
             )
             .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = LdapSearchRequest {
-            base: "uid=bob,ou=people,Dc=example,dc=com".to_string(),
-            scope: LdapSearchScope::Base,
-            aliases: LdapDerefAliases::Never,
-            sizelimit: 0,
-            timelimit: 0,
-            typesonly: false,
-            filter: LdapFilter::And(vec![]),
-            attrs: vec!["1.1".to_string()],
-        };
-        assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
-        );
-    }
-
-    #[tokio::test]
-    async fn test_bind_invalid_dn() {
-        let mock = MockTestBackendHandler::new();
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        uuid: uuid!("a1a2a3a4-b1b2-c1c2-d1d2-d3d4d5d6d7d8"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
         let mut ldap_handler =
             LdapHandler::new(mock, "dc=example,dc=com".to_string(), vec![], vec![]);
-
+        ldap_handler.set_active_directory_compatibility(true);
         let request = LdapBindRequest {
-            dn: "cn=bob,dc=example,dc=com".to_string(),
+            dn: "bob@example.com".to_string(),
             cred: LdapBindCred::Simple("pass".to_string()),
         };
         assert_eq!(
             ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            LdapResultCode::Success
         );
-        let request = LdapBindRequest {
-            dn: "uid=bob,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+        let request = make_user_search_request(
+            LdapFilter::And(vec![
+                LdapFilter::Equality("objectClass".to_string(), "user".to_string()),
+                LdapFilter::Equality("sAMAccountName".to_string(), "bob".to_string()),
+            ]),
+            vec![
+                "sAMAccountName",
+                "userPrincipalName",
+                "objectGUID",
+                "userAccountControl",
+            ],
         );
-        let request = LdapBindRequest {
-            dn: "uid=bob,ou=groups,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
         assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![
+                        LdapPartialAttribute {
+                            atype: "sAMAccountName".to_string(),
+                            vals: vec![b"bob".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "userPrincipalName".to_string(),
+                            vals: vec![b"bob@example.com".to_vec()],
+                        },
+                        LdapPartialAttribute {
+                            atype: "objectGUID".to_string(),
+                            vals: vec![vec![
+                                0xa4, 0xa3, 0xa2, 0xa1, 0xb2, 0xb1, 0xc2, 0xc1, 0xd1, 0xd2, 0xd3,
+                                0xd4, 0xd5, 0xd6, 0xd7, 0xd8
+                            ]],
+                        },
+                        LdapPartialAttribute {
+                            atype: "userAccountControl".to_string(),
+                            vals: vec![b"512".to_vec()],
+                        },
+                    ],
+                }),
+                make_search_success(),
+            ])
         );
-        let request = LdapBindRequest {
-            dn: "uid=bob,ou=people,dc=example,dc=fr".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
-        assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+    }
+
+    #[tokio::test]
+    async fn test_search_member_of_filter() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::MemberOf("group_1".to_string()))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "memberOf".to_string(),
+                "cn=group_1, ou=groups, dc=example,dc=com".to_string(),
+            ),
+            vec!["objectClass"],
         );
-        let request = LdapBindRequest {
-            dn: "uid=bob=test,ou=people,dc=example,dc=com".to_string(),
-            cred: LdapBindCred::Simple("pass".to_string()),
-        };
         assert_eq!(
-            ldap_handler.do_bind(&request).await.0,
-            LdapResultCode::NamingViolation,
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![make_search_success()])
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality("memberOf".to_string(), "group_1".to_string()),
+            vec!["objectClass"],
         );
-    }
-
-    #[test]
-    fn test_is_subtree() {
-        let subtree1 = &[
-            ("ou".to_string(), "people".to_string()),
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
-        let root = &[
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
-        assert!(is_subtree(subtree1, root));
-        assert!(!is_subtree(&[], root));
-    }
-
-    #[test]
-    fn test_parse_distinguished_name() {
-        let parsed_dn = &[
-            ("ou".to_string(), "people".to_string()),
-            ("dc".to_string(), "example".to_string()),
-            ("dc".to_string(), "com".to_string()),
-        ];
         assert_eq!(
-            parse_distinguished_name("ou=people,dc=example,dc=com").expect("parsing failed"),
-            parsed_dn
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError {
+                code: LdapResultCode::InvalidDNSyntax,
+                message: "Missing DN value".to_string()
+            })
+        );
+        let request = make_user_search_request(
+            LdapFilter::Equality(
+                "memberOf".to_string(),
+                "cn=mygroup,dc=example,dc=com".to_string(),
+            ),
+            vec!["objectClass"],
         );
         assert_eq!(
-            parse_distinguished_name(" ou  = people , dc = example , dc =  com ")
-                .expect("parsing failed"),
-            parsed_dn
+            ldap_handler.do_search_or_dse(&request).await,
+            Err(LdapError{
+                code: LdapResultCode::InvalidDNSyntax,
+                message: r#"Unexpected DN format. Got "cn=mygroup,dc=example,dc=com", expected: "uid=id,ou=groups,dc=example,dc=com""#.to_string()
+            })
         );
     }
 
     #[tokio::test]
-    async fn test_search_users() {
-        use chrono::prelude::*;
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users().times(1).return_once(|_, _| {
-            Ok(vec![
-                UserAndGroups {
+    async fn test_search_filters_lowercase() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
+                    vec![UserRequestFilter::Not(Box::new(
+                        UserRequestFilter::Equality(UserColumn::FirstName, "bob".to_string()),
+                    ))],
+                )]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
                     user: User {
                         user_id: UserId::new("bob_1"),
-                        email: "bob@bobmail.bob".to_string(),
-                        display_name: Some("Bôb Böbberson".to_string()),
-                        first_name: Some("Bôb".to_string()),
-                        last_name: Some("Böbberson".to_string()),
-                        uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
                         ..Default::default()
                     },
                     groups: None,
-                },
-                UserAndGroups {
-                    user: User {
-                        user_id: UserId::new("jim"),
-                        email: "jim@cricket.jim".to_string(),
-                        display_name: Some("Jimminy Cricket".to_string()),
-                        first_name: Some("Jim".to_string()),
-                        last_name: Some("Cricket".to_string()),
-                        avatar: Some(JpegPhoto::for_tests()),
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                        creation_date: Utc.with_ymd_and_hms(2014, 7, 8, 9, 10, 11).unwrap(),
-                    },
-                    groups: None,
-                },
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(
+            LdapFilter::And(vec![LdapFilter::Or(vec![LdapFilter::Not(Box::new(
+                LdapFilter::Equality("givenname".to_string(), "bob".to_string()),
+            ))])]),
+            vec!["objectclass"],
+        );
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                    attributes: vec![LdapPartialAttribute {
+                        atype: "objectclass".to_string(),
+                        vals: vec![
+                            b"inetOrgPerson".to_vec(),
+                            b"posixAccount".to_vec(),
+                            b"mailAccount".to_vec(),
+                            b"person".to_vec()
+                        ]
+                    },]
+                }),
+                make_search_success()
             ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_both() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob_1"),
+                    email: "bob@bobmail.bob".to_string(),
+                    display_name: Some("Bôb Böbberson".to_string()),
+                    first_name: Some("Bôb".to_string()),
+                    last_name: Some("Böbberson".to_string()),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
         });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![]))))
+            .times(1)
+            .return_once(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "group_1".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![UserId::new("bob"), UserId::new("john")],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    attributes: Vec::new(),
+                }])
+            });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
+        let request = make_search_request(
+            "dc=example,dc=com",
             LdapFilter::And(vec![]),
-            vec![
-                "objectClass",
-                "dn",
-                "uid",
-                "mail",
-                "givenName",
-                "sn",
-                "cn",
-                "createTimestamp",
-                "entryUuid",
-                "jpegPhoto",
-            ],
+            vec!["objectClass", "dn", "cn"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
@@ -1043,79 +5828,22 @@ mod tests {
                                 b"person".to_vec()
                             ]
                         },
-                        LdapPartialAttribute {
-                            atype: "uid".to_string(),
-                            vals: vec![b"bob_1".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "mail".to_string(),
-                            vals: vec![b"bob@bobmail.bob".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "givenName".to_string(),
-                            vals: vec!["Bôb".to_string().into_bytes()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "sn".to_string(),
-                            vals: vec!["Böbberson".to_string().into_bytes()]
-                        },
                         LdapPartialAttribute {
                             atype: "cn".to_string(),
                             vals: vec!["Bôb Böbberson".to_string().into_bytes()]
                         },
-                        LdapPartialAttribute {
-                            atype: "createTimestamp".to_string(),
-                            vals: vec![b"1970-01-01T00:00:00+00:00".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"698e1d5f-7a40-3151-8745-b9b8a37839da".to_vec()]
-                        },
                     ],
                 }),
                 LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=jim,ou=people,dc=example,dc=com".to_string(),
+                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
                     attributes: vec![
                         LdapPartialAttribute {
                             atype: "objectClass".to_string(),
-                            vals: vec![
-                                b"inetOrgPerson".to_vec(),
-                                b"posixAccount".to_vec(),
-                                b"mailAccount".to_vec(),
-                                b"person".to_vec()
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uid".to_string(),
-                            vals: vec![b"jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "mail".to_string(),
-                            vals: vec![b"jim@cricket.jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "givenName".to_string(),
-                            vals: vec![b"Jim".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "sn".to_string(),
-                            vals: vec![b"Cricket".to_vec()]
+                            vals: vec![b"groupOfUniqueNames".to_vec(),]
                         },
                         LdapPartialAttribute {
                             atype: "cn".to_string(),
-                            vals: vec![b"Jimminy Cricket".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "createTimestamp".to_string(),
-                            vals: vec![b"2014-07-08T09:10:11+00:00".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "jpegPhoto".to_string(),
-                            vals: vec![JpegPhoto::for_tests().into_bytes()]
+                            vals: vec![b"group_1".to_vec()]
                         },
                     ],
                 }),
@@ -1125,918 +5853,1762 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_search_groups() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![]))))
-            .times(1)
-            .return_once(|_| {
+    async fn test_search_wildcards() {
+        let mut mock = mock_backend_handler();
+
+        mock.expect_list_users().returning(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob_1"),
+                    email: "bob@bobmail.bob".to_string(),
+                    display_name: Some("Bôb Böbberson".to_string()),
+                    last_name: Some("Böbberson".to_string()),
+                    avatar: Some(JpegPhoto::for_tests()),
+                    uuid: uuid!("b4ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::And(vec![]))))
+            .returning(|_| {
+                Ok(vec![Group {
+                    id: GroupId(1),
+                    display_name: "group_1".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    users: vec![UserId::new("bob"), UserId::new("john")],
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                    attributes: Vec::new(),
+                }])
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+
+        // Test simple wildcard
+        let request =
+            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec!["*", "+"]);
+
+        // all: "objectclass", "dn", "uid", "mail", "givenname", "sn", "cn"
+        // Operational: "createtimestamp", "modifytimestamp", "entryuuid", "entrydn", "memberof"
+
+        let expected_result =
+            |operational_attributes: Vec<LdapPartialAttribute>,
+             group_operational_attributes: Vec<LdapPartialAttribute>| {
+                Ok(vec![
+                    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                        dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
+                        attributes: vec![
+                            LdapPartialAttribute {
+                                atype: "objectclass".to_string(),
+                                vals: vec![
+                                    b"inetOrgPerson".to_vec(),
+                                    b"posixAccount".to_vec(),
+                                    b"mailAccount".to_vec(),
+                                    b"person".to_vec(),
+                                ],
+                            },
+                            LdapPartialAttribute {
+                                atype: "uid".to_string(),
+                                vals: vec![b"bob_1".to_vec()],
+                            },
+                            LdapPartialAttribute {
+                                atype: "mail".to_string(),
+                                vals: vec![b"bob@bobmail.bob".to_vec()],
+                            },
+                            LdapPartialAttribute {
+                                atype: "sn".to_string(),
+                                vals: vec!["Böbberson".to_string().into_bytes()],
+                            },
+                            LdapPartialAttribute {
+                                atype: "cn".to_string(),
+                                vals: vec!["Bôb Böbberson".to_string().into_bytes()],
+                            },
+                            LdapPartialAttribute {
+                                atype: "jpegPhoto".to_string(),
+                                vals: vec![JpegPhoto::for_tests().into_bytes()],
+                            },
+                            LdapPartialAttribute {
+                                atype: "createtimestamp".to_string(),
+                                vals: vec![b"19700101000000Z".to_vec()],
+                            },
+                            LdapPartialAttribute {
+                                atype: "entryuuid".to_string(),
+                                vals: vec![b"b4ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                            },
+                        ]
+                        .into_iter()
+                        .chain(operational_attributes)
+                        .collect(),
+                    }),
+                    // "objectclass", "dn", "uid", "cn", "member", "uniquemember"
+                    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                        dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                        attributes: vec![
+                            LdapPartialAttribute {
+                                atype: "objectclass".to_string(),
+                                vals: vec![b"groupOfUniqueNames".to_vec()],
+                            },
+                            // UID
+                            LdapPartialAttribute {
+                                atype: "uid".to_string(),
+                                vals: vec![b"group_1".to_vec()],
+                            },
+                            LdapPartialAttribute {
+                                atype: "cn".to_string(),
+                                vals: vec![b"group_1".to_vec()],
+                            },
+                            //member / uniquemember : "uid={},ou=people,{}"
+                            LdapPartialAttribute {
+                                atype: "member".to_string(),
+                                vals: vec![
+                                    b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                                    b"uid=john,ou=people,dc=example,dc=com".to_vec(),
+                                ],
+                            },
+                            LdapPartialAttribute {
+                                atype: "uniquemember".to_string(),
+                                vals: vec![
+                                    b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                                    b"uid=john,ou=people,dc=example,dc=com".to_vec(),
+                                ],
+                            },
+                            LdapPartialAttribute {
+                                atype: "entryuuid".to_string(),
+                                vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+                            },
+                        ]
+                        .into_iter()
+                        .chain(group_operational_attributes)
+                        .collect(),
+                    }),
+                    make_search_success(),
+                ])
+            };
+        // The user has no groups, so there is no memberOf.
+        let expected_operational_result = expected_result(
+            vec![
+                LdapPartialAttribute {
+                    atype: "modifytimestamp".to_string(),
+                    vals: vec![b"19700101000000Z".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "entrydn".to_string(),
+                    vals: vec![b"uid=bob_1,ou=people,dc=example,dc=com".to_vec()],
+                },
+            ],
+            vec![
+                LdapPartialAttribute {
+                    atype: "entrydn".to_string(),
+                    vals: vec![b"cn=group_1,ou=groups,dc=example,dc=com".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "createtimestamp".to_string(),
+                    vals: vec![b"19700101000042Z".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "modifytimestamp".to_string(),
+                    vals: vec![b"19700101000042Z".to_vec()],
+                },
+            ],
+        );
+        let expected_result = expected_result(vec![], vec![]);
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            expected_operational_result
+        );
+
+        let request2 = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["objectclass", "obJEctclaSS", "dn", "*", "*"],
+        );
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request2).await,
+            expected_result
+        );
+
+        let request3 = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["*", "+", "+"],
+        );
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request3).await,
+            expected_operational_result
+        );
+
+        let request4 =
+            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec![""; 0]);
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request4).await,
+            expected_result
+        );
+
+        let request5 = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["objectclass", "dn", "uid", "*"],
+        );
+
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request5).await,
+            expected_result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_operational_attributes() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users().returning(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob"),
+                    email: "bob@bobmail.bob".to_string(),
+                    uuid: uuid!("b4ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    ..Default::default()
+                },
+                groups: Some(vec![GroupDetails {
+                    group_id: GroupId(1),
+                    display_name: "group_1".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                }]),
+            }])
+        });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let attribute = |name: &str, value: &str| LdapPartialAttribute {
+            atype: name.to_string(),
+            vals: vec![value.as_bytes().to_vec()],
+        };
+        let uid = || attribute("uid", "bob");
+        let mail = || attribute("mail", "bob@bobmail.bob");
+        let create_timestamp = |name: &str| attribute(name, "19700101000000Z");
+        let entry_uuid = || attribute("entryuuid", "b4ac75e0-2900-3e21-926c-2f732c26b3fc");
+        let entry_dn = || attribute("entrydn", "uid=bob,ou=people,dc=example,dc=com");
+        let member_of = |name: &str| attribute(name, "uid=group_1,ou=groups,dc=example,dc=com");
+        let object_class = || LdapPartialAttribute {
+            atype: "objectclass".to_string(),
+            vals: vec![
+                b"inetOrgPerson".to_vec(),
+                b"posixAccount".to_vec(),
+                b"mailAccount".to_vec(),
+                b"person".to_vec(),
+            ],
+        };
+        for (attributes, expected) in [
+            (
+                vec!["+"],
+                vec![
+                    create_timestamp("createtimestamp"),
+                    create_timestamp("modifytimestamp"),
+                    entry_uuid(),
+                    entry_dn(),
+                    member_of("memberof"),
+                ],
+            ),
+            (
+                vec!["uid", "+"],
+                vec![
+                    uid(),
+                    create_timestamp("createtimestamp"),
+                    create_timestamp("modifytimestamp"),
+                    entry_uuid(),
+                    entry_dn(),
+                    member_of("memberof"),
+                ],
+            ),
+            (
+                vec!["*", "memberOf", "createTimestamp"],
+                vec![
+                    member_of("memberOf"),
+                    create_timestamp("createTimestamp"),
+                    object_class(),
+                    uid(),
+                    mail(),
+                    entry_uuid(),
+                ],
+            ),
+            (
+                vec!["memberOf", "*", "+"],
+                vec![
+                    member_of("memberOf"),
+                    object_class(),
+                    uid(),
+                    mail(),
+                    create_timestamp("createtimestamp"),
+                    entry_uuid(),
+                    create_timestamp("modifytimestamp"),
+                    entry_dn(),
+                ],
+            ),
+        ] {
+            let request = make_search_request(
+                "ou=people,dc=example,dc=com",
+                LdapFilter::And(vec![]),
+                attributes.clone(),
+            );
+            assert_eq!(
+                ldap_handler.do_search_or_dse(&request).await,
                 Ok(vec![
-                    Group {
-                        id: GroupId(1),
-                        display_name: "group_1".to_string(),
-                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                        users: vec![UserId::new("bob"), UserId::new("john")],
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                    },
-                    Group {
-                        id: GroupId(3),
-                        display_name: "BestGroup".to_string(),
-                        creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                        users: vec![UserId::new("john")],
-                        uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                        dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                        attributes: expected,
+                    }),
+                    make_search_success(),
+                ]),
+                "{:?}",
+                attributes
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_ignores_deref_aliases() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
+            .times(4)
+            .returning(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
                     },
-                ])
+                    groups: None,
+                }])
             });
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::Not(Box::new(
+                    UserRequestFilter::And(vec![]),
+                )))),
+                eq(false),
+            )
+            .times(4)
+            .returning(|_, _| Ok(vec![]));
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        for aliases in [
+            LdapDerefAliases::Never,
+            LdapDerefAliases::InSearching,
+            LdapDerefAliases::FindingBaseObj,
+            LdapDerefAliases::Always,
+        ] {
+            let request = LdapSearchRequest {
+                aliases: aliases.clone(),
+                ..make_user_search_request(
+                    LdapFilter::Equality("objectClass".to_string(), "person".to_string()),
+                    vec!["1.1"],
+                )
+            };
+            assert_eq!(
+                ldap_handler.do_search_or_dse(&request).await,
+                Ok(vec![
+                    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                        dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                        attributes: vec![],
+                    }),
+                    make_search_success(),
+                ])
+            );
+            // There are no aliases.
+            let request = LdapSearchRequest {
+                aliases,
+                ..make_user_search_request(
+                    LdapFilter::Equality("objectClass".to_string(), "alias".to_string()),
+                    vec!["1.1"],
+                )
+            };
+            assert_eq!(
+                ldap_handler.do_search_or_dse(&request).await,
+                Ok(vec![make_search_success()])
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_wrong_base() {
+        let mut ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
         let request = make_search_request(
-            "ou=groups,dc=example,dc=cOm",
+            "ou=users,dc=example,dc=com",
             LdapFilter::And(vec![]),
-            vec!["objectClass", "dn", "cn", "uniqueMember", "entryUuid"],
+            vec!["objectClass"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"group_1".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![
-                                b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
-                                b"uid=john,ou=people,dc=example,dc=com".to_vec(),
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
-                        },
-                    ],
-                }),
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=BestGroup,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"BestGroup".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "uniqueMember".to_string(),
-                            vals: vec![b"uid=john,ou=people,dc=example,dc=com".to_vec()]
-                        },
-                        LdapPartialAttribute {
-                            atype: "entryUuid".to_string(),
-                            vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
-                        },
-                    ],
-                }),
-                make_search_success(),
-            ])
+            Ok(vec![make_search_success()])
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![
-                GroupRequestFilter::DisplayName("group_1".to_string()),
-                GroupRequestFilter::Member(UserId::new("bob")),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::Not(Box::new(
-                    GroupRequestFilter::And(vec![]),
-                )))),
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::And(vec![]))),
-            ]))))
+    async fn test_search_substring_filters() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::Substring(
+                        UserColumn::Email,
+                        SubStringFilter {
+                            final_: Some("@example.com".to_string()),
+                            ..Default::default()
+                        },
+                    ),
+                    UserRequestFilter::Substring(
+                        UserColumn::FirstName,
+                        SubStringFilter {
+                            initial: Some("jo".to_string()),
+                            any: vec!["h".to_string()],
+                            final_: None,
+                        },
+                    ),
+                    UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
+                ]))),
+                eq(false),
+            )
             .times(1)
-            .return_once(|_| {
-                Ok(vec![Group {
-                    display_name: "group_1".to_string(),
-                    id: GroupId(1),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    users: vec![],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                }])
-            });
+            .return_once(|_, _| Ok(vec![]));
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
+        let request = make_user_search_request(
             LdapFilter::And(vec![
-                LdapFilter::Equality("cN".to_string(), "Group_1".to_string()),
-                LdapFilter::Equality(
-                    "uniqueMember".to_string(),
-                    "uid=bob,ou=peopLe,Dc=eXample,dc=com".to_string(),
+                LdapFilter::Substring(
+                    "mail".to_string(),
+                    ldap3_proto::proto::LdapSubstringFilter {
+                        final_: Some("@example.com".to_string()),
+                        ..Default::default()
+                    },
+                ),
+                LdapFilter::Substring(
+                    "givenName".to_string(),
+                    ldap3_proto::proto::LdapSubstringFilter {
+                        initial: Some("jo".to_string()),
+                        any: vec!["h".to_string()],
+                        final_: None,
+                    },
+                ),
+                // Numbers can't be matched by substrings.
+                LdapFilter::Substring(
+                    "uidNumber".to_string(),
+                    ldap3_proto::proto::LdapSubstringFilter {
+                        initial: Some("1".to_string()),
+                        ..Default::default()
+                    },
                 ),
-                LdapFilter::Equality("obJEctclass".to_string(), "groupofUniqueNames".to_string()),
-                LdapFilter::Equality("objectclass".to_string(), "groupOfNames".to_string()),
-                LdapFilter::Present("objectclass".to_string()),
-                LdapFilter::Present("dn".to_string()),
-                LdapFilter::Not(Box::new(LdapFilter::Present(
-                    "random_attribUte".to_string(),
-                ))),
-                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
             ]),
-            vec!["1.1"],
+            vec!["objectClass"],
         );
         assert_eq!(
             ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![],
-                }),
-                make_search_success(),
-            ])
+            Ok(vec![make_search_success()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_password_change() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        use lldap_auth::*;
+        let mut rng = rand::rngs::OsRng;
+        let registration_start_request =
+            opaque::client::registration::start_registration("password", &mut rng).unwrap();
+        let request = registration::ClientRegistrationStartRequest {
+            username: "bob".to_string(),
+            registration_start_request: registration_start_request.message,
+        };
+        let start_response = opaque::server::registration::start_registration(
+            &opaque::server::ServerSetup::new(&mut rng),
+            request.registration_start_request,
+            &request.username,
+        )
+        .unwrap();
+        mock.expect_registration_start().times(1).return_once(|_| {
+            Ok(registration::ServerRegistrationStartResponse {
+                server_data: "".to_string(),
+                registration_response: start_response.message,
+            })
+        });
+        mock.expect_registration_finish()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_password_change_too_weak() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        mock.expect_registration_start().never();
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.set_password_policy(Arc::new(PasswordPolicy {
+            min_strength: 3,
+            ..PasswordPolicy::default()
+        }));
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password".to_string()),
+            }
+            .into(),
         );
+        assert!(matches!(
+            ldap_handler.handle_ldap_message(request).await.as_deref(),
+            Some([LdapOp::ExtendedResponse(LdapExtendedResponse {
+                res: LdapResultOp {
+                    code: LdapResultCode::ConstraintViolation,
+                    ..
+                },
+                ..
+            })])
+        ));
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter_2() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::Or(vec![
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
-                    "group_2".to_string(),
-                ))),
-            ]))))
+    async fn test_password_change_password_manager() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        use lldap_auth::*;
+        let mut rng = rand::rngs::OsRng;
+        let registration_start_request =
+            opaque::client::registration::start_registration("password", &mut rng).unwrap();
+        let request = registration::ClientRegistrationStartRequest {
+            username: "bob".to_string(),
+            registration_start_request: registration_start_request.message,
+        };
+        let start_response = opaque::server::registration::start_registration(
+            &opaque::server::ServerSetup::new(&mut rng),
+            request.registration_start_request,
+            &request.username,
+        )
+        .unwrap();
+        mock.expect_registration_start().times(1).return_once(|_| {
+            Ok(registration::ServerRegistrationStartResponse {
+                server_data: "".to_string(),
+                registration_response: start_response.message,
+            })
+        });
+        mock.expect_registration_finish()
             .times(1)
-            .return_once(|_| {
-                Ok(vec![Group {
-                    display_name: "group_1".to_string(),
-                    id: GroupId(1),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    users: vec![],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                }])
-            });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
-            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
-                "displayname".to_string(),
-                "group_2".to_string(),
-            )))]),
-            vec!["cn"],
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_password_manager_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password".to_string()),
+            }
+            .into(),
         );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![LdapPartialAttribute {
-                        atype: "cn".to_string(),
-                        vals: vec![b"group_1".to_vec()]
-                    },],
-                }),
-                make_search_success(),
-            ])
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
         );
     }
 
     #[tokio::test]
-    async fn test_search_group_as_scope() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![
-                GroupRequestFilter::And(vec![]),
-                GroupRequestFilter::DisplayName("rockstars".to_string()),
-            ]))))
+    async fn test_password_change_own_password() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("test"),
+                password: "old_pass".to_string(),
+            }))
             .times(1)
-            .return_once(|_| Ok(vec![]));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-
-        let request = LdapSearchRequest {
-            base: "uid=rockstars,ou=groups,Dc=example,dc=com".to_string(),
-            scope: LdapSearchScope::Base,
-            aliases: LdapDerefAliases::Never,
-            sizelimit: 0,
-            timelimit: 0,
-            typesonly: false,
-            filter: LdapFilter::And(vec![]),
-            attrs: vec!["1.1".to_string()],
+            .return_once(|_| Ok(()));
+        use lldap_auth::*;
+        let mut rng = rand::rngs::OsRng;
+        let registration_start_request =
+            opaque::client::registration::start_registration("password", &mut rng).unwrap();
+        let request = registration::ClientRegistrationStartRequest {
+            username: "test".to_string(),
+            registration_start_request: registration_start_request.message,
         };
+        let start_response = opaque::server::registration::start_registration(
+            &opaque::server::ServerSetup::new(&mut rng),
+            request.registration_start_request,
+            &request.username,
+        )
+        .unwrap();
+        mock.expect_registration_start().times(1).return_once(|_| {
+            Ok(registration::ServerRegistrationStartResponse {
+                server_data: "".to_string(),
+                registration_response: start_response.message,
+            })
+        });
+        mock.expect_registration_finish()
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        // Without an identity, the password of the bound user is changed.
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: None,
+                old_password: Some("old_pass".to_string()),
+                new_password: Some("password".to_string()),
+            }
+            .into(),
+        );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()]),
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::Success,
+                "".to_string(),
+            )])
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_error() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::Or(vec![
-                GroupRequestFilter::Not(Box::new(GroupRequestFilter::DisplayName(
-                    "group_2".to_string(),
-                ))),
-            ]))))
+    async fn test_password_change_wrong_old_password() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("test"),
+                password: "wrong".to_string(),
+            }))
             .times(1)
             .return_once(|_| {
-                Err(crate::domain::error::DomainError::InternalError(
-                    "Error getting groups".to_string(),
+                Err(crate::domain::error::DomainError::AuthenticationError(
+                    "Wrong password".to_string(),
                 ))
             });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
-            LdapFilter::Or(vec![LdapFilter::Not(Box::new(LdapFilter::Equality(
-                "displayname".to_string(),
-                "group_2".to_string(),
-            )))]),
-            vec!["cn"],
+        mock.expect_registration_start().never();
+        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=test,ou=people,dc=example,dc=com".to_string()),
+                old_password: Some("wrong".to_string()),
+                new_password: Some("password".to_string()),
+            }
+            .into(),
         );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::Other,
-                message: r#"Error while listing groups "ou=groups,dc=example,dc=com": Internal error: `Error getting groups`"#.to_string()
-            })
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::InvalidCredentials,
+                "Wrong old password".to_string(),
+            )])
         );
     }
 
     #[tokio::test]
-    async fn test_search_groups_filter_error() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_search_request(
-            "ou=groups,dc=example,dc=com",
-            LdapFilter::And(vec![LdapFilter::Substring(
-                "whatever".to_string(),
-                ldap3_proto::proto::LdapSubstringFilter::default(),
-            )]),
-            vec!["cn"],
+    async fn test_password_change_errors() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .returning(|_| Ok(HashSet::new()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: None,
+                old_password: None,
+                new_password: None,
+            }
+            .into(),
         );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::UnwillingToPerform,
-                message: r#"Unsupported group filter: Substring("whatever", LdapSubstringFilter { initial: None, any: [], final_: None })"#
-                    .to_string()
-            })
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::UnwillingToPerform,
+                "Missing the new password, generating one is not supported".to_string(),
+            )])
         );
-    }
-
-    #[tokio::test]
-    async fn test_search_filters() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
-                    vec![
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::UserId(UserId::new(
-                            "bob",
-                        )))),
-                        UserRequestFilter::And(vec![]),
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
-                        UserRequestFilter::And(vec![]),
-                        UserRequestFilter::And(vec![]),
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
-                        UserRequestFilter::Not(Box::new(UserRequestFilter::And(vec![]))),
-                    ],
-                )]))),
-                eq(false),
-            )
-            .times(1)
-            .return_once(|_, _| Ok(vec![]));
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
-            LdapFilter::And(vec![LdapFilter::Or(vec![
-                LdapFilter::Not(Box::new(LdapFilter::Equality(
-                    "uid".to_string(),
-                    "bob".to_string(),
-                ))),
-                LdapFilter::Equality("objectclass".to_string(), "persOn".to_string()),
-                LdapFilter::Equality("objectclass".to_string(), "other".to_string()),
-                LdapFilter::Present("objectClass".to_string()),
-                LdapFilter::Present("uid".to_string()),
-                LdapFilter::Present("unknown".to_string()),
-                LdapFilter::Equality("unknown_attribute".to_string(), "randomValue".to_string()),
-            ])]),
-            vec!["objectClass"],
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=groups,ou=people,dc=example,dc=com".to_string()),
+                old_password: None,
+                new_password: Some("password".to_string()),
+            }
+            .into(),
+        );
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::InvalidDNSyntax,
+                r#"Invalid username: Unexpected DN format. Got "uid=bob,ou=groups,ou=people,dc=example,dc=com", expected: "uid=id,ou=people,dc=example,dc=com""#.to_string(),
+            )])
         );
+        let request = LdapOp::ExtendedRequest(LdapExtendedRequest {
+            name: "test".to_string(),
+            value: None,
+        });
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()])
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::UnwillingToPerform,
+                "Unsupported extended operation: test".to_string(),
+            )])
         );
     }
 
     #[tokio::test]
-    async fn test_search_member_of_filter() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::MemberOf("group_1".to_string()))),
-                eq(false),
-            )
+    async fn test_cancel_without_operation() {
+        let mut ldap_handler = LdapHandler::new(
+            mock_backend_handler(),
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+        );
+        let cancel_request = |value: Element| {
+            LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: CANCEL_OID.to_string(),
+                value: Some(value.to_bytes()),
+            })
+        };
+        let extras = RequestExtras::default();
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(
+                    cancel_request(Element::sequence(vec![Element::integer(3)])),
+                    &[],
+                    &extras
+                )
+                .await,
+            Some(vec![(
+                make_extended_response(
+                    LdapResultCode::Other,
+                    "No operation in progress with the message ID 3".to_string(),
+                ),
+                vec![],
+                ResponseExtras {
+                    controls: vec![],
+                    result_code: Some(NO_SUCH_OPERATION),
+                },
+            )])
+        );
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(cancel_request(Element::integer(3)), &[], &extras)
+                .await,
+            Some(vec![(
+                make_extended_response(
+                    LdapResultCode::ProtocolError,
+                    "Invalid Cancel request".to_string(),
+                ),
+                vec![],
+                ResponseExtras::default(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let mut transaction = mock_backend_handler();
+        transaction
+            .expect_create_user()
+            .with(eq(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "".to_owned(),
+                display_name: Some("Bob".to_string()),
+                ..Default::default()
+            }))
             .times(1)
-            .return_once(|_, _| Ok(vec![]));
+            .return_once(|_| Ok(()));
+        transaction
+            .expect_end_transaction()
+            .with(eq(true))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut mock = mock_backend_handler();
+        mock.expect_begin_transaction()
+            .times(1)
+            .return_once(|| Ok(transaction));
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
-            LdapFilter::Equality(
-                "memberOf".to_string(),
-                "cn=group_1, ou=groups, dc=example,dc=com".to_string(),
-            ),
-            vec!["objectClass"],
-        );
+        let extras = RequestExtras::default();
+        let responses = ldap_handler
+            .handle_ldap_request(
+                LdapOp::ExtendedRequest(LdapExtendedRequest {
+                    name: START_TRANSACTION_OID.to_string(),
+                    value: None,
+                }),
+                &[],
+                &extras,
+            )
+            .await
+            .unwrap();
+        let identifier = match &responses[0].0 {
+            LdapOp::ExtendedResponse(response) => response.value.clone().unwrap(),
+            response => panic!("Unexpected response: {:?}", response),
+        };
+        let add_request = || {
+            LdapOp::AddRequest(LdapAddRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                attributes: vec![LdapPartialAttribute {
+                    atype: "cn".to_owned(),
+                    vals: vec![b"Bob".to_vec()],
+                }],
+            })
+        };
+        let transaction_extras = |identifier: &[u8]| RequestExtras {
+            controls: vec![RawControl {
+                oid: TRANSACTION_SPECIFICATION_OID.to_string(),
+                criticality: true,
+                value: Some(identifier.to_vec()),
+            }],
+            sasl: None,
+        };
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()])
+            ldap_handler
+                .handle_ldap_request(add_request(), &[], &transaction_extras(b"other"))
+                .await,
+            Some(vec![(
+                make_add_error(
+                    LdapResultCode::UnwillingToPerform,
+                    "Unknown transaction".to_string()
+                ),
+                vec![],
+                ResponseExtras::default(),
+            )])
         );
-        let request = make_user_search_request(
-            LdapFilter::Equality("memberOf".to_string(), "group_1".to_string()),
-            vec!["objectClass"],
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_request(add_request(), &[], &transaction_extras(&identifier))
+                .await,
+            Some(vec![(
+                make_add_error(LdapResultCode::Success, String::new()),
+                vec![],
+                ResponseExtras::default(),
+            )])
         );
+        let end_request = Element::sequence(vec![Element::octet_string(identifier)]);
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError {
-                code: LdapResultCode::InvalidDNSyntax,
-                message: "Missing DN value".to_string()
+            ldap_handler
+                .handle_ldap_request(
+                    LdapOp::ExtendedRequest(LdapExtendedRequest {
+                        name: END_TRANSACTION_OID.to_string(),
+                        value: Some(end_request.to_bytes()),
+                    }),
+                    &[],
+                    &extras,
+                )
+                .await,
+            Some(vec![(
+                make_extended_response(LdapResultCode::Success, String::new()),
+                vec![],
+                ResponseExtras::default(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_whoami() {
+        let whoami_request = || {
+            LdapOp::ExtendedRequest(LdapExtendedRequest {
+                name: WHOAMI_OID.to_string(),
+                value: None,
             })
+        };
+        let make_whoami_response = |authz_id: &str| {
+            Some(vec![LdapOp::ExtendedResponse(LdapExtendedResponse {
+                res: LdapResultOp {
+                    code: LdapResultCode::Success,
+                    matcheddn: "".to_string(),
+                    message: "".to_string(),
+                    referral: vec![],
+                },
+                name: None,
+                value: Some(authz_id.as_bytes().to_vec()),
+            })])
+        };
+        let mut ldap_handler = LdapHandler::new(
+            mock_backend_handler(),
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
         );
-        let request = make_user_search_request(
-            LdapFilter::Equality(
-                "memberOf".to_string(),
-                "cn=mygroup,dc=example,dc=com".to_string(),
-            ),
-            vec!["objectClass"],
+        assert_eq!(
+            ldap_handler.handle_ldap_message(whoami_request()).await,
+            make_whoami_response("")
         );
+        let mut ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::InvalidDNSyntax,
-                message: r#"Unexpected DN format. Got "cn=mygroup,dc=example,dc=com", expected: "uid=id,ou=groups,dc=example,dc=com""#.to_string()
-            })
+            ldap_handler.handle_ldap_message(whoami_request()).await,
+            make_whoami_response("dn:uid=test,ou=people,dc=example,dc=com")
         );
     }
 
     #[tokio::test]
-    async fn test_search_filters_lowercase() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users()
-            .with(
-                eq(Some(UserRequestFilter::And(vec![UserRequestFilter::Or(
-                    vec![UserRequestFilter::Not(Box::new(
-                        UserRequestFilter::Equality(UserColumn::FirstName, "bob".to_string()),
-                    ))],
-                )]))),
-                eq(false),
-            )
+    async fn test_password_change_unauthorized_password_manager() {
+        let mut mock = mock_backend_handler();
+        let mut groups = HashSet::new();
+        groups.insert(GroupDetails {
+            group_id: GroupId(0),
+            display_name: "lldap_admin".to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        });
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
             .times(1)
-            .return_once(|_, _| {
-                Ok(vec![UserAndGroups {
-                    user: User {
-                        user_id: UserId::new("bob_1"),
-                        ..Default::default()
-                    },
-                    groups: None,
-                }])
-            });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_user_search_request(
-            LdapFilter::And(vec![LdapFilter::Or(vec![LdapFilter::Not(Box::new(
-                LdapFilter::Equality("givenname".to_string(), "bob".to_string()),
-            ))])]),
-            vec!["objectclass"],
+            .return_once(|_| Ok(groups));
+        let mut ldap_handler = setup_bound_password_manager_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: Some("pass".to_string()),
+                new_password: Some("password".to_string()),
+            }
+            .into(),
         );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![LdapPartialAttribute {
-                        atype: "objectclass".to_string(),
-                        vals: vec![
-                            b"inetOrgPerson".to_vec(),
-                            b"posixAccount".to_vec(),
-                            b"mailAccount".to_vec(),
-                            b"person".to_vec()
-                        ]
-                    },]
-                }),
-                make_search_success()
-            ])
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify the password of user `bob`".to_string(),
+            )])
         );
     }
 
     #[tokio::test]
-    async fn test_search_both() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_list_users().times(1).return_once(|_, _| {
-            Ok(vec![UserAndGroups {
-                user: User {
-                    user_id: UserId::new("bob_1"),
-                    email: "bob@bobmail.bob".to_string(),
-                    display_name: Some("Bôb Böbberson".to_string()),
-                    first_name: Some("Bôb".to_string()),
-                    last_name: Some("Böbberson".to_string()),
-                    ..Default::default()
-                },
-                groups: None,
-            }])
-        });
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![]))))
+    async fn test_password_change_unauthorized_readonly() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
             .times(1)
-            .return_once(|_| {
-                Ok(vec![Group {
-                    id: GroupId(1),
-                    display_name: "group_1".to_string(),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    users: vec![UserId::new("bob"), UserId::new("john")],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                }])
-            });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectClass", "dn", "cn"],
+            .return_once(|_| Ok(HashSet::new()));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let request = LdapOp::ExtendedRequest(
+            LdapPasswordModifyRequest {
+                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
+                old_password: Some("pass".to_string()),
+                new_password: Some("password".to_string()),
+            }
+            .into(),
         );
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
-                    attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![
-                                b"inetOrgPerson".to_vec(),
-                                b"posixAccount".to_vec(),
-                                b"mailAccount".to_vec(),
-                                b"person".to_vec()
-                            ]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec!["Bôb Böbberson".to_string().into_bytes()]
-                        },
-                    ],
-                }),
-                LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                    dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                    attributes: vec![
-                        LdapPartialAttribute {
-                            atype: "objectClass".to_string(),
-                            vals: vec![b"groupOfUniqueNames".to_vec(),]
-                        },
-                        LdapPartialAttribute {
-                            atype: "cn".to_string(),
-                            vals: vec![b"group_1".to_vec()]
-                        },
-                    ],
-                }),
-                make_search_success(),
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_extended_response(
+                LdapResultCode::InsufficentAccessRights,
+                "User `test` cannot modify the password of user `bob`".to_string(),
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_root_dse() {
+        let mut ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
+        let request = LdapSearchRequest {
+            base: "".to_string(),
+            scope: LdapSearchScope::Base,
+            aliases: LdapDerefAliases::Never,
+            sizelimit: 0,
+            timelimit: 0,
+            typesonly: false,
+            filter: LdapFilter::Present("objectClass".to_string()),
+            attrs: vec!["supportedExtension".to_string()],
+        };
+        assert_eq!(
+            ldap_handler.do_search_or_dse(&request).await,
+            Ok(vec![
+                root_dse_response("dc=example,dc=com", SUPPORTED_SASL_MECHANISMS),
+                make_search_success()
             ])
         );
     }
 
-    #[tokio::test]
-    async fn test_search_wildcards() {
-        let mut mock = MockTestBackendHandler::new();
+    fn make_compare_request(dn: &str, atype: &str, val: &str) -> LdapOp {
+        LdapOp::CompareRequest(LdapCompareRequest {
+            dn: dn.to_string(),
+            atype: atype.to_string(),
+            val: val.as_bytes().to_vec(),
+        })
+    }
 
-        mock.expect_list_users().returning(|_, _| {
-            Ok(vec![UserAndGroups {
-                user: User {
-                    user_id: UserId::new("bob_1"),
-                    email: "bob@bobmail.bob".to_string(),
-                    display_name: Some("Bôb Böbberson".to_string()),
-                    last_name: Some("Böbberson".to_string()),
-                    avatar: Some(JpegPhoto::for_tests()),
-                    uuid: uuid!("b4ac75e0-2900-3e21-926c-2f732c26b3fc"),
-                    ..Default::default()
-                },
-                groups: None,
-            }])
-        });
-        mock.expect_list_groups()
-            .with(eq(Some(GroupRequestFilter::And(vec![]))))
-            .returning(|_| {
-                Ok(vec![Group {
-                    id: GroupId(1),
-                    display_name: "group_1".to_string(),
-                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-                    users: vec![UserId::new("bob"), UserId::new("john")],
-                    uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+    #[tokio::test]
+    async fn test_compare() {
+        let mut mock = mock_backend_handler();
+        let bob_filter = |filter| {
+            Some(UserRequestFilter::And(vec![
+                filter,
+                UserRequestFilter::UserId(UserId::new("bob")),
+            ]))
+        };
+        mock.expect_list_users()
+            .with(eq(bob_filter(UserRequestFilter::And(vec![]))), eq(false))
+            .times(2)
+            .returning(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
                 }])
             });
-        let mut ldap_handler = setup_bound_admin_handler(mock).await;
-
-        // Test simple wildcard
-        let request =
-            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec!["*", "+"]);
-
-        // all: "objectclass", "dn", "uid", "mail", "givenname", "sn", "cn"
-        // Operational: "createtimestamp"
-
-        let expected_result = Ok(vec![
-            LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                dn: "uid=bob_1,ou=people,dc=example,dc=com".to_string(),
-                attributes: vec![
-                    LdapPartialAttribute {
-                        atype: "objectclass".to_string(),
-                        vals: vec![
-                            b"inetOrgPerson".to_vec(),
-                            b"posixAccount".to_vec(),
-                            b"mailAccount".to_vec(),
-                            b"person".to_vec(),
-                        ],
-                    },
-                    LdapPartialAttribute {
-                        atype: "uid".to_string(),
-                        vals: vec![b"bob_1".to_vec()],
-                    },
-                    LdapPartialAttribute {
-                        atype: "mail".to_string(),
-                        vals: vec![b"bob@bobmail.bob".to_vec()],
-                    },
-                    LdapPartialAttribute {
-                        atype: "sn".to_string(),
-                        vals: vec!["Böbberson".to_string().into_bytes()],
-                    },
-                    LdapPartialAttribute {
-                        atype: "cn".to_string(),
-                        vals: vec!["Bôb Böbberson".to_string().into_bytes()],
-                    },
-                    LdapPartialAttribute {
-                        atype: "jpegPhoto".to_string(),
-                        vals: vec![JpegPhoto::for_tests().into_bytes()],
-                    },
-                    LdapPartialAttribute {
-                        atype: "createtimestamp".to_string(),
-                        vals: vec![chrono::Utc
-                            .timestamp_opt(0, 0)
-                            .unwrap()
-                            .to_rfc3339()
-                            .into_bytes()],
-                    },
-                    LdapPartialAttribute {
-                        atype: "entryuuid".to_string(),
-                        vals: vec![b"b4ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
-                    },
-                ],
-            }),
-            // "objectclass", "dn", "uid", "cn", "member", "uniquemember"
-            LdapOp::SearchResultEntry(LdapSearchResultEntry {
-                dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
-                attributes: vec![
-                    LdapPartialAttribute {
-                        atype: "objectclass".to_string(),
-                        vals: vec![b"groupOfUniqueNames".to_vec()],
-                    },
-                    // UID
-                    LdapPartialAttribute {
-                        atype: "uid".to_string(),
-                        vals: vec![b"group_1".to_vec()],
-                    },
-                    LdapPartialAttribute {
-                        atype: "cn".to_string(),
-                        vals: vec![b"group_1".to_vec()],
-                    },
-                    //member / uniquemember : "uid={},ou=people,{}"
-                    LdapPartialAttribute {
-                        atype: "member".to_string(),
-                        vals: vec![
-                            b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
-                            b"uid=john,ou=people,dc=example,dc=com".to_vec(),
-                        ],
-                    },
-                    LdapPartialAttribute {
-                        atype: "uniquemember".to_string(),
-                        vals: vec![
-                            b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
-                            b"uid=john,ou=people,dc=example,dc=com".to_vec(),
-                        ],
-                    },
-                    LdapPartialAttribute {
-                        atype: "entryuuid".to_string(),
-                        vals: vec![b"04ac75e0-2900-3e21-926c-2f732c26b3fc".to_vec()],
+        mock.expect_list_users()
+            .with(
+                eq(bob_filter(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "bob@bob.bob".to_string(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
                     },
-                ],
-            }),
-            make_search_success(),
-        ]);
-
+                    groups: None,
+                }])
+            });
+        mock.expect_list_users()
+            .with(
+                eq(bob_filter(UserRequestFilter::Equality(
+                    UserColumn::Email,
+                    "alice@bob.bob".to_string(),
+                ))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        mock.expect_list_users()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![
+                    UserRequestFilter::And(vec![]),
+                    UserRequestFilter::UserId(UserId::new("nobody")),
+                ]))),
+                eq(false),
+            )
+            .times(1)
+            .return_once(|_, _| Ok(vec![]));
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let compare = |atype: &str, val: &str| {
+            make_compare_request("uid=bob,ou=people,dc=example,dc=com", atype, val)
+        };
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            expected_result
-        );
-
-        let request2 = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectclass", "obJEctclaSS", "dn", "*", "*"],
+            ldap_handler
+                .handle_ldap_message(compare("mail", "bob@bob.bob"))
+                .await,
+            Some(vec![make_compare_response(
+                LdapResultCode::CompareTrue,
+                String::new()
+            )])
         );
-
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request2).await,
-            expected_result
+            ldap_handler
+                .handle_ldap_message(compare("mail", "alice@bob.bob"))
+                .await,
+            Some(vec![make_compare_response(
+                LdapResultCode::CompareFalse,
+                String::new()
+            )])
         );
-
-        let request3 = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["*", "+", "+"],
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_compare_request(
+                    "uid=nobody,ou=people,dc=example,dc=com",
+                    "mail",
+                    "bob@bob.bob"
+                ))
+                .await,
+            Some(vec![make_compare_response(
+                LdapResultCode::NoSuchObject,
+                "No such entry: uid=nobody,ou=people,dc=example,dc=com".to_string()
+            )])
         );
+    }
 
+    #[tokio::test]
+    async fn test_compare_password() {
+        let mut mock = mock_backend_handler();
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "right".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_bind()
+            .with(eq(BindRequest {
+                name: UserId::new("bob"),
+                password: "wrong".to_string(),
+            }))
+            .times(1)
+            .return_once(|_| {
+                Err(crate::domain::error::DomainError::AuthenticationError(
+                    "wrong".to_string(),
+                ))
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let compare = |password: &str| {
+            make_compare_request(
+                "uid=bob,ou=people,dc=example,dc=com",
+                "userPassword",
+                password,
+            )
+        };
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request3).await,
-            expected_result
+            ldap_handler.handle_ldap_message(compare("right")).await,
+            Some(vec![make_compare_response(
+                LdapResultCode::CompareTrue,
+                String::new()
+            )])
         );
-
-        let request4 =
-            make_search_request("dc=example,dc=com", LdapFilter::And(vec![]), vec![""; 0]);
-
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request4).await,
-            expected_result
+            ldap_handler.handle_ldap_message(compare("wrong")).await,
+            Some(vec![make_compare_response(
+                LdapResultCode::CompareFalse,
+                String::new()
+            )])
         );
-
-        let request5 = make_search_request(
-            "dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectclass", "dn", "uid", "*"],
+        // The session is still bound as the same user.
+        assert_eq!(
+            ldap_handler.user_info.as_ref().map(|u| u.user.clone()),
+            Some(UserId::new("test"))
         );
+    }
 
+    #[tokio::test]
+    async fn test_compare_password_requires_privileges() {
+        let mut ldap_handler =
+            setup_bound_handler_with_group(mock_backend_handler(), "regular").await;
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request5).await,
-            expected_result
+            ldap_handler
+                .handle_ldap_message(make_compare_request(
+                    "uid=bob,ou=people,dc=example,dc=com",
+                    "userPassword",
+                    "pass"
+                ))
+                .await,
+            Some(vec![make_compare_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Only the admins and read-only users can compare passwords".to_string()
+            )])
         );
     }
 
+    fn make_sync_request(cookie: Option<Vec<u8>>) -> Vec<LdapControl> {
+        vec![LdapControl::SyncRequest {
+            criticality: true,
+            mode: SyncRequestMode::RefreshOnly,
+            cookie,
+            reload_hint: false,
+        }]
+    }
+
     #[tokio::test]
-    async fn test_search_wrong_base() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_search_request(
-            "ou=users,dc=example,dc=com",
-            LdapFilter::And(vec![]),
-            vec!["objectClass"],
+    async fn test_sync_refresh() {
+        let bob_uuid = uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da");
+        let group_uuid = uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc");
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users().times(4).returning(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob"),
+                    display_name: Some("Bob".to_string()),
+                    uuid: uuid!("698e1d5f-7a40-3151-8745-b9b8a37839da"),
+                    ..Default::default()
+                },
+                groups: Some(vec![]),
+            }])
+        });
+        mock.expect_list_groups().times(4).returning(|_| {
+            Ok(vec![Group {
+                id: GroupId(1),
+                display_name: "group_1".to_string(),
+                creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                users: vec![UserId::new("bob")],
+                uuid: uuid!("04ac75e0-2900-3e21-926c-2f732c26b3fc"),
+                archived: false,
+                dynamic_filter: None,
+                gid_number: None,
+                email: None,
+                attributes: Vec::new(),
+            }])
+        });
+        let sync_entries = SyncEntries {
+            change_sequence: 2,
+            entries: [(bob_uuid.clone(), 2), (group_uuid.clone(), 1)]
+                .into_iter()
+                .collect(),
+        };
+        let returned_entries = sync_entries.clone();
+        mock.expect_record_sync_entries()
+            .times(2)
+            .returning(move |digests| {
+                assert_eq!(digests.len(), 2);
+                Ok(returned_entries.clone())
+            });
+        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
+        let mut request = make_search_request(
+            "dc=example,dc=com",
+            LdapFilter::Present("objectClass".to_string()),
+            vec!["cn"],
+        );
+        request.scope = LdapSearchScope::Subtree;
+        let sync_state = |state, uuid: &Uuid| {
+            vec![LdapControl::SyncState {
+                state,
+                entry_uuid: uuid::Uuid::parse_str(uuid.as_str()).unwrap(),
+                cookie: None,
+            }]
+        };
+        let sync_done = || {
+            vec![LdapControl::SyncDone {
+                cookie: Some(make_cookie(2)),
+                refresh_deletes: false,
+            }]
+        };
+        let bob_entry = |attributes| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                attributes,
+            })
+        };
+        let group_entry = |attributes| {
+            LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                dn: "cn=group_1,ou=groups,dc=example,dc=com".to_string(),
+                attributes,
+            })
+        };
+        // Without a cookie, everything is sent.
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message_with_controls(
+                    LdapOp::SearchRequest(request.clone()),
+                    &make_sync_request(None)
+                )
+                .await,
+            Some(vec![
+                (
+                    bob_entry(vec![LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"Bob".to_vec()]
+                    }]),
+                    sync_state(SyncStateValue::Add, &bob_uuid)
+                ),
+                (
+                    group_entry(vec![LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"group_1".to_vec()]
+                    }]),
+                    sync_state(SyncStateValue::Add, &group_uuid)
+                ),
+                (make_search_success(), sync_done()),
+            ])
         );
+        // The group didn't change since the first change sequence number.
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![make_search_success()])
+            ldap_handler
+                .handle_ldap_message_with_controls(
+                    LdapOp::SearchRequest(request),
+                    &make_sync_request(Some(make_cookie(1)))
+                )
+                .await,
+            Some(vec![
+                (
+                    bob_entry(vec![LdapPartialAttribute {
+                        atype: "cn".to_string(),
+                        vals: vec![b"Bob".to_vec()]
+                    }]),
+                    sync_state(SyncStateValue::Add, &bob_uuid)
+                ),
+                (
+                    group_entry(vec![]),
+                    sync_state(SyncStateValue::Present, &group_uuid)
+                ),
+                (make_search_success(), sync_done()),
+            ])
         );
     }
 
     #[tokio::test]
-    async fn test_search_unsupported_filters() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
-        let request = make_user_search_request(
-            LdapFilter::Substring(
-                "uid".to_string(),
-                ldap3_proto::proto::LdapSubstringFilter::default(),
-            ),
-            vec!["objectClass"],
-        );
+    async fn test_sync_refresh_requires_privileges() {
+        let mut ldap_handler =
+            setup_bound_handler_with_group(mock_backend_handler(), "regular").await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["cn"]);
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Err(LdapError{
-                code: LdapResultCode::UnwillingToPerform,
-                message: r#"Unsupported user filter: Substring("uid", LdapSubstringFilter { initial: None, any: [], final_: None })"#.to_string()
-            })
+            ldap_handler
+                .handle_ldap_message_with_controls(
+                    LdapOp::SearchRequest(request),
+                    &make_sync_request(None)
+                )
+                .await,
+            Some(vec![(
+                make_search_error(
+                    LdapResultCode::InsufficentAccessRights,
+                    "Only the admins and read-only users can synchronize the directory".to_string()
+                ),
+                vec![]
+            )])
         );
     }
 
+    fn make_paged_extras(size: i64, cookie: Vec<u8>) -> RequestExtras {
+        RequestExtras {
+            controls: vec![RawControl::new(
+                PAGED_RESULTS_OID,
+                Element::sequence(vec![Element::integer(size), Element::octet_string(cookie)]),
+            )],
+            sasl: None,
+        }
+    }
+
     #[tokio::test]
-    async fn test_password_change() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("bob")))
-            .returning(|_| Ok(HashSet::new()));
-        use lldap_auth::*;
-        let mut rng = rand::rngs::OsRng;
-        let registration_start_request =
-            opaque::client::registration::start_registration("password", &mut rng).unwrap();
-        let request = registration::ClientRegistrationStartRequest {
-            username: "bob".to_string(),
-            registration_start_request: registration_start_request.message,
-        };
-        let start_response = opaque::server::registration::start_registration(
-            &opaque::server::ServerSetup::new(&mut rng),
-            request.registration_start_request,
-            &request.username,
-        )
-        .unwrap();
-        mock.expect_registration_start().times(1).return_once(|_| {
-            Ok(registration::ServerRegistrationStartResponse {
-                server_data: "".to_string(),
-                registration_response: start_response.message,
-            })
+    async fn test_paged_search() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users().times(2).returning(|_, _| {
+            Ok(["bob", "john", "jim"]
+                .iter()
+                .map(|id| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(id),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
         });
-        mock.expect_registration_finish()
-            .times(1)
-            .return_once(|_| Ok(()));
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = LdapOp::ExtendedRequest(
-            LdapPasswordModifyRequest {
-                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
-                old_password: None,
-                new_password: Some("password".to_string()),
-            }
-            .into(),
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
+        let get_dns = |responses: &[(LdapOp, Vec<LdapControl>, ResponseExtras)]| {
+            responses
+                .iter()
+                .filter_map(|(op, _, _)| match op {
+                    LdapOp::SearchResultEntry(entry) => Some(entry.dn.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let first_page = ldap_handler
+            .handle_ldap_request(
+                LdapOp::SearchRequest(request.clone()),
+                &[],
+                &make_paged_extras(2, Vec::new()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&first_page),
+            vec![
+                "uid=bob,ou=people,dc=example,dc=com",
+                "uid=jim,ou=people,dc=example,dc=com"
+            ]
         );
+        let cookie = make_page_cookie(&request, "uid=jim,ou=people,dc=example,dc=com");
         assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_extended_response(
-                LdapResultCode::Success,
-                "".to_string(),
-            )])
+            first_page.last(),
+            Some(&(
+                make_search_success(),
+                vec![],
+                ResponseExtras::with_control(RawControl::new(
+                    PAGED_RESULTS_OID,
+                    make_paged_results_response(3, cookie.clone())
+                ))
+            ))
+        );
+        let second_page = ldap_handler
+            .handle_ldap_request(
+                LdapOp::SearchRequest(request.clone()),
+                &[],
+                &make_paged_extras(2, cookie),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            get_dns(&second_page),
+            vec!["uid=john,ou=people,dc=example,dc=com"]
+        );
+        assert_eq!(
+            second_page.last(),
+            Some(&(
+                make_search_success(),
+                vec![],
+                ResponseExtras::with_control(RawControl::new(
+                    PAGED_RESULTS_OID,
+                    make_paged_results_response(3, Vec::new())
+                ))
+            ))
         );
     }
 
     #[tokio::test]
-    async fn test_password_change_password_manager() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("bob")))
-            .returning(|_| Ok(HashSet::new()));
-        use lldap_auth::*;
-        let mut rng = rand::rngs::OsRng;
-        let registration_start_request =
-            opaque::client::registration::start_registration("password", &mut rng).unwrap();
-        let request = registration::ClientRegistrationStartRequest {
-            username: "bob".to_string(),
-            registration_start_request: registration_start_request.message,
-        };
-        let start_response = opaque::server::registration::start_registration(
-            &opaque::server::ServerSetup::new(&mut rng),
-            request.registration_start_request,
-            &request.username,
-        )
-        .unwrap();
-        mock.expect_registration_start().times(1).return_once(|_| {
-            Ok(registration::ServerRegistrationStartResponse {
-                server_data: "".to_string(),
-                registration_response: start_response.message,
-            })
+    async fn test_paged_search_with_concurrent_writes() {
+        let mut mock = mock_backend_handler();
+        let mut calls = 0;
+        mock.expect_list_users().times(2).returning(move |_, _| {
+            calls += 1;
+            // Alice and Kate are created between the two pages, before and after the cut.
+            let users: &[&str] = if calls == 1 {
+                &["bob", "john", "jim"]
+            } else {
+                &["kate", "bob", "alice", "john", "jim"]
+            };
+            Ok(users
+                .iter()
+                .map(|id| UserAndGroups {
+                    user: User {
+                        user_id: UserId::new(id),
+                        ..Default::default()
+                    },
+                    groups: None,
+                })
+                .collect())
         });
-        mock.expect_registration_finish()
-            .times(1)
-            .return_once(|_| Ok(()));
-        let mut ldap_handler = setup_bound_password_manager_handler(mock).await;
-        let request = LdapOp::ExtendedRequest(
-            LdapPasswordModifyRequest {
-                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
-                old_password: None,
-                new_password: Some("password".to_string()),
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
+        let mut dns = Vec::new();
+        let mut cookie = Vec::new();
+        for _ in 0..2 {
+            let page = ldap_handler
+                .handle_ldap_request(
+                    LdapOp::SearchRequest(request.clone()),
+                    &[],
+                    &make_paged_extras(2, cookie),
+                )
+                .await
+                .unwrap();
+            for (op, _, _) in &page {
+                if let LdapOp::SearchResultEntry(entry) = op {
+                    dns.push(entry.dn.clone());
+                }
             }
-            .into(),
-        );
+            let value = page.last().unwrap().2.controls[0].value.clone().unwrap();
+            cookie = parse_paged_results_request(Some(&value)).unwrap().cookie;
+        }
         assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_extended_response(
-                LdapResultCode::Success,
-                "".to_string(),
-            )])
+            dns,
+            vec![
+                "uid=bob,ou=people,dc=example,dc=com",
+                "uid=jim,ou=people,dc=example,dc=com",
+                "uid=john,ou=people,dc=example,dc=com",
+                "uid=kate,ou=people,dc=example,dc=com",
+            ]
         );
+        assert!(cookie.is_empty());
+    }
+
+    fn make_sort_extras(attribute: &str, criticality: bool) -> RequestExtras {
+        let key = Element::sequence(vec![
+            Element::octet_string(attribute),
+            Element::primitive(TagClass::Context, 1, vec![0xFF]),
+        ]);
+        let mut control = RawControl::new(SORT_REQUEST_OID, Element::sequence(vec![key]));
+        control.criticality = criticality;
+        RequestExtras {
+            controls: vec![control],
+            sasl: None,
+        }
     }
 
     #[tokio::test]
-    async fn test_password_change_errors() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("bob")))
-            .returning(|_| Ok(HashSet::new()));
+    async fn test_sorted_search() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_users_sorted()
+            .with(
+                eq(Some(UserRequestFilter::And(vec![]))),
+                eq(false),
+                eq(vec![SortKey {
+                    column: UserColumn::CreationDate,
+                    descending: true,
+                }]),
+            )
+            .times(1)
+            .return_once(|_, _, _| {
+                Ok(vec![UserAndGroups {
+                    user: User {
+                        user_id: UserId::new("bob"),
+                        ..Default::default()
+                    },
+                    groups: None,
+                }])
+            });
         let mut ldap_handler = setup_bound_admin_handler(mock).await;
-        let request = LdapOp::ExtendedRequest(
-            LdapPasswordModifyRequest {
-                user_identity: None,
-                old_password: None,
-                new_password: None,
-            }
-            .into(),
-        );
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
         assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_extended_response(
-                LdapResultCode::ConstraintViolation,
-                "Missing either user_id or password".to_string(),
-            )])
-        );
-        let request = LdapOp::ExtendedRequest(
-            LdapPasswordModifyRequest {
-                user_identity: Some("uid=bob,ou=groups,ou=people,dc=example,dc=com".to_string()),
-                old_password: None,
-                new_password: Some("password".to_string()),
-            }
-            .into(),
+            ldap_handler
+                .handle_ldap_request(
+                    LdapOp::SearchRequest(request),
+                    &[],
+                    &make_sort_extras("createTimestamp", true)
+                )
+                .await,
+            Some(vec![
+                (
+                    LdapOp::SearchResultEntry(LdapSearchResultEntry {
+                        dn: "uid=bob,ou=people,dc=example,dc=com".to_string(),
+                        attributes: vec![],
+                    }),
+                    vec![],
+                    ResponseExtras::default()
+                ),
+                (
+                    make_search_success(),
+                    vec![],
+                    ResponseExtras::with_control(RawControl::new(
+                        SORT_RESPONSE_OID,
+                        make_sort_response(SORT_SUCCESS, None)
+                    ))
+                )
+            ])
         );
-        assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_extended_response(
-                LdapResultCode::InvalidDNSyntax,
-                r#"Invalid username: Unexpected DN format. Got "uid=bob,ou=groups,ou=people,dc=example,dc=com", expected: "uid=id,ou=people,dc=example,dc=com""#.to_string(),
-            )])
+    }
+
+    #[tokio::test]
+    async fn test_sorted_search_of_groups() {
+        let mut ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
+        let request = make_search_request(
+            "ou=groups,dc=example,dc=com",
+            LdapFilter::And(vec![]),
+            vec!["1.1"],
         );
-        let request = LdapOp::ExtendedRequest(LdapExtendedRequest {
-            name: "test".to_string(),
-            value: None,
-        });
         assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_extended_response(
-                LdapResultCode::UnwillingToPerform,
-                "Unsupported extended operation: test".to_string(),
+            ldap_handler
+                .handle_ldap_request(
+                    LdapOp::SearchRequest(request),
+                    &[],
+                    &make_sort_extras("cn", true)
+                )
+                .await,
+            Some(vec![(
+                make_search_error(
+                    LdapResultCode::UnwillingToPerform,
+                    "The results of this search can't be sorted".to_string()
+                ),
+                vec![],
+                ResponseExtras {
+                    controls: vec![RawControl::new(
+                        SORT_RESPONSE_OID,
+                        make_sort_response(SORT_UNWILLING_TO_PERFORM, None)
+                    )],
+                    result_code: Some(UNAVAILABLE_CRITICAL_EXTENSION),
+                }
             )])
         );
     }
 
     #[tokio::test]
-    async fn test_password_change_unauthorized_password_manager() {
-        let mut mock = MockTestBackendHandler::new();
-        let mut groups = HashSet::new();
-        groups.insert(GroupDetails {
-            group_id: GroupId(0),
-            display_name: "lldap_admin".to_string(),
-            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
-            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
-        });
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("bob")))
+    async fn test_virtual_list_view() {
+        let mut mock = mock_backend_handler();
+        let filter = Some(UserRequestFilter::And(vec![
+            UserRequestFilter::And(vec![]),
+            UserRequestFilter::Enabled(true),
+        ]));
+        let make_page = |offset, limit| PageRequest {
+            order_by: UserColumn::CreationDate,
+            descending: true,
+            offset,
+            limit,
+        };
+        mock.expect_list_users_page()
+            .with(eq(filter.clone()), eq(make_page(0, 0)))
             .times(1)
-            .return_once(|_| Ok(groups));
-        let mut ldap_handler = setup_bound_password_manager_handler(mock).await;
-        let request = LdapOp::ExtendedRequest(
-            LdapPasswordModifyRequest {
-                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
-                old_password: Some("pass".to_string()),
-                new_password: Some("password".to_string()),
-            }
-            .into(),
+            .return_once(|_, _| {
+                Ok(Page {
+                    items: vec![],
+                    total_count: 1000,
+                })
+            });
+        mock.expect_list_users_page()
+            .with(eq(filter), eq(make_page(498, 3)))
+            .times(1)
+            .return_once(|_, _| {
+                Ok(Page {
+                    items: ["bob", "john", "jim"]
+                        .iter()
+                        .map(|id| User {
+                            user_id: UserId::new(id),
+                            ..Default::default()
+                        })
+                        .collect(),
+                    total_count: 1000,
+                })
+            });
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
+        let mut extras = make_sort_extras("createTimestamp", true);
+        extras.controls.push(RawControl::new(
+            VLV_REQUEST_OID,
+            Element::sequence(vec![
+                Element::integer(1),
+                Element::integer(1),
+                Element::constructed(
+                    TagClass::Context,
+                    0,
+                    vec![Element::integer(500), Element::integer(0)],
+                ),
+            ]),
+        ));
+        let responses = ldap_handler
+            .handle_ldap_request(LdapOp::SearchRequest(request), &[], &extras)
+            .await
+            .unwrap();
+        assert_eq!(responses.len(), 4);
+        assert_eq!(
+            responses.last(),
+            Some(&(
+                make_search_success(),
+                vec![],
+                ResponseExtras {
+                    controls: vec![
+                        RawControl::new(
+                            VLV_RESPONSE_OID,
+                            make_vlv_response(500, 1000, VLV_SUCCESS)
+                        ),
+                        RawControl::new(SORT_RESPONSE_OID, make_sort_response(SORT_SUCCESS, None)),
+                    ],
+                    result_code: None,
+                }
+            ))
         );
+    }
+
+    #[tokio::test]
+    async fn test_virtual_list_view_without_sorting() {
+        let mut ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
+        let extras = RequestExtras {
+            controls: vec![RawControl::new(
+                VLV_REQUEST_OID,
+                Element::sequence(vec![
+                    Element::integer(0),
+                    Element::integer(10),
+                    Element::constructed(
+                        TagClass::Context,
+                        0,
+                        vec![Element::integer(1), Element::integer(0)],
+                    ),
+                ]),
+            )],
+            sasl: None,
+        };
         assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_extended_response(
-                LdapResultCode::InsufficentAccessRights,
-                "User `test` cannot modify the password of user `bob`".to_string(),
+            ldap_handler
+                .handle_ldap_request(LdapOp::SearchRequest(request), &[], &extras)
+                .await,
+            Some(vec![make_vlv_error(
+                "The virtual list view needs the server-side sorting control",
+                VLV_SORT_CONTROL_MISSING
             )])
         );
     }
 
     #[tokio::test]
-    async fn test_password_change_unauthorized_readonly() {
-        let mut mock = MockTestBackendHandler::new();
-        mock.expect_get_user_groups()
-            .with(eq(UserId::new("bob")))
-            .times(1)
-            .return_once(|_| Ok(HashSet::new()));
-        let mut ldap_handler = setup_bound_readonly_handler(mock).await;
-        let request = LdapOp::ExtendedRequest(
-            LdapPasswordModifyRequest {
-                user_identity: Some("uid=bob,ou=people,dc=example,dc=com".to_string()),
-                old_password: Some("pass".to_string()),
-                new_password: Some("password".to_string()),
-            }
-            .into(),
-        );
+    async fn test_unsupported_critical_control() {
+        let mut ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
+        let request = make_user_search_request(LdapFilter::And(vec![]), vec!["1.1"]);
+        let extras = RequestExtras {
+            controls: vec![RawControl {
+                oid: "1.2.3.4".to_string(),
+                criticality: true,
+                value: None,
+            }],
+            sasl: None,
+        };
         assert_eq!(
-            ldap_handler.handle_ldap_message(request).await,
-            Some(vec![make_extended_response(
-                LdapResultCode::InsufficentAccessRights,
-                "User `test` cannot modify the password of user `bob`".to_string(),
+            ldap_handler
+                .handle_ldap_request(LdapOp::SearchRequest(request), &[], &extras)
+                .await,
+            Some(vec![(
+                make_search_error(
+                    LdapResultCode::UnwillingToPerform,
+                    "Unsupported critical control: 1.2.3.4".to_string()
+                ),
+                vec![],
+                ResponseExtras {
+                    controls: vec![],
+                    result_code: Some(UNAVAILABLE_CRITICAL_EXTENSION),
+                }
             )])
         );
     }
 
     #[tokio::test]
-    async fn test_search_root_dse() {
-        let mut ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+    async fn test_abandon_has_no_response() {
+        let mut ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(LdapOp::AbandonRequest(3))
+                .await,
+            Some(vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_subschema() {
+        let mut ldap_handler = LdapHandler::new(
+            mock_backend_handler(),
+            "dc=example,dc=com".to_string(),
+            vec![],
+            vec![],
+        );
         let request = LdapSearchRequest {
-            base: "".to_string(),
+            base: "cn=subschema".to_string(),
             scope: LdapSearchScope::Base,
             aliases: LdapDerefAliases::Never,
             sizelimit: 0,
             timelimit: 0,
             typesonly: false,
-            filter: LdapFilter::Present("objectClass".to_string()),
-            attrs: vec!["supportedExtension".to_string()],
+            filter: LdapFilter::Equality("objectClass".to_string(), "subschema".to_string()),
+            attrs: vec!["objectClasses".to_string(), "cn".to_string()],
+        };
+        let results = ldap_handler.do_search_or_dse(&request).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1], make_search_success());
+        let entry = match &results[0] {
+            LdapOp::SearchResultEntry(entry) => entry,
+            op => panic!("Expected an entry, got {:?}", op),
         };
+        assert_eq!(entry.dn, "cn=Subschema");
+        assert_eq!(entry.attributes.len(), 2);
+        assert_eq!(entry.attributes[0].atype, "objectClasses");
+        assert!(entry.attributes[0]
+            .vals
+            .iter()
+            .any(|v| v.starts_with(b"( 2.16.840.1.113730.3.2.2 NAME 'inetOrgPerson'")));
         assert_eq!(
-            ldap_handler.do_search_or_dse(&request).await,
-            Ok(vec![
-                root_dse_response("dc=example,dc=com"),
-                make_search_success()
-            ])
+            entry.attributes[1],
+            LdapPartialAttribute {
+                atype: "cn".to_string(),
+                vals: vec![b"Subschema".to_vec()],
+            }
         );
     }
 
     #[tokio::test]
     async fn test_create_user() {
-        let mut mock = MockTestBackendHandler::new();
+        let mut mock = mock_backend_handler();
         mock.expect_create_user()
             .with(eq(CreateUserRequest {
                 user_id: UserId::new("bob"),
@@ -2060,9 +7632,62 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_create_user_group_assignment_rules() {
+        let mut mock = mock_backend_handler();
+        mock.expect_create_user()
+            .with(eq(CreateUserRequest {
+                user_id: UserId::new("bob"),
+                email: "".to_owned(),
+                avatar: Some(JpegPhoto::for_tests()),
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| Ok(HashSet::new()));
+        mock.expect_list_users().times(1).return_once(|_, _| {
+            Ok(vec![UserAndGroups {
+                user: User {
+                    user_id: UserId::new("bob"),
+                    ..Default::default()
+                },
+                groups: None,
+            }])
+        });
+        mock.expect_list_groups()
+            .with(eq(Some(GroupRequestFilter::DisplayName(
+                "Family".to_owned(),
+            ))))
+            .times(1)
+            .return_once(|_| Ok(vec![make_family_group(vec![])]));
+        mock.expect_add_user_to_group()
+            .with(eq(UserId::new("bob")), eq(GroupId(5)))
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.set_group_assignment_rules(vec![GroupAssignmentRule {
+            filter: "(objectClass=person)".to_owned(),
+            group: "Family".to_owned(),
+        }]);
+        let request = LdapAddRequest {
+            dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+            attributes: vec![LdapPartialAttribute {
+                atype: "jpegPhoto".to_owned(),
+                vals: vec![JpegPhoto::for_tests().into_bytes()],
+            }],
+        };
+        assert_eq!(
+            ldap_handler.do_create_user(request).await,
+            Ok(vec![make_add_error(LdapResultCode::Success, String::new())])
+        );
+    }
+
     #[tokio::test]
     async fn test_create_user_wrong_ou() {
-        let ldap_handler = setup_bound_admin_handler(MockTestBackendHandler::new()).await;
+        let ldap_handler = setup_bound_admin_handler(mock_backend_handler()).await;
         let request = LdapAddRequest {
             dn: "uid=bob,ou=groups,dc=example,dc=com".to_owned(),
             attributes: vec![LdapPartialAttribute {
@@ -2078,7 +7703,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_search_filter_non_attribute() {
-        let mut mock = MockTestBackendHandler::new();
+        let mut mock = mock_backend_handler();
         mock.expect_list_users()
             .with(eq(Some(UserRequestFilter::And(vec![]))), eq(false))
             .times(1)
@@ -2093,4 +7718,434 @@ mod tests {
             Ok(vec![make_search_success()])
         );
     }
+
+    fn make_family_group(users: Vec<UserId>) -> Group {
+        Group {
+            id: GroupId(5),
+            display_name: "Family".to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            users,
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_group() {
+        let mut mock = mock_backend_handler();
+        mock.expect_create_group_with_members()
+            .withf(|name, user_ids| name == "Family" && user_ids == [UserId::new("bob")])
+            .times(1)
+            .return_once(|_, _| Ok(GroupId(5)));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::AddRequest(LdapAddRequest {
+            dn: "cn=Family,ou=groups,dc=example,dc=com".to_owned(),
+            attributes: vec![
+                LdapPartialAttribute {
+                    atype: "objectClass".to_owned(),
+                    vals: vec![b"groupOfUniqueNames".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "cn".to_owned(),
+                    vals: vec![b"Family".to_vec()],
+                },
+                LdapPartialAttribute {
+                    atype: "uniqueMember".to_owned(),
+                    vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()],
+                },
+            ],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_add_error(LdapResultCode::Success, String::new())])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_group_group_limit() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_groups()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| {
+                Ok([GroupDetails {
+                    group_id: GroupId(3),
+                    display_name: "Friends".to_string(),
+                    creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+                    uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+                    archived: false,
+                    dynamic_filter: None,
+                    gid_number: None,
+                    email: None,
+                }]
+                .into())
+            });
+        mock.expect_create_group_with_members().never();
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.set_membership_rules(
+            None,
+            false,
+            Some(GroupLimit {
+                max_groups: 1,
+                exempt_admins: false,
+            }),
+        );
+        let request = LdapOp::AddRequest(LdapAddRequest {
+            dn: "cn=Family,ou=groups,dc=example,dc=com".to_owned(),
+            attributes: vec![LdapPartialAttribute {
+                atype: "member".to_owned(),
+                vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()],
+            }],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_add_error(
+                LdapResultCode::ConstraintViolation,
+                "bob is already a member of 1 groups, the maximum is 1".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_user() {
+        let mut mock = mock_backend_handler();
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                email: Some("bob@example.com".to_string()),
+                display_name: Some("Bob".to_string()),
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let make_request = |atype: &str, vals: Vec<Vec<u8>>| {
+            LdapOp::ModifyRequest(LdapModifyRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                changes: vec![
+                    LdapModify {
+                        operation: LdapModifyType::Replace,
+                        modification: LdapPartialAttribute {
+                            atype: "mail".to_owned(),
+                            vals: vec![b"bob@example.com".to_vec()],
+                        },
+                    },
+                    LdapModify {
+                        operation: LdapModifyType::Replace,
+                        modification: LdapPartialAttribute {
+                            atype: atype.to_owned(),
+                            vals,
+                        },
+                    },
+                ],
+            })
+        };
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request("cn", vec![b"Bob".to_vec()]))
+                .await,
+            Some(vec![make_modify_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+        // The request is refused as a whole: the email is not updated.
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request("userPassword", vec![b"pass".to_vec()]))
+                .await,
+            Some(vec![make_modify_response(
+                LdapResultCode::UnwillingToPerform,
+                "Use the password modify extended operation to change passwords".to_string()
+            )])
+        );
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request("sn", vec![]))
+                .await,
+            Some(vec![make_modify_response(
+                LdapResultCode::ConstraintViolation,
+                "Expected a single new value for attribute sn".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_user_ssh_public_keys() {
+        let mut mock = mock_backend_handler();
+        mock.expect_get_user_details()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| {
+                Ok(User {
+                    user_id: UserId::new("bob"),
+                    ssh_public_keys: vec!["ssh-rsa AAAA".to_string()],
+                    ..Default::default()
+                })
+            });
+        mock.expect_update_user()
+            .with(eq(UpdateUserRequest {
+                user_id: UserId::new("bob"),
+                ssh_public_keys: Some(vec!["ssh-ed25519 BBBB bob@laptop".to_string()]),
+                ..Default::default()
+            }))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let make_change = |operation, val: &str| LdapModify {
+            operation,
+            modification: LdapPartialAttribute {
+                atype: "sshPublicKey".to_owned(),
+                vals: vec![val.as_bytes().to_vec()],
+            },
+        };
+        let make_request = |changes| {
+            LdapOp::ModifyRequest(LdapModifyRequest {
+                dn: "uid=bob,ou=people,dc=example,dc=com".to_owned(),
+                changes,
+            })
+        };
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request(vec![
+                    make_change(LdapModifyType::Add, "ssh-ed25519 BBBB bob@laptop"),
+                    make_change(LdapModifyType::Delete, "ssh-rsa AAAA"),
+                ]))
+                .await,
+            Some(vec![make_modify_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request(vec![make_change(
+                    LdapModifyType::Add,
+                    "not a key"
+                )]))
+                .await,
+            Some(vec![make_modify_response(
+                LdapResultCode::ConstraintViolation,
+                "Invalid SSH public key: Unknown SSH key type `not`".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_members() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups().with(eq(None)).return_once(|_| {
+            Ok(vec![make_family_group(vec![
+                UserId::new("bob"),
+                UserId::new("john"),
+            ])])
+        });
+        mock.expect_set_group_members()
+            .withf(|group_id, user_ids| {
+                *group_id == GroupId(5) && user_ids == [UserId::new("bob"), UserId::new("patrick")]
+            })
+            .times(1)
+            .return_once(|_, _| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "cn=family,ou=groups,dc=example,dc=com".to_owned(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Replace,
+                modification: LdapPartialAttribute {
+                    atype: "member".to_owned(),
+                    vals: vec![
+                        b"uid=bob,ou=people,dc=example,dc=com".to_vec(),
+                        b"uid=patrick,ou=people,dc=example,dc=com".to_vec(),
+                    ],
+                },
+            }],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_group_strict_adds() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(None))
+            .return_once(|_| Ok(vec![make_family_group(vec![UserId::new("bob")])]));
+        mock.expect_set_group_members().never();
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.set_membership_rules(None, true, None);
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "cn=family,ou=groups,dc=example,dc=com".to_owned(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Add,
+                modification: LdapPartialAttribute {
+                    atype: "member".to_owned(),
+                    vals: vec![b"uid=bob,ou=people,dc=example,dc=com".to_vec()],
+                },
+            }],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::AttributeOrValueExists,
+                "bob is already a member of the group".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_everyone_group() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups()
+            .with(eq(None))
+            .return_once(|_| Ok(vec![make_family_group(vec![])]));
+        mock.expect_delete_group().never();
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        ldap_handler.set_membership_rules(Some("Family".to_owned()), false, None);
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(LdapOp::DelRequest(
+                    "cn=family,ou=groups,dc=example,dc=com".to_owned()
+                ))
+                .await,
+            Some(vec![make_delete_response(
+                LdapResultCode::UnwillingToPerform,
+                "Cannot change the Family group".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_modify_admin_group_remove_self() {
+        let mut mock = mock_backend_handler();
+        mock.expect_list_groups().with(eq(None)).return_once(|_| {
+            Ok(vec![Group {
+                id: GroupId(42),
+                display_name: "lldap_admin".to_string(),
+                ..make_family_group(vec![UserId::new("bob"), UserId::new("test")])
+            }])
+        });
+        mock.expect_set_group_members().never();
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let request = LdapOp::ModifyRequest(LdapModifyRequest {
+            dn: "cn=lldap_admin,ou=groups,dc=example,dc=com".to_owned(),
+            changes: vec![LdapModify {
+                operation: LdapModifyType::Delete,
+                modification: LdapPartialAttribute {
+                    atype: "member".to_owned(),
+                    vals: vec![b"uid=test,ou=people,dc=example,dc=com".to_vec()],
+                },
+            }],
+        });
+        assert_eq!(
+            ldap_handler.handle_ldap_message(request).await,
+            Some(vec![make_modify_response(
+                LdapResultCode::UnwillingToPerform,
+                "Cannot remove the current user from the admin group".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_entries() {
+        let mut mock = mock_backend_handler();
+        mock.expect_delete_user()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_list_groups()
+            .with(eq(None))
+            .return_once(|_| Ok(vec![make_family_group(vec![])]));
+        mock.expect_delete_group()
+            .with(eq(GroupId(5)))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        let make_request = |dn: &str| LdapOp::DelRequest(dn.to_owned());
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request("uid=bob,ou=people,dc=example,dc=com"))
+                .await,
+            Some(vec![make_delete_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request("cn=family,ou=groups,dc=example,dc=com"))
+                .await,
+            Some(vec![make_delete_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(make_request("uid=test,ou=people,dc=example,dc=com"))
+                .await,
+            Some(vec![make_delete_response(
+                LdapResultCode::UnwillingToPerform,
+                "Cannot delete current user".to_string()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_user_audit_event() {
+        // Set first, so that it's matched before the expectations of the setup.
+        let mut mock = MockTestBackendHandler::new();
+        mock.expect_record_audit_event()
+            .withf(|event| {
+                event.action == "ldapDelete"
+                    && event.actor == Some(UserId::new("test"))
+                    && event.target.as_deref() == Some("uid=bob,ou=people,dc=example,dc=com")
+                    && event.success
+            })
+            .times(1)
+            .return_once(|_| Ok(()));
+        mock.expect_delete_user()
+            .with(eq(UserId::new("bob")))
+            .times(1)
+            .return_once(|_| Ok(()));
+        let mut ldap_handler = setup_bound_admin_handler(mock).await;
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(LdapOp::DelRequest(
+                    "uid=bob,ou=people,dc=example,dc=com".to_owned()
+                ))
+                .await,
+            Some(vec![make_delete_response(
+                LdapResultCode::Success,
+                String::new()
+            )])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_unauthorized() {
+        let mut mock = mock_backend_handler();
+        mock.expect_delete_user().never();
+        let mut ldap_handler = setup_bound_handler_with_group(mock, "regular").await;
+        assert_eq!(
+            ldap_handler
+                .handle_ldap_message(LdapOp::DelRequest(
+                    "uid=bob,ou=people,dc=example,dc=com".to_owned()
+                ))
+                .await,
+            Some(vec![make_delete_response(
+                LdapResultCode::InsufficentAccessRights,
+                "Unauthorized write".to_string()
+            )])
+        );
+    }
 }
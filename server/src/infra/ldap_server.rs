@@ -1,47 +1,156 @@
 use crate::{
     domain::{
-        handler::{BackendHandler, LoginHandler},
+        error::DomainError,
+        handler::{BackendHandler, LoginHandler, UserBackendHandler, UserRequestFilter},
+        ldap::cancel::{parse_cancel_request, CANCELED, CANCEL_OID},
         opaque_handler::OpaqueHandler,
+        types::{PasswordExpiry, UserColumn, UserId},
+    },
+    infra::{
+        bind_limiter::BindLimiter,
+        configuration::{
+            ClientCertUserSource, Configuration, GroupAssignmentRule, LdapServiceAccountGroup,
+        },
+        graphql::mutation::GroupLimit,
+        ldap_codec::{ExtendedLdapCodec, RequestExtras, ResponseExtras},
+        ldap_handler::LdapHandler,
+        sasl_gssapi::KerberosOptions,
+        stats,
+        tls_certificates::CertificateResolver,
     },
-    infra::{configuration::Configuration, ldap_handler::LdapHandler},
 };
 use actix_rt::net::TcpStream;
 use actix_server::ServerBuilder;
 use actix_service::{fn_service, ServiceFactoryExt};
-use anyhow::{anyhow, Context, Result};
-use ldap3_proto::{proto::LdapMsg, LdapCodec};
-use rustls::PrivateKey;
+use anyhow::{anyhow, bail, Context, Result};
+use ldap3_proto::proto::{
+    LdapExtendedResponse, LdapMsg, LdapOp, LdapResult as LdapResultOp, LdapResultCode,
+};
+use lldap_auth::password_strength::PasswordPolicy;
+use rustls::Certificate;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_rustls::TlsAcceptor as RustlsTlsAcceptor;
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
+
+/// OID of the unsolicited Notice of Disconnection (RFC 4511, section 4.4.1).
+const NOTICE_OF_DISCONNECTION_OID: &str = "1.3.6.1.4.1.1466.20036";
+/// OID of the StartTLS extended operation (RFC 4511, section 4.14).
+const START_TLS_OID: &str = "1.3.6.1.4.1.1466.20037";
+
+/// Whether a connection can still be upgraded with StartTLS.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum StartTls {
+    Unsupported,
+    Available,
+    Established,
+}
+
+/// A request, with the parts that `ldap3_proto` couldn't decode.
+type LdapRequest = (LdapMsg, RequestExtras);
+/// A response, with the parts that `ldap3_proto` can't encode.
+type LdapResponse = (LdapMsg, ResponseExtras);
+
+fn make_start_tls_response(msgid: i32, start_tls: StartTls) -> LdapMsg {
+    let (code, message) = match start_tls {
+        StartTls::Available => (LdapResultCode::Success, ""),
+        StartTls::Unsupported => (LdapResultCode::ProtocolError, "StartTLS is not enabled"),
+        StartTls::Established => (
+            LdapResultCode::OperationsError,
+            "TLS is already established",
+        ),
+    };
+    LdapMsg {
+        msgid,
+        op: LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code,
+                matcheddn: "".to_string(),
+                message: message.to_string(),
+                referral: vec![],
+            },
+            name: Some(START_TLS_OID.to_string()),
+            value: None,
+        }),
+        ctrl: vec![],
+    }
+}
+
+fn make_notice_of_disconnection(code: LdapResultCode, message: String) -> LdapMsg {
+    LdapMsg {
+        // Unsolicited notifications always use message ID 0.
+        msgid: 0,
+        op: LdapOp::ExtendedResponse(LdapExtendedResponse {
+            res: LdapResultOp {
+                code,
+                matcheddn: "".to_string(),
+                message,
+                referral: vec![],
+            },
+            name: Some(NOTICE_OF_DISCONNECTION_OID.to_string()),
+            value: None,
+        }),
+        ctrl: vec![],
+    }
+}
 
 #[instrument(skip_all, level = "info", name = "LDAP request")]
 async fn handle_ldap_message<Backend, Writer>(
-    msg: Result<LdapMsg, std::io::Error>,
+    msg: Result<LdapRequest, std::io::Error>,
     resp: &mut Writer,
     session: &mut LdapHandler<Backend>,
 ) -> Result<bool>
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler,
-    Writer: futures_util::Sink<LdapMsg> + Unpin,
-    <Writer as futures_util::Sink<LdapMsg>>::Error: std::error::Error + Send + Sync + 'static,
+    Writer: futures_util::Sink<LdapResponse> + Unpin,
+    <Writer as futures_util::Sink<LdapResponse>>::Error: std::error::Error + Send + Sync + 'static,
 {
     use futures_util::SinkExt;
-    let msg = msg.context("while receiving LDAP op")?;
-    debug!(?msg);
-    match session.handle_ldap_message(msg.op).await {
+    let (msg, extras) = match msg {
+        Ok(msg) => msg,
+        Err(e) => {
+            // The codec only understands LDAPv3: older clients (e.g. binding with version 2) end up
+            // here. Tell them why we're hanging up instead of silently dropping the connection.
+            warn!(
+                "Could not decode the LDAP message, closing the connection: {:#}",
+                e
+            );
+            resp.send((
+                make_notice_of_disconnection(
+                    LdapResultCode::ProtocolError,
+                    "Could not decode the request. Only LDAPv3 is supported".to_string(),
+                ),
+                ResponseExtras::default(),
+            ))
+            .await
+            .context("while sending a notice of disconnection")?;
+            resp.flush()
+                .await
+                .context("while flushing responses: {:#}")?;
+            return Ok(false);
+        }
+    };
+    debug!(?msg, ?extras);
+    match session
+        .handle_ldap_request(msg.op, &msg.ctrl, &extras)
+        .await
+    {
         None => return Ok(false),
         Some(result) => {
             if result.is_empty() {
                 debug!("No response");
             }
-            for response in result.into_iter() {
-                debug!(?response);
-                resp.send(LdapMsg {
-                    msgid: msg.msgid,
-                    op: response,
-                    ctrl: vec![],
-                })
+            for (response, ctrl, extras) in result.into_iter() {
+                debug!(?response, ?ctrl, ?extras);
+                resp.send((
+                    LdapMsg {
+                        msgid: msg.msgid,
+                        op: response,
+                        ctrl,
+                    },
+                    extras,
+                ))
                 .await
                 .context("while sending a response: {:#}")?
             }
@@ -54,87 +163,594 @@ where
     Ok(true)
 }
 
-#[instrument(skip_all, level = "info", name = "LDAP session")]
-async fn handle_ldap_stream<Stream, Backend>(
-    stream: Stream,
-    backend_handler: Backend,
+type LdapRequests<Reader> = FramedRead<Reader, ExtendedLdapCodec>;
+
+/// Waits for the next request, or returns `None` once the client disconnects or stays idle for
+/// longer than the timeout.
+async fn next_request<Reader: tokio::io::AsyncRead + Unpin>(
+    requests: &mut LdapRequests<Reader>,
+    idle_timeout: Option<Duration>,
+) -> Option<Result<LdapRequest, std::io::Error>> {
+    use tokio_stream::StreamExt;
+    match idle_timeout {
+        None => requests.next().await,
+        Some(idle_timeout) => match tokio::time::timeout(idle_timeout, requests.next()).await {
+            Ok(msg) => msg,
+            Err(_) => {
+                info!(
+                    "Closing the connection after {:?} of inactivity",
+                    idle_timeout
+                );
+                None
+            }
+        },
+    }
+}
+
+/// Answers a search cancelled by the client, and the Cancel request (RFC 3909).
+async fn send_cancel_responses<Writer>(
+    msgid: i32,
+    cancel_msgid: i32,
+    resp: &mut Writer,
+) -> Result<()>
+where
+    Writer: futures_util::Sink<LdapResponse> + Unpin,
+    <Writer as futures_util::Sink<LdapResponse>>::Error: std::error::Error + Send + Sync + 'static,
+{
+    use futures_util::SinkExt;
+    let make_result = |code, message: &str| LdapResultOp {
+        code,
+        matcheddn: "".to_string(),
+        message: message.to_string(),
+        referral: vec![],
+    };
+    resp.send((
+        LdapMsg {
+            msgid,
+            op: LdapOp::SearchResultDone(make_result(
+                LdapResultCode::Other,
+                "The search was cancelled",
+            )),
+            ctrl: vec![],
+        },
+        ResponseExtras {
+            controls: vec![],
+            result_code: Some(CANCELED),
+        },
+    ))
+    .await
+    .context("while sending a response: {:#}")?;
+    resp.send((
+        LdapMsg {
+            msgid: cancel_msgid,
+            op: LdapOp::ExtendedResponse(LdapExtendedResponse {
+                res: make_result(LdapResultCode::Success, ""),
+                name: None,
+                value: None,
+            }),
+            ctrl: vec![],
+        },
+        ResponseExtras::default(),
+    ))
+    .await
+    .context("while sending a response: {:#}")?;
+    resp.flush()
+        .await
+        .context("while flushing responses: {:#}")?;
+    Ok(())
+}
+
+/// Handles a search, unless the client abandons or cancels it first. While the search runs, at
+/// most one other request is read: it's returned to be handled next.
+async fn handle_abandonable_search<Reader, Writer, Backend>(
+    msg: LdapRequest,
+    requests: &mut LdapRequests<Reader>,
+    resp: &mut Writer,
+    session: &mut LdapHandler<Backend>,
+) -> Result<(bool, Option<Option<Result<LdapRequest, std::io::Error>>>)>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler,
+    Reader: tokio::io::AsyncRead + Unpin,
+    Writer: futures_util::Sink<LdapResponse> + Unpin,
+    <Writer as futures_util::Sink<LdapResponse>>::Error: std::error::Error + Send + Sync + 'static,
+{
+    use tokio_stream::StreamExt;
+    let msgid = msg.0.msgid;
+    let cancel_msgid = {
+        let search = handle_ldap_message(Ok(msg), resp, session);
+        tokio::pin!(search);
+        tokio::select! {
+            result = &mut search => return Ok((result?, None)),
+            next = requests.next() => match next {
+                Some(Ok((LdapMsg {
+                    op: LdapOp::AbandonRequest(abandoned),
+                    ..
+                }, _))) if abandoned == msgid => {
+                    debug!(msgid, "Search abandoned");
+                    return Ok((true, None));
+                }
+                Some(Ok((LdapMsg {
+                    msgid: cancel_msgid,
+                    op: LdapOp::ExtendedRequest(request),
+                    ..
+                }, _))) if request.name == CANCEL_OID
+                    && parse_cancel_request(request.value.as_deref()) == Some(msgid) => {
+                    cancel_msgid
+                }
+                next => return Ok((search.await?, Some(next))),
+            },
+        }
+    };
+    debug!(msgid, "Search cancelled");
+    send_cancel_responses(msgid, cancel_msgid, resp).await?;
+    Ok((true, None))
+}
+
+/// The settings of an LDAP session, from the configuration.
+#[derive(Clone)]
+struct SessionOptions {
     ldap_base_dn: String,
     ignored_user_attributes: Vec<String>,
     ignored_group_attributes: Vec<String>,
-) -> Result<Stream>
+    empty_password_bind_unwilling_to_perform: bool,
+    anonymous_bind_attributes: Option<Vec<String>>,
+    service_account_groups: Vec<LdapServiceAccountGroup>,
+    active_directory_compatibility: bool,
+    query_log: bool,
+    slow_query_threshold: Option<Duration>,
+    password_policy: Arc<PasswordPolicy>,
+    password_expiry: Option<PasswordExpiry>,
+    nested_groups_max_depth: u8,
+    require_tls: bool,
+    idle_timeout: Option<Duration>,
+    /// Shared by all the connections, when their number is limited.
+    connection_slots: Option<Arc<Semaphore>>,
+    bind_limiter: Option<Arc<BindLimiter>>,
+    user_attribute_aliases: HashMap<String, String>,
+    group_attribute_aliases: HashMap<String, String>,
+    group_assignment_rules: Vec<GroupAssignmentRule>,
+    everyone_group: Option<String>,
+    strict_group_membership_adds: bool,
+    group_limit: Option<GroupLimit>,
+    kerberos: Option<KerberosOptions>,
+}
+
+#[cfg(feature = "gssapi")]
+fn get_kerberos_options(realm: String) -> Option<KerberosOptions> {
+    Some(KerberosOptions {
+        realm,
+        new_context: Arc::new(crate::infra::sasl_gssapi::new_kerberos_context),
+    })
+}
+
+#[cfg(not(feature = "gssapi"))]
+fn get_kerberos_options(_realm: String) -> Option<KerberosOptions> {
+    warn!("ldap_kerberos_realm is ignored: lldap was built without the gssapi feature");
+    None
+}
+
+fn to_lowercase_aliases(aliases: &HashMap<String, String>) -> HashMap<String, String> {
+    aliases
+        .iter()
+        .map(|(alias, attribute)| (alias.to_ascii_lowercase(), attribute.to_ascii_lowercase()))
+        .collect()
+}
+
+impl SessionOptions {
+    fn new(config: &Configuration) -> Self {
+        Self {
+            ldap_base_dn: config.ldap_base_dn.clone(),
+            ignored_user_attributes: config.ignored_user_attributes.clone(),
+            ignored_group_attributes: config.ignored_group_attributes.clone(),
+            empty_password_bind_unwilling_to_perform: config
+                .ldap_empty_password_bind_unwilling_to_perform,
+            anonymous_bind_attributes: if config.ldap_anonymous_bind {
+                Some(config.ldap_anonymous_bind_attributes.clone())
+            } else {
+                None
+            },
+            service_account_groups: config.ldap_service_account_groups.clone(),
+            active_directory_compatibility: config.ldap_active_directory_compatibility,
+            query_log: config.ldap_query_log,
+            slow_query_threshold: Some(config.ldap_slow_query_threshold_ms)
+                .filter(|&ms| ms > 0)
+                .map(Duration::from_millis),
+            password_policy: Arc::new(config.get_password_policy()),
+            password_expiry: config.get_password_expiry(),
+            nested_groups_max_depth: config.nested_groups_max_depth,
+            require_tls: config.ldaps_options.require_tls,
+            idle_timeout: Some(config.ldap_idle_timeout_seconds)
+                .filter(|&seconds| seconds > 0)
+                .map(Duration::from_secs),
+            connection_slots: Some(config.ldap_max_connections)
+                .filter(|&max| max > 0)
+                .map(|max| Arc::new(Semaphore::new(max))),
+            bind_limiter: Some(&config.ldap_bind_rate_limit)
+                .filter(|options| options.enabled)
+                .map(|options| Arc::new(BindLimiter::new(options.clone()))),
+            user_attribute_aliases: to_lowercase_aliases(&config.ldap_user_attribute_aliases),
+            group_attribute_aliases: to_lowercase_aliases(&config.ldap_group_attribute_aliases),
+            group_assignment_rules: config.group_assignment_rules.clone(),
+            everyone_group: config.everyone_group.clone(),
+            strict_group_membership_adds: config.strict_group_membership_adds,
+            group_limit: config.max_groups_per_user.map(|max_groups| GroupLimit {
+                max_groups,
+                exempt_admins: config.max_groups_per_user_exempts_admins,
+            }),
+            kerberos: config
+                .ldap_kerberos_realm
+                .clone()
+                .and_then(get_kerberos_options),
+        }
+    }
+
+    /// Reserves a slot for a new connection, released when the permit is dropped. Returns `None`
+    /// if all the slots are taken.
+    fn acquire_connection_slot(&self) -> Option<Option<OwnedSemaphorePermit>> {
+        match &self.connection_slots {
+            None => Some(None),
+            Some(slots) => slots.clone().try_acquire_owned().ok().map(Some),
+        }
+    }
+}
+
+fn make_ldap_session<Backend>(
+    backend_handler: Backend,
+    options: SessionOptions,
+) -> LdapHandler<Backend>
 where
-    Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
-    Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite,
+    Backend: BackendHandler + LoginHandler + OpaqueHandler,
 {
-    use tokio_stream::StreamExt;
-    let (r, w) = tokio::io::split(stream);
-    // Configure the codec etc.
-    let mut requests = FramedRead::new(r, LdapCodec);
-    let mut resp = FramedWrite::new(w, LdapCodec);
-
     let mut session = LdapHandler::new(
         backend_handler,
-        ldap_base_dn,
-        ignored_user_attributes,
-        ignored_group_attributes,
+        options.ldap_base_dn,
+        options.ignored_user_attributes,
+        options.ignored_group_attributes,
+    );
+    session.set_empty_password_bind_unwilling_to_perform(
+        options.empty_password_bind_unwilling_to_perform,
     );
+    session.set_anonymous_bind_attributes(options.anonymous_bind_attributes);
+    session.set_service_account_groups(options.service_account_groups);
+    session.set_active_directory_compatibility(options.active_directory_compatibility);
+    session.set_query_log(options.query_log, options.slow_query_threshold);
+    session.set_password_policy(options.password_policy);
+    session.set_password_expiry(options.password_expiry);
+    session.set_nested_groups_max_depth(options.nested_groups_max_depth);
+    session.set_bind_limiter(options.bind_limiter);
+    session.set_attribute_aliases(
+        options.user_attribute_aliases,
+        options.group_attribute_aliases,
+    );
+    session.set_group_assignment_rules(options.group_assignment_rules);
+    session.set_membership_rules(
+        options.everyone_group,
+        options.strict_group_membership_adds,
+        options.group_limit,
+    );
+    session.set_kerberos_options(options.kerberos);
+    session
+}
 
-    while let Some(msg) = requests.next().await {
-        if !handle_ldap_message(msg, &mut resp, &mut session)
-            .await
-            .context("while handling incoming messages")?
+/// Serves the requests until the client disconnects, or until it asks for StartTLS while it's
+/// available. Returns the stream, and whether to start TLS on it.
+async fn serve_ldap_session<Stream, Backend>(
+    stream: Stream,
+    session: &mut LdapHandler<Backend>,
+    start_tls: StartTls,
+    idle_timeout: Option<Duration>,
+) -> Result<(Stream, bool)>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler,
+    Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite,
+{
+    use futures_util::SinkExt;
+    let (r, w) = tokio::io::split(stream);
+    // Configure the codec etc.
+    let mut requests = FramedRead::new(r, ExtendedLdapCodec);
+    let mut resp = FramedWrite::new(w, ExtendedLdapCodec);
+
+    let mut start_tls_accepted = false;
+    // A request read while handling a search.
+    let mut pending = None;
+    loop {
+        let msg = match pending.take() {
+            Some(next) => next,
+            None => next_request(&mut requests, idle_timeout).await,
+        };
+        let msg = match msg {
+            Some(msg) => msg,
+            None => break,
+        };
+        if let Ok((
+            LdapMsg {
+                msgid,
+                op: LdapOp::ExtendedRequest(request),
+                ..
+            },
+            _,
+        )) = &msg
         {
+            if request.name == START_TLS_OID {
+                debug!(?start_tls, "StartTLS request");
+                resp.send((
+                    make_start_tls_response(*msgid, start_tls),
+                    ResponseExtras::default(),
+                ))
+                .await
+                .context("while sending the StartTLS response")?;
+                resp.flush()
+                    .await
+                    .context("while flushing the StartTLS response")?;
+                if start_tls == StartTls::Available {
+                    start_tls_accepted = true;
+                    break;
+                }
+                continue;
+            }
+        }
+        let keep_going = match msg {
+            Ok(
+                msg @ (
+                    LdapMsg {
+                        op: LdapOp::SearchRequest(_),
+                        ..
+                    },
+                    _,
+                ),
+            ) => {
+                let (keep_going, next) =
+                    handle_abandonable_search(msg, &mut requests, &mut resp, session)
+                        .await
+                        .context("while handling incoming messages")?;
+                pending = next;
+                keep_going
+            }
+            msg => handle_ldap_message(msg, &mut resp, session)
+                .await
+                .context("while handling incoming messages")?,
+        };
+        if !keep_going {
             break;
         }
     }
-    Ok(requests.into_inner().unsplit(resp.into_inner()))
+    // The client has to wait for the response before starting the handshake (RFC 4511, section
+    // 4.14.1): anything already received would be lost by the upgrade.
+    if start_tls_accepted && !requests.read_buffer().is_empty() {
+        bail!("Received data between the StartTLS request and the TLS handshake");
+    }
+    Ok((
+        requests.into_inner().unsplit(resp.into_inner()),
+        start_tls_accepted,
+    ))
 }
 
-fn read_private_key(key_file: &str) -> Result<PrivateKey> {
-    use rustls_pemfile::{pkcs8_private_keys, rsa_private_keys};
-    use std::{fs::File, io::BufReader};
-    pkcs8_private_keys(&mut BufReader::new(File::open(key_file)?))
-        .map_err(anyhow::Error::from)
-        .and_then(|keys| {
-            keys.into_iter()
-                .next()
-                .ok_or_else(|| anyhow!("No PKCS8 key"))
-        })
-        .or_else(|_| {
-            rsa_private_keys(&mut BufReader::new(File::open(key_file)?))
-                .map_err(anyhow::Error::from)
-                .and_then(|keys| {
-                    keys.into_iter()
-                        .next()
-                        .ok_or_else(|| anyhow!("No PKCS1 key"))
-                })
-        })
-        .with_context(|| {
-            format!(
-                "Cannot read either PKCS1 or PKCS8 private key from {}",
-                key_file
-            )
-        })
-        .map(rustls::PrivateKey)
+/// The TLS settings shared by LDAPS and StartTLS.
+#[derive(Clone)]
+struct TlsOptions {
+    acceptor: RustlsTlsAcceptor,
+    client_cert_user_source: ClientCertUserSource,
+    require_client_cert: bool,
+}
+
+/// Performs the TLS handshake, and finds the user identified by the client certificate.
+async fn accept_tls<Backend: BackendHandler>(
+    stream: TcpStream,
+    backend_handler: &Backend,
+    tls_options: &TlsOptions,
+) -> Result<(tokio_rustls::server::TlsStream<TcpStream>, Option<UserId>)> {
+    let tls_stream = tls_options.acceptor.accept(stream).await?;
+    let client_certificate_user = get_client_certificate_user(
+        backend_handler,
+        tls_stream.get_ref().1.peer_certificates(),
+        tls_options.client_cert_user_source,
+    )
+    .await
+    .context("while mapping the client certificate to a user")?;
+    if tls_options.require_client_cert && client_certificate_user.is_none() {
+        bail!("The client certificate does not match any user");
+    }
+    Ok((tls_stream, client_certificate_user))
+}
+
+#[instrument(skip_all, level = "info", name = "LDAP session")]
+async fn handle_ldap_stream<Backend>(
+    stream: TcpStream,
+    backend_handler: Backend,
+    options: SessionOptions,
+    start_tls_options: Option<TlsOptions>,
+) -> Result<()>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
+{
+    let _connection_slot = match options.acquire_connection_slot() {
+        Some(slot) => slot,
+        None => {
+            warn!("Too many LDAP connections, closing the new one");
+            return Ok(());
+        }
+    };
+    let _connection_guard = stats::ldap_connection_opened();
+    let require_tls = options.require_tls;
+    let idle_timeout = options.idle_timeout;
+    let mut session = make_ldap_session(backend_handler.clone(), options);
+    session.set_bind_requires_tls(require_tls);
+    session.set_peer_address(stream.peer_addr().ok().map(|address| address.ip()));
+    let start_tls = match start_tls_options {
+        None => StartTls::Unsupported,
+        Some(_) => StartTls::Available,
+    };
+    let (stream, start_tls_accepted) =
+        serve_ldap_session(stream, &mut session, start_tls, idle_timeout).await?;
+    if let (true, Some(tls_options)) = (start_tls_accepted, start_tls_options) {
+        let (tls_stream, client_certificate_user) =
+            accept_tls(stream, &backend_handler, &tls_options)
+                .await
+                .context("during the StartTLS handshake")?;
+        session.set_bind_requires_tls(false);
+        if let Some(user_id) = client_certificate_user {
+            if !session.bind_with_client_certificate(user_id).await
+                && tls_options.require_client_cert
+            {
+                bail!("The account of the client certificate can't be used");
+            }
+        }
+        serve_ldap_session(
+            tls_stream,
+            &mut session,
+            StartTls::Established,
+            idle_timeout,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[instrument(skip_all, level = "info", name = "LDAPS session")]
+async fn handle_ldaps_stream<Backend>(
+    stream: TcpStream,
+    backend_handler: Backend,
+    options: SessionOptions,
+    tls_options: TlsOptions,
+) -> Result<()>
+where
+    Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
+{
+    // Checked before the handshake, which is the expensive part.
+    let _connection_slot = match options.acquire_connection_slot() {
+        Some(slot) => slot,
+        None => {
+            warn!("Too many LDAP connections, closing the new LDAPS one");
+            return Ok(());
+        }
+    };
+    let _connection_guard = stats::ldap_connection_opened();
+    let peer_address = stream.peer_addr().ok().map(|address| address.ip());
+    let (tls_stream, client_certificate_user) =
+        accept_tls(stream, &backend_handler, &tls_options).await?;
+    let idle_timeout = options.idle_timeout;
+    let mut session = make_ldap_session(backend_handler, options);
+    session.set_peer_address(peer_address);
+    if let Some(user_id) = client_certificate_user {
+        if !session.bind_with_client_certificate(user_id).await && tls_options.require_client_cert {
+            bail!("The account of the client certificate can't be used");
+        }
+    }
+    serve_ldap_session(
+        tls_stream,
+        &mut session,
+        StartTls::Established,
+        idle_timeout,
+    )
+    .await?;
+    Ok(())
 }
 
-fn get_tls_acceptor(config: &Configuration) -> Result<RustlsTlsAcceptor> {
-    use rustls::{Certificate, ServerConfig};
-    use rustls_pemfile::certs;
+/// Finds the user identified by a (verified) client certificate, if any.
+async fn get_client_certificate_user<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    certificates: Option<&[Certificate]>,
+    source: ClientCertUserSource,
+) -> Result<Option<UserId>> {
+    use x509_parser::{extensions::GeneralName, prelude::*};
+    // The first certificate is the client's own, the others are the chain of intermediates.
+    let certificate = match certificates.and_then(|c| c.first()) {
+        None => return Ok(None),
+        Some(certificate) => certificate,
+    };
+    let (_, certificate) = X509Certificate::from_der(&certificate.0)
+        .map_err(|e| anyhow!("Invalid client certificate: {:#}", e))?;
+    let alternative_names = || {
+        certificate
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|extension| extension.value.general_names.clone())
+            .unwrap_or_default()
+    };
+    let user_id = match source {
+        ClientCertUserSource::SubjectCn => certificate
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(UserId::new),
+        ClientCertUserSource::SanDns => {
+            alternative_names().into_iter().find_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(UserId::new(dns)),
+                _ => None,
+            })
+        }
+        ClientCertUserSource::SanEmail => {
+            let emails = alternative_names()
+                .into_iter()
+                .filter_map(|name| match name {
+                    GeneralName::RFC822Name(email) => Some(email.to_owned()),
+                    _ => None,
+                });
+            let mut user_id = None;
+            for email in emails {
+                let users = backend_handler
+                    .list_users(
+                        Some(UserRequestFilter::Equality(UserColumn::Email, email)),
+                        false,
+                    )
+                    .await?;
+                if let [user] = users.as_slice() {
+                    user_id = Some(user.user.user_id.clone());
+                    break;
+                }
+            }
+            user_id
+        }
+    };
+    match user_id {
+        None => Ok(None),
+        Some(user_id) => match backend_handler.get_user_details(&user_id).await {
+            Ok(_) => Ok(Some(user_id)),
+            Err(DomainError::EntityNotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        },
+    }
+}
+
+fn get_tls_acceptor(
+    config: &Configuration,
+    certificate_resolver: Arc<CertificateResolver>,
+) -> Result<RustlsTlsAcceptor> {
+    use rustls::{
+        server::{
+            AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, NoClientAuth,
+        },
+        RootCertStore, ServerConfig,
+    };
     use std::{fs::File, io::BufReader};
-    // Load TLS key and cert files
-    let certs = certs(&mut BufReader::new(File::open(
-        &config.ldaps_options.cert_file,
-    )?))?
-    .into_iter()
-    .map(Certificate)
-    .collect::<Vec<_>>();
-    let private_key = read_private_key(&config.ldaps_options.key_file)?;
+    let client_cert_verifier = match &config.ldaps_options.client_ca_file {
+        None => {
+            if config.ldaps_options.require_client_cert {
+                bail!("`require_client_cert` requires a `client_ca_file`");
+            }
+            NoClientAuth::new()
+        }
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for ca in rustls_pemfile::certs(&mut BufReader::new(File::open(ca_file)?))? {
+                roots
+                    .add(&Certificate(ca))
+                    .map_err(|e| anyhow!("Invalid CA certificate in {}: {:?}", ca_file, e))?;
+            }
+            if config.ldaps_options.require_client_cert {
+                AllowAnyAuthenticatedClient::new(roots)
+            } else {
+                AllowAnyAnonymousOrAuthenticatedClient::new(roots)
+            }
+        }
+    };
     let server_config = std::sync::Arc::new(
         ServerConfig::builder()
             .with_safe_defaults()
-            .with_no_client_auth()
-            .with_single_cert(certs, private_key)?,
+            .with_client_cert_verifier(client_cert_verifier)
+            .with_cert_resolver(certificate_resolver),
     );
     Ok(server_config.into())
 }
@@ -147,29 +763,44 @@ pub fn build_ldap_server<Backend>(
 where
     Backend: BackendHandler + LoginHandler + OpaqueHandler + 'static,
 {
-    let context = (
-        backend_handler,
-        config.ldap_base_dn.clone(),
-        config.ignored_user_attributes.clone(),
-        config.ignored_group_attributes.clone(),
-    );
+    let tls_options = if config.ldaps_options.enabled || config.ldaps_options.start_tls {
+        let certificate_resolver = Arc::new(
+            CertificateResolver::new(&config.ldaps_options)
+                .context("while setting up the SSL certificate")?,
+        );
+        certificate_resolver.clone().spawn_reloader(
+            Some(config.ldaps_options.cert_reload_interval_seconds)
+                .filter(|&seconds| seconds > 0)
+                .map(Duration::from_secs),
+        );
+        Some(TlsOptions {
+            acceptor: get_tls_acceptor(config, certificate_resolver)
+                .context("while setting up the SSL certificate")?,
+            client_cert_user_source: config.ldaps_options.client_cert_user_source,
+            require_client_cert: config.ldaps_options.require_client_cert,
+        })
+    } else {
+        None
+    };
+    if config.ldaps_options.require_tls && !config.ldaps_options.start_tls {
+        warn!("`require_tls` is set without `start_tls`: binds on the plain LDAP port will fail");
+    }
+    let context = (backend_handler, SessionOptions::new(config));
 
     let context_for_tls = context.clone();
+    let start_tls_options = tls_options
+        .clone()
+        .filter(|_| config.ldaps_options.start_tls);
 
     let binder = move || {
         let context = context.clone();
+        let start_tls_options = start_tls_options.clone();
         fn_service(move |stream: TcpStream| {
             let context = context.clone();
+            let start_tls_options = start_tls_options.clone();
             async move {
-                let (handler, base_dn, ignored_user_attributes, ignored_group_attributes) = context;
-                handle_ldap_stream(
-                    stream,
-                    handler,
-                    base_dn,
-                    ignored_user_attributes,
-                    ignored_group_attributes,
-                )
-                .await
+                let (handler, options) = context;
+                handle_ldap_stream(stream, handler, options, start_tls_options).await
             }
         })
         .map_err(|err: anyhow::Error| error!("[LDAP] Service Error: {:#}", err))
@@ -179,47 +810,34 @@ where
     let server_builder = server_builder
         .bind("ldap", (config.ldap_host.clone(), config.ldap_port), binder)
         .with_context(|| format!("while binding to the port {}", config.ldap_port));
-    if config.ldaps_options.enabled {
-        let tls_context = (
-            context_for_tls,
-            get_tls_acceptor(config).context("while setting up the SSL certificate")?,
-        );
-        let tls_binder = move || {
-            let tls_context = tls_context.clone();
-            fn_service(move |stream: TcpStream| {
+    match tls_options.filter(|_| config.ldaps_options.enabled) {
+        Some(tls_options) => {
+            let tls_context = (context_for_tls, tls_options);
+            let tls_binder = move || {
                 let tls_context = tls_context.clone();
-                async move {
-                    let (
-                        (handler, base_dn, ignored_user_attributes, ignored_group_attributes),
-                        tls_acceptor,
-                    ) = tls_context;
-                    let tls_stream = tls_acceptor.accept(stream).await?;
-                    handle_ldap_stream(
-                        tls_stream,
-                        handler,
-                        base_dn,
-                        ignored_user_attributes,
-                        ignored_group_attributes,
-                    )
-                    .await
-                }
-            })
-            .map_err(|err: anyhow::Error| error!("[LDAPS] Service Error: {:#}", err))
-        };
+                fn_service(move |stream: TcpStream| {
+                    let tls_context = tls_context.clone();
+                    async move {
+                        let ((handler, options), tls_options) = tls_context;
+                        handle_ldaps_stream(stream, handler, options, tls_options).await
+                    }
+                })
+                .map_err(|err: anyhow::Error| error!("[LDAPS] Service Error: {:#}", err))
+            };
 
-        info!(
-            "Starting the LDAPS server on port {}",
-            config.ldaps_options.port
-        );
-        server_builder.and_then(|s| {
-            s.bind(
-                "ldaps",
-                (config.ldap_host.clone(), config.ldaps_options.port),
-                tls_binder,
-            )
-            .with_context(|| format!("while binding to the port {}", config.ldaps_options.port))
-        })
-    } else {
-        server_builder
+            info!(
+                "Starting the LDAPS server on port {}",
+                config.ldaps_options.port
+            );
+            server_builder.and_then(|s| {
+                s.bind(
+                    "ldaps",
+                    (config.ldap_host.clone(), config.ldaps_options.port),
+                    tls_binder,
+                )
+                .with_context(|| format!("while binding to the port {}", config.ldaps_options.port))
+            })
+        }
+        None => server_builder,
     }
 }
@@ -0,0 +1,151 @@
+//! The proof of work challenges of the password logins, when enabled with `login_challenge`.
+//!
+//! The challenges carry their expiry date and a MAC, with a key generated at startup, so handing
+//! them out doesn't take any memory. Only the solved ones are kept until they expire: each
+//! challenge can be used for a single login attempt, so that a bot has to solve a new one for each
+//! password it tries. Like the TOTP challenges, they don't survive a restart.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use lldap_auth::proof_of_work;
+
+use crate::infra::sql_backend_handler::gen_random_string;
+
+/// How long the client has to solve the challenge and send the login request, in seconds.
+const CHALLENGE_VALIDITY_SECONDS: i64 = 5 * 60;
+
+pub struct LoginChallenges {
+    difficulty: u8,
+    key: Hmac<Sha256>,
+    /// The expiry of each solved challenge.
+    used_challenges: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl LoginChallenges {
+    pub fn new(difficulty: u8) -> Self {
+        Self {
+            difficulty,
+            key: Hmac::new_varkey(gen_random_string(32).as_bytes())
+                .expect("HMAC accepts keys of any size"),
+            used_challenges: Mutex::default(),
+        }
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = self.key.clone();
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    fn create_at(&self, now: DateTime<Utc>) -> proof_of_work::Challenge {
+        let expiry = now + Duration::seconds(CHALLENGE_VALIDITY_SECONDS);
+        let payload = format!("{}.{}", expiry.timestamp(), gen_random_string(16));
+        let mac = base64::encode_config(
+            self.mac(&payload).finalize().into_bytes(),
+            base64::URL_SAFE_NO_PAD,
+        );
+        proof_of_work::Challenge {
+            challenge: format!("{}.{}", payload, mac),
+            difficulty: self.difficulty,
+        }
+    }
+
+    pub fn create(&self) -> proof_of_work::Challenge {
+        self.create_at(Utc::now())
+    }
+
+    /// The expiry of the challenge, if this server created it.
+    fn get_expiry(&self, challenge: &str) -> Option<DateTime<Utc>> {
+        let (payload, mac) = challenge.rsplit_once('.')?;
+        let mac = base64::decode_config(mac, base64::URL_SAFE_NO_PAD).ok()?;
+        self.mac(payload).verify(&mac).ok()?;
+        let timestamp = payload.split_once('.')?.0.parse::<i64>().ok()?;
+        Utc.timestamp_opt(timestamp, 0).single()
+    }
+
+    fn check_at(&self, solution: Option<&proof_of_work::Solution>, now: DateTime<Utc>) -> bool {
+        let solution = match solution {
+            Some(solution) => solution,
+            None => return false,
+        };
+        let expiry = match self.get_expiry(&solution.challenge) {
+            Some(expiry) if expiry > now => expiry,
+            _ => return false,
+        };
+        if !proof_of_work::is_solution(&solution.challenge, solution.nonce, self.difficulty) {
+            return false;
+        }
+        let mut used_challenges = self.used_challenges.lock().unwrap();
+        used_challenges.retain(|_, expiry| *expiry > now);
+        used_challenges
+            .insert(solution.challenge.clone(), expiry)
+            .is_none()
+    }
+
+    /// Whether the solution is valid. A solved challenge can't be used again.
+    pub fn check(&self, solution: Option<&proof_of_work::Solution>) -> bool {
+        self.check_at(solution, Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let challenges = LoginChallenges::new(4);
+        let now = Utc::now();
+        let solution = challenges.create_at(now).solve();
+        assert!(challenges.check_at(Some(&solution), now));
+        // Only once.
+        assert!(!challenges.check_at(Some(&solution), now));
+        assert!(!challenges.check_at(None, now));
+
+        let challenge = challenges.create_at(now);
+        let wrong_nonce = (0..)
+            .find(|nonce| !proof_of_work::is_solution(&challenge.challenge, *nonce, 4))
+            .unwrap();
+        let wrong_solution = proof_of_work::Solution {
+            challenge: challenge.challenge.clone(),
+            nonce: wrong_nonce,
+        };
+        assert!(!challenges.check_at(Some(&wrong_solution), now));
+        assert!(challenges.check_at(Some(&challenge.solve()), now));
+    }
+
+    #[test]
+    fn test_forged_challenge() {
+        let challenges = LoginChallenges::new(4);
+        let now = Utc::now();
+        // Created by another server, with another key.
+        let solution = LoginChallenges::new(4).create_at(now).solve();
+        assert!(!challenges.check_at(Some(&solution), now));
+        // With a later expiry.
+        let challenge = challenges.create_at(now).challenge;
+        let (_, rest) = challenge.split_once('.').unwrap();
+        let forged = proof_of_work::Challenge {
+            challenge: format!("{}.{}", (now + Duration::days(1)).timestamp(), rest),
+            difficulty: 4,
+        };
+        assert!(!challenges.check_at(Some(&forged.solve()), now));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let challenges = LoginChallenges::new(4);
+        let now = Utc::now();
+        let validity = Duration::seconds(CHALLENGE_VALIDITY_SECONDS);
+        let solution = challenges.create_at(now).solve();
+        assert!(!challenges.check_at(Some(&solution), now + validity));
+        assert!(challenges.check_at(Some(&solution), now));
+        // The expired challenges are forgotten.
+        let later = challenges.create_at(now + validity).solve();
+        assert!(challenges.check_at(Some(&later), now + validity));
+        assert_eq!(challenges.used_challenges.lock().unwrap().len(), 1);
+    }
+}
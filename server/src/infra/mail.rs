@@ -1,10 +1,117 @@
-use crate::infra::{cli::SmtpEncryption, configuration::MailOptions};
-use anyhow::{Ok, Result};
+use crate::{
+    domain::types::SessionMetadata,
+    infra::{cli::SmtpEncryption, configuration::MailOptions},
+};
+use anyhow::{anyhow, bail, Ok, Result};
 use lettre::{
     message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
     AsyncTransport, Message, Tokio1Executor,
 };
-use tracing::debug;
+use tracing::{debug, warn};
+
+#[derive(Debug, PartialEq, Eq)]
+struct Email {
+    subject: String,
+    body: String,
+}
+
+/// Replaces the `{{ variable }}` placeholders of the template. Unknown variables are an error, so
+/// that a typo doesn't end up in the emails.
+fn render_template(template: &str, variables: &[(&str, &str)]) -> Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unclosed `{{{{`"))?
+            + start;
+        let name = rest[start + 2..end].trim();
+        let value = variables
+            .iter()
+            .find(|(variable, _)| *variable == name)
+            .ok_or_else(|| anyhow!("Unknown variable `{}`", name))?
+            .1;
+        result.push_str(value);
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Renders an email template: the first line is the subject, prefixed with `Subject:`, and the
+/// rest is the body.
+fn render_email_template(template: &str, variables: &[(&str, &str)]) -> Result<Email> {
+    let (subject, body) = template
+        .split_once('\n')
+        .and_then(|(subject, body)| Some((subject.strip_prefix("Subject:")?, body)))
+        .ok_or_else(|| anyhow!("The first line of the template must be `Subject: ...`"))?;
+    let subject = render_template(subject.trim(), variables)?;
+    if subject.contains('\n') {
+        bail!("The subject must fit on one line");
+    }
+    Ok(Email {
+        subject,
+        body: render_template(body, variables)?,
+    })
+}
+
+/// The paths to look for the template in the given language, most specific first: for `fr-CA`,
+/// `reset.txt` gives `reset.fr-CA.txt`, `reset.fr.txt` and then `reset.txt` itself. Invalid
+/// language tags are ignored, so that they can't point to another file.
+fn get_localized_template_paths(path: &str, language: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    if !language.is_empty()
+        && language
+            .split('-')
+            .all(|subtag| !subtag.is_empty() && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        let file_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let (stem, extension) = match path[file_start..].rfind('.') {
+            Some(i) if i > 0 => path.split_at(file_start + i),
+            _ => (path, ""),
+        };
+        let mut tag = language;
+        loop {
+            paths.push(format!("{}.{}{}", stem, tag, extension));
+            match tag.rfind('-') {
+                Some(i) => tag = &tag[..i],
+                None => break,
+            }
+        }
+    }
+    paths.push(path.to_owned());
+    paths
+}
+
+/// Renders the email from the configured template if there is one, in the language of the
+/// recipient if there is a template for it, falling back to the built-in email if it can't be
+/// rendered.
+fn render_email(
+    template: Option<&str>,
+    language: &str,
+    variables: &[(&str, &str)],
+    default: impl FnOnce() -> Email,
+) -> Email {
+    let paths = match template {
+        None => return default(),
+        Some(path) => get_localized_template_paths(path, language),
+    };
+    let path = paths
+        .iter()
+        .find(|path| std::path::Path::new(path).is_file())
+        .unwrap_or_else(|| paths.last().unwrap());
+    std::fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|template| render_email_template(&template, variables))
+        .unwrap_or_else(|e| {
+            warn!(
+                "Error rendering the email template `{}`, using the default email: {:#}",
+                path, e
+            );
+            default()
+        })
+}
 
 async fn send_email(to: Mailbox, subject: &str, body: String, options: &MailOptions) -> Result<()> {
     let from = options
@@ -38,31 +145,223 @@ async fn send_email(to: Mailbox, subject: &str, body: String, options: &MailOpti
 pub async fn send_password_reset_email(
     username: &str,
     to: &str,
+    language: &str,
     token: &str,
     domain: &str,
     options: &MailOptions,
 ) -> Result<()> {
     let to = to.parse()?;
-    let body = format!(
-        "Hello {},
+    let url = format!("{}/reset-password/step2/{}", domain, token);
+    let email = render_email(
+        options.password_reset_template.as_deref(),
+        language,
+        &[("username", username), ("url", &url)],
+        || Email {
+            subject: "[LLDAP] Password reset requested".to_string(),
+            body: format!(
+                "Hello {},
 This email has been sent to you in order to validate your identity.
 If you did not initiate the process your credentials might have been
 compromised. You should reset your password and contact an administrator.
 
-To reset your password please visit the following URL: {}/reset-password/step2/{}
+To reset your password please visit the following URL: {}
 
 Please contact an administrator if you did not initiate the process.",
-        username, domain, token
+                username, url
+            ),
+        },
+    );
+    send_email(to, &email.subject, email.body, options).await
+}
+
+pub async fn send_login_notification_email(
+    username: &str,
+    user_id: &str,
+    to: &str,
+    language: &str,
+    metadata: &SessionMetadata,
+    domain: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let ip_address = metadata.ip_address.as_deref().unwrap_or("unknown");
+    let user_agent = metadata.user_agent.as_deref().unwrap_or("unknown");
+    let url = format!("{}/user/{}", domain, user_id);
+    let email = render_email(
+        options.login_notification_template.as_deref(),
+        language,
+        &[
+            ("username", username),
+            ("ip_address", ip_address),
+            ("user_agent", user_agent),
+            ("url", &url),
+        ],
+        || Email {
+            subject: "[LLDAP] New login to your account".to_string(),
+            body: format!(
+                "Hello {},
+Your LLDAP account was just used to log in from a new device:
+
+IP address: {}
+Browser: {}
+
+If it was you, you can ignore this email. Otherwise, change your password
+right away and log out the other sessions from your user page: {}
+
+You can turn off these emails from the same page.",
+                username, ip_address, user_agent, url
+            ),
+        },
     );
-    send_email(to, "[LLDAP] Password reset requested", body, options).await
+    send_email(to, &email.subject, email.body, options).await
 }
 
-pub async fn send_test_email(to: Mailbox, options: &MailOptions) -> Result<()> {
-    send_email(
-        to,
-        "LLDAP test email",
-        "The test is successful! You can send emails from LLDAP".to_string(),
-        options,
-    )
-    .await
+/// Tells an approver of the group that a user asked to join it.
+pub async fn send_membership_request_email(
+    to: &str,
+    language: &str,
+    username: &str,
+    group: &str,
+    requested_by: &str,
+    url: &str,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let email = render_email(
+        options.membership_request_template.as_deref(),
+        language,
+        &[
+            ("username", username),
+            ("group", group),
+            ("requested_by", requested_by),
+            ("url", url),
+        ],
+        || Email {
+            subject: format!("[LLDAP] {} asks to join {}", username, group),
+            body: format!(
+                "Hello,
+{} asked for {} to join the group {}, which requires an approval.
+
+You can approve or deny the request from the page of the group: {}",
+                requested_by, username, group, url
+            ),
+        },
+    );
+    send_email(to, &email.subject, email.body, options).await
+}
+
+/// Tells the user whether their request to join the group was approved.
+pub async fn send_membership_decision_email(
+    username: &str,
+    to: &str,
+    language: &str,
+    group: &str,
+    approved: bool,
+    options: &MailOptions,
+) -> Result<()> {
+    let to = to.parse()?;
+    let decision = if approved { "approved" } else { "denied" };
+    let email = render_email(
+        options.membership_decision_template.as_deref(),
+        language,
+        &[
+            ("username", username),
+            ("group", group),
+            ("decision", decision),
+        ],
+        || Email {
+            subject: format!("[LLDAP] Your request to join {} was {}", group, decision),
+            body: format!(
+                "Hello {},
+The request for you to join the group {} was {}.",
+                username, group, decision
+            ),
+        },
+    );
+    send_email(to, &email.subject, email.body, options).await
+}
+
+pub async fn send_test_email(to: Mailbox, language: &str, options: &MailOptions) -> Result<()> {
+    let email = render_email(
+        options.test_email_template.as_deref(),
+        language,
+        &[],
+        || Email {
+            subject: "LLDAP test email".to_string(),
+            body: "The test is successful! You can send emails from LLDAP".to_string(),
+        },
+    );
+    send_email(to, &email.subject, email.body, options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let variables = [("username", "Bob"), ("url", "https://example.com")];
+        assert_eq!(
+            render_template("Hi {{username}}, go to {{ url }}.", &variables).unwrap(),
+            "Hi Bob, go to https://example.com."
+        );
+        assert_eq!(render_template("Hi! }}", &variables).unwrap(), "Hi! }}");
+        assert!(render_template("Hi {{name}}", &variables).is_err());
+        assert!(render_template("Hi {{username", &variables).is_err());
+    }
+
+    #[test]
+    fn test_render_email_template() {
+        let variables = [("username", "Bob")];
+        assert_eq!(
+            render_email_template(
+                "Subject: Hi {{username}}\nHello {{username}}!\n",
+                &variables
+            )
+            .unwrap(),
+            Email {
+                subject: "Hi Bob".to_string(),
+                body: "Hello Bob!\n".to_string(),
+            }
+        );
+        assert!(render_email_template("Hello {{username}}!\n", &variables).is_err());
+        assert!(render_email_template("Subject: Hi {{name}}\nHello!\n", &variables).is_err());
+    }
+
+    #[test]
+    fn test_render_email_falls_back_to_default() {
+        let default = || Email {
+            subject: "Default".to_string(),
+            body: "Default body".to_string(),
+        };
+        assert_eq!(render_email(None, "en", &[], default), default());
+        assert_eq!(
+            render_email(Some("/does/not/exist"), "en", &[], default),
+            default()
+        );
+    }
+
+    #[test]
+    fn test_get_localized_template_paths() {
+        assert_eq!(
+            get_localized_template_paths("/data/reset.txt", "fr-CA"),
+            vec![
+                "/data/reset.fr-CA.txt",
+                "/data/reset.fr.txt",
+                "/data/reset.txt"
+            ]
+        );
+        assert_eq!(
+            get_localized_template_paths("/data.d/reset", "de"),
+            vec!["/data.d/reset.de", "/data.d/reset"]
+        );
+        assert_eq!(
+            get_localized_template_paths("/data/reset.txt", "../fr"),
+            vec!["/data/reset.txt"]
+        );
+        assert_eq!(
+            get_localized_template_paths("/data/reset.txt", ""),
+            vec!["/data/reset.txt"]
+        );
+    }
 }
@@ -0,0 +1,171 @@
+//! The membership report of the access reviews: who is in which group, as flat rows that the web
+//! UI exports to CSV.
+
+use std::collections::HashMap;
+
+use juniper::{GraphQLEnum, GraphQLObject};
+
+use crate::domain::types::{Group, User, UserAndGroups};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, GraphQLEnum)]
+pub enum MembershipReportView {
+    /// One row per (group, member), ordered by group. Empty groups get a single row.
+    #[default]
+    Group,
+    /// One row per (user, group), ordered by user. Users without groups get a single row.
+    User,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, GraphQLObject)]
+/// A row of the membership report. The fields of the missing side are null, for the empty groups
+/// or the users without groups.
+pub struct MembershipReportRow {
+    pub group: Option<String>,
+    pub user_id: Option<String>,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+}
+
+pub fn get_group_view_rows(
+    groups: &[Group],
+    users: &HashMap<String, User>,
+) -> Vec<MembershipReportRow> {
+    let mut rows = Vec::new();
+    for group in groups {
+        if group.users.is_empty() {
+            rows.push(MembershipReportRow {
+                group: Some(group.display_name.clone()),
+                ..Default::default()
+            });
+        }
+        for user_id in &group.users {
+            let user = users.get(user_id.as_str());
+            rows.push(MembershipReportRow {
+                group: Some(group.display_name.clone()),
+                user_id: Some(user_id.to_string()),
+                display_name: user.and_then(|u| u.display_name.clone()),
+                email: user.map(|u| u.email.clone()),
+            });
+        }
+    }
+    rows
+}
+
+pub fn get_user_view_rows(users: &[UserAndGroups]) -> Vec<MembershipReportRow> {
+    let mut rows = Vec::new();
+    for UserAndGroups { user, groups } in users {
+        let mut group_names = groups
+            .iter()
+            .flatten()
+            .map(|g| Some(g.display_name.clone()))
+            .collect::<Vec<_>>();
+        group_names.sort_unstable();
+        if group_names.is_empty() {
+            group_names.push(None);
+        }
+        for group in group_names {
+            rows.push(MembershipReportRow {
+                group,
+                user_id: Some(user.user_id.to_string()),
+                display_name: user.display_name.clone(),
+                email: Some(user.email.clone()),
+            });
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{GroupDetails, GroupId, UserId};
+    use crate::uuid;
+    use chrono::TimeZone;
+
+    fn make_row(group: &str, user_id: &str, display_name: &str) -> MembershipReportRow {
+        let non_empty = |s: &str| Some(s.to_owned()).filter(|s| !s.is_empty());
+        MembershipReportRow {
+            group: non_empty(group),
+            user_id: non_empty(user_id),
+            display_name: non_empty(display_name),
+            email: non_empty(user_id).map(|u| format!("{}@example.com", u)),
+        }
+    }
+
+    fn make_user(name: &str, display_name: &str) -> User {
+        User {
+            user_id: UserId::new(name),
+            email: format!("{}@example.com", name),
+            display_name: Some(display_name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn make_group(id: i32, name: &str, users: Vec<UserId>) -> Group {
+        Group {
+            id: GroupId(id),
+            display_name: name.to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            modified_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            users,
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_group_view() {
+        let users = vec![make_user("bob", "Bob, Jr."), make_user("john", "John")]
+            .into_iter()
+            .map(|u| (u.user_id.as_str().to_owned(), u))
+            .collect::<HashMap<_, _>>();
+        let groups = vec![
+            make_group(1, "admins", vec![UserId::new("bob"), UserId::new("john")]),
+            make_group(2, "empty", vec![]),
+        ];
+        assert_eq!(
+            get_group_view_rows(&groups, &users),
+            vec![
+                make_row("admins", "bob", "Bob, Jr."),
+                make_row("admins", "john", "John"),
+                make_row("empty", "", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user_view() {
+        let group = |id, name: &str| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.to_string(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        };
+        let users = vec![
+            UserAndGroups {
+                user: make_user("bob", "Bob"),
+                groups: Some(vec![group(2, "users"), group(1, "admins")]),
+            },
+            UserAndGroups {
+                user: make_user("john", "John"),
+                groups: Some(vec![]),
+            },
+        ];
+        assert_eq!(
+            get_user_view_rows(&users),
+            vec![
+                make_row("admins", "bob", "Bob"),
+                make_row("users", "bob", "Bob"),
+                make_row("", "john", "John"),
+            ]
+        );
+    }
+}
@@ -1,14 +1,30 @@
 pub mod auth_service;
+pub mod bind_limiter;
 pub mod cli;
 pub mod configuration;
+pub mod credentials;
 pub mod db_cleaner;
 pub mod graphql;
+pub mod group_assignment;
 pub mod healthcheck;
+pub mod jwt_keys;
 pub mod jwt_sql_tables;
+pub mod ldap_codec;
 pub mod ldap_handler;
 pub mod ldap_server;
 pub mod logging;
+pub mod login_challenges;
 pub mod mail;
+pub mod membership_report;
+pub mod oidc;
+pub mod sasl_gssapi;
+pub mod scim;
 pub mod sql_backend_handler;
+pub mod sso_login;
+pub mod stats;
 pub mod tcp_backend_handler;
 pub mod tcp_server;
+pub mod tls_certificates;
+pub mod totp_challenges;
+pub mod webauthn;
+pub mod webhooks;
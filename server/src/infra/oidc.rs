@@ -0,0 +1,898 @@
+//! A minimal OpenID Connect provider, so that the applications that speak OIDC can log their
+//! users in with LLDAP without a separate identity provider.
+//!
+//! Only the authorization code flow is supported, with PKCE (required for the clients without a
+//! secret). The ID tokens are signed with an RSA key, generated on the first start next to the
+//! server key, and published by the JWKS endpoint.
+//!
+//! The application sends the browser to `/oidc/authorize`, which redirects to the web UI: the
+//! user logs in there if needed, and the web UI then sends them back to the application with the
+//! code. The pending requests, the codes and the access tokens are only kept in memory, like the
+//! TOTP challenges: they are short-lived, and a restart only means logging in again.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_httpauth::extractors::{basic::BasicAuth, bearer::BearerAuth};
+use anyhow::{anyhow, Context, Result};
+use reqwest::Url;
+use rsa::{
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding},
+    Hash, PaddingScheme, PublicKeyParts, RsaPrivateKey,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument};
+
+use lldap_auth::oidc;
+
+use crate::{
+    domain::{
+        handler::BackendHandler,
+        types::{GroupDetails, User, UserId},
+    },
+    infra::{
+        auth_service::{
+            check_account_is_active, error_to_api_response, get_authenticated_user, ApiResult,
+        },
+        configuration::{write_to_readonly_file, OidcClient},
+        sql_backend_handler::gen_random_string,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+
+const KEY_SIZE: usize = 2048;
+/// How long the user has to log in to the web UI.
+const REQUEST_VALIDITY: Duration = Duration::from_secs(10 * 60);
+/// How long the application has to exchange the code for the tokens.
+const CODE_VALIDITY: Duration = Duration::from_secs(60);
+/// The validity of the ID tokens and of the access tokens.
+const TOKEN_VALIDITY: Duration = Duration::from_secs(60 * 60);
+
+const SUPPORTED_SCOPES: [&str; 4] = ["openid", "profile", "email", "groups"];
+
+#[derive(Deserialize)]
+struct AuthorizeParams {
+    response_type: String,
+    client_id: String,
+    redirect_uri: String,
+    #[serde(default)]
+    scope: String,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenParams {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    /// The client credentials, if they are not sent with HTTP basic auth.
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    code_verifier: Option<String>,
+}
+
+#[derive(Clone)]
+struct AuthorizationRequest {
+    client_id: String,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+}
+
+/// A request that the user approved by logging in. The code, then the access token, stand for it.
+#[derive(Clone)]
+struct Grant {
+    request: AuthorizationRequest,
+    user_id: UserId,
+}
+
+struct Expiring<T> {
+    value: T,
+    expiry: Instant,
+}
+
+/// Values to look up by a random key, forgotten after `validity`.
+pub(crate) struct ExpiringMap<T> {
+    entries: Mutex<HashMap<String, Expiring<T>>>,
+    validity: Duration,
+}
+
+impl<T: Clone> ExpiringMap<T> {
+    pub(crate) fn new(validity: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            validity,
+        }
+    }
+
+    pub(crate) fn insert_at(&self, value: T, now: Instant) -> String {
+        let key = gen_random_string(64);
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, e| e.expiry > now);
+        entries.insert(
+            key.clone(),
+            Expiring {
+                value,
+                expiry: now + self.validity,
+            },
+        );
+        key
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<T> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|e| e.expiry > now)
+            .map(|e| e.value.clone())
+    }
+
+    /// Removes the value, so that it can't be used again.
+    pub(crate) fn take_at(&self, key: &str, now: Instant) -> Option<T> {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(key)
+            .filter(|e| e.expiry > now)
+            .map(|e| e.value)
+    }
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// The S256 PKCE challenge of `verifier`.
+pub(crate) fn pkce_challenge(verifier: &str) -> String {
+    base64_url(&Sha256::digest(verifier.as_bytes()))
+}
+
+/// Adds the query parameters to `redirect_uri`, which can already have some.
+pub(crate) fn redirect_url(
+    redirect_uri: &str,
+    params: &[(&str, &str)],
+    state: Option<&str>,
+) -> TcpResult<String> {
+    let mut url = Url::parse(redirect_uri)
+        .map_err(|e| TcpError::BadRequest(format!("Invalid redirect_uri: {}", e)))?;
+    {
+        let mut query = url.query_pairs_mut();
+        for (name, value) in params {
+            query.append_pair(name, value);
+        }
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    Ok(url.to_string())
+}
+
+/// Reads the signing key generated on a previous start, or generates and stores a new one.
+/// `name` is the use of the key, for the messages.
+pub(crate) fn get_signing_key(path: &Path, name: &str) -> Result<RsaPrivateKey> {
+    if path.exists() {
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read {} key file `{}`", name, path.display()))?;
+        return RsaPrivateKey::from_pkcs8_pem(&pem)
+            .map_err(|e| anyhow!("Invalid {} key file `{}`: {}", name, path.display(), e));
+    }
+    let key = RsaPrivateKey::new(&mut rand::rngs::OsRng, KEY_SIZE)
+        .map_err(|e| anyhow!("Could not generate the {} signing key: {}", name, e))?;
+    let pem = key
+        .to_pkcs8_pem(LineEnding::LF)
+        .map_err(|e| anyhow!("Could not serialize the {} signing key: {}", name, e))?;
+    write_to_readonly_file(path, pem.as_bytes())
+        .with_context(|| format!("Could not write {} key file `{}`", name, path.display()))?;
+    info!(
+        "Generated a new {} signing key in `{}`",
+        name,
+        path.display()
+    );
+    Ok(key)
+}
+
+/// The claims about the user that the scopes of the request give access to. Like in the JWTs of
+/// the web UI, the archived groups are left out.
+fn get_user_claims(
+    scopes: &[String],
+    user: &User,
+    groups: &HashSet<GroupDetails>,
+) -> Map<String, Value> {
+    let has_scope = |scope: &str| scopes.iter().any(|s| s == scope);
+    let mut claims = Map::new();
+    claims.insert("sub".to_string(), json!(user.user_id.as_str()));
+    if has_scope("profile") {
+        claims.insert(
+            "preferred_username".to_string(),
+            json!(user.user_id.as_str()),
+        );
+        if let Some(name) = &user.display_name {
+            claims.insert("name".to_string(), json!(name));
+        }
+        if let Some(first_name) = &user.first_name {
+            claims.insert("given_name".to_string(), json!(first_name));
+        }
+        if let Some(last_name) = &user.last_name {
+            claims.insert("family_name".to_string(), json!(last_name));
+        }
+    }
+    if has_scope("email") {
+        claims.insert("email".to_string(), json!(user.email));
+    }
+    if has_scope("groups") {
+        let mut group_names = groups
+            .iter()
+            .filter(|g| !g.archived)
+            .map(|g| g.display_name.as_str())
+            .collect::<Vec<_>>();
+        group_names.sort_unstable();
+        claims.insert("groups".to_string(), json!(group_names));
+    }
+    claims
+}
+
+pub struct OidcState {
+    /// The `http_url` of the server, without a trailing slash.
+    issuer: String,
+    clients: Vec<OidcClient>,
+    key: RsaPrivateKey,
+    key_id: String,
+    requests: ExpiringMap<AuthorizationRequest>,
+    codes: ExpiringMap<Grant>,
+    access_tokens: ExpiringMap<Grant>,
+}
+
+impl OidcState {
+    /// Reads the signing key from `key_path`, or generates it.
+    pub fn new(issuer: &str, clients: Vec<OidcClient>, key_path: &Path) -> Result<Self> {
+        Ok(Self::with_key(
+            issuer,
+            clients,
+            get_signing_key(key_path, "OIDC")?,
+        ))
+    }
+
+    fn with_key(issuer: &str, clients: Vec<OidcClient>, key: RsaPrivateKey) -> Self {
+        // Changes with the key, so that the applications know to fetch the new one.
+        let key_id = base64_url(&Sha256::digest(&key.n().to_bytes_be()))[..16].to_string();
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            clients,
+            key,
+            key_id,
+            requests: ExpiringMap::new(REQUEST_VALIDITY),
+            codes: ExpiringMap::new(CODE_VALIDITY),
+            access_tokens: ExpiringMap::new(TOKEN_VALIDITY),
+        }
+    }
+
+    fn get_client(&self, client_id: &str) -> Option<&OidcClient> {
+        self.clients.iter().find(|c| c.client_id == client_id)
+    }
+
+    /// Checks the request of the application, and returns where to send the browser: to the web
+    /// UI so that the user logs in, or back to the application with an error.
+    fn start_authorization_at(&self, params: AuthorizeParams, now: Instant) -> TcpResult<String> {
+        let client = self.get_client(&params.client_id).ok_or_else(|| {
+            TcpError::BadRequest(format!("Unknown OIDC client `{}`", params.client_id))
+        })?;
+        // Never send the browser to a URL that wasn't registered, not even with an error.
+        if !client.redirect_uris.contains(&params.redirect_uri) {
+            return Err(TcpError::BadRequest(format!(
+                "Invalid redirect_uri for OIDC client `{}`",
+                client.client_id
+            )));
+        }
+        let error = |error: &str| {
+            redirect_url(
+                &params.redirect_uri,
+                &[("error", error)],
+                params.state.as_deref(),
+            )
+        };
+        if params.response_type != "code" {
+            return error("unsupported_response_type");
+        }
+        let scopes = params
+            .scope
+            .split_whitespace()
+            .filter(|s| SUPPORTED_SCOPES.contains(s))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        if !scopes.iter().any(|s| s == "openid") {
+            return error("invalid_scope");
+        }
+        match (
+            &params.code_challenge,
+            params.code_challenge_method.as_deref(),
+        ) {
+            (Some(_), Some("S256")) => {}
+            (None, _) if client.client_secret.is_some() => {}
+            _ => return error("invalid_request"),
+        }
+        let request_id = self.requests.insert_at(
+            AuthorizationRequest {
+                client_id: params.client_id,
+                redirect_uri: params.redirect_uri,
+                scopes,
+                state: params.state,
+                nonce: params.nonce,
+                code_challenge: params.code_challenge,
+            },
+            now,
+        );
+        Ok(format!("/single-sign-on/{}", request_id))
+    }
+
+    /// Called once the user logged in to the web UI: returns the URL of the application, with
+    /// the code to exchange for the tokens.
+    fn authorize_at(&self, request_id: &str, user_id: UserId, now: Instant) -> TcpResult<String> {
+        let request = self.requests.take_at(request_id, now).ok_or_else(|| {
+            TcpError::BadRequest(
+                "The login request expired, go back to the application to try again".to_string(),
+            )
+        })?;
+        let redirect_uri = request.redirect_uri.clone();
+        let state = request.state.clone();
+        let code = self.codes.insert_at(Grant { request, user_id }, now);
+        redirect_url(&redirect_uri, &[("code", code.as_str())], state.as_deref())
+    }
+
+    /// Checks the code and the credentials of the application.
+    fn exchange_code_at(
+        &self,
+        params: &TokenParams,
+        client_id: &str,
+        client_secret: Option<&str>,
+        now: Instant,
+    ) -> TcpResult<Grant> {
+        if params.grant_type != "authorization_code" {
+            return Err(TcpError::BadRequest(format!(
+                "Unsupported grant_type `{}`",
+                params.grant_type
+            )));
+        }
+        let client = self
+            .get_client(client_id)
+            .ok_or_else(|| TcpError::UnauthorizedError("Unknown OIDC client".to_string()))?;
+        if let Some(secret) = &client.client_secret {
+            let is_valid = client_secret
+                .map(|s| {
+                    orion::util::secure_cmp(s.as_bytes(), secret.unsecure().as_bytes()).is_ok()
+                })
+                .unwrap_or(false);
+            if !is_valid {
+                return Err(TcpError::UnauthorizedError(
+                    "Invalid client secret".to_string(),
+                ));
+            }
+        }
+        // The code can only be used once, even if the exchange fails.
+        let grant = self
+            .codes
+            .take_at(&params.code, now)
+            .ok_or_else(|| TcpError::BadRequest("Invalid or expired code".to_string()))?;
+        if grant.request.client_id != client.client_id
+            || grant.request.redirect_uri != params.redirect_uri
+        {
+            return Err(TcpError::BadRequest(
+                "The code was issued for another client or redirect_uri".to_string(),
+            ));
+        }
+        if let Some(challenge) = &grant.request.code_challenge {
+            match &params.code_verifier {
+                Some(verifier) if pkce_challenge(verifier) == *challenge => {}
+                _ => return Err(TcpError::BadRequest("Invalid code_verifier".to_string())),
+            }
+        }
+        Ok(grant)
+    }
+
+    /// Signs the claims into a JWT with RS256.
+    fn sign(&self, claims: &Value) -> TcpResult<String> {
+        let header = json!({ "alg": "RS256", "typ": "JWT", "kid": self.key_id });
+        let message = format!(
+            "{}.{}",
+            base64_url(header.to_string().as_bytes()),
+            base64_url(claims.to_string().as_bytes())
+        );
+        let signature = self
+            .key
+            .sign(
+                PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256)),
+                &Sha256::digest(message.as_bytes()),
+            )
+            .map_err(|e| TcpError::InternalServerError(format!("Could not sign: {}", e)))?;
+        Ok(format!("{}.{}", message, base64_url(&signature)))
+    }
+
+    fn jwks(&self) -> Value {
+        json!({
+            "keys": [{
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": self.key_id,
+                "n": base64_url(&self.key.n().to_bytes_be()),
+                "e": base64_url(&self.key.e().to_bytes_be()),
+            }]
+        })
+    }
+
+    fn discovery_document(&self) -> Value {
+        let endpoint = |path: &str| format!("{}/oidc/{}", self.issuer, path);
+        json!({
+            "issuer": self.issuer,
+            "authorization_endpoint": endpoint("authorize"),
+            "token_endpoint": endpoint("token"),
+            "userinfo_endpoint": endpoint("userinfo"),
+            "jwks_uri": endpoint("jwks"),
+            "response_types_supported": ["code"],
+            "grant_types_supported": ["authorization_code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"],
+            "scopes_supported": SUPPORTED_SCOPES,
+            "token_endpoint_auth_methods_supported":
+                ["client_secret_basic", "client_secret_post", "none"],
+            "code_challenge_methods_supported": ["S256"],
+            "claims_supported": [
+                "sub", "iss", "aud", "exp", "iat", "nonce", "preferred_username", "name",
+                "given_name", "family_name", "email", "groups",
+            ],
+        })
+    }
+}
+
+fn get_oidc<Backend>(data: &AppState<Backend>) -> TcpResult<&OidcState> {
+    data.oidc
+        .as_deref()
+        .ok_or_else(|| TcpError::BadRequest("The OpenID Connect provider is disabled".to_string()))
+}
+
+/// The errors of the token endpoint, in the format of OAuth 2.0.
+fn token_error_response(error: TcpError) -> HttpResponse {
+    let (mut response, code) = match &error {
+        TcpError::UnauthorizedError(_) => (HttpResponse::Unauthorized(), "invalid_client"),
+        TcpError::BadRequest(_) => (HttpResponse::BadRequest(), "invalid_grant"),
+        _ => (HttpResponse::InternalServerError(), "server_error"),
+    };
+    response.json(json!({ "error": code, "error_description": error.to_string() }))
+}
+
+async fn get_authorize_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    params: web::Query<AuthorizeParams>,
+) -> HttpResponse {
+    get_oidc(&data)
+        .and_then(|oidc| oidc.start_authorization_at(params.into_inner(), Instant::now()))
+        .map(|location| {
+            HttpResponse::Found()
+                .append_header(("Location", location))
+                .finish()
+        })
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_authorize<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    request_id: web::Path<String>,
+) -> TcpResult<oidc::ServerAuthorizeResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let oidc = get_oidc(&data)?;
+    let user_id = get_authenticated_user(&data, &request).await?;
+    check_account_is_active(&data, &user_id).await?;
+    let redirect_url = oidc.authorize_at(&request_id, user_id.clone(), Instant::now())?;
+    info!("{} logged in to an application with OIDC", user_id);
+    Ok(oidc::ServerAuthorizeResponse { redirect_url })
+}
+
+async fn post_authorize_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    request_id: web::Path<String>,
+) -> ApiResult<oidc::ServerAuthorizeResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    post_authorize(data, request, request_id)
+        .await
+        .map(|res| ApiResult::Left(web::Json(res)))
+        .unwrap_or_else(error_to_api_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn post_token<Backend>(
+    data: web::Data<AppState<Backend>>,
+    basic_auth: Option<BasicAuth>,
+    params: web::Form<TokenParams>,
+) -> TcpResult<HttpResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let oidc = get_oidc(&data)?;
+    let (client_id, client_secret) = match &basic_auth {
+        Some(auth) => (&**auth.user_id(), auth.password().map(|p| &**p)),
+        None => (
+            params.client_id.as_deref().unwrap_or_default(),
+            params.client_secret.as_deref(),
+        ),
+    };
+    let now = Instant::now();
+    let grant = oidc.exchange_code_at(&params, client_id, client_secret, now)?;
+    // The account may have been disabled since the user logged in to the web UI.
+    check_account_is_active(&data, &grant.user_id).await?;
+    let user = data
+        .backend_handler
+        .get_user_details(&grant.user_id)
+        .await?;
+    let groups = data.backend_handler.get_user_groups(&grant.user_id).await?;
+    let mut claims = get_user_claims(&grant.request.scopes, &user, &groups);
+    let issued_at = chrono::Utc::now().timestamp();
+    claims.insert("iss".to_string(), json!(oidc.issuer));
+    claims.insert("aud".to_string(), json!(grant.request.client_id));
+    claims.insert("iat".to_string(), json!(issued_at));
+    claims.insert(
+        "exp".to_string(),
+        json!(issued_at + TOKEN_VALIDITY.as_secs() as i64),
+    );
+    if let Some(nonce) = &grant.request.nonce {
+        claims.insert("nonce".to_string(), json!(nonce));
+    }
+    let id_token = oidc.sign(&Value::Object(claims))?;
+    let scope = grant.request.scopes.join(" ");
+    let access_token = oidc.access_tokens.insert_at(grant, now);
+    Ok(HttpResponse::Ok()
+        .append_header(("Cache-Control", "no-store"))
+        .json(json!({
+            "access_token": access_token,
+            "token_type": "Bearer",
+            "expires_in": TOKEN_VALIDITY.as_secs(),
+            "id_token": id_token,
+            "scope": scope,
+        })))
+}
+
+async fn post_token_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    basic_auth: Option<BasicAuth>,
+    params: web::Form<TokenParams>,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    post_token(data, basic_auth, params)
+        .await
+        .unwrap_or_else(token_error_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_userinfo<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> TcpResult<HttpResponse>
+where
+    Backend: BackendHandler + 'static,
+{
+    let grant = get_oidc(&data)?
+        .access_tokens
+        .get_at(bearer.token(), Instant::now())
+        .ok_or_else(|| TcpError::UnauthorizedError("Invalid access token".to_string()))?;
+    // The access token outlives the account if it's disabled in the meantime.
+    check_account_is_active(&data, &grant.user_id).await?;
+    let user = data
+        .backend_handler
+        .get_user_details(&grant.user_id)
+        .await?;
+    let groups = data.backend_handler.get_user_groups(&grant.user_id).await?;
+    Ok(HttpResponse::Ok().json(get_user_claims(&grant.request.scopes, &user, &groups)))
+}
+
+async fn get_userinfo_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    bearer: BearerAuth,
+) -> HttpResponse
+where
+    Backend: BackendHandler + 'static,
+{
+    get_userinfo(data, bearer)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+async fn get_jwks_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    get_oidc(&data)
+        .map(|oidc| HttpResponse::Ok().json(oidc.jwks()))
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub async fn get_discovery_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    get_oidc(&data)
+        .map(|oidc| HttpResponse::Ok().json(oidc.discovery_document()))
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + 'static,
+{
+    cfg.service(web::resource("/authorize").route(web::get().to(get_authorize_handler::<Backend>)))
+        .service(
+            web::resource("/authorize/{request_id}")
+                // The web UI only has the JWT in the cookie.
+                .wrap(super::auth_service::CookieToHeaderTranslatorFactory)
+                .route(web::post().to(post_authorize_handler::<Backend>)),
+        )
+        .service(web::resource("/token").route(web::post().to(post_token_handler::<Backend>)))
+        .service(
+            web::resource("/userinfo")
+                .route(web::get().to(get_userinfo_handler::<Backend>))
+                .route(web::post().to(get_userinfo_handler::<Backend>)),
+        )
+        .service(web::resource("/jwks").route(web::get().to(get_jwks_handler::<Backend>)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::GroupId;
+    use chrono::TimeZone;
+    use rsa::PublicKey;
+    use secstr::SecUtf8;
+
+    fn make_state() -> OidcState {
+        let key = RsaPrivateKey::new(&mut rand::rngs::OsRng, 1024).unwrap();
+        OidcState::with_key(
+            "https://ldap.example.com/",
+            vec![
+                OidcClient {
+                    client_id: "app".to_string(),
+                    client_secret: Some(SecUtf8::from("secret")),
+                    redirect_uris: vec!["https://app.example.com/callback".to_string()],
+                },
+                OidcClient {
+                    client_id: "spa".to_string(),
+                    client_secret: None,
+                    redirect_uris: vec!["https://spa.example.com/callback?a=b".to_string()],
+                },
+            ],
+            key,
+        )
+    }
+
+    fn authorize_params(client_id: &str, redirect_uri: &str) -> AuthorizeParams {
+        AuthorizeParams {
+            response_type: "code".to_string(),
+            client_id: client_id.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            scope: "openid groups offline_access".to_string(),
+            state: Some("xyz".to_string()),
+            nonce: Some("n-0S6".to_string()),
+            code_challenge: None,
+            code_challenge_method: None,
+        }
+    }
+
+    fn token_params(code: &str, redirect_uri: &str, verifier: Option<&str>) -> TokenParams {
+        TokenParams {
+            grant_type: "authorization_code".to_string(),
+            code: code.to_string(),
+            redirect_uri: redirect_uri.to_string(),
+            client_id: None,
+            client_secret: None,
+            code_verifier: verifier.map(str::to_owned),
+        }
+    }
+
+    fn get_query_param(url: &str, name: &str) -> Option<String> {
+        Url::parse(url)
+            .unwrap()
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned())
+    }
+
+    #[test]
+    fn test_user_claims_skip_archived_groups() {
+        let make_group = |id, name: &str, archived| GroupDetails {
+            group_id: GroupId(id),
+            display_name: name.to_owned(),
+            creation_date: chrono::Utc.timestamp_opt(42, 42).unwrap(),
+            uuid: crate::uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        };
+        let user = User {
+            user_id: UserId::new("bob"),
+            ..Default::default()
+        };
+        let groups = [make_group(3, "family", false), make_group(4, "old", true)].into();
+        let claims = get_user_claims(&["openid".to_owned(), "groups".to_owned()], &user, &groups);
+        assert_eq!(claims["groups"], json!(["family"]));
+    }
+
+    #[test]
+    fn test_pkce_challenge() {
+        // From RFC 7636, appendix B.
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_start_authorization() {
+        let state = make_state();
+        let now = Instant::now();
+        let callback = "https://app.example.com/callback";
+        state
+            .start_authorization_at(authorize_params("unknown", callback), now)
+            .unwrap_err();
+        state
+            .start_authorization_at(authorize_params("app", "https://evil.com/callback"), now)
+            .unwrap_err();
+        let mut params = authorize_params("app", callback);
+        params.response_type = "token".to_string();
+        let location = state.start_authorization_at(params, now).unwrap();
+        assert_eq!(
+            location,
+            "https://app.example.com/callback?error=unsupported_response_type&state=xyz"
+        );
+        // The clients without a secret have to use PKCE.
+        let spa_callback = "https://spa.example.com/callback?a=b";
+        let location = state
+            .start_authorization_at(authorize_params("spa", spa_callback), now)
+            .unwrap();
+        assert_eq!(
+            get_query_param(&location, "error").as_deref(),
+            Some("invalid_request")
+        );
+        let location = state
+            .start_authorization_at(authorize_params("app", callback), now)
+            .unwrap();
+        assert!(location.starts_with("/single-sign-on/"));
+    }
+
+    #[test]
+    fn test_code_flow_with_pkce() {
+        let state = make_state();
+        let now = Instant::now();
+        let callback = "https://spa.example.com/callback?a=b";
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        let mut params = authorize_params("spa", callback);
+        params.code_challenge = Some(pkce_challenge(verifier));
+        params.code_challenge_method = Some("S256".to_string());
+        let location = state.start_authorization_at(params, now).unwrap();
+        let request_id = location.trim_start_matches("/single-sign-on/");
+        let redirect = state
+            .authorize_at(request_id, UserId::new("bob"), now)
+            .unwrap();
+        assert_eq!(get_query_param(&redirect, "a").as_deref(), Some("b"));
+        assert_eq!(get_query_param(&redirect, "state").as_deref(), Some("xyz"));
+        // The request can only be approved once.
+        state
+            .authorize_at(request_id, UserId::new("bob"), now)
+            .unwrap_err();
+        let code = get_query_param(&redirect, "code").unwrap();
+        let grant = state
+            .exchange_code_at(
+                &token_params(&code, callback, Some(verifier)),
+                "spa",
+                None,
+                now,
+            )
+            .unwrap();
+        assert_eq!(grant.user_id, UserId::new("bob"));
+        assert_eq!(grant.request.scopes, vec!["openid", "groups"]);
+        assert_eq!(grant.request.nonce.as_deref(), Some("n-0S6"));
+        // The code can only be used once.
+        state
+            .exchange_code_at(
+                &token_params(&code, callback, Some(verifier)),
+                "spa",
+                None,
+                now,
+            )
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_exchange_code_errors() {
+        let state = make_state();
+        let now = Instant::now();
+        let callback = "https://app.example.com/callback";
+        let get_code = || {
+            let location = state
+                .start_authorization_at(authorize_params("app", callback), now)
+                .unwrap();
+            let redirect = state
+                .authorize_at(
+                    location.trim_start_matches("/single-sign-on/"),
+                    UserId::new("bob"),
+                    now,
+                )
+                .unwrap();
+            get_query_param(&redirect, "code").unwrap()
+        };
+        let code = get_code();
+        let params = token_params(&code, callback, None);
+        state
+            .exchange_code_at(&params, "app", Some("wrong"), now)
+            .unwrap_err();
+        state
+            .exchange_code_at(&params, "spa", None, now)
+            .unwrap_err();
+        let code = get_code();
+        state
+            .exchange_code_at(
+                &token_params(&code, "https://app.example.com/other", None),
+                "app",
+                Some("secret"),
+                now,
+            )
+            .unwrap_err();
+        let code = get_code();
+        state
+            .exchange_code_at(
+                &token_params(&code, callback, None),
+                "app",
+                Some("secret"),
+                now + CODE_VALIDITY,
+            )
+            .unwrap_err();
+        let code = get_code();
+        state
+            .exchange_code_at(
+                &token_params(&code, callback, None),
+                "app",
+                Some("secret"),
+                now,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_sign() {
+        let state = make_state();
+        let token = state.sign(&json!({ "sub": "bob" })).unwrap();
+        let parts = token.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+        let header: Value = serde_json::from_slice(
+            &base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(header["alg"], "RS256");
+        assert_eq!(header["kid"], state.jwks()["keys"][0]["kid"]);
+        state
+            .key
+            .to_public_key()
+            .verify(
+                PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256)),
+                &Sha256::digest(format!("{}.{}", parts[0], parts[1]).as_bytes()),
+                &base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            state.discovery_document()["issuer"],
+            "https://ldap.example.com"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! The SASL GSSAPI mechanism (RFC 4752), to bind with a Kerberos ticket.
+//!
+//! The GSS-API exchange itself is left to the system's Kerberos library, with the `gssapi`
+//! feature. Like any Kerberos service, the server finds the key of its `ldap/<hostname>`
+//! principal in the default keytab, or in the one given by `KRB5_KTNAME`. Only the "no security
+//! layer" option is offered: the connection can be protected with TLS instead.
+
+use crate::domain::types::UserId;
+use anyhow::{bail, Result};
+use std::sync::Arc;
+
+/// An acceptor security context, established from the tokens of the client.
+pub trait GssapiContext: Send {
+    /// Processes a token of the client, and returns the one to send back, if any.
+    fn step(&mut self, token: &[u8]) -> Result<Option<Vec<u8>>>;
+    fn is_complete(&self) -> bool;
+    /// The principal of the client, once the context is complete.
+    fn source_name(&self) -> Result<String>;
+    fn wrap(&mut self, message: &[u8]) -> Result<Vec<u8>>;
+    fn unwrap(&mut self, message: &[u8]) -> Result<Vec<u8>>;
+}
+
+pub type GssapiContextFactory = Arc<dyn Fn() -> Result<Box<dyn GssapiContext>> + Send + Sync>;
+
+/// The Kerberos settings of the GSSAPI binds.
+#[derive(Clone)]
+pub struct KerberosOptions {
+    /// Only the principals of this realm are mapped to users.
+    pub realm: String,
+    pub new_context: GssapiContextFactory,
+}
+
+/// The bit of the "no security layer" option (RFC 4752, section 3.3).
+const NO_SECURITY_LAYER: u8 = 1;
+
+pub enum GssapiStep {
+    /// The exchange goes on, with this token for the client.
+    Continue(Vec<u8>),
+    /// The client is authenticated, and asked for this authorization identity, if any.
+    Done {
+        principal: String,
+        authz_id: Option<Vec<u8>>,
+    },
+}
+
+enum State {
+    Negotiating,
+    /// The context is complete, but the client still has to acknowledge its last token.
+    WaitingForClient,
+    SecurityLayer,
+}
+
+/// The state of a GSSAPI bind, across the bind requests of the client.
+pub struct GssapiBind {
+    context: Box<dyn GssapiContext>,
+    state: State,
+}
+
+impl GssapiBind {
+    pub fn new(context: Box<dyn GssapiContext>) -> Self {
+        Self {
+            context,
+            state: State::Negotiating,
+        }
+    }
+
+    /// The available security layers with the maximum size of the messages, which is 0 without
+    /// any layer.
+    fn offer_security_layers(&mut self) -> Result<GssapiStep> {
+        self.state = State::SecurityLayer;
+        Ok(GssapiStep::Continue(self.context.wrap(&[
+            NO_SECURITY_LAYER,
+            0,
+            0,
+            0,
+        ])?))
+    }
+
+    /// Processes the credentials of a bind request.
+    pub fn step(&mut self, token: &[u8]) -> Result<GssapiStep> {
+        match self.state {
+            State::Negotiating => {
+                let reply = self.context.step(token)?;
+                if !self.context.is_complete() {
+                    return Ok(GssapiStep::Continue(reply.unwrap_or_default()));
+                }
+                match reply {
+                    Some(reply) => {
+                        self.state = State::WaitingForClient;
+                        Ok(GssapiStep::Continue(reply))
+                    }
+                    None => self.offer_security_layers(),
+                }
+            }
+            State::WaitingForClient => self.offer_security_layers(),
+            State::SecurityLayer => {
+                let message = self.context.unwrap(token)?;
+                if message.len() < 4 {
+                    bail!("Invalid security layer message");
+                }
+                if message[0] & NO_SECURITY_LAYER == 0 {
+                    bail!("Only the connections without a security layer are supported");
+                }
+                Ok(GssapiStep::Done {
+                    principal: self.context.source_name()?,
+                    authz_id: Some(message[4..].to_vec()).filter(|authz_id| !authz_id.is_empty()),
+                })
+            }
+        }
+    }
+}
+
+/// Maps a principal of the form "user@REALM" to the user ID. The principals of another realm
+/// and the ones of services ("service/host@REALM") don't match any user.
+pub fn get_user_id_from_principal(principal: &str, realm: &str) -> Option<UserId> {
+    let (name, principal_realm) = principal.rsplit_once('@')?;
+    if principal_realm != realm || name.is_empty() || name.contains('/') {
+        return None;
+    }
+    Some(UserId::new(name))
+}
+
+#[cfg(feature = "gssapi")]
+mod kerberos {
+    use super::GssapiContext;
+    use anyhow::Result;
+    use libgssapi::{
+        context::{SecurityContext, ServerCtx},
+        credential::{Cred, CredUsage},
+        oid::{OidSet, GSS_MECH_KRB5},
+    };
+
+    struct KerberosContext(ServerCtx);
+
+    impl GssapiContext for KerberosContext {
+        fn step(&mut self, token: &[u8]) -> Result<Option<Vec<u8>>> {
+            Ok(self.0.step(token)?.map(|token| token.to_vec()))
+        }
+
+        fn is_complete(&self) -> bool {
+            self.0.is_complete()
+        }
+
+        fn source_name(&self) -> Result<String> {
+            Ok(self.0.source_name()?.to_string())
+        }
+
+        fn wrap(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+            Ok(self.0.wrap(false, message)?.to_vec())
+        }
+
+        fn unwrap(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+            Ok(self.0.unwrap(message)?.to_vec())
+        }
+    }
+
+    /// A context accepting the Kerberos tickets of any service principal of the keytab.
+    pub fn new_kerberos_context() -> Result<Box<dyn GssapiContext>> {
+        let mut mechanisms = OidSet::new()?;
+        mechanisms.add(&GSS_MECH_KRB5)?;
+        let credentials = Cred::acquire(None, None, CredUsage::Accept, Some(&mechanisms))?;
+        Ok(Box::new(KerberosContext(ServerCtx::new(credentials))))
+    }
+}
+
+#[cfg(feature = "gssapi")]
+pub use kerberos::new_kerberos_context;
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A context that is complete after the client's tokens "first" and "second", and answers
+    /// "challenge" to the first one. Wrapping is the identity.
+    pub struct FakeGssapiContext {
+        pub principal: String,
+        pub steps: usize,
+    }
+
+    impl GssapiContext for FakeGssapiContext {
+        fn step(&mut self, token: &[u8]) -> Result<Option<Vec<u8>>> {
+            match (self.steps, token) {
+                (0, b"first") => {
+                    self.steps = 1;
+                    Ok(Some(b"challenge".to_vec()))
+                }
+                (1, b"second") => {
+                    self.steps = 2;
+                    Ok(None)
+                }
+                _ => bail!("Unexpected token"),
+            }
+        }
+
+        fn is_complete(&self) -> bool {
+            self.steps == 2
+        }
+
+        fn source_name(&self) -> Result<String> {
+            Ok(self.principal.clone())
+        }
+
+        fn wrap(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+            Ok(message.to_vec())
+        }
+
+        fn unwrap(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+            Ok(message.to_vec())
+        }
+    }
+
+    pub fn make_fake_context(principal: &str) -> Box<dyn GssapiContext> {
+        Box::new(FakeGssapiContext {
+            principal: principal.to_string(),
+            steps: 0,
+        })
+    }
+
+    fn expect_continue(step: Result<GssapiStep>) -> Vec<u8> {
+        match step.unwrap() {
+            GssapiStep::Continue(token) => token,
+            GssapiStep::Done { .. } => panic!("The exchange should go on"),
+        }
+    }
+
+    #[test]
+    fn test_gssapi_bind() {
+        let mut bind = GssapiBind::new(make_fake_context("bob@EXAMPLE.COM"));
+        assert_eq!(expect_continue(bind.step(b"first")), b"challenge".to_vec());
+        assert_eq!(
+            expect_continue(bind.step(b"second")),
+            vec![NO_SECURITY_LAYER, 0, 0, 0]
+        );
+        match bind.step(b"\x01\0\0\0u:bob").unwrap() {
+            GssapiStep::Done {
+                principal,
+                authz_id,
+            } => {
+                assert_eq!(principal, "bob@EXAMPLE.COM");
+                assert_eq!(authz_id, Some(b"u:bob".to_vec()));
+            }
+            GssapiStep::Continue(_) => panic!("The exchange should be over"),
+        }
+    }
+
+    #[test]
+    fn test_gssapi_bind_with_security_layer() {
+        let mut bind = GssapiBind::new(make_fake_context("bob@EXAMPLE.COM"));
+        expect_continue(bind.step(b"first"));
+        expect_continue(bind.step(b"second"));
+        // Integrity protection only.
+        assert!(bind.step(b"\x02\0\x10\0").is_err());
+    }
+
+    #[test]
+    fn test_gssapi_bind_with_invalid_token() {
+        let mut bind = GssapiBind::new(make_fake_context("bob@EXAMPLE.COM"));
+        assert!(bind.step(b"second").is_err());
+    }
+
+    #[test]
+    fn test_get_user_id_from_principal() {
+        assert_eq!(
+            get_user_id_from_principal("Bob@EXAMPLE.COM", "EXAMPLE.COM"),
+            Some(UserId::new("bob"))
+        );
+        assert_eq!(
+            get_user_id_from_principal("bob@OTHER.COM", "EXAMPLE.COM"),
+            None
+        );
+        assert_eq!(
+            get_user_id_from_principal("host/client.example.com@EXAMPLE.COM", "EXAMPLE.COM"),
+            None
+        );
+        assert_eq!(get_user_id_from_principal("bob", "EXAMPLE.COM"), None);
+    }
+}
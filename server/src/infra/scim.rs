@@ -0,0 +1,1664 @@
+//! A SCIM 2.0 server (RFC 7643 and 7644), for the provisioning tools like Azure AD to create,
+//! update and delete the users and the groups with an API token.
+//!
+//! The users are identified by their user ID, and the groups by their numeric ID. Only the `eq`
+//! filters are supported, and the passwords can't be provisioned: the users reset them by email.
+
+use std::collections::HashSet;
+
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Map, Value};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{
+            AuditEvent, BackendHandler, CreateUserRequest, GroupBackendHandler, GroupRequestFilter,
+            UpdateGroupRequest, UpdateUserRequest, UserBackendHandler, UserRequestFilter,
+        },
+        types::{DateTime, Group, GroupDetails, GroupId, User, UserColumn, UserId},
+    },
+    infra::{
+        auth_service::{
+            check_if_token_or_api_token_is_valid, set_account_enabled, ValidationResults,
+        },
+        graphql::mutation::{add_membership, is_valid_email},
+        group_assignment::apply_group_assignment_rules,
+        tcp_server::AppState,
+    },
+};
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const CONTENT_TYPE: &str = "application/scim+json";
+
+/// The maximum number of resources in a page of a listing, also the default page size.
+const MAX_RESULTS: usize = 1000;
+
+#[derive(Debug, PartialEq, Eq)]
+struct ScimError {
+    status: StatusCode,
+    /// The `scimType` of the errors with a 400 status.
+    scim_type: Option<&'static str>,
+    detail: String,
+}
+
+impl ScimError {
+    fn new(status: StatusCode, scim_type: Option<&'static str>, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            scim_type,
+            detail: detail.into(),
+        }
+    }
+
+    fn invalid_value(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, Some("invalidValue"), detail)
+    }
+
+    fn invalid_filter(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, Some("invalidFilter"), detail)
+    }
+
+    fn invalid_path(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, Some("invalidPath"), detail)
+    }
+
+    fn mutability(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, Some("mutability"), detail)
+    }
+
+    fn forbidden(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, None, detail)
+    }
+
+    fn not_found(detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, None, detail)
+    }
+}
+
+impl From<DomainError> for ScimError {
+    fn from(error: DomainError) -> Self {
+        let (status, scim_type) = match error {
+            DomainError::AuthenticationError(_) | DomainError::AuthenticationProtocolError(_) => {
+                (StatusCode::UNAUTHORIZED, None)
+            }
+            DomainError::DatabaseError(_)
+            | DomainError::InternalError(_)
+            | DomainError::UnknownCryptoError(_) => (StatusCode::INTERNAL_SERVER_ERROR, None),
+            DomainError::EntityNotFound(_) => (StatusCode::NOT_FOUND, None),
+            DomainError::EntityAlreadyExists(_) => (StatusCode::CONFLICT, Some("uniqueness")),
+            DomainError::Base64DecodeError(_)
+            | DomainError::BinarySerializationError(_)
+            | DomainError::InvalidInput(_) => (StatusCode::BAD_REQUEST, Some("invalidValue")),
+        };
+        Self::new(status, scim_type, error.to_string())
+    }
+}
+
+impl std::fmt::Display for ScimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.detail)
+    }
+}
+
+impl ResponseError for ScimError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": self.status.as_u16().to_string(),
+            "detail": self.detail,
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = json!(scim_type);
+        }
+        HttpResponse::build(self.status)
+            .content_type(CONTENT_TYPE)
+            .body(body.to_string())
+    }
+}
+
+type ScimResult<T> = std::result::Result<T, ScimError>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+struct ScimName {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    given_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    family_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct ScimEmail {
+    value: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    type_: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_lenient_bool")]
+    primary: bool,
+}
+
+/// A member of a group, or a group of a user.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct ScimReference {
+    value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display: Option<String>,
+    #[serde(rename = "$ref", default, skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ScimMeta {
+    resource_type: &'static str,
+    created: String,
+    last_modified: String,
+    location: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ScimUser {
+    #[serde(default)]
+    schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    user_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<ScimName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    #[serde(default)]
+    emails: Vec<ScimEmail>,
+    /// Whether the account can currently be used to log in, from its validity dates.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_lenient_bool",
+        skip_serializing_if = "Option::is_none"
+    )]
+    active: Option<bool>,
+    /// Read-only: the memberships are changed through the groups.
+    #[serde(default, skip_deserializing)]
+    groups: Vec<ScimReference>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    meta: Option<ScimMeta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct ScimGroup {
+    #[serde(default)]
+    schemas: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    display_name: String,
+    #[serde(default)]
+    members: Vec<ScimReference>,
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    meta: Option<ScimMeta>,
+}
+
+/// Some clients, like Azure AD, send the booleans as strings.
+fn parse_lenient_bool<E: serde::de::Error>(value: Value) -> Result<Option<bool>, E> {
+    match value {
+        Value::Null => Ok(None),
+        Value::Bool(b) => Ok(Some(b)),
+        Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(Some(true)),
+        Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(Some(false)),
+        other => Err(E::custom(format!("Invalid boolean: {}", other))),
+    }
+}
+
+fn deserialize_optional_lenient_bool<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<bool>, D::Error> {
+    parse_lenient_bool(Value::deserialize(deserializer)?)
+}
+
+fn deserialize_lenient_bool<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(parse_lenient_bool(Value::deserialize(deserializer)?)?.unwrap_or_default())
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+struct PatchOperation {
+    op: String,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default)]
+    value: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PatchRequest {
+    #[serde(rename = "Operations")]
+    operations: Vec<PatchOperation>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct ListQuery {
+    filter: Option<String>,
+    /// 1-based.
+    start_index: Option<usize>,
+    count: Option<usize>,
+}
+
+/// The target of a patch operation: `attribute`, `attribute.subAttribute`, or
+/// `attribute[subAttribute eq "value"]` optionally followed by `.subAttribute`.
+#[derive(Debug, PartialEq, Eq)]
+struct PatchPath {
+    attribute: String,
+    /// The sub-attribute and the value of the filter on the elements of a multi-valued attribute.
+    filter: Option<(String, String)>,
+    sub_attribute: Option<String>,
+}
+
+/// Parses `attribute eq "value"`, the only supported filter. The attribute names are returned as
+/// is, they are case-insensitive.
+fn parse_eq_filter(filter: &str) -> ScimResult<(String, String)> {
+    let invalid = || ScimError::invalid_filter(format!("Unsupported filter: {}", filter));
+    let (attribute, rest) = filter
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+    let (operator, value) = rest
+        .trim_start()
+        .split_once(char::is_whitespace)
+        .ok_or_else(invalid)?;
+    if !operator.eq_ignore_ascii_case("eq") {
+        return Err(ScimError::invalid_filter(
+            "Only the eq filters are supported",
+        ));
+    }
+    let value = match serde_json::from_str::<Value>(value.trim()).map_err(|_| invalid())? {
+        Value::String(s) => s,
+        other => other.to_string(),
+    };
+    Ok((attribute.to_owned(), value))
+}
+
+fn parse_path(path: &str) -> ScimResult<PatchPath> {
+    let mut path = path.trim();
+    // The attribute can be prefixed by the URN of its schema.
+    if path.starts_with("urn:") {
+        let attribute_end = path.find('[').unwrap_or(path.len());
+        if let Some(schema_end) = path[..attribute_end].rfind(':') {
+            path = &path[schema_end + 1..];
+        }
+    }
+    let invalid = || ScimError::invalid_path(format!("Invalid path: {}", path));
+    let (attribute, filter, sub_attribute) = match path.find('[') {
+        None => match path.split_once('.') {
+            None => (path, None, None),
+            Some((attribute, sub_attribute)) => (attribute, None, Some(sub_attribute)),
+        },
+        Some(filter_start) => {
+            let filter_end = path.rfind(']').ok_or_else(invalid)?;
+            if filter_end < filter_start {
+                return Err(invalid());
+            }
+            let filter = parse_eq_filter(&path[filter_start + 1..filter_end])?;
+            let sub_attribute = match &path[filter_end + 1..] {
+                "" => None,
+                rest => Some(rest.strip_prefix('.').ok_or_else(invalid)?),
+            };
+            (&path[..filter_start], Some(filter), sub_attribute)
+        }
+    };
+    if attribute.is_empty() || sub_attribute.map_or(false, str::is_empty) {
+        return Err(invalid());
+    }
+    Ok(PatchPath {
+        attribute: attribute.to_owned(),
+        filter,
+        sub_attribute: sub_attribute.map(str::to_owned),
+    })
+}
+
+/// The key of the attribute in the object: the attribute names are case-insensitive.
+fn get_key(object: &Map<String, Value>, name: &str) -> String {
+    object
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| name.to_owned())
+}
+
+fn value_matches(value: Option<&Value>, expected: &str) -> bool {
+    match value {
+        Some(Value::String(s)) => s.eq_ignore_ascii_case(expected),
+        Some(other) => other.to_string() == expected,
+        None => false,
+    }
+}
+
+fn element_matches(element: &Value, (attribute, expected): &(String, String)) -> bool {
+    match element.as_object() {
+        Some(object) => value_matches(object.get(&get_key(object, attribute)), expected),
+        None => false,
+    }
+}
+
+/// Whether the two elements of a multi-valued attribute are the same, e.g. the same member.
+fn same_element(element: &Value, other: &Value) -> bool {
+    match other.get("value").and_then(Value::as_str) {
+        Some(value) => value_matches(element.get("value"), value),
+        None => element == other,
+    }
+}
+
+/// Sets the sub-attribute of a complex attribute, or merges the object into it.
+fn set_in_object(
+    object: &mut Map<String, Value>,
+    sub_attribute: Option<&str>,
+    value: &Value,
+) -> ScimResult<()> {
+    match (sub_attribute, value) {
+        (Some(sub_attribute), value) => {
+            object.insert(get_key(object, sub_attribute), value.clone());
+        }
+        (None, Value::Object(values)) => {
+            for (name, value) in values {
+                object.insert(get_key(object, name), value.clone());
+            }
+        }
+        (None, _) => return Err(ScimError::invalid_value("Expected an object")),
+    }
+    Ok(())
+}
+
+fn add_or_replace(
+    resource: &mut Map<String, Value>,
+    path: &PatchPath,
+    value: &Value,
+    is_add: bool,
+) -> ScimResult<()> {
+    let key = get_key(resource, &path.attribute);
+    let entry = resource.entry(key).or_insert(Value::Null);
+    let sub_attribute = path.sub_attribute.as_deref();
+    match &path.filter {
+        None => match (entry, sub_attribute, value) {
+            (Value::Array(elements), None, Value::Array(new_elements)) if is_add => {
+                for element in new_elements {
+                    if !elements.iter().any(|e| same_element(e, element)) {
+                        elements.push(element.clone());
+                    }
+                }
+            }
+            (Value::Object(object), _, _) if sub_attribute.is_some() || value.is_object() => {
+                set_in_object(object, sub_attribute, value)?
+            }
+            (entry, Some(sub_attribute), _) => {
+                let mut object = Map::new();
+                object.insert(sub_attribute.to_owned(), value.clone());
+                *entry = Value::Object(object);
+            }
+            (entry, None, _) => *entry = value.clone(),
+        },
+        Some(filter) => {
+            if entry.is_null() {
+                *entry = Value::Array(Vec::new());
+            }
+            let elements = entry.as_array_mut().ok_or_else(|| {
+                ScimError::invalid_path("Filters only apply to multi-valued attributes")
+            })?;
+            let mut matched = false;
+            for element in elements.iter_mut().filter(|e| element_matches(e, filter)) {
+                matched = true;
+                if let Value::Object(object) = element {
+                    set_in_object(object, sub_attribute, value)?;
+                }
+            }
+            if !matched {
+                // E.g. `emails[type eq "work"].value` for a user without work email.
+                let mut object = Map::new();
+                object.insert(filter.0.clone(), Value::String(filter.1.clone()));
+                set_in_object(&mut object, sub_attribute, value)?;
+                elements.push(Value::Object(object));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove(resource: &mut Map<String, Value>, path: &PatchPath, value: Option<&Value>) {
+    let key = get_key(resource, &path.attribute);
+    let sub_attribute = path.sub_attribute.as_deref();
+    match (resource.get_mut(&key), &path.filter, sub_attribute) {
+        (Some(Value::Array(elements)), None, None) => match value {
+            // Azure AD removes members this way.
+            Some(Value::Array(removed)) => {
+                elements.retain(|e| !removed.iter().any(|r| same_element(e, r)))
+            }
+            _ => elements.clear(),
+        },
+        (Some(Value::Object(object)), None, Some(sub_attribute)) => {
+            object.remove(&get_key(object, sub_attribute));
+        }
+        (Some(Value::Array(elements)), Some(filter), None) => {
+            elements.retain(|e| !element_matches(e, filter))
+        }
+        (Some(Value::Array(elements)), Some(filter), Some(sub_attribute)) => {
+            for element in elements.iter_mut().filter(|e| element_matches(e, filter)) {
+                if let Value::Object(object) = element {
+                    object.remove(&get_key(object, sub_attribute));
+                }
+            }
+        }
+        (Some(_), None, None) => {
+            resource.remove(&key);
+        }
+        _ => (),
+    }
+}
+
+/// Applies the operations of a PATCH request to the JSON of the resource.
+fn apply_patch(resource: &mut Value, operations: &[PatchOperation]) -> ScimResult<()> {
+    let resource = resource
+        .as_object_mut()
+        .ok_or_else(|| ScimError::invalid_value("Expected an object"))?;
+    for operation in operations {
+        let op = operation.op.to_ascii_lowercase();
+        match (op.as_str(), &operation.path, &operation.value) {
+            ("add" | "replace", Some(path), Some(value)) => {
+                add_or_replace(resource, &parse_path(path)?, value, op == "add")?
+            }
+            // Without a path, the keys of the value are the paths.
+            ("add" | "replace", None, Some(Value::Object(values))) => {
+                for (path, value) in values {
+                    add_or_replace(resource, &parse_path(path)?, value, op == "add")?;
+                }
+            }
+            ("add" | "replace", _, _) => {
+                return Err(ScimError::invalid_value(format!(
+                    "Missing or invalid value for the {} operation",
+                    operation.op
+                )))
+            }
+            ("remove", Some(path), value) => remove(resource, &parse_path(path)?, value.as_ref()),
+            ("remove", None, _) => {
+                return Err(ScimError::new(
+                    StatusCode::BAD_REQUEST,
+                    Some("noTarget"),
+                    "The remove operations need a path",
+                ))
+            }
+            _ => {
+                return Err(ScimError::invalid_value(format!(
+                    "Unknown operation: {}",
+                    operation.op
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn to_scim_user(user: User, groups: &[GroupDetails], base_url: &str, now: DateTime) -> ScimUser {
+    let location = format!("{}/Users/{}", base_url, user.user_id);
+    let mut groups = groups.iter().collect::<Vec<_>>();
+    groups.sort_by_key(|group| group.group_id.0);
+    let groups = groups
+        .into_iter()
+        .map(|group| ScimReference {
+            value: group.group_id.0.to_string(),
+            display: Some(group.display_name.clone()),
+            reference: Some(format!("{}/Groups/{}", base_url, group.group_id.0)),
+        })
+        .collect();
+    let emails = std::iter::once(ScimEmail {
+        value: user.email.clone(),
+        type_: Some("work".to_owned()),
+        primary: true,
+    })
+    .chain(user.email_aliases.iter().map(|alias| ScimEmail {
+        value: alias.clone(),
+        type_: Some("other".to_owned()),
+        primary: false,
+    }))
+    .collect();
+    ScimUser {
+        schemas: vec![USER_SCHEMA.to_owned()],
+        id: Some(user.user_id.to_string()),
+        user_name: user.user_id.to_string(),
+        name: (user.first_name.is_some() || user.last_name.is_some()).then(|| ScimName {
+            given_name: user.first_name.clone(),
+            family_name: user.last_name.clone(),
+        }),
+        display_name: user.display_name.clone(),
+        emails,
+        active: Some(user.is_active_at(now)),
+        groups,
+        meta: Some(ScimMeta {
+            resource_type: "User",
+            created: user.creation_date.to_rfc3339(),
+            last_modified: user.modified_date.to_rfc3339(),
+            location,
+        }),
+    }
+}
+
+fn to_scim_group(group: &Group, base_url: &str) -> ScimGroup {
+    ScimGroup {
+        schemas: vec![GROUP_SCHEMA.to_owned()],
+        id: Some(group.id.0.to_string()),
+        members: group
+            .users
+            .iter()
+            .map(|user_id| ScimReference {
+                value: user_id.to_string(),
+                display: None,
+                reference: Some(format!("{}/Users/{}", base_url, user_id)),
+            })
+            .collect(),
+        meta: Some(ScimMeta {
+            resource_type: "Group",
+            created: group.creation_date.to_rfc3339(),
+            last_modified: group.modified_date.to_rfc3339(),
+            location: format!("{}/Groups/{}", base_url, group.id.0),
+        }),
+        display_name: group.display_name.clone(),
+    }
+}
+
+/// The fields of a user that can be provisioned.
+#[derive(Debug, PartialEq, Eq)]
+struct UserFields {
+    email: String,
+    email_aliases: Vec<String>,
+    display_name: Option<String>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+}
+
+fn get_user_fields(user: &ScimUser) -> ScimResult<UserFields> {
+    let primary_email = user
+        .emails
+        .iter()
+        .find(|email| email.primary)
+        .or_else(|| user.emails.first())
+        .ok_or_else(|| ScimError::invalid_value("An email is required"))?;
+    if let Some(email) = user
+        .emails
+        .iter()
+        .find(|email| !is_valid_email(&email.value))
+    {
+        return Err(ScimError::invalid_value(format!(
+            "Invalid email address: {}",
+            email.value
+        )));
+    }
+    let mut email_aliases = Vec::new();
+    for email in &user.emails {
+        if !email.value.eq_ignore_ascii_case(&primary_email.value)
+            && !email_aliases.contains(&email.value)
+        {
+            email_aliases.push(email.value.clone());
+        }
+    }
+    let name = user.name.clone().unwrap_or_default();
+    Ok(UserFields {
+        email: primary_email.value.clone(),
+        email_aliases,
+        display_name: user.display_name.clone(),
+        first_name: name.given_name,
+        last_name: name.family_name,
+    })
+}
+
+fn parse_body<'a, T: Deserialize<'a>>(body: &'a [u8]) -> ScimResult<T> {
+    serde_json::from_slice(body)
+        .map_err(|e| ScimError::invalid_value(format!("Invalid request: {}", e)))
+}
+
+fn to_body(resource: impl Serialize) -> ScimResult<String> {
+    serde_json::to_string(&resource)
+        .map_err(|e| ScimError::new(StatusCode::INTERNAL_SERVER_ERROR, None, e.to_string()))
+}
+
+fn to_response(status: StatusCode, resource: impl Serialize) -> ScimResult<HttpResponse> {
+    Ok(HttpResponse::build(status)
+        .content_type(CONTENT_TYPE)
+        .body(to_body(resource)?))
+}
+
+fn to_created_response(resource: impl Serialize, location: &str) -> ScimResult<HttpResponse> {
+    Ok(HttpResponse::Created()
+        .content_type(CONTENT_TYPE)
+        .append_header(("Location", location))
+        .body(to_body(resource)?))
+}
+
+fn to_list_response<T: Serialize>(
+    resources: Vec<T>,
+    query: &ListQuery,
+) -> ScimResult<HttpResponse> {
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(MAX_RESULTS).min(MAX_RESULTS);
+    let total_results = resources.len();
+    let page = resources
+        .into_iter()
+        .skip(start_index - 1)
+        .take(count)
+        .collect::<Vec<_>>();
+    to_response(
+        StatusCode::OK,
+        json!({
+            "schemas": [LIST_RESPONSE_SCHEMA],
+            "totalResults": total_results,
+            "startIndex": start_index,
+            "itemsPerPage": page.len(),
+            "Resources": page,
+        }),
+    )
+}
+
+fn get_user_filter(filter: &str) -> ScimResult<UserRequestFilter> {
+    let (attribute, value) = parse_eq_filter(filter)?;
+    Ok(match attribute.to_ascii_lowercase().as_str() {
+        "id" | "username" => UserRequestFilter::UserId(UserId::new(&value)),
+        // Also matches the aliases.
+        "emails" | "emails.value" => UserRequestFilter::Equality(UserColumn::Email, value),
+        "displayname" => UserRequestFilter::Equality(UserColumn::DisplayName, value),
+        _ => {
+            return Err(ScimError::invalid_filter(format!(
+                "Unsupported filter attribute: {}",
+                attribute
+            )))
+        }
+    })
+}
+
+fn get_group_filter(filter: &str) -> ScimResult<GroupRequestFilter> {
+    let (attribute, value) = parse_eq_filter(filter)?;
+    Ok(match attribute.to_ascii_lowercase().as_str() {
+        "id" => match value.parse() {
+            Ok(id) => GroupRequestFilter::GroupId(GroupId(id)),
+            // Matches nothing.
+            Err(_) => GroupRequestFilter::Or(Vec::new()),
+        },
+        "displayname" => GroupRequestFilter::DisplayName(value),
+        _ => {
+            return Err(ScimError::invalid_filter(format!(
+                "Unsupported filter attribute: {}",
+                attribute
+            )))
+        }
+    })
+}
+
+fn get_base_url<Backend>(data: &AppState<Backend>) -> String {
+    format!("{}/scim/v2", data.server_url.trim_end_matches('/'))
+}
+
+async fn authenticate<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+) -> ScimResult<ValidationResults> {
+    use actix_web::FromRequest;
+    let bearer = BearerAuth::from_request(request, &mut actix_web::dev::Payload::None)
+        .await
+        .map_err(|_| ScimError::new(StatusCode::UNAUTHORIZED, None, "Missing bearer token"))?;
+    check_if_token_or_api_token_is_valid(data, bearer.token())
+        .await
+        .map_err(|e| ScimError::new(StatusCode::UNAUTHORIZED, None, e.to_string()))
+}
+
+/// Records a change in the audit log, like the GraphQL mutations do. A failure to record it is
+/// only logged.
+async fn audit<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+    validation_result: &ValidationResults,
+    action: &str,
+    target: String,
+    details: Option<String>,
+) {
+    let mut details = match details {
+        None => "Through SCIM".to_owned(),
+        Some(details) => format!("{}, through SCIM", details),
+    };
+    if validation_result.is_api_token() {
+        details.push_str(", with an API token");
+    }
+    let event = AuditEvent {
+        actor: Some(validation_result.user.clone()),
+        action: action.to_owned(),
+        target: Some(target),
+        ip_address: request
+            .connection_info()
+            .realip_remote_addr()
+            .map(str::to_owned),
+        success: true,
+        details: Some(details),
+    };
+    if let Err(e) = data.backend_handler.record_audit_event(event).await {
+        warn!("Could not record {} in the audit log: {}", action, e);
+    }
+}
+
+async fn get_scim_user<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    user_id: &UserId,
+) -> ScimResult<ScimUser> {
+    let user = data.backend_handler.get_user_details(user_id).await?;
+    let groups = data
+        .backend_handler
+        .get_user_groups(user_id)
+        .await?
+        .into_iter()
+        .collect::<Vec<_>>();
+    Ok(to_scim_user(
+        user,
+        &groups,
+        &get_base_url(data),
+        chrono::Utc::now(),
+    ))
+}
+
+async fn get_group<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    group_id: &str,
+) -> ScimResult<Group> {
+    let not_found = || ScimError::not_found(format!("No such group: {}", group_id));
+    let group_id = group_id.parse().map_err(|_| not_found())?;
+    data.backend_handler
+        .list_groups(Some(GroupRequestFilter::And(vec![
+            GroupRequestFilter::GroupId(GroupId(group_id)),
+            GroupRequestFilter::Archived(false),
+        ])))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(not_found)
+}
+
+/// The API tokens can't change the admins: they would get their rights.
+async fn check_not_admin_for_api_token<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    validation_result: &ValidationResults,
+    user_id: &UserId,
+) -> ScimResult<()> {
+    if !validation_result.is_api_token() {
+        return Ok(());
+    }
+    let groups = data.backend_handler.get_user_groups(user_id).await?;
+    if groups.iter().any(|g| g.display_name == "lldap_admin") {
+        return Err(ScimError::forbidden("API tokens cannot modify admins"));
+    }
+    Ok(())
+}
+
+fn check_user_name(user_name: &str) -> ScimResult<()> {
+    if user_name.is_empty()
+        || user_name
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err(ScimError::invalid_value(format!(
+            "Invalid userName: '{}'",
+            user_name
+        )));
+    }
+    Ok(())
+}
+
+/// Disables or enables the account. Disabling it ends its sessions, and re-activating it also
+/// removes the validity dates that prevent it from being used now.
+async fn set_active<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    user: &User,
+    active: bool,
+) -> ScimResult<()> {
+    if user.enabled != active {
+        set_account_enabled(
+            &data.backend_handler,
+            &data.jwt_blacklist,
+            &user.user_id,
+            active,
+        )
+        .await?;
+    }
+    let now = chrono::Utc::now();
+    let activation_date = user.activation_date.filter(|date| *date <= now);
+    let expiration_date = user.expiration_date.filter(|date| *date > now);
+    if active && (activation_date, expiration_date) != (user.activation_date, user.expiration_date)
+    {
+        data.backend_handler
+            .set_account_validity(&user.user_id, activation_date, expiration_date)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Replaces the fields of the user with those of the resource, for PUT and PATCH.
+async fn replace_user<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+    validation_result: &ValidationResults,
+    user: &User,
+    resource: ScimUser,
+) -> ScimResult<HttpResponse> {
+    if UserId::new(&resource.user_name) != user.user_id {
+        return Err(ScimError::mutability("The userName can't be changed"));
+    }
+    let fields = get_user_fields(&resource)?;
+    data.backend_handler
+        .update_user(UpdateUserRequest {
+            user_id: user.user_id.clone(),
+            email: Some(fields.email),
+            // The empty strings clear the fields.
+            display_name: Some(fields.display_name.unwrap_or_default()),
+            first_name: Some(fields.first_name.unwrap_or_default()),
+            last_name: Some(fields.last_name.unwrap_or_default()),
+            email_aliases: Some(fields.email_aliases),
+            ..Default::default()
+        })
+        .await?;
+    if let Some(active) = resource.active {
+        set_active(data, user, active).await?;
+    }
+    apply_group_assignment_rules(
+        &data.backend_handler,
+        &data.group_assignment_rules,
+        &user.user_id,
+    )
+    .await?;
+    audit(
+        data,
+        request,
+        validation_result,
+        "updateUser",
+        user.user_id.to_string(),
+        None,
+    )
+    .await;
+    to_response(StatusCode::OK, get_scim_user(data, &user.user_id).await?)
+}
+
+/// Checks that the writes to the user are allowed, and returns it.
+async fn get_writable_user<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    validation_result: &ValidationResults,
+    user_id: &UserId,
+) -> ScimResult<User> {
+    if !validation_result.can_manage_users() {
+        return Err(ScimError::forbidden("Unauthorized user modification"));
+    }
+    let user = data.backend_handler.get_user_details(user_id).await?;
+    check_not_admin_for_api_token(data, validation_result, user_id).await?;
+    Ok(user)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn list_users<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    query: web::Query<ListQuery>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    if !validation_result.is_admin_or_readonly() {
+        return Err(ScimError::forbidden("Unauthorized access to the users"));
+    }
+    let filter = query.filter.as_deref().map(get_user_filter).transpose()?;
+    debug!(?filter);
+    let base_url = get_base_url(&data);
+    let now = chrono::Utc::now();
+    let users = data
+        .backend_handler
+        .list_users(filter, true)
+        .await?
+        .into_iter()
+        .map(|u| to_scim_user(u.user, &u.groups.unwrap_or_default(), &base_url, now))
+        .collect();
+    to_list_response(users, &query)
+}
+
+#[instrument(skip_all, level = "debug", fields(user_id = %path))]
+async fn get_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    let user_id = UserId::new(&path);
+    if !validation_result.can_read(&user_id) {
+        return Err(ScimError::forbidden("Unauthorized access to the user"));
+    }
+    to_response(StatusCode::OK, get_scim_user(&data, &user_id).await?)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn create_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    if !validation_result.can_manage_users() {
+        return Err(ScimError::forbidden("Unauthorized user creation"));
+    }
+    let resource = parse_body::<ScimUser>(&body)?;
+    check_user_name(&resource.user_name)?;
+    let fields = get_user_fields(&resource)?;
+    let user_id = UserId::new(&resource.user_name);
+    debug!(?user_id);
+    data.backend_handler
+        .create_user(CreateUserRequest {
+            user_id: user_id.clone(),
+            email: fields.email,
+            display_name: fields.display_name,
+            first_name: fields.first_name,
+            last_name: fields.last_name,
+            email_aliases: fields.email_aliases,
+            ..Default::default()
+        })
+        .await?;
+    if resource.active == Some(false) {
+        let user = data.backend_handler.get_user_details(&user_id).await?;
+        set_active(&data, &user, false).await?;
+    }
+    apply_group_assignment_rules(
+        &data.backend_handler,
+        &data.group_assignment_rules,
+        &user_id,
+    )
+    .await?;
+    audit(
+        &data,
+        &request,
+        &validation_result,
+        "createUser",
+        user_id.to_string(),
+        None,
+    )
+    .await;
+    let location = format!("{}/Users/{}", get_base_url(&data), user_id);
+    to_created_response(get_scim_user(&data, &user_id).await?, &location)
+}
+
+#[instrument(skip_all, level = "debug", fields(user_id = %path))]
+async fn put_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    let user = get_writable_user(&data, &validation_result, &UserId::new(&path)).await?;
+    let resource = parse_body::<ScimUser>(&body)?;
+    replace_user(&data, &request, &validation_result, &user, resource).await
+}
+
+#[instrument(skip_all, level = "debug", fields(user_id = %path))]
+async fn patch_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    let user = get_writable_user(&data, &validation_result, &UserId::new(&path)).await?;
+    let patch = parse_body::<PatchRequest>(&body)?;
+    debug!(?patch);
+    let mut resource = serde_json::to_value(get_scim_user(&data, &user.user_id).await?)
+        .map_err(|e| ScimError::new(StatusCode::INTERNAL_SERVER_ERROR, None, e.to_string()))?;
+    apply_patch(&mut resource, &patch.operations)?;
+    let resource = serde_json::from_value::<ScimUser>(resource)
+        .map_err(|e| ScimError::invalid_value(format!("Invalid patched user: {}", e)))?;
+    replace_user(&data, &request, &validation_result, &user, resource).await
+}
+
+#[instrument(skip_all, level = "debug", fields(user_id = %path))]
+async fn delete_user<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    let user = get_writable_user(&data, &validation_result, &UserId::new(&path)).await?;
+    if validation_result.user == user.user_id {
+        return Err(ScimError::forbidden("Cannot delete current user"));
+    }
+    data.backend_handler.delete_user(&user.user_id).await?;
+    audit(
+        &data,
+        &request,
+        &validation_result,
+        "deleteUser",
+        user.user_id.to_string(),
+        None,
+    )
+    .await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Makes the members of the group match the resource, with the same rules as the GraphQL
+/// mutations.
+async fn set_group_members<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+    validation_result: &ValidationResults,
+    group: &Group,
+    members: &[ScimReference],
+) -> ScimResult<()> {
+    let members = members
+        .iter()
+        .map(|member| UserId::new(&member.value))
+        .collect::<HashSet<_>>();
+    let current_members = group.users.iter().cloned().collect::<HashSet<_>>();
+    if members == current_members {
+        return Ok(());
+    }
+    if group.dynamic_filter.is_some() {
+        return Err(ScimError::mutability(
+            "Cannot change the members of a dynamic group",
+        ));
+    }
+    // The API tokens can't give admin rights.
+    if !validation_result.is_admin() && group.display_name.starts_with("lldap_") {
+        return Err(ScimError::forbidden(
+            "Only admins can change the members of the lldap_ groups",
+        ));
+    }
+    let mut removed_members = current_members.difference(&members).collect::<Vec<_>>();
+    removed_members.sort_by_key(|user_id| user_id.as_str());
+    let mut added_members = members.difference(&current_members).collect::<Vec<_>>();
+    added_members.sort_by_key(|user_id| user_id.as_str());
+    if group.id == GroupId(1) && removed_members.contains(&&validation_result.user) {
+        return Err(ScimError::forbidden(
+            "Cannot remove admin rights for current user",
+        ));
+    }
+    let details = Some(format!("group {}", group.id.0));
+    for user_id in removed_members {
+        data.backend_handler
+            .remove_user_from_group(user_id, group.id)
+            .await?;
+        audit(
+            data,
+            request,
+            validation_result,
+            "removeUserFromGroup",
+            user_id.to_string(),
+            details.clone(),
+        )
+        .await;
+    }
+    for user_id in added_members {
+        add_membership(
+            &data.backend_handler,
+            user_id,
+            group.id,
+            false,
+            data.group_limit,
+        )
+        .await
+        .map_err(|e| ScimError::invalid_value(e.message()))?;
+        audit(
+            data,
+            request,
+            validation_result,
+            "addUserToGroup",
+            user_id.to_string(),
+            details.clone(),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// Checks that the writes to the group are allowed, and returns it.
+async fn get_writable_group<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    validation_result: &ValidationResults,
+    group_id: &str,
+) -> ScimResult<Group> {
+    if !validation_result.can_manage_groups() {
+        return Err(ScimError::forbidden("Unauthorized group modification"));
+    }
+    let group = get_group(data, group_id).await?;
+    // The "everyone" group is managed by the server, from the configuration.
+    if data.everyone_group.as_ref() == Some(&group.display_name) {
+        return Err(ScimError::forbidden(format!(
+            "Cannot change the {} group",
+            group.display_name
+        )));
+    }
+    Ok(group)
+}
+
+fn is_lldap_group_name(name: &str) -> bool {
+    name.to_ascii_lowercase().starts_with("lldap_")
+}
+
+/// Replaces the name and the members of the group with those of the resource, for PUT and PATCH.
+async fn replace_group<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    request: &HttpRequest,
+    validation_result: &ValidationResults,
+    group: &Group,
+    resource: ScimGroup,
+) -> ScimResult<HttpResponse> {
+    if resource.display_name != group.display_name {
+        if group.id == GroupId(1) {
+            return Err(ScimError::forbidden("Cannot change admin group details"));
+        }
+        // Like the members, the names of the lldap_ groups grant permissions.
+        if !validation_result.is_admin()
+            && (is_lldap_group_name(&group.display_name)
+                || is_lldap_group_name(&resource.display_name))
+        {
+            return Err(ScimError::forbidden(
+                "Only admins can name or rename the lldap_ groups",
+            ));
+        }
+        data.backend_handler
+            .update_group(UpdateGroupRequest {
+                group_id: group.id,
+                display_name: Some(resource.display_name.clone()),
+                archived: None,
+                gid_number: None,
+                email: None,
+                attributes: Vec::new(),
+            })
+            .await?;
+        audit(
+            data,
+            request,
+            validation_result,
+            "updateGroup",
+            format!("group {}", group.id.0),
+            None,
+        )
+        .await;
+    }
+    set_group_members(data, request, validation_result, group, &resource.members).await?;
+    let group = get_group(data, &group.id.0.to_string()).await?;
+    to_response(StatusCode::OK, to_scim_group(&group, &get_base_url(data)))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn list_groups<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    query: web::Query<ListQuery>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    if !validation_result.is_admin_or_readonly() {
+        return Err(ScimError::forbidden("Unauthorized access to the groups"));
+    }
+    let mut filters = vec![GroupRequestFilter::Archived(false)];
+    if let Some(filter) = &query.filter {
+        filters.push(get_group_filter(filter)?);
+    }
+    debug!(?filters);
+    let base_url = get_base_url(&data);
+    let groups = data
+        .backend_handler
+        .list_groups(Some(GroupRequestFilter::And(filters)))
+        .await?
+        .into_iter()
+        .map(|group| to_scim_group(&group, &base_url))
+        .collect();
+    to_list_response(groups, &query)
+}
+
+#[instrument(skip_all, level = "debug", fields(group_id = %path))]
+async fn get_group_handler<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    if !validation_result.is_admin_or_readonly() {
+        return Err(ScimError::forbidden("Unauthorized access to the group"));
+    }
+    let group = get_group(&data, &path).await?;
+    to_response(StatusCode::OK, to_scim_group(&group, &get_base_url(&data)))
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn create_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    if !validation_result.can_manage_groups() {
+        return Err(ScimError::forbidden("Unauthorized group creation"));
+    }
+    let resource = parse_body::<ScimGroup>(&body)?;
+    if resource.display_name.trim().is_empty() {
+        return Err(ScimError::invalid_value("The displayName can't be empty"));
+    }
+    if !validation_result.is_admin() && is_lldap_group_name(&resource.display_name) {
+        return Err(ScimError::forbidden(
+            "Only admins can name or rename the lldap_ groups",
+        ));
+    }
+    debug!(?resource.display_name);
+    let group_id = data
+        .backend_handler
+        .create_group(&resource.display_name)
+        .await?;
+    audit(
+        &data,
+        &request,
+        &validation_result,
+        "createGroup",
+        format!("group {}", group_id.0),
+        Some(resource.display_name.clone()),
+    )
+    .await;
+    let group = get_group(&data, &group_id.0.to_string()).await?;
+    set_group_members(
+        &data,
+        &request,
+        &validation_result,
+        &group,
+        &resource.members,
+    )
+    .await?;
+    let base_url = get_base_url(&data);
+    let group = get_group(&data, &group_id.0.to_string()).await?;
+    to_created_response(
+        to_scim_group(&group, &base_url),
+        &format!("{}/Groups/{}", base_url, group_id.0),
+    )
+}
+
+#[instrument(skip_all, level = "debug", fields(group_id = %path))]
+async fn put_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    let group = get_writable_group(&data, &validation_result, &path).await?;
+    let resource = parse_body::<ScimGroup>(&body)?;
+    replace_group(&data, &request, &validation_result, &group, resource).await
+}
+
+#[instrument(skip_all, level = "debug", fields(group_id = %path))]
+async fn patch_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    let group = get_writable_group(&data, &validation_result, &path).await?;
+    let patch = parse_body::<PatchRequest>(&body)?;
+    debug!(?patch);
+    let mut resource = serde_json::to_value(to_scim_group(&group, &get_base_url(&data)))
+        .map_err(|e| ScimError::new(StatusCode::INTERNAL_SERVER_ERROR, None, e.to_string()))?;
+    apply_patch(&mut resource, &patch.operations)?;
+    let resource = serde_json::from_value::<ScimGroup>(resource)
+        .map_err(|e| ScimError::invalid_value(format!("Invalid patched group: {}", e)))?;
+    replace_group(&data, &request, &validation_result, &group, resource).await
+}
+
+#[instrument(skip_all, level = "debug", fields(group_id = %path))]
+async fn delete_group<Backend: BackendHandler>(
+    data: web::Data<AppState<Backend>>,
+    request: HttpRequest,
+    path: web::Path<String>,
+) -> ScimResult<HttpResponse> {
+    let validation_result = authenticate(&data, &request).await?;
+    let group = get_writable_group(&data, &validation_result, &path).await?;
+    if group.id == GroupId(1) {
+        return Err(ScimError::forbidden("Cannot delete admin group"));
+    }
+    if !validation_result.is_admin() && is_lldap_group_name(&group.display_name) {
+        return Err(ScimError::forbidden(
+            "Only admins can delete the lldap_ groups",
+        ));
+    }
+    data.backend_handler.delete_group(group.id).await?;
+    audit(
+        &data,
+        &request,
+        &validation_result,
+        "deleteGroup",
+        format!("group {}", group.id.0),
+        None,
+    )
+    .await;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn get_service_provider_config() -> ScimResult<HttpResponse> {
+    to_response(
+        StatusCode::OK,
+        json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"],
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": MAX_RESULTS },
+            "changePassword": { "supported": false },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "OAuth Bearer Token",
+                "description": "An API token, or the JWT of a session, as a bearer token.",
+                "primary": true,
+            }],
+        }),
+    )
+}
+
+async fn get_resource_types() -> ScimResult<HttpResponse> {
+    let resource_type = |name: &str, endpoint: &str, schema: &str| {
+        json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ResourceType"],
+            "id": name,
+            "name": name,
+            "endpoint": endpoint,
+            "schema": schema,
+        })
+    };
+    to_list_response(
+        vec![
+            resource_type("User", "/Users", USER_SCHEMA),
+            resource_type("Group", "/Groups", GROUP_SCHEMA),
+        ],
+        &ListQuery::default(),
+    )
+}
+
+pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: BackendHandler + Sync + 'static,
+{
+    cfg.route(
+        "/ServiceProviderConfig",
+        web::get().to(get_service_provider_config),
+    )
+    .route("/ResourceTypes", web::get().to(get_resource_types))
+    .service(
+        web::resource("/Users")
+            .route(web::get().to(list_users::<Backend>))
+            .route(web::post().to(create_user::<Backend>)),
+    )
+    .service(
+        web::resource("/Users/{id}")
+            .route(web::get().to(get_user::<Backend>))
+            .route(web::put().to(put_user::<Backend>))
+            .route(web::patch().to(patch_user::<Backend>))
+            .route(web::delete().to(delete_user::<Backend>)),
+    )
+    .service(
+        web::resource("/Groups")
+            .route(web::get().to(list_groups::<Backend>))
+            .route(web::post().to(create_group::<Backend>)),
+    )
+    .service(
+        web::resource("/Groups/{id}")
+            .route(web::get().to(get_group_handler::<Backend>))
+            .route(web::put().to(put_group::<Backend>))
+            .route(web::patch().to(patch_group::<Backend>))
+            .route(web::delete().to(delete_group::<Backend>)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uuid;
+    use chrono::TimeZone;
+
+    fn patch(operations: Value) -> Vec<PatchOperation> {
+        serde_json::from_value(operations).unwrap()
+    }
+
+    #[test]
+    fn test_parse_eq_filter() {
+        assert_eq!(
+            parse_eq_filter(r#"userName eq "bob \"the\" builder""#),
+            Ok(("userName".to_owned(), "bob \"the\" builder".to_owned()))
+        );
+        assert_eq!(
+            parse_eq_filter("primary EQ true"),
+            Ok(("primary".to_owned(), "true".to_owned()))
+        );
+        assert_eq!(
+            parse_eq_filter(r#"userName sw "b""#).unwrap_err().scim_type,
+            Some("invalidFilter")
+        );
+        assert!(parse_eq_filter("userName").is_err());
+        assert!(parse_eq_filter(r#"userName eq "unterminated"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            parse_path("name.givenName"),
+            Ok(PatchPath {
+                attribute: "name".to_owned(),
+                filter: None,
+                sub_attribute: Some("givenName".to_owned()),
+            })
+        );
+        assert_eq!(
+            parse_path(r#"emails[type eq "work"].value"#),
+            Ok(PatchPath {
+                attribute: "emails".to_owned(),
+                filter: Some(("type".to_owned(), "work".to_owned())),
+                sub_attribute: Some("value".to_owned()),
+            })
+        );
+        assert_eq!(
+            parse_path(r#"members[value eq "bob"]"#),
+            Ok(PatchPath {
+                attribute: "members".to_owned(),
+                filter: Some(("value".to_owned(), "bob".to_owned())),
+                sub_attribute: None,
+            })
+        );
+        assert_eq!(
+            parse_path("urn:ietf:params:scim:schemas:core:2.0:User:displayName"),
+            Ok(PatchPath {
+                attribute: "displayName".to_owned(),
+                filter: None,
+                sub_attribute: None,
+            })
+        );
+        assert!(parse_path("").is_err());
+        assert!(parse_path(r#"emails[type eq "work"]value"#).is_err());
+    }
+
+    #[test]
+    fn test_patch_user() {
+        let mut resource = json!({
+            "userName": "bob",
+            "displayName": "Bob",
+            "emails": [{ "value": "bob@example.com", "type": "work", "primary": true }],
+            "active": true,
+        });
+        // Like Azure AD sends them.
+        apply_patch(
+            &mut resource,
+            &patch(json!([
+                { "op": "Replace", "path": "displayName", "value": "Robert" },
+                { "op": "Add", "path": "name.givenName", "value": "Robert" },
+                {
+                    "op": "Replace",
+                    "path": "emails[type eq \"work\"].value",
+                    "value": "robert@example.com",
+                },
+                {
+                    "op": "Add",
+                    "path": "emails[type eq \"other\"].value",
+                    "value": "r@example.com",
+                },
+                { "op": "Replace", "value": { "active": "False", "name.familyName": "Smith" } },
+            ])),
+        )
+        .unwrap();
+        let user = serde_json::from_value::<ScimUser>(resource).unwrap();
+        assert_eq!(user.active, Some(false));
+        assert_eq!(
+            get_user_fields(&user),
+            Ok(UserFields {
+                email: "robert@example.com".to_owned(),
+                email_aliases: vec!["r@example.com".to_owned()],
+                display_name: Some("Robert".to_owned()),
+                first_name: Some("Robert".to_owned()),
+                last_name: Some("Smith".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_patch_group_members() {
+        let mut resource = json!({
+            "displayName": "users",
+            "members": [{ "value": "bob" }, { "value": "john" }],
+        });
+        apply_patch(
+            &mut resource,
+            &patch(json!([
+                {
+                    "op": "add",
+                    "path": "members",
+                    "value": [{ "value": "alice" }, { "value": "Bob" }],
+                },
+                { "op": "remove", "path": "members[value eq \"john\"]" },
+            ])),
+        )
+        .unwrap();
+        assert_eq!(
+            resource["members"],
+            json!([{ "value": "bob" }, { "value": "alice" }])
+        );
+        apply_patch(
+            &mut resource,
+            &patch(json!([{ "op": "Remove", "path": "members", "value": [{ "value": "alice" }] }])),
+        )
+        .unwrap();
+        assert_eq!(resource["members"], json!([{ "value": "bob" }]));
+        apply_patch(
+            &mut resource,
+            &patch(json!([{ "op": "remove", "path": "members" }])),
+        )
+        .unwrap();
+        assert_eq!(resource["members"], json!([]));
+        assert_eq!(
+            apply_patch(&mut resource, &patch(json!([{ "op": "remove" }])))
+                .unwrap_err()
+                .scim_type,
+            Some("noTarget")
+        );
+    }
+
+    #[test]
+    fn test_to_scim_user() {
+        let date = chrono::Utc.timestamp_opt(42, 0).unwrap();
+        let user = User {
+            user_id: UserId::new("bob"),
+            email: "bob@example.com".to_owned(),
+            display_name: Some("Bob".to_owned()),
+            last_name: Some("Smith".to_owned()),
+            email_aliases: vec!["b@example.com".to_owned()],
+            expiration_date: Some(date),
+            creation_date: date,
+            modified_date: date,
+            ..Default::default()
+        };
+        let groups = vec![GroupDetails {
+            group_id: GroupId(3),
+            display_name: "users".to_owned(),
+            creation_date: date,
+            uuid: uuid!("a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8"),
+            archived: false,
+            dynamic_filter: None,
+            gid_number: None,
+            email: None,
+        }];
+        let now = chrono::Utc.timestamp_opt(43, 0).unwrap();
+        assert_eq!(
+            serde_json::to_value(to_scim_user(user, &groups, "https://ldap/scim/v2", now)).unwrap(),
+            json!({
+                "schemas": [USER_SCHEMA],
+                "id": "bob",
+                "userName": "bob",
+                "name": { "familyName": "Smith" },
+                "displayName": "Bob",
+                "emails": [
+                    { "value": "bob@example.com", "type": "work", "primary": true },
+                    { "value": "b@example.com", "type": "other", "primary": false },
+                ],
+                // Expired.
+                "active": false,
+                "groups": [{
+                    "value": "3",
+                    "display": "users",
+                    "$ref": "https://ldap/scim/v2/Groups/3",
+                }],
+                "meta": {
+                    "resourceType": "User",
+                    "created": "1970-01-01T00:00:42+00:00",
+                    "lastModified": "1970-01-01T00:00:42+00:00",
+                    "location": "https://ldap/scim/v2/Users/bob",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_get_user_fields_errors() {
+        let user = |emails: Value| {
+            serde_json::from_value::<ScimUser>(json!({ "userName": "bob", "emails": emails }))
+                .unwrap()
+        };
+        assert!(get_user_fields(&user(json!([]))).is_err());
+        assert!(get_user_fields(&user(json!([{ "value": "not an email" }]))).is_err());
+        // Without a primary email, the first one is used.
+        assert_eq!(
+            get_user_fields(&user(json!([
+                { "value": "a@example.com" },
+                { "value": "b@example.com" },
+            ])))
+            .unwrap()
+            .email,
+            "a@example.com"
+        );
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(
+            get_user_filter(r#"userName eq "Bob""#),
+            Ok(UserRequestFilter::UserId(UserId::new("bob")))
+        );
+        assert_eq!(
+            get_user_filter(r#"emails.value eq "bob@example.com""#),
+            Ok(UserRequestFilter::Equality(
+                UserColumn::Email,
+                "bob@example.com".to_owned()
+            ))
+        );
+        assert!(get_user_filter(r#"externalId eq "1234""#).is_err());
+        assert_eq!(
+            get_group_filter(r#"displayName eq "users""#),
+            Ok(GroupRequestFilter::DisplayName("users".to_owned()))
+        );
+        assert_eq!(
+            get_group_filter(r#"id eq "abc""#),
+            Ok(GroupRequestFilter::Or(Vec::new()))
+        );
+    }
+}
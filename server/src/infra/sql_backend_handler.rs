@@ -3,18 +3,17 @@ use crate::domain::{
     error::*,
     model::{self, JwtRefreshStorageColumn, JwtStorageColumn, PasswordResetTokensColumn},
     sql_backend_handler::SqlBackendHandler,
-    types::UserId,
+    types::{SessionMetadata, UserId},
 };
 use async_trait::async_trait;
 use sea_orm::{
-    sea_query::Cond, ActiveModelTrait, ColumnTrait, EntityTrait, FromQueryResult, IntoActiveModel,
-    QueryFilter, QuerySelect,
+    ActiveModelTrait, ColumnTrait, EntityTrait, FromQueryResult, IntoActiveModel, QueryFilter,
+    QuerySelect,
 };
-use sea_query::Expr;
 use std::collections::HashSet;
 use tracing::{debug, instrument};
 
-fn gen_random_string(len: usize) -> String {
+pub(crate) fn gen_random_string(len: usize) -> String {
     use rand::{distributions::Alphanumeric, rngs::SmallRng, Rng, SeedableRng};
     let mut rng = SmallRng::from_entropy();
     std::iter::repeat(())
@@ -46,8 +45,12 @@ impl TcpBackendHandler for SqlBackendHandler {
     }
 
     #[instrument(skip_all, level = "debug")]
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)> {
-        debug!(?user);
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        metadata: SessionMetadata,
+    ) -> Result<(String, chrono::Duration)> {
+        debug!(?user, ?metadata);
         // TODO: Initialize the rng only once. Maybe Arc<Cell>?
         let refresh_token = gen_random_string(100);
         let refresh_token_hash = {
@@ -58,16 +61,42 @@ impl TcpBackendHandler for SqlBackendHandler {
             s.finish()
         };
         let duration = chrono::Duration::days(30);
+        let now = chrono::Utc::now();
         let new_token = model::jwt_refresh_storage::Model {
             refresh_token_hash: refresh_token_hash as i64,
             user_id: user.clone(),
-            expiry_date: chrono::Utc::now() + duration,
+            expiry_date: now + duration,
+            creation_date: Some(now),
+            ip_address: metadata.ip_address,
+            user_agent: metadata.user_agent,
         }
         .into_active_model();
         new_token.insert(&self.sql_pool).await?;
         Ok((refresh_token, duration))
     }
 
+    #[instrument(skip_all, level = "debug")]
+    async fn register_jwt(
+        &self,
+        user: &UserId,
+        jwt_hash: u64,
+        refresh_token_hash: u64,
+        expiry_date: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        debug!(?user);
+        model::jwt_storage::Model {
+            jwt_hash: jwt_hash as i64,
+            user_id: user.clone(),
+            expiry_date,
+            blacklisted: false,
+            refresh_token_hash: Some(refresh_token_hash as i64),
+        }
+        .into_active_model()
+        .insert(&self.sql_pool)
+        .await?;
+        Ok(())
+    }
+
     #[instrument(skip_all, level = "debug")]
     async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool> {
         debug!(?user);
@@ -80,39 +109,6 @@ impl TcpBackendHandler for SqlBackendHandler {
         )
     }
 
-    #[instrument(skip_all, level = "debug")]
-    async fn blacklist_jwts(&self, user: &UserId) -> Result<HashSet<u64>> {
-        debug!(?user);
-        let valid_tokens = model::JwtStorage::find()
-            .select_only()
-            .column(JwtStorageColumn::JwtHash)
-            .filter(
-                Cond::all()
-                    .add(JwtStorageColumn::UserId.eq(user))
-                    .add(JwtStorageColumn::Blacklisted.eq(false)),
-            )
-            .into_model::<OnlyJwtHash>()
-            .all(&self.sql_pool)
-            .await?
-            .into_iter()
-            .map(|t| t.jwt_hash as u64)
-            .collect::<HashSet<u64>>();
-        model::JwtStorage::update_many()
-            .col_expr(JwtStorageColumn::Blacklisted, Expr::value(true))
-            .filter(JwtStorageColumn::UserId.eq(user))
-            .exec(&self.sql_pool)
-            .await?;
-        Ok(valid_tokens)
-    }
-
-    #[instrument(skip_all, level = "debug")]
-    async fn delete_refresh_token(&self, refresh_token_hash: u64) -> Result<()> {
-        model::JwtRefreshStorage::delete_by_id(refresh_token_hash as i64)
-            .exec(&self.sql_pool)
-            .await?;
-        Ok(())
-    }
-
     #[instrument(skip_all, level = "debug")]
     async fn start_password_reset(&self, user: &UserId) -> Result<Option<String>> {
         debug!(?user);
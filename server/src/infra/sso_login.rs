@@ -0,0 +1,570 @@
+//! Login to the web UI with an upstream OpenID Connect provider, see `sso_login`.
+//!
+//! The login page sends the browser to `/auth/sso/start`, which redirects it to the provider with
+//! a new state, nonce and PKCE challenge. The provider sends the user back to the `/sso-login`
+//! page of the web UI, which passes the code on to `/auth/sso/callback`: the server exchanges it
+//! for an ID token, and opens a session for the user linked to its subject.
+//!
+//! With `jit_provisioning`, an identity that isn't linked to a user yet gets a new one, created
+//! from the claims of the ID token, and the next logins update its email and names. The user ID
+//! is taken from the `preferred_username`, the local part of the email or the subject. If a user
+//! already has it, a suffix derived from the subject is added: the same identity always ends up
+//! with the same user ID, whatever the order of the logins.
+//!
+//! The signature of the ID token isn't checked: it comes straight from the token endpoint, over
+//! TLS, which authenticates the provider (OpenID Connect Core, section 3.1.3.7).
+
+use std::time::{Duration, Instant};
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument, warn};
+
+use lldap_auth::sso;
+
+use crate::{
+    domain::{
+        error::DomainError,
+        handler::{BackendHandler, CreateUserRequest, GroupRequestFilter, UpdateUserRequest},
+        types::UserId,
+    },
+    infra::{
+        auth_service::open_session,
+        configuration::{GroupAssignmentRule, SsoLoginOptions},
+        group_assignment::apply_group_assignment_rules,
+        oidc::{pkce_challenge, redirect_url, ExpiringMap},
+        sql_backend_handler::gen_random_string,
+        tcp_backend_handler::TcpBackendHandler,
+        tcp_server::{error_to_http_response, AppState, TcpError, TcpResult},
+    },
+};
+
+/// How long the user has to log in with the provider.
+const LOGIN_VALIDITY: Duration = Duration::from_secs(10 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SCOPES: &str = "openid profile email";
+
+#[derive(Clone)]
+struct PendingLogin {
+    nonce: String,
+    code_verifier: String,
+}
+
+pub struct SsoLoginState {
+    options: SsoLoginOptions,
+    /// The `/sso-login` page of the web UI.
+    redirect_uri: String,
+    client: reqwest::Client,
+    /// By state.
+    logins: ExpiringMap<PendingLogin>,
+}
+
+impl SsoLoginState {
+    #[cfg_attr(not(feature = "sso_login"), allow(dead_code))]
+    pub fn new(server_url: &str, options: SsoLoginOptions) -> Result<Self> {
+        Ok(Self {
+            options,
+            redirect_uri: format!("{}/sso-login", server_url.trim_end_matches('/')),
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .context("Could not create the HTTP client")?,
+            logins: ExpiringMap::new(LOGIN_VALIDITY),
+        })
+    }
+}
+
+/// The claims of the ID token that we use.
+#[derive(Debug, Default, Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    /// A single client ID, or a list of them.
+    aud: Value,
+    exp: i64,
+    nonce: Option<String>,
+    email: Option<String>,
+    name: Option<String>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+    preferred_username: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    state: String,
+    code: Option<String>,
+    /// Set by the provider instead of the code when the login failed.
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Reads the claims of the ID token, and checks that it was issued for this login.
+fn parse_id_token(
+    id_token: &str,
+    options: &SsoLoginOptions,
+    nonce: &str,
+    now: i64,
+) -> Result<IdTokenClaims> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| anyhow!("The ID token isn't a JWT"))?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+        .context("Invalid ID token payload")?;
+    let claims: IdTokenClaims =
+        serde_json::from_slice(&payload).context("Invalid ID token claims")?;
+    if claims.iss != options.issuer {
+        bail!("Unexpected issuer `{}`", claims.iss);
+    }
+    let is_for_client = match &claims.aud {
+        Value::String(audience) => audience == &options.client_id,
+        Value::Array(audiences) => audiences
+            .iter()
+            .any(|audience| audience.as_str() == Some(options.client_id.as_str())),
+        _ => false,
+    };
+    if !is_for_client {
+        bail!("The ID token was issued for another client");
+    }
+    if claims.exp <= now {
+        bail!("The ID token expired");
+    }
+    if claims.nonce.as_deref() != Some(nonce) {
+        bail!("The nonce of the ID token doesn't match");
+    }
+    Ok(claims)
+}
+
+/// Keeps the characters that are safe in a user ID, and in a DN.
+fn sanitize_user_id(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// The user ID to create for the identity, then the one to use instead if a user already has the
+/// first one. Both only depend on the claims.
+fn get_user_id_candidates(claims: &IdTokenClaims) -> [UserId; 2] {
+    let email_name = claims
+        .email
+        .as_deref()
+        .and_then(|email| email.split('@').next());
+    let name = [
+        claims.preferred_username.as_deref(),
+        email_name,
+        Some(claims.sub.as_str()),
+    ]
+    .iter()
+    .flatten()
+    .map(|name| sanitize_user_id(name))
+    .find(|name| !name.is_empty())
+    .unwrap_or_else(|| "user".to_owned());
+    let hash = Sha256::digest(format!("{}|{}", claims.iss, claims.sub).as_bytes());
+    let suffix = &format!("{:x}", hash)[..8];
+    [
+        UserId::new(&name),
+        UserId::new(&format!("{}-{}", name, suffix)),
+    ]
+}
+
+async fn user_exists<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    user_id: &UserId,
+) -> TcpResult<bool> {
+    match backend_handler.get_user_details(user_id).await {
+        Ok(_) => Ok(true),
+        Err(DomainError::EntityNotFound(_)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Creates the user and links it to the identity. The groups that don't exist are skipped.
+async fn create_user<Backend: BackendHandler>(
+    backend_handler: &Backend,
+    options: &SsoLoginOptions,
+    rules: &[GroupAssignmentRule],
+    request: CreateUserRequest,
+    claims: &IdTokenClaims,
+) -> TcpResult<()> {
+    let user_id = request.user_id.clone();
+    backend_handler.create_user(request).await?;
+    backend_handler
+        .link_sso_user(&claims.iss, &claims.sub, &user_id)
+        .await?;
+    for group_name in &options.default_groups {
+        let groups = backend_handler
+            .list_groups(Some(GroupRequestFilter::DisplayName(group_name.clone())))
+            .await?;
+        match groups.first() {
+            Some(group) if group.dynamic_filter.is_none() => {
+                backend_handler
+                    .add_user_to_group(&user_id, group.id)
+                    .await?
+            }
+            _ => warn!(
+                "The SSO default group `{}` isn't a static group",
+                group_name
+            ),
+        }
+    }
+    apply_group_assignment_rules(backend_handler, rules, &user_id).await?;
+    Ok(())
+}
+
+/// Creates the user of an identity that logs in for the first time.
+async fn provision_user<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    options: &SsoLoginOptions,
+    claims: &IdTokenClaims,
+) -> TcpResult<UserId> {
+    let email = claims.email.clone().ok_or_else(|| {
+        TcpError::UnauthorizedError("The ID token has no email to create the user".to_string())
+    })?;
+    let [user_id, fallback_user_id] = get_user_id_candidates(claims);
+    let user_id = if !user_exists(&data.backend_handler, &user_id).await? {
+        user_id
+    } else if !user_exists(&data.backend_handler, &fallback_user_id).await? {
+        warn!(
+            "The user {} already exists, creating {} for the SSO identity `{}`",
+            user_id, fallback_user_id, claims.sub
+        );
+        fallback_user_id
+    } else {
+        warn!(
+            "Both {} and {} already exist, cannot create a user for the SSO identity `{}`",
+            user_id, fallback_user_id, claims.sub
+        );
+        return Err(TcpError::UnauthorizedError(
+            "Could not create a user for this identity".to_string(),
+        ));
+    };
+    let request = CreateUserRequest {
+        user_id: user_id.clone(),
+        email,
+        display_name: claims.name.clone(),
+        first_name: claims.given_name.clone(),
+        last_name: claims.family_name.clone(),
+        ..Default::default()
+    };
+    // Either the user is created with its link and its groups, or not at all.
+    let transaction = data.backend_handler.begin_transaction().await?;
+    let result = create_user(
+        &transaction,
+        options,
+        &data.group_assignment_rules,
+        request,
+        claims,
+    )
+    .await;
+    transaction.end_transaction(result.is_ok()).await?;
+    result?;
+    info!(
+        "Created the user {} for the SSO identity `{}`",
+        user_id, claims.sub
+    );
+    Ok(user_id)
+}
+
+/// Copies the claims of the ID token that changed to the user.
+async fn update_user<Backend: BackendHandler>(
+    data: &AppState<Backend>,
+    user_id: &UserId,
+    claims: &IdTokenClaims,
+) -> TcpResult<()> {
+    let user = data.backend_handler.get_user_details(user_id).await?;
+    let changed = |claim: &Option<String>, value: Option<&String>| {
+        claim.clone().filter(|claim| Some(claim) != value)
+    };
+    let request = UpdateUserRequest {
+        user_id: user_id.clone(),
+        email: changed(&claims.email, Some(&user.email)),
+        display_name: changed(&claims.name, user.display_name.as_ref()),
+        first_name: changed(&claims.given_name, user.first_name.as_ref()),
+        last_name: changed(&claims.family_name, user.last_name.as_ref()),
+        ..Default::default()
+    };
+    if request.email.is_none()
+        && request.display_name.is_none()
+        && request.first_name.is_none()
+        && request.last_name.is_none()
+    {
+        return Ok(());
+    }
+    data.backend_handler.update_user(request).await?;
+    apply_group_assignment_rules(&data.backend_handler, &data.group_assignment_rules, user_id)
+        .await?;
+    Ok(())
+}
+
+fn get_sso_login<Backend>(data: &AppState<Backend>) -> TcpResult<&SsoLoginState> {
+    data.sso_login
+        .as_deref()
+        .ok_or_else(|| TcpError::BadRequest("The SSO login is disabled".to_string()))
+}
+
+/// Exchanges the code for the ID token.
+async fn get_id_token(state: &SsoLoginState, code: &str, code_verifier: &str) -> Result<String> {
+    let response = state
+        .client
+        .post(&state.options.token_endpoint)
+        .basic_auth(
+            &state.options.client_id,
+            Some(state.options.client_secret.unsecure()),
+        )
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &state.redirect_uri),
+            ("code_verifier", code_verifier),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response: TokenResponse =
+        serde_json::from_str(&response).context("Invalid response of the token endpoint")?;
+    Ok(response.id_token)
+}
+
+async fn get_claims(
+    state: &SsoLoginState,
+    login: &PendingLogin,
+    code: &str,
+) -> Result<IdTokenClaims> {
+    let id_token = get_id_token(state, code, &login.code_verifier).await?;
+    parse_id_token(
+        &id_token,
+        &state.options,
+        &login.nonce,
+        chrono::Utc::now().timestamp(),
+    )
+}
+
+/// Whether the login page offers the SSO login.
+async fn get_sso_login_info_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    HttpResponse::Ok().json(sso::ServerSsoLoginInfo {
+        enabled: data.sso_login.is_some(),
+    })
+}
+
+fn get_sso_login_start<Backend>(data: &AppState<Backend>) -> TcpResult<String> {
+    let state = get_sso_login(data)?;
+    let nonce = gen_random_string(32);
+    let code_verifier = gen_random_string(64);
+    let code_challenge = pkce_challenge(&code_verifier);
+    let login_state = state.logins.insert_at(
+        PendingLogin {
+            nonce: nonce.clone(),
+            code_verifier,
+        },
+        Instant::now(),
+    );
+    redirect_url(
+        &state.options.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", &state.options.client_id),
+            ("redirect_uri", &state.redirect_uri),
+            ("scope", SCOPES),
+            ("nonce", &nonce),
+            ("code_challenge", &code_challenge),
+            ("code_challenge_method", "S256"),
+        ],
+        Some(&login_state),
+    )
+}
+
+async fn get_sso_login_start_handler<Backend>(data: web::Data<AppState<Backend>>) -> HttpResponse {
+    get_sso_login_start(&data)
+        .map(|location| {
+            HttpResponse::Found()
+                .append_header(("Location", location))
+                .finish()
+        })
+        .unwrap_or_else(error_to_http_response)
+}
+
+#[instrument(skip_all, level = "debug")]
+async fn get_sso_login_callback<Backend>(
+    data: web::Data<AppState<Backend>>,
+    params: web::Query<CallbackParams>,
+    request: HttpRequest,
+) -> TcpResult<HttpResponse>
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    let state = get_sso_login(&data)?;
+    let login = state
+        .logins
+        .take_at(&params.state, Instant::now())
+        .ok_or_else(|| TcpError::UnauthorizedError("Invalid or expired SSO login".to_string()))?;
+    if let Some(error) = &params.error {
+        return Err(TcpError::UnauthorizedError(format!(
+            "The SSO provider refused the login: {}",
+            error
+        )));
+    }
+    let code = params
+        .code
+        .as_deref()
+        .ok_or_else(|| TcpError::BadRequest("Missing code".to_string()))?;
+    let claims = get_claims(state, &login, code).await.map_err(|e| {
+        warn!("Rejected an SSO login: {:#}", e);
+        TcpError::UnauthorizedError("Invalid SSO login".to_string())
+    })?;
+    let options = &state.options;
+    let user_id = match data
+        .backend_handler
+        .get_sso_user(&claims.iss, &claims.sub)
+        .await?
+    {
+        Some(user_id) => {
+            if options.jit_provisioning {
+                // E.g. the new email is already used by another user.
+                if let Err(e) = update_user(&data, &user_id, &claims).await {
+                    warn!("Could not update {} from the SSO claims: {}", user_id, e);
+                }
+            }
+            user_id
+        }
+        None if options.jit_provisioning => provision_user(&data, options, &claims).await?,
+        None => {
+            warn!(
+                "Rejected the SSO login of `{}`: no user is linked to it",
+                claims.sub
+            );
+            return Err(TcpError::UnauthorizedError(
+                "No user is linked to this identity".to_string(),
+            ));
+        }
+    };
+    info!("{} logged in with SSO", user_id);
+    open_session(&data, &user_id, &request).await
+}
+
+async fn get_sso_login_callback_handler<Backend>(
+    data: web::Data<AppState<Backend>>,
+    params: web::Query<CallbackParams>,
+    request: HttpRequest,
+) -> HttpResponse
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    get_sso_login_callback(data, params, request)
+        .await
+        .unwrap_or_else(error_to_http_response)
+}
+
+pub fn configure_server<Backend>(cfg: &mut web::ServiceConfig)
+where
+    Backend: TcpBackendHandler + BackendHandler + 'static,
+{
+    cfg.service(web::resource("").route(web::get().to(get_sso_login_info_handler::<Backend>)))
+        .service(
+            web::resource("/start").route(web::get().to(get_sso_login_start_handler::<Backend>)),
+        )
+        .service(
+            web::resource("/callback")
+                .route(web::get().to(get_sso_login_callback_handler::<Backend>)),
+        );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secstr::SecUtf8;
+    use serde_json::json;
+
+    fn make_options() -> SsoLoginOptions {
+        SsoLoginOptions {
+            issuer: "https://sso.example.com".to_string(),
+            authorization_endpoint: "https://sso.example.com/authorize".to_string(),
+            token_endpoint: "https://sso.example.com/token".to_string(),
+            client_id: "lldap".to_string(),
+            client_secret: SecUtf8::from("secret"),
+            jit_provisioning: true,
+            default_groups: Vec::new(),
+        }
+    }
+
+    fn make_id_token(claims: Value) -> String {
+        format!(
+            "eyJhbGciOiJSUzI1NiJ9.{}.signature",
+            base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[test]
+    fn test_parse_id_token() {
+        let options = make_options();
+        let claims = json!({
+            "iss": "https://sso.example.com",
+            "sub": "1234",
+            "aud": ["lldap", "other"],
+            "exp": 2000,
+            "nonce": "abc",
+            "email": "bob@example.com",
+        });
+        let token = make_id_token(claims.clone());
+        let parsed = parse_id_token(&token, &options, "abc", 1000).unwrap();
+        assert_eq!(parsed.sub, "1234");
+        assert_eq!(parsed.email.as_deref(), Some("bob@example.com"));
+        assert!(parse_id_token(&token, &options, "other nonce", 1000).is_err());
+        assert!(parse_id_token(&token, &options, "abc", 2000).is_err());
+        let mut other_issuer = claims.clone();
+        other_issuer["iss"] = json!("https://evil.example.com");
+        assert!(parse_id_token(&make_id_token(other_issuer), &options, "abc", 1000).is_err());
+        let mut other_client = claims;
+        other_client["aud"] = json!("other");
+        assert!(parse_id_token(&make_id_token(other_client), &options, "abc", 1000).is_err());
+        assert!(parse_id_token("garbage", &options, "abc", 1000).is_err());
+    }
+
+    #[test]
+    fn test_get_user_id_candidates() {
+        let claims = IdTokenClaims {
+            iss: "https://sso.example.com".to_string(),
+            sub: "1234".to_string(),
+            preferred_username: Some("Bob Smith".to_string()),
+            email: Some("bob@example.com".to_string()),
+            ..Default::default()
+        };
+        let [user_id, fallback_user_id] = get_user_id_candidates(&claims);
+        assert_eq!(user_id, UserId::new("bobsmith"));
+        assert!(fallback_user_id.as_str().starts_with("bobsmith-"));
+        assert_eq!(fallback_user_id.as_str().len(), "bobsmith-".len() + 8);
+        // The fallback is derived from the identity, not from the order of the logins.
+        assert_eq!(get_user_id_candidates(&claims)[1], fallback_user_id);
+        let other_identity = IdTokenClaims {
+            sub: "5678".to_string(),
+            ..claims
+        };
+        assert_ne!(get_user_id_candidates(&other_identity)[1], fallback_user_id);
+        let without_username = IdTokenClaims {
+            preferred_username: None,
+            ..other_identity
+        };
+        assert_eq!(
+            get_user_id_candidates(&without_username)[0],
+            UserId::new("bob")
+        );
+        let without_email = IdTokenClaims {
+            email: None,
+            ..without_username
+        };
+        assert_eq!(
+            get_user_id_candidates(&without_email)[0],
+            UserId::new("5678")
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Lightweight process-wide counters, exposed to admins through the `stats` GraphQL query.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static ACTIVE_LDAP_CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+static SUCCESSFUL_LDAP_BINDS: AtomicU64 = AtomicU64::new(0);
+static FAILED_LDAP_BINDS: AtomicU64 = AtomicU64::new(0);
+static GRAPHQL_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Counts an LDAP connection as active for as long as the guard is alive.
+pub struct LdapConnectionGuard(());
+
+impl Drop for LdapConnectionGuard {
+    fn drop(&mut self) {
+        ACTIVE_LDAP_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn ldap_connection_opened() -> LdapConnectionGuard {
+    ACTIVE_LDAP_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    LdapConnectionGuard(())
+}
+
+pub fn record_ldap_bind(success: bool) {
+    if success {
+        SUCCESSFUL_LDAP_BINDS.fetch_add(1, Ordering::Relaxed);
+    } else {
+        FAILED_LDAP_BINDS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn record_graphql_request() {
+    GRAPHQL_REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    pub active_ldap_connections: u64,
+    pub successful_ldap_binds: u64,
+    pub failed_ldap_binds: u64,
+    pub graphql_requests: u64,
+}
+
+pub fn get_counters() -> Counters {
+    Counters {
+        active_ldap_connections: ACTIVE_LDAP_CONNECTIONS.load(Ordering::Relaxed),
+        successful_ldap_binds: SUCCESSFUL_LDAP_BINDS.load(Ordering::Relaxed),
+        failed_ldap_binds: FAILED_LDAP_BINDS.load(Ordering::Relaxed),
+        graphql_requests: GRAPHQL_REQUESTS.load(Ordering::Relaxed),
+    }
+}
@@ -1,15 +1,29 @@
 use async_trait::async_trait;
 use std::collections::HashSet;
 
-use crate::domain::{error::Result, types::UserId};
+use crate::domain::{
+    error::Result,
+    types::{SessionMetadata, UserId},
+};
 
 #[async_trait]
 pub trait TcpBackendHandler {
     async fn get_jwt_blacklist(&self) -> anyhow::Result<HashSet<u64>>;
-    async fn create_refresh_token(&self, user: &UserId) -> Result<(String, chrono::Duration)>;
+    async fn create_refresh_token(
+        &self,
+        user: &UserId,
+        metadata: SessionMetadata,
+    ) -> Result<(String, chrono::Duration)>;
+    /// Records a JWT created from the refresh token, so that it can be blacklisted when its
+    /// session is ended.
+    async fn register_jwt(
+        &self,
+        user: &UserId,
+        jwt_hash: u64,
+        refresh_token_hash: u64,
+        expiry_date: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()>;
     async fn check_token(&self, refresh_token_hash: u64, user: &UserId) -> Result<bool>;
-    async fn blacklist_jwts(&self, user: &UserId) -> Result<HashSet<u64>>;
-    async fn delete_refresh_token(&self, refresh_token_hash: u64) -> Result<()>;
 
     /// Request a token to reset a user's password.
     /// If the user doesn't exist, returns `Ok(None)`, otherwise `Ok(Some(token))`.
@@ -36,22 +50,92 @@ mockall::mock! {
     #[async_trait]
     impl GroupBackendHandler for TestTcpBackendHandler {
         async fn list_groups(&self, filters: Option<GroupRequestFilter>) -> Result<Vec<Group>>;
+        async fn list_groups_page(&self, filters: Option<GroupRequestFilter>, page: PageRequest<GroupColumn>) -> Result<Page<GroupDetails>>;
         async fn get_group_details(&self, group_id: GroupId) -> Result<GroupDetails>;
         async fn update_group(&self, request: UpdateGroupRequest) -> Result<()>;
         async fn create_group(&self, group_name: &str) -> Result<GroupId>;
+        async fn create_dynamic_group(&self, group_name: &str, filter: &str) -> Result<GroupId>;
         async fn delete_group(&self, group_id: GroupId) -> Result<()>;
+        async fn delete_groups(&self, group_ids: &[GroupId]) -> Result<()>;
+        async fn add_group_to_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()>;
+        async fn remove_group_from_group(&self, group_id: GroupId, parent_group_id: GroupId) -> Result<()>;
+        async fn list_group_nestings(&self) -> Result<Vec<GroupNesting>>;
+        async fn list_group_managers(&self, group_id: GroupId) -> Result<Vec<UserId>>;
+        async fn add_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        async fn remove_group_manager(&self, group_id: GroupId, user_id: &UserId) -> Result<()>;
+        async fn create_membership_request(&self, group_id: GroupId, user_id: &UserId, requested_by: &UserId) -> Result<MembershipRequest>;
+        async fn list_membership_requests(&self, group_id: Option<GroupId>) -> Result<Vec<MembershipRequest>>;
+        async fn get_membership_request(&self, request_id: i32) -> Result<MembershipRequest>;
+        async fn approve_membership_request(&self, request_id: i32) -> Result<()>;
+        async fn delete_membership_request(&self, request_id: i32) -> Result<()>;
+        async fn get_group_attributes(&self, group_id: GroupId) -> Result<Vec<AttributeValue>>;
+        async fn list_group_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_group_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_group_attribute(&self, name: &str) -> Result<()>;
     }
     #[async_trait]
     impl UserBackendHandler for TestBackendHandler {
         async fn list_users(&self, filters: Option<UserRequestFilter>, get_groups: bool) -> Result<Vec<UserAndGroups>>;
+        async fn list_users_sorted(&self, filters: Option<UserRequestFilter>, get_groups: bool, sort_keys: Vec<SortKey<UserColumn>>) -> Result<Vec<UserAndGroups>>;
+        async fn list_users_page(&self, filters: Option<UserRequestFilter>, page: PageRequest<UserColumn>) -> Result<Page<User>>;
         async fn get_user_details(&self, user_id: &UserId) -> Result<User>;
         async fn create_user(&self, request: CreateUserRequest) -> Result<()>;
+        async fn check_emails_available(&self, user_id: &UserId, email: &str, email_aliases: &[String]) -> Result<()>;
+        async fn create_users(&self, requests: Vec<CreateUserRequest>, best_effort: bool) -> Result<Vec<Result<()>>>;
         async fn update_user(&self, request: UpdateUserRequest) -> Result<()>;
         async fn delete_user(&self, user_id: &UserId) -> Result<()>;
+        async fn delete_users(&self, user_ids: &[UserId], best_effort: bool) -> Result<Vec<Result<()>>>;
         async fn get_user_groups(&self, user_id: &UserId) -> Result<HashSet<GroupDetails>>;
         async fn add_user_to_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn add_users_to_group(&self, user_ids: &[UserId], group_id: GroupId, best_effort: bool) -> Result<Vec<Result<()>>>;
         async fn remove_user_from_group(&self, user_id: &UserId, group_id: GroupId) -> Result<()>;
+        async fn set_group_members(&self, group_id: GroupId, user_ids: &[UserId]) -> Result<()>;
+        async fn create_group_with_members(&self, group_name: &str, user_ids: &[UserId]) -> Result<GroupId>;
+        async fn merge_users(&self, request: MergeUsersRequest) -> Result<UserMergeReport>;
+        async fn list_user_attribute_schema(&self) -> Result<Vec<AttributeSchema>>;
+        async fn add_user_attribute(&self, schema: AttributeSchema) -> Result<()>;
+        async fn delete_user_attribute(&self, name: &str) -> Result<()>;
     }
     #[async_trait]
-    impl BackendHandler for TestTcpBackendHandler {}
+    impl BackendHandler for TestTcpBackendHandler {
+        async fn get_stats(&self) -> Result<BackendStats>;
+        async fn repair_memberships(&self) -> Result<MembershipRepairReport>;
+        async fn optimize_database(&self) -> Result<DatabaseOptimizationReport>;
+        async fn create_magic_login_token(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+        async fn consume_magic_login_token(&self, token: &str) -> Result<UserId>;
+        async fn create_password_reset_token(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+        async fn start_totp_enrollment(&self, user_id: &UserId) -> Result<String>;
+        async fn confirm_totp_enrollment(&self, user_id: &UserId, code: &str) -> Result<Vec<String>>;
+        async fn is_totp_enabled(&self, user_id: &UserId) -> Result<bool>;
+        async fn check_totp_code(&self, user_id: &UserId, code: &str) -> Result<bool>;
+        async fn disable_totp(&self, user_id: &UserId) -> Result<()>;
+        async fn unlock_user(&self, user_id: &UserId) -> Result<()>;
+        async fn use_grace_login(&self, user_id: &UserId, max_grace_logins: u32) -> Result<Option<u32>>;
+        async fn import_password_hash(&self, user_id: &UserId, password_hash: &str) -> Result<()>;
+        async fn create_temporary_password(&self, user_id: &UserId, valid_for: chrono::Duration) -> Result<String>;
+        async fn consume_temporary_password(&self, user_id: &UserId, password: &str) -> Result<()>;
+        async fn set_account_validity(&self, user_id: &UserId, activation_date: Option<DateTime>, expiration_date: Option<DateTime>) -> Result<()>;
+        async fn set_user_enabled(&self, user_id: &UserId, enabled: bool) -> Result<()>;
+        async fn add_webauthn_credential(&self, credential: WebauthnCredential) -> Result<()>;
+        async fn list_webauthn_credentials(&self, user_id: &UserId) -> Result<Vec<WebauthnCredential>>;
+        async fn update_webauthn_credential(&self, credential_id: &str, credential: String) -> Result<()>;
+        async fn delete_webauthn_credential(&self, user_id: &UserId, credential_id: &str) -> Result<()>;
+        async fn get_sso_user(&self, issuer: &str, subject: &str) -> Result<Option<UserId>>;
+        async fn link_sso_user(&self, issuer: &str, subject: &str, user_id: &UserId) -> Result<()>;
+        async fn record_sync_entries(&self, digests: Vec<(Uuid, String)>) -> Result<SyncEntries>;
+        async fn create_api_token(&self, user_id: &UserId, name: &str, scopes: &[ApiTokenScope]) -> Result<(ApiToken, String)>;
+        async fn list_api_tokens(&self) -> Result<Vec<ApiToken>>;
+        async fn get_api_token(&self, secret: &str) -> Result<Option<ApiToken>>;
+        async fn delete_api_token(&self, token_id: &str) -> Result<()>;
+        async fn list_sessions(&self, user_id: &UserId) -> Result<Vec<Session>>;
+        async fn delete_session(&self, user_id: &UserId, session_id: i64) -> Result<HashSet<u64>>;
+        async fn delete_sessions(&self, user_id: &UserId) -> Result<HashSet<u64>>;
+        async fn record_audit_event(&self, event: AuditEvent) -> Result<()>;
+        async fn list_audit_logs(&self, filter: AuditLogFilter, offset: u64, limit: u64) -> Result<Page<AuditLogEntry>>;
+        async fn create_webhook(&self, url: &str, events: &[WebhookEvent]) -> Result<Webhook>;
+        async fn list_webhooks(&self) -> Result<Vec<Webhook>>;
+        async fn delete_webhook(&self, webhook_id: i32) -> Result<()>;
+        async fn begin_transaction(&self) -> Result<Self>;
+        async fn end_transaction(self, commit: bool) -> Result<()>;
+    }
 }
@@ -3,12 +3,26 @@ use crate::{
         error::DomainError,
         handler::{BackendHandler, LoginHandler},
         opaque_handler::OpaqueHandler,
+        types::{DirectoryChange, PasswordExpiry, UserColumn},
     },
     infra::{
         auth_service,
-        configuration::{Configuration, MailOptions},
+        configuration::{
+            Configuration, GroupAssignmentRule, MailOptions, ProxyAuthOptions, SsoLoginOptions,
+            UserDetailsSection,
+        },
+        graphql::{
+            mutation::GroupLimit,
+            persisted_queries::{load_queries_from_directory, PersistedQueries},
+        },
+        jwt_keys::JwtKeys,
         logging::CustomRootSpanBuilder,
+        login_challenges::LoginChallenges,
+        oidc::{self, OidcState},
+        sso_login::SsoLoginState,
         tcp_backend_handler::*,
+        totp_challenges::TotpChallenges,
+        webauthn::WebauthnState,
     },
 };
 use actix_files::{Files, NamedFile};
@@ -17,12 +31,12 @@ use actix_server::ServerBuilder;
 use actix_service::map_config;
 use actix_web::{dev::AppConfig, web, App, HttpResponse};
 use anyhow::{Context, Result};
-use hmac::{Hmac, NewMac};
-use sha2::Sha512;
-use std::collections::HashSet;
+use lldap_auth::password_strength::PasswordPolicy;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use std::sync::RwLock;
-use tracing::info;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
 
 async fn index() -> actix_web::Result<NamedFile> {
     let path = PathBuf::from(r"app/index.html");
@@ -54,7 +68,9 @@ pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
             | DomainError::UnknownCryptoError(_) => HttpResponse::InternalServerError(),
             DomainError::Base64DecodeError(_)
             | DomainError::BinarySerializationError(_)
-            | DomainError::EntityNotFound(_) => HttpResponse::BadRequest(),
+            | DomainError::EntityNotFound(_)
+            | DomainError::EntityAlreadyExists(_)
+            | DomainError::InvalidInput(_) => HttpResponse::BadRequest(),
         },
         TcpError::BadRequest(_) => HttpResponse::BadRequest(),
         TcpError::InternalServerError(_) => HttpResponse::InternalServerError(),
@@ -63,31 +79,75 @@ pub(crate) fn error_to_http_response(error: TcpError) -> HttpResponse {
     .body(error.to_string())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn http_config<Backend>(
     cfg: &mut web::ServiceConfig,
     backend_handler: Backend,
-    jwt_secret: secstr::SecUtf8,
-    jwt_blacklist: HashSet<u64>,
-    server_url: String,
-    mail_options: MailOptions,
+    jwt_keys: Arc<JwtKeys>,
+    jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
+    persisted_queries: HashMap<String, String>,
+    totp_challenges: Arc<TotpChallenges>,
+    login_challenges: Option<Arc<LoginChallenges>>,
+    webauthn: Option<Arc<WebauthnState>>,
+    oidc: Option<Arc<OidcState>>,
+    sso_login: Option<Arc<SsoLoginState>>,
+    changes: broadcast::Sender<DirectoryChange>,
+    config: &Configuration,
 ) where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Sync + 'static,
 {
     cfg.app_data(web::Data::new(AppState::<Backend> {
         backend_handler,
-        jwt_key: Hmac::new_varkey(jwt_secret.unsecure().as_bytes()).unwrap(),
-        jwt_blacklist: RwLock::new(jwt_blacklist),
-        server_url,
-        mail_options,
+        jwt_keys,
+        jwt_blacklist,
+        totp_challenges,
+        login_challenges,
+        webauthn,
+        oidc,
+        sso_login,
+        server_url: config.http_url.clone(),
+        mail_options: config.smtp_options.clone(),
+        default_language: config.default_language.clone(),
+        ldap_base_dn: config.ldap_base_dn.to_ascii_lowercase(),
+        password_policy: Arc::new(config.get_password_policy()),
+        password_expiry: config.get_password_expiry(),
+        group_assignment_rules: config.group_assignment_rules.clone(),
+        user_details_layout: if config.user_details_layout.is_empty() {
+            UserDetailsSection::default_layout()
+        } else {
+            config.user_details_layout.clone()
+        },
+        enable_magic_login_links: config.enable_magic_login_links,
+        proxy_auth: config.proxy_auth.clone(),
+        user_search_columns: config
+            .get_user_search_columns()
+            .expect("Invalid user_search_attributes"),
+        everyone_group: config.everyone_group.clone(),
+        strict_group_membership_adds: config.strict_group_membership_adds,
+        group_limit: config.max_groups_per_user.map(|max_groups| GroupLimit {
+            max_groups,
+            exempt_admins: config.max_groups_per_user_exempts_admins,
+        }),
+        membership_approval_groups: config.membership_approval_groups.clone(),
+        persisted_queries: PersistedQueries::new(persisted_queries, config.persisted_queries_only),
+        changes,
     }))
     .route("/health", web::get().to(|| HttpResponse::Ok().finish()))
     .service(web::scope("/auth").configure(auth_service::configure_server::<Backend>))
+    // OpenID Connect provider.
+    .service(web::scope("/oidc").configure(oidc::configure_server::<Backend>))
+    .route(
+        "/.well-known/openid-configuration",
+        web::get().to(oidc::get_discovery_handler::<Backend>),
+    )
     // API endpoint.
     .service(
         web::scope("/api")
             .wrap(auth_service::CookieToHeaderTranslatorFactory)
             .configure(super::graphql::api::configure_endpoint::<Backend>),
     )
+    // SCIM 2.0 provisioning.
+    .service(web::scope("/scim/v2").configure(super::scim::configure_server::<Backend>))
     // Serve the /pkg path with the compiled WASM app.
     .service(Files::new("/pkg", "./app/pkg"))
     // Serve static files
@@ -102,29 +162,125 @@ fn http_config<Backend>(
     );
 }
 
+#[cfg(feature = "sso_login")]
+fn get_sso_login_state(
+    server_url: &str,
+    options: &SsoLoginOptions,
+) -> Result<Option<Arc<SsoLoginState>>> {
+    let state = SsoLoginState::new(server_url, options.clone())
+        .context("while setting up the SSO login")?;
+    info!("SSO login enabled with {}", options.issuer);
+    Ok(Some(Arc::new(state)))
+}
+
+#[cfg(not(feature = "sso_login"))]
+fn get_sso_login_state(
+    _server_url: &str,
+    _options: &SsoLoginOptions,
+) -> Result<Option<Arc<SsoLoginState>>> {
+    warn!("sso_login is ignored: lldap was built without the sso_login feature");
+    Ok(None)
+}
+
 pub(crate) struct AppState<Backend> {
     pub backend_handler: Backend,
-    pub jwt_key: Hmac<Sha512>,
-    pub jwt_blacklist: RwLock<HashSet<u64>>,
+    pub jwt_keys: Arc<JwtKeys>,
+    /// Shared by all the workers, so that the revoked sessions are rejected by all of them.
+    pub jwt_blacklist: Arc<RwLock<HashSet<u64>>>,
+    /// The logins waiting for the TOTP code of the user, shared by all the workers.
+    pub totp_challenges: Arc<TotpChallenges>,
+    /// The proof of work of the password logins, shared by all the workers. `None` if disabled.
+    pub login_challenges: Option<Arc<LoginChallenges>>,
+    /// The security key and passkey logins. `None` if `http_url` isn't a valid origin.
+    pub webauthn: Option<Arc<WebauthnState>>,
+    /// `None` if no OIDC client is configured.
+    pub oidc: Option<Arc<OidcState>>,
+    /// `None` if the SSO login is disabled.
+    pub sso_login: Option<Arc<SsoLoginState>>,
     pub server_url: String,
     pub mail_options: MailOptions,
+    /// The language of the users who didn't set a preferred language.
+    pub default_language: String,
+    /// The LDAP base DN, in lowercase.
+    pub ldap_base_dn: String,
+    pub password_policy: Arc<PasswordPolicy>,
+    /// `None` if the passwords don't expire.
+    pub password_expiry: Option<PasswordExpiry>,
+    pub group_assignment_rules: Vec<GroupAssignmentRule>,
+    pub user_details_layout: Vec<UserDetailsSection>,
+    pub enable_magic_login_links: bool,
+    pub proxy_auth: ProxyAuthOptions,
+    pub user_search_columns: Vec<UserColumn>,
+    pub everyone_group: Option<String>,
+    pub strict_group_membership_adds: bool,
+    pub group_limit: Option<GroupLimit>,
+    pub membership_approval_groups: Vec<String>,
+    pub persisted_queries: PersistedQueries,
+    /// The changes of the directory, streamed to the GraphQL subscriptions.
+    pub changes: broadcast::Sender<DirectoryChange>,
 }
 
 pub async fn build_tcp_server<Backend>(
     config: &Configuration,
     backend_handler: Backend,
+    changes: broadcast::Sender<DirectoryChange>,
     server_builder: ServerBuilder,
 ) -> Result<ServerBuilder>
 where
     Backend: TcpBackendHandler + BackendHandler + LoginHandler + OpaqueHandler + Sync + 'static,
 {
-    let jwt_secret = config.jwt_secret.clone();
-    let jwt_blacklist = backend_handler
-        .get_jwt_blacklist()
-        .await
-        .context("while getting the jwt blacklist")?;
-    let server_url = config.http_url.clone();
-    let mail_options = config.smtp_options.clone();
+    let jwt_blacklist = Arc::new(RwLock::new(
+        backend_handler
+            .get_jwt_blacklist()
+            .await
+            .context("while getting the jwt blacklist")?,
+    ));
+    let persisted_queries = match &config.persisted_queries_dir {
+        None => HashMap::new(),
+        Some(dir) => {
+            let queries = load_queries_from_directory(std::path::Path::new(dir))
+                .context("while loading the persisted queries")?;
+            info!("Loaded {} persisted queries from {}", queries.len(), dir);
+            queries
+        }
+    };
+    let jwt_keys =
+        Arc::new(JwtKeys::from_config(config).context("while loading the JWT signing keys")?);
+    let totp_challenges = Arc::new(TotpChallenges::default());
+    let login_challenges = if config.login_challenge.enabled {
+        Some(Arc::new(LoginChallenges::new(
+            config.login_challenge.difficulty,
+        )))
+    } else {
+        None
+    };
+    let webauthn = match WebauthnState::new(&config.http_url) {
+        Ok(webauthn) => Some(Arc::new(webauthn)),
+        Err(e) => {
+            warn!(
+                "Security key logins are disabled, invalid http_url '{}': {:#}",
+                config.http_url, e
+            );
+            None
+        }
+    };
+    let oidc = if config.oidc_clients.is_empty() {
+        None
+    } else {
+        let key_path = std::path::Path::new(&config.key_file).with_file_name("oidc_key.pem");
+        let oidc = OidcState::new(&config.http_url, config.oidc_clients.clone(), &key_path)
+            .context("while setting up the OpenID Connect provider")?;
+        info!(
+            "OpenID Connect provider enabled for {} clients",
+            config.oidc_clients.len()
+        );
+        Some(Arc::new(oidc))
+    };
+    let sso_login = match &config.sso_login {
+        None => None,
+        Some(options) => get_sso_login_state(&config.http_url, options)?,
+    };
+    let server_config = config.clone();
     info!("Starting the API/web server on port {}", config.http_port);
     server_builder
         .bind(
@@ -132,10 +288,16 @@ where
             (config.http_host.clone(), config.http_port),
             move || {
                 let backend_handler = backend_handler.clone();
-                let jwt_secret = jwt_secret.clone();
+                let jwt_keys = jwt_keys.clone();
                 let jwt_blacklist = jwt_blacklist.clone();
-                let server_url = server_url.clone();
-                let mail_options = mail_options.clone();
+                let persisted_queries = persisted_queries.clone();
+                let totp_challenges = totp_challenges.clone();
+                let login_challenges = login_challenges.clone();
+                let webauthn = webauthn.clone();
+                let oidc = oidc.clone();
+                let sso_login = sso_login.clone();
+                let changes = changes.clone();
+                let config = server_config.clone();
                 HttpServiceBuilder::new()
                     .finish(map_config(
                         App::new()
@@ -144,10 +306,16 @@ where
                                 http_config(
                                     cfg,
                                     backend_handler,
-                                    jwt_secret,
+                                    jwt_keys,
                                     jwt_blacklist,
-                                    server_url,
-                                    mail_options,
+                                    persisted_queries,
+                                    totp_challenges,
+                                    login_challenges,
+                                    webauthn,
+                                    oidc,
+                                    sso_login,
+                                    changes,
+                                    &config,
                                 )
                             }),
                         |_| AppConfig::default(),
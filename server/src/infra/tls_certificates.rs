@@ -0,0 +1,219 @@
+//! The server certificates of LDAPS and StartTLS, which can be replaced without a restart.
+//!
+//! The certificates are reloaded from their files on SIGHUP, and optionally when the files change
+//! on disk (e.g. after a renewal by certbot). Only the new handshakes use the new certificates: the
+//! established connections are not affected. A certificate can also be configured per server name
+//! (SNI), the main one being used for the other names and for clients that don't send one.
+
+use anyhow::{anyhow, Context, Result};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    Certificate, PrivateKey,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use tracing::{error, info, warn};
+
+use crate::infra::configuration::LdapsOptions;
+
+fn read_private_key(key_file: &str) -> Result<PrivateKey> {
+    use rustls_pemfile::{pkcs8_private_keys, rsa_private_keys};
+    use std::{fs::File, io::BufReader};
+    pkcs8_private_keys(&mut BufReader::new(File::open(key_file)?))
+        .map_err(anyhow::Error::from)
+        .and_then(|keys| {
+            keys.into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No PKCS8 key"))
+        })
+        .or_else(|_| {
+            rsa_private_keys(&mut BufReader::new(File::open(key_file)?))
+                .map_err(anyhow::Error::from)
+                .and_then(|keys| {
+                    keys.into_iter()
+                        .next()
+                        .ok_or_else(|| anyhow!("No PKCS1 key"))
+                })
+        })
+        .with_context(|| {
+            format!(
+                "Cannot read either PKCS1 or PKCS8 private key from {}",
+                key_file
+            )
+        })
+        .map(rustls::PrivateKey)
+}
+
+fn read_certified_key(cert_file: &str, key_file: &str) -> Result<CertifiedKey> {
+    use rustls_pemfile::certs;
+    use std::{fs::File, io::BufReader};
+    let certs = certs(&mut BufReader::new(
+        File::open(cert_file).with_context(|| format!("while opening {}", cert_file))?,
+    ))?
+    .into_iter()
+    .map(Certificate)
+    .collect::<Vec<_>>();
+    if certs.is_empty() {
+        return Err(anyhow!("No certificate in {}", cert_file));
+    }
+    let private_key = read_private_key(key_file)?;
+    let signing_key = rustls::sign::any_supported_type(&private_key)
+        .map_err(|_| anyhow!("Unsupported private key type in {}", key_file))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// The files of a certificate, and the server name it's for (`None` for the main one).
+#[derive(Clone, Debug)]
+struct CertificateFiles {
+    server_name: Option<String>,
+    cert_file: String,
+    key_file: String,
+}
+
+impl CertificateFiles {
+    fn modification_times(&self) -> [Option<SystemTime>; 2] {
+        let modified = |file: &str| std::fs::metadata(file).and_then(|m| m.modified()).ok();
+        [modified(&self.cert_file), modified(&self.key_file)]
+    }
+}
+
+struct LoadedCertificates {
+    default: Arc<CertifiedKey>,
+    /// By lowercase server name.
+    by_server_name: HashMap<String, Arc<CertifiedKey>>,
+    modification_times: Vec<[Option<SystemTime>; 2]>,
+}
+
+/// Picks the certificate from the server name requested by the client.
+pub struct CertificateResolver {
+    files: Vec<CertificateFiles>,
+    certificates: RwLock<LoadedCertificates>,
+}
+
+impl CertificateResolver {
+    pub fn new(options: &LdapsOptions) -> Result<Self> {
+        let files = std::iter::once(CertificateFiles {
+            server_name: None,
+            cert_file: options.cert_file.clone(),
+            key_file: options.key_file.clone(),
+        })
+        .chain(options.sni_certificates.iter().map(|c| CertificateFiles {
+            server_name: Some(c.server_name.to_ascii_lowercase()),
+            cert_file: c.cert_file.clone(),
+            key_file: c.key_file.clone(),
+        }))
+        .collect::<Vec<_>>();
+        let certificates = RwLock::new(Self::load(&files)?);
+        Ok(Self {
+            files,
+            certificates,
+        })
+    }
+
+    fn load(files: &[CertificateFiles]) -> Result<LoadedCertificates> {
+        // Read before loading, so that a change during the loading is seen next time.
+        let modification_times = files.iter().map(|f| f.modification_times()).collect();
+        let mut default = None;
+        let mut by_server_name = HashMap::new();
+        for file in files {
+            let key = Arc::new(
+                read_certified_key(&file.cert_file, &file.key_file)
+                    .with_context(|| format!("while loading the certificate {}", file.cert_file))?,
+            );
+            match &file.server_name {
+                None => default = Some(key),
+                Some(name) => {
+                    by_server_name.insert(name.clone(), key);
+                }
+            }
+        }
+        Ok(LoadedCertificates {
+            default: default.expect("the main certificate is always loaded"),
+            by_server_name,
+            modification_times,
+        })
+    }
+
+    /// Reads all the certificates again. If one of them can't be loaded, the previous ones are
+    /// kept.
+    pub fn reload(&self) -> Result<()> {
+        let certificates = Self::load(&self.files)?;
+        *self.certificates.write().unwrap() = certificates;
+        Ok(())
+    }
+
+    fn is_modified(&self) -> bool {
+        let certificates = self.certificates.read().unwrap();
+        self.files
+            .iter()
+            .zip(&certificates.modification_times)
+            .any(|(file, times)| &file.modification_times() != times)
+    }
+
+    fn reload_and_log(&self, reason: &str) {
+        match self.reload() {
+            Ok(()) => info!("Reloaded the TLS certificates ({})", reason),
+            Err(e) => error!(
+                "Could not reload the TLS certificates, keeping the previous ones: {:#}",
+                e
+            ),
+        }
+    }
+
+    /// Reloads the certificates on SIGHUP and, with an interval, when their files are modified.
+    pub fn spawn_reloader(self: Arc<Self>, check_interval: Option<Duration>) {
+        tokio::spawn(async move {
+            #[cfg(unix)]
+            let mut hangup =
+                match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                    Ok(signal) => Some(signal),
+                    Err(e) => {
+                        warn!("Cannot reload the TLS certificates on SIGHUP: {:#}", e);
+                        None
+                    }
+                };
+            let mut interval = check_interval.map(tokio::time::interval);
+            loop {
+                let on_hangup = async {
+                    #[cfg(unix)]
+                    if let Some(hangup) = hangup.as_mut() {
+                        return hangup.recv().await;
+                    }
+                    std::future::pending().await
+                };
+                let on_tick = async {
+                    match interval.as_mut() {
+                        Some(interval) => interval.tick().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                tokio::select! {
+                    signal = on_hangup => match signal {
+                        Some(()) => self.reload_and_log("SIGHUP"),
+                        None => return,
+                    },
+                    _ = on_tick => {
+                        if self.is_modified() {
+                            self.reload_and_log("the files changed");
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let certificates = self.certificates.read().unwrap();
+        client_hello
+            .server_name()
+            .and_then(|name| certificates.by_server_name.get(&name.to_ascii_lowercase()))
+            .or(Some(&certificates.default))
+            .cloned()
+    }
+}
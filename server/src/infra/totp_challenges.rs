@@ -0,0 +1,116 @@
+//! The web UI logins waiting for the TOTP code of the user, once the password was verified.
+//!
+//! The challenges are only kept in memory: a restart of the server means logging in again with
+//! the password. Each challenge allows a few attempts at the code, after which the login has to
+//! start over, which slows down guessing the code of a user whose password leaked.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{domain::types::UserId, infra::sql_backend_handler::gen_random_string};
+
+/// How long the user has to enter the code.
+const CHALLENGE_VALIDITY: Duration = Duration::from_secs(5 * 60);
+const MAX_ATTEMPTS: u8 = 5;
+
+struct Challenge {
+    user_id: UserId,
+    expiry: Instant,
+    attempts_left: u8,
+}
+
+#[derive(Default)]
+pub struct TotpChallenges {
+    challenges: Mutex<HashMap<String, Challenge>>,
+}
+
+impl TotpChallenges {
+    fn create_at(&self, user_id: UserId, now: Instant) -> String {
+        let token = gen_random_string(100);
+        let mut challenges = self.challenges.lock().unwrap();
+        challenges.retain(|_, c| c.expiry > now);
+        challenges.insert(
+            token.clone(),
+            Challenge {
+                user_id,
+                expiry: now + CHALLENGE_VALIDITY,
+                attempts_left: MAX_ATTEMPTS,
+            },
+        );
+        token
+    }
+
+    /// Returns the challenge to send back with the code.
+    pub fn create(&self, user_id: UserId) -> String {
+        self.create_at(user_id, Instant::now())
+    }
+
+    fn start_attempt_at(&self, token: &str, now: Instant) -> Option<UserId> {
+        let mut challenges = self.challenges.lock().unwrap();
+        let (user_id, attempts_left) = match challenges.get_mut(token) {
+            Some(challenge) if challenge.expiry > now && challenge.attempts_left > 0 => {
+                challenge.attempts_left -= 1;
+                (challenge.user_id.clone(), challenge.attempts_left)
+            }
+            _ => {
+                challenges.remove(token);
+                return None;
+            }
+        };
+        if attempts_left == 0 {
+            challenges.remove(token);
+        }
+        Some(user_id)
+    }
+
+    /// Counts an attempt at the code, and returns the user who has to enter it. `None` if the
+    /// challenge doesn't exist, expired or has no attempts left.
+    pub fn start_attempt(&self, token: &str) -> Option<UserId> {
+        self.start_attempt_at(token, Instant::now())
+    }
+
+    /// Called once the code was accepted, so that the challenge can't be used again.
+    pub fn remove(&self, token: &str) {
+        self.challenges.lock().unwrap().remove(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attempts() {
+        let challenges = TotpChallenges::default();
+        let now = Instant::now();
+        let bob = UserId::new("bob");
+        let token = challenges.create_at(bob.clone(), now);
+        for _ in 0..MAX_ATTEMPTS {
+            assert_eq!(challenges.start_attempt_at(&token, now), Some(bob.clone()));
+        }
+        assert_eq!(challenges.start_attempt_at(&token, now), None);
+        assert_eq!(challenges.start_attempt_at("unknown", now), None);
+
+        let token = challenges.create_at(bob.clone(), now);
+        challenges.remove(&token);
+        assert_eq!(challenges.start_attempt_at(&token, now), None);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let challenges = TotpChallenges::default();
+        let now = Instant::now();
+        let token = challenges.create_at(UserId::new("bob"), now);
+        assert_eq!(
+            challenges.start_attempt_at(&token, now + CHALLENGE_VALIDITY),
+            None
+        );
+        // The expired challenges are dropped when creating new ones.
+        challenges.create_at(UserId::new("bob"), now);
+        challenges.create_at(UserId::new("patrick"), now + CHALLENGE_VALIDITY);
+        assert_eq!(challenges.challenges.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,221 @@
+//! Web UI logins with a security key or a passkey (WebAuthn), instead of the password.
+//!
+//! The relying party is the host of `http_url`, so the web UI has to be served from that URL for
+//! the browsers to accept the credentials. The ceremonies in progress are only kept in memory,
+//! like the TOTP challenges.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use webauthn_rs::prelude::{
+    AuthenticationResult, CreationChallengeResponse, Passkey, PasskeyAuthentication,
+    PasskeyRegistration, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse, Url, Webauthn, WebauthnBuilder,
+};
+
+use crate::{
+    domain::types::{User, UserId, WebauthnCredential},
+    infra::{
+        sql_backend_handler::gen_random_string,
+        tcp_server::{TcpError, TcpResult},
+    },
+};
+
+/// How long the user has to answer the prompt of the browser.
+const CEREMONY_VALIDITY: Duration = Duration::from_secs(5 * 60);
+/// The name of the service shown by the browsers.
+const RELYING_PARTY_NAME: &str = "LLDAP";
+
+struct Pending<T> {
+    state: T,
+    expiry: Instant,
+}
+
+impl<T> Pending<T> {
+    fn new(state: T) -> Self {
+        Self {
+            state,
+            expiry: Instant::now() + CEREMONY_VALIDITY,
+        }
+    }
+}
+
+pub struct WebauthnState {
+    webauthn: Webauthn,
+    registrations: Mutex<HashMap<UserId, Pending<PasskeyRegistration>>>,
+    authentications: Mutex<HashMap<String, (UserId, Pending<PasskeyAuthentication>)>>,
+}
+
+fn to_internal_error<E: std::fmt::Display>(e: E) -> TcpError {
+    TcpError::InternalServerError(e.to_string())
+}
+
+fn parse_credential(credential: &WebauthnCredential) -> TcpResult<Passkey> {
+    serde_json::from_str(&credential.credential).map_err(to_internal_error)
+}
+
+/// The credential ID serializes to URL-safe base64.
+fn get_credential_id(passkey: &Passkey) -> TcpResult<String> {
+    match serde_json::to_value(passkey.cred_id()).map_err(to_internal_error)? {
+        serde_json::Value::String(id) => Ok(id),
+        _ => Err(TcpError::InternalServerError(
+            "Unexpected credential ID format".to_string(),
+        )),
+    }
+}
+
+impl WebauthnState {
+    pub fn new(http_url: &str) -> anyhow::Result<Self> {
+        let origin = Url::parse(http_url)?;
+        let relying_party_id = origin
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("No host in '{}'", http_url))?
+            .to_owned();
+        Ok(Self {
+            webauthn: WebauthnBuilder::new(&relying_party_id, &origin)?
+                .rp_name(RELYING_PARTY_NAME)
+                .build()?,
+            registrations: Mutex::default(),
+            authentications: Mutex::default(),
+        })
+    }
+
+    /// Returns the options to pass to the browser. The existing credentials of the user are
+    /// excluded, so that an authenticator can't be registered twice.
+    pub fn start_registration(
+        &self,
+        user: &User,
+        credentials: &[WebauthnCredential],
+    ) -> TcpResult<CreationChallengeResponse> {
+        let user_unique_id =
+            webauthn_rs::prelude::Uuid::parse_str(user.uuid.as_str()).map_err(to_internal_error)?;
+        let exclude_credentials = credentials
+            .iter()
+            .map(|c| parse_credential(c).map(|p| p.cred_id().clone()))
+            .collect::<TcpResult<Vec<_>>>()?;
+        let (options, state) = self
+            .webauthn
+            .start_passkey_registration(
+                user_unique_id,
+                user.user_id.as_str(),
+                user.display_name
+                    .as_deref()
+                    .unwrap_or_else(|| user.user_id.as_str()),
+                Some(exclude_credentials),
+            )
+            .map_err(to_internal_error)?;
+        let mut registrations = self.registrations.lock().unwrap();
+        let now = Instant::now();
+        registrations.retain(|_, r| r.expiry > now);
+        // Starting over replaces the previous registration of the user.
+        registrations.insert(user.user_id.clone(), Pending::new(state));
+        Ok(options)
+    }
+
+    /// Checks the response of the authenticator, and returns the credential to store.
+    pub fn finish_registration(
+        &self,
+        user_id: &UserId,
+        name: String,
+        response: &RegisterPublicKeyCredential,
+    ) -> TcpResult<WebauthnCredential> {
+        let pending = self
+            .registrations
+            .lock()
+            .unwrap()
+            .remove(user_id)
+            .filter(|r| r.expiry > Instant::now())
+            .ok_or_else(|| TcpError::BadRequest("No pending registration".to_string()))?;
+        let passkey = self
+            .webauthn
+            .finish_passkey_registration(response, &pending.state)
+            .map_err(|e| TcpError::BadRequest(format!("Invalid credential: {}", e)))?;
+        let credential_id = get_credential_id(&passkey)?;
+        if credential_id.len() > 255 {
+            return Err(TcpError::BadRequest(
+                "The credential ID of this authenticator is too long".to_string(),
+            ));
+        }
+        Ok(WebauthnCredential {
+            credential_id,
+            user_id: user_id.clone(),
+            name,
+            credential: serde_json::to_string(&passkey).map_err(to_internal_error)?,
+            creation_date: chrono::Utc::now(),
+        })
+    }
+
+    /// Returns the challenge to send back with the response of the authenticator, and the
+    /// options to pass to the browser.
+    pub fn start_authentication(
+        &self,
+        user_id: UserId,
+        credentials: &[WebauthnCredential],
+    ) -> TcpResult<(String, RequestChallengeResponse)> {
+        let passkeys = credentials
+            .iter()
+            .map(parse_credential)
+            .collect::<TcpResult<Vec<_>>>()?;
+        let (options, state) = self
+            .webauthn
+            .start_passkey_authentication(&passkeys)
+            .map_err(to_internal_error)?;
+        let token = gen_random_string(100);
+        let mut authentications = self.authentications.lock().unwrap();
+        let now = Instant::now();
+        authentications.retain(|_, (_, a)| a.expiry > now);
+        authentications.insert(token.clone(), (user_id, Pending::new(state)));
+        Ok((token, options))
+    }
+
+    /// Checks the signature of the authenticator, and returns the user who logged in. Each
+    /// challenge can only be used once.
+    pub fn finish_authentication(
+        &self,
+        token: &str,
+        response: &PublicKeyCredential,
+    ) -> TcpResult<(UserId, AuthenticationResult)> {
+        let (user_id, pending) = self
+            .authentications
+            .lock()
+            .unwrap()
+            .remove(token)
+            .filter(|(_, a)| a.expiry > Instant::now())
+            .ok_or_else(|| {
+                TcpError::UnauthorizedError("Invalid or expired login, start over".to_string())
+            })?;
+        let result = self
+            .webauthn
+            .finish_passkey_authentication(response, &pending.state)
+            .map_err(|e| TcpError::UnauthorizedError(format!("Invalid credential: {}", e)))?;
+        Ok((user_id, result))
+    }
+
+    /// The credential with the new signature counter, if the login used it and changed it.
+    pub fn get_updated_credential(
+        credential: &WebauthnCredential,
+        result: &AuthenticationResult,
+    ) -> TcpResult<Option<String>> {
+        let mut passkey = parse_credential(credential)?;
+        match passkey.update_credential(result) {
+            Some(true) => Ok(Some(
+                serde_json::to_string(&passkey).map_err(to_internal_error)?,
+            )),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        WebauthnState::new("https://ldap.example.com").unwrap();
+        WebauthnState::new("not a URL").unwrap_err();
+    }
+}
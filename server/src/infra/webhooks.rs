@@ -0,0 +1,204 @@
+//! The webhooks registered by the admins: the changes of the directory are posted to them as
+//! JSON, signed with the secret of each webhook.
+//!
+//! The deliveries run in the background, and are retried with a growing delay until the webhook
+//! answers with a success. They are only kept in memory: the pending ones are lost on restart.
+
+use std::time::Duration;
+
+use hmac::{Hmac, Mac, NewMac};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::domain::{
+    handler::BackendHandler,
+    types::{DateTime, DirectoryChange, Webhook, WebhookEvent},
+};
+
+/// `sha256=` followed by the hex-encoded HMAC-SHA256 of the body, keyed with the secret.
+pub const SIGNATURE_HEADER: &str = "x-lldap-signature";
+pub const EVENT_HEADER: &str = "x-lldap-event";
+
+const MAX_ATTEMPTS: u32 = 5;
+/// Doubled after each failed attempt: the last one is 2.5 minutes after the first.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn get_payload(event: WebhookEvent, change: &DirectoryChange, date: DateTime) -> serde_json::Value {
+    let mut payload = json!({
+        "event": event.as_str(),
+        "date": date.to_rfc3339(),
+    });
+    if let Some(user_id) = change.user_id() {
+        payload["user_id"] = user_id.as_str().into();
+    }
+    if let Some(group_id) = change.group_id() {
+        payload["group_id"] = group_id.0.into();
+    }
+    if let DirectoryChange::MembershipChanged { added, .. } = change {
+        payload["added"] = (*added).into();
+    }
+    payload
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!(
+        "sha256={}",
+        data_encoding::HEXLOWER.encode(&mac.finalize().into_bytes())
+    )
+}
+
+async fn deliver(client: reqwest::Client, webhook: Webhook, event: WebhookEvent, body: String) {
+    let signature = sign(&webhook.secret, body.as_bytes());
+    let mut delay = INITIAL_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event.as_str())
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        match result {
+            Ok(_) => {
+                debug!(
+                    "Delivered the {} event to webhook {}",
+                    event.as_str(),
+                    webhook.webhook_id
+                );
+                return;
+            }
+            Err(e) => warn!(
+                "Attempt {}/{} to deliver the {} event to webhook {} failed: {}",
+                attempt,
+                MAX_ATTEMPTS,
+                event.as_str(),
+                webhook.webhook_id,
+                e
+            ),
+        }
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+    warn!(
+        "Giving up on the delivery of the {} event to webhook {}",
+        event.as_str(),
+        webhook.webhook_id
+    );
+}
+
+/// Sends the changes to the webhooks subscribed to their event, until the channel is closed.
+pub fn start<Backend>(handler: Backend, mut changes: broadcast::Receiver<DirectoryChange>)
+where
+    Backend: BackendHandler + Sync + 'static,
+{
+    tokio::spawn(async move {
+        let client = match reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Could not create the HTTP client, webhooks disabled: {}", e);
+                return;
+            }
+        };
+        loop {
+            let change = match changes.recv().await {
+                Ok(change) => change,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(
+                        "Too many changes at once, {} were not sent to the webhooks",
+                        missed
+                    );
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let event = match change.event() {
+                Some(event) => event,
+                None => continue,
+            };
+            let webhooks = match handler.list_webhooks().await {
+                Ok(webhooks) => webhooks,
+                Err(e) => {
+                    warn!(
+                        "Could not list the webhooks for a {} event: {}",
+                        event.as_str(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            let webhooks = webhooks
+                .into_iter()
+                .filter(|webhook| webhook.events.contains(&event))
+                .collect::<Vec<_>>();
+            if webhooks.is_empty() {
+                continue;
+            }
+            let body = get_payload(event, &change, chrono::Utc::now()).to_string();
+            for webhook in webhooks {
+                tokio::spawn(deliver(client.clone(), webhook, event, body.clone()));
+            }
+        }
+        info!("Webhook deliveries stopped");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::types::{GroupId, UserId};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_payload() {
+        let date = chrono::Utc.timestamp_opt(42, 0).unwrap();
+        assert_eq!(
+            get_payload(
+                WebhookEvent::UserCreated,
+                &DirectoryChange::UserCreated(UserId::new("Bob")),
+                date
+            ),
+            json!({
+                "event": "user_created",
+                "date": "1970-01-01T00:00:42+00:00",
+                "user_id": "bob",
+            })
+        );
+        assert_eq!(
+            get_payload(
+                WebhookEvent::GroupMembershipChanged,
+                &DirectoryChange::MembershipChanged {
+                    user_id: UserId::new("bob"),
+                    group_id: GroupId(3),
+                    added: false,
+                },
+                date
+            ),
+            json!({
+                "event": "group_membership_changed",
+                "date": "1970-01-01T00:00:42+00:00",
+                "user_id": "bob",
+                "group_id": 3,
+                "added": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -6,17 +6,28 @@ use std::time::Duration;
 
 use crate::{
     domain::{
-        handler::{CreateUserRequest, GroupBackendHandler, GroupRequestFilter, UserBackendHandler},
+        handler::{
+            BackendHandler, CreateUserRequest, GroupBackendHandler, GroupRequestFilter,
+            UserBackendHandler,
+        },
+        legacy_password,
         sql_backend_handler::SqlBackendHandler,
         sql_opaque_handler::register_password,
+        sql_tables::DbConnection,
+        types::UserId,
+    },
+    infra::{
+        cli::*, configuration::Configuration, db_cleaner::Scheduler,
+        group_assignment::apply_group_assignment_rules, healthcheck, mail,
     },
-    infra::{cli::*, configuration::Configuration, db_cleaner::Scheduler, healthcheck, mail},
 };
 use actix::Actor;
 use actix_server::ServerBuilder;
 use anyhow::{anyhow, Context, Result};
 use futures_util::TryFutureExt;
-use sea_orm::Database;
+use lldap_auth::password_strength::MIN_PASSWORD_LENGTH;
+use sea_orm::{Database, EntityTrait};
+use secstr::SecUtf8;
 use tracing::*;
 
 mod domain;
@@ -66,25 +77,65 @@ async fn ensure_group_exists(handler: &SqlBackendHandler, group_name: &str) -> R
     Ok(())
 }
 
-#[instrument(skip_all)]
-async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
-    info!("Starting LLDAP version {}", env!("CARGO_PKG_VERSION"));
+/// The "everyone" group is a dynamic group matching all the users, kept in sync with the
+/// configuration at startup.
+async fn ensure_everyone_group_exists(handler: &SqlBackendHandler, group_name: &str) -> Result<()> {
+    const EVERYONE_FILTER: &str = "(objectClass=person)";
+    match handler
+        .list_groups(Some(GroupRequestFilter::DisplayName(group_name.to_owned())))
+        .await?
+        .first()
+    {
+        None => {
+            info!(
+                "Creating the {} group, containing all the users",
+                group_name
+            );
+            handler
+                .create_dynamic_group(group_name, EVERYONE_FILTER)
+                .await
+                .context(format!("while creating {} group", group_name))?;
+        }
+        Some(group) if group.dynamic_filter.as_deref() != Some(EVERYONE_FILTER) => {
+            anyhow::bail!(
+                "The everyone_group {} already exists, but doesn't contain all the users",
+                group_name
+            );
+        }
+        Some(_) => (),
+    }
+    Ok(())
+}
 
-    let sql_pool = {
-        let mut sql_opt = sea_orm::ConnectOptions::new(config.database_url.clone());
-        sql_opt
-            .max_connections(5)
-            .sqlx_logging(true)
-            .sqlx_logging_level(log::LevelFilter::Debug);
-        Database::connect(sql_opt).await?
-    };
+async fn connect_to_database(config: &Configuration) -> Result<DbConnection> {
+    let mut sql_opt = sea_orm::ConnectOptions::new(config.database_url.clone());
+    sql_opt
+        .max_connections(5)
+        .sqlx_logging(true)
+        .sqlx_logging_level(log::LevelFilter::Debug);
+    let sql_pool = Database::connect(sql_opt).await?;
     domain::sql_tables::init_table(&sql_pool)
         .await
         .context("while creating the tables")?;
-    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone());
+    Ok(sql_pool)
+}
+
+#[instrument(skip_all)]
+async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
+    info!("Starting LLDAP version {}", env!("CARGO_PKG_VERSION"));
+
+    let sql_pool = connect_to_database(&config).await?;
+    // The webhooks and the GraphQL subscriptions each get their own receiver.
+    let (changes, change_receiver) = tokio::sync::broadcast::channel(1024);
+    let backend_handler = SqlBackendHandler::new(config.clone(), sql_pool.clone())
+        .with_change_notifications(changes.clone());
+    infra::webhooks::start(backend_handler.clone(), change_receiver);
     ensure_group_exists(&backend_handler, "lldap_admin").await?;
     ensure_group_exists(&backend_handler, "lldap_password_manager").await?;
     ensure_group_exists(&backend_handler, "lldap_strict_readonly").await?;
+    if let Some(group_name) = &config.everyone_group {
+        ensure_everyone_group_exists(&backend_handler, group_name).await?;
+    }
     if let Err(e) = backend_handler.get_user_details(&config.ldap_user_dn).await {
         warn!("Could not get admin user, trying to create it: {:#}", e);
         create_admin_user(&backend_handler, &config)
@@ -99,12 +150,22 @@ async fn set_up_server(config: Configuration) -> Result<ServerBuilder> {
     )
     .context("while binding the LDAP server")?;
     infra::jwt_sql_tables::init_table(&sql_pool).await?;
+    if config.jwt_secret_regenerated() {
+        // The existing JWTs can't be verified anymore, the refresh tokens must go too.
+        domain::model::JwtRefreshStorage::delete_many()
+            .exec(&sql_pool)
+            .await
+            .context("while deleting the refresh tokens")?;
+        warn!("JWT secret regenerated, all the users have been logged out");
+    }
     let server_builder =
-        infra::tcp_server::build_tcp_server(&config, backend_handler, server_builder)
+        infra::tcp_server::build_tcp_server(&config, backend_handler, changes, server_builder)
             .await
             .context("while binding the TCP server")?;
     // Run every hour.
-    let scheduler = Scheduler::new("0 0 * * * * *", sql_pool);
+    let audit_log_retention = (config.audit_log_retention_days > 0)
+        .then(|| chrono::Duration::days(config.audit_log_retention_days.into()));
+    let scheduler = Scheduler::new("0 0 * * * * *", sql_pool, audit_log_retention);
     scheduler.start();
     Ok(server_builder)
 }
@@ -143,12 +204,197 @@ fn send_test_email_command(opts: TestEmailOpts) -> Result<()> {
         .build()?;
 
     runtime.block_on(
-        mail::send_test_email(to, &config.smtp_options)
+        mail::send_test_email(to, &config.default_language, &config.smtp_options)
             .unwrap_or_else(|e| error!("Could not send email: {:#}", e)),
     );
     Ok(())
 }
 
+fn read_password(opts: &CreateUserOpts) -> Result<Option<SecUtf8>> {
+    let password = match (&opts.password, opts.password_file.as_deref()) {
+        (Some(password), _) => password.clone(),
+        (None, Some("-")) => {
+            let mut password = String::new();
+            std::io::stdin()
+                .read_line(&mut password)
+                .context("while reading the password from the standard input")?;
+            password
+        }
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .with_context(|| format!("while reading the password file `{}`", path))?,
+        (None, None) => return Ok(None),
+    };
+    let password = password.trim_end_matches(|c| c == '\n' || c == '\r');
+    if password.len() < MIN_PASSWORD_LENGTH {
+        return Err(anyhow!(
+            "Minimum password length is {} characters, got {} characters",
+            MIN_PASSWORD_LENGTH,
+            password.len()
+        ));
+    }
+    Ok(Some(SecUtf8::from(password)))
+}
+
+async fn create_user(
+    handler: &SqlBackendHandler,
+    opts: CreateUserOpts,
+    password: Option<SecUtf8>,
+) -> Result<()> {
+    if opts.uid.is_empty()
+        || opts
+            .uid
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err(anyhow!("Invalid user ID: `{}`", opts.uid));
+    }
+    if !infra::graphql::mutation::is_valid_email(&opts.email) {
+        return Err(anyhow!("Invalid email address: `{}`", opts.email));
+    }
+    if let Some(password_hash) = &opts.password_hash {
+        legacy_password::check_format(password_hash)?;
+    }
+    if let Some(password) = &password {
+        handler
+            .config
+            .get_password_policy()
+            .check(
+                password.unsecure(),
+                &[opts.uid.as_str(), opts.email.as_str()],
+            )
+            .map_err(|e| anyhow!(e))?;
+    }
+    // Resolve all the groups first, to avoid creating a user with only some of the memberships.
+    let mut group_ids = Vec::new();
+    for group_name in &opts.groups {
+        let groups = handler
+            .list_groups(Some(GroupRequestFilter::DisplayName(group_name.clone())))
+            .await?;
+        match groups.first() {
+            Some(group) => group_ids.push(group.id),
+            None => return Err(anyhow!("Group `{}` does not exist", group_name)),
+        }
+    }
+    let user_id = UserId::new(&opts.uid);
+    handler
+        .create_user(CreateUserRequest {
+            user_id: user_id.clone(),
+            email: opts.email,
+            display_name: opts.display_name,
+            first_name: opts.first_name,
+            last_name: opts.last_name,
+            ..Default::default()
+        })
+        .await
+        .context("while creating the user")?;
+    if let Some(password) = password {
+        register_password(handler, &user_id, &password)
+            .await
+            .context("while setting the password")?;
+    }
+    if let Some(password_hash) = &opts.password_hash {
+        handler
+            .import_password_hash(&user_id, password_hash)
+            .await
+            .context("while importing the password hash")?;
+    }
+    for group_id in group_ids {
+        handler
+            .add_user_to_group(&user_id, group_id)
+            .await
+            .context("while adding the user to a group")?;
+    }
+    let assigned_groups =
+        apply_group_assignment_rules(handler, &handler.config.group_assignment_rules, &user_id)
+            .await
+            .context("while applying the group assignment rules")?;
+    println!("Created user {}", user_id);
+    for group in assigned_groups {
+        println!("Added {} to group {} (assignment rule)", user_id, group);
+    }
+    Ok(())
+}
+
+fn create_user_command(opts: CreateUserOpts) -> Result<()> {
+    let password = read_password(&opts)?;
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async {
+        let sql_pool = connect_to_database(&config).await?;
+        let handler = SqlBackendHandler::new(config, sql_pool);
+        create_user(&handler, opts, password).await
+    })
+}
+
+fn credentials_command(opts: CredentialsOpts, import: bool) -> Result<()> {
+    let config = infra::configuration::init(opts.clone())?;
+    infra::logging::init(&config)?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(async {
+        let sql_pool = connect_to_database(&config).await?;
+        let handler = SqlBackendHandler::new(config, sql_pool);
+        if import {
+            let count = infra::credentials::import_credentials(&handler, &opts.file).await?;
+            println!(
+                "Imported the passwords of {} users from {}",
+                count, opts.file
+            );
+        } else {
+            let count = infra::credentials::export_credentials(&handler, &opts.file).await?;
+            println!("Exported the passwords of {} users to {}", count, opts.file);
+        }
+        Ok(())
+    })
+}
+
+fn optimize_database_command(opts: OptimizeDatabaseOpts) -> Result<()> {
+    let config = infra::configuration::init(opts)?;
+    infra::logging::init(&config)?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    if config.database_url.starts_with("sqlite:") {
+        println!("The database is locked for writing until the optimization completes");
+    }
+    runtime.block_on(async {
+        let sql_pool = connect_to_database(&config).await?;
+        let handler = SqlBackendHandler::new(config, sql_pool);
+        let report = handler
+            .optimize_database()
+            .await
+            .context("while optimizing the database")?;
+        match (report.size_before, report.size_after) {
+            (Some(before), Some(after)) => {
+                println!("Optimized the database, from {} to {} bytes", before, after)
+            }
+            _ => println!("Optimized the database"),
+        }
+        Ok(())
+    })
+}
+
+fn rotate_jwt_key_command(opts: RotateJwtKeyOpts) -> Result<()> {
+    let config = infra::configuration::init(opts)?;
+    infra::logging::init(&config)?;
+    if config.jwt_algorithm != infra::configuration::JwtAlgorithm::Rs256 {
+        warn!("jwt_algorithm is not RS256: the new key won't be used until it is");
+    }
+    infra::jwt_keys::rotate_key(&config).context("while rotating the JWT signing key")?;
+    println!("Rotated the JWT signing key, restart the server to use it");
+    Ok(())
+}
+
 fn run_healthcheck(opts: RunOpts) -> Result<()> {
     debug!("CLI: {:#?}", &opts);
     let config = infra::configuration::init(opts)?;
@@ -186,5 +432,10 @@ fn main() -> Result<()> {
         Command::Run(opts) => run_server_command(opts),
         Command::HealthCheck(opts) => run_healthcheck(opts),
         Command::SendTestEmail(opts) => send_test_email_command(opts),
+        Command::CreateUser(opts) => create_user_command(opts),
+        Command::ExportCredentials(opts) => credentials_command(opts, false),
+        Command::ImportCredentials(opts) => credentials_command(opts, true),
+        Command::OptimizeDatabase(opts) => optimize_database_command(opts),
+        Command::RotateJwtKey(opts) => rotate_jwt_key_command(opts),
     }
 }